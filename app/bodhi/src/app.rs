@@ -2,26 +2,40 @@ use crate::{native::NativeCommand, AppError};
 use axum::Router;
 use bodhicore::{
   cli::{Cli, Command, ServeCommand},
+  objs::LogFormat,
   service::{AppService, EnvService, EnvServiceFn, HfHubService, LocalDataService},
-  CreateCommand, DefaultStdoutWriter, EnvCommand, ListCommand, ManageAliasCommand, PullCommand,
-  RunCommand,
+  BenchCommand, CacheCommand, CatalogCommand, ChatsCommand, CompletionsCommand, CreateCommand,
+  DaemonCommand, DbCommand, DefaultStdoutWriter, EnvCommand, EvalCommand, ExportCommand,
+  ImportCommand, InfoCommand, KeysCommand, ListCommand, LoginCommand, ManageAliasCommand,
+  PullCommand, ReplayCommand, RunCommand, UpdateCommand, UsageCommand, WorkerCommand,
 };
 use clap::Parser;
 use include_dir::{include_dir, Dir};
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+  trace::{self, Tracer},
+  Resource,
+};
 use std::{env, path::Path, sync::Arc};
+use tokio::runtime::Runtime;
 use tower_serve_static::ServeDir;
 use tracing_appender::non_blocking::WorkerGuard;
 use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
 static ASSETS: Dir<'static> = include_dir!("$CARGO_MANIFEST_DIR/../out");
 
-pub fn main_internal(env_service: Arc<EnvService>) -> super::Result<()> {
+fn build_app_service(env_service: Arc<EnvService>, proxy: Option<String>) -> Arc<AppService> {
   let bodhi_home = env_service.bodhi_home();
   let hf_cache = env_service.hf_cache();
   let data_service = LocalDataService::new(bodhi_home);
-  let hub_service = HfHubService::new_from_hf_cache(hf_cache, true);
-  let service = Arc::new(AppService::new(env_service, hub_service, data_service));
+  let mut hub_service = HfHubService::new_from_hf_cache(hf_cache, true);
+  hub_service.proxy(env_service.proxy_config(proxy));
+  hub_service.endpoint(&env_service.hf_endpoint());
+  Arc::new(AppService::new(env_service, hub_service, data_service))
+}
 
+pub fn main_internal(env_service: Arc<EnvService>) -> super::Result<()> {
   let args = env::args().collect::<Vec<_>>();
   if args.len() == 1
     && args
@@ -30,6 +44,7 @@ pub fn main_internal(env_service: Arc<EnvService>) -> super::Result<()> {
       .contains(".app/Contents/MacOS/")
   {
     // the app was launched using Bodhi.app, launch the native app with system tray
+    let service = build_app_service(env_service, None);
     NativeCommand::new(service, true).execute(Some(static_router()))?;
     return Ok(());
   }
@@ -37,6 +52,8 @@ pub fn main_internal(env_service: Arc<EnvService>) -> super::Result<()> {
   // the app was called from wrapper
   // or the executable was called from outside the `Bodhi.app` bundle
   let cli = Cli::parse();
+  let proxy_config = env_service.proxy_config(cli.proxy.clone());
+  let service = build_app_service(env_service, cli.proxy);
   match cli.command {
     Command::Envs {} => {
       EnvCommand::new(service).execute()?;
@@ -46,16 +63,40 @@ pub fn main_internal(env_service: Arc<EnvService>) -> super::Result<()> {
     }
     list @ Command::List { .. } => {
       let list_command = ListCommand::try_from(list)?;
-      list_command.execute(service)?;
+      list_command.execute(service, &mut DefaultStdoutWriter::default())?;
     }
     serve @ Command::Serve { .. } => {
       let serve_command = ServeCommand::try_from(serve)?;
       serve_command.execute(service)?;
     }
+    catalog @ Command::Catalog { .. } => {
+      let catalog_command = CatalogCommand::try_from(catalog)?;
+      catalog_command.execute(service, &proxy_config, &mut DefaultStdoutWriter::default())?;
+    }
+    cache @ Command::Cache { .. } => {
+      let cache_command = CacheCommand::try_from(cache)?;
+      cache_command.execute(service, &mut DefaultStdoutWriter::default())?;
+    }
+    import @ Command::Import { .. } => {
+      let import_command = ImportCommand::try_from(import)?;
+      import_command.execute(service, &mut DefaultStdoutWriter::default())?;
+    }
+    login @ Command::Login { .. } => {
+      let login_command = LoginCommand::try_from(login)?;
+      login_command.execute(service)?;
+    }
     pull @ Command::Pull { .. } => {
       let pull_command = PullCommand::try_from(pull)?;
       pull_command.execute(service)?;
     }
+    bench @ Command::Bench { .. } => {
+      let bench_command = BenchCommand::try_from(bench)?;
+      bench_command.execute(service, &mut DefaultStdoutWriter::default())?;
+    }
+    update @ Command::Update { .. } => {
+      let update_command = UpdateCommand::try_from(update)?;
+      update_command.execute(service, &mut DefaultStdoutWriter::default())?;
+    }
     create @ Command::Create { .. } => {
       let create_command = CreateCommand::try_from(create)?;
       create_command.execute(service)?;
@@ -80,20 +121,135 @@ pub fn main_internal(env_service: Arc<EnvService>) -> super::Result<()> {
       let rm = ManageAliasCommand::try_from(rm)?;
       rm.execute(service, &mut DefaultStdoutWriter::default())?;
     }
+    alias @ Command::Alias { .. } => {
+      let alias = ManageAliasCommand::try_from(alias)?;
+      alias.execute(service, &mut DefaultStdoutWriter::default())?;
+    }
+    export @ Command::Export { .. } => {
+      let export_command = ExportCommand::try_from(export)?;
+      export_command.execute(service, &mut DefaultStdoutWriter::default())?;
+    }
+    chats @ Command::Chats { .. } => {
+      let chats_command = ChatsCommand::try_from(chats)?;
+      chats_command.execute(service, &mut DefaultStdoutWriter::default())?;
+    }
+    usage @ Command::Usage { .. } => {
+      let usage_command = UsageCommand::try_from(usage)?;
+      usage_command.execute(service, &mut DefaultStdoutWriter::default())?;
+    }
+    replay @ Command::Replay { .. } => {
+      let replay_command = ReplayCommand::try_from(replay)?;
+      replay_command.execute(service, &mut DefaultStdoutWriter::default())?;
+    }
+    eval @ Command::Eval { .. } => {
+      let eval_command = EvalCommand::try_from(eval)?;
+      eval_command.execute(service, &mut DefaultStdoutWriter::default())?;
+    }
+    db @ Command::Db { .. } => {
+      let db_command = DbCommand::try_from(db)?;
+      db_command.execute(service, &mut DefaultStdoutWriter::default())?;
+    }
+    keys @ Command::Keys { .. } => {
+      let keys_command = KeysCommand::try_from(keys)?;
+      keys_command.execute(service, &mut DefaultStdoutWriter::default())?;
+    }
+    info @ Command::Info { .. } => {
+      let info_command = InfoCommand::try_from(info)?;
+      info_command.execute(service, &mut DefaultStdoutWriter::default())?;
+    }
+    daemon @ (Command::Ps {} | Command::Stop {}) => {
+      let daemon_command = DaemonCommand::try_from(daemon)?;
+      daemon_command.execute(service, &mut DefaultStdoutWriter::default())?;
+    }
+    completions @ Command::Completions { .. } => {
+      let completions_command = CompletionsCommand::try_from(completions)?;
+      completions_command.execute(service, &mut DefaultStdoutWriter::default())?;
+    }
+    worker @ Command::Worker { .. } => {
+      let worker_command = WorkerCommand::try_from(worker)?;
+      worker_command.execute()?;
+    }
   }
   Ok(())
 }
 
-pub fn setup_logs(logs_dir: &Path) -> super::Result<WorkerGuard> {
+/// Flushes the logs, and the traces pending export, when dropped
+pub struct LogGuard {
+  _worker_guard: WorkerGuard,
+  otlp_runtime: Option<Runtime>,
+}
+
+impl Drop for LogGuard {
+  fn drop(&mut self) {
+    if self.otlp_runtime.is_some() {
+      opentelemetry::global::shutdown_tracer_provider();
+    }
+  }
+}
+
+pub fn setup_logs(
+  logs_dir: &Path,
+  log_format: LogFormat,
+  otlp_endpoint: Option<String>,
+) -> super::Result<LogGuard> {
   let file_appender = tracing_appender::rolling::daily(logs_dir, "bodhi.log");
   let (non_blocking, guard) = tracing_appender::non_blocking(file_appender);
   let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
   let filter = filter.add_directive("hf_hub=error".parse().unwrap());
+  let (text_layer, json_layer) = match log_format {
+    LogFormat::Text => (Some(fmt::layer().with_writer(non_blocking)), None),
+    LogFormat::Json => {
+      // one object per line, with the fields of the current span, e.g. the request id
+      let json_layer = fmt::layer()
+        .json()
+        .with_current_span(true)
+        .with_span_list(false)
+        .with_writer(non_blocking);
+      (None, Some(json_layer))
+    }
+  };
+  let (otlp_runtime, tracer) = match otlp_endpoint.map(|endpoint| otlp_tracer(&endpoint)) {
+    Some(Ok((runtime, tracer))) => (Some(runtime), Some(tracer)),
+    Some(Err(err)) => {
+      eprintln!("failed to configure the OTLP exporter, traces will not be exported: {err}");
+      (None, None)
+    }
+    None => (None, None),
+  };
   tracing_subscriber::registry()
     .with(filter)
-    .with(fmt::layer().with_writer(non_blocking))
+    .with(text_layer)
+    .with(json_layer)
+    .with(tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer)))
     .init();
-  Ok(guard)
+  Ok(LogGuard {
+    _worker_guard: guard,
+    otlp_runtime,
+  })
+}
+
+// the commands run on their own short-lived runtimes, so the batch exporter gets a
+// runtime that lives as long as the app
+fn otlp_tracer(endpoint: &str) -> super::Result<(Runtime, Tracer)> {
+  let runtime = tokio::runtime::Builder::new_multi_thread()
+    .worker_threads(1)
+    .thread_name("otlp-exporter")
+    .enable_all()
+    .build()?;
+  let enter = runtime.enter();
+  let tracer = opentelemetry_otlp::new_pipeline()
+    .tracing()
+    .with_exporter(
+      opentelemetry_otlp::new_exporter()
+        .tonic()
+        .with_endpoint(endpoint),
+    )
+    .with_trace_config(
+      trace::config().with_resource(Resource::new(vec![KeyValue::new("service.name", "bodhi")])),
+    )
+    .install_batch(opentelemetry_sdk::runtime::Tokio)?;
+  drop(enter);
+  Ok((runtime, tracer))
 }
 
 fn static_router() -> Router {
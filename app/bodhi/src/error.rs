@@ -19,6 +19,8 @@ pub enum AppError {
   Cli(#[from] CliError),
   #[error(transparent)]
   Db(#[from] DbError),
+  #[error(transparent)]
+  Trace(#[from] opentelemetry::trace::TraceError),
 }
 
 pub(crate) type Result<T> = std::result::Result<T, AppError>;
@@ -2,6 +2,6 @@ mod app;
 mod error;
 mod native;
 
-pub use app::{main_internal, setup_logs};
+pub use app::{main_internal, setup_logs, LogGuard};
 pub use error::AppError;
 pub(crate) use error::Result;
@@ -3,9 +3,12 @@
 
 use std::sync::Arc;
 
-use bodhi::{main_internal, setup_logs, AppError};
-use bodhicore::service::{env_wrapper::EnvWrapper, EnvService};
-use tracing_appender::non_blocking::WorkerGuard;
+use bodhi::{main_internal, setup_logs, AppError, LogGuard};
+use bodhicore::{
+  cli::Cli,
+  service::{env_wrapper::EnvWrapper, EnvService, EnvServiceFn},
+};
+use clap::Parser;
 
 pub fn main() {
   let mut env_service = EnvService::new(EnvWrapper::default());
@@ -24,9 +27,14 @@ pub fn main() {
       std::process::exit(1);
     }
   };
+  // logging is set up before the command runs, so peek at the flag here
+  let log_format = Cli::try_parse()
+    .ok()
+    .and_then(|cli| cli.log_format)
+    .unwrap_or_else(|| env_service.log_format());
   let _guard = match env_service.setup_logs_dir() {
-    Ok(logs_dir) => setup_logs(&logs_dir),
-    Err(err) => Err::<WorkerGuard, AppError>(err.into()),
+    Ok(logs_dir) => setup_logs(&logs_dir, log_format, env_service.otlp_endpoint()),
+    Err(err) => Err::<LogGuard, AppError>(err.into()),
   };
   if _guard.is_err() {
     eprintln!("failed to configure logging, will be skipped");
@@ -12,6 +12,9 @@ pub struct NativeCommand {
   ui: bool,
 }
 
+// the port of a running bodhi server is likely in use, the app tries the next ports instead of failing
+static PORT_RANGE: u16 = 10;
+
 type ServerHandleState = Arc<Mutex<Option<ServerShutdownHandle>>>;
 
 impl NativeCommand {
@@ -27,14 +30,25 @@ impl NativeCommand {
   async fn aexecute(&self, static_router: Option<Router>) -> crate::error::Result<()> {
     let host = self.service.env_service().host();
     let port = self.service.env_service().port();
-    let addr = format!("http://{host}:{port}/");
-    let addr_clone = addr.clone();
-    let cmd = ServeCommand::ByParams { host, port };
+    let cmd = ServeCommand::ByParams {
+      host: host.clone(),
+      port,
+      port_range: PORT_RANGE,
+      capture_on_error: false,
+      uds: None,
+      ui_dir: None,
+      model: None,
+      strict: false,
+      detach: false,
+    };
     let server_handle = cmd.aexecute(self.service.clone(), static_router).await?;
+    let addr = format!("http://{host}:{}/", server_handle.port());
+    let addr_clone = addr.clone();
     let ui = self.ui;
 
     let system_tray = SystemTray::new().with_menu(
       SystemTrayMenu::new()
+        .add_item(CustomMenuItem::new("address", format!("Running on {addr}")).disabled())
         .add_item(CustomMenuItem::new("homepage", "Open Homepage"))
         .add_item(CustomMenuItem::new("quit".to_string(), "Quit")),
     );
@@ -0,0 +1,16 @@
+use std::process::Command;
+
+fn main() {
+  // commit of the llama-server-bindings submodule, pinning the llama.cpp version, shown by `bodhi info`
+  let commit = Command::new("git")
+    .args(["rev-parse", "--short", "HEAD"])
+    .current_dir("../llama-server-bindings")
+    .output()
+    .ok()
+    .filter(|output| output.status.success())
+    .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    .filter(|commit| !commit.is_empty())
+    .unwrap_or_else(|| "unknown".to_string());
+  println!("cargo:rustc-env=BODHI_BINDINGS_COMMIT={commit}");
+  println!("cargo:rerun-if-changed=../.git/modules/llama-server-bindings/HEAD");
+}
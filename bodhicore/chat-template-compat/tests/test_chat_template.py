@@ -7,7 +7,8 @@ cases = list(inputs_yaml().keys())
 # cases = ["error-user-at-even-with-system"]
 
 
-# covered: llama2, llama2-legacy, llama3, phi3, gemma, zephyr, deepseek, command-r, openchat, empty
+# covered: llama2, llama2-legacy, llama3, phi3, gemma, zephyr, deepseek, command-r, openchat, chatml,
+# mistral-v3, mistral-v7, qwen2.5, deepseek-v2, deepseek-r1, hermes2-pro, hermes3, empty
 # missing: monarch, orion, vicuna, vicuna-orca
 @pytest.mark.parametrize(
   ["format", "model"],
@@ -15,7 +16,6 @@ cases = list(inputs_yaml().keys())
     ("llama3", "meta-llama/Meta-Llama-3-8B-Instruct"),
     ("llama2", "meta-llama/Llama-2-13b-chat-hf"),
     ("phi3", "microsoft/Phi-3-mini-4k-instruct"),
-    ("llama2-legacy", "mistralai/Mistral-7B-Instruct-v0.3"),
     ("llama2-legacy", "mistralai/Mixtral-8x7B-Instruct-v0.1"),
     ("gemma", "google/gemma-7b-it"),
     # ("zephyr", "HuggingFaceH4/zephyr-7b-beta"),
@@ -23,6 +23,14 @@ cases = list(inputs_yaml().keys())
     ("command-r", "CohereForAI/c4ai-command-r-plus"),
     ("openchat", "openchat/openchat-3.6-8b-20240522"),
     ("tinyllama", "TinyLlama/TinyLlama-1.1B-Chat-v1.0"),
+    ("chatml", "teknium/OpenHermes-2.5-Mistral-7B"),
+    ("mistral-v3", "mistralai/Mistral-7B-Instruct-v0.3"),
+    ("mistral-v7", "mistralai/Mistral-Large-Instruct-2411"),
+    ("qwen2.5", "Qwen/Qwen2.5-7B-Instruct"),
+    ("deepseek-v2", "deepseek-ai/DeepSeek-V2.5"),
+    ("deepseek-r1", "deepseek-ai/DeepSeek-R1-Distill-Qwen-7B"),
+    ("hermes2-pro", "NousResearch/Hermes-2-Pro-Llama-3-8B"),
+    ("hermes3", "NousResearch/Hermes-3-Llama-3.1-8B"),
   ],
 )
 @pytest.mark.parametrize(
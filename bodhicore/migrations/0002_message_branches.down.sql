@@ -0,0 +1,3 @@
+-- Drop branched messages, and remove the branch column
+DELETE FROM messages WHERE branch_id IS NOT NULL;
+ALTER TABLE messages DROP COLUMN branch_id;
@@ -0,0 +1,3 @@
+-- Messages replaced by an edit are kept as a branch instead of being deleted,
+-- active messages of a conversation have branch_id set to NULL
+ALTER TABLE messages ADD COLUMN branch_id TEXT;
@@ -0,0 +1,4 @@
+-- Timestamps are stored as UTC epoch seconds, indexed for the chat listings sorted by created or updated time
+CREATE INDEX idx_conversations_created_at ON conversations (created_at);
+CREATE INDEX idx_conversations_updated_at ON conversations (updated_at);
+CREATE INDEX idx_messages_conversation_id_created_at ON messages (conversation_id, created_at);
@@ -0,0 +1,14 @@
+-- Chat completion requests sampled to run on a second model alias, with both outputs for comparison
+CREATE TABLE canary_samples (
+    id TEXT PRIMARY KEY NOT NULL,
+    created_at INTEGER NOT NULL,
+    model TEXT NOT NULL,
+    canary_model TEXT NOT NULL,
+    request TEXT NOT NULL,
+    output TEXT NOT NULL,
+    canary_output TEXT NOT NULL,
+    canary_error TEXT,
+    diff TEXT NOT NULL
+);
+
+CREATE INDEX idx_canary_samples_created_at ON canary_samples (created_at);
@@ -0,0 +1,2 @@
+-- Time taken to serve the request, NULL for the requests recorded before it was tracked
+ALTER TABLE usage_records ADD COLUMN latency_ms INTEGER;
@@ -0,0 +1,5 @@
+-- Name of the API key that created the conversation when BODHI_AUTH is enabled,
+-- NULL for the conversations created with the auth disabled
+ALTER TABLE conversations ADD COLUMN owner TEXT;
+
+CREATE INDEX idx_conversations_owner ON conversations (owner);
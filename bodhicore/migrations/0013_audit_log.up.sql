@@ -0,0 +1,12 @@
+-- Admin actions, e.g. the alias and setting changes, with the values before and after the change
+CREATE TABLE audit_log (
+    id TEXT PRIMARY KEY NOT NULL,
+    created_at INTEGER NOT NULL,
+    actor TEXT,
+    action TEXT NOT NULL,
+    target TEXT NOT NULL,
+    before_value TEXT,
+    after_value TEXT
+);
+
+CREATE INDEX idx_audit_log_created_at ON audit_log (created_at);
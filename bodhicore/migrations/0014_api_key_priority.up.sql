@@ -0,0 +1,2 @@
+-- Priority of the requests made using the key, the batch requests wait for the interactive ones
+ALTER TABLE api_keys ADD COLUMN priority TEXT NOT NULL DEFAULT 'interactive';
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_downloads_created_at;
+DROP TABLE IF EXISTS downloads;
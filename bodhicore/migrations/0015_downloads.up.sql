@@ -0,0 +1,15 @@
+-- Model files pulled into $HF_HOME in the background, with their progress
+CREATE TABLE downloads (
+    id TEXT PRIMARY KEY NOT NULL,
+    repo TEXT NOT NULL,
+    filename TEXT NOT NULL,
+    revision TEXT NOT NULL,
+    status TEXT NOT NULL,
+    downloaded_bytes INTEGER NOT NULL DEFAULT 0,
+    total_bytes INTEGER,
+    error TEXT,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+CREATE INDEX idx_downloads_created_at ON downloads (created_at);
@@ -0,0 +1,10 @@
+-- The chats are owned by the name of the API key, so the names of the active keys are unique.
+-- A revoked key's name is reused by its new key, to keep the chats when a key is rotated.
+-- The active keys sharing the name of an older active key are renamed with their id
+UPDATE api_keys SET name = name || '-' || id
+  WHERE revoked_at IS NULL AND EXISTS (
+    SELECT 1 FROM api_keys older
+      WHERE older.name = api_keys.name AND older.revoked_at IS NULL
+        AND (older.created_at < api_keys.created_at OR (older.created_at = api_keys.created_at AND older.id < api_keys.id))
+  );
+CREATE UNIQUE INDEX idx_api_keys_name ON api_keys (name) WHERE revoked_at IS NULL;
@@ -0,0 +1,3 @@
+-- Drop the messages before the conversations they reference
+DROP TABLE IF EXISTS messages;
+DROP TABLE IF EXISTS conversations;
@@ -0,0 +1,20 @@
+-- Create the conversations table
+CREATE TABLE conversations (
+    id TEXT PRIMARY KEY NOT NULL,
+    title TEXT NOT NULL,
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL
+);
+
+-- Create the messages table, seq keeps the order the messages are saved in,
+-- same as the rowid of the sqlite table
+CREATE TABLE messages (
+    seq BIGSERIAL NOT NULL UNIQUE,
+    id TEXT PRIMARY KEY NOT NULL,
+    conversation_id TEXT NOT NULL,
+    role TEXT NOT NULL,
+    name TEXT,
+    content TEXT,
+    created_at BIGINT NOT NULL,
+    FOREIGN KEY (conversation_id) REFERENCES conversations(id)
+);
@@ -0,0 +1,8 @@
+-- Read-only links to share a conversation, a link is valid till expires_at
+CREATE TABLE share_links (
+    token TEXT PRIMARY KEY NOT NULL,
+    conversation_id TEXT NOT NULL,
+    created_at BIGINT NOT NULL,
+    expires_at BIGINT NOT NULL,
+    FOREIGN KEY (conversation_id) REFERENCES conversations(id)
+);
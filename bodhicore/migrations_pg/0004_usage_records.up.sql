@@ -0,0 +1,12 @@
+-- Ledger of the chat completion requests served, with the client that sent the request
+CREATE TABLE usage_records (
+    id TEXT PRIMARY KEY NOT NULL,
+    created_at BIGINT NOT NULL,
+    model TEXT NOT NULL,
+    user_agent TEXT,
+    app_name TEXT,
+    prompt_tokens BIGINT NOT NULL DEFAULT 0,
+    completion_tokens BIGINT NOT NULL DEFAULT 0
+);
+
+CREATE INDEX idx_usage_records_created_at ON usage_records (created_at);
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS idx_messages_conversation_id_created_at;
+DROP INDEX IF EXISTS idx_conversations_updated_at;
+DROP INDEX IF EXISTS idx_conversations_created_at;
@@ -0,0 +1,5 @@
+-- Full-text index over the message content, the tsvector column is generated from the content
+ALTER TABLE messages ADD COLUMN content_tsv tsvector
+    GENERATED ALWAYS AS (to_tsvector('english', COALESCE(content, ''))) STORED;
+
+CREATE INDEX idx_messages_content_tsv ON messages USING GIN (content_tsv);
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_canary_samples_created_at;
+DROP TABLE IF EXISTS canary_samples;
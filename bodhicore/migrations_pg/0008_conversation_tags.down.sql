@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_conversation_tags_tag;
+DROP TABLE IF EXISTS conversation_tags;
@@ -0,0 +1,10 @@
+-- Tags to organize the conversations, e.g. work, personal or a project name
+CREATE TABLE conversation_tags (
+    conversation_id TEXT NOT NULL,
+    tag TEXT NOT NULL,
+    created_at BIGINT NOT NULL,
+    PRIMARY KEY (conversation_id, tag),
+    FOREIGN KEY (conversation_id) REFERENCES conversations(id)
+);
+
+CREATE INDEX idx_conversation_tags_tag ON conversation_tags (tag);
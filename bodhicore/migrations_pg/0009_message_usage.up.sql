@@ -0,0 +1,5 @@
+-- Model and token usage of the replies generated by the server,
+-- NULL for the messages written by the user or saved by the client
+ALTER TABLE messages ADD COLUMN model TEXT;
+ALTER TABLE messages ADD COLUMN prompt_tokens BIGINT;
+ALTER TABLE messages ADD COLUMN completion_tokens BIGINT;
@@ -0,0 +1,9 @@
+-- Keys to access the APIs when BODHI_AUTH is enabled, only the sha256 of the key is stored
+CREATE TABLE api_keys (
+    id TEXT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    role TEXT NOT NULL,
+    key_hash TEXT NOT NULL UNIQUE,
+    created_at BIGINT NOT NULL,
+    revoked_at BIGINT
+);
@@ -0,0 +1,17 @@
+-- Model files pulled into $HF_HOME in the background, with their progress,
+-- seq keeps the order the downloads are created in
+CREATE TABLE downloads (
+    id TEXT PRIMARY KEY NOT NULL,
+    seq BIGSERIAL NOT NULL UNIQUE,
+    repo TEXT NOT NULL,
+    filename TEXT NOT NULL,
+    revision TEXT NOT NULL,
+    status TEXT NOT NULL,
+    downloaded_bytes BIGINT NOT NULL DEFAULT 0,
+    total_bytes BIGINT,
+    error TEXT,
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL
+);
+
+CREATE INDEX idx_downloads_created_at ON downloads (created_at);
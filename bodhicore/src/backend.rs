@@ -0,0 +1,202 @@
+use crate::{
+  error::Common, objs::Backend, service::BODHI_BACKEND_VARIANT, system_info::nvidia_gpus,
+  BodhiError,
+};
+use std::{
+  env,
+  path::{Path, PathBuf},
+  process::Command,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackendSelection {
+  pub backend: Backend,
+  /// the binary shipped for the backend, None if the running binary is built with it
+  pub variant: Option<PathBuf>,
+}
+
+/// Picks the backend to run the models with, out of the ones this binary is built with and the
+/// variants shipped alongside it as `bodhi-<backend>`, e.g. `bodhi-cuda` or `bodhi-vulkan`.
+/// `relaunched` is the backend the running binary was relaunched for, used as is.
+#[allow(clippy::result_large_err)]
+pub fn select_backend(
+  requested: Backend,
+  relaunched: Option<Backend>,
+) -> crate::error::Result<BackendSelection> {
+  if let Some(backend) = relaunched {
+    return Ok(BackendSelection {
+      backend,
+      variant: None,
+    });
+  }
+  let variants = match env::current_exe() {
+    Ok(exe) => shipped_variants(&exe),
+    Err(err) => {
+      tracing::warn!(
+        ?err,
+        "error finding the running binary, skipping the backend variants"
+      );
+      vec![]
+    }
+  };
+  choose(requested, &Backend::compiled(), &variants, &supported())
+}
+
+/// Runs the same command using the binary shipped for the backend, replacing the running process
+/// on unix, and exiting with its exit code otherwise
+#[allow(clippy::result_large_err)]
+pub fn relaunch(backend: Backend, variant: &Path) -> crate::error::Result<()> {
+  tracing::info!(%backend, variant = %variant.display(), "relaunching using the binary of the backend");
+  let mut command = Command::new(variant);
+  command
+    .args(env::args_os().skip(1))
+    .env(BODHI_BACKEND_VARIANT, backend.to_string());
+  #[cfg(unix)]
+  {
+    use std::os::unix::process::CommandExt;
+    let source = command.exec();
+    Err(
+      Common::IoFile {
+        source,
+        path: variant.display().to_string(),
+      }
+      .into(),
+    )
+  }
+  #[cfg(not(unix))]
+  {
+    let status = command.status().map_err(|source| Common::IoFile {
+      source,
+      path: variant.display().to_string(),
+    })?;
+    std::process::exit(status.code().unwrap_or(1));
+  }
+}
+
+fn choose(
+  requested: Backend,
+  compiled: &[Backend],
+  variants: &[(Backend, PathBuf)],
+  supported: &[Backend],
+) -> crate::error::Result<BackendSelection> {
+  let available = |backend: Backend| {
+    if compiled.contains(&backend) {
+      return Some(BackendSelection {
+        backend,
+        variant: None,
+      });
+    }
+    variants
+      .iter()
+      .find(|(variant, _)| variant == &backend)
+      .map(|(_, path)| BackendSelection {
+        backend,
+        variant: Some(path.clone()),
+      })
+  };
+  match requested {
+    Backend::Auto => Ok(
+      Backend::ACCELERATED
+        .into_iter()
+        .filter(|backend| supported.contains(backend))
+        .find_map(available)
+        .unwrap_or(BackendSelection {
+          backend: Backend::Cpu,
+          variant: None,
+        }),
+    ),
+    backend => available(backend).ok_or(BodhiError::BackendUnavailable(backend)),
+  }
+}
+
+fn shipped_variants(exe: &Path) -> Vec<(Backend, PathBuf)> {
+  let Some(dir) = exe.parent() else {
+    return vec![];
+  };
+  Backend::ACCELERATED
+    .into_iter()
+    .map(|backend| {
+      let filename = format!("bodhi-{backend}{}", env::consts::EXE_SUFFIX);
+      (backend, dir.join(filename))
+    })
+    .filter(|(_, path)| path.is_file() && path != exe)
+    .collect()
+}
+
+// the GPU backends the machine has the hardware and the drivers for
+fn supported() -> Vec<Backend> {
+  let mut backends = vec![];
+  if cfg!(all(target_os = "macos", target_arch = "aarch64")) {
+    backends.push(Backend::Metal);
+  }
+  if !nvidia_gpus().is_empty() {
+    backends.push(Backend::Cuda);
+  }
+  // installed along with the Vulkan drivers, or the Vulkan SDK
+  let vulkan = Command::new("vulkaninfo")
+    .arg("--summary")
+    .output()
+    .map(|output| output.status.success())
+    .unwrap_or(false);
+  if vulkan {
+    backends.push(Backend::Vulkan);
+  }
+  backends
+}
+
+#[cfg(test)]
+mod test {
+  use super::{choose, shipped_variants, BackendSelection};
+  use crate::objs::Backend;
+  use rstest::rstest;
+  use std::{fs, path::PathBuf};
+  use tempfile::TempDir;
+
+  fn selection(backend: Backend, variant: Option<&str>) -> BackendSelection {
+    BackendSelection {
+      backend,
+      variant: variant.map(PathBuf::from),
+    }
+  }
+
+  #[rstest]
+  #[case(Backend::Auto, vec![Backend::Cuda, Backend::Vulkan], selection(Backend::Cuda, Some("/opt/bodhi/bodhi-cuda")))]
+  #[case(Backend::Auto, vec![Backend::Vulkan], selection(Backend::Vulkan, None))]
+  #[case(Backend::Auto, vec![], selection(Backend::Cpu, None))]
+  #[case(Backend::Cpu, vec![Backend::Cuda], selection(Backend::Cpu, None))]
+  #[case(Backend::Vulkan, vec![], selection(Backend::Vulkan, None))]
+  fn test_backend_choose(
+    #[case] requested: Backend,
+    #[case] supported: Vec<Backend>,
+    #[case] expected: BackendSelection,
+  ) -> anyhow::Result<()> {
+    let compiled = vec![Backend::Cpu, Backend::Vulkan];
+    let variants = vec![(Backend::Cuda, PathBuf::from("/opt/bodhi/bodhi-cuda"))];
+    let result = choose(requested, &compiled, &variants, &supported)?;
+    assert_eq!(expected, result);
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_backend_choose_unavailable() -> anyhow::Result<()> {
+    let result = choose(Backend::Metal, &[Backend::Cpu], &[], &[Backend::Metal]);
+    assert_eq!(
+      "the metal backend is not available in this installation, install the build with metal support or set BODHI_BACKEND to auto",
+      result.unwrap_err().to_string()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_backend_shipped_variants() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let exe = temp.path().join("bodhi");
+    fs::write(&exe, "")?;
+    let cuda = temp
+      .path()
+      .join(format!("bodhi-cuda{}", std::env::consts::EXE_SUFFIX));
+    fs::write(&cuda, "")?;
+    assert_eq!(vec![(Backend::Cuda, cuda)], shipped_variants(&exe));
+    Ok(())
+  }
+}
@@ -10,3 +10,15 @@ pub unsafe fn llama_server_disable_logging() {
 pub fn disable_llama_log() {
   llama_server_bindings::disable_llama_log()
 }
+
+/// The features llama.cpp was built with and detected on the CPU, e.g. `AVX2 = 1 | NEON = 0 | ...`
+pub fn llama_system_info() -> String {
+  let info = unsafe { llama_server_bindings::bindings::llama_print_system_info() };
+  if info.is_null() {
+    return String::new();
+  }
+  unsafe { std::ffi::CStr::from_ptr(info) }
+    .to_string_lossy()
+    .trim()
+    .to_string()
+}
@@ -0,0 +1,509 @@
+use crate::{
+  db::objs::{Conversation, Message},
+  error::BodhiError,
+  objs::{ChatCompletionRequest, OAIRequestParams},
+};
+use async_openai::types::{
+  ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage,
+  ChatCompletionRequestSystemMessage, ChatCompletionRequestUserMessage,
+  ChatCompletionRequestUserMessageContent, CreateChatCompletionRequestArgs, Role,
+};
+use chrono::Utc;
+use clap::{error::ErrorKind, Parser};
+use serde_json::Value;
+use std::{fmt, time::Duration};
+
+static TITLE_MAX_CHARS: usize = 50;
+
+/// Commands typed in place of the prompt in `bodhi run`, to change the chat session
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum SlashCommand {
+  Help,
+  Bye,
+  Set { param: String, value: String },
+  System(Option<String>),
+  Clear,
+  Model(String),
+  Save,
+  Retry,
+  Stats,
+}
+
+impl SlashCommand {
+  pub(crate) const HELP: &'static str = r#"/set <param> <value>: set a request param for the session, e.g. /set temperature 0.2
+/system <prompt>: set the system prompt, /system alone removes it
+/clear: clear the chat, keeping the system prompt and the params
+/model <alias>: switch to another model alias, keeping the chat
+/save: save the chat, to see it in the Web UI or export it using `bodhi export`
+/retry: generate the last reply again
+/stats: show or hide the tokens and timings of the replies
+/bye: exit the interactive mode
+/? or /help: show help
+""": start and end a multiline prompt"#;
+
+  pub(crate) fn parse(input: &str) -> Result<Self, String> {
+    let (name, args) = match input.trim().split_once(char::is_whitespace) {
+      Some((name, args)) => (name, args.trim()),
+      None => (input.trim(), ""),
+    };
+    match (name, args) {
+      ("/?" | "/help", _) => Ok(SlashCommand::Help),
+      ("/bye", _) => Ok(SlashCommand::Bye),
+      ("/clear", _) => Ok(SlashCommand::Clear),
+      ("/save", _) => Ok(SlashCommand::Save),
+      ("/retry", _) => Ok(SlashCommand::Retry),
+      ("/stats", _) => Ok(SlashCommand::Stats),
+      ("/system", "") => Ok(SlashCommand::System(None)),
+      ("/system", prompt) => Ok(SlashCommand::System(Some(prompt.to_string()))),
+      ("/model", "") => Err("usage: /model <alias>".to_string()),
+      ("/model", alias) => Ok(SlashCommand::Model(alias.to_string())),
+      ("/set", args) => match args.split_once(char::is_whitespace) {
+        Some((param, value)) => Ok(SlashCommand::Set {
+          param: param.to_string(),
+          value: value.trim().to_string(),
+        }),
+        None => Err("usage: /set <param> <value>, e.g. /set temperature 0.2".to_string()),
+      },
+      _ => Err(format!(
+        "unknown command `{input}`. type `/?` for list of commands."
+      )),
+    }
+  }
+}
+
+/// Tokens and timings of a streamed reply, to compare the speed of the context params of a model
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct ReplyStats {
+  prompt_tokens: Option<i64>,
+  completion_tokens: Option<i64>,
+  chunks: i64,
+  time_to_first_token: Option<Duration>,
+  total: Duration,
+}
+
+impl ReplyStats {
+  /// Records the streamed chunk, received the elapsed time after sending the request
+  pub(crate) fn record(&mut self, chunk: &Value, elapsed: Duration) {
+    let has_content = chunk["choices"][0]["delta"]["content"]
+      .as_str()
+      .is_some_and(|content| !content.is_empty());
+    if has_content {
+      self.time_to_first_token.get_or_insert(elapsed);
+      self.chunks += 1;
+    }
+    // the final chunk has the token usage, if reported by the server
+    if let Some(usage) = chunk.get("usage").filter(|usage| usage.is_object()) {
+      self.prompt_tokens = usage["prompt_tokens"].as_i64();
+      self.completion_tokens = usage["completion_tokens"].as_i64();
+    }
+    self.total = elapsed;
+  }
+
+  // a chunk is streamed for every generated token, when the usage is not reported
+  pub(crate) fn generated_tokens(&self) -> i64 {
+    self.completion_tokens.unwrap_or(self.chunks)
+  }
+
+  pub(crate) fn tokens_per_sec(&self) -> Option<f64> {
+    let generating = self.total.checked_sub(self.time_to_first_token?)?;
+    if generating.is_zero() {
+      return None;
+    }
+    Some(self.generated_tokens() as f64 / generating.as_secs_f64())
+  }
+}
+
+impl fmt::Display for ReplyStats {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if let Some(prompt_tokens) = self.prompt_tokens {
+      write!(f, "prompt: {prompt_tokens} tokens, ")?;
+    }
+    write!(f, "generated: {} tokens", self.generated_tokens())?;
+    if let Some(time_to_first_token) = self.time_to_first_token {
+      write!(
+        f,
+        ", first token: {:.2}s",
+        time_to_first_token.as_secs_f64()
+      )?;
+    }
+    if let Some(tokens_per_sec) = self.tokens_per_sec() {
+      write!(f, ", {tokens_per_sec:.1} tokens/s")?;
+    }
+    write!(f, ", total: {:.2}s", self.total.as_secs_f64())
+  }
+}
+
+// parses the `/set` params using the cli args of the request params, so they are validated the same way
+#[derive(Debug, Default, Parser)]
+#[command(no_binary_name = true)]
+struct SessionParams {
+  #[clap(flatten)]
+  params: OAIRequestParams,
+}
+
+/// The chat of `bodhi run`, along with the system prompt and the request params set using the slash commands
+#[derive(Debug, Default)]
+pub(crate) struct ChatSession {
+  pub(crate) system: Option<String>,
+  params: SessionParams,
+  pub(crate) messages: Vec<Message>,
+  conversation: Option<Conversation>,
+}
+
+impl ChatSession {
+  /// Resumes the saved conversation, saving the chat updates the same conversation
+  pub(crate) fn resume(mut conversation: Conversation) -> Self {
+    let mut messages = std::mem::take(&mut conversation.messages);
+    let system = match messages.first() {
+      Some(message) if message.role == "system" => messages.remove(0).content,
+      _ => None,
+    };
+    Self {
+      system,
+      messages,
+      conversation: Some(conversation),
+      ..Default::default()
+    }
+  }
+
+  /// Id of the conversation the chat is saved as
+  pub(crate) fn saved_id(&self) -> Option<&str> {
+    self
+      .conversation
+      .as_ref()
+      .map(|conversation| conversation.id.as_str())
+      .filter(|id| !id.is_empty())
+  }
+
+  /// The chat so far, as shown while chatting
+  pub(crate) fn transcript(&self) -> String {
+    let mut transcript = String::new();
+    if let Some(system) = &self.system {
+      transcript.push_str(&format!("system: {system}\n\n"));
+    }
+    for message in &self.messages {
+      let content = message.content.as_deref().unwrap_or_default();
+      match message.role.as_str() {
+        "assistant" => transcript.push_str(&format!("{content}\n\n")),
+        _ => transcript.push_str(&format!(">>> {content}\n")),
+      }
+    }
+    transcript
+  }
+
+  pub(crate) fn set_param(&mut self, param: &str, value: &str) -> Result<(), String> {
+    let arg = format!("--{}", param.replace('_', "-"));
+    self
+      .params
+      .try_update_from([arg.as_str(), value])
+      .map_err(|err| match err.kind() {
+        ErrorKind::UnknownArgument => format!(
+          "unknown param `{param}`, supported params: temperature, top_p, max_tokens, seed, stop, frequency_penalty, presence_penalty, user, top_k, min_p, repeat_penalty, repeat_last_n, typical_p, mirostat, mirostat_tau, mirostat_eta"
+        ),
+        _ => err
+          .to_string()
+          .lines()
+          .next()
+          .unwrap_or_default()
+          .trim_start_matches("error: ")
+          .to_string(),
+      })
+  }
+
+  pub(crate) fn push_prompt(&mut self, prompt: &str) {
+    self.messages.push(Message {
+      role: "user".to_string(),
+      content: Some(prompt.to_string()),
+      created_at: Utc::now(),
+      ..Default::default()
+    });
+  }
+
+  pub(crate) fn push_reply(&mut self, model: &str, content: String) {
+    self.messages.push(Message {
+      role: "assistant".to_string(),
+      content: Some(content),
+      created_at: Utc::now(),
+      model: Some(model.to_string()),
+      ..Default::default()
+    });
+  }
+
+  /// Removes the last reply to generate it again, returns false if there is no prompt to reply to
+  pub(crate) fn retry(&mut self) -> bool {
+    if self
+      .messages
+      .last()
+      .is_some_and(|message| message.role == "assistant")
+    {
+      self.messages.pop();
+    }
+    self
+      .messages
+      .last()
+      .is_some_and(|message| message.role == "user")
+  }
+
+  /// Clears the chat, the next save creates a new conversation
+  pub(crate) fn clear(&mut self) {
+    self.messages.clear();
+    self.conversation = None;
+  }
+
+  /// Streamed chat completion request for the chat
+  #[allow(clippy::result_large_err)]
+  pub(crate) fn request(&self, model: &str) -> crate::error::Result<ChatCompletionRequest> {
+    let mut messages = Vec::new();
+    if let Some(system) = &self.system {
+      messages.push(ChatCompletionRequestMessage::System(
+        ChatCompletionRequestSystemMessage {
+          content: system.clone(),
+          role: Role::System,
+          name: None,
+        },
+      ));
+    }
+    for message in &self.messages {
+      let content = message.content.clone().unwrap_or_default();
+      let message = match message.role.as_str() {
+        "assistant" => ChatCompletionRequestMessage::Assistant(
+          ChatCompletionRequestAssistantMessageArgs::default()
+            .content(content)
+            .build()
+            .map_err(BodhiError::BuildError)?,
+        ),
+        _ => ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
+          content: ChatCompletionRequestUserMessageContent::Text(content),
+          role: Role::User,
+          name: None,
+        }),
+      };
+      messages.push(message);
+    }
+    let request = CreateChatCompletionRequestArgs::default()
+      .model(model)
+      .stream(true)
+      .messages(messages)
+      .build()
+      .map_err(BodhiError::BuildError)?;
+    let mut request = ChatCompletionRequest::from(request);
+    self.params.params.update(&mut request);
+    Ok(request)
+  }
+
+  /// The chat as a conversation to save, saving again updates the same conversation
+  pub(crate) fn conversation(&mut self) -> &mut Conversation {
+    let title = self.title();
+    let messages = self
+      .system
+      .iter()
+      .map(|system| Message {
+        role: "system".to_string(),
+        content: Some(system.clone()),
+        created_at: Utc::now(),
+        ..Default::default()
+      })
+      .chain(self.messages.iter().cloned())
+      .collect::<Vec<_>>();
+    let conversation = self.conversation.get_or_insert_with(|| Conversation {
+      title,
+      created_at: Utc::now(),
+      ..Default::default()
+    });
+    conversation.messages = messages;
+    conversation
+  }
+
+  // the first line of the first prompt
+  fn title(&self) -> String {
+    self
+      .messages
+      .iter()
+      .find(|message| message.role == "user")
+      .and_then(|message| message.content.as_deref())
+      .and_then(|content| content.lines().next())
+      .map(|line| line.chars().take(TITLE_MAX_CHARS).collect::<String>())
+      .unwrap_or_else(|| "bodhi run".to_string())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{ChatSession, ReplyStats, SlashCommand};
+  use crate::db::objs::{Conversation, Message};
+  use rstest::rstest;
+  use serde_json::{json, Value};
+  use std::time::Duration;
+
+  #[rstest]
+  #[case("/?", SlashCommand::Help)]
+  #[case("/help", SlashCommand::Help)]
+  #[case("/bye", SlashCommand::Bye)]
+  #[case("/clear", SlashCommand::Clear)]
+  #[case("/save", SlashCommand::Save)]
+  #[case("/retry", SlashCommand::Retry)]
+  #[case("/stats", SlashCommand::Stats)]
+  #[case("/system", SlashCommand::System(None))]
+  #[case("/system You are a pirate.", SlashCommand::System(Some("You are a pirate.".to_string())))]
+  #[case("/model tinyllama:instruct", SlashCommand::Model("tinyllama:instruct".to_string()))]
+  #[case("/set temperature 0.2", SlashCommand::Set { param: "temperature".to_string(), value: "0.2".to_string() })]
+  fn test_slash_command_parse(#[case] input: &str, #[case] expected: SlashCommand) {
+    assert_eq!(Ok(expected), SlashCommand::parse(input));
+  }
+
+  #[rstest]
+  #[case("/model", "usage: /model <alias>")]
+  #[case(
+    "/set temperature",
+    "usage: /set <param> <value>, e.g. /set temperature 0.2"
+  )]
+  #[case(
+    "/unknown",
+    "unknown command `/unknown`. type `/?` for list of commands."
+  )]
+  fn test_slash_command_parse_error(#[case] input: &str, #[case] expected: &str) {
+    assert_eq!(Err(expected.to_string()), SlashCommand::parse(input));
+  }
+
+  #[rstest]
+  fn test_chat_session_request_with_system_and_params() -> anyhow::Result<()> {
+    let mut session = ChatSession {
+      system: Some("You are a pirate.".to_string()),
+      ..Default::default()
+    };
+    session.set_param("temperature", "0.2").unwrap();
+    session.set_param("max_tokens", "100").unwrap();
+    session.push_prompt("What day comes after Monday?");
+    session.push_reply("testalias:instruct", "Tuesday, matey.".to_string());
+    let request = serde_json::to_value(session.request("testalias:instruct")?)?;
+    assert_eq!(json! {0.2f32}, request["temperature"]);
+    assert_eq!(json! {100}, request["max_tokens"]);
+    assert_eq!(json! {true}, request["stream"]);
+    let roles = request["messages"]
+      .as_array()
+      .expect("messages should be an array")
+      .iter()
+      .map(|message| message["role"].clone())
+      .collect::<Vec<Value>>();
+    assert_eq!(
+      vec![json! {"system"}, json! {"user"}, json! {"assistant"}],
+      roles
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("temperature", "3", "between 0 and 2")]
+  #[case("temperature", "hot", "valid floating point number")]
+  #[case("top_k", "lots", "invalid value 'lots'")]
+  #[case("mirostat", "3", "invalid value '3'")]
+  #[case("logprobs", "true", "unknown param `logprobs`")]
+  fn test_chat_session_set_param_invalid(
+    #[case] param: &str,
+    #[case] value: &str,
+    #[case] expected: &str,
+  ) {
+    let mut session = ChatSession::default();
+    let err = session.set_param(param, value).unwrap_err();
+    assert!(err.contains(expected), "unexpected error: {err}");
+  }
+
+  #[rstest]
+  fn test_chat_session_retry_drops_last_reply() {
+    let mut session = ChatSession::default();
+    assert!(!session.retry());
+    session.push_prompt("What day comes after Monday?");
+    session.push_reply("testalias:instruct", "Wednesday".to_string());
+    assert!(session.retry());
+    assert_eq!(1, session.messages.len());
+    assert_eq!("user", session.messages[0].role);
+  }
+
+  #[rstest]
+  fn test_chat_session_conversation_updates_saved_conversation() {
+    let mut session = ChatSession {
+      system: Some("You are a pirate.".to_string()),
+      ..Default::default()
+    };
+    session.push_prompt("What day comes after Monday?\nAnswer in one word.");
+    let conversation = session.conversation();
+    assert_eq!("What day comes after Monday?", conversation.title);
+    assert_eq!(2, conversation.messages.len());
+    conversation.id = "testid".to_string();
+    session.push_reply("testalias:instruct", "Tuesday".to_string());
+    let conversation = session.conversation();
+    assert_eq!("testid", conversation.id);
+    assert_eq!(3, conversation.messages.len());
+    session.clear();
+    assert_eq!("", session.conversation().id);
+  }
+
+  #[rstest]
+  fn test_chat_session_resume_conversation() {
+    let message = |role: &str, content: &str| Message {
+      id: format!("{role}-id"),
+      conversation_id: "testid".to_string(),
+      role: role.to_string(),
+      content: Some(content.to_string()),
+      ..Default::default()
+    };
+    let conversation = Conversation {
+      id: "testid".to_string(),
+      title: "What day comes after Monday?".to_string(),
+      messages: vec![
+        message("system", "You are a pirate."),
+        message("user", "What day comes after Monday?"),
+        message("assistant", "Tuesday, matey."),
+      ],
+      ..Default::default()
+    };
+    let mut session = ChatSession::resume(conversation);
+    assert_eq!(Some("You are a pirate."), session.system.as_deref());
+    assert_eq!(2, session.messages.len());
+    assert_eq!(Some("testid"), session.saved_id());
+    assert_eq!(
+      "system: You are a pirate.\n\n>>> What day comes after Monday?\nTuesday, matey.\n\n",
+      session.transcript()
+    );
+    let conversation = session.conversation();
+    assert_eq!("testid", conversation.id);
+    assert_eq!("system", conversation.messages[0].role);
+    assert_eq!(3, conversation.messages.len());
+  }
+
+  fn chunk(content: &str) -> Value {
+    json! {{"choices": [{"index": 0, "delta": {"content": content}}]}}
+  }
+
+  #[rstest]
+  fn test_reply_stats_uses_reported_usage() {
+    let mut stats = ReplyStats::default();
+    stats.record(&chunk(""), Duration::from_millis(100));
+    stats.record(&chunk(" Tuesday"), Duration::from_millis(500));
+    stats.record(&chunk("."), Duration::from_millis(1000));
+    stats.record(
+      &json! {{
+        "choices": [{"index": 0, "delta": {}, "finish_reason": "stop"}],
+        "usage": {"prompt_tokens": 12, "completion_tokens": 10, "total_tokens": 22},
+      }},
+      Duration::from_millis(2500),
+    );
+    assert_eq!(Some(5.0), stats.tokens_per_sec());
+    assert_eq!(
+      "prompt: 12 tokens, generated: 10 tokens, first token: 0.50s, 5.0 tokens/s, total: 2.50s",
+      stats.to_string()
+    );
+  }
+
+  #[rstest]
+  fn test_reply_stats_counts_chunks_without_usage() {
+    let mut stats = ReplyStats::default();
+    stats.record(&chunk(" Tuesday"), Duration::from_millis(1000));
+    stats.record(&chunk("."), Duration::from_millis(2000));
+    assert_eq!(Some(2.0), stats.tokens_per_sec());
+    assert_eq!(
+      "generated: 2 tokens, first token: 1.00s, 2.0 tokens/s, total: 2.00s",
+      stats.to_string()
+    );
+  }
+}
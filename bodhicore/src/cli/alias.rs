@@ -1,11 +1,15 @@
-use crate::{error::Common, service::AppServiceFn, CliError, Command, StdoutWriter};
-use std::{env, sync::Arc};
+use crate::{
+  error::Common, objs::AliasLint, service::AppServiceFn, AliasAction, CliError, Command,
+  StdoutWriter,
+};
+use std::{fs, path::PathBuf, sync::Arc};
 
 pub enum ManageAliasCommand {
   Show { alias: String },
   Copy { alias: String, new_alias: String },
   Edit { alias: String },
   Delete { alias: String },
+  Lint { file: Option<PathBuf> },
 }
 
 impl TryFrom<Command> for ManageAliasCommand {
@@ -17,6 +21,9 @@ impl TryFrom<Command> for ManageAliasCommand {
       Command::Cp { alias, new_alias } => Ok(ManageAliasCommand::Copy { alias, new_alias }),
       Command::Edit { alias } => Ok(ManageAliasCommand::Edit { alias }),
       Command::Rm { alias } => Ok(ManageAliasCommand::Delete { alias }),
+      Command::Alias {
+        action: AliasAction::Lint { file },
+      } => Ok(ManageAliasCommand::Lint { file }),
       cmd => Err(CliError::ConvertCommand(
         cmd.to_string(),
         "show".to_string(),
@@ -44,6 +51,9 @@ impl ManageAliasCommand {
       ManageAliasCommand::Delete { alias } => {
         self.delete(alias, service, stdout)?;
       }
+      ManageAliasCommand::Lint { file } => {
+        self.lint(file.as_ref(), service, stdout)?;
+      }
     };
     Ok(())
   }
@@ -91,6 +101,53 @@ impl ManageAliasCommand {
     Ok(())
   }
 
+  fn lint(
+    &self,
+    file: Option<&PathBuf>,
+    service: Arc<dyn AppServiceFn>,
+    stdout: &mut dyn StdoutWriter,
+  ) -> crate::error::Result<()> {
+    let lints = match file {
+      Some(file) => {
+        let content = fs::read_to_string(file).map_err(|err| Common::IoFile {
+          source: err,
+          path: file.display().to_string(),
+        })?;
+        vec![AliasLint::new(file.display().to_string(), &content)]
+      }
+      None => service.data_service().lint_aliases()?,
+    };
+    let mut output = String::new();
+    for lint in &lints {
+      if lint.is_valid() {
+        output.push_str(&format!("{}: ok\n", lint.file));
+        continue;
+      }
+      let status = if lint.skipped {
+        "invalid, skipped"
+      } else {
+        "warnings"
+      };
+      output.push_str(&format!("{}: {status}\n", lint.file));
+      for diagnostic in &lint.diagnostics {
+        let line = diagnostic
+          .line
+          .map(|line| format!("line {line}: "))
+          .unwrap_or_default();
+        output.push_str(&format!("  {line}{}\n", diagnostic.message));
+        if let Some(suggestion) = &diagnostic.suggestion {
+          output.push_str(&format!("    help: {suggestion}\n"));
+        }
+      }
+    }
+    stdout.write(&output).map_err(Common::from)?;
+    let invalid = lints.iter().filter(|lint| lint.skipped).count();
+    if invalid > 0 {
+      return Err(crate::BodhiError::AliasLint(invalid));
+    }
+    Ok(())
+  }
+
   fn edit(
     &self,
     alias: &str,
@@ -98,8 +155,8 @@ impl ManageAliasCommand {
     stdout: &mut dyn StdoutWriter,
   ) -> crate::error::Result<()> {
     let filename = service.data_service().alias_filename(alias)?;
-    match env::var("EDITOR") {
-      Ok(editor) => {
+    match service.env_service().editor() {
+      Some(editor) => {
         stdout
           .write(&format!(
             "opening file '{}' in external EDITOR '{}'.\n",
@@ -114,7 +171,7 @@ impl ManageAliasCommand {
           .wait()
           .map_err(Common::from)?;
       }
-      Err(_) => {
+      None => {
         stdout
           .write(&format!(
             "opening file '{}' in using system 'open'.\n",
@@ -137,7 +194,7 @@ impl ManageAliasCommand {
 mod test {
   use crate::{
     test_utils::{app_service_stub, AppServiceTuple},
-    Command, ManageAliasCommand, MockStdoutWriter,
+    AliasAction, BodhiError, Command, ManageAliasCommand, MockStdoutWriter,
   };
   use mockall::predicate::eq;
   use rstest::rstest;
@@ -182,6 +239,45 @@ chat_template: TinyLlama/TinyLlama-1.1B-Chat-v1.0
     Ok(())
   }
 
+  #[rstest]
+  fn test_manage_alias_lint(app_service_stub: AppServiceTuple) -> anyhow::Result<()> {
+    let AppServiceTuple(_temp_bodhi_home, _temp_hf_home, bodhi_home, _, service) = app_service_stub;
+    let invalid = bodhi_home.join("aliases").join("invalid--instruct.yaml");
+    std::fs::write(
+      &invalid,
+      r#"alias: invalid:instruct
+repo: MyFactory/invalid-gguf
+filename: invalid.Q8_0.gguf
+snapshot: main
+features:
+- chat
+chat_tempalte: llama3
+"#,
+    )?;
+    let lint = ManageAliasCommand::try_from(Command::Alias {
+      action: AliasAction::Lint { file: None },
+    })?;
+    let expected = format!(
+      r#"{}: invalid, skipped
+  line 7: unknown field `chat_tempalte`, it is ignored
+    help: did you mean `chat_template`?
+  missing field `chat_template`
+    help: add `chat_template: llama3`
+"#,
+      invalid.display()
+    );
+    let mut mock = MockStdoutWriter::default();
+    mock
+      .expect_write()
+      .withf(move |input| {
+        input.contains(&expected) && input.contains("llama3--instruct.yaml: ok\n")
+      })
+      .return_once(|input| Ok(input.len()));
+    let result = lint.execute(Arc::new(service), &mut mock);
+    assert!(matches!(result, Err(BodhiError::AliasLint(1))));
+    Ok(())
+  }
+
   #[rstest]
   fn test_manage_alias_copy(app_service_stub: AppServiceTuple) -> anyhow::Result<()> {
     let AppServiceTuple(_temp_bodhi_home, _temp_hf_home, bodhi_home, _, service) = app_service_stub;
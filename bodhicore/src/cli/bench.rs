@@ -0,0 +1,327 @@
+use super::{CliError, StdoutWriter};
+#[cfg(not(test))]
+use crate::interactive::InteractiveRuntime;
+#[cfg(test)]
+use crate::test_utils::MockInteractiveRuntime as InteractiveRuntime;
+use crate::{
+  error::{BodhiError, Common},
+  interactive::{BenchOptions, BenchResult},
+  objs::GptContextParams,
+  service::AppServiceFn,
+  Command,
+};
+use prettytable::{format, row, Table};
+use serde::Serialize;
+use std::sync::Arc;
+
+#[derive(Debug, PartialEq)]
+pub struct BenchCommand {
+  alias: String,
+  threads: Vec<u32>,
+  n_gpu_layers: Vec<i32>,
+  options: BenchOptions,
+  json: bool,
+}
+
+/// Benchmark result for one combination of the threads and the GPU layers
+#[derive(Debug, Serialize)]
+struct BenchRow {
+  n_threads: Option<u32>,
+  n_gpu_layers: Option<i32>,
+  #[serde(flatten)]
+  result: BenchResult,
+}
+
+impl TryFrom<Command> for BenchCommand {
+  type Error = CliError;
+
+  fn try_from(value: Command) -> Result<Self, Self::Error> {
+    match value {
+      Command::Bench {
+        alias,
+        threads,
+        n_gpu_layers,
+        n_prompt,
+        n_gen,
+        json,
+      } => {
+        if n_prompt == 0 || n_gen == 0 {
+          return Err(CliError::BadRequest(
+            "--n-prompt and --n-gen should be greater than 0".to_string(),
+          ));
+        }
+        Ok(BenchCommand {
+          alias,
+          threads,
+          n_gpu_layers,
+          options: BenchOptions { n_prompt, n_gen },
+          json,
+        })
+      }
+      cmd => Err(CliError::ConvertCommand(
+        cmd.to_string(),
+        "bench".to_string(),
+      )),
+    }
+  }
+}
+
+impl BenchCommand {
+  #[allow(clippy::result_large_err)]
+  pub fn execute(
+    self,
+    service: Arc<dyn AppServiceFn>,
+    stdout: &mut dyn StdoutWriter,
+  ) -> crate::error::Result<()> {
+    let alias = service
+      .data_service()
+      .find_alias(&self.alias)
+      .ok_or_else(|| BodhiError::AliasNotFound(self.alias.clone()))?;
+    let runtime = InteractiveRuntime::new();
+    let mut rows = Vec::<BenchRow>::new();
+    for context_params in self.context_params(&alias.context_params) {
+      let mut alias = alias.clone();
+      let n_threads = context_params.n_threads;
+      let n_gpu_layers = context_params.n_gpu_layers;
+      alias.context_params = context_params;
+      let result = runtime.bench(alias, service.clone(), self.options.clone())?;
+      rows.push(BenchRow {
+        n_threads,
+        n_gpu_layers,
+        result,
+      });
+    }
+    let output = if self.json {
+      let json = serde_json::to_string_pretty(&rows).map_err(Common::SerdeJsonDeserialize)?;
+      format!("{json}\n")
+    } else {
+      self.table(&rows).to_string()
+    };
+    stdout.write(&output).map_err(Common::Io)?;
+    Ok(())
+  }
+
+  // every combination of the threads and the GPU layers, benchmarked with a single sequence,
+  // and a context large enough for the chat template and the words taking more than a token
+  fn context_params(&self, base: &GptContextParams) -> Vec<GptContextParams> {
+    let threads = match self.threads.is_empty() {
+      true => vec![base.n_threads],
+      false => self.threads.iter().copied().map(Some).collect(),
+    };
+    let n_gpu_layers = match self.n_gpu_layers.is_empty() {
+      true => vec![base.n_gpu_layers],
+      false => self.n_gpu_layers.iter().copied().map(Some).collect(),
+    };
+    let n_ctx = self
+      .options
+      .n_prompt
+      .saturating_add(u32::from(self.options.n_gen))
+      .saturating_mul(2);
+    let n_ctx = i32::try_from(n_ctx)
+      .unwrap_or(i32::MAX)
+      .max(base.n_ctx.unwrap_or_default());
+    threads
+      .iter()
+      .flat_map(|n_threads| {
+        n_gpu_layers
+          .iter()
+          .map(move |n_gpu_layers| GptContextParams {
+            n_threads: *n_threads,
+            n_gpu_layers: *n_gpu_layers,
+            n_ctx: Some(n_ctx),
+            n_parallel: Some(1),
+            n_predict: None,
+            ..base.clone()
+          })
+      })
+      .collect()
+  }
+
+  fn table(&self, rows: &[BenchRow]) -> Table {
+    let mut table = Table::new();
+    table.add_row(row![
+      "THREADS",
+      "GPU LAYERS",
+      "LOAD",
+      "MEMORY",
+      format!("PP{} T/S", self.options.n_prompt),
+      format!("TG{} T/S", self.options.n_gen),
+    ]);
+    for row in rows {
+      let BenchResult {
+        load_secs,
+        memory_bytes,
+        prompt_tokens_per_sec,
+        generated_tokens_per_sec,
+        ..
+      } = &row.result;
+      table.add_row(row![
+        or_default(row.n_threads),
+        or_default(row.n_gpu_layers),
+        format!("{load_secs:.2}s"),
+        memory_bytes
+          .map(|size| format!("{:.2} GB", size as f64 / 2_f64.powf(30.0)))
+          .unwrap_or_else(|| String::from("-")),
+        tokens_per_sec(*prompt_tokens_per_sec),
+        tokens_per_sec(*generated_tokens_per_sec),
+      ]);
+    }
+    table.set_format(format::FormatBuilder::default().padding(2, 2).build());
+    table
+  }
+}
+
+fn or_default<T: ToString>(value: Option<T>) -> String {
+  value
+    .map(|value| value.to_string())
+    .unwrap_or_else(|| String::from("default"))
+}
+
+fn tokens_per_sec(value: Option<f64>) -> String {
+  value
+    .map(|value| format!("{value:.1}"))
+    .unwrap_or_else(|| String::from("-"))
+}
+
+#[cfg(test)]
+mod test {
+  use super::BenchCommand;
+  use crate::{
+    interactive::{BenchOptions, BenchResult},
+    objs::{Alias, GptContextParams},
+    service::{MockDataService, MockEnvServiceFn, MockHubService},
+    test_utils::{AppServiceStubMock, MockInteractiveRuntime},
+    Command, MockStdoutWriter,
+  };
+  use mockall::predicate::eq;
+  use rstest::rstest;
+  use serial_test::serial;
+  use std::sync::Arc;
+
+  fn bench_command(threads: Vec<u32>, n_gpu_layers: Vec<i32>, json: bool) -> BenchCommand {
+    BenchCommand {
+      alias: "testalias:instruct".to_string(),
+      threads,
+      n_gpu_layers,
+      options: BenchOptions {
+        n_prompt: 512,
+        n_gen: 128,
+      },
+      json,
+    }
+  }
+
+  #[rstest]
+  fn test_bench_command_from_cli() -> anyhow::Result<()> {
+    let command = BenchCommand::try_from(Command::Bench {
+      alias: "testalias:instruct".to_string(),
+      threads: vec![4, 8],
+      n_gpu_layers: vec![],
+      n_prompt: 512,
+      n_gen: 128,
+      json: true,
+    })?;
+    assert_eq!(bench_command(vec![4, 8], vec![], true), command);
+    let result = BenchCommand::try_from(Command::Bench {
+      alias: "testalias:instruct".to_string(),
+      threads: vec![],
+      n_gpu_layers: vec![],
+      n_prompt: 0,
+      n_gen: 128,
+      json: false,
+    });
+    assert_eq!(
+      "--n-prompt and --n-gen should be greater than 0",
+      result.unwrap_err().to_string()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_bench_command_context_params_combinations() {
+    let base = GptContextParams {
+      n_threads: Some(2),
+      n_ctx: Some(4096),
+      n_parallel: Some(4),
+      n_keep: Some(24),
+      ..Default::default()
+    };
+    let params = bench_command(vec![4, 8], vec![0, 99], false).context_params(&base);
+    let actual = params
+      .iter()
+      .map(|params| (params.n_threads, params.n_gpu_layers))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      vec![
+        (Some(4), Some(0)),
+        (Some(4), Some(99)),
+        (Some(8), Some(0)),
+        (Some(8), Some(99)),
+      ],
+      actual
+    );
+    assert!(params.iter().all(|params| params.n_ctx == Some(4096)
+      && params.n_parallel == Some(1)
+      && params.n_keep == Some(24)));
+  }
+
+  #[rstest]
+  fn test_bench_command_context_params_defaults_to_alias() {
+    let base = GptContextParams {
+      n_threads: Some(6),
+      ..Default::default()
+    };
+    let params = bench_command(vec![], vec![], false).context_params(&base);
+    assert_eq!(1, params.len());
+    assert_eq!(Some(6), params[0].n_threads);
+    assert_eq!(None, params[0].n_gpu_layers);
+    assert_eq!(Some(1280), params[0].n_ctx);
+  }
+
+  #[rstest]
+  #[serial(InteractiveRuntime)]
+  fn test_bench_command_prints_results() -> anyhow::Result<()> {
+    let mut mock_data_service = MockDataService::new();
+    mock_data_service
+      .expect_find_alias()
+      .with(eq("testalias:instruct"))
+      .return_once(|_| Some(Alias::testalias()));
+    let mut mock_interactive = MockInteractiveRuntime::default();
+    mock_interactive
+      .expect_bench()
+      .withf(|alias, _, options| {
+        alias.context_params.n_threads == Some(4) && options.n_prompt == 512
+      })
+      .times(1)
+      .return_once(|_, _, _| {
+        Ok(BenchResult {
+          load_secs: 1.5,
+          memory_bytes: None,
+          prompt_tokens: Some(520),
+          prompt_tokens_per_sec: Some(250.0),
+          generated_tokens: 128,
+          generated_tokens_per_sec: Some(20.5),
+        })
+      });
+    let ctx = MockInteractiveRuntime::new_context();
+    ctx.expect().return_once(move || mock_interactive);
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .withf(|input| {
+        input.contains("PP512 T/S")
+          && input.contains("TG128 T/S")
+          && input.contains("1.50s")
+          && input.contains("250.0")
+          && input.contains("20.5")
+      })
+      .return_once(|input| Ok(input.len()));
+    let service = AppServiceStubMock::new(
+      MockEnvServiceFn::new(),
+      MockHubService::new(),
+      mock_data_service,
+    );
+    bench_command(vec![4], vec![], false).execute(Arc::new(service), &mut stdout)?;
+    Ok(())
+  }
+}
@@ -0,0 +1,487 @@
+use super::{CliError, StdoutWriter};
+use crate::{
+  error::{BodhiError, Common},
+  objs::{Repo, REFS, REFS_MAIN},
+  service::AppServiceFn,
+  utils::human_size,
+  CacheAction, Command,
+};
+use prettytable::{format, row, Table};
+use std::{
+  collections::HashSet,
+  fs,
+  path::{Path, PathBuf},
+  sync::Arc,
+};
+use walkdir::WalkDir;
+
+static MODELS_PREFIX: &str = "models--";
+static SNAPSHOTS: &str = "snapshots";
+static NO_EXIST: &str = ".no_exist";
+static BLOBS: &str = "blobs";
+
+#[derive(Debug, PartialEq)]
+pub enum CacheCommand {
+  Usage,
+  Prune { dry_run: bool },
+}
+
+impl TryFrom<Command> for CacheCommand {
+  type Error = CliError;
+
+  fn try_from(value: Command) -> Result<Self, Self::Error> {
+    match value {
+      Command::Cache { action } => match action {
+        CacheAction::Usage {} => Ok(CacheCommand::Usage),
+        CacheAction::Prune { dry_run } => Ok(CacheCommand::Prune { dry_run }),
+      },
+      cmd => Err(CliError::ConvertCommand(
+        cmd.to_string(),
+        "cache".to_string(),
+      )),
+    }
+  }
+}
+
+impl CacheCommand {
+  #[allow(clippy::result_large_err)]
+  pub fn execute(
+    self,
+    service: Arc<dyn AppServiceFn>,
+    stdout: &mut dyn StdoutWriter,
+  ) -> crate::error::Result<()> {
+    match self {
+      CacheCommand::Usage => self.usage(service, stdout)?,
+      CacheCommand::Prune { dry_run } => self.prune(dry_run, service, stdout)?,
+    }
+    Ok(())
+  }
+
+  fn usage(
+    &self,
+    service: Arc<dyn AppServiceFn>,
+    stdout: &mut dyn StdoutWriter,
+  ) -> crate::error::Result<()> {
+    let hf_cache = service.env_service().hf_cache();
+    let bodhi_home = service.env_service().bodhi_home();
+    let mut repos = repo_dirs(&hf_cache)?
+      .into_iter()
+      .map(|(repo, path)| (repo, dir_size(&path)))
+      .collect::<Vec<_>>();
+    repos.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let hf_cache_size = repos.iter().map(|(_, size)| size).sum::<u64>();
+    let mut table = Table::new();
+    table.add_row(row!["REPO", "SIZE"]);
+    for (repo, size) in repos {
+      table.add_row(row![repo, human_size(size)]);
+    }
+    table.set_format(format::FormatBuilder::default().padding(2, 2).build());
+    stdout.write(&table.to_string()).map_err(Common::from)?;
+    stdout
+      .write(&format!(
+        "\nhuggingface cache '{}': {}\nbodhi home '{}': {}\n",
+        hf_cache.display(),
+        human_size(hf_cache_size),
+        bodhi_home.display(),
+        human_size(dir_size(&bodhi_home)),
+      ))
+      .map_err(Common::from)?;
+    Ok(())
+  }
+
+  fn prune(
+    &self,
+    dry_run: bool,
+    service: Arc<dyn AppServiceFn>,
+    stdout: &mut dyn StdoutWriter,
+  ) -> crate::error::Result<()> {
+    let action = if dry_run { "would remove" } else { "removed" };
+    let referenced = referenced_snapshots(service.clone())?;
+    let mut freed = 0;
+    for (repo, repo_dir) in repo_dirs(&service.env_service().hf_cache())? {
+      let keep = referenced
+        .iter()
+        .filter(|(referenced_repo, _)| referenced_repo == &repo)
+        .filter_map(|(_, snapshot)| resolve_snapshot(&repo_dir, snapshot))
+        .collect::<HashSet<_>>();
+      let (kept, pruned): (Vec<_>, Vec<_>) = snapshots(&repo_dir)?
+        .into_iter()
+        .partition(|snapshot| keep.contains(snapshot));
+      if pruned.is_empty() {
+        continue;
+      }
+      if kept.is_empty() {
+        let size = dir_size(&repo_dir);
+        if !dry_run {
+          remove_dir(&repo_dir)?;
+        }
+        stdout
+          .write(&format!("{action} repo '{repo}' ({})\n", human_size(size)))
+          .map_err(Common::from)?;
+        freed += size;
+        continue;
+      }
+      let kept_blobs = kept
+        .iter()
+        .flat_map(|snapshot| linked_blobs(&repo_dir, &repo_dir.join(SNAPSHOTS).join(snapshot)))
+        .collect::<HashSet<_>>();
+      let mut pruned_blobs = HashSet::new();
+      for snapshot in pruned {
+        let snapshot_dir = repo_dir.join(SNAPSHOTS).join(&snapshot);
+        let blobs = linked_blobs(&repo_dir, &snapshot_dir)
+          .into_iter()
+          .filter(|blob| !kept_blobs.contains(blob) && pruned_blobs.insert(blob.clone()))
+          .collect::<Vec<_>>();
+        let size = dir_size(&snapshot_dir)
+          + blobs
+            .iter()
+            .filter_map(|blob| fs::metadata(blob).ok())
+            .map(|metadata| metadata.len())
+            .sum::<u64>();
+        if !dry_run {
+          remove_dir(&snapshot_dir)?;
+          let no_exist_dir = repo_dir.join(NO_EXIST).join(&snapshot);
+          if no_exist_dir.exists() {
+            remove_dir(&no_exist_dir)?;
+          }
+          for blob in &blobs {
+            fs::remove_file(blob).map_err(|err| Common::IoFile {
+              source: err,
+              path: blob.display().to_string(),
+            })?;
+          }
+        }
+        stdout
+          .write(&format!(
+            "{action} snapshot '{snapshot}' of repo '{repo}' ({})\n",
+            human_size(size)
+          ))
+          .map_err(Common::from)?;
+        freed += size;
+      }
+    }
+    let summary = if freed == 0 {
+      "nothing to prune, all snapshots in huggingface cache are used by model aliases\n".to_string()
+    } else if dry_run {
+      format!(
+        "{} can be freed, run `bodhi cache prune` without --dry-run to remove\n",
+        human_size(freed)
+      )
+    } else {
+      format!("{} freed\n", human_size(freed))
+    };
+    stdout.write(&summary).map_err(Common::from)?;
+    Ok(())
+  }
+}
+
+/// (repo, snapshot) pairs used by the model aliases, including the tokenizer repo of the chat template.
+/// Fails if an alias file is skipped for being invalid, as the files it uses would be pruned.
+#[allow(clippy::result_large_err)]
+fn referenced_snapshots(
+  service: Arc<dyn AppServiceFn>,
+) -> crate::error::Result<HashSet<(String, String)>> {
+  let skipped = service
+    .data_service()
+    .lint_aliases()?
+    .into_iter()
+    .filter(|lint| lint.skipped)
+    .count();
+  if skipped > 0 {
+    return Err(BodhiError::PruneSkippedAliases(skipped));
+  }
+  let mut referenced = HashSet::new();
+  for alias in service.data_service().list_aliases()? {
+    if let Ok(tokenizer_repo) = Repo::try_from(alias.chat_template.clone()) {
+      referenced.insert((tokenizer_repo.to_string(), REFS_MAIN.to_string()));
+    }
+    referenced.insert((alias.repo.to_string(), alias.snapshot));
+  }
+  Ok(referenced)
+}
+
+fn resolve_snapshot(repo_dir: &Path, snapshot: &str) -> Option<String> {
+  if snapshot.starts_with(REFS) {
+    fs::read_to_string(repo_dir.join(snapshot))
+      .ok()
+      .map(|snapshot| snapshot.trim().to_string())
+  } else {
+    Some(snapshot.to_string())
+  }
+}
+
+#[allow(clippy::result_large_err)]
+fn repo_dirs(hf_cache: &Path) -> crate::error::Result<Vec<(String, PathBuf)>> {
+  let mut repos = list_dirs(hf_cache)?
+    .into_iter()
+    .filter_map(|name| {
+      let repo = name.strip_prefix(MODELS_PREFIX)?.replacen("--", "/", 1);
+      Some((repo, hf_cache.join(name)))
+    })
+    .collect::<Vec<_>>();
+  repos.sort();
+  Ok(repos)
+}
+
+#[allow(clippy::result_large_err)]
+fn snapshots(repo_dir: &Path) -> crate::error::Result<Vec<String>> {
+  let mut snapshots = list_dirs(&repo_dir.join(SNAPSHOTS))?;
+  snapshots.sort();
+  Ok(snapshots)
+}
+
+#[allow(clippy::result_large_err)]
+fn list_dirs(dir: &Path) -> crate::error::Result<Vec<String>> {
+  if !dir.is_dir() {
+    return Ok(vec![]);
+  }
+  let entries = fs::read_dir(dir).map_err(|err| Common::IoFile {
+    source: err,
+    path: dir.display().to_string(),
+  })?;
+  let dirs = entries
+    .filter_map(|entry| entry.ok())
+    .filter(|entry| entry.path().is_dir())
+    .map(|entry| entry.file_name().to_string_lossy().into_owned())
+    .collect::<Vec<_>>();
+  Ok(dirs)
+}
+
+/// blob files of the repo the symlinks in the snapshot dir point to, the local files linked using
+/// `bodhi create --file` or `bodhi import ollama` are outside the blobs dir and never returned
+fn linked_blobs(repo_dir: &Path, snapshot_dir: &Path) -> Vec<PathBuf> {
+  let Ok(blobs_dir) = fs::canonicalize(repo_dir.join(BLOBS)) else {
+    return vec![];
+  };
+  WalkDir::new(snapshot_dir)
+    .into_iter()
+    .filter_map(|entry| entry.ok())
+    .filter(|entry| entry.path_is_symlink())
+    .filter_map(|entry| fs::canonicalize(entry.path()).ok())
+    .filter(|blob| blob.starts_with(&blobs_dir))
+    .collect()
+}
+
+/// size of the files in dir, symlinks are not followed so blobs are counted once
+fn dir_size(dir: &Path) -> u64 {
+  WalkDir::new(dir)
+    .into_iter()
+    .filter_map(|entry| entry.ok())
+    .filter(|entry| entry.file_type().is_file())
+    .filter_map(|entry| entry.metadata().ok())
+    .map(|metadata| metadata.len())
+    .sum()
+}
+
+#[allow(clippy::result_large_err)]
+fn remove_dir(dir: &Path) -> crate::error::Result<()> {
+  fs::remove_dir_all(dir).map_err(|err| Common::IoFile {
+    source: err,
+    path: dir.display().to_string(),
+  })?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use super::{human_size, CacheCommand};
+  use crate::{
+    error::BodhiError,
+    objs::{Alias, AliasLint, Repo},
+    service::{HubServiceFn, MockDataService, MockEnvServiceFn, MockHubService},
+    test_utils::{
+      app_service_stub, hf_cache, hub_service, AppServiceStubMock, AppServiceTuple, HubServiceTuple,
+    },
+    CacheAction, Command, StdoutWriter,
+  };
+  use rstest::rstest;
+  use std::{fs, io, path::PathBuf, sync::Arc};
+  use tempfile::TempDir;
+
+  #[derive(Default)]
+  struct StringWriter(String);
+
+  impl StdoutWriter for StringWriter {
+    fn write(&mut self, str: &str) -> io::Result<usize> {
+      self.0.push_str(str);
+      Ok(str.len())
+    }
+  }
+
+  #[rstest]
+  #[case(CacheAction::Usage {}, CacheCommand::Usage)]
+  #[case(CacheAction::Prune { dry_run: true }, CacheCommand::Prune { dry_run: true })]
+  fn test_cache_command_try_from(
+    #[case] action: CacheAction,
+    #[case] expected: CacheCommand,
+  ) -> anyhow::Result<()> {
+    let result = CacheCommand::try_from(Command::Cache { action })?;
+    assert_eq!(expected, result);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(0, "0 B")]
+  #[case(1023, "1023 B")]
+  #[case(1536, "1.50 KB")]
+  #[case(10 * 1024 * 1024 * 1024, "10.00 GB")]
+  fn test_cache_human_size(#[case] size: u64, #[case] expected: &str) {
+    assert_eq!(expected, human_size(size));
+  }
+
+  #[rstest]
+  fn test_cache_usage(app_service_stub: AppServiceTuple) -> anyhow::Result<()> {
+    let AppServiceTuple(_temp_bodhi_home, _temp_hf_home, bodhi_home, hf_cache, service) =
+      app_service_stub;
+    let mut stdout = StringWriter::default();
+    CacheCommand::Usage.execute(Arc::new(service), &mut stdout)?;
+    assert!(stdout.0.contains("MyFactory/testalias-gguf"));
+    assert!(stdout.0.contains("meta-llama/Llama-2-70b-chat-hf"));
+    assert!(stdout
+      .0
+      .contains(&format!("huggingface cache '{}'", hf_cache.display())));
+    assert!(stdout
+      .0
+      .contains(&format!("bodhi home '{}'", bodhi_home.display())));
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_cache_prune_dry_run_keeps_files(app_service_stub: AppServiceTuple) -> anyhow::Result<()> {
+    let AppServiceTuple(_temp_bodhi_home, _temp_hf_home, _, hf_cache, service) = app_service_stub;
+    let mut stdout = StringWriter::default();
+    CacheCommand::Prune { dry_run: true }.execute(Arc::new(service), &mut stdout)?;
+    assert!(stdout
+      .0
+      .contains("would remove repo 'MyFactory/testalias-gguf'"));
+    assert!(!stdout.0.contains("meta-llama/Meta-Llama-3-8B-Instruct"));
+    assert!(hf_cache.join("models--MyFactory--testalias-gguf").exists());
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_cache_prune_removes_unreferenced(
+    app_service_stub: AppServiceTuple,
+  ) -> anyhow::Result<()> {
+    let AppServiceTuple(_temp_bodhi_home, _temp_hf_home, _, hf_cache, service) = app_service_stub;
+    let mut stdout = StringWriter::default();
+    CacheCommand::Prune { dry_run: false }.execute(Arc::new(service), &mut stdout)?;
+    assert!(stdout.0.contains("removed repo 'MyFactory/testalias-gguf'"));
+    assert!(!hf_cache.join("models--MyFactory--testalias-gguf").exists());
+    // tokenizer repo of the llama3 chat template used by aliases is kept
+    assert!(hf_cache
+      .join("models--meta-llama--Meta-Llama-3-8B-Instruct/snapshots/c4a54320a52ed5f88b7a2f84496903ea4ff07b45")
+      .exists());
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_cache_prune_removes_unreferenced_snapshot_and_blobs(
+    hf_cache: (TempDir, PathBuf),
+  ) -> anyhow::Result<()> {
+    let (_temp_hf_home, hf_cache) = hf_cache;
+    let hf_cache_clone = hf_cache.clone();
+    let mut env_service = MockEnvServiceFn::new();
+    env_service
+      .expect_hf_cache()
+      .returning(move || hf_cache_clone.clone());
+    let mut data_service = MockDataService::new();
+    data_service
+      .expect_lint_aliases()
+      .return_once(|| Ok(vec![]));
+    data_service.expect_list_aliases().return_once(|| {
+      Ok(vec![Alias {
+        repo: Repo::try_from("meta-llama/Llama-2-70b-chat-hf").unwrap(),
+        snapshot: "e9149a12809580e8602995856f8098ce973d1080".to_string(),
+        ..Alias::testalias()
+      }])
+    });
+    let service = AppServiceStubMock::new(env_service, MockHubService::new(), data_service);
+    let mut stdout = StringWriter::default();
+    CacheCommand::Prune { dry_run: false }.execute(Arc::new(service), &mut stdout)?;
+    let repo_dir = hf_cache.join("models--meta-llama--Llama-2-70b-chat-hf");
+    assert!(!repo_dir
+      .join("snapshots/9ff8b00464fc439a64bb374769dec3dd627be1c2")
+      .exists());
+    assert!(repo_dir
+      .join("blobs/a0024735c8dd7afe47fe72792b2c4edaff63bd3b")
+      .exists());
+    assert!(repo_dir
+      .join("snapshots/e9149a12809580e8602995856f8098ce973d1080/tokenizer_config.json")
+      .exists());
+    assert!(stdout.0.contains(
+      "removed snapshot '9ff8b00464fc439a64bb374769dec3dd627be1c2' of repo 'meta-llama/Llama-2-70b-chat-hf'"
+    ));
+    assert!(!hf_cache.join("models--MyFactory--testalias-gguf").exists());
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_cache_prune_keeps_linked_local_files(hub_service: HubServiceTuple) -> anyhow::Result<()> {
+    let HubServiceTuple(_temp_hf_home, hf_cache, hub_service) = hub_service;
+    let temp_models = TempDir::new()?;
+    let old_file = temp_models.path().join("mymodel.Q4_0.gguf");
+    let new_file = temp_models.path().join("mymodel.Q8_0.gguf");
+    fs::write(&old_file, "GGUF old model")?;
+    fs::write(&new_file, "GGUF new model")?;
+    let repo = Repo::try_from("local/mymodel")?;
+    hub_service.link_local_file(&old_file, &repo, "mymodel.gguf", "0000000000000001", false)?;
+    hub_service.link_local_file(&new_file, &repo, "mymodel.gguf", "0000000000000002", false)?;
+    let hf_cache_clone = hf_cache.clone();
+    let mut env_service = MockEnvServiceFn::new();
+    env_service
+      .expect_hf_cache()
+      .returning(move || hf_cache_clone.clone());
+    let mut data_service = MockDataService::new();
+    data_service
+      .expect_lint_aliases()
+      .return_once(|| Ok(vec![]));
+    data_service.expect_list_aliases().return_once(move || {
+      Ok(vec![Alias {
+        repo,
+        filename: "mymodel.gguf".to_string(),
+        snapshot: "0000000000000002".to_string(),
+        ..Alias::testalias()
+      }])
+    });
+    let service = AppServiceStubMock::new(env_service, MockHubService::new(), data_service);
+    let mut stdout = StringWriter::default();
+    CacheCommand::Prune { dry_run: false }.execute(Arc::new(service), &mut stdout)?;
+    assert!(stdout
+      .0
+      .contains("removed snapshot '0000000000000001' of repo 'local/mymodel'"));
+    assert!(!hf_cache
+      .join("models--local--mymodel/snapshots/0000000000000001")
+      .exists());
+    assert_eq!("GGUF old model", fs::read_to_string(&old_file)?);
+    assert_eq!("GGUF new model", fs::read_to_string(&new_file)?);
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_cache_prune_fails_with_skipped_alias_files(
+    hf_cache: (TempDir, PathBuf),
+  ) -> anyhow::Result<()> {
+    let (_temp_hf_home, hf_cache) = hf_cache;
+    let hf_cache_clone = hf_cache.clone();
+    let mut env_service = MockEnvServiceFn::new();
+    env_service
+      .expect_hf_cache()
+      .returning(move || hf_cache_clone.clone());
+    let mut data_service = MockDataService::new();
+    data_service.expect_lint_aliases().return_once(|| {
+      Ok(vec![AliasLint {
+        file: "testalias.yaml".to_string(),
+        skipped: true,
+        diagnostics: vec![],
+      }])
+    });
+    data_service.expect_list_aliases().never();
+    let service = AppServiceStubMock::new(env_service, MockHubService::new(), data_service);
+    let mut stdout = StringWriter::default();
+    let result = CacheCommand::Prune { dry_run: false }.execute(Arc::new(service), &mut stdout);
+    assert!(matches!(result, Err(BodhiError::PruneSkippedAliases(1))));
+    assert!(hf_cache.join("models--MyFactory--testalias-gguf").exists());
+    Ok(())
+  }
+}
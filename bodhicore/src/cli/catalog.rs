@@ -0,0 +1,191 @@
+use super::{CatalogAction, CliError, StdoutWriter};
+use crate::{
+  error::{BodhiError, Common},
+  service::{AppServiceFn, DataServiceError, ProxyConfig, BODHI_CATALOG_URLS},
+  Command,
+};
+use std::sync::Arc;
+
+#[derive(Debug, PartialEq)]
+pub enum CatalogCommand {
+  Update,
+}
+
+impl TryFrom<Command> for CatalogCommand {
+  type Error = CliError;
+
+  fn try_from(value: Command) -> Result<Self, Self::Error> {
+    match value {
+      Command::Catalog {
+        action: CatalogAction::Update {},
+      } => Ok(CatalogCommand::Update),
+      cmd => Err(CliError::ConvertCommand(
+        cmd.to_string(),
+        "catalog".to_string(),
+      )),
+    }
+  }
+}
+
+impl CatalogCommand {
+  #[allow(clippy::result_large_err)]
+  pub fn execute(
+    self,
+    service: Arc<dyn AppServiceFn>,
+    proxy: &ProxyConfig,
+    stdout: &mut dyn StdoutWriter,
+  ) -> crate::error::Result<()> {
+    match self {
+      CatalogCommand::Update => update_catalogs(service, proxy, stdout),
+    }
+  }
+}
+
+// downloads every catalog, and fails with the last error if any of them could not be updated
+#[allow(clippy::result_large_err)]
+fn update_catalogs(
+  service: Arc<dyn AppServiceFn>,
+  proxy: &ProxyConfig,
+  stdout: &mut dyn StdoutWriter,
+) -> crate::error::Result<()> {
+  let urls = service.env_service().catalog_urls();
+  if urls.is_empty() {
+    stdout
+      .write(&format!(
+        "No model catalogs configured, set ${BODHI_CATALOG_URLS} to a comma separated list of catalog urls\n"
+      ))
+      .map_err(Common::Io)?;
+    return Ok(());
+  }
+  let mut result = Ok(());
+  for url in urls {
+    let updated = fetch_catalog(&url, proxy).and_then(|contents| {
+      service
+        .data_service()
+        .save_catalog(&catalog_name(&url), &contents)
+    });
+    let message = match updated {
+      Ok(count) => format!("catalog '{url}' updated with {count} model aliases\n"),
+      Err(err) => {
+        let message = format!("catalog '{url}' could not be updated: {err}\n");
+        result = Err(BodhiError::from(err));
+        message
+      }
+    };
+    stdout.write(&message).map_err(Common::Io)?;
+  }
+  result
+}
+
+fn fetch_catalog(url: &str, proxy: &ProxyConfig) -> Result<String, DataServiceError> {
+  let fetch_err = |reason: String| DataServiceError::CatalogFetch {
+    url: url.to_string(),
+    reason,
+  };
+  let mut builder = ureq::AgentBuilder::new();
+  if let Some(proxy) = proxy.proxy_for(url_host(url)) {
+    let proxy = ureq::Proxy::new(proxy).map_err(|err| fetch_err(err.to_string()))?;
+    builder = builder.proxy(proxy);
+  }
+  builder
+    .build()
+    .get(url)
+    .call()
+    .map_err(|err| fetch_err(err.to_string()))?
+    .into_string()
+    .map_err(|err| fetch_err(err.to_string()))
+}
+
+fn url_host(url: &str) -> &str {
+  let url = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+  url.split('/').next().unwrap_or_default()
+}
+
+/// Name of the downloaded catalog file, e.g. `models.example.com/team.yaml` for `https://models.example.com/team.yaml`
+fn catalog_name(url: &str) -> String {
+  let name = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+  let name = name.trim_end_matches('/');
+  name
+    .strip_suffix(".yaml")
+    .or_else(|| name.strip_suffix(".yml"))
+    .unwrap_or(name)
+    .to_string()
+}
+
+#[cfg(test)]
+mod test {
+  use super::{catalog_name, url_host, CatalogCommand};
+  use crate::{
+    service::{MockDataService, MockEnvServiceFn, MockHubService, ProxyConfig},
+    test_utils::AppServiceStubMock,
+    CatalogAction, Command, MockStdoutWriter,
+  };
+  use mockall::predicate::eq;
+  use rstest::rstest;
+  use std::sync::Arc;
+
+  #[rstest]
+  fn test_catalog_command_from_cli() -> anyhow::Result<()> {
+    let command = CatalogCommand::try_from(Command::Catalog {
+      action: CatalogAction::Update {},
+    })?;
+    assert_eq!(CatalogCommand::Update, command);
+    let result = CatalogCommand::try_from(Command::App { ui: false });
+    assert_eq!(
+      "Command 'app' cannot be converted into command 'catalog'",
+      result.unwrap_err().to_string()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("https://models.example.com/team.yaml", "models.example.com/team")]
+  #[case("http://10.0.0.1:8080/catalogs/llm.yml", "10.0.0.1:8080/catalogs/llm")]
+  #[case("https://models.example.com/catalog/", "models.example.com/catalog")]
+  fn test_catalog_name(#[case] url: &str, #[case] expected: &str) {
+    assert_eq!(expected, catalog_name(url));
+  }
+
+  #[rstest]
+  #[case("https://models.example.com/team.yaml", "models.example.com")]
+  #[case("http://10.0.0.1:8080/catalogs/llm.yml", "10.0.0.1:8080")]
+  fn test_catalog_url_host(#[case] url: &str, #[case] expected: &str) {
+    assert_eq!(expected, url_host(url));
+  }
+
+  #[rstest]
+  fn test_catalog_update_without_urls() -> anyhow::Result<()> {
+    let mut env_service = MockEnvServiceFn::new();
+    env_service.expect_catalog_urls().return_once(Vec::new);
+    let service =
+      AppServiceStubMock::new(env_service, MockHubService::new(), MockDataService::new());
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .with(eq("No model catalogs configured, set $BODHI_CATALOG_URLS to a comma separated list of catalog urls\n"))
+      .return_once(|input| Ok(input.len()));
+    CatalogCommand::Update.execute(Arc::new(service), &ProxyConfig::default(), &mut stdout)?;
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_catalog_update_reports_unreachable_catalog() -> anyhow::Result<()> {
+    let mut env_service = MockEnvServiceFn::new();
+    env_service
+      .expect_catalog_urls()
+      .return_once(|| vec!["http://127.0.0.1:1/team.yaml".to_string()]);
+    let service =
+      AppServiceStubMock::new(env_service, MockHubService::new(), MockDataService::new());
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .withf(|input| {
+        input.starts_with("catalog 'http://127.0.0.1:1/team.yaml' could not be updated")
+      })
+      .return_once(|input| Ok(input.len()));
+    let result =
+      CatalogCommand::Update.execute(Arc::new(service), &ProxyConfig::default(), &mut stdout);
+    assert!(result.is_err());
+    Ok(())
+  }
+}
@@ -0,0 +1,136 @@
+use super::{CliError, StdoutWriter};
+use crate::{
+  db::{objs::ConversationsQuery, DbPool, DbServiceFn, TimeService},
+  error::{BodhiError, Common},
+  objs::DisplayTimezone,
+  service::AppServiceFn,
+  Command,
+};
+use prettytable::{format, row, Table};
+use std::sync::Arc;
+use tokio::runtime::Builder;
+
+#[derive(Debug, PartialEq)]
+pub struct ChatsCommand;
+
+impl TryFrom<Command> for ChatsCommand {
+  type Error = CliError;
+
+  fn try_from(value: Command) -> Result<Self, Self::Error> {
+    match value {
+      Command::Chats {} => Ok(ChatsCommand),
+      cmd => Err(CliError::ConvertCommand(
+        cmd.to_string(),
+        "chats".to_string(),
+      )),
+    }
+  }
+}
+
+impl ChatsCommand {
+  #[allow(clippy::result_large_err)]
+  pub fn execute(
+    self,
+    service: Arc<dyn AppServiceFn>,
+    stdout: &mut dyn StdoutWriter,
+  ) -> crate::error::Result<()> {
+    let runtime = Builder::new_current_thread()
+      .enable_all()
+      .build()
+      .map_err(Common::from)?;
+    runtime.block_on(async move {
+      let db_service =
+        DbPool::connect_service(&service.env_service().database_url(), Arc::new(TimeService))
+          .await?;
+      db_service.migrate().await?;
+      let timezone = service.env_service().timezone();
+      self
+        .aexecute(db_service.as_ref(), &timezone, stdout)
+        .await?;
+      Ok::<(), BodhiError>(())
+    })?;
+    Ok(())
+  }
+
+  async fn aexecute(
+    self,
+    db_service: &dyn DbServiceFn,
+    timezone: &DisplayTimezone,
+    stdout: &mut dyn StdoutWriter,
+  ) -> crate::error::Result<()> {
+    let mut table = Table::new();
+    table.add_row(row!["ID", "TITLE", "CREATED", "UPDATED"]);
+    for convo in db_service
+      .list_conversations(&ConversationsQuery::default())
+      .await?
+    {
+      table.add_row(row![
+        convo.id,
+        convo.title,
+        timezone.display(&convo.created_at),
+        timezone.display(&convo.updated_at),
+      ]);
+    }
+    table.set_format(format::FormatBuilder::default().padding(2, 2).build());
+    stdout.write(&table.to_string()).map_err(Common::Io)?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::ChatsCommand;
+  use crate::{
+    db::{objs::ConversationBuilder, DbService, DbServiceFn},
+    objs::DisplayTimezone,
+    test_utils::db_service,
+    Command, MockStdoutWriter,
+  };
+  use chrono::{DateTime, TimeZone, Utc};
+  use rstest::rstest;
+  use tempfile::TempDir;
+
+  #[rstest]
+  fn test_chats_command_from_cli() -> anyhow::Result<()> {
+    assert_eq!(ChatsCommand, ChatsCommand::try_from(Command::Chats {})?);
+    let result = ChatsCommand::try_from(Command::App { ui: false });
+    assert_eq!(
+      "Command 'app' cannot be converted into command 'chats'",
+      result.unwrap_err().to_string()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_chats_command_lists_chats_in_timezone(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let mut convo = ConversationBuilder::default()
+      .title("Days of the week")
+      .created_at(Utc.with_ymd_and_hms(2024, 5, 1, 10, 30, 0).unwrap())
+      .build()?;
+    db_service.save_conversation(&mut convo).await?;
+    let id = convo.id.clone();
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .withf(move |input| {
+        input.contains("CREATED")
+          && input.contains(&id)
+          && input.contains("Days of the week")
+          && input.contains("2024-05-01 16:00:00 +05:30")
+      })
+      .return_once(|input| Ok(input.len()));
+    ChatsCommand
+      .aexecute(
+        &db_service,
+        &DisplayTimezone::Named(chrono_tz::Asia::Kolkata),
+        &mut stdout,
+      )
+      .await?;
+    Ok(())
+  }
+}
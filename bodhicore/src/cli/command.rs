@@ -1,6 +1,13 @@
-use crate::objs::{ChatTemplateId, GptContextParams, OAIRequestParams, GGUF_EXTENSION, REGEX_REPO};
+use crate::db::objs::{ApiKeyRole, UsagePeriod};
+use crate::db::ExportFormat;
+use crate::objs::{
+  AliasFeature, Backend, ChatTemplateId, GptContextParams, LogFormat, OAIRequestParams,
+  RequestPriority, GGUF_EXTENSION, REGEX_REPO,
+};
 use crate::service::{DEFAULT_HOST, DEFAULT_PORT_STR};
-use clap::{ArgGroup, Parser, Subcommand};
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use std::path::PathBuf;
 use strum::Display;
 
 #[derive(Debug, PartialEq, Parser)]
@@ -8,6 +15,15 @@ use strum::Display;
 #[command(version)]
 #[command(about = "Run GenerativeAI LLMs locally and serve them via OpenAI compatible API")]
 pub struct Cli {
+  /// HTTP(S) proxy to download models from huggingface.co, e.g. `http://proxy.example.com:3128`.
+  /// Defaults to $HTTPS_PROXY/$HTTP_PROXY, hosts listed in $NO_PROXY are not proxied
+  #[clap(long, global = true)]
+  pub proxy: Option<String>,
+
+  /// Format of the log lines written to $BODHI_LOGS, defaults to $BODHI_LOG_FORMAT or text
+  #[clap(long, global = true, value_enum)]
+  pub log_format: Option<LogFormat>,
+
   #[command(subcommand)]
   pub command: Command,
 }
@@ -32,6 +48,27 @@ pub enum Command {
     /// Start on the given port
     #[clap(short, default_value = DEFAULT_PORT_STR, value_parser = clap::value_parser!(u16).range(1..=65535))]
     port: u16,
+    /// When the port is in use, try up to the given number of subsequent ports, e.g. 10 to try 1135 to 1145
+    #[clap(long, default_value_t = 0)]
+    port_range: u16,
+    /// When a generation fails, write a debug bundle to $BODHI_HOME/debug, reproduce it using `bodhi replay`
+    #[clap(long)]
+    capture_on_error: bool,
+    /// Listen on the given unix domain socket instead of the host and port, e.g. ~/.cache/bodhi/bodhi.sock
+    #[clap(long)]
+    uds: Option<PathBuf>,
+    /// Serve the web UI from the given directory instead of the built-in UI, e.g. the build output of the frontend
+    #[clap(long)]
+    ui_dir: Option<PathBuf>,
+    /// Load the given model alias on startup instead of on the first request, defaults to $BODHI_DEFAULT_ALIAS
+    #[clap(long)]
+    model: Option<String>,
+    /// Refuse to load a model estimated to need more memory than available, instead of warning
+    #[clap(long)]
+    strict: bool,
+    /// Run the server in the background, writing its pid to $BODHI_HOME/bodhi.pid, stop it using `bodhi stop`
+    #[clap(long)]
+    detach: bool,
   },
   /// list the model aliases on local
   #[clap(group = ArgGroup::new("variant"))]
@@ -42,6 +79,37 @@ pub enum Command {
     /// List the compatible GGUF model files from $HF_HOME folder on local system
     #[clap(long, short = 'm', group = "variant")]
     models: bool,
+    /// List the local model aliases and the pre-configured model aliases together,
+    /// with their download status, size on disk and whether a newer snapshot is downloaded
+    #[clap(long, short = 'a', group = "variant")]
+    all: bool,
+    /// Show the changes to the pre-configured model aliases since the last upgrades, use with --remote
+    #[clap(long, requires = "remote")]
+    whats_new: bool,
+    /// Output format, `json` or `yaml` to consume the list in scripts
+    #[clap(long, value_enum, default_value_t)]
+    format: ListFormat,
+  },
+  /// Manage the model catalogs, the pre-configured model aliases listed by `bodhi list -r`
+  Catalog {
+    #[command(subcommand)]
+    action: CatalogAction,
+  },
+  /// Manage the disk usage of the huggingface cache and bodhi home
+  Cache {
+    #[command(subcommand)]
+    action: CacheAction,
+  },
+  /// Import models from other local model runners as model aliases
+  Import {
+    #[command(subcommand)]
+    source: ImportSource,
+  },
+  /// Login to huggingface.co with an access token, required to pull models from gated repos
+  Login {
+    /// Huggingface access token, prompts for the token if not provided
+    #[clap(long)]
+    token: Option<String>,
   },
   /// Pull a compatible GGUF model from huggingface.co repository
   #[clap(group = ArgGroup::new("pull").required(true))]
@@ -56,14 +124,67 @@ pub enum Command {
     repo: Option<String>,
 
     /// The GGUF model file to pull from the repo, e.g. `tinyllama-1.1b-chat-v1.0.Q4_0.gguf`,
+    /// or a glob to pull all the matching files, e.g. `'*.Q4_K_M.gguf'`.
+    /// All the parts of a split model file, e.g. `model-00001-of-00003.gguf`, are pulled together
     #[clap(long, short = 'f', requires = "repo", value_parser = gguf_filename_parser)]
     filename: Option<String>,
 
+    /// The branch, tag or commit sha of the repo to pull the model from, defaults to `main`.
+    /// The resolved commit sha is saved as the model alias snapshot.
+    #[clap(long)]
+    revision: Option<String>,
+
     /// If the file already exists in $HF_HOME, force download and overwrite it
     #[clap(long = "force")]
     force: bool,
   },
 
+  /// Benchmark the prompt processing and generation speed of the model alias,
+  /// for every combination of the given threads and GPU layers
+  Bench {
+    /// The model alias to benchmark, run `bodhi list` to list the existing model aliases
+    alias: String,
+
+    /// Comma separated number of threads to benchmark, e.g. `4,8`,
+    /// defaults to the `n_threads` of the model alias
+    #[clap(long, short = 't', value_delimiter = ',')]
+    threads: Vec<u32>,
+
+    /// Comma separated number of layers to offload to the GPU to benchmark, e.g. `0,99`,
+    /// defaults to the `n_gpu_layers` of the model alias
+    #[clap(long, short = 'g', value_delimiter = ',')]
+    n_gpu_layers: Vec<i32>,
+
+    /// Number of prompt tokens to process
+    #[clap(long, default_value_t = 512)]
+    n_prompt: u32,
+
+    /// Number of tokens to generate
+    #[clap(long, default_value_t = 128)]
+    n_gen: u16,
+
+    /// Print the results as JSON
+    #[clap(long)]
+    json: bool,
+  },
+
+  /// Check the repo of the model alias for a newer snapshot of the model file,
+  /// and download and pin the alias to it on confirmation
+  #[clap(group = ArgGroup::new("target").required(true))]
+  Update {
+    /// The model alias to update, run `bodhi list` to list the existing model aliases
+    #[clap(group = "target")]
+    alias: Option<String>,
+
+    /// Check and update all the model aliases
+    #[clap(long, group = "target")]
+    all: bool,
+
+    /// Update without asking for confirmation
+    #[clap(long, short = 'y')]
+    yes: bool,
+  },
+
   /// Create a new model alias
   #[clap(group = ArgGroup::new("template").required(true))]
   Create {
@@ -72,12 +193,17 @@ pub enum Command {
     alias: String,
 
     /// The hugging face repo to pull the model from, e.g. `TheBloke/TinyLlama-1.1B-Chat-v1.0-GGUF`
-    #[clap(long, short = 'r', value_parser = repo_parser)]
-    repo: String,
+    #[clap(long, short = 'r', value_parser = repo_parser, required_unless_present = "file")]
+    repo: Option<String>,
 
     /// The gguf model file to pull from the repo, e.g. `tinyllama-1.1b-chat-v1.0.Q4_0.gguf`,
-    #[clap(long, short = 'f', value_parser = gguf_filename_parser)]
-    filename: String,
+    #[clap(long, short = 'f', value_parser = gguf_filename_parser, required_unless_present = "file")]
+    filename: Option<String>,
+
+    /// Local gguf model file to use instead of pulling from a repo, e.g. `~/models/tinyllama.Q4_0.gguf`.
+    /// The file is symlinked into $HF_HOME and used in place.
+    #[clap(long, value_parser = gguf_file_parser, conflicts_with_all = ["repo", "filename"])]
+    file: Option<PathBuf>,
 
     /// In-built chat template mapping to use to convert chat messages to LLM prompt
     #[clap(long, group = "template")]
@@ -91,6 +217,11 @@ pub enum Command {
     #[clap(long)]
     family: Option<String>,
 
+    /// What the model alias can be used for, comma separated, e.g. `rerank` for the reranking models.
+    /// The endpoints reject the aliases not having the feature they serve
+    #[clap(long, value_enum, value_delimiter = ',', default_value = "chat")]
+    features: Vec<AliasFeature>,
+
     /// If the file already exists in $HF_HOME, force download and overwrite it
     #[clap(long)]
     force: bool,
@@ -102,9 +233,42 @@ pub enum Command {
     context_params: GptContextParams,
   },
   /// Run the given model alias in interactive mode.
+  /// If any of --prompt, --output, --json or --stdin-format is given, or the input is piped,
+  /// writes a single completion for the prompt or the input from stdin instead.
   Run {
     /// Model alias to run, run `bodhi list` to list the existing model aliases
     alias: String,
+
+    /// Prompt for a single completion, printed to stdout instead of starting the interactive chat
+    #[clap(long, short = 'p', conflicts_with = "stdin_format")]
+    prompt: Option<String>,
+
+    /// Write the completion to the given file instead of stdout
+    #[clap(long, short = 'o')]
+    output: Option<PathBuf>,
+
+    /// Write the full OpenAI chat completion response as JSON, including the token usage
+    #[clap(long)]
+    json: bool,
+
+    /// Format of the input read from stdin, `text` for a single user prompt,
+    /// or `messages-json` for an array of OpenAI chat messages
+    #[clap(long)]
+    stdin_format: Option<StdinFormat>,
+
+    /// Save the chat after every reply, to browse it in the Web UI or resume it later
+    #[clap(long)]
+    save: bool,
+
+    /// Resume the saved chat with the given id, saving the new replies to it,
+    /// run `bodhi chats` to list the saved chats
+    #[clap(long, conflicts_with = "save")]
+    resume: Option<String>,
+
+    /// Print the prompt tokens, generated tokens, time to first token and tokens/sec after every reply,
+    /// toggle it in the chat using `/stats`
+    #[clap(long)]
+    stats: bool,
   },
   /// Display the given alias configuration
   Show {
@@ -128,6 +292,210 @@ pub enum Command {
     /// Model alias to delete, run `bodhi list` to list the existing model aliases
     alias: String,
   },
+  /// Manage the model alias files in $BODHI_HOME/aliases
+  Alias {
+    #[command(subcommand)]
+    action: AliasAction,
+  },
+  /// Export a chat with its messages from the Web UI into a shareable file
+  Export {
+    /// Id of the chat to export
+    id: String,
+    /// Format of the exported chat
+    #[clap(long, value_enum, default_value_t)]
+    format: ExportFormat,
+    /// Write the exported chat to the given file instead of stdout
+    #[clap(long, short = 'o')]
+    output: Option<PathBuf>,
+  },
+  /// List the chats saved from the Web UI, with timestamps in the $BODHI_TIMEZONE timezone
+  Chats {},
+  /// Show the requests, tokens and latency of the model aliases by client, aggregated by period
+  /// from the chat completions served by `bodhi serve`
+  Usage {
+    /// Period to aggregate the usage by, in UTC
+    #[clap(long, value_enum, default_value_t = UsagePeriod::Day)]
+    period: UsagePeriod,
+    /// Number of rows to show, the latest periods first
+    #[clap(long, default_value_t = 20)]
+    limit: u32,
+  },
+  /// Reproduce a failed request from a debug bundle captured by `bodhi serve --capture-on-error`
+  Replay {
+    /// Path of the debug bundle file in $BODHI_HOME/debug
+    bundle: PathBuf,
+  },
+  /// Evaluate the outputs of the model aliases
+  Eval {
+    #[command(subcommand)]
+    action: EvalAction,
+  },
+  /// Manage the database of the chats and usage in $BODHI_HOME
+  Db {
+    #[command(subcommand)]
+    action: DbAction,
+  },
+  /// Manage the API keys required by `bodhi serve` when $BODHI_AUTH is enabled
+  Keys {
+    #[command(subcommand)]
+    action: KeysAction,
+  },
+  /// Show the system and runtime details for bug reports, the CPU features, the GPUs,
+  /// the llama.cpp build, the version and the $BODHI_HOME and $HF_HOME paths
+  Info {
+    /// Print the details as JSON
+    #[clap(long)]
+    json: bool,
+  },
+  /// Show the bodhi server running in the background, started using `bodhi serve --detach`
+  Ps {},
+  /// Stop the bodhi server running in the background, letting the in-flight requests finish
+  Stop {},
+  /// Generate the shell completion script, completing the model aliases for run, show, cp, edit and rm,
+  /// e.g. `source <(bodhi completions bash)`
+  Completions {
+    /// Shell to generate the completion script for
+    #[clap(value_enum, required_unless_present = "aliases")]
+    shell: Option<Shell>,
+    /// List the model alias names, used by the completion scripts
+    #[clap(long, hide = true, conflicts_with = "shell")]
+    aliases: bool,
+  },
+  /// Run the llama.cpp context for the server started with $BODHI_ISOLATE_INFERENCE, serving
+  /// its requests over stdin and stdout
+  #[clap(hide = true)]
+  Worker {
+    /// Refuse to load a model estimated to need more memory than available
+    #[clap(long)]
+    strict: bool,
+    /// Backend picked by the server
+    #[clap(long, value_enum, default_value_t)]
+    backend: Backend,
+  },
+}
+
+#[derive(Debug, PartialEq, Subcommand)]
+pub enum CacheAction {
+  /// Show the disk usage of each model repo in $HF_HOME and of $BODHI_HOME
+  Usage {},
+  /// Remove the model snapshots from $HF_HOME not used by any model alias
+  Prune {
+    /// List the snapshots that would be removed without removing them
+    #[clap(long)]
+    dry_run: bool,
+  },
+}
+
+#[derive(Debug, PartialEq, Subcommand)]
+pub enum EvalAction {
+  /// Compare the outputs of the requests sampled to run on the canary model alias,
+  /// configured in the `canary` section of $BODHI_HOME/settings.yaml
+  Canary {
+    #[command(subcommand)]
+    action: CanaryAction,
+  },
+}
+
+#[derive(Debug, PartialEq, Subcommand)]
+pub enum CanaryAction {
+  /// Show the most recent canary samples, with the diff of the model output against the canary output
+  Report {
+    /// Number of the most recent samples to show
+    #[clap(long, default_value_t = 10)]
+    limit: u32,
+  },
+}
+
+#[derive(Debug, PartialEq, Subcommand)]
+pub enum DbAction {
+  /// Apply the pending migrations of the database schema
+  Migrate {
+    /// List the applied and pending migrations without applying them
+    #[clap(long)]
+    status: bool,
+  },
+  /// Back up the sqlite database and the model aliases to a new directory
+  Backup {
+    /// Directory to create for the backup
+    path: PathBuf,
+  },
+  /// Restore the sqlite database and the model aliases from a backup, stop `bodhi serve` before
+  /// restoring. The current database is kept as $BODHI_HOME/bodhi.sqlite.bak
+  Restore {
+    /// Directory of the backup created by `bodhi db backup`
+    path: PathBuf,
+  },
+}
+
+#[derive(Debug, PartialEq, Subcommand)]
+pub enum AliasAction {
+  /// Check the model alias files for the missing, unknown and invalid fields, with the line and a
+  /// suggested fix for each problem. The invalid files are skipped by `bodhi list` and `bodhi serve`
+  Lint {
+    /// Model alias file to check instead of all the files in $BODHI_HOME/aliases
+    file: Option<PathBuf>,
+  },
+}
+
+#[derive(Debug, PartialEq, Subcommand)]
+pub enum KeysAction {
+  /// Create an API key, the key is shown only once
+  Create {
+    /// Name to identify the key, e.g. the person or the app using it
+    name: String,
+    /// Role of the key, readonly can only list, user can also run inference, admin can also
+    /// manage the model aliases and the settings
+    #[clap(long, value_enum, default_value_t = ApiKeyRole::User)]
+    role: ApiKeyRole,
+    /// Priority of the requests using the key, the batch requests only use the model when no
+    /// interactive request is using it, e.g. for the evaluations and other background jobs
+    #[clap(long, value_enum, default_value_t = RequestPriority::Interactive)]
+    priority: RequestPriority,
+  },
+  /// List the API keys, including the revoked keys
+  List {},
+  /// Revoke the API key, the requests using it are rejected from then on
+  Revoke {
+    /// Id of the key, as shown by `bodhi keys list`
+    id: String,
+  },
+}
+
+#[derive(Debug, PartialEq, Subcommand)]
+pub enum CatalogAction {
+  /// Download the model catalogs at the urls in $BODHI_CATALOG_URLS into $BODHI_HOME/catalogs
+  Update {},
+}
+
+#[derive(Debug, PartialEq, Subcommand)]
+pub enum ImportSource {
+  /// Register the GGUF models of a local Ollama installation as model aliases
+  Ollama {
+    /// Ollama models directory, defaults to $OLLAMA_MODELS or ~/.ollama/models
+    #[clap(long)]
+    models_dir: Option<PathBuf>,
+    /// Copy the model files to $HF_HOME instead of symlinking to the Ollama blobs
+    #[clap(long)]
+    copy: bool,
+    /// Overwrite the existing model aliases with the same name
+    #[clap(long)]
+    force: bool,
+  },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum ListFormat {
+  #[default]
+  Table,
+  Json,
+  Yaml,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum StdinFormat {
+  #[default]
+  Text,
+  MessagesJson,
 }
 
 fn repo_parser(repo: &str) -> Result<String, String> {
@@ -146,6 +514,10 @@ fn gguf_filename_parser(filename: &str) -> Result<String, String> {
   }
 }
 
+fn gguf_file_parser(file: &str) -> Result<PathBuf, String> {
+  gguf_filename_parser(file).map(PathBuf::from)
+}
+
 #[allow(clippy::too_many_arguments)]
 #[cfg(test)]
 mod test {
@@ -177,6 +549,141 @@ mod test {
     Ok(())
   }
 
+  #[rstest]
+  #[case(vec!["bodhi", "--proxy", "http://proxy:3128", "list"], Some("http://proxy:3128"))]
+  #[case(vec!["bodhi", "pull", "llama3:instruct", "--proxy", "http://proxy:3128"], Some("http://proxy:3128"))]
+  #[case(vec!["bodhi", "list"], None)]
+  fn test_cli_proxy(#[case] args: Vec<&str>, #[case] expected: Option<&str>) -> anyhow::Result<()> {
+    let cli = Cli::try_parse_from(args)?;
+    assert_eq!(expected.map(str::to_string), cli.proxy);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(vec!["bodhi", "--log-format", "json", "serve"], Some(LogFormat::Json))]
+  #[case(vec!["bodhi", "serve", "--log-format", "text"], Some(LogFormat::Text))]
+  #[case(vec!["bodhi", "serve"], None)]
+  fn test_cli_log_format(
+    #[case] args: Vec<&str>,
+    #[case] expected: Option<LogFormat>,
+  ) -> anyhow::Result<()> {
+    let cli = Cli::try_parse_from(args)?;
+    assert_eq!(expected, cli.log_format);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(vec!["bodhi", "login"], None)]
+  #[case(vec!["bodhi", "login", "--token", "hf_testtoken"], Some("hf_testtoken"))]
+  fn test_cli_login(#[case] args: Vec<&str>, #[case] token: Option<&str>) -> anyhow::Result<()> {
+    let cli = Cli::try_parse_from(args)?;
+    let expected = Command::Login {
+      token: token.map(str::to_string),
+    };
+    assert_eq!(expected, cli.command);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(vec!["bodhi", "cache", "usage"], CacheAction::Usage {})]
+  #[case(vec!["bodhi", "cache", "prune"], CacheAction::Prune { dry_run: false })]
+  #[case(vec!["bodhi", "cache", "prune", "--dry-run"], CacheAction::Prune { dry_run: true })]
+  fn test_cli_cache(#[case] args: Vec<&str>, #[case] action: CacheAction) -> anyhow::Result<()> {
+    let cli = Cli::try_parse_from(args)?;
+    assert_eq!(Command::Cache { action }, cli.command);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(vec!["bodhi", "import", "ollama"], ImportSource::Ollama { models_dir: None, copy: false, force: false })]
+  #[case(
+    vec!["bodhi", "import", "ollama", "--models-dir", "/data/ollama", "--copy", "--force"],
+    ImportSource::Ollama { models_dir: Some(PathBuf::from("/data/ollama")), copy: true, force: true }
+  )]
+  fn test_cli_import(#[case] args: Vec<&str>, #[case] source: ImportSource) -> anyhow::Result<()> {
+    let cli = Cli::try_parse_from(args)?;
+    assert_eq!(Command::Import { source }, cli.command);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(vec!["bodhi", "export", "testid"], Command::Export {
+    id: "testid".to_string(),
+    format: ExportFormat::Markdown,
+    output: None,
+  })]
+  #[case(vec!["bodhi", "export", "testid", "--format", "json", "-o", "chat.json"], Command::Export {
+    id: "testid".to_string(),
+    format: ExportFormat::Json,
+    output: Some(PathBuf::from("chat.json")),
+  })]
+  fn test_cli_export(#[case] args: Vec<&str>, #[case] expected: Command) -> anyhow::Result<()> {
+    let cli = Cli::try_parse_from(args)?;
+    assert_eq!(expected, cli.command);
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_cli_catalog_update() -> anyhow::Result<()> {
+    let cli = Cli::try_parse_from(vec!["bodhi", "catalog", "update"])?;
+    assert_eq!(
+      Command::Catalog {
+        action: CatalogAction::Update {}
+      },
+      cli.command
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_cli_chats() -> anyhow::Result<()> {
+    let cli = Cli::try_parse_from(vec!["bodhi", "chats"])?;
+    assert_eq!(Command::Chats {}, cli.command);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(vec!["bodhi", "usage"], Command::Usage {
+    period: UsagePeriod::Day,
+    limit: 20,
+  })]
+  #[case(vec!["bodhi", "usage", "--period", "week", "--limit", "5"], Command::Usage {
+    period: UsagePeriod::Week,
+    limit: 5,
+  })]
+  fn test_cli_usage(#[case] args: Vec<&str>, #[case] expected: Command) -> anyhow::Result<()> {
+    let cli = Cli::try_parse_from(args)?;
+    assert_eq!(expected, cli.command);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(vec!["bodhi", "completions", "zsh"], Command::Completions {
+    shell: Some(Shell::Zsh),
+    aliases: false,
+  })]
+  #[case(vec!["bodhi", "completions", "--aliases"], Command::Completions {
+    shell: None,
+    aliases: true,
+  })]
+  fn test_cli_completions(
+    #[case] args: Vec<&str>,
+    #[case] expected: Command,
+  ) -> anyhow::Result<()> {
+    let cli = Cli::try_parse_from(args)?;
+    assert_eq!(expected, cli.command);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(vec!["bodhi", "completions"])]
+  #[case(vec!["bodhi", "completions", "bash", "--aliases"])]
+  #[case(vec!["bodhi", "completions", "tcsh"])]
+  fn test_cli_completions_invalid(#[case] args: Vec<&str>) -> anyhow::Result<()> {
+    assert!(Cli::try_parse_from(args).is_err());
+    Ok(())
+  }
+
   #[test]
   fn test_cli_app_invalid() -> anyhow::Result<()> {
     let args = vec!["bodhi", "app", "--extra", "args"];
@@ -208,6 +715,217 @@ For more information, try '--help'.
     let expected = Command::Serve {
       host: String::from(host),
       port,
+      port_range: 0,
+      capture_on_error: false,
+      uds: None,
+      ui_dir: None,
+      model: None,
+      strict: false,
+      detach: false,
+    };
+    assert_eq!(expected, cli.command);
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_cli_serve_capture_on_error() -> anyhow::Result<()> {
+    let cli = Cli::try_parse_from(vec!["bodhi", "serve", "--capture-on-error"])?;
+    let expected = Command::Serve {
+      host: String::from("127.0.0.1"),
+      port: 1135,
+      port_range: 0,
+      capture_on_error: true,
+      uds: None,
+      ui_dir: None,
+      model: None,
+      strict: false,
+      detach: false,
+    };
+    assert_eq!(expected, cli.command);
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_cli_serve_uds() -> anyhow::Result<()> {
+    let cli = Cli::try_parse_from(vec!["bodhi", "serve", "--uds", "/tmp/bodhi.sock"])?;
+    let expected = Command::Serve {
+      host: String::from("127.0.0.1"),
+      port: 1135,
+      port_range: 0,
+      capture_on_error: false,
+      uds: Some(PathBuf::from("/tmp/bodhi.sock")),
+      ui_dir: None,
+      model: None,
+      strict: false,
+      detach: false,
+    };
+    assert_eq!(expected, cli.command);
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_cli_serve_port_range() -> anyhow::Result<()> {
+    let cli = Cli::try_parse_from(vec!["bodhi", "serve", "--port-range", "10"])?;
+    let expected = Command::Serve {
+      host: String::from("127.0.0.1"),
+      port: 1135,
+      port_range: 10,
+      capture_on_error: false,
+      uds: None,
+      ui_dir: None,
+      model: None,
+      strict: false,
+      detach: false,
+    };
+    assert_eq!(expected, cli.command);
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_cli_serve_ui_dir() -> anyhow::Result<()> {
+    let cli = Cli::try_parse_from(vec!["bodhi", "serve", "--ui-dir", "app/out"])?;
+    let expected = Command::Serve {
+      host: String::from("127.0.0.1"),
+      port: 1135,
+      port_range: 0,
+      capture_on_error: false,
+      uds: None,
+      ui_dir: Some(PathBuf::from("app/out")),
+      model: None,
+      strict: false,
+      detach: false,
+    };
+    assert_eq!(expected, cli.command);
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_cli_serve_model() -> anyhow::Result<()> {
+    let cli = Cli::try_parse_from(vec![
+      "bodhi",
+      "serve",
+      "--model",
+      "tinyllama:instruct",
+      "--strict",
+    ])?;
+    let expected = Command::Serve {
+      host: String::from("127.0.0.1"),
+      port: 1135,
+      port_range: 0,
+      capture_on_error: false,
+      uds: None,
+      ui_dir: None,
+      model: Some(String::from("tinyllama:instruct")),
+      strict: true,
+      detach: false,
+    };
+    assert_eq!(expected, cli.command);
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_cli_serve_detach() -> anyhow::Result<()> {
+    let cli = Cli::try_parse_from(vec!["bodhi", "serve", "-p", "8080", "--detach"])?;
+    let expected = Command::Serve {
+      host: String::from("127.0.0.1"),
+      port: 8080,
+      port_range: 0,
+      capture_on_error: false,
+      uds: None,
+      ui_dir: None,
+      model: None,
+      strict: false,
+      detach: true,
+    };
+    assert_eq!(expected, cli.command);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(vec!["bodhi", "info"], Command::Info { json: false })]
+  #[case(vec!["bodhi", "info", "--json"], Command::Info { json: true })]
+  fn test_cli_info(#[case] args: Vec<&str>, #[case] expected: Command) -> anyhow::Result<()> {
+    let cli = Cli::try_parse_from(args)?;
+    assert_eq!(expected, cli.command);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(vec!["bodhi", "worker"], Command::Worker { strict: false, backend: Backend::Auto })]
+  #[case(
+    vec!["bodhi", "worker", "--backend", "cuda", "--strict"],
+    Command::Worker { strict: true, backend: Backend::Cuda }
+  )]
+  fn test_cli_worker(#[case] args: Vec<&str>, #[case] expected: Command) -> anyhow::Result<()> {
+    let cli = Cli::try_parse_from(args)?;
+    assert_eq!(expected, cli.command);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(vec!["bodhi", "ps"], Command::Ps {})]
+  #[case(vec!["bodhi", "stop"], Command::Stop {})]
+  fn test_cli_daemon(#[case] args: Vec<&str>, #[case] expected: Command) -> anyhow::Result<()> {
+    let cli = Cli::try_parse_from(args)?;
+    assert_eq!(expected, cli.command);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(vec!["bodhi", "eval", "canary", "report"], CanaryAction::Report { limit: 10 })]
+  #[case(vec!["bodhi", "eval", "canary", "report", "--limit", "3"], CanaryAction::Report { limit: 3 })]
+  fn test_cli_eval_canary(
+    #[case] args: Vec<&str>,
+    #[case] action: CanaryAction,
+  ) -> anyhow::Result<()> {
+    let cli = Cli::try_parse_from(args)?;
+    let expected = Command::Eval {
+      action: EvalAction::Canary { action },
+    };
+    assert_eq!(expected, cli.command);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(vec!["bodhi", "db", "migrate"], DbAction::Migrate { status: false })]
+  #[case(vec!["bodhi", "db", "migrate", "--status"], DbAction::Migrate { status: true })]
+  #[case(vec!["bodhi", "db", "backup", "backups/today"], DbAction::Backup { path: PathBuf::from("backups/today") })]
+  #[case(vec!["bodhi", "db", "restore", "backups/today"], DbAction::Restore { path: PathBuf::from("backups/today") })]
+  fn test_cli_db(#[case] args: Vec<&str>, #[case] action: DbAction) -> anyhow::Result<()> {
+    let cli = Cli::try_parse_from(args)?;
+    let expected = Command::Db { action };
+    assert_eq!(expected, cli.command);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(vec!["bodhi", "alias", "lint"], AliasAction::Lint { file: None })]
+  #[case(vec!["bodhi", "alias", "lint", "aliases/llama3--instruct.yaml"], AliasAction::Lint { file: Some(PathBuf::from("aliases/llama3--instruct.yaml")) })]
+  fn test_cli_alias(#[case] args: Vec<&str>, #[case] action: AliasAction) -> anyhow::Result<()> {
+    let cli = Cli::try_parse_from(args)?;
+    let expected = Command::Alias { action };
+    assert_eq!(expected, cli.command);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(vec!["bodhi", "keys", "create", "alice"], KeysAction::Create { name: "alice".to_string(), role: ApiKeyRole::User, priority: RequestPriority::Interactive })]
+  #[case(vec!["bodhi", "keys", "create", "ops", "--role", "admin"], KeysAction::Create { name: "ops".to_string(), role: ApiKeyRole::Admin, priority: RequestPriority::Interactive })]
+  #[case(vec!["bodhi", "keys", "create", "evals", "--priority", "batch"], KeysAction::Create { name: "evals".to_string(), role: ApiKeyRole::User, priority: RequestPriority::Batch })]
+  #[case(vec!["bodhi", "keys", "list"], KeysAction::List {})]
+  #[case(vec!["bodhi", "keys", "revoke", "testid"], KeysAction::Revoke { id: "testid".to_string() })]
+  fn test_cli_keys(#[case] args: Vec<&str>, #[case] action: KeysAction) -> anyhow::Result<()> {
+    let cli = Cli::try_parse_from(args)?;
+    let expected = Command::Keys { action };
+    assert_eq!(expected, cli.command);
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_cli_replay() -> anyhow::Result<()> {
+    let cli = Cli::try_parse_from(vec!["bodhi", "replay", "bundle.json"])?;
+    let expected = Command::Replay {
+      bundle: PathBuf::from("bundle.json"),
     };
     assert_eq!(expected, cli.command);
     Ok(())
@@ -232,16 +950,29 @@ For more information, try '--help'.
   }
 
   #[rstest]
-  #[case(vec!["bodhi", "list"], false, false)]
-  #[case(vec!["bodhi", "list", "-r"], true, false)]
-  #[case(vec!["bodhi", "list", "-m"], false, true)]
+  #[case(vec!["bodhi", "list"], false, false, false, false, ListFormat::Table)]
+  #[case(vec!["bodhi", "list", "-r"], true, false, false, false, ListFormat::Table)]
+  #[case(vec!["bodhi", "list", "-m"], false, true, false, false, ListFormat::Table)]
+  #[case(vec!["bodhi", "list", "-a"], false, false, true, false, ListFormat::Table)]
+  #[case(vec!["bodhi", "list", "-r", "--whats-new"], true, false, false, true, ListFormat::Table)]
+  #[case(vec!["bodhi", "list", "--format", "json"], false, false, false, false, ListFormat::Json)]
+  #[case(vec!["bodhi", "list", "-m", "--format", "yaml"], false, true, false, false, ListFormat::Yaml)]
   fn test_cli_list(
     #[case] args: Vec<&str>,
     #[case] remote: bool,
     #[case] models: bool,
+    #[case] all: bool,
+    #[case] whats_new: bool,
+    #[case] format: ListFormat,
   ) -> anyhow::Result<()> {
     let cli = Cli::try_parse_from(args)?;
-    let expected = Command::List { remote, models };
+    let expected = Command::List {
+      remote,
+      models,
+      all,
+      whats_new,
+      format,
+    };
     assert_eq!(expected, cli.command);
     Ok(())
   }
@@ -251,6 +982,13 @@ For more information, try '--help'.
 
 Usage: bodhi list --remote
 
+For more information, try '--help'.
+"#)]
+  #[case(vec!["bodhi", "list", "--whats-new"], r#"error: the following required arguments were not provided:
+  --remote
+
+Usage: bodhi list --remote --whats-new
+
 For more information, try '--help'.
 "#)]
   fn test_cli_list_invalid(#[case] args: Vec<&str>, #[case] err_msg: String) -> anyhow::Result<()> {
@@ -261,16 +999,64 @@ For more information, try '--help'.
   }
 
   #[rstest]
-  #[case(vec!["bodhi", "run", "llama3:instruct"], "llama3:instruct")]
-  fn test_cli_run(#[case] args: Vec<&str>, #[case] alias: String) -> anyhow::Result<()> {
+  #[case(vec!["bodhi", "run", "llama3:instruct"], Command::Run {
+    alias: "llama3:instruct".to_string(),
+    prompt: None,
+    output: None,
+    json: false,
+    stdin_format: None,
+    save: false,
+    resume: None,
+    stats: false,
+  })]
+  #[case(vec!["bodhi", "run", "llama3:instruct", "-o", "out.txt", "--json", "--stdin-format", "messages-json"], Command::Run {
+    alias: "llama3:instruct".to_string(),
+    prompt: None,
+    output: Some(PathBuf::from("out.txt")),
+    json: true,
+    stdin_format: Some(StdinFormat::MessagesJson),
+    save: false,
+    resume: None,
+    stats: false,
+  })]
+  #[case(vec!["bodhi", "run", "llama3:instruct", "--save"], Command::Run {
+    alias: "llama3:instruct".to_string(),
+    prompt: None,
+    output: None,
+    json: false,
+    stdin_format: None,
+    save: true,
+    resume: None,
+    stats: false,
+  })]
+  #[case(vec!["bodhi", "run", "llama3:instruct", "--resume", "testid", "--stats"], Command::Run {
+    alias: "llama3:instruct".to_string(),
+    prompt: None,
+    output: None,
+    json: false,
+    stdin_format: None,
+    save: false,
+    resume: Some("testid".to_string()),
+    stats: true,
+  })]
+  #[case(vec!["bodhi", "run", "llama3:instruct", "-p", "What day comes after Monday?", "--json"], Command::Run {
+    alias: "llama3:instruct".to_string(),
+    prompt: Some("What day comes after Monday?".to_string()),
+    output: None,
+    json: true,
+    stdin_format: None,
+    save: false,
+    resume: None,
+    stats: false,
+  })]
+  fn test_cli_run(#[case] args: Vec<&str>, #[case] expected: Command) -> anyhow::Result<()> {
     let cli = Cli::try_parse_from(args)?;
-    let expected = Command::Run { alias };
     assert_eq!(expected, cli.command);
     Ok(())
   }
 
   #[rstest]
-  #[case(vec!["bodhi", "pull", "llama3:instruct"], Some(String::from("llama3:instruct")), None, None, false)]
+  #[case(vec!["bodhi", "pull", "llama3:instruct"], Some(String::from("llama3:instruct")), None, None, None, false)]
   #[case(vec!["bodhi",
       "pull",
       "-r", "QuantFactory/Meta-Llama-3-8B-Instruct-GGUF",
@@ -279,6 +1065,7 @@ For more information, try '--help'.
     None,
     Some(String::from("QuantFactory/Meta-Llama-3-8B-Instruct-GGUF")),
     Some(String::from("Meta-Llama-3-8B-Instruct.Q8_0.gguf")),
+    None,
     false
   )]
   #[case(vec![ "bodhi", "pull",
@@ -288,6 +1075,7 @@ For more information, try '--help'.
     None,
     Some(String::from("QuantFactory/Meta-Llama-3-8B-Instruct-GGUF")),
     Some(String::from("Meta-Llama-3-8B-Instruct.Q8_0.gguf")),
+    None,
     false
   )]
   #[case(vec![ "bodhi", "pull",
@@ -297,13 +1085,16 @@ For more information, try '--help'.
     None,
     Some(String::from("QuantFactory/Meta-Llama-3-8B-Instruct-GGUF")),
     Some(String::from("Meta-Llama-3-8B-Instruct.Q8_0.gguf")),
+    None,
     false
   )]
+  #[case(vec!["bodhi", "pull", "llama3:instruct", "--revision", "v1.0"], Some(String::from("llama3:instruct")), None, None, Some(String::from("v1.0")), false)]
   fn test_cli_pull_valid(
     #[case] args: Vec<&str>,
     #[case] alias: Option<String>,
     #[case] repo: Option<String>,
     #[case] filename: Option<String>,
+    #[case] revision: Option<String>,
     #[case] force: bool,
   ) -> anyhow::Result<()> {
     let actual = Cli::try_parse_from(args)?.command;
@@ -311,6 +1102,7 @@ For more information, try '--help'.
       alias,
       repo,
       filename,
+      revision,
       force,
     };
     assert_eq!(expected, actual);
@@ -345,6 +1137,53 @@ For more information, try '--help'.
     Ok(())
   }
 
+  #[rstest]
+  #[case(vec!["bodhi", "bench", "llama3:instruct"], vec![], vec![], 512, 128, false)]
+  #[case(vec!["bodhi", "bench", "llama3:instruct", "-t", "4,8", "-g", "0,99", "--n-prompt", "256", "--n-gen", "64", "--json"],
+    vec![4, 8], vec![0, 99], 256, 64, true)]
+  fn test_cli_bench_valid(
+    #[case] args: Vec<&str>,
+    #[case] threads: Vec<u32>,
+    #[case] n_gpu_layers: Vec<i32>,
+    #[case] n_prompt: u32,
+    #[case] n_gen: u16,
+    #[case] json: bool,
+  ) -> anyhow::Result<()> {
+    let actual = Cli::try_parse_from(args)?.command;
+    let expected = Command::Bench {
+      alias: "llama3:instruct".to_string(),
+      threads,
+      n_gpu_layers,
+      n_prompt,
+      n_gen,
+      json,
+    };
+    assert_eq!(expected, actual);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(vec!["bodhi", "update", "llama3:instruct"], Some(String::from("llama3:instruct")), false, false)]
+  #[case(vec!["bodhi", "update", "--all", "-y"], None, true, true)]
+  fn test_cli_update_valid(
+    #[case] args: Vec<&str>,
+    #[case] alias: Option<String>,
+    #[case] all: bool,
+    #[case] yes: bool,
+  ) -> anyhow::Result<()> {
+    let actual = Cli::try_parse_from(args)?.command;
+    assert_eq!(Command::Update { alias, all, yes }, actual);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(vec!["bodhi", "update"])]
+  #[case(vec!["bodhi", "update", "llama3:instruct", "--all"])]
+  fn test_cli_update_invalid(#[case] args: Vec<&str>) -> anyhow::Result<()> {
+    assert!(Cli::try_parse_from(args).is_err());
+    Ok(())
+  }
+
   #[rstest]
   #[case(vec![
     "bodhi", "create",
@@ -397,7 +1236,15 @@ For more information, try '--help'.
       stop: vec!["\n".to_string(), "\n\n".to_string()],
       temperature: Some(0.8),
       top_p: Some(0.9),
-      user: Some("testuser".to_string())
+      user: Some("testuser".to_string()),
+      top_k: None,
+      min_p: None,
+      repeat_penalty: None,
+      repeat_last_n: None,
+      typical_p: None,
+      mirostat: None,
+      mirostat_tau: None,
+      mirostat_eta: None,
     },
     GptContextParams {
       n_seed: None,
@@ -406,6 +1253,10 @@ For more information, try '--help'.
       n_parallel: Some(4),
       n_predict: Some(512),
       n_keep: Some(4),
+      n_gpu_layers: None,
+      truncation: None,
+      warmup: None,
+      backend: None,
     }
   ,
   )]
@@ -422,11 +1273,13 @@ For more information, try '--help'.
     let actual = Cli::try_parse_from(args)?.command;
     let expected = Command::Create {
       alias,
-      repo,
-      filename,
+      repo: Some(repo),
+      filename: Some(filename),
+      file: None,
       chat_template: Some(chat_template),
       tokenizer_config: None,
       family: Some(family),
+      features: vec![AliasFeature::Chat],
       force: false,
       oai_request_params,
       context_params,
@@ -435,6 +1288,79 @@ For more information, try '--help'.
     Ok(())
   }
 
+  #[rstest]
+  fn test_cli_create_with_file() -> anyhow::Result<()> {
+    let args = vec![
+      "bodhi",
+      "create",
+      "mymodel:instruct",
+      "--file",
+      "/models/mymodel.Q4_0.gguf",
+      "--chat-template",
+      "llama3",
+    ];
+    let actual = Cli::try_parse_from(args)?.command;
+    let expected = Command::Create {
+      alias: "mymodel:instruct".to_string(),
+      repo: None,
+      filename: None,
+      file: Some(PathBuf::from("/models/mymodel.Q4_0.gguf")),
+      chat_template: Some(ChatTemplateId::Llama3),
+      tokenizer_config: None,
+      family: None,
+      features: vec![AliasFeature::Chat],
+      force: false,
+      oai_request_params: OAIRequestParams::default(),
+      context_params: GptContextParams::default(),
+    };
+    assert_eq!(expected, actual);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("rerank", vec![AliasFeature::Rerank])]
+  #[case("chat,embedding", vec![AliasFeature::Chat, AliasFeature::Embedding])]
+  fn test_cli_create_with_features(
+    #[case] features: &str,
+    #[case] expected: Vec<AliasFeature>,
+  ) -> anyhow::Result<()> {
+    let args = vec![
+      "bodhi",
+      "create",
+      "bge-reranker",
+      "--file",
+      "/models/bge-reranker.Q8_0.gguf",
+      "--chat-template",
+      "llama3",
+      "--features",
+      features,
+    ];
+    let actual = Cli::try_parse_from(args)?.command;
+    let Command::Create { features, .. } = actual else {
+      panic!("expected create command");
+    };
+    assert_eq!(expected, features);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(vec!["bodhi", "create", "mymodel:instruct", "--chat-template", "llama3"])]
+  #[case(vec![
+    "bodhi", "create", "mymodel:instruct",
+    "--file", "/models/mymodel.Q4_0.gguf",
+    "--repo", "MyFactory/testalias-gguf",
+    "--chat-template", "llama3",
+  ])]
+  #[case(vec![
+    "bodhi", "create", "mymodel:instruct",
+    "--file", "/models/mymodel.safetensors",
+    "--chat-template", "llama3",
+  ])]
+  fn test_cli_create_with_file_invalid(#[case] args: Vec<&str>) -> anyhow::Result<()> {
+    assert!(Cli::try_parse_from(args).is_err());
+    Ok(())
+  }
+
   #[rstest]
   #[case(vec![
     "bodhi", "create",
@@ -494,21 +1420,41 @@ For more information, try '--help'.
 
   #[rstest]
   #[case(Command::App {ui: false}, "app")]
-  #[case(Command::Serve {host: Default::default(), port: 0}, "serve")]
-  #[case(Command::List {remote: false, models: false}, "list")]
-  #[case(Command::Pull { alias: None, repo: None, filename: None, force: false }, "pull")]
+  #[case(Command::Serve {host: Default::default(), port: 0, port_range: 0, capture_on_error: false, uds: None, ui_dir: None, model: None, strict: false, detach: false}, "serve")]
+  #[case(Command::List {remote: false, models: false, all: false, whats_new: false, format: ListFormat::Table}, "list")]
+  #[case(Command::Pull { alias: None, repo: None, filename: None, revision: None, force: false }, "pull")]
+  #[case(Command::Update { alias: None, all: true, yes: false }, "update")]
+  #[case(Command::Bench { alias: Default::default(), threads: vec![], n_gpu_layers: vec![], n_prompt: 512, n_gen: 128, json: false }, "bench")]
   #[case(Command::Create {
       alias: Default::default(),
       repo: Default::default(),
       filename: Default::default(),
+      file: None,
       chat_template: None,
       tokenizer_config: None,
       family: None,
+      features: vec![],
       force: false,
       oai_request_params: OAIRequestParams::default(),
       context_params: GptContextParams::default(),
     }, "create")]
-  #[case(Command::Run {alias: Default::default()}, "run")]
+  #[case(Command::Run {alias: Default::default(), prompt: None, output: None, json: false, stdin_format: None, save: false, resume: None, stats: false}, "run")]
+  #[case(Command::Cache {action: CacheAction::Usage {}}, "cache")]
+  #[case(Command::Catalog {action: CatalogAction::Update {}}, "catalog")]
+  #[case(Command::Import {source: ImportSource::Ollama {models_dir: None, copy: false, force: false}}, "import")]
+  #[case(Command::Export {id: Default::default(), format: ExportFormat::Markdown, output: None}, "export")]
+  #[case(Command::Chats {}, "chats")]
+  #[case(Command::Usage {period: UsagePeriod::Day, limit: 20}, "usage")]
+  #[case(Command::Replay {bundle: PathBuf::from("bundle.json")}, "replay")]
+  #[case(Command::Eval {action: EvalAction::Canary {action: CanaryAction::Report {limit: 10}}}, "eval")]
+  #[case(Command::Db {action: DbAction::Migrate {status: false}}, "db")]
+  #[case(Command::Alias {action: AliasAction::Lint {file: None}}, "alias")]
+  #[case(Command::Keys {action: KeysAction::List {}}, "keys")]
+  #[case(Command::Info {json: false}, "info")]
+  #[case(Command::Ps {}, "ps")]
+  #[case(Command::Stop {}, "stop")]
+  #[case(Command::Completions {shell: Some(Shell::Bash), aliases: false}, "completions")]
+  #[case(Command::Worker {strict: false, backend: Backend::Auto}, "worker")]
   fn test_cli_to_string(#[case] cmd: Command, #[case] expected: String) -> anyhow::Result<()> {
     assert_eq!(expected, cmd.to_string());
     Ok(())
@@ -0,0 +1,159 @@
+use super::{Cli, CliError, StdoutWriter};
+use crate::{error::Common, service::AppServiceFn, Command};
+use clap::CommandFactory;
+use clap_complete::Shell;
+use std::sync::Arc;
+
+const BASH_ALIASES: &str = r#"
+_bodhi_aliases() {
+    local cur="${COMP_WORDS[COMP_CWORD]}"
+    if [[ ${COMP_CWORD} -eq 2 && ${cur} != -* ]]; then
+        case "${COMP_WORDS[1]}" in
+            run|show|cp|edit|rm)
+                COMPREPLY=($(compgen -W "$(bodhi completions --aliases 2>/dev/null)" -- "${cur}"))
+                return 0
+                ;;
+        esac
+    fi
+    _bodhi "$@"
+}
+complete -F _bodhi_aliases -o bashdefault -o default bodhi
+"#;
+
+const ZSH_ALIASES: &str = r#"
+_bodhi_aliases() {
+    if (( CURRENT == 3 )) && [[ ${words[2]} == (run|show|cp|edit|rm) && ${words[CURRENT]} != -* ]]; then
+        local -a aliases
+        aliases=(${(f)"$(bodhi completions --aliases 2>/dev/null)"})
+        compadd -a aliases
+        return
+    fi
+    _bodhi "$@"
+}
+compdef _bodhi_aliases bodhi
+"#;
+
+const FISH_ALIASES: &str = r#"
+complete -c bodhi -n "__fish_seen_subcommand_from run show cp edit rm; and test (count (commandline -opc)) -eq 2" -f -a "(bodhi completions --aliases 2>/dev/null)"
+"#;
+
+#[derive(Debug, PartialEq)]
+pub enum CompletionsCommand {
+  /// completion script for the shell
+  Script(Shell),
+  /// names of the model aliases, called by the completion script
+  Aliases,
+}
+
+impl TryFrom<Command> for CompletionsCommand {
+  type Error = CliError;
+
+  fn try_from(value: Command) -> Result<Self, Self::Error> {
+    match value {
+      Command::Completions {
+        shell: Some(shell),
+        aliases: false,
+      } => Ok(CompletionsCommand::Script(shell)),
+      Command::Completions { aliases: true, .. } => Ok(CompletionsCommand::Aliases),
+      cmd => Err(CliError::ConvertCommand(
+        cmd.to_string(),
+        "completions".to_string(),
+      )),
+    }
+  }
+}
+
+impl CompletionsCommand {
+  #[allow(clippy::result_large_err)]
+  pub fn execute(
+    self,
+    service: Arc<dyn AppServiceFn>,
+    stdout: &mut dyn StdoutWriter,
+  ) -> crate::error::Result<()> {
+    let output = match self {
+      CompletionsCommand::Script(shell) => script(shell),
+      CompletionsCommand::Aliases => service
+        .data_service()
+        .list_aliases()?
+        .into_iter()
+        .map(|alias| format!("{}\n", alias.alias))
+        .collect::<String>(),
+    };
+    stdout.write(&output).map_err(Common::Io)?;
+    Ok(())
+  }
+}
+
+fn script(shell: Shell) -> String {
+  let mut buf = Vec::new();
+  clap_complete::generate(shell, &mut Cli::command(), "bodhi", &mut buf);
+  let mut script = String::from_utf8_lossy(&buf).into_owned();
+  // clap only completes the static values, the model aliases are listed by calling bodhi
+  match shell {
+    Shell::Bash => script.push_str(BASH_ALIASES),
+    Shell::Zsh => script.push_str(ZSH_ALIASES),
+    Shell::Fish => script.push_str(FISH_ALIASES),
+    _ => {}
+  }
+  script
+}
+
+#[cfg(test)]
+mod test {
+  use super::CompletionsCommand;
+  use crate::{
+    test_utils::{app_service_stub, AppServiceTuple},
+    Command, MockStdoutWriter,
+  };
+  use clap_complete::Shell;
+  use mockall::predicate::eq;
+  use rstest::rstest;
+  use std::sync::Arc;
+
+  #[rstest]
+  #[case(Command::Completions { shell: Some(Shell::Bash), aliases: false }, CompletionsCommand::Script(Shell::Bash))]
+  #[case(Command::Completions { shell: None, aliases: true }, CompletionsCommand::Aliases)]
+  fn test_completions_command_from_cli(
+    #[case] input: Command,
+    #[case] expected: CompletionsCommand,
+  ) -> anyhow::Result<()> {
+    assert_eq!(expected, CompletionsCommand::try_from(input)?);
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_completions_command_lists_aliases(
+    app_service_stub: AppServiceTuple,
+  ) -> anyhow::Result<()> {
+    let AppServiceTuple(_temp_bodhi_home, _temp_hf_home, _, _, service) = app_service_stub;
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .with(eq(
+        "llama3:instruct\ntestalias-exists:instruct\ntinyllama:instruct\n",
+      ))
+      .return_once(|input| Ok(input.len()));
+    CompletionsCommand::Aliases.execute(Arc::new(service), &mut stdout)?;
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Shell::Bash, "complete -F _bodhi_aliases")]
+  #[case(Shell::Zsh, "compdef _bodhi_aliases bodhi")]
+  #[case(Shell::Fish, "bodhi completions --aliases")]
+  #[case(Shell::PowerShell, "Register-ArgumentCompleter")]
+  fn test_completions_command_script(
+    app_service_stub: AppServiceTuple,
+    #[case] shell: Shell,
+    #[case] expected: &'static str,
+  ) -> anyhow::Result<()> {
+    let AppServiceTuple(_temp_bodhi_home, _temp_hf_home, _, _, service) = app_service_stub;
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .withf(move |input| input.contains(expected) && input.contains("run"))
+      .return_once(|input| Ok(input.len()));
+    CompletionsCommand::Script(shell).execute(Arc::new(service), &mut stdout)?;
+    Ok(())
+  }
+}
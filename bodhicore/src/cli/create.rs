@@ -1,13 +1,24 @@
 use super::{CliError, Command};
 use crate::{
-  error::{BodhiError, Result},
+  error::{BodhiError, Common, Result},
   objs::{
-    default_features, Alias, ChatTemplate, GptContextParams, OAIRequestParams, Repo, REFS_MAIN,
-    TOKENIZER_CONFIG_JSON,
+    Alias, AliasFeature, ChatTemplate, GptContextParams, OAIRequestParams, Repo, DEFAULT_REVISION,
+    GGUF_EXTENSION, REFS_MAIN, TOKENIZER_CONFIG_JSON,
   },
-  service::AppServiceFn,
+  service::{AppServiceFn, HubServiceError},
 };
-use std::sync::Arc;
+use sha2::{Digest, Sha256};
+use std::{
+  fs,
+  path::{Path, PathBuf},
+  sync::Arc,
+};
+
+static LOCAL_REPO_OWNER: &str = "local";
+static README_MD: &str = "README.md";
+// the base models looked up for the tokenizer_config.json, e.g. the GGUF repo of a fine-tune of
+// the original model
+const MAX_BASE_MODEL_DEPTH: usize = 3;
 
 #[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(test, derive(derive_new::new, derive_builder::Builder))]
@@ -16,8 +27,10 @@ pub struct CreateCommand {
   alias: String,
   repo: Repo,
   filename: String,
+  file: Option<PathBuf>,
   chat_template: ChatTemplate,
   family: Option<String>,
+  features: Vec<AliasFeature>,
   force: bool,
   oai_request_params: OAIRequestParams,
   context_params: GptContextParams,
@@ -32,9 +45,11 @@ impl TryFrom<Command> for CreateCommand {
         alias,
         repo,
         filename,
+        file,
         chat_template,
         tokenizer_config,
         family,
+        features,
         force,
         oai_request_params,
         context_params,
@@ -50,12 +65,23 @@ impl TryFrom<Command> for CreateCommand {
             }
           },
         };
+        let (repo, filename) = match (&file, repo, filename) {
+          (Some(file), _, _) => local_repo_filename(file)?,
+          (None, Some(repo), Some(filename)) => (Repo::try_from(repo)?, filename),
+          (None, repo, filename) => {
+            return Err(CliError::BadRequest(format!(
+              "cannot initialize create command with invalid state. repo: '{repo:?}', filename: '{filename:?}', file: None"
+            )))
+          }
+        };
         let result = CreateCommand {
           alias,
-          repo: Repo::try_from(repo)?,
+          repo,
           filename,
+          file,
           chat_template,
           family,
+          features,
           force,
           oai_request_params,
           context_params,
@@ -76,91 +102,233 @@ impl CreateCommand {
     if !self.force && service.data_service().find_alias(&self.alias).is_some() {
       return Err(BodhiError::AliasExists(self.alias.clone()));
     }
-    let local_model_file =
-      service
-        .hub_service()
-        .find_local_file(&self.repo, &self.filename, REFS_MAIN)?;
-    let local_model_file = match local_model_file {
-      Some(local_model_file) => {
+    let alias = self.build_alias(service.as_ref())?;
+    service.data_service().save_alias(&alias)?;
+    println!(
+      "model alias: '{}' saved to $BODHI_HOME/aliases",
+      alias.alias
+    );
+    Ok(())
+  }
+
+  /// Links or pulls the model file and the tokenizer config into $HF_HOME, and returns the model
+  /// alias using them, not saved yet
+  #[allow(clippy::result_large_err)]
+  pub(crate) fn build_alias(self, service: &dyn AppServiceFn) -> Result<Alias> {
+    let local_model_file = match &self.file {
+      Some(file) => {
+        let local_model_file = service.hub_service().link_local_file(
+          file,
+          &self.repo,
+          &self.filename,
+          &local_snapshot(file),
+          false,
+        )?;
         println!(
-          "repo: '{}', filename: '{}' already exists in $HF_HOME",
-          &self.repo, &self.filename
+          "file: '{}' linked to $HF_HOME as repo: '{}', filename: '{}'",
+          file.display(),
+          &self.repo,
+          &self.filename
         );
         local_model_file
       }
-      None => service
-        .hub_service()
-        .download(&self.repo, &self.filename, self.force)?,
+      None => {
+        let local_model_file =
+          service
+            .hub_service()
+            .find_local_file(&self.repo, &self.filename, REFS_MAIN)?;
+        match local_model_file {
+          Some(local_model_file) => {
+            println!(
+              "repo: '{}', filename: '{}' already exists in $HF_HOME",
+              &self.repo, &self.filename
+            );
+            local_model_file
+          }
+          None => service.hub_service().download(
+            &self.repo,
+            &self.filename,
+            DEFAULT_REVISION,
+            self.force,
+          )?,
+        }
+      }
     };
-    let chat_template_repo = Repo::try_from(self.chat_template.clone())?;
-    let tokenizer_file = service.hub_service().find_local_file(
-      &chat_template_repo,
-      TOKENIZER_CONFIG_JSON,
-      REFS_MAIN,
-    )?;
+    let chat_template = match &self.chat_template {
+      ChatTemplate::Id(_) => {
+        let chat_template_repo = Repo::try_from(self.chat_template.clone())?;
+        self.fetch_tokenizer(service, &chat_template_repo)?;
+        self.chat_template.clone()
+      }
+      ChatTemplate::Repo(repo) => {
+        ChatTemplate::Repo(self.resolve_tokenizer_repo(service, repo.clone())?)
+      }
+    };
+    let alias: Alias = Alias::new(
+      self.alias,
+      self.family,
+      self.repo,
+      self.filename,
+      local_model_file.snapshot.clone(),
+      self
+        .features
+        .iter()
+        .map(|feature| feature.to_string())
+        .collect(),
+      chat_template,
+      self.oai_request_params,
+      self.context_params,
+    );
+    Ok(alias)
+  }
+
+  // the repo having the tokenizer_config.json, the given repo or else the base model listed in
+  // its model card, e.g. the original model of a GGUF repo
+  #[allow(clippy::result_large_err)]
+  fn resolve_tokenizer_repo(&self, service: &dyn AppServiceFn, repo: Repo) -> Result<Repo> {
+    let mut repo = repo;
+    let mut depth = 0;
+    loop {
+      let err = match self.fetch_tokenizer(service, &repo) {
+        Ok(()) => return Ok(repo),
+        Err(err) if err.is_not_found() && depth < MAX_BASE_MODEL_DEPTH => err,
+        Err(err) => return Err(err.into()),
+      };
+      let Some(base_model) = self.base_model(service, &repo)? else {
+        return Err(err.into());
+      };
+      println!(
+        "tokenizer_config.json not found in repo: '{}', using the base model repo: '{}' from its model card",
+        repo, base_model
+      );
+      repo = base_model;
+      depth += 1;
+    }
+  }
+
+  fn fetch_tokenizer(
+    &self,
+    service: &dyn AppServiceFn,
+    repo: &Repo,
+  ) -> std::result::Result<(), HubServiceError> {
+    let tokenizer_file =
+      service
+        .hub_service()
+        .find_local_file(repo, TOKENIZER_CONFIG_JSON, REFS_MAIN)?;
     match tokenizer_file {
       Some(_) if !self.force => {
         println!(
           "tokenizer from repo: '{}', filename: '{}' already exists in $HF_HOME",
-          &self.repo, &self.filename
+          repo, TOKENIZER_CONFIG_JSON
         );
       }
       _ => {
-        service
-          .hub_service()
-          .download(&chat_template_repo, TOKENIZER_CONFIG_JSON, self.force)?;
+        service.hub_service().download(
+          repo,
+          TOKENIZER_CONFIG_JSON,
+          DEFAULT_REVISION,
+          self.force,
+        )?;
         println!(
           "tokenizer from repo: '{}', filename: '{}' downloaded into $HF_HOME",
-          &self.repo, &self.filename
+          repo, TOKENIZER_CONFIG_JSON
         );
       }
     }
-    let alias: Alias = Alias::new(
-      self.alias,
-      self.family,
-      self.repo,
-      self.filename,
-      local_model_file.snapshot.clone(),
-      default_features(),
-      self.chat_template,
-      self.oai_request_params,
-      self.context_params,
-    );
-    service.data_service().save_alias(&alias)?;
-    println!(
-      "model alias: '{}' saved to $BODHI_HOME/aliases",
-      alias.alias
-    );
     Ok(())
   }
+
+  #[allow(clippy::result_large_err)]
+  fn base_model(&self, service: &dyn AppServiceFn, repo: &Repo) -> Result<Option<Repo>> {
+    let readme = match service
+      .hub_service()
+      .download(repo, README_MD, DEFAULT_REVISION, self.force)
+    {
+      Ok(readme) => readme,
+      Err(err) if err.is_not_found() => return Ok(None),
+      Err(err) => return Err(err.into()),
+    };
+    let path = readme.path();
+    let content = fs::read_to_string(&path).map_err(|source| Common::IoFile {
+      source,
+      path: path.display().to_string(),
+    })?;
+    Ok(card_base_model(&content).filter(|base_model| base_model != repo))
+  }
+}
+
+// the `base_model` in the yaml front matter of the model card, the first one if many
+fn card_base_model(readme: &str) -> Option<Repo> {
+  let front_matter = readme.trim_start().strip_prefix("---")?;
+  let (front_matter, _) = front_matter.split_once("\n---")?;
+  let front_matter = serde_yaml::from_str::<serde_yaml::Value>(front_matter).ok()?;
+  let base_model = match front_matter.get("base_model")? {
+    serde_yaml::Value::Sequence(base_models) => base_models.first()?.as_str()?,
+    base_model => base_model.as_str()?,
+  };
+  Repo::try_from(base_model.to_string()).ok()
+}
+
+/// local files are added to $HF_HOME under the `local/<file stem>` repo
+fn local_repo_filename(file: &Path) -> std::result::Result<(Repo, String), CliError> {
+  let filename = file
+    .file_name()
+    .map(|filename| filename.to_string_lossy().to_string())
+    .ok_or_else(|| CliError::BadRequest(format!("'{}' is not a file", file.display())))?;
+  let name = filename
+    .strip_suffix(GGUF_EXTENSION)
+    .unwrap_or(&filename)
+    .chars()
+    .map(|c| match c {
+      'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '.' | '-' => c,
+      _ => '-',
+    })
+    .collect::<String>();
+  let repo = Repo::try_from(format!("{LOCAL_REPO_OWNER}/{name}"))?;
+  Ok((repo, filename))
+}
+
+/// snapshot id derived from the sha256 of the absolute path, so creating an alias for the same
+/// file reuses the snapshot, also after upgrading bodhi
+fn local_snapshot(file: &Path) -> String {
+  let path = file.canonicalize().unwrap_or_else(|_| file.to_path_buf());
+  Sha256::digest(path.to_string_lossy().as_bytes())
+    .iter()
+    .take(8)
+    .map(|byte| format!("{byte:02x}"))
+    .collect()
 }
 
 #[cfg(test)]
 mod test {
-  use super::CreateCommand;
+  use super::{card_base_model, local_snapshot, CreateCommand, README_MD};
   use crate::{
     cli::Command,
     objs::{
-      Alias, ChatTemplate, ChatTemplateId, GptContextParams, HubFile, OAIRequestParams, Repo,
-      REFS_MAIN, TOKENIZER_CONFIG_JSON,
+      Alias, AliasFeature, ChatTemplate, ChatTemplateId, GptContextParams, HubFile,
+      OAIRequestParams, Repo, DEFAULT_REVISION, REFS_MAIN, TOKENIZER_CONFIG_JSON,
     },
-    service::{MockDataService, MockEnvServiceFn, MockHubService},
+    service::{HubServiceError, MockDataService, MockEnvServiceFn, MockHubService},
     test_utils::AppServiceStubMock,
   };
   use anyhow_trace::anyhow_trace;
-  use mockall::predicate::eq;
+  use hf_hub::api::sync::ApiError;
+  use mockall::predicate::{always, eq};
   use rstest::rstest;
-  use std::{path::PathBuf, sync::Arc};
+  use std::{fs, path::PathBuf, sync::Arc};
+  use tempfile::TempDir;
 
   #[rstest]
   #[case(
   Command::Create {
     alias: "testalias:instruct".to_string(),
-    repo: "MyFactory/testalias-gguf".to_string(),
-    filename: "testalias.Q8_0.gguf".to_string(),
+    repo: Some("MyFactory/testalias-gguf".to_string()),
+    filename: Some("testalias.Q8_0.gguf".to_string()),
+    file: None,
     chat_template: Some(ChatTemplateId::Llama3),
     tokenizer_config: None,
     family: Some("testalias".to_string()),
+    features: vec![AliasFeature::Chat],
     force: false,
     oai_request_params: OAIRequestParams::default(),
     context_params: GptContextParams::default(),
@@ -169,8 +337,36 @@ mod test {
     alias: "testalias:instruct".to_string(),
     repo: Repo::try_from("MyFactory/testalias-gguf".to_string())?,
     filename: "testalias.Q8_0.gguf".to_string(),
+    file: None,
     chat_template: ChatTemplate::Id(ChatTemplateId::Llama3),
     family: Some("testalias".to_string()),
+    features: vec![AliasFeature::Chat],
+    force: false,
+    oai_request_params: OAIRequestParams::default(),
+    context_params: GptContextParams::default(),
+  })]
+  #[case(
+  Command::Create {
+    alias: "mymodel:instruct".to_string(),
+    repo: None,
+    filename: None,
+    file: Some(PathBuf::from("/models/My Model.Q4_0.gguf")),
+    chat_template: Some(ChatTemplateId::Llama3),
+    tokenizer_config: None,
+    family: None,
+    features: vec![AliasFeature::Chat],
+    force: false,
+    oai_request_params: OAIRequestParams::default(),
+    context_params: GptContextParams::default(),
+  },
+  CreateCommand {
+    alias: "mymodel:instruct".to_string(),
+    repo: Repo::try_from("local/My-Model.Q4_0")?,
+    filename: "My Model.Q4_0.gguf".to_string(),
+    file: Some(PathBuf::from("/models/My Model.Q4_0.gguf")),
+    chat_template: ChatTemplate::Id(ChatTemplateId::Llama3),
+    family: None,
+    features: vec![AliasFeature::Chat],
     force: false,
     oai_request_params: OAIRequestParams::default(),
     context_params: GptContextParams::default(),
@@ -203,8 +399,10 @@ mod test {
       alias: "testalias:instruct".to_string(),
       repo: Repo::try_from("MyFactory/testalias-gguf".to_string())?,
       filename: "testalias.Q8_0.gguf".to_string(),
+      file: None,
       chat_template: ChatTemplate::Id(ChatTemplateId::Llama3),
       family: None,
+      features: vec![AliasFeature::Chat],
       force: false,
       oai_request_params: OAIRequestParams::default(),
       context_params: GptContextParams::default(),
@@ -252,9 +450,10 @@ mod test {
       .with(
         eq(create.repo.clone()),
         eq(create.filename.clone()),
+        eq(DEFAULT_REVISION),
         eq(false),
       )
-      .return_once(|_, _, _| Ok(HubFile::testalias()));
+      .return_once(|_, _, _, _| Ok(HubFile::testalias()));
     mock_hub_service
       .expect_find_local_file()
       .with(eq(Repo::llama3()), eq(TOKENIZER_CONFIG_JSON), eq(REFS_MAIN))
@@ -298,17 +497,23 @@ mod test {
       .with(
         eq(create.repo.clone()),
         eq(create.filename.clone()),
+        eq(DEFAULT_REVISION),
         eq(false),
       )
-      .return_once(|_, _, _| Ok(HubFile::testalias()));
+      .return_once(|_, _, _, _| Ok(HubFile::testalias()));
     mock_hub_service
       .expect_find_local_file()
       .with(eq(tokenizer_repo.clone()), eq(TOKENIZER_CONFIG_JSON), eq(REFS_MAIN))
       .return_once(|_, _, _| Ok(None));
     mock_hub_service
       .expect_download()
-      .with(eq(tokenizer_repo), eq(TOKENIZER_CONFIG_JSON), eq(false))
-      .return_once(|_, _, _| Ok(HubFile::testalias_tokenizer()));
+      .with(
+        eq(tokenizer_repo),
+        eq(TOKENIZER_CONFIG_JSON),
+        eq(DEFAULT_REVISION),
+        eq(false),
+      )
+      .return_once(|_, _, _, _| Ok(HubFile::testalias_tokenizer()));
     let alias = Alias::test_alias_instruct_builder()
       .chat_template(chat_template.clone())
       .build()
@@ -322,4 +527,251 @@ mod test {
     create.execute(Arc::new(service))?;
     Ok(())
   }
+
+  fn not_found() -> HubServiceError {
+    let response = ureq::Response::new(404, "Not Found", "Entry not found").unwrap();
+    HubServiceError::ApiError(ApiError::RequestError(Box::new(ureq::Error::Status(
+      404, response,
+    ))))
+  }
+
+  #[rstest]
+  #[case(
+    "---\nlicense: mit\nbase_model: MyFactory/testalias\n---\n# testalias GGUF",
+    Some("MyFactory/testalias")
+  )]
+  #[case(
+    "---\nbase_model:\n- MyFactory/testalias\n- MyFactory/other\n---\n",
+    Some("MyFactory/testalias")
+  )]
+  #[case("---\nlicense: mit\n---\n# testalias GGUF", None)]
+  #[case("# testalias GGUF\nbase_model: MyFactory/testalias", None)]
+  fn test_create_card_base_model(
+    #[case] readme: &str,
+    #[case] expected: Option<&str>,
+  ) -> anyhow::Result<()> {
+    let expected = expected.map(Repo::try_from).transpose()?;
+    assert_eq!(expected, card_base_model(readme));
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_create_execute_resolves_tokenizer_from_base_model() -> anyhow::Result<()> {
+    let gguf_repo = Repo::try_from("MyFactory/testalias-gguf")?;
+    let base_repo = Repo::try_from("MyFactory/testalias")?;
+    let create = CreateCommand::testalias_builder()
+      .chat_template(ChatTemplate::Repo(gguf_repo.clone()))
+      .build()
+      .unwrap();
+    let temp_hf_home = TempDir::new()?;
+    let readme = HubFile::new(
+      temp_hf_home.path().to_path_buf(),
+      gguf_repo.clone(),
+      README_MD.to_string(),
+      "main".to_string(),
+      None,
+    );
+    fs::create_dir_all(readme.path().parent().unwrap())?;
+    fs::write(
+      readme.path(),
+      "---\nbase_model: MyFactory/testalias\n---\n# testalias GGUF\n",
+    )?;
+    let mut mock_data_service = MockDataService::default();
+    mock_data_service
+      .expect_find_alias()
+      .with(eq(create.alias.clone()))
+      .return_once(|_| None);
+    let mut mock_hub_service = MockHubService::new();
+    mock_hub_service
+      .expect_find_local_file()
+      .with(
+        eq(create.repo.clone()),
+        eq(create.filename.clone()),
+        eq(REFS_MAIN),
+      )
+      .return_once(|_, _, _| Ok(Some(HubFile::testalias())));
+    mock_hub_service
+      .expect_find_local_file()
+      .with(
+        eq(gguf_repo.clone()),
+        eq(TOKENIZER_CONFIG_JSON),
+        eq(REFS_MAIN),
+      )
+      .return_once(|_, _, _| Ok(None));
+    mock_hub_service
+      .expect_download()
+      .with(
+        eq(gguf_repo.clone()),
+        eq(TOKENIZER_CONFIG_JSON),
+        eq(DEFAULT_REVISION),
+        eq(false),
+      )
+      .return_once(|_, _, _, _| Err(not_found()));
+    mock_hub_service
+      .expect_download()
+      .with(
+        eq(gguf_repo),
+        eq(README_MD),
+        eq(DEFAULT_REVISION),
+        eq(false),
+      )
+      .return_once(|_, _, _, _| Ok(readme));
+    mock_hub_service
+      .expect_find_local_file()
+      .with(
+        eq(base_repo.clone()),
+        eq(TOKENIZER_CONFIG_JSON),
+        eq(REFS_MAIN),
+      )
+      .return_once(|_, _, _| Ok(Some(HubFile::testalias_tokenizer())));
+    let alias = Alias::test_alias_instruct_builder()
+      .chat_template(ChatTemplate::Repo(base_repo))
+      .build()
+      .unwrap();
+    mock_data_service
+      .expect_save_alias()
+      .with(eq(alias))
+      .return_once(|_| Ok(PathBuf::from("ignored")));
+    let service =
+      AppServiceStubMock::new(MockEnvServiceFn::new(), mock_hub_service, mock_data_service);
+    create.execute(Arc::new(service))?;
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_create_execute_fails_if_tokenizer_and_base_model_not_found() -> anyhow::Result<()> {
+    let gguf_repo = Repo::try_from("MyFactory/testalias-gguf")?;
+    let create = CreateCommand::testalias_builder()
+      .chat_template(ChatTemplate::Repo(gguf_repo.clone()))
+      .build()
+      .unwrap();
+    let mut mock_data_service = MockDataService::default();
+    mock_data_service
+      .expect_find_alias()
+      .with(eq(create.alias.clone()))
+      .return_once(|_| None);
+    mock_data_service.expect_save_alias().never();
+    let mut mock_hub_service = MockHubService::new();
+    mock_hub_service
+      .expect_find_local_file()
+      .with(
+        eq(create.repo.clone()),
+        eq(create.filename.clone()),
+        eq(REFS_MAIN),
+      )
+      .return_once(|_, _, _| Ok(Some(HubFile::testalias())));
+    mock_hub_service
+      .expect_find_local_file()
+      .with(
+        eq(gguf_repo.clone()),
+        eq(TOKENIZER_CONFIG_JSON),
+        eq(REFS_MAIN),
+      )
+      .return_once(|_, _, _| Ok(None));
+    mock_hub_service
+      .expect_download()
+      .with(
+        eq(gguf_repo.clone()),
+        eq(TOKENIZER_CONFIG_JSON),
+        eq(DEFAULT_REVISION),
+        eq(false),
+      )
+      .return_once(|_, _, _, _| Err(not_found()));
+    mock_hub_service
+      .expect_download()
+      .with(
+        eq(gguf_repo),
+        eq(README_MD),
+        eq(DEFAULT_REVISION),
+        eq(false),
+      )
+      .return_once(|_, _, _, _| Err(not_found()));
+    let service =
+      AppServiceStubMock::new(MockEnvServiceFn::new(), mock_hub_service, mock_data_service);
+    let result = create.execute(Arc::new(service));
+    assert!(result.is_err());
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_create_execute_with_file_links_file_saves_alias() -> anyhow::Result<()> {
+    let file = PathBuf::from("/models/testalias.Q8_0.gguf");
+    let create = CreateCommand::testalias_builder()
+      .file(Some(file.clone()))
+      .build()
+      .unwrap();
+    let mut mock_data_service = MockDataService::default();
+    mock_data_service
+      .expect_find_alias()
+      .with(eq(create.alias.clone()))
+      .return_once(|_| None);
+    let mut mock_hub_service = MockHubService::default();
+    mock_hub_service
+      .expect_link_local_file()
+      .with(
+        eq(file),
+        eq(create.repo.clone()),
+        eq(create.filename.clone()),
+        always(),
+        eq(false),
+      )
+      .return_once(|_, _, _, _, _| Ok(HubFile::testalias()));
+    mock_hub_service.expect_download().never();
+    mock_hub_service
+      .expect_find_local_file()
+      .with(eq(Repo::llama3()), eq(TOKENIZER_CONFIG_JSON), eq(REFS_MAIN))
+      .return_once(|_, _, _| Ok(Some(HubFile::llama3_tokenizer())));
+    mock_data_service
+      .expect_save_alias()
+      .with(eq(Alias::testalias()))
+      .return_once(|_| Ok(PathBuf::from(".")));
+    let service =
+      AppServiceStubMock::new(MockEnvServiceFn::new(), mock_hub_service, mock_data_service);
+    create.execute(Arc::new(service))?;
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_create_execute_saves_alias_with_features() -> anyhow::Result<()> {
+    let create = CreateCommand::testalias_builder()
+      .features(vec![AliasFeature::Rerank])
+      .build()
+      .unwrap();
+    let mut mock_data_service = MockDataService::default();
+    mock_data_service
+      .expect_find_alias()
+      .with(eq(create.alias.clone()))
+      .return_once(|_| None);
+    let mut mock_hub_service = MockHubService::default();
+    mock_hub_service
+      .expect_find_local_file()
+      .with(
+        eq(create.repo.clone()),
+        eq(create.filename.clone()),
+        eq(REFS_MAIN),
+      )
+      .return_once(|_, _, _| Ok(Some(HubFile::testalias())));
+    mock_hub_service
+      .expect_find_local_file()
+      .with(eq(Repo::llama3()), eq(TOKENIZER_CONFIG_JSON), eq(REFS_MAIN))
+      .return_once(|_, _, _| Ok(Some(HubFile::llama3_tokenizer())));
+    let alias = Alias {
+      features: vec!["rerank".to_string()],
+      ..Alias::testalias()
+    };
+    mock_data_service
+      .expect_save_alias()
+      .with(eq(alias))
+      .return_once(|_| Ok(PathBuf::from(".")));
+    let service =
+      AppServiceStubMock::new(MockEnvServiceFn::new(), mock_hub_service, mock_data_service);
+    create.execute(Arc::new(service))?;
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_create_local_snapshot_is_stable() {
+    let file = PathBuf::from("/models/mymodel.Q4_0.gguf");
+    assert_eq!("3b77e41c635691e8", local_snapshot(&file));
+  }
 }
@@ -0,0 +1,420 @@
+use super::{CliError, StdoutWriter};
+use crate::{
+  error::Common,
+  service::{AppServiceFn, PID_FILE, SERVE_OUT},
+  Command,
+};
+use chrono::{DateTime, Utc};
+use prettytable::{format::FormatBuilder, row, Table};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+  env,
+  fs::{self, File},
+  io,
+  path::{Path, PathBuf},
+  process::{self, Stdio},
+  sync::Arc,
+  time::{Duration, Instant},
+};
+
+/// Running server, written to $BODHI_HOME/bodhi.pid once the server is ready
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerInfo {
+  pub pid: u32,
+  pub host: String,
+  pub port: u16,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub uds: Option<PathBuf>,
+  pub started_at: DateTime<Utc>,
+}
+
+impl ServerInfo {
+  pub(crate) fn write(&self, bodhi_home: &Path) -> Result<(), Common> {
+    let path = bodhi_home.join(PID_FILE);
+    let content = serde_json::to_string(self)?;
+    fs::write(&path, content).map_err(|source| Common::IoFile {
+      source,
+      path: path.display().to_string(),
+    })?;
+    Ok(())
+  }
+
+  /// Reads the pid file, an unreadable pid file is treated as no server running
+  pub(crate) fn read(bodhi_home: &Path) -> Option<Self> {
+    let content = fs::read_to_string(bodhi_home.join(PID_FILE)).ok()?;
+    serde_json::from_str::<ServerInfo>(&content).ok()
+  }
+
+  /// Removes the pid file, if it is of the server with the given pid
+  pub(crate) fn remove(bodhi_home: &Path, pid: u32) {
+    if Self::read(bodhi_home).is_some_and(|info| info.pid == pid) {
+      if let Err(err) = fs::remove_file(bodhi_home.join(PID_FILE)) {
+        tracing::warn!(?err, "error removing the pid file");
+      }
+    }
+  }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DaemonCommand {
+  Ps,
+  Stop,
+}
+
+impl TryFrom<Command> for DaemonCommand {
+  type Error = CliError;
+
+  fn try_from(value: Command) -> Result<Self, Self::Error> {
+    match value {
+      Command::Ps {} => Ok(DaemonCommand::Ps),
+      Command::Stop {} => Ok(DaemonCommand::Stop),
+      cmd => Err(CliError::ConvertCommand(cmd.to_string(), "ps".to_string())),
+    }
+  }
+}
+
+impl DaemonCommand {
+  pub fn execute(
+    self,
+    service: Arc<dyn AppServiceFn>,
+    stdout: &mut dyn StdoutWriter,
+  ) -> crate::error::Result<()> {
+    let env_service = service.env_service();
+    let bodhi_home = env_service.bodhi_home();
+    match self {
+      DaemonCommand::Ps => ps(&bodhi_home, stdout)?,
+      DaemonCommand::Stop => {
+        // the server lets the in-flight requests finish before exiting
+        let timeout = Duration::from_secs(env_service.drain_timeout_secs() + 10);
+        stop(&bodhi_home, timeout, stdout)?;
+      }
+    }
+    Ok(())
+  }
+}
+
+// the server from the pid file, removing the pid file left behind by a server that crashed
+pub(crate) fn running_server(bodhi_home: &Path) -> Option<ServerInfo> {
+  let info = ServerInfo::read(bodhi_home)?;
+  if is_alive(info.pid) {
+    return Some(info);
+  }
+  ServerInfo::remove(bodhi_home, info.pid);
+  None
+}
+
+fn ps(bodhi_home: &Path, stdout: &mut dyn StdoutWriter) -> crate::error::Result<()> {
+  let Some(info) = running_server(bodhi_home) else {
+    stdout
+      .write("no bodhi server running\n")
+      .map_err(Common::from)?;
+    return Ok(());
+  };
+  let ready = ready_status(&info);
+  let model = ready
+    .as_ref()
+    .and_then(|ready| ready["model"].as_str())
+    .and_then(|model| Path::new(model).file_name())
+    .map(|model| model.to_string_lossy().to_string())
+    .unwrap_or_else(|| "-".to_string());
+  let queue = ready
+    .as_ref()
+    .and_then(|ready| ready["queue_depth"].as_u64())
+    .map(|queue| queue.to_string())
+    .unwrap_or_else(|| "-".to_string());
+  let address = match &info.uds {
+    Some(uds) => uds.display().to_string(),
+    None => format!("http://{}:{}", info.host, info.port),
+  };
+  let uptime = format_uptime((Utc::now() - info.started_at).num_seconds());
+  let mut table = Table::new();
+  table.add_row(row!["PID", "ADDRESS", "MODEL", "QUEUE", "UPTIME"]);
+  table.add_row(row![info.pid, address, model, queue, uptime]);
+  table.set_format(FormatBuilder::default().padding(2, 2).build());
+  stdout.write(&table.to_string()).map_err(Common::from)?;
+  Ok(())
+}
+
+// the /ready status of the server, None if the server cannot be reached over http
+fn ready_status(info: &ServerInfo) -> Option<Value> {
+  if info.uds.is_some() {
+    return None;
+  }
+  let host = if info.host == "0.0.0.0" {
+    "127.0.0.1"
+  } else {
+    &info.host
+  };
+  let url = format!("http://{host}:{}/ready", info.port);
+  match ureq::get(&url).timeout(Duration::from_secs(2)).call() {
+    Ok(response) | Err(ureq::Error::Status(_, response)) => response.into_json::<Value>().ok(),
+    Err(err) => {
+      tracing::debug!(?err, url, "error reading the server status");
+      None
+    }
+  }
+}
+
+fn stop(
+  bodhi_home: &Path,
+  timeout: Duration,
+  stdout: &mut dyn StdoutWriter,
+) -> crate::error::Result<()> {
+  let Some(info) = running_server(bodhi_home) else {
+    stdout
+      .write("no bodhi server running\n")
+      .map_err(Common::from)?;
+    return Ok(());
+  };
+  terminate(info.pid).map_err(Common::from)?;
+  let start = Instant::now();
+  while is_alive(info.pid) {
+    if start.elapsed() > timeout {
+      return Err(
+        Common::from(io::Error::new(
+          io::ErrorKind::TimedOut,
+          format!(
+            "bodhi server with pid {} did not stop within {}s",
+            info.pid,
+            timeout.as_secs()
+          ),
+        ))
+        .into(),
+      );
+    }
+    std::thread::sleep(Duration::from_millis(200));
+  }
+  ServerInfo::remove(bodhi_home, info.pid);
+  stdout
+    .write(&format!("stopped bodhi server with pid {}\n", info.pid))
+    .map_err(Common::from)?;
+  Ok(())
+}
+
+/// Runs the current `bodhi serve` command again in the background without `--detach`,
+/// and waits for the background server to be ready
+pub(crate) fn spawn_detached(
+  bodhi_home: &Path,
+  logs_dir: &Path,
+  timeout: Duration,
+) -> crate::error::Result<ServerInfo> {
+  if let Some(info) = running_server(bodhi_home) {
+    return Err(
+      Common::from(io::Error::new(
+        io::ErrorKind::AddrInUse,
+        format!(
+          "bodhi server already running with pid {}, stop it using `bodhi stop`",
+          info.pid
+        ),
+      ))
+      .into(),
+    );
+  }
+  let exe = env::current_exe().map_err(Common::from)?;
+  let args = env::args_os().skip(1).filter(|arg| arg != "--detach");
+  fs::create_dir_all(logs_dir).map_err(Common::from)?;
+  let out_path = logs_dir.join(SERVE_OUT);
+  let out = File::create(&out_path).map_err(|source| Common::IoFile {
+    source,
+    path: out_path.display().to_string(),
+  })?;
+  let err = out.try_clone().map_err(Common::from)?;
+  let mut command = process::Command::new(exe);
+  command
+    .args(args)
+    .stdin(Stdio::null())
+    .stdout(out)
+    .stderr(err);
+  #[cfg(unix)]
+  {
+    use std::os::unix::process::CommandExt;
+    // in its own process group, so the Ctrl+C of the terminal does not stop the server
+    command.process_group(0);
+  }
+  let mut child = command.spawn().map_err(Common::from)?;
+  let start = Instant::now();
+  loop {
+    if let Some(info) = ServerInfo::read(bodhi_home).filter(|info| info.pid == child.id()) {
+      return Ok(info);
+    }
+    if let Some(status) = child.try_wait().map_err(Common::from)? {
+      return Err(
+        Common::from(io::Error::other(format!(
+          "bodhi server exited with {status}, see {} for the output",
+          out_path.display()
+        )))
+        .into(),
+      );
+    }
+    if start.elapsed() > timeout {
+      return Err(
+        Common::from(io::Error::new(
+          io::ErrorKind::TimedOut,
+          format!(
+            "bodhi server with pid {} not ready within {}s, see {} for the output",
+            child.id(),
+            timeout.as_secs(),
+            out_path.display()
+          ),
+        ))
+        .into(),
+      );
+    }
+    std::thread::sleep(Duration::from_millis(200));
+  }
+}
+
+fn format_uptime(secs: i64) -> String {
+  let secs = secs.max(0);
+  match (secs / 3600, secs % 3600 / 60, secs % 60) {
+    (0, 0, secs) => format!("{secs}s"),
+    (0, mins, secs) => format!("{mins}m {secs}s"),
+    (hours, mins, _) => format!("{hours}h {mins}m"),
+  }
+}
+
+#[cfg(unix)]
+fn is_alive(pid: u32) -> bool {
+  // signal 0 only checks the process exists
+  unsafe { libc::kill(pid as libc::pid_t, 0) == 0 }
+}
+
+#[cfg(unix)]
+fn terminate(pid: u32) -> io::Result<()> {
+  if unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) } == 0 {
+    Ok(())
+  } else {
+    Err(io::Error::last_os_error())
+  }
+}
+
+#[cfg(not(unix))]
+fn is_alive(_pid: u32) -> bool {
+  true
+}
+
+#[cfg(not(unix))]
+fn terminate(_pid: u32) -> io::Result<()> {
+  Err(io::Error::new(
+    io::ErrorKind::Unsupported,
+    "bodhi stop is not supported on this platform",
+  ))
+}
+
+#[cfg(test)]
+mod test {
+  use super::{format_uptime, ps, stop, DaemonCommand, ServerInfo};
+  use crate::{service::PID_FILE, Command, MockStdoutWriter};
+  use chrono::Utc;
+  use mockall::predicate::eq;
+  use rstest::rstest;
+  use std::{process::Command as Process, time::Duration};
+  use tempfile::TempDir;
+
+  #[rstest]
+  #[case(Command::Ps {}, DaemonCommand::Ps)]
+  #[case(Command::Stop {}, DaemonCommand::Stop)]
+  fn test_daemon_command_from_cli(
+    #[case] command: Command,
+    #[case] expected: DaemonCommand,
+  ) -> anyhow::Result<()> {
+    assert_eq!(expected, DaemonCommand::try_from(command)?);
+    let result = DaemonCommand::try_from(Command::App { ui: false });
+    assert_eq!(
+      "Command 'app' cannot be converted into command 'ps'",
+      result.unwrap_err().to_string()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_daemon_server_info_write_read_remove() -> anyhow::Result<()> {
+    let bodhi_home = TempDir::new()?;
+    let info = ServerInfo {
+      pid: 4242,
+      host: "0.0.0.0".to_string(),
+      port: 8080,
+      uds: None,
+      started_at: Utc::now(),
+    };
+    info.write(bodhi_home.path())?;
+    assert_eq!(Some(info), ServerInfo::read(bodhi_home.path()));
+    ServerInfo::remove(bodhi_home.path(), 4343);
+    assert!(bodhi_home.path().join(PID_FILE).exists());
+    ServerInfo::remove(bodhi_home.path(), 4242);
+    assert_eq!(None, ServerInfo::read(bodhi_home.path()));
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(42, "42s")]
+  #[case(185, "3m 5s")]
+  #[case(7385, "2h 3m")]
+  fn test_daemon_format_uptime(#[case] secs: i64, #[case] expected: &str) {
+    assert_eq!(expected, format_uptime(secs));
+  }
+
+  #[rstest]
+  fn test_daemon_ps_no_server_running() -> anyhow::Result<()> {
+    let bodhi_home = TempDir::new()?;
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .with(eq("no bodhi server running\n"))
+      .return_once(|msg| Ok(msg.len()));
+    ps(bodhi_home.path(), &mut stdout)?;
+    Ok(())
+  }
+
+  #[cfg(unix)]
+  #[rstest]
+  fn test_daemon_ps_removes_stale_pid_file() -> anyhow::Result<()> {
+    let bodhi_home = TempDir::new()?;
+    // pid of a process that has exited
+    let mut child = Process::new("true").spawn()?;
+    child.wait()?;
+    ServerInfo {
+      pid: child.id(),
+      host: "127.0.0.1".to_string(),
+      port: 1135,
+      uds: None,
+      started_at: Utc::now(),
+    }
+    .write(bodhi_home.path())?;
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .with(eq("no bodhi server running\n"))
+      .return_once(|msg| Ok(msg.len()));
+    ps(bodhi_home.path(), &mut stdout)?;
+    assert!(!bodhi_home.path().join(PID_FILE).exists());
+    Ok(())
+  }
+
+  #[cfg(unix)]
+  #[rstest]
+  fn test_daemon_stop_terminates_server() -> anyhow::Result<()> {
+    let bodhi_home = TempDir::new()?;
+    let mut child = Process::new("sleep").arg("30").spawn()?;
+    ServerInfo {
+      pid: child.id(),
+      host: "127.0.0.1".to_string(),
+      port: 1135,
+      uds: None,
+      started_at: Utc::now(),
+    }
+    .write(bodhi_home.path())?;
+    // reaps the child once terminated, so it is not left as a zombie that looks alive
+    let waiter = std::thread::spawn(move || child.wait());
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .withf(|msg| msg.starts_with("stopped bodhi server with pid"))
+      .return_once(|msg| Ok(msg.len()));
+    stop(bodhi_home.path(), Duration::from_secs(5), &mut stdout)?;
+    assert!(!waiter.join().unwrap()?.success());
+    assert!(!bodhi_home.path().join(PID_FILE).exists());
+    Ok(())
+  }
+}
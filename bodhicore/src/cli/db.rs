@@ -0,0 +1,530 @@
+use super::{CliError, StdoutWriter};
+use crate::{
+  db::{DbError, DbPool, DbService, DbServiceFn, TimeService},
+  error::{BodhiError, Common},
+  objs::DisplayTimezone,
+  service::{AppServiceFn, ALIASES_DIR, PROD_DB},
+  Command, DbAction,
+};
+use prettytable::{format, row, Table};
+use std::{
+  fs, io,
+  path::{Path, PathBuf},
+  sync::Arc,
+};
+use tokio::runtime::Builder;
+
+#[derive(Debug, PartialEq)]
+pub enum DbCommand {
+  Migrate { status: bool },
+  Backup { path: PathBuf },
+  Restore { path: PathBuf },
+}
+
+impl TryFrom<Command> for DbCommand {
+  type Error = CliError;
+
+  fn try_from(value: Command) -> Result<Self, Self::Error> {
+    match value {
+      Command::Db { action } => match action {
+        DbAction::Migrate { status } => Ok(DbCommand::Migrate { status }),
+        DbAction::Backup { path } => Ok(DbCommand::Backup { path }),
+        DbAction::Restore { path } => Ok(DbCommand::Restore { path }),
+      },
+      cmd => Err(CliError::ConvertCommand(cmd.to_string(), "db".to_string())),
+    }
+  }
+}
+
+impl DbCommand {
+  #[allow(clippy::result_large_err)]
+  pub fn execute(
+    self,
+    service: Arc<dyn AppServiceFn>,
+    stdout: &mut dyn StdoutWriter,
+  ) -> crate::error::Result<()> {
+    let runtime = Builder::new_current_thread()
+      .enable_all()
+      .build()
+      .map_err(Common::from)?;
+    runtime.block_on(async move {
+      let env_service = service.env_service();
+      let database_url = env_service.database_url();
+      match self {
+        DbCommand::Migrate { status } => {
+          let db_service = DbPool::connect_service(&database_url, Arc::new(TimeService)).await?;
+          migrate(db_service.as_ref(), status, &env_service.timezone(), stdout).await?;
+        }
+        DbCommand::Backup { path } => {
+          let db_service = DbPool::connect_service(&database_url, Arc::new(TimeService)).await?;
+          backup(
+            db_service.as_ref(),
+            &env_service.aliases_dir(),
+            &path,
+            stdout,
+          )
+          .await?;
+        }
+        DbCommand::Restore { path } => {
+          if !database_url.starts_with("sqlite:") {
+            return Err(BodhiError::Db(DbError::Unsupported(
+              "restore of a postgres database, use pg_restore instead".to_string(),
+            )));
+          }
+          restore(
+            &env_service.db_path(),
+            &env_service.aliases_dir(),
+            &path,
+            stdout,
+          )
+          .await?;
+        }
+      }
+      Ok::<(), BodhiError>(())
+    })?;
+    Ok(())
+  }
+}
+
+async fn migrate(
+  db_service: &dyn DbServiceFn,
+  status: bool,
+  timezone: &DisplayTimezone,
+  stdout: &mut dyn StdoutWriter,
+) -> crate::error::Result<()> {
+  let migrations = db_service.migration_status().await?;
+  if status {
+    let mut table = Table::new();
+    table.add_row(row!["VERSION", "DESCRIPTION", "APPLIED"]);
+    for migration in migrations {
+      let applied = match (&migration.applied_at, migration.unknown) {
+        (Some(applied_at), false) => timezone.display(applied_at),
+        (_, true) => "unknown, applied by a newer version of bodhi".to_string(),
+        (None, false) => "pending".to_string(),
+      };
+      table.add_row(row![migration.version, migration.description, applied]);
+    }
+    table.set_format(format::FormatBuilder::default().padding(2, 2).build());
+    stdout.write(&table.to_string()).map_err(Common::from)?;
+    return Ok(());
+  }
+  let pending = migrations
+    .iter()
+    .filter(|migration| migration.applied_at.is_none())
+    .count();
+  db_service.migrate().await?;
+  let version = migrations
+    .last()
+    .map(|migration| migration.version)
+    .unwrap_or_default();
+  stdout
+    .write(&format!(
+      "applied {pending} migrations, the database schema is at version {version}\n"
+    ))
+    .map_err(Common::from)?;
+  Ok(())
+}
+
+// the backup is a directory with the database snapshot and a copy of the model alias yamls
+async fn backup(
+  db_service: &dyn DbServiceFn,
+  aliases_dir: &Path,
+  path: &Path,
+  stdout: &mut dyn StdoutWriter,
+) -> crate::error::Result<()> {
+  if path.exists() {
+    return Err(
+      Common::IoDir {
+        source: io::Error::new(io::ErrorKind::AlreadyExists, "backup path already exists"),
+        path: path.display().to_string(),
+      }
+      .into(),
+    );
+  }
+  let backup_aliases = path.join(ALIASES_DIR);
+  fs::create_dir_all(&backup_aliases).map_err(|source| Common::IoDir {
+    source,
+    path: backup_aliases.display().to_string(),
+  })?;
+  if let Err(err) = db_service.backup(&path.join(PROD_DB)).await {
+    _ = fs::remove_dir_all(path);
+    return Err(err.into());
+  }
+  let aliases = match copy_yaml_files(aliases_dir, &backup_aliases) {
+    Ok(aliases) => aliases,
+    Err(err) => {
+      _ = fs::remove_dir_all(path);
+      return Err(err.into());
+    }
+  };
+  stdout
+    .write(&format!(
+      "backed up the database and {aliases} model aliases to '{}'\n",
+      path.display()
+    ))
+    .map_err(Common::from)?;
+  Ok(())
+}
+
+async fn restore(
+  db_path: &Path,
+  aliases_dir: &Path,
+  path: &Path,
+  stdout: &mut dyn StdoutWriter,
+) -> crate::error::Result<()> {
+  let backup_db = path.join(PROD_DB);
+  if !backup_db.is_file() {
+    return Err(
+      Common::IoFile {
+        source: io::Error::new(
+          io::ErrorKind::NotFound,
+          "not a backup created by `bodhi db backup`",
+        ),
+        path: backup_db.display().to_string(),
+      }
+      .into(),
+    );
+  }
+  // the backup is migrated as a copy, so the database is not replaced by a backup
+  // that fails to migrate, e.g. one created by a newer version of bodhi
+  let restoring = db_path.with_extension("sqlite.restore");
+  copy_file(&backup_db, &restoring)?;
+  let (pool, db_service) = open_sqlite(&restoring).await?;
+  let migrated = db_service.migrate().await;
+  pool.close().await;
+  if let Err(err) = migrated {
+    _ = fs::remove_file(&restoring);
+    return Err(err.into());
+  }
+  let previous = db_path.with_extension("sqlite.bak");
+  let keep_previous = db_path.exists();
+  if keep_previous {
+    remove_file(&previous)?;
+    let (pool, db_service) = open_sqlite(db_path).await?;
+    let snapshot = db_service.backup(&previous).await;
+    pool.close().await;
+    snapshot?;
+  }
+  for suffix in ["", "-wal", "-shm"] {
+    remove_file(&PathBuf::from(format!("{}{suffix}", db_path.display())))?;
+  }
+  fs::rename(&restoring, db_path).map_err(|source| Common::IoFile {
+    source,
+    path: db_path.display().to_string(),
+  })?;
+  // the aliases are restored into an empty directory, so the aliases created after the backup
+  // do not point to models the restored database knows nothing about
+  let previous_aliases = aliases_dir.with_extension("bak");
+  let keep_previous_aliases = aliases_dir.is_dir();
+  if keep_previous_aliases {
+    remove_dir(&previous_aliases)?;
+    fs::rename(aliases_dir, &previous_aliases).map_err(|source| Common::IoDir {
+      source,
+      path: aliases_dir.display().to_string(),
+    })?;
+  }
+  fs::create_dir_all(aliases_dir).map_err(|source| Common::IoDir {
+    source,
+    path: aliases_dir.display().to_string(),
+  })?;
+  let aliases = copy_yaml_files(&path.join(ALIASES_DIR), aliases_dir)?;
+  let mut message = format!(
+    "restored the database and {aliases} model aliases from '{}'\n",
+    path.display()
+  );
+  if keep_previous {
+    message.push_str(&format!(
+      "the previous database is kept at '{}'\n",
+      previous.display()
+    ));
+  }
+  if keep_previous_aliases {
+    message.push_str(&format!(
+      "the previous model aliases are kept at '{}'\n",
+      previous_aliases.display()
+    ));
+  }
+  stdout.write(&message).map_err(Common::from)?;
+  Ok(())
+}
+
+async fn open_sqlite(path: &Path) -> Result<(sqlx::SqlitePool, DbService), DbError> {
+  let pool = DbPool::connect(&format!("sqlite:{}", path.display())).await?;
+  let db_service = DbService::new(pool.clone(), Arc::new(TimeService));
+  Ok((pool, db_service))
+}
+
+fn copy_yaml_files(from: &Path, to: &Path) -> Result<usize, Common> {
+  if !from.is_dir() {
+    return Ok(0);
+  }
+  let entries = fs::read_dir(from).map_err(|source| Common::IoDir {
+    source,
+    path: from.display().to_string(),
+  })?;
+  let mut copied = 0;
+  for entry in entries {
+    let file = entry.map_err(Common::from)?.path();
+    if file.is_file() && file.extension().is_some_and(|ext| ext == "yaml") {
+      if let Some(filename) = file.file_name() {
+        copy_file(&file, &to.join(filename))?;
+        copied += 1;
+      }
+    }
+  }
+  Ok(copied)
+}
+
+fn copy_file(from: &Path, to: &Path) -> Result<(), Common> {
+  fs::copy(from, to).map_err(|source| Common::IoFile {
+    source,
+    path: to.display().to_string(),
+  })?;
+  Ok(())
+}
+
+fn remove_file(path: &Path) -> Result<(), Common> {
+  match fs::remove_file(path) {
+    Err(err) if err.kind() != io::ErrorKind::NotFound => Err(Common::IoFile {
+      source: err,
+      path: path.display().to_string(),
+    }),
+    _ => Ok(()),
+  }
+}
+
+fn remove_dir(path: &Path) -> Result<(), Common> {
+  match fs::remove_dir_all(path) {
+    Err(err) if err.kind() != io::ErrorKind::NotFound => Err(Common::IoDir {
+      source: err,
+      path: path.display().to_string(),
+    }),
+    _ => Ok(()),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{backup, migrate, restore, DbCommand};
+  use crate::{
+    db::{objs::Conversation, DbService, DbServiceFn, TimeService, MIGRATOR},
+    objs::DisplayTimezone,
+    service::{ALIASES_DIR, PROD_DB},
+    test_utils::db_service,
+    Command, DbAction, MockStdoutWriter,
+  };
+  use chrono::{DateTime, Utc};
+  use rstest::rstest;
+  use sqlx::SqlitePool;
+  use std::{
+    fs::{self, File},
+    path::PathBuf,
+    sync::Arc,
+  };
+  use tempfile::TempDir;
+
+  #[rstest]
+  #[case(DbAction::Migrate { status: false }, DbCommand::Migrate { status: false })]
+  #[case(DbAction::Migrate { status: true }, DbCommand::Migrate { status: true })]
+  #[case(
+    DbAction::Backup { path: PathBuf::from("backup") },
+    DbCommand::Backup { path: PathBuf::from("backup") }
+  )]
+  #[case(
+    DbAction::Restore { path: PathBuf::from("backup") },
+    DbCommand::Restore { path: PathBuf::from("backup") }
+  )]
+  fn test_db_command_from_cli(
+    #[case] action: DbAction,
+    #[case] expected: DbCommand,
+  ) -> anyhow::Result<()> {
+    let command = Command::Db { action };
+    assert_eq!(expected, DbCommand::try_from(command)?);
+    let result = DbCommand::try_from(Command::App { ui: false });
+    assert_eq!(
+      "Command 'app' cannot be converted into command 'db'",
+      result.unwrap_err().to_string()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_db_command_migrate_status(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .withf(|input| {
+        input.contains("VERSION")
+          && input.contains("create conversations")
+          && !input.contains("pending")
+      })
+      .return_once(|input| Ok(input.len()));
+    migrate(&db_service, true, &DisplayTimezone::Utc, &mut stdout).await?;
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_db_command_migrate_applies_pending() -> anyhow::Result<()> {
+    let tempdir = tempfile::tempdir()?;
+    let dbpath = tempdir.path().join("testdb.sqlite");
+    File::create(&dbpath)?;
+    let pool = SqlitePool::connect(&format!("sqlite:{}", dbpath.display())).await?;
+    let db_service = DbService::new(pool, Arc::new(TimeService));
+    let migrations = MIGRATOR
+      .iter()
+      .filter(|migration| migration.migration_type.is_up_migration())
+      .collect::<Vec<_>>();
+
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .withf(|input| input.contains("pending") && !input.contains("unknown"))
+      .return_once(|input| Ok(input.len()));
+    migrate(&db_service, true, &DisplayTimezone::Utc, &mut stdout).await?;
+
+    let expected = format!(
+      "applied {} migrations, the database schema is at version {}\n",
+      migrations.len(),
+      migrations.last().unwrap().version
+    );
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .withf(move |input| input == expected)
+      .return_once(|input| Ok(input.len()));
+    migrate(&db_service, false, &DisplayTimezone::Utc, &mut stdout).await?;
+    assert!(db_service
+      .migration_status()
+      .await?
+      .iter()
+      .all(|migration| migration.applied_at.is_some()));
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_db_command_backup_and_restore() -> anyhow::Result<()> {
+    let home = tempfile::tempdir()?;
+    let db_path = home.path().join(PROD_DB);
+    File::create(&db_path)?;
+    let aliases_dir = home.path().join(ALIASES_DIR);
+    fs::create_dir_all(&aliases_dir)?;
+    fs::write(
+      aliases_dir.join("llama3--instruct.yaml"),
+      "alias: llama3:instruct\n",
+    )?;
+    let pool = SqlitePool::connect(&format!("sqlite:{}", db_path.display())).await?;
+    let db_service = DbService::new(pool.clone(), Arc::new(TimeService));
+    db_service.migrate().await?;
+    let mut conversation = Conversation {
+      title: "backed up".to_string(),
+      ..Default::default()
+    };
+    db_service.save_conversation(&mut conversation).await?;
+
+    let backup_dir = home.path().join("backup");
+    let expected = format!(
+      "backed up the database and 1 model aliases to '{}'\n",
+      backup_dir.display()
+    );
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .withf(move |input| input == expected)
+      .return_once(|input| Ok(input.len()));
+    backup(&db_service, &aliases_dir, &backup_dir, &mut stdout).await?;
+    assert!(backup_dir.join(PROD_DB).exists());
+    assert!(backup_dir
+      .join(ALIASES_DIR)
+      .join("llama3--instruct.yaml")
+      .exists());
+
+    db_service.delete_all_conversations(None).await?;
+    fs::remove_file(aliases_dir.join("llama3--instruct.yaml"))?;
+    fs::write(aliases_dir.join("phi3--mini.yaml"), "alias: phi3:mini\n")?;
+    pool.close().await;
+
+    let expected = format!(
+      "restored the database and 1 model aliases from '{}'\nthe previous database is kept at '{}'\nthe previous model aliases are kept at '{}'\n",
+      backup_dir.display(),
+      home.path().join("bodhi.sqlite.bak").display(),
+      home.path().join("aliases.bak").display()
+    );
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .withf(move |input| input == expected)
+      .return_once(|input| Ok(input.len()));
+    restore(&db_path, &aliases_dir, &backup_dir, &mut stdout).await?;
+    assert!(aliases_dir.join("llama3--instruct.yaml").exists());
+    assert!(!aliases_dir.join("phi3--mini.yaml").exists());
+    assert!(home
+      .path()
+      .join("aliases.bak")
+      .join("phi3--mini.yaml")
+      .exists());
+    assert!(!home.path().join("bodhi.sqlite.restore").exists());
+
+    let pool = SqlitePool::connect(&format!("sqlite:{}", db_path.display())).await?;
+    let db_service = DbService::new(pool, Arc::new(TimeService));
+    let restored = db_service
+      .get_conversation_with_messages(&conversation.id)
+      .await?;
+    assert_eq!("backed up", restored.title);
+
+    let previous = home.path().join("bodhi.sqlite.bak");
+    let pool = SqlitePool::connect(&format!("sqlite:{}", previous.display())).await?;
+    let db_service = DbService::new(pool, Arc::new(TimeService));
+    assert!(db_service
+      .get_conversation_with_messages(&conversation.id)
+      .await
+      .is_err());
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_db_command_backup_fails_if_path_exists(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (temp, _now, db_service) = db_service;
+    let mut stdout = MockStdoutWriter::default();
+    let result = backup(&db_service, temp.path(), temp.path(), &mut stdout).await;
+    assert!(result
+      .unwrap_err()
+      .to_string()
+      .contains("backup path already exists"));
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_db_command_restore_fails_if_not_a_backup() -> anyhow::Result<()> {
+    let home = tempfile::tempdir()?;
+    let db_path = home.path().join(PROD_DB);
+    fs::write(&db_path, "")?;
+    let not_a_backup = home.path().join("downloads");
+    fs::create_dir_all(&not_a_backup)?;
+    let mut stdout = MockStdoutWriter::default();
+    let result = restore(
+      &db_path,
+      &home.path().join(ALIASES_DIR),
+      &not_a_backup,
+      &mut stdout,
+    )
+    .await;
+    assert!(result
+      .unwrap_err()
+      .to_string()
+      .contains("not a backup created by `bodhi db backup`"));
+    assert!(db_path.exists());
+    Ok(())
+  }
+}
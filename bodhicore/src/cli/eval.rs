@@ -0,0 +1,188 @@
+use super::{CliError, StdoutWriter};
+use crate::{
+  db::{DbPool, DbServiceFn, TimeService},
+  error::{BodhiError, Common},
+  objs::DisplayTimezone,
+  service::AppServiceFn,
+  CanaryAction, Command, EvalAction,
+};
+use std::sync::Arc;
+use tokio::runtime::Builder;
+
+#[derive(Debug, PartialEq)]
+pub enum EvalCommand {
+  CanaryReport { limit: u32 },
+}
+
+impl TryFrom<Command> for EvalCommand {
+  type Error = CliError;
+
+  fn try_from(value: Command) -> Result<Self, Self::Error> {
+    match value {
+      Command::Eval { action } => match action {
+        EvalAction::Canary { action } => match action {
+          CanaryAction::Report { limit } => Ok(EvalCommand::CanaryReport { limit }),
+        },
+      },
+      cmd => Err(CliError::ConvertCommand(
+        cmd.to_string(),
+        "eval".to_string(),
+      )),
+    }
+  }
+}
+
+impl EvalCommand {
+  #[allow(clippy::result_large_err)]
+  pub fn execute(
+    self,
+    service: Arc<dyn AppServiceFn>,
+    stdout: &mut dyn StdoutWriter,
+  ) -> crate::error::Result<()> {
+    let runtime = Builder::new_current_thread()
+      .enable_all()
+      .build()
+      .map_err(Common::from)?;
+    runtime.block_on(async move {
+      let db_service =
+        DbPool::connect_service(&service.env_service().database_url(), Arc::new(TimeService))
+          .await?;
+      db_service.migrate().await?;
+      let timezone = service.env_service().timezone();
+      self
+        .aexecute(db_service.as_ref(), &timezone, stdout)
+        .await?;
+      Ok::<(), BodhiError>(())
+    })?;
+    Ok(())
+  }
+
+  async fn aexecute(
+    self,
+    db_service: &dyn DbServiceFn,
+    timezone: &DisplayTimezone,
+    stdout: &mut dyn StdoutWriter,
+  ) -> crate::error::Result<()> {
+    let EvalCommand::CanaryReport { limit } = self;
+    let samples = db_service.list_canary_samples(limit).await?;
+    if samples.is_empty() {
+      stdout
+        .write("No canary samples yet, configure the `canary` section of $BODHI_HOME/settings.yaml to sample the requests\n")
+        .map_err(Common::Io)?;
+      return Ok(());
+    }
+    let identical = samples
+      .iter()
+      .filter(|sample| sample.canary_error.is_none() && sample.output == sample.canary_output)
+      .count();
+    let mut report = format!(
+      "{} canary samples, {} with identical outputs\n",
+      samples.len(),
+      identical
+    );
+    for sample in samples {
+      report.push_str(&format!(
+        "\n{}  {} vs {}  ({})\n",
+        timezone.display(&sample.created_at),
+        sample.model,
+        sample.canary_model,
+        sample.id
+      ));
+      if let Some(error) = &sample.canary_error {
+        report.push_str(&format!("canary failed: {error}\n"));
+      } else if sample.output == sample.canary_output {
+        report.push_str("outputs are identical\n");
+      } else {
+        report.push_str(&sample.diff);
+      }
+    }
+    stdout.write(&report).map_err(Common::Io)?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::EvalCommand;
+  use crate::{
+    db::{objs::CanarySample, DbService, DbServiceFn},
+    objs::DisplayTimezone,
+    test_utils::db_service,
+    CanaryAction, Command, EvalAction, MockStdoutWriter,
+  };
+  use chrono::{DateTime, Utc};
+  use mockall::predicate::eq;
+  use rstest::rstest;
+  use tempfile::TempDir;
+
+  #[rstest]
+  fn test_eval_command_from_cli() -> anyhow::Result<()> {
+    let command = Command::Eval {
+      action: EvalAction::Canary {
+        action: CanaryAction::Report { limit: 5 },
+      },
+    };
+    assert_eq!(
+      EvalCommand::CanaryReport { limit: 5 },
+      EvalCommand::try_from(command)?
+    );
+    let result = EvalCommand::try_from(Command::App { ui: false });
+    assert_eq!(
+      "Command 'app' cannot be converted into command 'eval'",
+      result.unwrap_err().to_string()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_eval_command_canary_report(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let mut sample = CanarySample {
+      model: "llama3:instruct".to_string(),
+      canary_model: "phi3:mini".to_string(),
+      request: "{}".to_string(),
+      output: "Tuesday\n".to_string(),
+      canary_output: "Wednesday\n".to_string(),
+      canary_error: None,
+      diff: "--- llama3:instruct\n+++ phi3:mini\n@@ -1 +1 @@\n-Tuesday\n+Wednesday\n".to_string(),
+      ..Default::default()
+    };
+    db_service.save_canary_sample(&mut sample).await?;
+    let expected = format!(
+      "1 canary samples, 0 with identical outputs\n\n{}  llama3:instruct vs phi3:mini  ({})\n--- llama3:instruct\n+++ phi3:mini\n@@ -1 +1 @@\n-Tuesday\n+Wednesday\n",
+      DisplayTimezone::Utc.display(&sample.created_at),
+      sample.id
+    );
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .withf(move |input| input == expected)
+      .return_once(|input| Ok(input.len()));
+    EvalCommand::CanaryReport { limit: 10 }
+      .aexecute(&db_service, &DisplayTimezone::Utc, &mut stdout)
+      .await?;
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_eval_command_canary_report_empty(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .with(eq("No canary samples yet, configure the `canary` section of $BODHI_HOME/settings.yaml to sample the requests\n"))
+      .return_once(|input| Ok(input.len()));
+    EvalCommand::CanaryReport { limit: 10 }
+      .aexecute(&db_service, &DisplayTimezone::Utc, &mut stdout)
+      .await?;
+    Ok(())
+  }
+}
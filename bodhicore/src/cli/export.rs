@@ -0,0 +1,214 @@
+use super::{CliError, StdoutWriter};
+use crate::{
+  db::{export_conversation, DbPool, DbServiceFn, ExportFormat, TimeService},
+  error::{BodhiError, Common},
+  objs::DisplayTimezone,
+  service::AppServiceFn,
+  Command,
+};
+use std::{fs, path::PathBuf, sync::Arc};
+use tokio::runtime::Builder;
+
+#[derive(Debug, PartialEq)]
+pub struct ExportCommand {
+  id: String,
+  format: ExportFormat,
+  output: Option<PathBuf>,
+}
+
+impl TryFrom<Command> for ExportCommand {
+  type Error = CliError;
+
+  fn try_from(value: Command) -> Result<Self, Self::Error> {
+    match value {
+      Command::Export { id, format, output } => Ok(ExportCommand { id, format, output }),
+      cmd => Err(CliError::ConvertCommand(
+        cmd.to_string(),
+        "export".to_string(),
+      )),
+    }
+  }
+}
+
+impl ExportCommand {
+  #[allow(clippy::result_large_err)]
+  pub fn execute(
+    self,
+    service: Arc<dyn AppServiceFn>,
+    stdout: &mut dyn StdoutWriter,
+  ) -> crate::error::Result<()> {
+    let runtime = Builder::new_current_thread()
+      .enable_all()
+      .build()
+      .map_err(Common::from)?;
+    runtime.block_on(async move {
+      let db_service =
+        DbPool::connect_service(&service.env_service().database_url(), Arc::new(TimeService))
+          .await?;
+      db_service.migrate().await?;
+      let timezone = service.env_service().timezone();
+      self
+        .aexecute(db_service.as_ref(), &timezone, stdout)
+        .await?;
+      Ok::<(), BodhiError>(())
+    })?;
+    Ok(())
+  }
+
+  async fn aexecute(
+    self,
+    db_service: &dyn DbServiceFn,
+    timezone: &DisplayTimezone,
+    stdout: &mut dyn StdoutWriter,
+  ) -> crate::error::Result<()> {
+    let convo = db_service.get_conversation_with_messages(&self.id).await?;
+    let content = export_conversation(&convo, self.format, timezone).map_err(Common::from)?;
+    match self.output {
+      Some(output) => {
+        fs::write(&output, content).map_err(|err| Common::IoFile {
+          source: err,
+          path: output.display().to_string(),
+        })?;
+        stdout
+          .write(&format!(
+            "exported chat '{}' to '{}'\n",
+            self.id,
+            output.display()
+          ))
+          .map_err(Common::Io)?;
+      }
+      None => {
+        stdout.write(&content).map_err(Common::Io)?;
+      }
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::ExportCommand;
+  use crate::{
+    db::{
+      objs::{ConversationBuilder, MessageBuilder},
+      DbService, DbServiceFn, ExportFormat,
+    },
+    objs::DisplayTimezone,
+    test_utils::db_service,
+    Command, MockStdoutWriter,
+  };
+  use chrono::{DateTime, Utc};
+  use mockall::predicate::eq;
+  use rstest::rstest;
+  use std::{fs, path::PathBuf};
+  use tempfile::TempDir;
+
+  #[rstest]
+  #[case(Command::Export {
+    id: "testid".to_string(),
+    format: ExportFormat::Json,
+    output: Some(PathBuf::from("chat.json")),
+  }, ExportCommand {
+    id: "testid".to_string(),
+    format: ExportFormat::Json,
+    output: Some(PathBuf::from("chat.json")),
+  })]
+  fn test_export_command_from_cli(
+    #[case] input: Command,
+    #[case] expected: ExportCommand,
+  ) -> anyhow::Result<()> {
+    assert_eq!(expected, ExportCommand::try_from(input)?);
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_export_command_from_invalid_cli() -> anyhow::Result<()> {
+    let result = ExportCommand::try_from(Command::App { ui: false });
+    assert_eq!(
+      "Command 'app' cannot be converted into command 'export'",
+      result.unwrap_err().to_string()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_export_command_writes_to_stdout(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let mut convo = ConversationBuilder::default()
+      .title("test title")
+      .messages(vec![MessageBuilder::default()
+        .role("user")
+        .content("test content")
+        .build()?])
+      .build()?;
+    db_service.save_conversation(&mut convo).await?;
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .withf(|input| input.starts_with("# test title\n") && input.contains("test content"))
+      .return_once(|input| Ok(input.len()));
+    ExportCommand {
+      id: convo.id.clone(),
+      format: ExportFormat::Markdown,
+      output: None,
+    }
+    .aexecute(&db_service, &DisplayTimezone::Utc, &mut stdout)
+    .await?;
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_export_command_writes_to_file(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (temp, _now, db_service) = db_service;
+    let mut convo = ConversationBuilder::default().title("test title").build()?;
+    db_service.save_conversation(&mut convo).await?;
+    let output = temp.path().join("chat.json");
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .with(eq(format!(
+        "exported chat '{}' to '{}'\n",
+        convo.id,
+        output.display()
+      )))
+      .return_once(|input| Ok(input.len()));
+    ExportCommand {
+      id: convo.id.clone(),
+      format: ExportFormat::Json,
+      output: Some(output.clone()),
+    }
+    .aexecute(&db_service, &DisplayTimezone::Utc, &mut stdout)
+    .await?;
+    let exported = serde_json::from_str::<serde_json::Value>(&fs::read_to_string(output)?)?;
+    assert_eq!("test title", exported["title"]);
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_export_command_chat_not_found(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let mut stdout = MockStdoutWriter::default();
+    stdout.expect_write().never();
+    let result = ExportCommand {
+      id: "unknown".to_string(),
+      format: ExportFormat::Markdown,
+      output: None,
+    }
+    .aexecute(&db_service, &DisplayTimezone::Utc, &mut stdout)
+    .await;
+    assert!(result.is_err());
+    Ok(())
+  }
+}
@@ -0,0 +1,579 @@
+use super::{CliError, StdoutWriter};
+use crate::{
+  error::{BodhiError, Common},
+  objs::{
+    default_features, Alias, ChatTemplate, ChatTemplateId, GptContextParams, OAIRequestParams, Repo,
+  },
+  service::AppServiceFn,
+  Command, ImportSource,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+  fs,
+  path::{Path, PathBuf},
+  sync::Arc,
+};
+use walkdir::WalkDir;
+
+static OLLAMA_LIBRARY: &str = "library";
+static MEDIA_TYPE_MODEL: &str = "application/vnd.ollama.image.model";
+static MEDIA_TYPE_TEMPLATE: &str = "application/vnd.ollama.image.template";
+static MEDIA_TYPE_PARAMS: &str = "application/vnd.ollama.image.params";
+
+#[derive(Debug, PartialEq)]
+pub enum ImportCommand {
+  Ollama {
+    models_dir: Option<PathBuf>,
+    copy: bool,
+    force: bool,
+  },
+}
+
+impl TryFrom<Command> for ImportCommand {
+  type Error = CliError;
+
+  fn try_from(value: Command) -> Result<Self, Self::Error> {
+    match value {
+      Command::Import { source } => match source {
+        ImportSource::Ollama {
+          models_dir,
+          copy,
+          force,
+        } => Ok(ImportCommand::Ollama {
+          models_dir,
+          copy,
+          force,
+        }),
+      },
+      cmd => Err(CliError::ConvertCommand(
+        cmd.to_string(),
+        "import".to_string(),
+      )),
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaManifest {
+  layers: Vec<OllamaLayer>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OllamaLayer {
+  media_type: String,
+  digest: String,
+}
+
+impl OllamaManifest {
+  fn layer(&self, media_type: &str) -> Option<&OllamaLayer> {
+    self
+      .layers
+      .iter()
+      .find(|layer| layer.media_type == media_type)
+  }
+}
+
+/// A model tag found in the ollama manifests, e.g. `library/llama3/latest`
+#[derive(Debug, PartialEq)]
+struct OllamaModel {
+  namespace: String,
+  name: String,
+  tag: String,
+  manifest: PathBuf,
+}
+
+impl OllamaModel {
+  fn alias(&self) -> String {
+    if self.namespace == OLLAMA_LIBRARY {
+      format!("{}:{}", self.name, self.tag)
+    } else {
+      format!("{}/{}:{}", self.namespace, self.name, self.tag)
+    }
+  }
+
+  fn repo(&self) -> String {
+    if self.namespace == OLLAMA_LIBRARY {
+      format!("ollama/{}", self.name)
+    } else {
+      format!("ollama/{}--{}", self.namespace, self.name)
+    }
+  }
+
+  fn filename(&self) -> String {
+    format!("{}-{}.gguf", self.name, self.tag)
+  }
+}
+
+impl ImportCommand {
+  #[allow(clippy::result_large_err)]
+  pub fn execute(
+    self,
+    service: Arc<dyn AppServiceFn>,
+    stdout: &mut dyn StdoutWriter,
+  ) -> crate::error::Result<()> {
+    match self {
+      ImportCommand::Ollama {
+        models_dir,
+        copy,
+        force,
+      } => {
+        let models_dir = match models_dir {
+          Some(models_dir) => models_dir,
+          None => service
+            .env_service()
+            .ollama_models_dir()
+            .ok_or(BodhiError::HomeDirectory)?,
+        };
+        import_ollama(&models_dir, copy, force, service, stdout)
+      }
+    }
+  }
+}
+
+#[allow(clippy::result_large_err)]
+fn import_ollama(
+  models_dir: &Path,
+  copy: bool,
+  force: bool,
+  service: Arc<dyn AppServiceFn>,
+  stdout: &mut dyn StdoutWriter,
+) -> crate::error::Result<()> {
+  let models = ollama_models(models_dir);
+  if models.is_empty() {
+    writeln(
+      stdout,
+      &format!(
+        "no ollama models found in '{}', use --models-dir to set the ollama models directory",
+        models_dir.display()
+      ),
+    )?;
+    return Ok(());
+  }
+  let mut imported = 0;
+  for model in models {
+    let alias = model.alias();
+    if !force && service.data_service().find_alias(&alias).is_some() {
+      writeln(
+        stdout,
+        &format!("skipped '{alias}': model alias already exists, use --force to overwrite"),
+      )?;
+      continue;
+    }
+    let content = fs::read_to_string(&model.manifest).map_err(|err| Common::IoFile {
+      source: err,
+      path: model.manifest.display().to_string(),
+    })?;
+    let manifest = serde_json::from_str::<OllamaManifest>(&content).map_err(Common::from)?;
+    let Some(model_layer) = manifest.layer(MEDIA_TYPE_MODEL) else {
+      writeln(
+        stdout,
+        &format!("skipped '{alias}': manifest does not have a model layer"),
+      )?;
+      continue;
+    };
+    let template = match manifest.layer(MEDIA_TYPE_TEMPLATE) {
+      Some(layer) => read_blob(models_dir, &layer.digest)?,
+      None => String::new(),
+    };
+    let Some(chat_template) = detect_chat_template(&template) else {
+      writeln(
+        stdout,
+        &format!(
+          "skipped '{alias}': chat template not recognized, use `bodhi create` with --tokenizer-config to configure it"
+        ),
+      )?;
+      continue;
+    };
+    let params = match manifest.layer(MEDIA_TYPE_PARAMS) {
+      Some(layer) => {
+        let content = read_blob(models_dir, &layer.digest)?;
+        serde_json::from_str::<Value>(&content).map_err(Common::from)?
+      }
+      None => Value::Null,
+    };
+    let (request_params, context_params) = convert_params(&params);
+    let repo = Repo::try_from(model.repo())?;
+    let snapshot = digest_hex(&model_layer.digest).to_string();
+    service.hub_service().link_local_file(
+      &blob_path(models_dir, &model_layer.digest),
+      &repo,
+      &model.filename(),
+      &snapshot,
+      copy,
+    )?;
+    let alias = Alias::new(
+      alias,
+      Some(model.name.clone()),
+      repo,
+      model.filename(),
+      snapshot,
+      default_features(),
+      ChatTemplate::Id(chat_template.clone()),
+      request_params,
+      context_params,
+    );
+    service.data_service().save_alias(&alias)?;
+    writeln(
+      stdout,
+      &format!(
+        "imported '{}' as model alias '{}' with chat template '{}'",
+        model.manifest.display(),
+        alias.alias,
+        chat_template
+      ),
+    )?;
+    imported += 1;
+  }
+  writeln(
+    stdout,
+    &format!("{imported} model(s) imported to $BODHI_HOME/aliases"),
+  )?;
+  Ok(())
+}
+
+/// lists the models from the ollama manifests directory, `manifests/<registry>/<namespace>/<name>/<tag>`
+fn ollama_models(models_dir: &Path) -> Vec<OllamaModel> {
+  let manifests_dir = models_dir.join("manifests");
+  if !manifests_dir.is_dir() {
+    return vec![];
+  }
+  let mut models = WalkDir::new(&manifests_dir)
+    .min_depth(4)
+    .max_depth(4)
+    .into_iter()
+    .filter_map(|entry| entry.ok())
+    .filter(|entry| entry.file_type().is_file())
+    .filter_map(|entry| {
+      let relative = entry.path().strip_prefix(&manifests_dir).ok()?;
+      let parts = relative
+        .iter()
+        .map(|part| part.to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+      match parts.as_slice() {
+        [_registry, namespace, name, tag] => Some(OllamaModel {
+          namespace: namespace.clone(),
+          name: name.clone(),
+          tag: tag.clone(),
+          manifest: entry.path().to_path_buf(),
+        }),
+        _ => None,
+      }
+    })
+    .collect::<Vec<_>>();
+  models.sort_by(|a, b| a.manifest.cmp(&b.manifest));
+  models
+}
+
+fn digest_hex(digest: &str) -> &str {
+  digest.strip_prefix("sha256:").unwrap_or(digest)
+}
+
+fn blob_path(models_dir: &Path, digest: &str) -> PathBuf {
+  models_dir
+    .join("blobs")
+    .join(format!("sha256-{}", digest_hex(digest)))
+}
+
+#[allow(clippy::result_large_err)]
+fn read_blob(models_dir: &Path, digest: &str) -> crate::error::Result<String> {
+  let path = blob_path(models_dir, digest);
+  let content = fs::read_to_string(&path).map_err(|err| Common::IoFile {
+    source: err,
+    path: path.display().to_string(),
+  })?;
+  Ok(content)
+}
+
+/// matches the ollama Go template against the markers of the known chat templates
+fn detect_chat_template(template: &str) -> Option<ChatTemplateId> {
+  let markers = [
+    ("<|start_header_id|>", ChatTemplateId::Llama3),
+    ("<|START_OF_TURN_TOKEN|>", ChatTemplateId::CommandR),
+    ("<start_of_turn>", ChatTemplateId::Gemma),
+    ("<|end|>", ChatTemplateId::Phi3),
+    ("GPT4 Correct", ChatTemplateId::Openchat),
+    ("<｜User｜>", ChatTemplateId::Deepseek),
+    ("### Instruction:", ChatTemplateId::Deepseek),
+    ("<|user|>", ChatTemplateId::Tinyllama),
+    ("[INST]", ChatTemplateId::Llama2),
+  ];
+  markers
+    .into_iter()
+    .find(|(marker, _)| template.contains(marker))
+    .map(|(_, id)| id)
+}
+
+/// converts the ollama Modelfile `PARAMETER`s to the alias request and context params
+fn convert_params(params: &Value) -> (OAIRequestParams, GptContextParams) {
+  let float = |key: &str| params.get(key).and_then(Value::as_f64).map(|v| v as f32);
+  let int = |key: &str| params.get(key).and_then(Value::as_i64);
+  let stop = match params.get("stop") {
+    Some(Value::Array(values)) => values
+      .iter()
+      .filter_map(|value| value.as_str().map(str::to_string))
+      .collect(),
+    Some(Value::String(value)) => vec![value.clone()],
+    _ => vec![],
+  };
+  let request_params = OAIRequestParams {
+    frequency_penalty: float("frequency_penalty"),
+    max_tokens: int("num_predict").and_then(|v| u16::try_from(v).ok()),
+    presence_penalty: float("presence_penalty"),
+    seed: int("seed"),
+    stop,
+    temperature: float("temperature"),
+    top_p: float("top_p"),
+    user: None,
+    top_k: int("top_k").and_then(|v| i32::try_from(v).ok()),
+    min_p: float("min_p"),
+    repeat_penalty: float("repeat_penalty"),
+    repeat_last_n: int("repeat_last_n").and_then(|v| i32::try_from(v).ok()),
+    typical_p: float("typical_p"),
+    mirostat: int("mirostat").and_then(|v| u8::try_from(v).ok()),
+    mirostat_tau: float("mirostat_tau"),
+    mirostat_eta: float("mirostat_eta"),
+  };
+  let context_params = GptContextParams {
+    n_ctx: int("num_ctx").and_then(|v| i32::try_from(v).ok()),
+    n_threads: int("num_thread").and_then(|v| u32::try_from(v).ok()),
+    ..Default::default()
+  };
+  (request_params, context_params)
+}
+
+#[allow(clippy::result_large_err)]
+fn writeln(stdout: &mut dyn StdoutWriter, line: &str) -> crate::error::Result<()> {
+  stdout.write(&format!("{line}\n")).map_err(Common::from)?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use super::{convert_params, detect_chat_template, ImportCommand};
+  use crate::{
+    cli::StdoutWriter,
+    objs::{ChatTemplate, ChatTemplateId, Repo},
+    service::AppServiceFn,
+    test_utils::{app_service_stub, AppServiceTuple},
+    Command, ImportSource,
+  };
+  use rstest::rstest;
+  use serde_json::json;
+  use std::{fs, io, path::Path, sync::Arc};
+  use tempfile::TempDir;
+
+  #[derive(Default)]
+  struct StringWriter(String);
+
+  impl StdoutWriter for StringWriter {
+    fn write(&mut self, str: &str) -> io::Result<usize> {
+      self.0.push_str(str);
+      Ok(str.len())
+    }
+  }
+
+  #[rstest]
+  fn test_import_command_try_from() -> anyhow::Result<()> {
+    let result = ImportCommand::try_from(Command::Import {
+      source: ImportSource::Ollama {
+        models_dir: Some("/tmp/ollama".into()),
+        copy: true,
+        force: false,
+      },
+    })?;
+    assert_eq!(
+      ImportCommand::Ollama {
+        models_dir: Some("/tmp/ollama".into()),
+        copy: true,
+        force: false
+      },
+      result
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(
+    "<|start_header_id|>user<|end_header_id|>\n\n{{ .Prompt }}<|eot_id|>",
+    Some(ChatTemplateId::Llama3)
+  )]
+  #[case(
+    "<start_of_turn>user\n{{ .Prompt }}<end_of_turn>",
+    Some(ChatTemplateId::Gemma)
+  )]
+  #[case("<|user|>\n{{ .Prompt }}<|end|>", Some(ChatTemplateId::Phi3))]
+  #[case("<|user|>\n{{ .Prompt }}</s>", Some(ChatTemplateId::Tinyllama))]
+  #[case("[INST] {{ .Prompt }} [/INST]", Some(ChatTemplateId::Llama2))]
+  #[case("<|im_start|>user\n{{ .Prompt }}<|im_end|>", None)]
+  fn test_import_detect_chat_template(
+    #[case] template: &str,
+    #[case] expected: Option<ChatTemplateId>,
+  ) {
+    assert_eq!(expected, detect_chat_template(template));
+  }
+
+  #[rstest]
+  fn test_import_convert_params() {
+    let (request_params, context_params) = convert_params(&json! {{
+      "stop": ["<|eot_id|>", "<|end_of_text|>"],
+      "temperature": 0.5,
+      "num_ctx": 4096,
+      "num_predict": 256,
+      "seed": 42,
+      "top_k": 20,
+      "repeat_penalty": 1.1,
+      "mirostat": 1
+    }});
+    assert_eq!(
+      vec!["<|eot_id|>".to_string(), "<|end_of_text|>".to_string()],
+      request_params.stop
+    );
+    assert_eq!(Some(0.5), request_params.temperature);
+    assert_eq!(Some(256), request_params.max_tokens);
+    assert_eq!(Some(42), request_params.seed);
+    assert_eq!(Some(20), request_params.top_k);
+    assert_eq!(Some(1.1), request_params.repeat_penalty);
+    assert_eq!(Some(1), request_params.mirostat);
+    assert_eq!(Some(4096), context_params.n_ctx);
+  }
+
+  fn write_blob(models_dir: &Path, hex: &str, content: &str) -> anyhow::Result<()> {
+    fs::write(
+      models_dir.join("blobs").join(format!("sha256-{hex}")),
+      content,
+    )?;
+    Ok(())
+  }
+
+  fn ollama_dir() -> anyhow::Result<TempDir> {
+    let temp_dir = TempDir::new()?;
+    let models_dir = temp_dir.path();
+    fs::create_dir_all(models_dir.join("blobs"))?;
+    write_blob(models_dir, "aaaa", "GGUF model")?;
+    write_blob(
+      models_dir,
+      "bbbb",
+      "<|start_header_id|>user<|end_header_id|>\n\n{{ .Prompt }}<|eot_id|>",
+    )?;
+    write_blob(
+      models_dir,
+      "cccc",
+      r#"{"stop":["<|eot_id|>"],"num_ctx":8192}"#,
+    )?;
+    write_blob(models_dir, "dddd", "<|im_start|>{{ .Prompt }}<|im_end|>")?;
+    let manifests = models_dir.join("manifests/registry.ollama.ai/library");
+    fs::create_dir_all(manifests.join("llama3"))?;
+    fs::write(
+      manifests.join("llama3/latest"),
+      json! {{
+        "schemaVersion": 2,
+        "layers": [
+          {"mediaType": "application/vnd.ollama.image.model", "digest": "sha256:aaaa", "size": 10},
+          {"mediaType": "application/vnd.ollama.image.template", "digest": "sha256:bbbb", "size": 20},
+          {"mediaType": "application/vnd.ollama.image.params", "digest": "sha256:cccc", "size": 30}
+        ]
+      }}
+      .to_string(),
+    )?;
+    fs::create_dir_all(manifests.join("qwen"))?;
+    fs::write(
+      manifests.join("qwen/7b"),
+      json! {{
+        "schemaVersion": 2,
+        "layers": [
+          {"mediaType": "application/vnd.ollama.image.model", "digest": "sha256:aaaa", "size": 10},
+          {"mediaType": "application/vnd.ollama.image.template", "digest": "sha256:dddd", "size": 20}
+        ]
+      }}
+      .to_string(),
+    )?;
+    Ok(temp_dir)
+  }
+
+  #[rstest]
+  #[case(false)]
+  #[case(true)]
+  fn test_import_ollama_registers_aliases(
+    app_service_stub: AppServiceTuple,
+    #[case] copy: bool,
+  ) -> anyhow::Result<()> {
+    let AppServiceTuple(_temp_bodhi_home, _temp_hf_home, _, hf_cache, service) = app_service_stub;
+    let data_service = service.data_service();
+    let ollama_dir = ollama_dir()?;
+    let mut stdout = StringWriter::default();
+    ImportCommand::Ollama {
+      models_dir: Some(ollama_dir.path().to_path_buf()),
+      copy,
+      force: false,
+    }
+    .execute(Arc::new(service), &mut stdout)?;
+    let alias = data_service
+      .find_alias("llama3:latest")
+      .expect("alias should be imported");
+    assert_eq!(Repo::try_from("ollama/llama3")?, alias.repo);
+    assert_eq!("llama3-latest.gguf", alias.filename);
+    assert_eq!("aaaa", alias.snapshot);
+    assert_eq!(
+      ChatTemplate::Id(ChatTemplateId::Llama3),
+      alias.chat_template
+    );
+    assert_eq!(vec!["<|eot_id|>".to_string()], alias.request_params.stop);
+    assert_eq!(Some(8192), alias.context_params.n_ctx);
+    let model_file = hf_cache.join("models--ollama--llama3/snapshots/aaaa/llama3-latest.gguf");
+    assert_eq!("GGUF model", fs::read_to_string(&model_file)?);
+    assert_eq!(
+      !copy,
+      model_file.symlink_metadata()?.file_type().is_symlink()
+    );
+    assert!(data_service.find_alias("qwen:7b").is_none());
+    assert!(stdout
+      .0
+      .contains("skipped 'qwen:7b': chat template not recognized"));
+    assert!(stdout.0.contains("1 model(s) imported"));
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_import_ollama_skips_existing_alias(
+    app_service_stub: AppServiceTuple,
+  ) -> anyhow::Result<()> {
+    let AppServiceTuple(_temp_bodhi_home, _temp_hf_home, _, _, service) = app_service_stub;
+    let ollama_dir = ollama_dir()?;
+    let service = Arc::new(service);
+    ImportCommand::Ollama {
+      models_dir: Some(ollama_dir.path().to_path_buf()),
+      copy: false,
+      force: false,
+    }
+    .execute(service.clone(), &mut StringWriter::default())?;
+    let mut stdout = StringWriter::default();
+    ImportCommand::Ollama {
+      models_dir: Some(ollama_dir.path().to_path_buf()),
+      copy: false,
+      force: false,
+    }
+    .execute(service, &mut stdout)?;
+    assert!(stdout
+      .0
+      .contains("skipped 'llama3:latest': model alias already exists"));
+    assert!(stdout.0.contains("0 model(s) imported"));
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_import_ollama_empty_dir(app_service_stub: AppServiceTuple) -> anyhow::Result<()> {
+    let AppServiceTuple(_temp_bodhi_home, _temp_hf_home, _, _, service) = app_service_stub;
+    let ollama_dir = TempDir::new()?;
+    let mut stdout = StringWriter::default();
+    ImportCommand::Ollama {
+      models_dir: Some(ollama_dir.path().to_path_buf()),
+      copy: false,
+      force: false,
+    }
+    .execute(Arc::new(service), &mut stdout)?;
+    assert!(stdout.0.contains("no ollama models found"));
+    Ok(())
+  }
+}
@@ -0,0 +1,92 @@
+use super::{CliError, StdoutWriter};
+use crate::{error::Common, service::AppServiceFn, system_info::SystemInfo, Command};
+use std::sync::Arc;
+
+#[derive(Debug, PartialEq)]
+pub struct InfoCommand {
+  json: bool,
+}
+
+impl TryFrom<Command> for InfoCommand {
+  type Error = CliError;
+
+  fn try_from(value: Command) -> Result<Self, Self::Error> {
+    match value {
+      Command::Info { json } => Ok(InfoCommand { json }),
+      cmd => Err(CliError::ConvertCommand(
+        cmd.to_string(),
+        "info".to_string(),
+      )),
+    }
+  }
+}
+
+impl InfoCommand {
+  #[allow(clippy::result_large_err)]
+  pub fn execute(
+    self,
+    service: Arc<dyn AppServiceFn>,
+    stdout: &mut dyn StdoutWriter,
+  ) -> crate::error::Result<()> {
+    let info = SystemInfo::collect(service.env_service().as_ref());
+    let output = if self.json {
+      let json = serde_json::to_string_pretty(&info).map_err(Common::SerdeJsonDeserialize)?;
+      format!("{json}\n")
+    } else {
+      info.to_text()
+    };
+    stdout.write(&output).map_err(Common::from)?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::InfoCommand;
+  use crate::{
+    service::{MockDataService, MockEnvServiceFn, MockHubService},
+    test_utils::AppServiceStubMock,
+    Command, MockStdoutWriter,
+  };
+  use rstest::rstest;
+  use serde_json::Value;
+  use std::{path::PathBuf, sync::Arc};
+
+  #[rstest]
+  fn test_info_command_from_cli() -> anyhow::Result<()> {
+    let result = InfoCommand::try_from(Command::Info { json: true })?;
+    assert_eq!(InfoCommand { json: true }, result);
+    let result = InfoCommand::try_from(Command::App { ui: false });
+    assert_eq!(
+      "Command 'app' cannot be converted into command 'info'",
+      result.unwrap_err().to_string()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_info_command_prints_json() -> anyhow::Result<()> {
+    let mut env_service = MockEnvServiceFn::new();
+    env_service
+      .expect_bodhi_home()
+      .return_const(PathBuf::from("/tmp/bodhi"));
+    env_service
+      .expect_hf_home()
+      .return_const(PathBuf::from("/tmp/huggingface"));
+    let service =
+      AppServiceStubMock::new(env_service, MockHubService::new(), MockDataService::new());
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .withf(|input| {
+        serde_json::from_str::<Value>(input).is_ok_and(|info| {
+          info["version"] == env!("CARGO_PKG_VERSION")
+            && info["bodhi_home"] == "/tmp/bodhi"
+            && info["hf_home"] == "/tmp/huggingface"
+        })
+      })
+      .return_once(|input| Ok(input.len()));
+    InfoCommand { json: true }.execute(Arc::new(service), &mut stdout)?;
+    Ok(())
+  }
+}
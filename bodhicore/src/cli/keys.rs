@@ -0,0 +1,263 @@
+use super::{CliError, StdoutWriter};
+use crate::{
+  db::{
+    objs::{ApiKeyRole, AuditAction, AuditEntry},
+    DbPool, DbServiceFn, TimeService,
+  },
+  error::{BodhiError, Common},
+  objs::{DisplayTimezone, RequestPriority},
+  service::AppServiceFn,
+  Command, KeysAction,
+};
+use chrono::{DateTime, Utc};
+use prettytable::{format, row, Table};
+use std::sync::Arc;
+use tokio::runtime::Builder;
+
+#[derive(Debug, PartialEq)]
+pub enum KeysCommand {
+  Create {
+    name: String,
+    role: ApiKeyRole,
+    priority: RequestPriority,
+  },
+  List,
+  Revoke {
+    id: String,
+  },
+}
+
+impl TryFrom<Command> for KeysCommand {
+  type Error = CliError;
+
+  fn try_from(value: Command) -> Result<Self, Self::Error> {
+    match value {
+      Command::Keys { action } => match action {
+        KeysAction::Create {
+          name,
+          role,
+          priority,
+        } => Ok(KeysCommand::Create {
+          name,
+          role,
+          priority,
+        }),
+        KeysAction::List {} => Ok(KeysCommand::List),
+        KeysAction::Revoke { id } => Ok(KeysCommand::Revoke { id }),
+      },
+      cmd => Err(CliError::ConvertCommand(
+        cmd.to_string(),
+        "keys".to_string(),
+      )),
+    }
+  }
+}
+
+impl KeysCommand {
+  #[allow(clippy::result_large_err)]
+  pub fn execute(
+    self,
+    service: Arc<dyn AppServiceFn>,
+    stdout: &mut dyn StdoutWriter,
+  ) -> crate::error::Result<()> {
+    let runtime = Builder::new_current_thread()
+      .enable_all()
+      .build()
+      .map_err(Common::from)?;
+    runtime.block_on(async move {
+      let db_service =
+        DbPool::connect_service(&service.env_service().database_url(), Arc::new(TimeService))
+          .await?;
+      db_service.migrate().await?;
+      let timezone = service.env_service().timezone();
+      let auth = service.env_service().auth();
+      self
+        .aexecute(db_service.as_ref(), &timezone, auth, stdout)
+        .await?;
+      Ok::<(), BodhiError>(())
+    })?;
+    Ok(())
+  }
+
+  async fn aexecute(
+    self,
+    db_service: &dyn DbServiceFn,
+    timezone: &DisplayTimezone,
+    auth: bool,
+    stdout: &mut dyn StdoutWriter,
+  ) -> crate::error::Result<()> {
+    let output = match self {
+      KeysCommand::Create {
+        name,
+        role,
+        priority,
+      } => {
+        let (api_key, secret) = db_service.create_api_key(&name, role, priority).await?;
+        record_key_audit(
+          db_service,
+          AuditAction::KeyCreate,
+          &api_key.id,
+          Some(format!("{} ({})", api_key.name, api_key.role)),
+        )
+        .await;
+        let mut output = format!(
+          "created API key '{}' with the {} role, id {}\n{secret}\nthe key is not stored, keep it safe as it is not shown again\n",
+          api_key.name, api_key.role, api_key.id
+        );
+        if !auth {
+          output.push_str("the keys are not required till BODHI_AUTH is enabled\n");
+        }
+        output
+      }
+      KeysCommand::List => {
+        let mut table = Table::new();
+        table.add_row(row!["ID", "NAME", "ROLE", "PRIORITY", "CREATED", "REVOKED"]);
+        for api_key in db_service.list_api_keys().await? {
+          table.add_row(row![
+            api_key.id,
+            api_key.name,
+            api_key.role,
+            api_key.priority,
+            timezone.display(&api_key.created_at),
+            api_key
+              .revoked_at
+              .map(|revoked_at| timezone.display(&revoked_at))
+              .unwrap_or_default(),
+          ]);
+        }
+        table.set_format(format::FormatBuilder::default().padding(2, 2).build());
+        table.to_string()
+      }
+      KeysCommand::Revoke { id } => {
+        db_service.revoke_api_key(&id).await?;
+        record_key_audit(db_service, AuditAction::KeyRevoke, &id, None).await;
+        format!("revoked API key {id}\n")
+      }
+    };
+    stdout.write(&output).map_err(Common::from)?;
+    Ok(())
+  }
+}
+
+// the keys are managed only from the cli, recorded with `cli` as the actor
+async fn record_key_audit(
+  db_service: &dyn DbServiceFn,
+  action: AuditAction,
+  id: &str,
+  after: Option<String>,
+) {
+  let mut entry = AuditEntry {
+    id: String::new(),
+    created_at: DateTime::<Utc>::default(),
+    actor: Some("cli".to_string()),
+    action,
+    target: id.to_string(),
+    before: None,
+    after,
+  };
+  if let Err(err) = db_service.save_audit_entry(&mut entry).await {
+    tracing::warn!(?err, ?entry, "error recording the audit entry");
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::KeysCommand;
+  use crate::{
+    db::{
+      objs::{ApiKeyRole, AuditAction},
+      DbService, DbServiceFn,
+    },
+    objs::{DisplayTimezone, RequestPriority},
+    test_utils::db_service,
+    Command, KeysAction, MockStdoutWriter,
+  };
+  use chrono::{DateTime, Utc};
+  use rstest::rstest;
+  use tempfile::TempDir;
+
+  #[rstest]
+  #[case(
+    KeysAction::Create { name: "alice".to_string(), role: ApiKeyRole::Admin, priority: RequestPriority::Batch },
+    KeysCommand::Create { name: "alice".to_string(), role: ApiKeyRole::Admin, priority: RequestPriority::Batch }
+  )]
+  #[case(KeysAction::List {}, KeysCommand::List)]
+  #[case(
+    KeysAction::Revoke { id: "testid".to_string() },
+    KeysCommand::Revoke { id: "testid".to_string() }
+  )]
+  fn test_keys_command_from_cli(
+    #[case] action: KeysAction,
+    #[case] expected: KeysCommand,
+  ) -> anyhow::Result<()> {
+    assert_eq!(expected, KeysCommand::try_from(Command::Keys { action })?);
+    let result = KeysCommand::try_from(Command::App { ui: false });
+    assert_eq!(
+      "Command 'app' cannot be converted into command 'keys'",
+      result.unwrap_err().to_string()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_keys_command_create_list_revoke(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .withf(|input| {
+        input.starts_with("created API key 'alice' with the user role")
+          && input.contains("\nbodhi-")
+          && input.contains("BODHI_AUTH")
+      })
+      .return_once(|input| Ok(input.len()));
+    KeysCommand::Create {
+      name: "alice".to_string(),
+      role: ApiKeyRole::User,
+      priority: RequestPriority::Interactive,
+    }
+    .aexecute(&db_service, &DisplayTimezone::Utc, false, &mut stdout)
+    .await?;
+    let id = db_service.list_api_keys().await?[0].id.clone();
+
+    let mut stdout = MockStdoutWriter::default();
+    let expected = format!("revoked API key {id}\n");
+    stdout
+      .expect_write()
+      .withf(move |input| input == expected)
+      .return_once(|input| Ok(input.len()));
+    KeysCommand::Revoke { id: id.clone() }
+      .aexecute(&db_service, &DisplayTimezone::Utc, true, &mut stdout)
+      .await?;
+
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .withf(move |input| {
+        input.contains("REVOKED") && input.contains(&id) && input.contains("alice")
+      })
+      .return_once(|input| Ok(input.len()));
+    KeysCommand::List
+      .aexecute(&db_service, &DisplayTimezone::Utc, true, &mut stdout)
+      .await?;
+
+    let actions = db_service
+      .list_audit_entries(None, None, 10)
+      .await?
+      .into_iter()
+      .map(|entry| (entry.action, entry.actor))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      vec![
+        (AuditAction::KeyRevoke, Some("cli".to_string())),
+        (AuditAction::KeyCreate, Some("cli".to_string())),
+      ],
+      actions
+    );
+    Ok(())
+  }
+}
@@ -1,16 +1,64 @@
-use super::CliError;
-use crate::{objs::RemoteModel, service::AppServiceFn, Command};
+use super::{CliError, ListFormat, StdoutWriter};
+use crate::{
+  error::Common,
+  objs::{RemoteModel, Repo, REFS_MAIN},
+  service::AppServiceFn,
+  Command,
+};
 use prettytable::{
   format::{self},
   row, Row, Table,
 };
+use serde::Serialize;
 use std::sync::Arc;
 
 #[derive(Debug, PartialEq)]
 pub enum ListCommand {
+  Local { format: ListFormat },
+  Remote { format: ListFormat },
+  Models { format: ListFormat },
+  All { format: ListFormat },
+  WhatsNew { format: ListFormat },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, strum::Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+enum InventoryStatus {
+  /// pre-configured model alias, configured locally
+  Installed,
+  /// pre-configured model alias, not configured yet
+  Available,
+  /// model alias created locally
   Local,
-  Remote,
-  Models,
+}
+
+/// Entry of `bodhi list --all`, a local or pre-configured model alias
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct InventoryEntry {
+  alias: String,
+  repo: Repo,
+  filename: String,
+  status: InventoryStatus,
+  size: Option<u64>,
+  update_available: bool,
+}
+
+impl From<InventoryEntry> for Row {
+  fn from(entry: InventoryEntry) -> Self {
+    let human_size = entry
+      .size
+      .map(|size| format!("{:.2} GB", size as f64 / 2_f64.powf(30.0)))
+      .unwrap_or_else(|| String::from("-"));
+    row![
+      entry.alias,
+      entry.repo,
+      entry.filename,
+      entry.status,
+      human_size,
+      if entry.update_available { "yes" } else { "" }
+    ]
+  }
 }
 
 impl TryFrom<Command> for ListCommand {
@@ -18,13 +66,24 @@ impl TryFrom<Command> for ListCommand {
 
   fn try_from(value: Command) -> Result<Self, Self::Error> {
     match value {
-      Command::List { remote, models } => match (remote, models) {
-        (true, false) => Ok(ListCommand::Remote),
-        (false, true) => Ok(ListCommand::Models),
-        (false, false) => Ok(ListCommand::Local),
-        (true, true) => Err(CliError::BadRequest(format!(
+      Command::List {
+        remote,
+        models,
+        all,
+        whats_new,
+        format,
+      } => match (remote, models, all) {
+        (true, true, _) => Err(CliError::BadRequest(format!(
           "cannot initialize list command with invalid state. --remote: {remote}, --models: {models}"
         ))),
+        (true, false, false) if whats_new => Ok(ListCommand::WhatsNew { format }),
+        (true, false, false) => Ok(ListCommand::Remote { format }),
+        (false, true, false) => Ok(ListCommand::Models { format }),
+        (false, false, true) => Ok(ListCommand::All { format }),
+        (false, false, false) => Ok(ListCommand::Local { format }),
+        (_, _, true) => Err(CliError::BadRequest(format!(
+          "cannot initialize list command with invalid state. --all cannot be used with --remote: {remote}, --models: {models}"
+        ))),
       },
       cmd => Err(CliError::ConvertCommand(cmd.to_string(), "list".to_string())),
     }
@@ -33,79 +92,275 @@ impl TryFrom<Command> for ListCommand {
 
 impl ListCommand {
   #[allow(clippy::result_large_err)]
-  pub fn execute(self, service: Arc<dyn AppServiceFn>) -> crate::error::Result<()> {
+  pub fn execute(
+    self,
+    service: Arc<dyn AppServiceFn>,
+    stdout: &mut dyn StdoutWriter,
+  ) -> crate::error::Result<()> {
     match self {
-      ListCommand::Local => self.list_local_model_alias(service)?,
-      ListCommand::Remote => self.list_remote_models(service)?,
-      ListCommand::Models => self.list_local_models(service)?,
+      ListCommand::Local { format } => list_local_model_alias(service, format, stdout)?,
+      ListCommand::Remote { format } => list_remote_models(service, format, stdout)?,
+      ListCommand::Models { format } => list_local_models(service, format, stdout)?,
+      ListCommand::All { format } => list_all(service, format, stdout)?,
+      ListCommand::WhatsNew { format } => list_catalog_changes(service, format, stdout)?,
     }
     Ok(())
   }
+}
 
-  fn list_local_model_alias(self, service: Arc<dyn AppServiceFn>) -> crate::error::Result<()> {
-    let mut table = Table::new();
-    table.add_row(row![
-      "ALIAS",
-      "FAMILY",
-      "REPO",
-      "FILENAME",
-      "FEATURES",
-      "CHAT TEMPLATE"
-    ]);
-    let aliases = service.data_service().list_aliases()?;
-    for row in aliases.into_iter().map(Row::from) {
-      table.add_row(row);
+// writes the items as json or yaml, returns false for the table format
+#[allow(clippy::result_large_err)]
+fn write_serialized<T: Serialize + ?Sized>(
+  items: &T,
+  format: ListFormat,
+  stdout: &mut dyn StdoutWriter,
+) -> crate::error::Result<bool> {
+  let output = match format {
+    ListFormat::Table => return Ok(false),
+    ListFormat::Json => {
+      let json = serde_json::to_string_pretty(items).map_err(Common::SerdeJsonDeserialize)?;
+      format!("{json}\n")
     }
-    table.set_format(format::FormatBuilder::default().padding(2, 2).build());
-    table.printstd();
-    println!();
-    println!("To run a model alias, run `bodhi run <ALIAS>`");
-    Ok(())
+    ListFormat::Yaml => serde_yaml::to_string(items).map_err(Common::SerdeYamlDeserialize)?,
+  };
+  stdout.write(&output).map_err(Common::Io)?;
+  Ok(true)
+}
+
+#[allow(clippy::result_large_err)]
+fn write_table(
+  table: &mut Table,
+  footer: &str,
+  stdout: &mut dyn StdoutWriter,
+) -> crate::error::Result<()> {
+  table.set_format(format::FormatBuilder::default().padding(2, 2).build());
+  stdout
+    .write(&format!("{table}{footer}"))
+    .map_err(Common::Io)?;
+  Ok(())
+}
+
+#[allow(clippy::result_large_err)]
+fn list_local_model_alias(
+  service: Arc<dyn AppServiceFn>,
+  format: ListFormat,
+  stdout: &mut dyn StdoutWriter,
+) -> crate::error::Result<()> {
+  let aliases = service.data_service().list_aliases()?;
+  if write_serialized(&aliases, format, stdout)? {
+    return Ok(());
   }
+  let mut table = Table::new();
+  table.add_row(row![
+    "ALIAS",
+    "FAMILY",
+    "REPO",
+    "FILENAME",
+    "FEATURES",
+    "CHAT TEMPLATE"
+  ]);
+  for row in aliases.into_iter().map(Row::from) {
+    table.add_row(row);
+  }
+  let skipped = service
+    .data_service()
+    .lint_aliases()?
+    .into_iter()
+    .filter(|lint| lint.skipped)
+    .count();
+  let mut footer = String::from("\nTo run a model alias, run `bodhi run <ALIAS>`\n");
+  if skipped > 0 {
+    footer.push_str(&format!(
+      "Skipped {skipped} invalid model alias file(s), run `bodhi alias lint` for the problems\n"
+    ));
+  }
+  write_table(&mut table, &footer, stdout)
+}
 
-  fn list_local_models(self, service: Arc<dyn AppServiceFn>) -> crate::error::Result<()> {
-    let mut table = Table::new();
-    table.add_row(row!["REPO", "FILENAME", "SNAPSHOT", "SIZE"]);
-    let mut models = service.hub_service().list_local_models();
-    models.sort_by(|a, b| a.repo.cmp(&b.repo));
-    for row in models.into_iter().map(Row::from) {
-      table.add_row(row);
+#[allow(clippy::result_large_err)]
+fn list_local_models(
+  service: Arc<dyn AppServiceFn>,
+  format: ListFormat,
+  stdout: &mut dyn StdoutWriter,
+) -> crate::error::Result<()> {
+  let mut models = service.hub_service().list_local_models();
+  models.sort_by(|a, b| a.repo.cmp(&b.repo));
+  if write_serialized(&models, format, stdout)? {
+    return Ok(());
+  }
+  let mut table = Table::new();
+  table.add_row(row!["REPO", "FILENAME", "SNAPSHOT", "SIZE"]);
+  for row in models.into_iter().map(Row::from) {
+    table.add_row(row);
+  }
+  write_table(&mut table, "", stdout)
+}
+
+#[allow(clippy::result_large_err)]
+fn list_remote_models(
+  service: Arc<dyn AppServiceFn>,
+  format: ListFormat,
+  stdout: &mut dyn StdoutWriter,
+) -> crate::error::Result<()> {
+  let models: Vec<RemoteModel> = service.data_service().list_remote_models()?;
+  if write_serialized(&models, format, stdout)? {
+    return Ok(());
+  }
+  let mut table = Table::new();
+  table.add_row(row![
+    "ALIAS",
+    "FAMILY",
+    "REPO",
+    "FILENAME",
+    "FEATURES",
+    "CHAT TEMPLATE"
+  ]);
+  for row in models.into_iter().map(Row::from) {
+    table.add_row(row);
+  }
+  write_table(
+    &mut table,
+    "\nTo download and configure the model alias, run `bodhi pull <ALIAS>`\n",
+    stdout,
+  )
+}
+
+#[allow(clippy::result_large_err)]
+fn list_all(
+  service: Arc<dyn AppServiceFn>,
+  format: ListFormat,
+  stdout: &mut dyn StdoutWriter,
+) -> crate::error::Result<()> {
+  let entries = inventory(service.as_ref())?;
+  if write_serialized(&entries, format, stdout)? {
+    return Ok(());
+  }
+  let mut table = Table::new();
+  table.add_row(row![
+    "ALIAS", "REPO", "FILENAME", "STATUS", "SIZE", "UPDATE"
+  ]);
+  for row in entries.into_iter().map(Row::from) {
+    table.add_row(row);
+  }
+  write_table(
+    &mut table,
+    "\nTo download and configure an available model alias, run `bodhi pull <ALIAS>`\n",
+    stdout,
+  )
+}
+
+// merges the local and the pre-configured model aliases, sorted by alias name
+#[allow(clippy::result_large_err)]
+fn inventory(service: &dyn AppServiceFn) -> crate::error::Result<Vec<InventoryEntry>> {
+  let hub_service = service.hub_service();
+  let aliases = service.data_service().list_aliases()?;
+  let remote_models = service.data_service().list_remote_models()?;
+  let mut entries = aliases
+    .into_iter()
+    .map(|alias| {
+      let status = if remote_models.iter().any(|model| model.alias == alias.alias) {
+        InventoryStatus::Installed
+      } else {
+        InventoryStatus::Local
+      };
+      let size = hub_service
+        .find_local_file(&alias.repo, &alias.filename, &alias.snapshot)
+        .ok()
+        .flatten()
+        .and_then(|file| file.size);
+      // refs/main moves to the newer snapshot when the file is downloaded again
+      let update_available = hub_service
+        .find_local_file(&alias.repo, &alias.filename, REFS_MAIN)
+        .ok()
+        .flatten()
+        .is_some_and(|file| file.snapshot != alias.snapshot);
+      InventoryEntry {
+        alias: alias.alias,
+        repo: alias.repo,
+        filename: alias.filename,
+        status,
+        size,
+        update_available,
+      }
+    })
+    .collect::<Vec<_>>();
+  for model in remote_models {
+    if entries.iter().any(|entry| entry.alias == model.alias) {
+      continue;
     }
-    table.set_format(format::FormatBuilder::default().padding(2, 2).build());
-    table.printstd();
-    Ok(())
+    let size = hub_service
+      .find_local_file(&model.repo, &model.filename, REFS_MAIN)
+      .ok()
+      .flatten()
+      .and_then(|file| file.size);
+    entries.push(InventoryEntry {
+      alias: model.alias,
+      repo: model.repo,
+      filename: model.filename,
+      status: InventoryStatus::Available,
+      size,
+      update_available: false,
+    });
   }
+  entries.sort_by(|a, b| a.alias.cmp(&b.alias));
+  Ok(entries)
+}
 
-  fn list_remote_models(self, service: Arc<dyn AppServiceFn>) -> crate::error::Result<()> {
-    let models: Vec<RemoteModel> = service.data_service().list_remote_models()?;
+#[allow(clippy::result_large_err)]
+fn list_catalog_changes(
+  service: Arc<dyn AppServiceFn>,
+  format: ListFormat,
+  stdout: &mut dyn StdoutWriter,
+) -> crate::error::Result<()> {
+  let catalog_changes = service.data_service().list_catalog_changes()?;
+  if write_serialized(&catalog_changes, format, stdout)? {
+    return Ok(());
+  }
+  if catalog_changes.is_empty() {
+    stdout
+      .write("No changes to the pre-configured model aliases yet\n")
+      .map_err(Common::Io)?;
+    return Ok(());
+  }
+  let timezone = service.env_service().timezone();
+  let mut output = String::new();
+  for diff in catalog_changes {
     let mut table = Table::new();
-    table.add_row(row![
-      "ALIAS",
-      "FAMILY",
-      "REPO",
-      "FILENAME",
-      "FEATURES",
-      "CHAT TEMPLATE"
-    ]);
-    for row in models.into_iter().map(Row::from) {
-      table.add_row(row);
+    table.add_row(row!["CHANGE", "ALIAS", "REPO", "FILENAME", "PREVIOUS"]);
+    for change in diff.changes {
+      table.add_row(row![
+        change.kind,
+        change.alias,
+        change.repo,
+        change.filename,
+        change.previous.unwrap_or_default()
+      ]);
     }
     table.set_format(format::FormatBuilder::default().padding(2, 2).build());
-    table.printstd();
-    println!();
-    println!("To download and configure the model alias, run `bodhi pull <ALIAS>`");
-    Ok(())
+    output.push_str(&format!(
+      "Updated on {}\n{table}\n",
+      timezone.display(&diff.created_at)
+    ));
   }
+  output.push_str("To download and configure the model alias, run `bodhi pull <ALIAS>`\n");
+  stdout.write(&output).map_err(Common::Io)?;
+  Ok(())
 }
 
 #[cfg(test)]
 mod test {
-  use super::{Command, ListCommand};
+  use super::{inventory, Command, InventoryEntry, InventoryStatus, ListCommand};
+  use crate::{
+    test_utils::{app_service_stub, AppServiceTuple},
+    ListFormat, MockStdoutWriter,
+  };
   use rstest::rstest;
+  use std::{fs, sync::Arc};
 
   #[rstest]
   #[case(Command::App {ui: false}, "Command 'app' cannot be converted into command 'list'")]
-  #[case(Command::List {remote: true, models: true}, "cannot initialize list command with invalid state. --remote: true, --models: true")]
+  #[case(Command::List {remote: true, models: true, all: false, whats_new: false, format: ListFormat::Table}, "cannot initialize list command with invalid state. --remote: true, --models: true")]
+  #[case(Command::List {remote: false, models: true, all: true, whats_new: false, format: ListFormat::Table}, "cannot initialize list command with invalid state. --all cannot be used with --remote: false, --models: true")]
   fn test_list_invalid_try_from(#[case] input: Command, #[case] expected: String) {
     let result = ListCommand::try_from(input);
     assert!(result.is_err());
@@ -116,15 +371,38 @@ mod test {
   #[case(Command::List {
     remote: false,
     models: false,
-  }, ListCommand::Local)]
+    all: false,
+    whats_new: false,
+    format: ListFormat::Table,
+  }, ListCommand::Local { format: ListFormat::Table })]
   #[case(Command::List {
     remote: true,
     models: false,
-  }, ListCommand::Remote)]
+    all: false,
+    whats_new: false,
+    format: ListFormat::Json,
+  }, ListCommand::Remote { format: ListFormat::Json })]
   #[case(Command::List {
     remote: false,
     models: true,
-  }, ListCommand::Models)]
+    all: false,
+    whats_new: false,
+    format: ListFormat::Yaml,
+  }, ListCommand::Models { format: ListFormat::Yaml })]
+  #[case(Command::List {
+    remote: false,
+    models: false,
+    all: true,
+    whats_new: false,
+    format: ListFormat::Json,
+  }, ListCommand::All { format: ListFormat::Json })]
+  #[case(Command::List {
+    remote: true,
+    models: false,
+    all: false,
+    whats_new: true,
+    format: ListFormat::Table,
+  }, ListCommand::WhatsNew { format: ListFormat::Table })]
   fn test_list_valid_try_from(
     #[case] input: Command,
     #[case] expected: ListCommand,
@@ -133,4 +411,124 @@ mod test {
     assert_eq!(expected, result);
     Ok(())
   }
+
+  #[rstest]
+  fn test_list_local_json(app_service_stub: AppServiceTuple) -> anyhow::Result<()> {
+    let AppServiceTuple(_temp_bodhi_home, _temp_hf_home, _, _, service) = app_service_stub;
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .withf(|input| {
+        let aliases = serde_json::from_str::<serde_json::Value>(input).unwrap();
+        let names = aliases
+          .as_array()
+          .unwrap()
+          .iter()
+          .map(|alias| alias["alias"].as_str().unwrap().to_string())
+          .collect::<Vec<_>>();
+        names
+          == vec![
+            "llama3:instruct",
+            "testalias-exists:instruct",
+            "tinyllama:instruct",
+          ]
+      })
+      .return_once(|input| Ok(input.len()));
+    ListCommand::Local {
+      format: ListFormat::Json,
+    }
+    .execute(Arc::new(service), &mut stdout)?;
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(ListFormat::Yaml, "- alias: tinyllama:instruct\n")]
+  #[case(ListFormat::Table, "To run a model alias, run `bodhi run <ALIAS>`")]
+  fn test_list_local_formats(
+    app_service_stub: AppServiceTuple,
+    #[case] format: ListFormat,
+    #[case] expected: &'static str,
+  ) -> anyhow::Result<()> {
+    let AppServiceTuple(_temp_bodhi_home, _temp_hf_home, _, _, service) = app_service_stub;
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .withf(move |input| input.contains(expected) && input.contains("llama3:instruct"))
+      .return_once(|input| Ok(input.len()));
+    ListCommand::Local { format }.execute(Arc::new(service), &mut stdout)?;
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_list_local_reports_skipped_aliases(
+    app_service_stub: AppServiceTuple,
+  ) -> anyhow::Result<()> {
+    let AppServiceTuple(_temp_bodhi_home, _temp_hf_home, bodhi_home, _, service) = app_service_stub;
+    std::fs::write(
+      bodhi_home.join("aliases").join("invalid--instruct.yaml"),
+      "alias: invalid:instruct\n",
+    )?;
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .withf(|input| {
+        !input.contains("invalid:instruct")
+          && input.contains(
+            "Skipped 1 invalid model alias file(s), run `bodhi alias lint` for the problems",
+          )
+      })
+      .return_once(|input| Ok(input.len()));
+    ListCommand::Local {
+      format: ListFormat::Table,
+    }
+    .execute(Arc::new(service), &mut stdout)?;
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_list_all_inventory(app_service_stub: AppServiceTuple) -> anyhow::Result<()> {
+    let AppServiceTuple(_temp_bodhi_home, _temp_hf_home, _, hf_cache, service) = app_service_stub;
+    let repo_dir = hf_cache.join("models--TheBloke--TinyLlama-1.1B-Chat-v0.3-GGUF");
+    for snapshot in ["b32046744d93031a26c8e925de2c8932c305f7b9", "newsnapshot"] {
+      let snapshot_dir = repo_dir.join("snapshots").join(snapshot);
+      fs::create_dir_all(&snapshot_dir)?;
+      fs::write(
+        snapshot_dir.join("tinyllama-1.1b-chat-v0.3.Q2_K.gguf"),
+        "model",
+      )?;
+    }
+    fs::create_dir_all(repo_dir.join("refs"))?;
+    fs::write(repo_dir.join("refs").join("main"), "newsnapshot")?;
+
+    let entries = inventory(&service)?;
+    let tinyllama = entries
+      .iter()
+      .find(|entry| entry.alias == "tinyllama:instruct")
+      .unwrap();
+    assert_eq!(
+      (InventoryStatus::Local, Some(5), true),
+      (tinyllama.status, tinyllama.size, tinyllama.update_available)
+    );
+    let status = |alias: &str| {
+      entries
+        .iter()
+        .find(|entry: &&InventoryEntry| entry.alias == alias)
+        .map(|entry| entry.status)
+    };
+    assert_eq!(Some(InventoryStatus::Installed), status("llama3:instruct"));
+    assert_eq!(Some(InventoryStatus::Available), status("llama2:chat"));
+    assert_eq!(
+      Some(InventoryStatus::Local),
+      status("testalias-exists:instruct")
+    );
+    let names = entries
+      .iter()
+      .map(|entry| entry.alias.as_str())
+      .collect::<Vec<_>>();
+    let mut sorted = names.clone();
+    sorted.sort();
+    sorted.dedup();
+    assert_eq!(sorted, names);
+    Ok(())
+  }
 }
@@ -0,0 +1,125 @@
+use super::CliError;
+use crate::{
+  error::Common,
+  service::{AppServiceFn, HubServiceError},
+  Command,
+};
+use dialoguer::{theme::ColorfulTheme, Password};
+use std::{io, sync::Arc};
+
+#[derive(Debug, PartialEq)]
+pub struct LoginCommand {
+  token: Option<String>,
+}
+
+impl TryFrom<Command> for LoginCommand {
+  type Error = CliError;
+
+  fn try_from(value: Command) -> Result<Self, Self::Error> {
+    match value {
+      Command::Login { token } => Ok(LoginCommand { token }),
+      cmd => Err(CliError::ConvertCommand(
+        cmd.to_string(),
+        "login".to_string(),
+      )),
+    }
+  }
+}
+
+impl LoginCommand {
+  #[allow(clippy::result_large_err)]
+  pub fn execute(self, service: Arc<dyn AppServiceFn>) -> crate::error::Result<()> {
+    let token = match self.token {
+      Some(token) => token,
+      None => Password::with_theme(&ColorfulTheme::default())
+        .with_prompt("Huggingface access token (from https://huggingface.co/settings/tokens)")
+        .interact()
+        .map_err(|err| Common::Io(io::Error::from(err)))?,
+    };
+    let token = token.trim();
+    if token.is_empty() {
+      return Err(HubServiceError::InvalidToken.into());
+    }
+    let username = service.hub_service().whoami(token)?;
+    let path = service.hub_service().save_token(token)?;
+    println!(
+      "logged in to huggingface as '{username}', token saved to '{}'",
+      path.display()
+    );
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::LoginCommand;
+  use crate::{
+    service::{HubServiceError, MockDataService, MockEnvServiceFn, MockHubService},
+    test_utils::AppServiceStubMock,
+    Command,
+  };
+  use mockall::predicate::eq;
+  use rstest::rstest;
+  use std::{path::PathBuf, sync::Arc};
+
+  #[rstest]
+  fn test_login_command_try_from() -> anyhow::Result<()> {
+    let command = Command::Login {
+      token: Some("hf_testtoken".to_string()),
+    };
+    let result = LoginCommand::try_from(command)?;
+    assert_eq!(
+      LoginCommand {
+        token: Some("hf_testtoken".to_string())
+      },
+      result
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_login_command_validates_and_saves_token() -> anyhow::Result<()> {
+    let mut mock_hub_service = MockHubService::new();
+    mock_hub_service
+      .expect_whoami()
+      .with(eq("hf_testtoken"))
+      .return_once(|_| Ok("testuser".to_string()));
+    mock_hub_service
+      .expect_save_token()
+      .with(eq("hf_testtoken"))
+      .return_once(|_| Ok(PathBuf::from("/tmp/huggingface/token")));
+    let service = AppServiceStubMock::new(
+      MockEnvServiceFn::new(),
+      mock_hub_service,
+      MockDataService::new(),
+    );
+    LoginCommand {
+      token: Some(" hf_testtoken ".to_string()),
+    }
+    .execute(Arc::new(service))?;
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_login_command_invalid_token_not_saved() -> anyhow::Result<()> {
+    let mut mock_hub_service = MockHubService::new();
+    mock_hub_service
+      .expect_whoami()
+      .return_once(|_| Err(HubServiceError::InvalidToken));
+    mock_hub_service.expect_save_token().never();
+    let service = AppServiceStubMock::new(
+      MockEnvServiceFn::new(),
+      mock_hub_service,
+      MockDataService::new(),
+    );
+    let result = LoginCommand {
+      token: Some("hf_invalid".to_string()),
+    }
+    .execute(Arc::new(service));
+    assert_eq!(
+      "huggingface token is invalid or expired, create a new access token at https://huggingface.co/settings/tokens",
+      result.unwrap_err().to_string()
+    );
+    Ok(())
+  }
+}
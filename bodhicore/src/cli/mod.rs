@@ -1,24 +1,58 @@
+mod bench;
+mod cache;
+mod catalog;
+mod chats;
 mod command;
+mod completions;
 #[cfg(not(test))]
 mod create;
 #[cfg(test)]
 pub mod create;
+mod daemon;
+mod db;
 mod envs;
 mod error;
+mod eval;
+mod export;
+mod import;
+mod info;
+mod keys;
 mod list;
+mod login;
 mod out_writer;
 mod pull;
+mod replay;
 mod run;
 mod serve;
+mod update;
+mod usage;
+mod worker;
 mod alias;
 
+pub use bench::BenchCommand;
+pub use cache::CacheCommand;
+pub use catalog::CatalogCommand;
+pub use chats::ChatsCommand;
 pub use command::*;
+pub use completions::CompletionsCommand;
 pub use create::CreateCommand;
+pub use daemon::{DaemonCommand, ServerInfo};
+pub use db::DbCommand;
 pub use envs::EnvCommand;
 pub use error::CliError;
+pub use eval::EvalCommand;
+pub use export::ExportCommand;
+pub use import::ImportCommand;
+pub use info::InfoCommand;
+pub use keys::KeysCommand;
 pub use list::ListCommand;
+pub use login::LoginCommand;
 pub use out_writer::*;
 pub use pull::PullCommand;
+pub use replay::ReplayCommand;
 pub use run::RunCommand;
 pub use serve::*;
+pub use update::UpdateCommand;
+pub use usage::UsageCommand;
+pub use worker::WorkerCommand;
 pub use alias::ManageAliasCommand;
@@ -1,21 +1,29 @@
 use super::CliError;
 use crate::{
   error::BodhiError,
-  objs::{Alias, HubFile, REFS_MAIN, TOKENIZER_CONFIG_JSON},
-  service::AppServiceFn,
+  objs::{revision_snapshot, Alias, HubFile, DEFAULT_REVISION, TOKENIZER_CONFIG_JSON},
+  service::{AppServiceFn, HubServiceError},
   Command, Repo,
 };
+use glob::Pattern;
+use once_cell::sync::Lazy;
+use regex::Regex;
 use std::sync::Arc;
 
+static REGEX_SPLIT_GGUF: Lazy<Regex> =
+  Lazy::new(|| Regex::new(r"^(?P<prefix>.+)-(?P<part>\d{5})-of-(?P<total>\d{5})\.gguf$").unwrap());
+
 #[derive(Debug, PartialEq)]
 pub enum PullCommand {
   ByAlias {
     alias: String,
+    revision: Option<String>,
     force: bool,
   },
   ByRepoFile {
     repo: Repo,
     filename: String,
+    revision: Option<String>,
     force: bool,
   },
 }
@@ -29,14 +37,25 @@ impl TryFrom<Command> for PullCommand {
         alias,
         repo,
         filename,
+        revision,
         force,
       } => {
         let pull_command = match alias {
-          Some(alias) => PullCommand::ByAlias { alias, force },
+          Some(alias) => PullCommand::ByAlias {
+            alias,
+            revision,
+            force,
+          },
           None => match (repo, filename) {
+            (Some(_), Some(filename)) if is_glob(&filename) && Pattern::new(&filename).is_err() => {
+              return Err(CliError::BadRequest(format!(
+                "invalid filename pattern '{filename}'"
+              )))
+            }
             (Some(repo), Some(filename)) => PullCommand::ByRepoFile {
               repo: Repo::try_from(repo)?,
               filename,
+              revision,
               force,
             },
             (repo, filename) => return Err(CliError::BadRequest(format!(
@@ -58,7 +77,11 @@ impl PullCommand {
   #[allow(clippy::result_large_err)]
   pub fn execute(self, service: Arc<dyn AppServiceFn>) -> crate::error::Result<()> {
     match self {
-      PullCommand::ByAlias { alias, force } => {
+      PullCommand::ByAlias {
+        alias,
+        revision,
+        force,
+      } => {
         if !force && service.data_service().find_alias(&alias).is_some() {
           return Err(BodhiError::AliasExists(alias));
         }
@@ -69,14 +92,27 @@ impl PullCommand {
           service.clone(),
           &model.repo,
           &model.filename,
-          REFS_MAIN,
+          revision.as_deref().unwrap_or(DEFAULT_REVISION),
           force,
         )?;
+        // the other parts of a split model file are pulled at the same snapshot
+        for part in split_parts(&model.filename)
+          .iter()
+          .filter(|part| **part != model.filename)
+        {
+          PullCommand::download_file_if_missing(
+            service.clone(),
+            &model.repo,
+            part,
+            &local_model_file.snapshot,
+            force,
+          )?;
+        }
         _ = PullCommand::download_file_if_missing(
           service.clone(),
           &Repo::try_from(model.chat_template.clone())?,
           TOKENIZER_CONFIG_JSON,
-          REFS_MAIN,
+          DEFAULT_REVISION,
           force,
         )?;
         let alias = Alias::new(
@@ -100,19 +136,31 @@ impl PullCommand {
       PullCommand::ByRepoFile {
         repo,
         filename,
+        revision,
         force,
       } => {
-        let local_model_file = service
-          .hub_service()
-          .find_local_file(&repo, &filename, REFS_MAIN)?;
-        match local_model_file {
-          Some(_) if !force => {
-            println!("repo: '{repo}', filename: '{filename}' already exists in $HF_HOME");
-            return Ok(());
-          }
-          _ => {
-            service.hub_service().download(&repo, &filename, force)?;
-            println!("repo: '{repo}', filename: '{filename}' downloaded into $HF_HOME");
+        let revision = revision.as_deref().unwrap_or(DEFAULT_REVISION);
+        let filenames =
+          PullCommand::resolve_filenames(service.clone(), &repo, &filename, revision)?;
+        for filename in filenames {
+          let local_model_file = service.hub_service().find_local_file(
+            &repo,
+            &filename,
+            &revision_snapshot(revision),
+          )?;
+          match local_model_file {
+            Some(_) if !force => {
+              println!("repo: '{repo}', filename: '{filename}' already exists in $HF_HOME");
+            }
+            _ => {
+              let local_model_file = service
+                .hub_service()
+                .download(&repo, &filename, revision, force)?;
+              println!(
+                "repo: '{repo}', filename: '{filename}' downloaded into $HF_HOME at snapshot '{}'",
+                local_model_file.snapshot
+              );
+            }
           }
         }
         Ok(())
@@ -120,16 +168,57 @@ impl PullCommand {
     }
   }
 
+  // expands the filename glob using the files in the repo, and the split model files into all their parts
+  #[allow(clippy::result_large_err)]
+  fn resolve_filenames(
+    service: Arc<dyn AppServiceFn>,
+    repo: &Repo,
+    filename: &str,
+    revision: &str,
+  ) -> crate::error::Result<Vec<String>> {
+    let filenames = if is_glob(filename) {
+      let matched = match Pattern::new(filename) {
+        Ok(pattern) => service
+          .hub_service()
+          .list_repo_files(repo, revision)?
+          .into_iter()
+          .filter(|file| pattern.matches(file))
+          .collect::<Vec<_>>(),
+        Err(_) => vec![],
+      };
+      if matched.is_empty() {
+        return Err(
+          HubServiceError::NoMatchingFiles {
+            pattern: filename.to_string(),
+            repo: repo.to_string(),
+          }
+          .into(),
+        );
+      }
+      matched
+    } else {
+      vec![filename.to_string()]
+    };
+    let mut result = Vec::<String>::new();
+    for part in filenames.iter().flat_map(|filename| split_parts(filename)) {
+      if !result.contains(&part) {
+        result.push(part);
+      }
+    }
+    Ok(result)
+  }
+
   fn download_file_if_missing(
     service: Arc<dyn AppServiceFn>,
     repo: &Repo,
     filename: &str,
-    snapshot: &str,
+    revision: &str,
     force: bool,
   ) -> crate::error::Result<HubFile> {
-    let local_model_file = service
-      .hub_service()
-      .find_local_file(repo, filename, snapshot)?;
+    let local_model_file =
+      service
+        .hub_service()
+        .find_local_file(repo, filename, &revision_snapshot(revision))?;
     match local_model_file {
       Some(local_model_file) if !force => {
         println!(
@@ -139,7 +228,9 @@ impl PullCommand {
         Ok(local_model_file)
       }
       _ => {
-        let local_model_file = service.hub_service().download(repo, filename, force)?;
+        let local_model_file = service
+          .hub_service()
+          .download(repo, filename, revision, force)?;
         println!(
           "repo: '{}', filename: '{}' downloaded into $HF_HOME",
           repo, filename
@@ -150,10 +241,30 @@ impl PullCommand {
   }
 }
 
+fn is_glob(filename: &str) -> bool {
+  filename.contains(['*', '?', '['])
+}
+
+/// All the parts of a split model file, e.g. `model-00001-of-00003.gguf`, or the file itself if not split
+pub(super) fn split_parts(filename: &str) -> Vec<String> {
+  match REGEX_SPLIT_GGUF.captures(filename) {
+    Some(captures) => {
+      let prefix = &captures["prefix"];
+      let total = &captures["total"];
+      let count = total.parse::<u32>().unwrap_or(1);
+      (1..=count)
+        .map(|part| format!("{prefix}-{part:05}-of-{total}.gguf"))
+        .collect()
+    }
+    None => vec![filename.to_string()],
+  }
+}
+
 #[cfg(test)]
 mod test {
+  use super::split_parts;
   use crate::{
-    objs::{Alias, HubFile, RemoteModel, Repo, REFS_MAIN, TOKENIZER_CONFIG_JSON},
+    objs::{Alias, HubFile, RemoteModel, Repo, DEFAULT_REVISION, REFS_MAIN, TOKENIZER_CONFIG_JSON},
     service::{MockDataService, MockEnvServiceFn, MockHubService, ALIASES_DIR},
     test_utils::{app_service_stub, AppServiceStubMock, AppServiceTuple},
     Command, PullCommand,
@@ -170,6 +281,7 @@ mod test {
     let alias = String::from("testalias-exists:instruct");
     let pull = PullCommand::ByAlias {
       alias,
+      revision: None,
       force: false,
     };
     let result = pull.execute(Arc::new(service));
@@ -209,9 +321,10 @@ mod test {
       .with(
         eq(remote_model.repo),
         eq(remote_model.filename.clone()),
+        eq(DEFAULT_REVISION),
         eq(false),
       )
-      .return_once(|_, _, _| Ok(HubFile::testalias()));
+      .return_once(|_, _, _, _| Ok(HubFile::testalias()));
     mock_hub_service
       .expect_find_local_file()
       .with(eq(Repo::llama3()), eq(TOKENIZER_CONFIG_JSON), eq(REFS_MAIN))
@@ -225,6 +338,57 @@ mod test {
       AppServiceStubMock::new(MockEnvServiceFn::new(), mock_hub_service, mock_data_service);
     let pull = PullCommand::ByAlias {
       alias: remote_model.alias,
+      revision: None,
+      force: false,
+    };
+    pull.execute(Arc::new(service))?;
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_pull_by_alias_at_revision_pins_the_snapshot() -> anyhow::Result<()> {
+    let remote_model = RemoteModel::testalias();
+    let mut mock_data_service = MockDataService::new();
+    mock_data_service
+      .expect_find_alias()
+      .with(eq(remote_model.alias.clone()))
+      .returning(|_| None);
+    let remote_clone = remote_model.clone();
+    mock_data_service
+      .expect_find_remote_model()
+      .with(eq(remote_model.alias.clone()))
+      .return_once(move |_| Ok(Some(remote_clone)));
+    let mut mock_hub_service = MockHubService::new();
+    mock_hub_service
+      .expect_find_local_file()
+      .with(
+        eq(remote_model.repo.clone()),
+        eq(remote_model.filename.clone()),
+        eq("refs/v1.0"),
+      )
+      .return_once(|_, _, _| Ok(None));
+    mock_hub_service
+      .expect_download()
+      .with(
+        eq(remote_model.repo.clone()),
+        eq(remote_model.filename.clone()),
+        eq("v1.0"),
+        eq(false),
+      )
+      .return_once(|_, _, _, _| Ok(HubFile::testalias()));
+    mock_hub_service
+      .expect_find_local_file()
+      .with(eq(Repo::llama3()), eq(TOKENIZER_CONFIG_JSON), eq(REFS_MAIN))
+      .return_once(|_, _, _| Ok(Some(HubFile::llama3_tokenizer())));
+    mock_data_service
+      .expect_save_alias()
+      .withf(|alias| alias.snapshot == HubFile::testalias().snapshot)
+      .return_once(|_| Ok(PathBuf::from("ignored")));
+    let service =
+      AppServiceStubMock::new(MockEnvServiceFn::new(), mock_hub_service, mock_data_service);
+    let pull = PullCommand::ByAlias {
+      alias: remote_model.alias,
+      revision: Some("v1.0".to_string()),
       force: false,
     };
     pull.execute(Arc::new(service))?;
@@ -238,6 +402,7 @@ mod test {
     let pull = PullCommand::ByRepoFile {
       repo: repo.clone(),
       filename: filename.to_string(),
+      revision: None,
       force: false,
     };
     let mut mock_hub_service = MockHubService::new();
@@ -247,8 +412,8 @@ mod test {
       .return_once(|_, _, _| Ok(None));
     mock_hub_service
       .expect_download()
-      .with(eq(repo), eq(filename), eq(false))
-      .return_once(|_, _, _| Ok(HubFile::testalias()));
+      .with(eq(repo), eq(filename), eq(DEFAULT_REVISION), eq(false))
+      .return_once(|_, _, _, _| Ok(HubFile::testalias()));
     let mock_data_service = MockDataService::new();
     let service =
       AppServiceStubMock::new(MockEnvServiceFn::new(), mock_hub_service, mock_data_service);
@@ -261,19 +426,23 @@ mod test {
     alias: Some("llama3:instruct".to_string()),
     repo: None,
     filename: None,
+    revision: None,
     force: false,
   }, PullCommand::ByAlias {
     alias: "llama3:instruct".to_string(),
+    revision: None,
     force: false,
   })]
   #[case(Command::Pull {
     alias: None,
     repo: Some("QuantFactory/Meta-Llama-3-8B-Instruct-GGUF".to_string()),
     filename: Some("Meta-Llama-3-8B-Instruct.Q8_0.gguf".to_string()),
+    revision: Some("v1.0".to_string()),
     force: false,
   },
   PullCommand::ByRepoFile {
     repo: Repo::try_from("QuantFactory/Meta-Llama-3-8B-Instruct-GGUF").unwrap(), filename: "Meta-Llama-3-8B-Instruct.Q8_0.gguf".to_string(), 
+    revision: Some("v1.0".to_string()),
     force: false
   })]
   fn test_pull_command_try_from_command(
@@ -292,6 +461,7 @@ mod test {
     let AppServiceTuple(_temp_bodhi, _temp_hf, bodhi_home, _, service) = app_service_stub;
     let command = PullCommand::ByAlias {
       alias: "testalias:instruct".to_string(),
+      revision: None,
       force: false,
     };
     command.execute(Arc::new(service))?;
@@ -314,4 +484,106 @@ chat_template: llama3
     );
     Ok(())
   }
+
+  #[rstest]
+  #[case("model.Q4_K_M.gguf", vec!["model.Q4_K_M.gguf"])]
+  #[case("model.Q4_K_M-00002-of-00003.gguf", vec![
+    "model.Q4_K_M-00001-of-00003.gguf",
+    "model.Q4_K_M-00002-of-00003.gguf",
+    "model.Q4_K_M-00003-of-00003.gguf",
+  ])]
+  fn test_pull_split_parts(#[case] filename: &str, #[case] expected: Vec<&str>) {
+    assert_eq!(expected, split_parts(filename));
+  }
+
+  #[rstest]
+  fn test_pull_by_repo_file_glob_pulls_matching_files_and_parts() -> anyhow::Result<()> {
+    let repo = Repo::try_from("MyFactory/testalias-gguf")?;
+    let pull = PullCommand::ByRepoFile {
+      repo: repo.clone(),
+      filename: "*.Q4_K_M*.gguf".to_string(),
+      revision: None,
+      force: false,
+    };
+    let mut mock_hub_service = MockHubService::new();
+    mock_hub_service
+      .expect_list_repo_files()
+      .with(eq(repo.clone()), eq(DEFAULT_REVISION))
+      .return_once(|_, _| {
+        Ok(vec![
+          "README.md".to_string(),
+          "small.Q4_K_M.gguf".to_string(),
+          "small.Q8_0.gguf".to_string(),
+          "large.Q4_K_M-00001-of-00002.gguf".to_string(),
+        ])
+      });
+    for filename in [
+      "small.Q4_K_M.gguf",
+      "large.Q4_K_M-00001-of-00002.gguf",
+      "large.Q4_K_M-00002-of-00002.gguf",
+    ] {
+      mock_hub_service
+        .expect_find_local_file()
+        .with(eq(repo.clone()), eq(filename), eq(REFS_MAIN))
+        .return_once(|_, _, _| Ok(None));
+      mock_hub_service
+        .expect_download()
+        .with(
+          eq(repo.clone()),
+          eq(filename),
+          eq(DEFAULT_REVISION),
+          eq(false),
+        )
+        .times(1)
+        .return_once(|_, _, _, _| Ok(HubFile::testalias()));
+    }
+    let service = AppServiceStubMock::new(
+      MockEnvServiceFn::new(),
+      mock_hub_service,
+      MockDataService::new(),
+    );
+    pull.execute(Arc::new(service))?;
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_pull_by_repo_file_glob_fails_if_no_match() -> anyhow::Result<()> {
+    let repo = Repo::try_from("MyFactory/testalias-gguf")?;
+    let pull = PullCommand::ByRepoFile {
+      repo: repo.clone(),
+      filename: "*.Q2_K.gguf".to_string(),
+      revision: None,
+      force: false,
+    };
+    let mut mock_hub_service = MockHubService::new();
+    mock_hub_service
+      .expect_list_repo_files()
+      .return_once(|_, _| Ok(vec!["testalias.Q8_0.gguf".to_string()]));
+    let service = AppServiceStubMock::new(
+      MockEnvServiceFn::new(),
+      mock_hub_service,
+      MockDataService::new(),
+    );
+    let result = pull.execute(Arc::new(service));
+    assert_eq!(
+      "no files matching '*.Q2_K.gguf' found in huggingface repo 'MyFactory/testalias-gguf'",
+      result.unwrap_err().to_string()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_pull_command_try_from_invalid_glob() {
+    let result = PullCommand::try_from(Command::Pull {
+      alias: None,
+      repo: Some("MyFactory/testalias-gguf".to_string()),
+      filename: Some("[.gguf".to_string()),
+      revision: None,
+      force: false,
+    });
+    assert_eq!(
+      "invalid filename pattern '[.gguf'",
+      result.unwrap_err().to_string()
+    );
+  }
 }
@@ -0,0 +1,150 @@
+use super::CliError;
+#[cfg(not(test))]
+use crate::interactive::InteractiveRuntime;
+#[cfg(test)]
+use crate::test_utils::MockInteractiveRuntime as InteractiveRuntime;
+use crate::{error::Common, server::DebugBundle, service::AppServiceFn, Command, StdoutWriter};
+use std::{path::PathBuf, sync::Arc};
+
+#[derive(Debug, PartialEq)]
+pub struct ReplayCommand {
+  bundle: PathBuf,
+}
+
+impl TryFrom<Command> for ReplayCommand {
+  type Error = CliError;
+
+  fn try_from(value: Command) -> Result<Self, Self::Error> {
+    match value {
+      Command::Replay { bundle } => Ok(ReplayCommand { bundle }),
+      cmd => Err(CliError::ConvertCommand(
+        cmd.to_string(),
+        "replay".to_string(),
+      )),
+    }
+  }
+}
+
+impl ReplayCommand {
+  #[allow(clippy::result_large_err)]
+  pub fn execute(
+    self,
+    service: Arc<dyn AppServiceFn>,
+    stdout: &mut dyn StdoutWriter,
+  ) -> crate::error::Result<()> {
+    let bundle = DebugBundle::read(&self.bundle)?;
+    let alias = bundle.alias()?;
+    stdout
+      .write(&format!(
+        "replaying request for alias '{}' captured at {} by bodhi {}\noriginal error: {}\n",
+        alias.alias, bundle.created_at, bundle.version, bundle.error
+      ))
+      .map_err(Common::Io)?;
+    let response = InteractiveRuntime::new().replay(alias, service, bundle.request)?;
+    let response = serde_json::to_string_pretty(&response).map_err(Common::SerdeJsonDeserialize)?;
+    stdout.write(&format!("{response}\n")).map_err(Common::Io)?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::ReplayCommand;
+  use crate::{
+    objs::{Alias, ChatCompletionRequest},
+    server::DebugBundle,
+    service::{MockDataService, MockEnvServiceFn, MockHubService, TemplateLimits},
+    test_utils::{AppServiceStubMock, MockInteractiveRuntime},
+    Command, MockStdoutWriter,
+  };
+  use async_openai::types::CreateChatCompletionResponse;
+  use chrono::{TimeZone, Utc};
+  use mockall::predicate::{always, eq};
+  use rstest::rstest;
+  use serde_json::json;
+  use serial_test::serial;
+  use std::{fs, path::PathBuf, sync::Arc};
+  use tempfile::TempDir;
+
+  #[rstest]
+  fn test_replay_command_from_cli() -> anyhow::Result<()> {
+    let result = ReplayCommand::try_from(Command::Replay {
+      bundle: PathBuf::from("bundle.json"),
+    })?;
+    assert_eq!(
+      ReplayCommand {
+        bundle: PathBuf::from("bundle.json")
+      },
+      result
+    );
+    let result = ReplayCommand::try_from(Command::App { ui: false });
+    assert_eq!(
+      "Command 'app' cannot be converted into command 'replay'",
+      result.unwrap_err().to_string()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[serial(InteractiveRuntime)]
+  fn test_replay_command_runs_the_captured_request() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let request = serde_json::from_value::<ChatCompletionRequest>(json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}]
+    }})?;
+    let bundle = DebugBundle {
+      version: "0.0.1".to_string(),
+      created_at: Utc.with_ymd_and_hms(2024, 5, 1, 10, 30, 0).unwrap(),
+      error: "test error".to_string(),
+      request: request.clone(),
+      alias: serde_yaml::to_string(&Alias::testalias())?,
+      model_file: PathBuf::from("/tmp/testalias.Q8_0.gguf"),
+      tokenizer_file: PathBuf::from("/tmp/tokenizer_config.json"),
+      template_limits: TemplateLimits::default(),
+      rendered_prompt: None,
+      logs_tail: vec![],
+    };
+    let bundle_path = temp.path().join("bundle.json");
+    fs::write(&bundle_path, serde_json::to_string(&bundle)?)?;
+    let response = serde_json::from_value::<CreateChatCompletionResponse>(json! {{
+      "id": "chatcmpl-test",
+      "object": "chat.completion",
+      "created": 1714559400,
+      "model": "testalias:instruct",
+      "choices": [{
+        "index": 0,
+        "message": {"role": "assistant", "content": "Tuesday"},
+        "finish_reason": "stop"
+      }]
+    }})?;
+    let response_clone = response.clone();
+    let mut mock_interactive = MockInteractiveRuntime::default();
+    mock_interactive
+      .expect_replay()
+      .with(eq(Alias::testalias()), always(), eq(request))
+      .return_once(move |_, _, _| Ok(response_clone));
+    let ctx = MockInteractiveRuntime::new_context();
+    ctx.expect().return_once(move || mock_interactive);
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .with(eq("replaying request for alias 'testalias:instruct' captured at 2024-05-01 10:30:00 UTC by bodhi 0.0.1\noriginal error: test error\n"))
+      .return_once(|input| Ok(input.len()));
+    let expected = format!("{}\n", serde_json::to_string_pretty(&response)?);
+    stdout
+      .expect_write()
+      .with(eq(expected))
+      .return_once(|input| Ok(input.len()));
+    let service = AppServiceStubMock::new(
+      MockEnvServiceFn::new(),
+      MockHubService::new(),
+      MockDataService::new(),
+    );
+    ReplayCommand {
+      bundle: bundle_path,
+    }
+    .execute(Arc::new(service), &mut stdout)?;
+    Ok(())
+  }
+}
@@ -3,69 +3,247 @@ use super::CliError;
 use crate::interactive::InteractiveRuntime;
 #[cfg(test)]
 use crate::test_utils::MockInteractiveRuntime as InteractiveRuntime;
-use crate::{error::BodhiError, service::AppServiceFn, Command, PullCommand};
-use std::sync::Arc;
+use crate::{
+  error::{BodhiError, Common},
+  interactive::ChatOptions,
+  objs::Alias,
+  service::AppServiceFn,
+  Command, DefaultStdoutWriter, PullCommand, StdinFormat, StdoutWriter,
+};
+use async_openai::types::{
+  ChatCompletionRequestMessage, ChatCompletionRequestUserMessageArgs, CreateChatCompletionResponse,
+};
+use std::{
+  fs,
+  io::{self, Cursor, IsTerminal, Read},
+  path::PathBuf,
+  sync::Arc,
+};
+
+#[derive(Debug, PartialEq)]
 pub enum RunCommand {
-  WithAlias { alias: String },
+  WithAlias {
+    alias: String,
+    save: bool,
+    resume: Option<String>,
+    stats: bool,
+  },
+  /// single completion for the prompt, or the input from stdin, for scripting
+  Batch {
+    alias: String,
+    prompt: Option<String>,
+    output: Option<PathBuf>,
+    json: bool,
+    stdin_format: StdinFormat,
+  },
 }
 
 impl TryFrom<Command> for RunCommand {
   type Error = CliError;
 
   fn try_from(value: Command) -> std::result::Result<Self, Self::Error> {
+    RunCommand::from_command(value, io::stdin().is_terminal())
+  }
+}
+
+impl RunCommand {
+  // runs the interactive chat, unless any of the batch args is given or the input is piped
+  fn from_command(value: Command, interactive_stdin: bool) -> Result<Self, CliError> {
     match value {
-      Command::Run { alias } => Ok(RunCommand::WithAlias { alias }),
+      Command::Run {
+        alias,
+        prompt: None,
+        output: None,
+        json: false,
+        stdin_format: None,
+        save,
+        resume,
+        stats,
+      } if interactive_stdin => Ok(RunCommand::WithAlias {
+        alias,
+        save,
+        resume,
+        stats,
+      }),
+      Command::Run {
+        alias,
+        prompt,
+        output,
+        json,
+        stdin_format,
+        ..
+      } => Ok(RunCommand::Batch {
+        alias,
+        prompt,
+        output,
+        json,
+        stdin_format: stdin_format.unwrap_or_default(),
+      }),
       cmd => Err(CliError::ConvertCommand(cmd.to_string(), "run".to_string())),
     }
   }
-}
 
-impl RunCommand {
   #[allow(clippy::result_large_err)]
   pub fn execute(self, service: Arc<dyn AppServiceFn>) -> crate::error::Result<()> {
     match self {
-      RunCommand::WithAlias { alias } => {
-        let alias = match service.data_service().find_alias(&alias) {
-          Some(alias_obj) => alias_obj,
-          None => match service.data_service().find_remote_model(&alias)? {
-            Some(remote_model) => {
-              let command = PullCommand::ByAlias {
-                alias: remote_model.alias.clone(),
-                force: false,
-              };
-              println!("downloading files to run model alias '{}'", remote_model.alias);
-              command.execute(service.clone())?;
-              match service.data_service().find_alias(&alias) {
-                Some(alias_obj) => alias_obj,
-                None => return Err(BodhiError::AliasNotFound(alias)),
-              }
-            }
-            None => return Err(BodhiError::AliasNotFound(alias)),
-          },
+      RunCommand::WithAlias {
+        alias,
+        save,
+        resume,
+        stats,
+      } => {
+        let alias = RunCommand::find_or_pull_alias(alias, service.clone())?;
+        let options = ChatOptions {
+          save,
+          resume,
+          stats,
         };
-        InteractiveRuntime::new().execute(alias, service)?;
+        InteractiveRuntime::new().execute(alias, service, options)?;
         Ok(())
       }
+      RunCommand::Batch {
+        alias,
+        prompt,
+        output,
+        json,
+        stdin_format,
+      } => {
+        // the prompt is used in place of the input from stdin
+        let (mut input, stdin_format): (Box<dyn Read>, StdinFormat) = match prompt {
+          Some(prompt) => (Box::new(Cursor::new(prompt)), StdinFormat::Text),
+          None => (Box::new(io::stdin()), stdin_format),
+        };
+        RunCommand::execute_batch(
+          alias,
+          output,
+          json,
+          stdin_format,
+          service,
+          &mut input,
+          &mut DefaultStdoutWriter::default(),
+        )
+      }
+    }
+  }
+
+  #[allow(clippy::result_large_err)]
+  fn execute_batch(
+    alias: String,
+    output: Option<PathBuf>,
+    json: bool,
+    stdin_format: StdinFormat,
+    service: Arc<dyn AppServiceFn>,
+    input: &mut dyn Read,
+    stdout: &mut dyn StdoutWriter,
+  ) -> crate::error::Result<()> {
+    let alias = RunCommand::find_or_pull_alias(alias, service.clone())?;
+    let mut content = String::new();
+    input.read_to_string(&mut content).map_err(Common::Io)?;
+    let messages = parse_messages(&content, stdin_format)?;
+    let response = InteractiveRuntime::new().complete(alias, service, messages)?;
+    let result = format_response(&response, json)?;
+    match output {
+      Some(output) => fs::write(&output, result).map_err(|err| Common::IoFile {
+        source: err,
+        path: output.display().to_string(),
+      })?,
+      None => {
+        stdout.write(&result).map_err(Common::Io)?;
+      }
+    }
+    Ok(())
+  }
+
+  #[allow(clippy::result_large_err)]
+  fn find_or_pull_alias(
+    alias: String,
+    service: Arc<dyn AppServiceFn>,
+  ) -> crate::error::Result<Alias> {
+    if let Some(alias_obj) = service.data_service().find_alias(&alias) {
+      return Ok(alias_obj);
+    }
+    let Some(remote_model) = service.data_service().find_remote_model(&alias)? else {
+      return Err(BodhiError::AliasNotFound(alias));
+    };
+    let command = PullCommand::ByAlias {
+      alias: remote_model.alias.clone(),
+      revision: None,
+      force: false,
+    };
+    println!(
+      "downloading files to run model alias '{}'",
+      remote_model.alias
+    );
+    command.execute(service.clone())?;
+    match service.data_service().find_alias(&alias) {
+      Some(alias_obj) => Ok(alias_obj),
+      None => Err(BodhiError::AliasNotFound(alias)),
     }
   }
 }
 
+#[allow(clippy::result_large_err)]
+fn parse_messages(
+  content: &str,
+  stdin_format: StdinFormat,
+) -> crate::error::Result<Vec<ChatCompletionRequestMessage>> {
+  let messages = match stdin_format {
+    StdinFormat::Text => vec![ChatCompletionRequestMessage::User(
+      ChatCompletionRequestUserMessageArgs::default()
+        .content(content.trim_end())
+        .build()
+        .map_err(BodhiError::BuildError)?,
+    )],
+    StdinFormat::MessagesJson => serde_json::from_str::<Vec<ChatCompletionRequestMessage>>(content)
+      .map_err(|err| Common::SerdeJsonSerialize {
+        source: err,
+        value: content.to_string(),
+      })?,
+  };
+  Ok(messages)
+}
+
+#[allow(clippy::result_large_err)]
+fn format_response(
+  response: &CreateChatCompletionResponse,
+  json: bool,
+) -> crate::error::Result<String> {
+  if json {
+    let result = serde_json::to_string_pretty(response).map_err(Common::SerdeJsonDeserialize)?;
+    return Ok(format!("{result}\n"));
+  }
+  let content = response
+    .choices
+    .first()
+    .and_then(|choice| choice.message.content.clone())
+    .unwrap_or_default();
+  Ok(format!("{content}\n"))
+}
+
 #[cfg(test)]
 mod test {
   use crate::{
-    objs::{Alias, HubFile, RemoteModel, REFS_MAIN, TOKENIZER_CONFIG_JSON},
+    interactive::ChatOptions,
+    objs::{Alias, HubFile, RemoteModel, DEFAULT_REVISION, REFS_MAIN, TOKENIZER_CONFIG_JSON},
     service::{MockDataService, MockEnvServiceFn, MockHubService},
     test_utils::{AppServiceStubMock, MockInteractiveRuntime},
-    Repo, RunCommand,
+    Command, MockStdoutWriter, Repo, RunCommand, StdinFormat,
   };
+  use async_openai::types::CreateChatCompletionResponse;
   use mockall::predicate::{always, eq};
   use rstest::rstest;
-  use std::{path::PathBuf, sync::Arc};
+  use serde_json::json;
+  use serial_test::serial;
+  use std::{fs, path::PathBuf, sync::Arc};
+  use tempfile::TempDir;
 
   #[rstest]
   fn test_run_with_alias_return_error_if_alias_not_found() -> anyhow::Result<()> {
     let run_command = RunCommand::WithAlias {
       alias: "testalias:instruct".to_string(),
+      save: false,
+      resume: None,
+      stats: false,
     };
     let mut mock_data_service = MockDataService::new();
     mock_data_service
@@ -93,9 +271,13 @@ Run `bodhi list -r` to see list of pre-configured model aliases
   }
 
   #[rstest]
+  #[serial(InteractiveRuntime)]
   fn test_run_with_alias_downloads_a_known_alias_if_not_configured() -> anyhow::Result<()> {
     let run_command = RunCommand::WithAlias {
       alias: "testalias:instruct".to_string(),
+      save: false,
+      resume: None,
+      stats: false,
     };
     let mut mock_data_service = MockDataService::default();
     mock_data_service
@@ -122,9 +304,10 @@ Run `bodhi list -r` to see list of pre-configured model aliases
       .with(
         eq(Repo::testalias()),
         eq("testalias.Q8_0.gguf"),
+        eq(DEFAULT_REVISION),
         eq(false),
       )
-      .return_once(|_, _, _| Ok(HubFile::testalias()));
+      .return_once(|_, _, _, _| Ok(HubFile::testalias()));
 
     mock_hub_service
       .expect_find_local_file()
@@ -132,8 +315,13 @@ Run `bodhi list -r` to see list of pre-configured model aliases
       .return_once(|_, _, _| Ok(None));
     mock_hub_service
       .expect_download()
-      .with(eq(Repo::llama3()), eq(TOKENIZER_CONFIG_JSON), eq(false))
-      .return_once(|_, _, _| Ok(HubFile::llama3_tokenizer()));
+      .with(
+        eq(Repo::llama3()),
+        eq(TOKENIZER_CONFIG_JSON),
+        eq(DEFAULT_REVISION),
+        eq(false),
+      )
+      .return_once(|_, _, _, _| Ok(HubFile::llama3_tokenizer()));
     mock_data_service
       .expect_save_alias()
       .with(eq(Alias::testalias()))
@@ -145,8 +333,8 @@ Run `bodhi list -r` to see list of pre-configured model aliases
     let mut mock_interactive = MockInteractiveRuntime::default();
     mock_interactive
       .expect_execute()
-      .with(eq(Alias::testalias()), always())
-      .return_once(|_, _| Ok(()));
+      .with(eq(Alias::testalias()), always(), eq(ChatOptions::default()))
+      .return_once(|_, _, _| Ok(()));
     let service =
       AppServiceStubMock::new(MockEnvServiceFn::new(), mock_hub_service, mock_data_service);
     let ctx = MockInteractiveRuntime::new_context();
@@ -154,4 +342,156 @@ Run `bodhi list -r` to see list of pre-configured model aliases
     run_command.execute(Arc::new(service))?;
     Ok(())
   }
+
+  #[rstest]
+  #[case(None, None, None, false, true, RunCommand::WithAlias {
+    alias: "testalias:instruct".to_string(),
+    save: false,
+    resume: None,
+    stats: false,
+  })]
+  #[case(None, None, None, false, false, RunCommand::Batch {
+    alias: "testalias:instruct".to_string(),
+    prompt: None,
+    output: None,
+    json: false,
+    stdin_format: StdinFormat::Text,
+  })]
+  #[case(Some("What day comes after Monday?".to_string()), None, None, false, true, RunCommand::Batch {
+    alias: "testalias:instruct".to_string(),
+    prompt: Some("What day comes after Monday?".to_string()),
+    output: None,
+    json: false,
+    stdin_format: StdinFormat::Text,
+  })]
+  #[case(None, Some(PathBuf::from("out.txt")), None, false, true, RunCommand::Batch {
+    alias: "testalias:instruct".to_string(),
+    prompt: None,
+    output: Some(PathBuf::from("out.txt")),
+    json: false,
+    stdin_format: StdinFormat::Text,
+  })]
+  #[case(None, None, Some(StdinFormat::MessagesJson), true, true, RunCommand::Batch {
+    alias: "testalias:instruct".to_string(),
+    prompt: None,
+    output: None,
+    json: true,
+    stdin_format: StdinFormat::MessagesJson,
+  })]
+  fn test_run_command_from_command(
+    #[case] prompt: Option<String>,
+    #[case] output: Option<PathBuf>,
+    #[case] stdin_format: Option<StdinFormat>,
+    #[case] json: bool,
+    #[case] interactive_stdin: bool,
+    #[case] expected: RunCommand,
+  ) -> anyhow::Result<()> {
+    let command = Command::Run {
+      alias: "testalias:instruct".to_string(),
+      prompt,
+      output,
+      json,
+      stdin_format,
+      save: false,
+      resume: None,
+      stats: false,
+    };
+    assert_eq!(
+      expected,
+      RunCommand::from_command(command, interactive_stdin)?
+    );
+    Ok(())
+  }
+
+  fn completion_response() -> CreateChatCompletionResponse {
+    serde_json::from_value(json! {{
+      "id": "testid",
+      "model": "testalias:instruct",
+      "choices": [{
+        "index": 0,
+        "message": {"role": "assistant", "content": "Tuesday"},
+      }],
+      "created": 1704067200,
+      "object": "chat.completion",
+      "usage": {"prompt_tokens": 12, "completion_tokens": 2, "total_tokens": 14},
+    }})
+    .unwrap()
+  }
+
+  fn service_with_alias() -> AppServiceStubMock {
+    let mut mock_data_service = MockDataService::new();
+    mock_data_service
+      .expect_find_alias()
+      .with(eq("testalias:instruct"))
+      .return_once(|_| Some(Alias::testalias()));
+    AppServiceStubMock::new(
+      MockEnvServiceFn::new(),
+      MockHubService::new(),
+      mock_data_service,
+    )
+  }
+
+  #[rstest]
+  #[serial(InteractiveRuntime)]
+  fn test_run_batch_text_prompt_to_stdout() -> anyhow::Result<()> {
+    let mut mock_interactive = MockInteractiveRuntime::default();
+    mock_interactive
+      .expect_complete()
+      .withf(|alias, _, messages| {
+        alias == &Alias::testalias()
+          && serde_json::to_value(messages).unwrap()
+            == json! {[{"role": "user", "content": "What day comes after Monday?"}]}
+      })
+      .return_once(|_, _, _| Ok(completion_response()));
+    let ctx = MockInteractiveRuntime::new_context();
+    ctx.expect().return_once(move || mock_interactive);
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .with(eq("Tuesday\n"))
+      .return_once(|input| Ok(input.len()));
+    RunCommand::execute_batch(
+      "testalias:instruct".to_string(),
+      None,
+      false,
+      StdinFormat::Text,
+      Arc::new(service_with_alias()),
+      &mut "What day comes after Monday?\n".as_bytes(),
+      &mut stdout,
+    )?;
+    Ok(())
+  }
+
+  #[rstest]
+  #[serial(InteractiveRuntime)]
+  fn test_run_batch_messages_json_to_output_file() -> anyhow::Result<()> {
+    let input = json! {[
+      {"role": "system", "content": "You are a helpful assistant."},
+      {"role": "user", "content": "What day comes after Monday?"},
+    ]};
+    let expected_messages = input.clone();
+    let mut mock_interactive = MockInteractiveRuntime::default();
+    mock_interactive
+      .expect_complete()
+      .withf(move |_, _, messages| serde_json::to_value(messages).unwrap() == expected_messages)
+      .return_once(|_, _, _| Ok(completion_response()));
+    let ctx = MockInteractiveRuntime::new_context();
+    ctx.expect().return_once(move || mock_interactive);
+    let temp = TempDir::new()?;
+    let output = temp.path().join("out.json");
+    RunCommand::execute_batch(
+      "testalias:instruct".to_string(),
+      Some(output.clone()),
+      true,
+      StdinFormat::MessagesJson,
+      Arc::new(service_with_alias()),
+      &mut input.to_string().as_bytes(),
+      &mut MockStdoutWriter::default(),
+    )?;
+    let result =
+      serde_json::from_str::<CreateChatCompletionResponse>(&fs::read_to_string(output)?)?;
+    assert_eq!(completion_response(), result);
+    assert_eq!(14, result.usage.unwrap().total_tokens);
+    Ok(())
+  }
 }
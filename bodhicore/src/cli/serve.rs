@@ -1,18 +1,44 @@
-use super::{CliError, Command};
+use super::{daemon, CliError, Command, ServerInfo};
 use crate::{
-  db::{DbPool, DbService, DbServiceFn, TimeService},
+  backend::{relaunch, select_backend},
+  db::{DbPool, TimeService},
   error::Common,
-  server::{build_routes, build_server_handle, shutdown_signal, ServerHandle, ShutdownCallback},
-  service::AppServiceFn,
-  BodhiError, SharedContextRw, SharedContextRwFn,
+  objs::{Alias, ObjError},
+  server::{
+    build_routes, build_server_handle, check_aliases, run_watchdog, shutdown_signal,
+    static_dir_router, watch_aliases, AliasQuarantine, InferenceMonitor, ResponseCache,
+    ServerHandle, ShutdownCallback,
+  },
+  service::{AppServiceFn, HubServiceError},
+  BodhiError, IsolatedContextRw, SharedContextRw, SharedContextRwFn,
 };
 use axum::Router;
-use std::sync::Arc;
+use chrono::Utc;
+use llama_server_bindings::{GptParams, GptParamsBuilder};
+use std::{
+  io,
+  path::{Path, PathBuf},
+  sync::Arc,
+  time::Duration,
+};
 use tokio::{runtime::Builder, sync::oneshot::Sender, task::JoinHandle};
 
+// loading the settings, the database migrations and the alias check run before the server is ready
+static DETACH_READY_TIMEOUT_SECS: u64 = 60;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum ServeCommand {
-  ByParams { host: String, port: u16 },
+  ByParams {
+    host: String,
+    port: u16,
+    port_range: u16,
+    capture_on_error: bool,
+    uds: Option<PathBuf>,
+    ui_dir: Option<PathBuf>,
+    model: Option<String>,
+    strict: bool,
+    detach: bool,
+  },
 }
 
 impl TryFrom<Command> for ServeCommand {
@@ -20,7 +46,27 @@ impl TryFrom<Command> for ServeCommand {
 
   fn try_from(value: Command) -> Result<Self, Self::Error> {
     match value {
-      Command::Serve { host, port } => Ok(ServeCommand::ByParams { host, port }),
+      Command::Serve {
+        host,
+        port,
+        port_range,
+        capture_on_error,
+        uds,
+        ui_dir,
+        model,
+        strict,
+        detach,
+      } => Ok(ServeCommand::ByParams {
+        host,
+        port,
+        port_range,
+        capture_on_error,
+        uds,
+        ui_dir,
+        model,
+        strict,
+        detach,
+      }),
       cmd => Err(CliError::ConvertCommand(
         cmd.to_string(),
         "serve".to_string(),
@@ -45,9 +91,16 @@ impl ShutdownCallback for ShutdownContextCallback {
 pub struct ServerShutdownHandle {
   join_handle: JoinHandle<Result<(), BodhiError>>,
   shutdown: Sender<()>,
+  bodhi_home: PathBuf,
+  port: u16,
 }
 
 impl ServerShutdownHandle {
+  /// The port the server is listening on, can differ from the requested port with `--port-range`
+  pub fn port(&self) -> u16 {
+    self.port
+  }
+
   pub async fn shutdown_on_ctrlc(self) -> crate::error::Result<()> {
     shutdown_signal().await;
     self.shutdown().await?;
@@ -55,82 +108,170 @@ impl ServerShutdownHandle {
   }
 
   pub async fn shutdown(self) -> crate::error::Result<()> {
+    #[cfg(unix)]
+    crate::server::notify_stopping();
     match self.shutdown.send(()) {
       Ok(()) => {}
       Err(err) => tracing::warn!(?err, "error sending shutdown signal on shutdown channel"),
     };
-    (self.join_handle.await.map_err(Common::Join)?)?;
+    let result = self.join_handle.await.map_err(Common::Join)?;
+    ServerInfo::remove(&self.bodhi_home, std::process::id());
+    result?;
     Ok(())
   }
 }
 
 impl ServeCommand {
   pub fn execute(&self, service: Arc<dyn AppServiceFn>) -> crate::error::Result<()> {
-    match self {
-      ServeCommand::ByParams { host, port } => {
-        self.execute_by_params(host, *port, service, None)?;
-        Ok(())
-      }
+    let ServeCommand::ByParams { detach, .. } = self;
+    if *detach {
+      let env_service = service.env_service();
+      let info = daemon::spawn_detached(
+        &env_service.bodhi_home(),
+        &env_service.logs_dir(),
+        Duration::from_secs(DETACH_READY_TIMEOUT_SECS),
+      )?;
+      println!(
+        "server started in the background with pid {}, stop it using `bodhi stop`",
+        info.pid
+      );
+      return Ok(());
     }
-  }
-
-  pub async fn aexecute(
-    &self,
-    service: Arc<dyn AppServiceFn>,
-    static_router: Option<Router>,
-  ) -> crate::error::Result<ServerShutdownHandle> {
-    match self {
-      ServeCommand::ByParams { host, port } => {
-        let handle = self
-          .aexecute_by_params(host, *port, service, static_router)
-          .await?;
-        Ok(handle)
-      }
-    }
-  }
-
-  fn execute_by_params(
-    &self,
-    host: &str,
-    port: u16,
-    service: Arc<dyn AppServiceFn>,
-    static_router: Option<Router>,
-  ) -> crate::error::Result<()> {
     let runtime = Builder::new_multi_thread()
       .enable_all()
       .build()
       .map_err(Common::from)?;
     runtime.block_on(async move {
-      let handle = self
-        .aexecute_by_params(host, port, service, static_router)
-        .await?;
+      let handle = self.aexecute(service, None).await?;
       handle.shutdown_on_ctrlc().await?;
       Ok::<(), BodhiError>(())
     })?;
     Ok(())
   }
 
-  async fn aexecute_by_params(
+  pub async fn aexecute(
     &self,
-    host: &str,
-    port: u16,
     service: Arc<dyn AppServiceFn>,
     static_router: Option<Router>,
   ) -> crate::error::Result<ServerShutdownHandle> {
-    let dbpath = service.env_service().db_path();
-    let pool = DbPool::connect(&format!("sqlite:{}", dbpath.display())).await?;
-    let db_service = DbService::new(pool, Arc::new(TimeService));
+    let ServeCommand::ByParams {
+      host,
+      port,
+      port_range,
+      capture_on_error,
+      uds,
+      ui_dir,
+      model,
+      strict,
+      ..
+    } = self;
+    let uds = uds.as_deref();
+    let static_router = match ui_dir.clone().or_else(|| service.env_service().ui_dir()) {
+      Some(ui_dir) => {
+        if !ui_dir.is_dir() {
+          return Err(
+            Common::IoFile {
+              source: io::Error::new(io::ErrorKind::NotFound, "ui directory not found"),
+              path: ui_dir.display().to_string(),
+            }
+            .into(),
+          );
+        }
+        tracing::info!(ui_dir = %ui_dir.display(), "serving the web UI from the directory");
+        Some(static_dir_router(&ui_dir))
+      }
+      None => static_router,
+    };
+    // loading the model before accepting the requests avoids the slow first request
+    let preload = match model
+      .clone()
+      .or_else(|| service.env_service().default_alias())
+    {
+      Some(alias) => {
+        tracing::info!(%alias, "loading the model alias on startup");
+        Some(preload_params(service.as_ref(), &alias)?)
+      }
+      None => None,
+    };
+    let requested = preload
+      .as_ref()
+      .and_then(|(alias, _)| alias.context_params.backend)
+      .unwrap_or_else(|| service.env_service().backend());
+    let backend = select_backend(requested, service.env_service().backend_variant())?;
+    if let Some(variant) = &backend.variant {
+      relaunch(backend.backend, variant)?;
+    }
+    tracing::info!(backend = %backend.backend, "running the models using the backend");
+    let db_service =
+      DbPool::connect_service(&service.env_service().database_url(), Arc::new(TimeService)).await?;
     db_service.migrate().await?;
 
+    let bodhi_home = service.env_service().bodhi_home();
     let ServerHandle {
       server,
       shutdown,
       ready_rx,
-    } = build_server_handle(host, port);
+    } = build_server_handle(host, *port);
+    #[cfg(unix)]
+    let activated = crate::server::activated_listener();
+    #[cfg(not(unix))]
+    let activated = None;
+    let activated_used = activated.is_some();
+    let server = server
+      .with_port_range(*port_range)
+      .with_drain_timeout(Duration::from_secs(
+        service.env_service().drain_timeout_secs(),
+      ))
+      .with_uds(uds.map(Path::to_path_buf))
+      .with_activated_listener(activated);
 
-    let ctx = SharedContextRw::new_shared_rw(None).await?;
-    let ctx: Arc<dyn SharedContextRwFn> = Arc::new(ctx);
-    let app = build_routes(ctx.clone(), service, Arc::new(db_service), static_router);
+    let (gpt_params, warmup) = match preload {
+      Some((alias, gpt_params)) => (
+        Some(gpt_params),
+        alias.context_params.warmup.unwrap_or_default(),
+      ),
+      None => (None, false),
+    };
+    let ctx: Arc<dyn SharedContextRwFn> = if service.env_service().isolate_inference() {
+      Arc::new(IsolatedContextRw::start(gpt_params, *strict, backend.backend).await?)
+    } else {
+      Arc::new(SharedContextRw::new_with_options(gpt_params, *strict, backend.backend).await?)
+    };
+    if warmup {
+      if let Err(err) = ctx.warmup().await {
+        tracing::warn!(?err, "error warming up the model");
+      }
+    }
+    let quarantine = Arc::new(AliasQuarantine::default());
+    tokio::spawn(check_aliases(service.clone(), quarantine.clone()));
+    let response_cache = Arc::new(ResponseCache::new(
+      service.env_service().response_cache_settings(),
+    ));
+    tokio::spawn(watch_aliases(
+      service.clone(),
+      ctx.clone(),
+      quarantine.clone(),
+      response_cache.clone(),
+    ));
+    let inference_monitor = Arc::new(InferenceMonitor::default());
+    let watchdog_secs = service.env_service().watchdog_secs();
+    if watchdog_secs > 0 {
+      tokio::spawn(run_watchdog(
+        ctx.clone(),
+        inference_monitor.clone(),
+        Duration::from_secs(watchdog_secs),
+      ));
+    }
+    let app = build_routes(
+      ctx.clone(),
+      service,
+      db_service,
+      quarantine,
+      inference_monitor,
+      response_cache,
+      *capture_on_error,
+      static_router,
+    );
 
     let join_handle = tokio::spawn(async move {
       let callback = Box::new(ShutdownContextCallback { ctx });
@@ -142,34 +283,124 @@ impl ServeCommand {
         }
       }
     });
-    match ready_rx.await {
-      Ok(()) => {
-        println!("server started on http://{host}:{port}");
+    let bound_port = match ready_rx.await {
+      Ok(bound_port) => {
+        #[cfg(unix)]
+        crate::server::notify_ready();
+        let listening_on = match (activated_used, uds) {
+          (true, _) => "the socket passed by systemd".to_string(),
+          (false, Some(uds)) => format!("unix domain socket {}", uds.display()),
+          (false, None) => format!("http://{host}:{bound_port}"),
+        };
+        println!("server started on {listening_on}");
+        // keeps the pid file of the server already running, e.g. started using `--detach`
+        if let Some(other) = daemon::running_server(&bodhi_home) {
+          tracing::info!(
+            pid = other.pid,
+            "bodhi server already running, not writing the pid file"
+          );
+        } else {
+          let info = ServerInfo {
+            pid: std::process::id(),
+            host: host.to_string(),
+            port: bound_port,
+            uds: uds.map(Path::to_path_buf),
+            started_at: Utc::now(),
+          };
+          if let Err(err) = info.write(&bodhi_home) {
+            tracing::warn!(?err, "error writing the pid file");
+          }
+        }
+        bound_port
       }
-      Err(err) => tracing::warn!(?err, "ready channel closed before could receive signal"),
-    }
+      Err(err) => {
+        tracing::warn!(?err, "ready channel closed before could receive signal");
+        *port
+      }
+    };
     Ok(ServerShutdownHandle {
       join_handle,
       shutdown,
+      bodhi_home,
+      port: bound_port,
     })
   }
 }
 
+#[allow(clippy::result_large_err)]
+fn preload_params(
+  service: &dyn AppServiceFn,
+  alias: &str,
+) -> crate::error::Result<(Alias, GptParams)> {
+  let alias = service
+    .data_service()
+    .find_alias(alias)
+    .ok_or_else(|| BodhiError::AliasNotFound(alias.to_string()))?;
+  let model_file = service
+    .hub_service()
+    .find_local_file(&alias.repo, &alias.filename, &alias.snapshot)?
+    .ok_or_else(|| {
+      let filepath =
+        service
+          .hub_service()
+          .model_file_path(&alias.repo, &alias.filename, &alias.snapshot);
+      let dirname = filepath
+        .parent()
+        .map(|dir| dir.display().to_string())
+        .unwrap_or_default();
+      let hf_home = service.env_service().hf_home().display().to_string();
+      HubServiceError::FileMissing {
+        filename: alias.filename.clone(),
+        dirname: dirname
+          .strip_prefix(&hf_home)
+          .unwrap_or(&dirname)
+          .to_string(),
+      }
+    })?;
+  let mut gpt_params = GptParamsBuilder::default()
+    .model(model_file.path().display().to_string())
+    .build()
+    .map_err(ObjError::from)?;
+  alias.context_params.update(&mut gpt_params);
+  Ok((alias, gpt_params))
+}
+
 #[cfg(test)]
 mod test {
-  use super::{Command, ServeCommand};
+  use super::{preload_params, Command, ServeCommand};
+  use crate::{
+    objs::{Alias, HubFile},
+    service::{MockDataService, MockEnvServiceFn, MockHubService},
+    test_utils::AppServiceStubMock,
+  };
+  use mockall::predicate::eq;
   use rstest::rstest;
+  use std::path::PathBuf;
 
   #[rstest]
   fn test_serve_command_from_serve() -> anyhow::Result<()> {
     let cmd = Command::Serve {
       host: "localhost".to_string(),
       port: 1135,
+      port_range: 10,
+      capture_on_error: true,
+      uds: Some(PathBuf::from("/tmp/bodhi.sock")),
+      ui_dir: Some(PathBuf::from("app/out")),
+      model: Some("tinyllama:instruct".to_string()),
+      strict: true,
+      detach: true,
     };
     let result = ServeCommand::try_from(cmd)?;
     let expected = ServeCommand::ByParams {
       host: "localhost".to_string(),
       port: 1135,
+      port_range: 10,
+      capture_on_error: true,
+      uds: Some(PathBuf::from("/tmp/bodhi.sock")),
+      ui_dir: Some(PathBuf::from("app/out")),
+      model: Some("tinyllama:instruct".to_string()),
+      strict: true,
+      detach: true,
     };
     assert_eq!(expected, result);
     Ok(())
@@ -180,6 +411,7 @@ mod test {
     let cmd = Command::List {
       remote: false,
       models: false,
+      whats_new: false,
     };
     let result = ServeCommand::try_from(cmd);
     assert!(result.is_err());
@@ -189,4 +421,41 @@ mod test {
     );
     Ok(())
   }
+
+  #[rstest]
+  fn test_serve_preload_params_of_alias() -> anyhow::Result<()> {
+    let alias = Alias::testalias();
+    let mut data_service = MockDataService::new();
+    data_service
+      .expect_find_alias()
+      .with(eq("testalias:instruct"))
+      .return_once(move |_| Some(alias));
+    let mut hub_service = MockHubService::new();
+    hub_service
+      .expect_find_local_file()
+      .return_once(|_, _, _| Ok(Some(HubFile::testalias())));
+    let service = AppServiceStubMock::new(MockEnvServiceFn::new(), hub_service, data_service);
+    let (alias, gpt_params) = preload_params(&service, "testalias:instruct")?;
+    assert_eq!("testalias:instruct", alias.alias);
+    assert_eq!(
+      HubFile::testalias().path().display().to_string(),
+      gpt_params.model
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_serve_preload_params_alias_not_found() -> anyhow::Result<()> {
+    let mut data_service = MockDataService::new();
+    data_service.expect_find_alias().return_once(|_| None);
+    let service =
+      AppServiceStubMock::new(MockEnvServiceFn::new(), MockHubService::new(), data_service);
+    let result = preload_params(&service, "notexists:instruct");
+    assert!(result.is_err());
+    assert!(result
+      .unwrap_err()
+      .to_string()
+      .starts_with("model alias 'notexists:instruct' not found"));
+    Ok(())
+  }
 }
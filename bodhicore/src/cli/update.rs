@@ -0,0 +1,310 @@
+use super::{pull::split_parts, CliError, StdoutWriter};
+use crate::{
+  error::{BodhiError, Common},
+  objs::{Alias, DEFAULT_REVISION},
+  service::{AppServiceFn, DataServiceError},
+  Command,
+};
+use dialoguer::{theme::ColorfulTheme, Confirm};
+use std::{io, sync::Arc};
+
+#[derive(Debug, PartialEq)]
+pub struct UpdateCommand {
+  /// the alias to update, or all the aliases if not set
+  alias: Option<String>,
+  yes: bool,
+}
+
+impl TryFrom<Command> for UpdateCommand {
+  type Error = CliError;
+
+  fn try_from(value: Command) -> Result<Self, Self::Error> {
+    match value {
+      Command::Update { alias, all, yes } => match (alias, all) {
+        (Some(alias), false) => Ok(UpdateCommand {
+          alias: Some(alias),
+          yes,
+        }),
+        (None, true) => Ok(UpdateCommand { alias: None, yes }),
+        _ => Err(CliError::BadRequest(
+          "either the model alias or --all should be provided".to_string(),
+        )),
+      },
+      cmd => Err(CliError::ConvertCommand(
+        cmd.to_string(),
+        "update".to_string(),
+      )),
+    }
+  }
+}
+
+impl UpdateCommand {
+  // checks every alias before updating any of them, and fails with the last error if any of them could not be checked
+  #[allow(clippy::result_large_err)]
+  pub fn execute(
+    self,
+    service: Arc<dyn AppServiceFn>,
+    stdout: &mut dyn StdoutWriter,
+  ) -> crate::error::Result<()> {
+    let aliases = match &self.alias {
+      Some(alias) => vec![service
+        .data_service()
+        .find_alias(alias)
+        .ok_or_else(|| DataServiceError::AliasNotExists(alias.clone()))?],
+      None => service.data_service().list_aliases()?,
+    };
+    let mut result = Ok(());
+    let mut updates = Vec::<(Alias, String)>::new();
+    for alias in aliases {
+      let latest =
+        service
+          .hub_service()
+          .latest_snapshot(&alias.repo, &alias.filename, DEFAULT_REVISION);
+      let message = match latest {
+        Ok(latest) if latest == alias.snapshot => format!(
+          "alias '{}': up to date at snapshot '{}'\n",
+          alias.alias,
+          short_sha(&alias.snapshot)
+        ),
+        Ok(latest) => {
+          let message = format!(
+            "alias '{}': newer snapshot of '{}' available in repo '{}', snapshot '{}' -> '{}'\n",
+            alias.alias,
+            alias.filename,
+            alias.repo,
+            short_sha(&alias.snapshot),
+            short_sha(&latest)
+          );
+          updates.push((alias, latest));
+          message
+        }
+        Err(err) => {
+          let message = format!(
+            "alias '{}': could not check for updates: {err}\n",
+            alias.alias
+          );
+          result = Err(BodhiError::from(err));
+          message
+        }
+      };
+      stdout.write(&message).map_err(Common::Io)?;
+    }
+    if updates.is_empty() {
+      return result;
+    }
+    if !self.yes && !confirm(updates.len())? {
+      stdout
+        .write("update cancelled, no model alias was changed\n")
+        .map_err(Common::Io)?;
+      return result;
+    }
+    for (mut alias, latest) in updates {
+      for filename in split_parts(&alias.filename) {
+        service
+          .hub_service()
+          .download(&alias.repo, &filename, &latest, false)?;
+      }
+      alias.snapshot = latest;
+      service.data_service().save_alias(&alias)?;
+      stdout
+        .write(&format!(
+          "alias '{}': updated to snapshot '{}'\n",
+          alias.alias,
+          short_sha(&alias.snapshot)
+        ))
+        .map_err(Common::Io)?;
+    }
+    result
+  }
+}
+
+#[allow(clippy::result_large_err)]
+fn confirm(count: usize) -> crate::error::Result<bool> {
+  let confirmed = Confirm::with_theme(&ColorfulTheme::default())
+    .with_prompt(format!(
+      "Download and update {count} model alias(es) to the newer snapshot?"
+    ))
+    .default(false)
+    .interact()
+    .map_err(|err| Common::Io(io::Error::from(err)))?;
+  Ok(confirmed)
+}
+
+fn short_sha(sha: &str) -> &str {
+  sha.get(..8).unwrap_or(sha)
+}
+
+#[cfg(test)]
+mod test {
+  use super::UpdateCommand;
+  use crate::{
+    objs::{Alias, HubFile, DEFAULT_REVISION},
+    service::{HubServiceError, MockDataService, MockEnvServiceFn, MockHubService},
+    test_utils::{AppServiceStubMock, SNAPSHOT},
+    Command, MockStdoutWriter, Repo,
+  };
+  use mockall::predicate::eq;
+  use rstest::rstest;
+  use std::{path::PathBuf, sync::Arc};
+
+  const LATEST: &str = "9f8e7d6c5b4a39281706f5e4d3c2b1a098765432";
+
+  #[rstest]
+  #[case(Command::Update { alias: Some("testalias:instruct".to_string()), all: false, yes: false },
+    UpdateCommand { alias: Some("testalias:instruct".to_string()), yes: false })]
+  #[case(Command::Update { alias: None, all: true, yes: true }, UpdateCommand { alias: None, yes: true })]
+  fn test_update_command_from_cli(
+    #[case] input: Command,
+    #[case] expected: UpdateCommand,
+  ) -> anyhow::Result<()> {
+    assert_eq!(expected, UpdateCommand::try_from(input)?);
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_update_command_from_cli_without_target() -> anyhow::Result<()> {
+    let result = UpdateCommand::try_from(Command::Update {
+      alias: None,
+      all: false,
+      yes: false,
+    });
+    assert_eq!(
+      "either the model alias or --all should be provided",
+      result.unwrap_err().to_string()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_update_command_alias_up_to_date() -> anyhow::Result<()> {
+    let mut mock_data_service = MockDataService::new();
+    mock_data_service
+      .expect_find_alias()
+      .with(eq("testalias:instruct"))
+      .return_once(|_| Some(Alias::testalias()));
+    let mut mock_hub_service = MockHubService::new();
+    mock_hub_service
+      .expect_latest_snapshot()
+      .with(
+        eq(Repo::testalias()),
+        eq("testalias.Q8_0.gguf"),
+        eq(DEFAULT_REVISION),
+      )
+      .return_once(|_, _, _| Ok(SNAPSHOT.to_string()));
+    let service =
+      AppServiceStubMock::new(MockEnvServiceFn::new(), mock_hub_service, mock_data_service);
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .with(eq(
+        "alias 'testalias:instruct': up to date at snapshot '5007652f'\n",
+      ))
+      .return_once(|input| Ok(input.len()));
+    let update = UpdateCommand {
+      alias: Some("testalias:instruct".to_string()),
+      yes: true,
+    };
+    update.execute(Arc::new(service), &mut stdout)?;
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_update_command_downloads_and_pins_newer_snapshot() -> anyhow::Result<()> {
+    let mut mock_data_service = MockDataService::new();
+    mock_data_service
+      .expect_find_alias()
+      .with(eq("testalias:instruct"))
+      .return_once(|_| Some(Alias::testalias()));
+    let updated = Alias::test_alias_instruct_builder()
+      .snapshot(LATEST.to_string())
+      .build()?;
+    mock_data_service
+      .expect_save_alias()
+      .with(eq(updated))
+      .return_once(|_| Ok(PathBuf::from("ignored")));
+    let mut mock_hub_service = MockHubService::new();
+    mock_hub_service
+      .expect_latest_snapshot()
+      .return_once(|_, _, _| Ok(LATEST.to_string()));
+    mock_hub_service
+      .expect_download()
+      .with(
+        eq(Repo::testalias()),
+        eq("testalias.Q8_0.gguf"),
+        eq(LATEST),
+        eq(false),
+      )
+      .return_once(|_, _, _, _| Ok(HubFile::testalias()));
+    let service =
+      AppServiceStubMock::new(MockEnvServiceFn::new(), mock_hub_service, mock_data_service);
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .with(eq("alias 'testalias:instruct': newer snapshot of 'testalias.Q8_0.gguf' available in repo 'MyFactory/testalias-gguf', snapshot '5007652f' -> '9f8e7d6c'\n"))
+      .return_once(|input| Ok(input.len()));
+    stdout
+      .expect_write()
+      .with(eq(
+        "alias 'testalias:instruct': updated to snapshot '9f8e7d6c'\n",
+      ))
+      .return_once(|input| Ok(input.len()));
+    let update = UpdateCommand {
+      alias: Some("testalias:instruct".to_string()),
+      yes: true,
+    };
+    update.execute(Arc::new(service), &mut stdout)?;
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_update_command_reports_check_failure() -> anyhow::Result<()> {
+    let mut mock_data_service = MockDataService::new();
+    mock_data_service
+      .expect_list_aliases()
+      .return_once(|| Ok(vec![Alias::testalias()]));
+    let mut mock_hub_service = MockHubService::new();
+    mock_hub_service
+      .expect_latest_snapshot()
+      .return_once(|_, _, _| {
+        Err(HubServiceError::MissingHeader {
+          header: "x-repo-commit".to_string(),
+          url: "https://huggingface.co".to_string(),
+        })
+      });
+    let service =
+      AppServiceStubMock::new(MockEnvServiceFn::new(), mock_hub_service, mock_data_service);
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .withf(|input| input.starts_with("alias 'testalias:instruct': could not check for updates"))
+      .return_once(|input| Ok(input.len()));
+    let update = UpdateCommand {
+      alias: None,
+      yes: true,
+    };
+    let result = update.execute(Arc::new(service), &mut stdout);
+    assert!(result.is_err());
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_update_command_alias_not_found() -> anyhow::Result<()> {
+    let mut mock_data_service = MockDataService::new();
+    mock_data_service
+      .expect_find_alias()
+      .with(eq("notexists:instruct"))
+      .return_once(|_| None);
+    let service = AppServiceStubMock::new(
+      MockEnvServiceFn::new(),
+      MockHubService::new(),
+      mock_data_service,
+    );
+    let update = UpdateCommand {
+      alias: Some("notexists:instruct".to_string()),
+      yes: true,
+    };
+    let result = update.execute(Arc::new(service), &mut MockStdoutWriter::default());
+    assert!(result.is_err());
+    Ok(())
+  }
+}
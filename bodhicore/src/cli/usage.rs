@@ -0,0 +1,192 @@
+use super::{CliError, StdoutWriter};
+use crate::{
+  db::{objs::UsagePeriod, DbPool, DbServiceFn, TimeService},
+  error::{BodhiError, Common},
+  service::AppServiceFn,
+  Command,
+};
+use prettytable::{format, row, Table};
+use std::sync::Arc;
+use tokio::runtime::Builder;
+
+#[derive(Debug, PartialEq)]
+pub struct UsageCommand {
+  period: UsagePeriod,
+  limit: u32,
+}
+
+impl TryFrom<Command> for UsageCommand {
+  type Error = CliError;
+
+  fn try_from(value: Command) -> Result<Self, Self::Error> {
+    match value {
+      Command::Usage { period, limit } => Ok(UsageCommand { period, limit }),
+      cmd => Err(CliError::ConvertCommand(
+        cmd.to_string(),
+        "usage".to_string(),
+      )),
+    }
+  }
+}
+
+impl UsageCommand {
+  #[allow(clippy::result_large_err)]
+  pub fn execute(
+    self,
+    service: Arc<dyn AppServiceFn>,
+    stdout: &mut dyn StdoutWriter,
+  ) -> crate::error::Result<()> {
+    let runtime = Builder::new_current_thread()
+      .enable_all()
+      .build()
+      .map_err(Common::from)?;
+    runtime.block_on(async move {
+      let db_service =
+        DbPool::connect_service(&service.env_service().database_url(), Arc::new(TimeService))
+          .await?;
+      db_service.migrate().await?;
+      self.aexecute(db_service.as_ref(), stdout).await?;
+      Ok::<(), BodhiError>(())
+    })?;
+    Ok(())
+  }
+
+  async fn aexecute(
+    self,
+    db_service: &dyn DbServiceFn,
+    stdout: &mut dyn StdoutWriter,
+  ) -> crate::error::Result<()> {
+    let stats = db_service
+      .list_usage_stats(self.period, None, self.limit)
+      .await?;
+    if stats.is_empty() {
+      stdout
+        .write("No usage recorded yet, the chat completions served by `bodhi serve` are recorded\n")
+        .map_err(Common::Io)?;
+      return Ok(());
+    }
+    let mut table = Table::new();
+    table.add_row(row![
+      self.period.to_string().to_uppercase(),
+      "MODEL",
+      "CLIENT",
+      "REQUESTS",
+      "PROMPT TOKENS",
+      "COMPLETION TOKENS",
+      "AVG LATENCY"
+    ]);
+    for stat in stats {
+      let latency = stat
+        .avg_latency_ms
+        .map(|latency_ms| format!("{latency_ms}ms"))
+        .unwrap_or_default();
+      table.add_row(row![
+        stat.period,
+        stat.model,
+        stat.client.unwrap_or_default(),
+        stat.requests,
+        stat.prompt_tokens,
+        stat.completion_tokens,
+        latency
+      ]);
+    }
+    table.set_format(format::FormatBuilder::default().padding(2, 2).build());
+    stdout.write(&table.to_string()).map_err(Common::Io)?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::UsageCommand;
+  use crate::{
+    db::{
+      objs::{UsagePeriod, UsageRecord},
+      DbService, DbServiceFn,
+    },
+    test_utils::db_service,
+    Command, MockStdoutWriter,
+  };
+  use chrono::{DateTime, Utc};
+  use rstest::rstest;
+  use tempfile::TempDir;
+
+  #[rstest]
+  fn test_usage_command_from_cli() -> anyhow::Result<()> {
+    let command = Command::Usage {
+      period: UsagePeriod::Week,
+      limit: 5,
+    };
+    assert_eq!(
+      UsageCommand {
+        period: UsagePeriod::Week,
+        limit: 5
+      },
+      UsageCommand::try_from(command)?
+    );
+    let result = UsageCommand::try_from(Command::Chats {});
+    assert_eq!(
+      "Command 'chats' cannot be converted into command 'usage'",
+      result.unwrap_err().to_string()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_usage_command_shows_usage_by_period(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_temp, now, db_service) = db_service;
+    let mut record = UsageRecord {
+      model: "testalias:instruct".to_string(),
+      app_name: Some("notes".to_string()),
+      prompt_tokens: 15,
+      completion_tokens: 3,
+      latency_ms: Some(250),
+      ..Default::default()
+    };
+    db_service.save_usage(&mut record).await?;
+    let day = now.format("%Y-%m-%d").to_string();
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .withf(move |input| {
+        input.contains("DAY")
+          && input.contains(&day)
+          && input.contains("testalias:instruct")
+          && input.contains("notes")
+          && input.contains("250ms")
+      })
+      .return_once(|input| Ok(input.len()));
+    UsageCommand {
+      period: UsagePeriod::Day,
+      limit: 20,
+    }
+    .aexecute(&db_service, &mut stdout)
+    .await?;
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_usage_command_without_usage(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .withf(|input| input.starts_with("No usage recorded yet"))
+      .return_once(|input| Ok(input.len()));
+    UsageCommand {
+      period: UsagePeriod::Day,
+      limit: 20,
+    }
+    .aexecute(&db_service, &mut stdout)
+    .await?;
+    Ok(())
+  }
+}
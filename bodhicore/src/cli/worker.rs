@@ -0,0 +1,63 @@
+use super::CliError;
+use crate::{error::Common, objs::Backend, worker::run_worker, Command};
+use tokio::runtime::Builder;
+
+#[derive(Debug, PartialEq)]
+pub struct WorkerCommand {
+  strict: bool,
+  backend: Backend,
+}
+
+impl TryFrom<Command> for WorkerCommand {
+  type Error = CliError;
+
+  fn try_from(value: Command) -> Result<Self, Self::Error> {
+    match value {
+      Command::Worker { strict, backend } => Ok(WorkerCommand { strict, backend }),
+      cmd => Err(CliError::ConvertCommand(
+        cmd.to_string(),
+        "worker".to_string(),
+      )),
+    }
+  }
+}
+
+impl WorkerCommand {
+  #[allow(clippy::result_large_err)]
+  pub fn execute(self) -> crate::error::Result<()> {
+    let runtime = Builder::new_multi_thread()
+      .enable_all()
+      .build()
+      .map_err(Common::from)?;
+    runtime.block_on(run_worker(self.strict, self.backend))?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::WorkerCommand;
+  use crate::{objs::Backend, Command};
+  use rstest::rstest;
+
+  #[rstest]
+  fn test_worker_command_from_cli() -> anyhow::Result<()> {
+    let result = WorkerCommand::try_from(Command::Worker {
+      strict: true,
+      backend: Backend::Cpu,
+    })?;
+    assert_eq!(
+      WorkerCommand {
+        strict: true,
+        backend: Backend::Cpu
+      },
+      result
+    );
+    let result = WorkerCommand::try_from(Command::App { ui: false });
+    assert_eq!(
+      "Command 'app' cannot be converted into command 'worker'",
+      result.unwrap_err().to_string()
+    );
+    Ok(())
+  }
+}
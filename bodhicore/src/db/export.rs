@@ -0,0 +1,225 @@
+use super::objs::{Conversation, Message};
+use crate::objs::DisplayTimezone;
+use chrono::{serde::ts_milliseconds, DateTime, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize, ValueEnum, Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum ExportFormat {
+  #[default]
+  Markdown,
+  Json,
+}
+
+impl ExportFormat {
+  pub fn extension(&self) -> &'static str {
+    match self {
+      ExportFormat::Markdown => "md",
+      ExportFormat::Json => "json",
+    }
+  }
+
+  pub fn content_type(&self) -> &'static str {
+    match self {
+      ExportFormat::Markdown => "text/markdown; charset=utf-8",
+      ExportFormat::Json => "application/json",
+    }
+  }
+}
+
+/// Conversation in the json export, unlike the UI API it keeps the message timestamps
+/// so the conversation can be imported as-is on another machine
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedConversation {
+  #[serde(default)]
+  pub id: String,
+  #[serde(default)]
+  pub title: String,
+  #[serde(with = "ts_milliseconds", default)]
+  pub created_at: DateTime<Utc>,
+  #[serde(with = "ts_milliseconds", default)]
+  pub updated_at: DateTime<Utc>,
+  #[serde(default)]
+  pub messages: Vec<ExportedMessage>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedMessage {
+  #[serde(default)]
+  pub id: String,
+  pub role: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub name: Option<String>,
+  #[serde(default)]
+  pub content: Option<String>,
+  #[serde(with = "ts_milliseconds", default)]
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<&Conversation> for ExportedConversation {
+  fn from(value: &Conversation) -> Self {
+    ExportedConversation {
+      id: value.id.clone(),
+      title: value.title.clone(),
+      created_at: value.created_at,
+      updated_at: value.updated_at,
+      messages: value.messages.iter().map(ExportedMessage::from).collect(),
+    }
+  }
+}
+
+impl From<&Message> for ExportedMessage {
+  fn from(value: &Message) -> Self {
+    ExportedMessage {
+      id: value.id.clone(),
+      role: value.role.clone(),
+      name: value.name.clone(),
+      content: value.content.clone(),
+      created_at: value.created_at,
+    }
+  }
+}
+
+/// Serializes the conversation with its messages into a shareable document.
+/// The markdown export renders the timestamps in `timezone`, the json export keeps them as UTC epoch millis.
+pub fn export_conversation(
+  convo: &Conversation,
+  format: ExportFormat,
+  timezone: &DisplayTimezone,
+) -> Result<String, serde_json::Error> {
+  match format {
+    ExportFormat::Markdown => Ok(export_markdown(convo, timezone)),
+    ExportFormat::Json => serde_json::to_string_pretty(&ExportedConversation::from(convo)),
+  }
+}
+
+fn export_markdown(convo: &Conversation, timezone: &DisplayTimezone) -> String {
+  let title = if convo.title.trim().is_empty() {
+    "Untitled chat"
+  } else {
+    convo.title.trim()
+  };
+  let mut output = format!("# {title}\n\n");
+  output.push_str(&format!(
+    "_Exported from Bodhi, created {}_\n",
+    timezone.display(&convo.created_at)
+  ));
+  for message in &convo.messages {
+    let mut role = message.role.clone();
+    if let Some(first) = role.get_mut(0..1) {
+      first.make_ascii_uppercase();
+    }
+    match &message.name {
+      Some(name) => output.push_str(&format!("\n### {role} ({name})\n\n")),
+      None => output.push_str(&format!("\n### {role}\n\n")),
+    }
+    output.push_str(message.content.as_deref().unwrap_or_default().trim_end());
+    output.push('\n');
+  }
+  output
+}
+
+#[cfg(test)]
+mod test {
+  use super::{export_conversation, ExportFormat};
+  use crate::{
+    db::objs::{ConversationBuilder, MessageBuilder},
+    objs::DisplayTimezone,
+  };
+  use chrono::{TimeZone, Utc};
+  use rstest::rstest;
+  use serde_json::{json, Value};
+
+  #[rstest]
+  #[case(DisplayTimezone::Utc, "2024-05-01 10:30:00 +00:00")]
+  #[case(
+    DisplayTimezone::Named(chrono_tz::Asia::Kolkata),
+    "2024-05-01 16:00:00 +05:30"
+  )]
+  fn test_export_conversation_markdown(
+    #[case] timezone: DisplayTimezone,
+    #[case] created: &str,
+  ) -> anyhow::Result<()> {
+    let convo = ConversationBuilder::default()
+      .id("testid")
+      .title("Days of the week")
+      .created_at(Utc.with_ymd_and_hms(2024, 5, 1, 10, 30, 0).unwrap())
+      .messages(vec![
+        MessageBuilder::default()
+          .role("user")
+          .content("What day comes after Monday?")
+          .build()?,
+        MessageBuilder::default()
+          .role("assistant")
+          .content("Tuesday\n")
+          .build()?,
+      ])
+      .build()?;
+    let output = export_conversation(&convo, ExportFormat::Markdown, &timezone)?;
+    assert_eq!(
+      format!(
+        r#"# Days of the week
+
+_Exported from Bodhi, created {created}_
+
+### User
+
+What day comes after Monday?
+
+### Assistant
+
+Tuesday
+"#
+      ),
+      output
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_export_conversation_markdown_untitled() -> anyhow::Result<()> {
+    let convo = ConversationBuilder::default().build()?;
+    let output = export_conversation(&convo, ExportFormat::Markdown, &DisplayTimezone::Utc)?;
+    assert!(output.starts_with("# Untitled chat\n"));
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_export_conversation_json() -> anyhow::Result<()> {
+    let created_at = Utc.with_ymd_and_hms(2024, 5, 1, 10, 30, 0).unwrap();
+    let convo = ConversationBuilder::default()
+      .id("testid")
+      .title("test title")
+      .created_at(created_at)
+      .updated_at(created_at)
+      .messages(vec![MessageBuilder::default()
+        .id("msgid")
+        .role("user")
+        .content("test content")
+        .created_at(created_at)
+        .build()?])
+      .build()?;
+    let output = export_conversation(&convo, ExportFormat::Json, &DisplayTimezone::Utc)?;
+    assert_eq!(
+      json! {{
+        "id": "testid",
+        "title": "test title",
+        "createdAt": 1714559400000i64,
+        "updatedAt": 1714559400000i64,
+        "messages": [{
+          "id": "msgid",
+          "role": "user",
+          "content": "test content",
+          "createdAt": 1714559400000i64
+        }]
+      }},
+      serde_json::from_str::<Value>(&output)?
+    );
+    Ok(())
+  }
+}
@@ -0,0 +1,330 @@
+use super::{
+  export::ExportedConversation,
+  objs::{Conversation, Message},
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ImportError {
+  #[error("unknown import format, expected a chat exported from Bodhi, or the conversations.json from a ChatGPT export")]
+  UnknownFormat,
+  #[error("invalid chat in import: {0}")]
+  Invalid(#[from] serde_json::Error),
+}
+
+/// Parses the chats from a Bodhi json export, a list of Bodhi json exports,
+/// or the `conversations.json` from a ChatGPT data export.
+/// Timestamps and ids of the chats are kept as-is, so importing the same chat again replaces it.
+pub fn import_conversations(
+  value: Value,
+  now: DateTime<Utc>,
+) -> Result<Vec<Conversation>, ImportError> {
+  let items = match value {
+    Value::Array(items) => items,
+    item @ Value::Object(_) => vec![item],
+    _ => return Err(ImportError::UnknownFormat),
+  };
+  items
+    .into_iter()
+    .map(|item| {
+      if item.get("mapping").is_some() {
+        let chat = serde_json::from_value::<ChatGptConversation>(item)?;
+        Ok(chat.into_conversation(now))
+      } else if item.get("messages").is_some() {
+        let chat = serde_json::from_value::<ExportedConversation>(item)?;
+        Ok(from_exported(chat, now))
+      } else {
+        Err(ImportError::UnknownFormat)
+      }
+    })
+    .collect()
+}
+
+fn from_exported(chat: ExportedConversation, now: DateTime<Utc>) -> Conversation {
+  let created_at = or_now(chat.created_at, now);
+  Conversation {
+    id: chat.id,
+    title: chat.title,
+    created_at,
+    updated_at: or_now(chat.updated_at, created_at),
+    tags: Vec::new(),
+    owner: None,
+    messages: chat
+      .messages
+      .into_iter()
+      .map(|message| Message {
+        id: message.id,
+        conversation_id: String::new(),
+        role: message.role,
+        name: message.name,
+        content: message.content,
+        created_at: or_now(message.created_at, created_at),
+        ..Default::default()
+      })
+      .collect(),
+  }
+}
+
+fn or_now(timestamp: DateTime<Utc>, now: DateTime<Utc>) -> DateTime<Utc> {
+  if timestamp == DateTime::<Utc>::default() {
+    now
+  } else {
+    timestamp
+  }
+}
+
+fn from_epoch_secs(secs: Option<f64>) -> Option<DateTime<Utc>> {
+  secs.and_then(|secs| DateTime::<Utc>::from_timestamp_millis((secs * 1000.0) as i64))
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatGptConversation {
+  #[serde(default)]
+  title: Option<String>,
+  #[serde(default)]
+  create_time: Option<f64>,
+  #[serde(default)]
+  update_time: Option<f64>,
+  mapping: HashMap<String, ChatGptNode>,
+  #[serde(default)]
+  current_node: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatGptNode {
+  #[serde(default)]
+  message: Option<ChatGptMessage>,
+  #[serde(default)]
+  parent: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatGptMessage {
+  author: ChatGptAuthor,
+  #[serde(default)]
+  create_time: Option<f64>,
+  content: ChatGptContent,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatGptAuthor {
+  role: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatGptContent {
+  content_type: String,
+  #[serde(default)]
+  parts: Vec<Value>,
+}
+
+impl ChatGptConversation {
+  /// Follows the active branch from `current_node` back to the root, ChatGPT keeps the
+  /// edited and regenerated messages as sibling nodes
+  fn into_conversation(mut self, now: DateTime<Utc>) -> Conversation {
+    let created_at = from_epoch_secs(self.create_time).unwrap_or(now);
+    let mut nodes = vec![];
+    let mut next = self.current_node.take();
+    while let Some(node) = next.and_then(|id| self.mapping.remove(&id)) {
+      next = node.parent.clone();
+      nodes.push(node);
+    }
+    nodes.reverse();
+    let messages = nodes
+      .into_iter()
+      .filter_map(|node| node.message)
+      .filter(|message| {
+        matches!(
+          message.author.role.as_str(),
+          "user" | "assistant" | "system"
+        ) && message.content.content_type == "text"
+      })
+      .filter_map(|message| {
+        let content = message
+          .content
+          .parts
+          .iter()
+          .filter_map(|part| part.as_str())
+          .collect::<Vec<_>>()
+          .join("\n");
+        if content.trim().is_empty() {
+          return None;
+        }
+        Some(Message {
+          role: message.author.role,
+          content: Some(content),
+          created_at: from_epoch_secs(message.create_time).unwrap_or(created_at),
+          ..Default::default()
+        })
+      })
+      .collect();
+    Conversation {
+      id: String::new(),
+      title: self.title.unwrap_or_default(),
+      created_at,
+      updated_at: from_epoch_secs(self.update_time).unwrap_or(created_at),
+      tags: Vec::new(),
+      messages,
+      owner: None,
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{import_conversations, ImportError};
+  use crate::{
+    db::{
+      export_conversation,
+      objs::{ConversationBuilder, MessageBuilder},
+      ExportFormat,
+    },
+    objs::DisplayTimezone,
+  };
+  use chrono::{TimeZone, Utc};
+  use rstest::rstest;
+  use serde_json::{json, Value};
+
+  #[rstest]
+  fn test_import_conversations_from_export() -> anyhow::Result<()> {
+    let created_at = Utc.with_ymd_and_hms(2024, 5, 1, 10, 30, 0).unwrap();
+    let convo = ConversationBuilder::default()
+      .id("testid")
+      .title("test title")
+      .created_at(created_at)
+      .updated_at(created_at)
+      .messages(vec![
+        MessageBuilder::default()
+          .id("msg-1")
+          .role("user")
+          .content("What day comes after Monday?")
+          .created_at(created_at)
+          .build()?,
+        MessageBuilder::default()
+          .id("msg-2")
+          .role("assistant")
+          .content("Tuesday")
+          .created_at(created_at)
+          .build()?,
+      ])
+      .build()?;
+    let exported = export_conversation(&convo, ExportFormat::Json, &DisplayTimezone::Utc)?;
+    let imported = import_conversations(serde_json::from_str::<Value>(&exported)?, Utc::now())?;
+    assert_eq!(vec![convo], imported);
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_import_conversations_defaults_missing_timestamps() -> anyhow::Result<()> {
+    let now = Utc.with_ymd_and_hms(2024, 5, 1, 10, 30, 0).unwrap();
+    let imported = import_conversations(
+      json! {[{
+        "title": "test title",
+        "messages": [{"role": "user", "content": "test content"}]
+      }]},
+      now,
+    )?;
+    assert_eq!(1, imported.len());
+    assert_eq!(now, imported[0].created_at);
+    assert_eq!(now, imported[0].messages[0].created_at);
+    assert_eq!("", imported[0].id);
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_import_conversations_from_chatgpt() -> anyhow::Result<()> {
+    let imported = import_conversations(
+      json! {[{
+        "title": "Days of the week",
+        "create_time": 1714559400.5,
+        "update_time": 1714559460.0,
+        "current_node": "node-4",
+        "mapping": {
+          "root": {"id": "root", "message": null, "parent": null, "children": ["node-1"]},
+          "node-1": {
+            "id": "node-1",
+            "message": {
+              "author": {"role": "system"},
+              "create_time": null,
+              "content": {"content_type": "text", "parts": [""]}
+            },
+            "parent": "root",
+            "children": ["node-2"]
+          },
+          "node-2": {
+            "id": "node-2",
+            "message": {
+              "author": {"role": "user"},
+              "create_time": 1714559410.0,
+              "content": {"content_type": "text", "parts": ["What day comes after Monday?"]}
+            },
+            "parent": "node-1",
+            "children": ["node-3", "node-4"]
+          },
+          "node-3": {
+            "id": "node-3",
+            "message": {
+              "author": {"role": "assistant"},
+              "create_time": 1714559420.0,
+              "content": {"content_type": "text", "parts": ["Wednesday"]}
+            },
+            "parent": "node-2",
+            "children": []
+          },
+          "node-4": {
+            "id": "node-4",
+            "message": {
+              "author": {"role": "assistant"},
+              "create_time": 1714559430.0,
+              "content": {"content_type": "text", "parts": ["Tuesday"]}
+            },
+            "parent": "node-2",
+            "children": []
+          }
+        }
+      }]},
+      Utc::now(),
+    )?;
+    assert_eq!(1, imported.len());
+    let convo = &imported[0];
+    assert_eq!("Days of the week", convo.title);
+    assert_eq!(
+      Utc.timestamp_millis_opt(1714559400500).unwrap(),
+      convo.created_at
+    );
+    let messages = convo
+      .messages
+      .iter()
+      .map(|message| {
+        (
+          message.role.as_str(),
+          message.content.as_deref().unwrap_or_default(),
+        )
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(
+      vec![
+        ("user", "What day comes after Monday?"),
+        ("assistant", "Tuesday")
+      ],
+      messages
+    );
+    assert_eq!(
+      Utc.with_ymd_and_hms(2024, 5, 1, 10, 30, 30).unwrap(),
+      convo.messages[1].created_at
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(json! {"not a chat"})]
+  #[case(json! {[{"title": "no messages"}]})]
+  fn test_import_conversations_unknown_format(#[case] input: Value) -> anyhow::Result<()> {
+    let result = import_conversations(input, Utc::now());
+    assert!(matches!(result, Err(ImportError::UnknownFormat)));
+    Ok(())
+  }
+}
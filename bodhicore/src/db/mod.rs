@@ -1,7 +1,13 @@
+mod export;
+mod import;
 mod no_op;
 pub mod objs;
+mod pg_service;
 mod service;
 mod sqlite_pool;
 
-pub use service::{DbError, DbService, DbServiceFn, TimeService, TimeServiceFn};
+pub use export::{export_conversation, ExportFormat, ExportedConversation, ExportedMessage};
+pub use import::{import_conversations, ImportError};
+pub use pg_service::{PgDbService, PG_MIGRATOR};
+pub use service::{DbError, DbService, DbServiceFn, TimeService, TimeServiceFn, MIGRATOR};
 pub use sqlite_pool::DbPool;
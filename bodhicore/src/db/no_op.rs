@@ -1,8 +1,15 @@
 use super::{
-  objs::{Conversation, Message},
-  service::CONVERSATIONS,
+  objs::{
+    ApiKey, ApiKeyRole, AuditAction, AuditEntry, CanarySample, ClientUsage, Conversation,
+    ConversationUsage, ConversationsQuery, Download, Message, MessageMatch, MigrationStatus,
+    ShareLink, UsagePeriod, UsageRecord, UsageStats,
+  },
+  service::{API_KEYS, CONVERSATIONS, DOWNLOADS, MESSAGES, SHARE_LINKS},
   DbError, DbServiceFn,
 };
+use crate::objs::RequestPriority;
+use chrono::{DateTime, Duration, Utc};
+use std::path::Path;
 
 #[derive(Debug, PartialEq)]
 pub(super) struct NoOpDbService {}
@@ -19,6 +26,16 @@ impl DbServiceFn for NoOpDbService {
     Ok(())
   }
 
+  async fn migration_status(&self) -> Result<Vec<MigrationStatus>, DbError> {
+    Ok(vec![])
+  }
+
+  async fn backup(&self, _path: &Path) -> Result<(), DbError> {
+    Err(DbError::Unsupported(
+      "backup of the no-op database".to_string(),
+    ))
+  }
+
   async fn save_conversation(&self, _conversation: &mut Conversation) -> Result<(), DbError> {
     Ok(())
   }
@@ -27,7 +44,10 @@ impl DbServiceFn for NoOpDbService {
     Ok(())
   }
 
-  async fn list_conversations(&self) -> Result<Vec<Conversation>, DbError> {
+  async fn list_conversations(
+    &self,
+    _query: &ConversationsQuery,
+  ) -> Result<Vec<Conversation>, DbError> {
     Ok(vec![])
   }
 
@@ -38,7 +58,7 @@ impl DbServiceFn for NoOpDbService {
     })
   }
 
-  async fn delete_all_conversations(&self) -> Result<(), DbError> {
+  async fn delete_all_conversations(&self, _owner: Option<&str>) -> Result<(), DbError> {
     Ok(())
   }
 
@@ -48,17 +68,194 @@ impl DbServiceFn for NoOpDbService {
       table: CONVERSATIONS.to_string(),
     })
   }
+
+  async fn edit_message(
+    &self,
+    _conversation_id: &str,
+    _message_id: &str,
+    _content: &str,
+  ) -> Result<Message, DbError> {
+    Err(DbError::Sqlx {
+      source: sqlx::Error::RowNotFound,
+      table: MESSAGES.to_string(),
+    })
+  }
+
+  async fn branch_messages(
+    &self,
+    _conversation_id: &str,
+    _message_id: &str,
+  ) -> Result<(), DbError> {
+    Err(DbError::Sqlx {
+      source: sqlx::Error::RowNotFound,
+      table: MESSAGES.to_string(),
+    })
+  }
+
+  async fn fork_conversation(
+    &self,
+    _conversation_id: &str,
+    _message_id: &str,
+  ) -> Result<Conversation, DbError> {
+    Err(DbError::Sqlx {
+      source: sqlx::Error::RowNotFound,
+      table: CONVERSATIONS.to_string(),
+    })
+  }
+
+  async fn create_share_link(
+    &self,
+    _conversation_id: &str,
+    _expires_in: Duration,
+  ) -> Result<ShareLink, DbError> {
+    Err(DbError::Sqlx {
+      source: sqlx::Error::RowNotFound,
+      table: CONVERSATIONS.to_string(),
+    })
+  }
+
+  async fn get_shared_conversation(&self, _token: &str) -> Result<Conversation, DbError> {
+    Err(DbError::Sqlx {
+      source: sqlx::Error::RowNotFound,
+      table: SHARE_LINKS.to_string(),
+    })
+  }
+
+  async fn save_usage(&self, _record: &mut UsageRecord) -> Result<(), DbError> {
+    Ok(())
+  }
+
+  async fn list_client_usage(
+    &self,
+    _since: Option<DateTime<Utc>>,
+    _limit: u32,
+  ) -> Result<Vec<ClientUsage>, DbError> {
+    Ok(vec![])
+  }
+
+  async fn list_usage_stats(
+    &self,
+    _period: UsagePeriod,
+    _since: Option<DateTime<Utc>>,
+    _limit: u32,
+  ) -> Result<Vec<UsageStats>, DbError> {
+    Ok(vec![])
+  }
+
+  async fn list_conversation_usage(
+    &self,
+    _since: Option<DateTime<Utc>>,
+    _owner: Option<&str>,
+    _limit: u32,
+  ) -> Result<Vec<ConversationUsage>, DbError> {
+    Ok(vec![])
+  }
+
+  async fn search_messages(
+    &self,
+    _query: &str,
+    _owner: Option<&str>,
+    _limit: u32,
+  ) -> Result<Vec<MessageMatch>, DbError> {
+    Ok(vec![])
+  }
+
+  async fn tag_conversation(&self, _conversation_id: &str, _tag: &str) -> Result<(), DbError> {
+    Err(DbError::Sqlx {
+      source: sqlx::Error::RowNotFound,
+      table: CONVERSATIONS.to_string(),
+    })
+  }
+
+  async fn untag_conversation(&self, _conversation_id: &str, _tag: &str) -> Result<(), DbError> {
+    Ok(())
+  }
+
+  async fn save_canary_sample(&self, _sample: &mut CanarySample) -> Result<(), DbError> {
+    Ok(())
+  }
+
+  async fn list_canary_samples(&self, _limit: u32) -> Result<Vec<CanarySample>, DbError> {
+    Ok(vec![])
+  }
+
+  async fn create_api_key(
+    &self,
+    _name: &str,
+    _role: ApiKeyRole,
+    _priority: RequestPriority,
+  ) -> Result<(ApiKey, String), DbError> {
+    Err(DbError::Unsupported(
+      "api keys in the no-op database".to_string(),
+    ))
+  }
+
+  async fn list_api_keys(&self) -> Result<Vec<ApiKey>, DbError> {
+    Ok(vec![])
+  }
+
+  async fn revoke_api_key(&self, _id: &str) -> Result<(), DbError> {
+    Err(DbError::Sqlx {
+      source: sqlx::Error::RowNotFound,
+      table: API_KEYS.to_string(),
+    })
+  }
+
+  async fn find_api_key(&self, _secret: &str) -> Result<ApiKey, DbError> {
+    Err(DbError::Sqlx {
+      source: sqlx::Error::RowNotFound,
+      table: API_KEYS.to_string(),
+    })
+  }
+
+  async fn save_audit_entry(&self, _entry: &mut AuditEntry) -> Result<(), DbError> {
+    Ok(())
+  }
+
+  async fn list_audit_entries(
+    &self,
+    _action: Option<AuditAction>,
+    _since: Option<DateTime<Utc>>,
+    _limit: u32,
+  ) -> Result<Vec<AuditEntry>, DbError> {
+    Ok(vec![])
+  }
+
+  async fn create_download(&self, _download: &mut Download) -> Result<(), DbError> {
+    Err(DbError::Unsupported(
+      "downloads with the no-op database".to_string(),
+    ))
+  }
+
+  async fn update_download(&self, _download: &mut Download) -> Result<(), DbError> {
+    Err(DbError::Sqlx {
+      source: sqlx::Error::RowNotFound,
+      table: DOWNLOADS.to_string(),
+    })
+  }
+
+  async fn get_download(&self, _id: &str) -> Result<Download, DbError> {
+    Err(DbError::Sqlx {
+      source: sqlx::Error::RowNotFound,
+      table: DOWNLOADS.to_string(),
+    })
+  }
+
+  async fn list_downloads(&self, _limit: u32) -> Result<Vec<Download>, DbError> {
+    Ok(vec![])
+  }
 }
 
 #[cfg(test)]
 mod test {
   use super::{
     super::{
-      objs::{Conversation, Message},
+      objs::{Conversation, ConversationsQuery, Message},
       DbServiceFn,
     },
     NoOpDbService,
   };
+  use std::path::Path;
 
   #[tokio::test]
   async fn test_no_op_save() -> anyhow::Result<()> {
@@ -78,7 +275,9 @@ mod test {
 
   #[tokio::test]
   async fn test_no_op_list_convos() -> anyhow::Result<()> {
-    let convos = NoOpDbService::new().list_conversations().await?;
+    let convos = NoOpDbService::new()
+      .list_conversations(&ConversationsQuery::default())
+      .await?;
     assert!(convos.is_empty());
     Ok(())
   }
@@ -93,7 +292,49 @@ mod test {
 
   #[tokio::test]
   async fn test_no_op_delete_all() -> anyhow::Result<()> {
-    NoOpDbService::new().delete_all_conversations().await?;
+    NoOpDbService::new().delete_all_conversations(None).await?;
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_no_op_edit_message() -> anyhow::Result<()> {
+    let result = NoOpDbService::new()
+      .edit_message("testid", "testmsgid", "test content")
+      .await;
+    assert!(result.is_err());
+    assert_eq!("sqlx_query: no rows returned by a query that expected to return at least one row\ntable: messages", result.unwrap_err().to_string());
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_no_op_fork_convo() -> anyhow::Result<()> {
+    let result = NoOpDbService::new()
+      .fork_conversation("testid", "testmsgid")
+      .await;
+    assert!(result.is_err());
+    assert_eq!("sqlx_query: no rows returned by a query that expected to return at least one row\ntable: conversations", result.unwrap_err().to_string());
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_no_op_backup() -> anyhow::Result<()> {
+    let result = NoOpDbService::new()
+      .backup(Path::new("backup.sqlite"))
+      .await;
+    assert_eq!(
+      "unsupported: backup of the no-op database",
+      result.unwrap_err().to_string()
+    );
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_no_op_get_shared_convo() -> anyhow::Result<()> {
+    let result = NoOpDbService::new()
+      .get_shared_conversation("testtoken")
+      .await;
+    assert!(result.is_err());
+    assert_eq!("sqlx_query: no rows returned by a query that expected to return at least one row\ntable: share_links", result.unwrap_err().to_string());
     Ok(())
   }
 
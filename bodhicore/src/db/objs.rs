@@ -1,9 +1,11 @@
-use crate::objs::is_default;
+use crate::objs::{is_default, RequestPriority};
 #[allow(unused_imports)]
 use crate::objs::BuilderError;
 use chrono::{serde::ts_milliseconds, DateTime, Utc};
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
+use strum::{Display, EnumString};
 
 #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, FromRow)]
 #[cfg_attr(test, derive(derive_builder::Builder))]
@@ -30,7 +32,14 @@ pub struct Conversation {
     skip_serializing
   )]
   pub updated_at: DateTime<Utc>,
+  /// tags of the conversation, in alphabetical order, managed using the tag APIs
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub tags: Vec<String>,
   pub messages: Vec<Message>,
+  /// name of the API key that created the conversation when `BODHI_AUTH` is enabled,
+  /// the conversations are visible only to the keys with the name
+  #[serde(skip)]
+  pub owner: Option<String>,
 }
 
 #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, FromRow)]
@@ -44,7 +53,7 @@ pub struct Conversation {
   )
 )]
 pub struct Message {
-  #[serde(default, skip_serializing)]
+  #[serde(default, skip_serializing_if = "String::is_empty")]
   pub id: String,
   #[serde(default, skip_serializing)]
   pub conversation_id: String,
@@ -54,6 +63,313 @@ pub struct Message {
   pub content: Option<String>,
   #[serde(default, skip_serializing)]
   pub created_at: DateTime<Utc>,
+  /// model alias that generated the reply, and the tokens used,
+  /// set for the replies generated by the server
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub model: Option<String>,
+  #[serde(
+    rename = "promptTokens",
+    default,
+    skip_serializing_if = "Option::is_none"
+  )]
+  pub prompt_tokens: Option<i64>,
+  #[serde(
+    rename = "completionTokens",
+    default,
+    skip_serializing_if = "Option::is_none"
+  )]
+  pub completion_tokens: Option<i64>,
+}
+
+/// Read-only link to a conversation, valid till `expires_at`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct ShareLink {
+  pub token: String,
+  #[serde(rename = "conversationId")]
+  pub conversation_id: String,
+  #[serde(rename = "createdAt", with = "ts_milliseconds")]
+  pub created_at: DateTime<Utc>,
+  #[serde(rename = "expiresAt", with = "ts_milliseconds")]
+  pub expires_at: DateTime<Utc>,
+}
+
+/// Entry in the usage ledger for a chat completion request served
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageRecord {
+  #[serde(default)]
+  pub id: String,
+  #[serde(with = "ts_milliseconds", default)]
+  pub created_at: DateTime<Utc>,
+  pub model: String,
+  /// `User-Agent` header of the request
+  pub user_agent: Option<String>,
+  /// app name self-declared by the client using the `X-Client-Name` header
+  pub app_name: Option<String>,
+  pub prompt_tokens: i64,
+  pub completion_tokens: i64,
+  /// time taken to serve the request, not recorded for the older requests
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub latency_ms: Option<i64>,
+}
+
+/// Requests and token usage of a client, aggregated from the usage ledger
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientUsage {
+  pub user_agent: Option<String>,
+  pub app_name: Option<String>,
+  pub requests: i64,
+  pub prompt_tokens: i64,
+  pub completion_tokens: i64,
+}
+
+/// Token usage of the replies generated by the server in a conversation
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversationUsage {
+  pub conversation_id: String,
+  pub title: String,
+  pub replies: i64,
+  pub prompt_tokens: i64,
+  pub completion_tokens: i64,
+}
+
+/// Period to aggregate the usage ledger by, in UTC. The weeks start on Monday.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize, ValueEnum, Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum UsagePeriod {
+  Hour,
+  #[default]
+  Day,
+  Week,
+  Month,
+}
+
+/// Requests, tokens and latency of a model alias used by a client in a period,
+/// aggregated from the usage ledger
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageStats {
+  /// start of the period, e.g. `2024-05-01` for a day or a week, `2024-05-01 10:00` for an hour
+  /// and `2024-05` for a month
+  pub period: String,
+  pub model: String,
+  /// app name of the client if declared, else its `User-Agent`
+  pub client: Option<String>,
+  pub requests: i64,
+  pub prompt_tokens: i64,
+  pub completion_tokens: i64,
+  /// average latency of the requests with the latency recorded
+  pub avg_latency_ms: Option<i64>,
+}
+
+/// Role of an API key, a role has all the access of the roles before it
+#[derive(
+  Debug,
+  Clone,
+  Copy,
+  Default,
+  PartialEq,
+  Eq,
+  PartialOrd,
+  Ord,
+  Serialize,
+  Deserialize,
+  ValueEnum,
+  Display,
+  EnumString,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum ApiKeyRole {
+  /// list the models, chats and usage
+  Readonly,
+  /// run inference and manage the chats
+  #[default]
+  User,
+  /// manage the model aliases and the settings
+  Admin,
+}
+
+/// Key to access the APIs when `BODHI_AUTH` is enabled, the key itself is shown only when
+/// created, only its sha256 is stored
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKey {
+  pub id: String,
+  pub name: String,
+  pub role: ApiKeyRole,
+  #[serde(default)]
+  pub priority: RequestPriority,
+  #[serde(with = "ts_milliseconds")]
+  pub created_at: DateTime<Utc>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// Admin action recorded in the audit log
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ValueEnum, Display, EnumString)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum AuditAction {
+  AliasCreate,
+  AliasUpdate,
+  AliasDelete,
+  SettingUpdate,
+  KeyCreate,
+  KeyRevoke,
+  ModelPull,
+}
+
+/// Entry in the audit log, with the values before and after the change
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+  #[serde(default)]
+  pub id: String,
+  #[serde(with = "ts_milliseconds", default)]
+  pub created_at: DateTime<Utc>,
+  /// name of the API key of the request, `cli` for the bodhi commands,
+  /// not set for the requests with `BODHI_AUTH` disabled
+  pub actor: Option<String>,
+  pub action: AuditAction,
+  /// the changed alias, setting, or API key id
+  pub target: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub before: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub after: Option<String>,
+}
+
+/// State of a model file download, the queued downloads wait for the running one to finish
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, Display, EnumString)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum DownloadStatus {
+  #[default]
+  Queued,
+  Downloading,
+  /// stopped with the downloaded part kept, resuming continues from there
+  Paused,
+  Completed,
+  Failed,
+  Cancelled,
+}
+
+impl DownloadStatus {
+  /// The download is not queued, running or paused anymore
+  pub fn is_finished(&self) -> bool {
+    matches!(
+      self,
+      DownloadStatus::Completed | DownloadStatus::Failed | DownloadStatus::Cancelled
+    )
+  }
+}
+
+/// Model file pulled into $HF_HOME in the background, with its progress
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Download {
+  #[serde(default)]
+  pub id: String,
+  pub repo: String,
+  pub filename: String,
+  pub revision: String,
+  #[serde(default)]
+  pub status: DownloadStatus,
+  /// bytes downloaded so far, including the ones downloaded before a resume
+  #[serde(default)]
+  pub downloaded_bytes: u64,
+  /// size of the file, if known from the response of huggingface
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub total_bytes: Option<u64>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub error: Option<String>,
+  #[serde(with = "ts_milliseconds", default)]
+  pub created_at: DateTime<Utc>,
+  #[serde(with = "ts_milliseconds", default)]
+  pub updated_at: DateTime<Utc>,
+}
+
+/// Schema migration of the database, `applied_at` is not set for the pending migrations
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationStatus {
+  pub version: i64,
+  pub description: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub applied_at: Option<DateTime<Utc>>,
+  /// applied by a newer version of the app, the app does not start with such a database
+  #[serde(default, skip_serializing_if = "is_default")]
+  pub unknown: bool,
+}
+
+/// Chat completion request sampled to also run on the canary model alias, after responding
+/// with the output of the requested model
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct CanarySample {
+  #[serde(default)]
+  pub id: String,
+  #[serde(with = "ts_milliseconds", default)]
+  pub created_at: DateTime<Utc>,
+  pub model: String,
+  pub canary_model: String,
+  /// the chat completion request as json
+  pub request: String,
+  pub output: String,
+  pub canary_output: String,
+  /// error running the request on the canary model, the canary output is empty if set
+  pub canary_error: Option<String>,
+  /// unified diff of the output against the canary output
+  pub diff: String,
+}
+
+/// Message matching a full-text search, with the matched terms wrapped in `<mark>` in the snippet
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageMatch {
+  pub conversation_id: String,
+  pub title: String,
+  pub message_id: String,
+  pub role: String,
+  pub snippet: String,
+  #[serde(with = "ts_milliseconds")]
+  pub created_at: DateTime<Utc>,
+}
+
+/// Column to sort the conversations by, the most recent conversation first
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConversationSort {
+  #[default]
+  CreatedAt,
+  UpdatedAt,
+}
+
+impl ConversationSort {
+  pub(crate) fn column(&self) -> &'static str {
+    match self {
+      ConversationSort::CreatedAt => "created_at",
+      ConversationSort::UpdatedAt => "updated_at",
+    }
+  }
+}
+
+/// Page of the conversations to list, all the conversations if `limit` is not set
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConversationsQuery {
+  pub sort: ConversationSort,
+  pub limit: Option<u32>,
+  pub offset: u32,
+  /// only the conversations with the tag
+  pub tag: Option<String>,
+  /// only the conversations of the owner, set from the API key of the request
+  #[serde(skip)]
+  pub owner: Option<String>,
 }
 
 #[cfg(test)]
@@ -75,7 +391,9 @@ mod test {
     title: "test title".to_string(),
     created_at: DateTime::<Utc>::from_timestamp_millis(1704070800000).unwrap(),
     updated_at: DateTime::<Utc>::default(),
+    tags: vec![],
     messages: vec![],
+    owner: None,
   })]
   #[case(
     r#"{
@@ -95,6 +413,7 @@ mod test {
     title: "test title".to_string(),
     created_at: DateTime::<Utc>::from_timestamp_millis(1704070800000).unwrap(),
     updated_at: DateTime::<Utc>::from_timestamp_millis(1704070800000).unwrap(),
+    tags: vec![],
     messages: vec![
       Message { 
         id: "".to_string(), 
@@ -103,7 +422,11 @@ mod test {
         name: None, 
         content: Some("What day comes after Monday?".to_string()), 
         created_at: DateTime::<Utc>::default(), 
+        model: None,
+        prompt_tokens: None,
+        completion_tokens: None,
       }],
+    owner: None,
   })]
   fn test_db_objs_serialize(
     #[case] input: String,
@@ -0,0 +1,969 @@
+use super::{
+  objs::{
+    ApiKey, ApiKeyRole, AuditAction, AuditEntry, CanarySample, ClientUsage, Conversation,
+    ConversationUsage, ConversationsQuery, Download, Message, MessageMatch, MigrationStatus,
+    ShareLink, UsagePeriod, UsageRecord, UsageStats,
+  },
+  service::{
+    api_key_hash, forked_conversation, from_api_key_row, from_audit_row, from_db_tags,
+    from_db_timestamp, from_download_row, merge_migration_status, new_api_key_secret, ApiKeyRow,
+    AuditRow, DownloadRow, API_KEYS, AUDIT_LOG, CANARY_SAMPLES, CONVERSATIONS, CONVERSATION_TAGS,
+    DOWNLOADS, MESSAGES, SHARE_LINKS, SQLX_MIGRATIONS, USAGE_RECORDS,
+  },
+  DbError, DbServiceFn, TimeServiceFn,
+};
+use crate::objs::RequestPriority;
+use chrono::{DateTime, Duration, Utc};
+use derive_new::new;
+use sqlx::{migrate::Migrator, PgExecutor, PgPool};
+use std::{path::Path, sync::Arc};
+use uuid::Uuid;
+
+/// Numbered migrations in `migrations_pg/`, same versions as the sqlite [`super::MIGRATOR`]
+pub static PG_MIGRATOR: Migrator = sqlx::migrate!("./migrations_pg");
+
+/// [`DbServiceFn`] backed by Postgres, for the deployments serving a team from a shared
+/// database. Timestamps are stored as epoch seconds same as in sqlite, and the `seq` column
+/// of messages keeps the order the messages are saved in.
+#[derive(Debug, Clone, new)]
+pub struct PgDbService {
+  pool: PgPool,
+  time_service: Arc<dyn TimeServiceFn>,
+}
+
+impl PgDbService {
+  // moves the active messages from the given seq onwards to a new branch
+  async fn branch_from(
+    &self,
+    executor: impl PgExecutor<'_>,
+    conversation_id: &str,
+    seq: i64,
+  ) -> Result<(), DbError> {
+    let branch_id = Uuid::new_v4().to_string();
+    sqlx::query(
+      "UPDATE messages SET branch_id = $1 WHERE conversation_id = $2 AND branch_id IS NULL AND seq >= $3",
+    )
+    .bind(&branch_id)
+    .bind(conversation_id)
+    .bind(seq)
+    .execute(executor)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: MESSAGES.to_string(),
+    })?;
+    Ok(())
+  }
+
+  async fn touch_conversation(
+    &self,
+    executor: impl PgExecutor<'_>,
+    conversation_id: &str,
+    now: DateTime<Utc>,
+  ) -> Result<(), DbError> {
+    sqlx::query("UPDATE conversations SET updated_at = $1 WHERE id = $2")
+      .bind(now.timestamp())
+      .bind(conversation_id)
+      .execute(executor)
+      .await
+      .map_err(|source| DbError::Sqlx {
+        source,
+        table: CONVERSATIONS.to_string(),
+      })?;
+    Ok(())
+  }
+
+  async fn find_active_message(
+    &self,
+    executor: impl PgExecutor<'_>,
+    conversation_id: &str,
+    message_id: &str,
+  ) -> Result<(i64, String, Option<String>), DbError> {
+    sqlx::query_as::<_, (i64, String, Option<String>)>(
+      "SELECT seq, role, name FROM messages WHERE id = $1 AND conversation_id = $2 AND branch_id IS NULL",
+    )
+    .bind(message_id)
+    .bind(conversation_id)
+    .fetch_one(executor)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: MESSAGES.to_string(),
+    })
+  }
+
+  async fn insert_message(
+    &self,
+    executor: impl PgExecutor<'_>,
+    message: &mut Message,
+  ) -> Result<(), DbError> {
+    if message.id.is_empty() {
+      message.id = Uuid::new_v4().to_string();
+    }
+    // the upsert updates only a message of the same conversation, a message id of another
+    // conversation is not taken over
+    let result = sqlx::query(
+      "INSERT INTO messages
+        (id, conversation_id, role, name, content, created_at, model, prompt_tokens, completion_tokens)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        ON CONFLICT(id) DO UPDATE SET role = $3, name = $4, content = $5, created_at = $6, model = $7, prompt_tokens = $8, completion_tokens = $9
+        WHERE messages.conversation_id = EXCLUDED.conversation_id",
+    )
+    .bind(&message.id)
+    .bind(&message.conversation_id)
+    .bind(&message.role)
+    .bind(&message.name)
+    .bind(&message.content)
+    .bind(message.created_at.timestamp())
+    .bind(&message.model)
+    .bind(message.prompt_tokens)
+    .bind(message.completion_tokens)
+    .execute(executor)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: MESSAGES.to_string(),
+    })?;
+    if result.rows_affected() == 0 {
+      return Err(DbError::MessageExists(message.id.clone()));
+    }
+    Ok(())
+  }
+
+  async fn delete_from(&self, table: &str, filter: Option<(&str, &str)>) -> Result<(), DbError> {
+    let result = match filter {
+      Some((column, value)) => {
+        sqlx::query(&format!("DELETE FROM {table} WHERE {column} = $1"))
+          .bind(value)
+          .execute(&self.pool)
+          .await
+      }
+      None => {
+        sqlx::query(&format!("DELETE FROM {table}"))
+          .execute(&self.pool)
+          .await
+      }
+    };
+    result.map_err(|source| DbError::Sqlx {
+      source,
+      table: table.to_string(),
+    })?;
+    Ok(())
+  }
+}
+
+#[async_trait::async_trait]
+impl DbServiceFn for PgDbService {
+  async fn migrate(&self) -> Result<(), DbError> {
+    PG_MIGRATOR.run(&self.pool).await?;
+    Ok(())
+  }
+
+  async fn migration_status(&self) -> Result<Vec<MigrationStatus>, DbError> {
+    let (exists,) = sqlx::query_as::<_, (bool,)>("SELECT to_regclass($1) IS NOT NULL")
+      .bind(SQLX_MIGRATIONS)
+      .fetch_one(&self.pool)
+      .await
+      .map_err(|source| DbError::Sqlx {
+        source,
+        table: SQLX_MIGRATIONS.to_string(),
+      })?;
+    let applied = if !exists {
+      vec![]
+    } else {
+      sqlx::query_as::<_, (i64, String, i64)>(
+        "SELECT version, description, EXTRACT(EPOCH FROM installed_on)::BIGINT
+          FROM _sqlx_migrations WHERE success",
+      )
+      .fetch_all(&self.pool)
+      .await
+      .map_err(|source| DbError::Sqlx {
+        source,
+        table: SQLX_MIGRATIONS.to_string(),
+      })?
+    };
+    Ok(merge_migration_status(&PG_MIGRATOR, applied))
+  }
+
+  async fn backup(&self, _path: &Path) -> Result<(), DbError> {
+    Err(DbError::Unsupported(
+      "backup of a postgres database, use pg_dump instead".to_string(),
+    ))
+  }
+
+  async fn save_conversation(&self, conversation: &mut Conversation) -> Result<(), DbError> {
+    let mut tx = self.pool.begin().await.map_err(|source| DbError::Sqlx {
+      source,
+      table: CONVERSATIONS.to_string(),
+    })?;
+    if conversation.id.is_empty() {
+      conversation.id = Uuid::new_v4().to_string()
+    } else {
+      // branched messages are retained, only the active messages are replaced
+      sqlx::query("DELETE FROM messages WHERE conversation_id = $1 AND branch_id IS NULL")
+        .bind(&conversation.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|source| DbError::Sqlx {
+          source,
+          table: MESSAGES.to_string(),
+        })?;
+    }
+    conversation.updated_at = self.time_service.utc_now();
+    sqlx::query(
+      "INSERT INTO conversations (id, title, created_at, updated_at, owner)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT(id) DO UPDATE SET title = $2, updated_at = $4",
+    )
+    .bind(&conversation.id)
+    .bind(&conversation.title)
+    .bind(conversation.created_at.timestamp())
+    .bind(conversation.updated_at.timestamp())
+    .bind(&conversation.owner)
+    .execute(&mut *tx)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: CONVERSATIONS.to_string(),
+    })?;
+    for message in &mut conversation.messages {
+      message.conversation_id.clone_from(&conversation.id);
+      self.insert_message(&mut *tx, message).await?;
+    }
+    tx.commit().await.map_err(|source| DbError::Sqlx {
+      source,
+      table: CONVERSATIONS.to_string(),
+    })?;
+    Ok(())
+  }
+
+  async fn save_message(&self, message: &mut Message) -> Result<(), DbError> {
+    self.insert_message(&self.pool, message).await
+  }
+
+  async fn list_conversations(
+    &self,
+    query: &ConversationsQuery,
+  ) -> Result<Vec<Conversation>, DbError> {
+    // id breaks the ties of the sort column, so the pages do not overlap
+    let sql = format!(
+      "SELECT id, title, created_at, updated_at,
+          COALESCE((SELECT json_agg(tag)::TEXT FROM conversation_tags t WHERE t.conversation_id = c.id), '[]') AS tags,
+          owner
+        FROM conversations c
+        WHERE ($1::TEXT IS NULL OR id IN (SELECT conversation_id FROM conversation_tags WHERE tag = $1))
+          AND ($4::TEXT IS NULL OR owner = $4)
+        ORDER BY {} DESC, id DESC
+        LIMIT $2 OFFSET $3",
+      query.sort.column()
+    );
+    // a null limit is no limit in postgres
+    let conversations =
+      sqlx::query_as::<_, (String, String, i64, i64, String, Option<String>)>(&sql)
+        .bind(&query.tag)
+        .bind(query.limit.map(i64::from))
+        .bind(i64::from(query.offset))
+        .bind(&query.owner)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|source| DbError::Sqlx {
+          source,
+          table: CONVERSATIONS.to_string(),
+        })?;
+    let result = conversations
+      .into_iter()
+      .map(
+        |(id, title, created_at, updated_at, tags, owner)| Conversation {
+          id,
+          title,
+          created_at: from_db_timestamp(created_at),
+          updated_at: from_db_timestamp(updated_at),
+          tags: from_db_tags(&tags),
+          messages: Vec::new(),
+          owner,
+        },
+      )
+      .collect();
+    Ok(result)
+  }
+
+  async fn get_conversation_with_messages(&self, id: &str) -> Result<Conversation, DbError> {
+    let messages = sqlx::query_as::<_, Message>(
+      "SELECT id, conversation_id, role, name, content, to_timestamp(created_at) AS created_at, model, prompt_tokens, completion_tokens
+        FROM messages WHERE conversation_id = $1 AND branch_id IS NULL ORDER BY seq",
+    )
+    .bind(id)
+    .fetch_all(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: MESSAGES.to_string(),
+    })?;
+    let (id, title, created_at, updated_at, tags, owner) =
+      sqlx::query_as::<_, (String, String, i64, i64, String, Option<String>)>(
+        "SELECT id, title, created_at, updated_at,
+            COALESCE((SELECT json_agg(tag)::TEXT FROM conversation_tags t WHERE t.conversation_id = c.id), '[]') AS tags,
+            owner
+          FROM conversations c WHERE id = $1",
+      )
+      .bind(id)
+      .fetch_one(&self.pool)
+      .await
+      .map_err(|source| DbError::Sqlx {
+        source,
+        table: CONVERSATIONS.to_string(),
+      })?;
+    Ok(Conversation {
+      id,
+      title,
+      created_at: from_db_timestamp(created_at),
+      updated_at: from_db_timestamp(updated_at),
+      tags: from_db_tags(&tags),
+      messages,
+      owner,
+    })
+  }
+
+  async fn edit_message(
+    &self,
+    conversation_id: &str,
+    message_id: &str,
+    content: &str,
+  ) -> Result<Message, DbError> {
+    let mut tx = self.pool.begin().await.map_err(|source| DbError::Sqlx {
+      source,
+      table: MESSAGES.to_string(),
+    })?;
+    let (seq, role, name) = self
+      .find_active_message(&mut *tx, conversation_id, message_id)
+      .await?;
+    self.branch_from(&mut *tx, conversation_id, seq).await?;
+    let now = self.time_service.utc_now();
+    let mut message = Message {
+      id: Uuid::new_v4().to_string(),
+      conversation_id: conversation_id.to_string(),
+      role,
+      name,
+      content: Some(content.to_string()),
+      created_at: now,
+      ..Default::default()
+    };
+    self.insert_message(&mut *tx, &mut message).await?;
+    self
+      .touch_conversation(&mut *tx, conversation_id, now)
+      .await?;
+    tx.commit().await.map_err(|source| DbError::Sqlx {
+      source,
+      table: MESSAGES.to_string(),
+    })?;
+    Ok(message)
+  }
+
+  async fn branch_messages(&self, conversation_id: &str, message_id: &str) -> Result<(), DbError> {
+    let (seq, _, _) = self
+      .find_active_message(&self.pool, conversation_id, message_id)
+      .await?;
+    self.branch_from(&self.pool, conversation_id, seq).await?;
+    self
+      .touch_conversation(&self.pool, conversation_id, self.time_service.utc_now())
+      .await
+  }
+
+  async fn fork_conversation(
+    &self,
+    conversation_id: &str,
+    message_id: &str,
+  ) -> Result<Conversation, DbError> {
+    let original = self.get_conversation_with_messages(conversation_id).await?;
+    let mut conversation = forked_conversation(original, message_id, self.time_service.utc_now())?;
+    self.save_conversation(&mut conversation).await?;
+    Ok(conversation)
+  }
+
+  async fn delete_conversations(&self, id: &str) -> Result<(), DbError> {
+    for table in [CONVERSATION_TAGS, SHARE_LINKS, MESSAGES] {
+      self
+        .delete_from(table, Some(("conversation_id", id)))
+        .await?;
+    }
+    self.delete_from(CONVERSATIONS, Some(("id", id))).await
+  }
+
+  async fn delete_all_conversations(&self, owner: Option<&str>) -> Result<(), DbError> {
+    let Some(owner) = owner else {
+      for table in [CONVERSATION_TAGS, SHARE_LINKS, MESSAGES, CONVERSATIONS] {
+        self.delete_from(table, None).await?;
+      }
+      return Ok(());
+    };
+    for table in [CONVERSATION_TAGS, SHARE_LINKS, MESSAGES] {
+      sqlx::query(&format!(
+        "DELETE FROM {table} WHERE conversation_id IN (SELECT id FROM conversations WHERE owner = $1)"
+      ))
+      .bind(owner)
+      .execute(&self.pool)
+      .await
+      .map_err(|source| DbError::Sqlx {
+        source,
+        table: table.to_string(),
+      })?;
+    }
+    self
+      .delete_from(CONVERSATIONS, Some(("owner", owner)))
+      .await
+  }
+
+  async fn create_share_link(
+    &self,
+    conversation_id: &str,
+    expires_in: Duration,
+  ) -> Result<ShareLink, DbError> {
+    sqlx::query_as::<_, (String,)>("SELECT id FROM conversations WHERE id = $1")
+      .bind(conversation_id)
+      .fetch_one(&self.pool)
+      .await
+      .map_err(|source| DbError::Sqlx {
+        source,
+        table: CONVERSATIONS.to_string(),
+      })?;
+    let now = self.time_service.utc_now();
+    let share_link = ShareLink {
+      token: Uuid::new_v4().simple().to_string(),
+      conversation_id: conversation_id.to_string(),
+      created_at: now,
+      expires_at: now + expires_in,
+    };
+    sqlx::query(
+      "INSERT INTO share_links (token, conversation_id, created_at, expires_at) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(&share_link.token)
+    .bind(&share_link.conversation_id)
+    .bind(share_link.created_at.timestamp())
+    .bind(share_link.expires_at.timestamp())
+    .execute(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: SHARE_LINKS.to_string(),
+    })?;
+    Ok(share_link)
+  }
+
+  async fn get_shared_conversation(&self, token: &str) -> Result<Conversation, DbError> {
+    let (conversation_id,) = sqlx::query_as::<_, (String,)>(
+      "SELECT conversation_id FROM share_links WHERE token = $1 AND expires_at > $2",
+    )
+    .bind(token)
+    .bind(self.time_service.utc_now().timestamp())
+    .fetch_one(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: SHARE_LINKS.to_string(),
+    })?;
+    let mut conversation = self
+      .get_conversation_with_messages(&conversation_id)
+      .await?;
+    // tags are for the owner to organize the chats, not shared
+    conversation.tags.clear();
+    Ok(conversation)
+  }
+
+  async fn save_usage(&self, record: &mut UsageRecord) -> Result<(), DbError> {
+    record.id = Uuid::new_v4().to_string();
+    record.created_at = self.time_service.utc_now();
+    sqlx::query(
+      "INSERT INTO usage_records (id, created_at, model, user_agent, app_name, prompt_tokens, completion_tokens, latency_ms) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+    )
+    .bind(&record.id)
+    .bind(record.created_at.timestamp())
+    .bind(&record.model)
+    .bind(&record.user_agent)
+    .bind(&record.app_name)
+    .bind(record.prompt_tokens)
+    .bind(record.completion_tokens)
+    .bind(record.latency_ms)
+    .execute(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: USAGE_RECORDS.to_string(),
+    })?;
+    Ok(())
+  }
+
+  async fn list_client_usage(
+    &self,
+    since: Option<DateTime<Utc>>,
+    limit: u32,
+  ) -> Result<Vec<ClientUsage>, DbError> {
+    let since = since.map(|since| since.timestamp()).unwrap_or(i64::MIN);
+    // SUM of BIGINT is NUMERIC in postgres, cast back to decode as i64
+    let clients = sqlx::query_as::<_, ClientUsage>(
+      "SELECT user_agent, app_name, COUNT(*) AS requests, SUM(prompt_tokens)::BIGINT AS prompt_tokens, SUM(completion_tokens)::BIGINT AS completion_tokens
+        FROM usage_records
+        WHERE created_at >= $1
+        GROUP BY user_agent, app_name
+        ORDER BY requests DESC, SUM(prompt_tokens) + SUM(completion_tokens) DESC
+        LIMIT $2",
+    )
+    .bind(since)
+    .bind(i64::from(limit))
+    .fetch_all(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: USAGE_RECORDS.to_string(),
+    })?;
+    Ok(clients)
+  }
+
+  async fn list_usage_stats(
+    &self,
+    period: UsagePeriod,
+    since: Option<DateTime<Utc>>,
+    limit: u32,
+  ) -> Result<Vec<UsageStats>, DbError> {
+    let since = since.map(|since| since.timestamp()).unwrap_or(i64::MIN);
+    // same period labels as in sqlite, date_trunc starts the weeks on Monday
+    let period = match period {
+      UsagePeriod::Hour => {
+        "to_char(to_timestamp(created_at) AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:00')"
+      }
+      UsagePeriod::Day => "to_char(to_timestamp(created_at) AT TIME ZONE 'UTC', 'YYYY-MM-DD')",
+      UsagePeriod::Week => {
+        "to_char(date_trunc('week', to_timestamp(created_at) AT TIME ZONE 'UTC'), 'YYYY-MM-DD')"
+      }
+      UsagePeriod::Month => "to_char(to_timestamp(created_at) AT TIME ZONE 'UTC', 'YYYY-MM')",
+    };
+    let stats = sqlx::query_as::<_, UsageStats>(&format!(
+      "SELECT {period} AS period, model, COALESCE(app_name, user_agent) AS client, COUNT(*) AS requests,
+          SUM(prompt_tokens)::BIGINT AS prompt_tokens, SUM(completion_tokens)::BIGINT AS completion_tokens,
+          AVG(latency_ms)::BIGINT AS avg_latency_ms
+        FROM usage_records
+        WHERE created_at >= $1
+        GROUP BY 1, 2, 3
+        ORDER BY 1 DESC, requests DESC, SUM(prompt_tokens) + SUM(completion_tokens) DESC, 2, 3
+        LIMIT $2"
+    ))
+    .bind(since)
+    .bind(i64::from(limit))
+    .fetch_all(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: USAGE_RECORDS.to_string(),
+    })?;
+    Ok(stats)
+  }
+
+  async fn list_conversation_usage(
+    &self,
+    since: Option<DateTime<Utc>>,
+    owner: Option<&str>,
+    limit: u32,
+  ) -> Result<Vec<ConversationUsage>, DbError> {
+    let since = since.map(|since| since.timestamp()).unwrap_or(i64::MIN);
+    let conversations = sqlx::query_as::<_, ConversationUsage>(
+      "SELECT c.id AS conversation_id, c.title, COUNT(*) AS replies,
+          COALESCE(SUM(m.prompt_tokens), 0)::BIGINT AS prompt_tokens, COALESCE(SUM(m.completion_tokens), 0)::BIGINT AS completion_tokens
+        FROM messages m
+        JOIN conversations c ON c.id = m.conversation_id
+        WHERE m.created_at >= $1 AND (m.prompt_tokens IS NOT NULL OR m.completion_tokens IS NOT NULL)
+          AND ($3::TEXT IS NULL OR c.owner = $3)
+        GROUP BY c.id, c.title
+        ORDER BY COALESCE(SUM(m.prompt_tokens), 0) + COALESCE(SUM(m.completion_tokens), 0) DESC, c.id
+        LIMIT $2",
+    )
+    .bind(since)
+    .bind(i64::from(limit))
+    .bind(owner)
+    .fetch_all(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: MESSAGES.to_string(),
+    })?;
+    Ok(conversations)
+  }
+
+  async fn search_messages(
+    &self,
+    query: &str,
+    owner: Option<&str>,
+    limit: u32,
+  ) -> Result<Vec<MessageMatch>, DbError> {
+    let Some(query) = tsquery(query) else {
+      return Ok(vec![]);
+    };
+    let matches = sqlx::query_as::<_, MessageMatch>(
+      "SELECT c.id AS conversation_id, c.title, m.id AS message_id, m.role,
+          ts_headline('english', COALESCE(m.content, ''), q, 'StartSel=<mark>, StopSel=</mark>, MaxWords=16, MinWords=4') AS snippet,
+          to_timestamp(m.created_at) AS created_at
+        FROM messages m
+        JOIN conversations c ON c.id = m.conversation_id
+        CROSS JOIN to_tsquery('english', $1) AS q
+        WHERE m.content_tsv @@ q AND m.branch_id IS NULL AND ($3::TEXT IS NULL OR c.owner = $3)
+        ORDER BY ts_rank(m.content_tsv, q) DESC
+        LIMIT $2",
+    )
+    .bind(query)
+    .bind(i64::from(limit))
+    .bind(owner)
+    .fetch_all(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: MESSAGES.to_string(),
+    })?;
+    Ok(matches)
+  }
+
+  async fn tag_conversation(&self, conversation_id: &str, tag: &str) -> Result<(), DbError> {
+    sqlx::query_as::<_, (String,)>("SELECT id FROM conversations WHERE id = $1")
+      .bind(conversation_id)
+      .fetch_one(&self.pool)
+      .await
+      .map_err(|source| DbError::Sqlx {
+        source,
+        table: CONVERSATIONS.to_string(),
+      })?;
+    sqlx::query(
+      "INSERT INTO conversation_tags (conversation_id, tag, created_at) VALUES ($1, $2, $3)
+        ON CONFLICT(conversation_id, tag) DO NOTHING",
+    )
+    .bind(conversation_id)
+    .bind(tag)
+    .bind(self.time_service.utc_now().timestamp())
+    .execute(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: CONVERSATION_TAGS.to_string(),
+    })?;
+    Ok(())
+  }
+
+  async fn untag_conversation(&self, conversation_id: &str, tag: &str) -> Result<(), DbError> {
+    sqlx::query("DELETE FROM conversation_tags WHERE conversation_id = $1 AND tag = $2")
+      .bind(conversation_id)
+      .bind(tag)
+      .execute(&self.pool)
+      .await
+      .map_err(|source| DbError::Sqlx {
+        source,
+        table: CONVERSATION_TAGS.to_string(),
+      })?;
+    Ok(())
+  }
+
+  async fn save_canary_sample(&self, sample: &mut CanarySample) -> Result<(), DbError> {
+    sample.id = Uuid::new_v4().to_string();
+    sample.created_at = self.time_service.utc_now();
+    sqlx::query(
+      "INSERT INTO canary_samples (id, created_at, model, canary_model, request, output, canary_output, canary_error, diff) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+    )
+    .bind(&sample.id)
+    .bind(sample.created_at.timestamp())
+    .bind(&sample.model)
+    .bind(&sample.canary_model)
+    .bind(&sample.request)
+    .bind(&sample.output)
+    .bind(&sample.canary_output)
+    .bind(&sample.canary_error)
+    .bind(&sample.diff)
+    .execute(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: CANARY_SAMPLES.to_string(),
+    })?;
+    Ok(())
+  }
+
+  async fn list_canary_samples(&self, limit: u32) -> Result<Vec<CanarySample>, DbError> {
+    let samples = sqlx::query_as::<_, CanarySample>(
+      "SELECT id, to_timestamp(created_at) AS created_at, model, canary_model, request, output, canary_output, canary_error, diff
+        FROM canary_samples
+        ORDER BY created_at DESC, id DESC
+        LIMIT $1",
+    )
+    .bind(i64::from(limit))
+    .fetch_all(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: CANARY_SAMPLES.to_string(),
+    })?;
+    Ok(samples)
+  }
+
+  async fn create_api_key(
+    &self,
+    name: &str,
+    role: ApiKeyRole,
+    priority: RequestPriority,
+  ) -> Result<(ApiKey, String), DbError> {
+    let existing = sqlx::query_as::<_, (String,)>(
+      "SELECT id FROM api_keys WHERE name = $1 AND revoked_at IS NULL",
+    )
+    .bind(name)
+    .fetch_optional(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: API_KEYS.to_string(),
+    })?;
+    if existing.is_some() {
+      return Err(DbError::ApiKeyExists(name.to_string()));
+    }
+    let secret = new_api_key_secret();
+    let api_key = ApiKey {
+      id: Uuid::new_v4().to_string(),
+      name: name.to_string(),
+      role,
+      priority,
+      created_at: self.time_service.utc_now(),
+      revoked_at: None,
+    };
+    sqlx::query(
+      "INSERT INTO api_keys (id, name, role, priority, key_hash, created_at) VALUES ($1, $2, $3, $4, $5, $6)",
+    )
+    .bind(&api_key.id)
+    .bind(&api_key.name)
+    .bind(api_key.role.to_string())
+    .bind(api_key.priority.to_string())
+    .bind(api_key_hash(&secret))
+    .bind(api_key.created_at.timestamp())
+    .execute(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: API_KEYS.to_string(),
+    })?;
+    Ok((api_key, secret))
+  }
+
+  async fn list_api_keys(&self) -> Result<Vec<ApiKey>, DbError> {
+    let rows = sqlx::query_as::<_, ApiKeyRow>(
+      "SELECT id, name, role, priority, created_at, revoked_at FROM api_keys ORDER BY created_at, id",
+    )
+    .fetch_all(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: API_KEYS.to_string(),
+    })?;
+    Ok(rows.into_iter().map(from_api_key_row).collect())
+  }
+
+  async fn revoke_api_key(&self, id: &str) -> Result<(), DbError> {
+    let result =
+      sqlx::query("UPDATE api_keys SET revoked_at = $1 WHERE id = $2 AND revoked_at IS NULL")
+        .bind(self.time_service.utc_now().timestamp())
+        .bind(id)
+        .execute(&self.pool)
+        .await
+        .map_err(|source| DbError::Sqlx {
+          source,
+          table: API_KEYS.to_string(),
+        })?;
+    if result.rows_affected() == 0 {
+      return Err(DbError::Sqlx {
+        source: sqlx::Error::RowNotFound,
+        table: API_KEYS.to_string(),
+      });
+    }
+    Ok(())
+  }
+
+  async fn find_api_key(&self, secret: &str) -> Result<ApiKey, DbError> {
+    let row = sqlx::query_as::<_, ApiKeyRow>(
+      "SELECT id, name, role, priority, created_at, revoked_at FROM api_keys WHERE key_hash = $1 AND revoked_at IS NULL",
+    )
+    .bind(api_key_hash(secret))
+    .fetch_one(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: API_KEYS.to_string(),
+    })?;
+    Ok(from_api_key_row(row))
+  }
+
+  async fn save_audit_entry(&self, entry: &mut AuditEntry) -> Result<(), DbError> {
+    entry.id = Uuid::new_v4().to_string();
+    entry.created_at = self.time_service.utc_now();
+    sqlx::query(
+      "INSERT INTO audit_log (id, created_at, actor, action, target, before_value, after_value)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)",
+    )
+    .bind(&entry.id)
+    .bind(entry.created_at.timestamp())
+    .bind(&entry.actor)
+    .bind(entry.action.to_string())
+    .bind(&entry.target)
+    .bind(&entry.before)
+    .bind(&entry.after)
+    .execute(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: AUDIT_LOG.to_string(),
+    })?;
+    Ok(())
+  }
+
+  async fn list_audit_entries(
+    &self,
+    action: Option<AuditAction>,
+    since: Option<DateTime<Utc>>,
+    limit: u32,
+  ) -> Result<Vec<AuditEntry>, DbError> {
+    let since = since.map(|since| since.timestamp()).unwrap_or(i64::MIN);
+    let rows = sqlx::query_as::<_, AuditRow>(
+      "SELECT id, created_at, actor, action, target, before_value, after_value
+        FROM audit_log
+        WHERE created_at >= $1 AND ($2::TEXT IS NULL OR action = $2)
+        ORDER BY created_at DESC, seq DESC
+        LIMIT $3",
+    )
+    .bind(since)
+    .bind(action.map(|action| action.to_string()))
+    .bind(i64::from(limit))
+    .fetch_all(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: AUDIT_LOG.to_string(),
+    })?;
+    Ok(rows.into_iter().filter_map(from_audit_row).collect())
+  }
+
+  async fn create_download(&self, download: &mut Download) -> Result<(), DbError> {
+    download.id = Uuid::new_v4().to_string();
+    download.created_at = self.time_service.utc_now();
+    download.updated_at = download.created_at;
+    sqlx::query(
+      "INSERT INTO downloads (id, repo, filename, revision, status, downloaded_bytes, total_bytes, error, created_at, updated_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+    )
+    .bind(&download.id)
+    .bind(&download.repo)
+    .bind(&download.filename)
+    .bind(&download.revision)
+    .bind(download.status.to_string())
+    .bind(download.downloaded_bytes as i64)
+    .bind(download.total_bytes.map(|total| total as i64))
+    .bind(&download.error)
+    .bind(download.created_at.timestamp())
+    .bind(download.updated_at.timestamp())
+    .execute(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: DOWNLOADS.to_string(),
+    })?;
+    Ok(())
+  }
+
+  async fn update_download(&self, download: &mut Download) -> Result<(), DbError> {
+    download.updated_at = self.time_service.utc_now();
+    let result = sqlx::query(
+      "UPDATE downloads SET status = $1, downloaded_bytes = $2, total_bytes = $3, error = $4, updated_at = $5
+        WHERE id = $6",
+    )
+    .bind(download.status.to_string())
+    .bind(download.downloaded_bytes as i64)
+    .bind(download.total_bytes.map(|total| total as i64))
+    .bind(&download.error)
+    .bind(download.updated_at.timestamp())
+    .bind(&download.id)
+    .execute(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: DOWNLOADS.to_string(),
+    })?;
+    if result.rows_affected() == 0 {
+      return Err(DbError::Sqlx {
+        source: sqlx::Error::RowNotFound,
+        table: DOWNLOADS.to_string(),
+      });
+    }
+    Ok(())
+  }
+
+  async fn get_download(&self, id: &str) -> Result<Download, DbError> {
+    let row = sqlx::query_as::<_, DownloadRow>(
+      "SELECT id, repo, filename, revision, status, downloaded_bytes, total_bytes, error, created_at, updated_at
+        FROM downloads WHERE id = $1",
+    )
+    .bind(id)
+    .fetch_one(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: DOWNLOADS.to_string(),
+    })?;
+    Ok(from_download_row(row))
+  }
+
+  async fn list_downloads(&self, limit: u32) -> Result<Vec<Download>, DbError> {
+    let rows = sqlx::query_as::<_, DownloadRow>(
+      "SELECT id, repo, filename, revision, status, downloaded_bytes, total_bytes, error, created_at, updated_at
+        FROM downloads
+        ORDER BY created_at DESC, seq DESC
+        LIMIT $1",
+    )
+    .bind(i64::from(limit))
+    .fetch_all(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: DOWNLOADS.to_string(),
+    })?;
+    Ok(rows.into_iter().map(from_download_row).collect())
+  }
+}
+
+/// Quotes each term of the user query as a lexeme of the tsquery, so the tsquery syntax
+/// characters are matched as text, the last term matches as a prefix same as in sqlite
+fn tsquery(query: &str) -> Option<String> {
+  let terms = query
+    .split_whitespace()
+    .map(|term| format!("'{}'", term.replace('\\', "\\\\").replace('\'', "''")))
+    .collect::<Vec<_>>();
+  if terms.is_empty() {
+    return None;
+  }
+  Some(format!("{}:*", terms.join(" & ")))
+}
+
+#[cfg(test)]
+mod test {
+  use super::{tsquery, PG_MIGRATOR};
+  use crate::db::MIGRATOR;
+  use rstest::rstest;
+
+  #[rstest]
+  #[case("", None)]
+  #[case("rust", Some("'rust':*"))]
+  #[case("rust async", Some("'rust' & 'async':*"))]
+  #[case("it's a\\b", Some("'it''s' & 'a\\\\b':*"))]
+  fn test_pg_service_tsquery(#[case] query: &str, #[case] expected: Option<&str>) {
+    assert_eq!(expected.map(str::to_string), tsquery(query));
+  }
+
+  #[rstest]
+  fn test_pg_service_migrations_match_sqlite() {
+    let versions = |migrator: &sqlx::migrate::Migrator| {
+      migrator
+        .iter()
+        .filter(|migration| migration.migration_type.is_up_migration())
+        .map(|migration| (migration.version, migration.description.to_string()))
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(versions(&MIGRATOR), versions(&PG_MIGRATOR));
+  }
+}
@@ -1,15 +1,37 @@
 use super::{
   no_op::NoOpDbService,
-  objs::{Conversation, Message},
+  objs::{
+    ApiKey, ApiKeyRole, AuditAction, AuditEntry, CanarySample, ClientUsage, Conversation,
+    ConversationUsage, ConversationsQuery, Download, DownloadStatus, Message, MessageMatch,
+    MigrationStatus, ShareLink, UsagePeriod, UsageRecord, UsageStats,
+  },
 };
-use chrono::{DateTime, Timelike, Utc};
+use crate::objs::RequestPriority;
+use chrono::{DateTime, Duration, Timelike, Utc};
 use derive_new::new;
-use sqlx::{migrate::MigrateError, SqlitePool};
-use std::sync::Arc;
+use sha2::{Digest, Sha256};
+use sqlx::{
+  migrate::{MigrateError, Migrator},
+  SqliteExecutor, SqlitePool,
+};
+use std::{path::Path, sync::Arc};
 use uuid::Uuid;
 
 pub static CONVERSATIONS: &str = "conversations";
 pub static MESSAGES: &str = "messages";
+pub static SHARE_LINKS: &str = "share_links";
+pub static USAGE_RECORDS: &str = "usage_records";
+pub static MESSAGES_FTS: &str = "messages_fts";
+pub static CANARY_SAMPLES: &str = "canary_samples";
+pub static CONVERSATION_TAGS: &str = "conversation_tags";
+pub static API_KEYS: &str = "api_keys";
+pub static AUDIT_LOG: &str = "audit_log";
+pub static DOWNLOADS: &str = "downloads";
+pub static SQLX_MIGRATIONS: &str = "_sqlx_migrations";
+
+/// Numbered migrations in `migrations/`, applied in order and only forward, the applied
+/// versions are recorded in the `_sqlx_migrations` table of the database
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
 
 pub trait TimeServiceFn: std::fmt::Debug + Send + Sync {
   fn utc_now(&self) -> DateTime<Utc>;
@@ -25,6 +47,12 @@ impl TimeServiceFn for TimeService {
   }
 }
 
+/// Timestamps are stored as UTC epoch seconds, they are converted to the
+/// user's timezone only when rendered, see [`crate::objs::DisplayTimezone`]
+pub(crate) fn from_db_timestamp(secs: i64) -> DateTime<Utc> {
+  DateTime::<Utc>::from_timestamp(secs, 0).unwrap_or_default()
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum DbError {
   #[error("sqlx_query: {source}\ntable: {table}")]
@@ -41,23 +69,176 @@ pub enum DbError {
   },
   #[error("sqlx_migrate: {0}")]
   Migrate(#[from] MigrateError),
+  #[error("unsupported: {0}")]
+  Unsupported(String),
+  #[error("api_key_exists: an active API key with the name '{0}' already exists")]
+  ApiKeyExists(String),
+  #[error("message_exists: the message '{0}' belongs to another conversation")]
+  MessageExists(String),
 }
 
 #[async_trait::async_trait]
 pub trait DbServiceFn: std::fmt::Debug + Send + Sync {
+  /// Applies the pending migrations, fails if the database has a migration applied by a
+  /// newer version of the app
   async fn migrate(&self) -> Result<(), DbError>;
 
+  /// Status of the migrations known to the app, and of the unknown migrations applied to
+  /// the database, ordered by version
+  async fn migration_status(&self) -> Result<Vec<MigrationStatus>, DbError>;
+
+  /// Writes a consistent snapshot of the database to a new file at `path`, while the database
+  /// is in use
+  async fn backup(&self, path: &Path) -> Result<(), DbError>;
+
+  /// Saves the conversation, the owner of an existing conversation is not changed. Fails with
+  /// `MessageExists` if a message has the id of a message of another conversation.
   async fn save_conversation(&self, conversation: &mut Conversation) -> Result<(), DbError>;
 
   async fn save_message(&self, message: &mut Message) -> Result<(), DbError>;
 
-  async fn list_conversations(&self) -> Result<Vec<Conversation>, DbError>;
+  async fn list_conversations(
+    &self,
+    query: &ConversationsQuery,
+  ) -> Result<Vec<Conversation>, DbError>;
 
   async fn delete_conversations(&self, id: &str) -> Result<(), DbError>;
 
-  async fn delete_all_conversations(&self) -> Result<(), DbError>;
+  /// Deletes the conversations of the owner, all the conversations if `owner` is not set
+  async fn delete_all_conversations(&self, owner: Option<&str>) -> Result<(), DbError>;
 
   async fn get_conversation_with_messages(&self, id: &str) -> Result<Conversation, DbError>;
+
+  /// Replaces the content of the message, the message and its downstream messages are
+  /// kept as a branch, and a new message with the updated content is returned.
+  async fn edit_message(
+    &self,
+    conversation_id: &str,
+    message_id: &str,
+    content: &str,
+  ) -> Result<Message, DbError>;
+
+  /// Moves the message and its downstream messages to a branch, e.g. to regenerate a reply.
+  /// Fails with `RowNotFound` if the active message does not exist.
+  async fn branch_messages(&self, conversation_id: &str, message_id: &str) -> Result<(), DbError>;
+
+  /// Copies the conversation up to and including the message into a new conversation of the same owner.
+  /// The messages in branches and the tags are not copied.
+  /// Fails with `RowNotFound` if the conversation or the active message does not exist.
+  async fn fork_conversation(
+    &self,
+    conversation_id: &str,
+    message_id: &str,
+  ) -> Result<Conversation, DbError>;
+
+  /// Creates a read-only link to the conversation, valid for `expires_in` from now
+  async fn create_share_link(
+    &self,
+    conversation_id: &str,
+    expires_in: Duration,
+  ) -> Result<ShareLink, DbError>;
+
+  /// Conversation with messages for the share link token, fails with `RowNotFound`
+  /// if the token does not exist or has expired
+  async fn get_shared_conversation(&self, token: &str) -> Result<Conversation, DbError>;
+
+  /// Adds the record to the usage ledger, `id` and `created_at` are set by the service
+  async fn save_usage(&self, record: &mut UsageRecord) -> Result<(), DbError>;
+
+  /// Usage grouped by client, optionally only the requests since the given time,
+  /// ordered by the number of requests, top clients first
+  async fn list_client_usage(
+    &self,
+    since: Option<DateTime<Utc>>,
+    limit: u32,
+  ) -> Result<Vec<ClientUsage>, DbError>;
+
+  /// Usage grouped by period, model and client, optionally only the requests since the given
+  /// time, latest periods first and the top models and clients first in a period
+  async fn list_usage_stats(
+    &self,
+    period: UsagePeriod,
+    since: Option<DateTime<Utc>>,
+    limit: u32,
+  ) -> Result<Vec<UsageStats>, DbError>;
+
+  /// Token usage of the replies generated by the server grouped by conversation, including the
+  /// replies in branches, optionally only the replies since the given time, top conversations first.
+  /// Only the conversations of the owner are included if `owner` is set.
+  async fn list_conversation_usage(
+    &self,
+    since: Option<DateTime<Utc>>,
+    owner: Option<&str>,
+    limit: u32,
+  ) -> Result<Vec<ConversationUsage>, DbError>;
+
+  /// Full-text search over the active messages, best matches first.
+  /// The terms in `query` are matched as words, the last one as a prefix.
+  /// Only the conversations of the owner are searched if `owner` is set.
+  async fn search_messages(
+    &self,
+    query: &str,
+    owner: Option<&str>,
+    limit: u32,
+  ) -> Result<Vec<MessageMatch>, DbError>;
+
+  /// Adds the tag to the conversation, tagging again with the same tag is a no-op.
+  /// Fails with `RowNotFound` if the conversation does not exist.
+  async fn tag_conversation(&self, conversation_id: &str, tag: &str) -> Result<(), DbError>;
+
+  /// Removes the tag from the conversation, if tagged
+  async fn untag_conversation(&self, conversation_id: &str, tag: &str) -> Result<(), DbError>;
+
+  /// Saves the canary sample, `id` and `created_at` are set by the service
+  async fn save_canary_sample(&self, sample: &mut CanarySample) -> Result<(), DbError>;
+
+  /// Most recent canary samples first
+  async fn list_canary_samples(&self, limit: u32) -> Result<Vec<CanarySample>, DbError>;
+
+  /// Creates an API key with the role and the priority of its requests, returns the key along
+  /// with the generated secret. The secret is not stored and cannot be retrieved later.
+  /// Fails with `ApiKeyExists` if an active key has the name, as the chats are owned by the name
+  /// of the key. The name of a revoked key can be reused, to keep its chats.
+  async fn create_api_key(
+    &self,
+    name: &str,
+    role: ApiKeyRole,
+    priority: RequestPriority,
+  ) -> Result<(ApiKey, String), DbError>;
+
+  /// All the API keys including the revoked keys, the oldest first
+  async fn list_api_keys(&self) -> Result<Vec<ApiKey>, DbError>;
+
+  /// Revokes the API key, fails with `RowNotFound` if the key does not exist or is already revoked
+  async fn revoke_api_key(&self, id: &str) -> Result<(), DbError>;
+
+  /// Active API key having the secret, fails with `RowNotFound` if no such key exists
+  /// or the key is revoked
+  async fn find_api_key(&self, secret: &str) -> Result<ApiKey, DbError>;
+
+  /// Adds the entry to the audit log, `id` and `created_at` are set by the service
+  async fn save_audit_entry(&self, entry: &mut AuditEntry) -> Result<(), DbError>;
+
+  /// Most recent audit entries first, optionally only of the action and since the given time
+  async fn list_audit_entries(
+    &self,
+    action: Option<AuditAction>,
+    since: Option<DateTime<Utc>>,
+    limit: u32,
+  ) -> Result<Vec<AuditEntry>, DbError>;
+
+  /// Saves the download, `id`, `created_at` and `updated_at` are set by the service
+  async fn create_download(&self, download: &mut Download) -> Result<(), DbError>;
+
+  /// Saves the status, the progress and the error of the download, `updated_at` is set by the
+  /// service. Fails with `RowNotFound` if the download does not exist
+  async fn update_download(&self, download: &mut Download) -> Result<(), DbError>;
+
+  /// Fails with `RowNotFound` if the download does not exist
+  async fn get_download(&self, id: &str) -> Result<Download, DbError>;
+
+  /// Most recent downloads first
+  async fn list_downloads(&self, limit: u32) -> Result<Vec<Download>, DbError>;
 }
 
 #[derive(Debug, Clone, new)]
@@ -70,59 +251,59 @@ impl DbService {
   pub fn no_op() -> impl DbServiceFn {
     NoOpDbService::new()
   }
-}
-
-#[async_trait::async_trait]
-impl DbServiceFn for DbService {
-  async fn migrate(&self) -> Result<(), DbError> {
-    sqlx::migrate!("./migrations").run(&self.pool).await?;
-    Ok(())
-  }
 
-  async fn save_conversation(&self, conversation: &mut Conversation) -> Result<(), DbError> {
-    if conversation.id.is_empty() {
-      conversation.id = Uuid::new_v4().to_string()
-    } else {
-      self.delete_conversations(&conversation.id).await?;
-    }
-    conversation.updated_at = self.time_service.utc_now();
+  // moves the active messages from the given row onwards to a new branch
+  async fn branch_from(
+    &self,
+    executor: impl SqliteExecutor<'_>,
+    conversation_id: &str,
+    rowid: i64,
+  ) -> Result<(), DbError> {
+    let branch_id = Uuid::new_v4().to_string();
     sqlx::query(
-      "INSERT INTO conversations
-        (
-          id,
-          title,
-          created_at,
-          updated_at
-        )
-        VALUES (?, ?, ?, ?)
-        ON CONFLICT(id) DO UPDATE SET title = ?, updated_at = ?",
+      "UPDATE messages SET branch_id = ? WHERE conversation_id = ? AND branch_id IS NULL AND rowid >= ?",
     )
-    .bind(&conversation.id)
-    .bind(&conversation.title)
-    .bind(conversation.created_at.timestamp())
-    .bind(conversation.updated_at.timestamp())
-    .bind(&conversation.title)
-    .bind(conversation.updated_at.timestamp())
-    .execute(&self.pool)
+    .bind(&branch_id)
+    .bind(conversation_id)
+    .bind(rowid)
+    .execute(executor)
     .await
     .map_err(|source| DbError::Sqlx {
       source,
-      table: CONVERSATIONS.to_string(),
+      table: MESSAGES.to_string(),
     })?;
-    for message in &mut conversation.messages {
-      if message.conversation_id.is_empty() {
-        message.conversation_id.clone_from(&conversation.id);
-      }
-      self.save_message(message).await?;
-    }
     Ok(())
   }
 
-  async fn save_message(&self, message: &mut Message) -> Result<(), DbError> {
+  async fn touch_conversation(
+    &self,
+    executor: impl SqliteExecutor<'_>,
+    conversation_id: &str,
+    now: DateTime<Utc>,
+  ) -> Result<(), DbError> {
+    sqlx::query("UPDATE conversations SET updated_at = ? WHERE id = ?")
+      .bind(now.timestamp())
+      .bind(conversation_id)
+      .execute(executor)
+      .await
+      .map_err(|source| DbError::Sqlx {
+        source,
+        table: CONVERSATIONS.to_string(),
+      })?;
+    Ok(())
+  }
+
+  async fn insert_message(
+    &self,
+    executor: impl SqliteExecutor<'_>,
+    message: &mut Message,
+  ) -> Result<(), DbError> {
     if message.id.is_empty() {
       message.id = Uuid::new_v4().to_string();
     }
-    sqlx::query(
+    // the upsert updates only a message of the same conversation, a message id of another
+    // conversation is not taken over
+    let result = sqlx::query(
       "INSERT INTO messages
         (
           id,
@@ -130,10 +311,14 @@ impl DbServiceFn for DbService {
           role,
           name,
           content,
-          created_at
+          created_at,
+          model,
+          prompt_tokens,
+          completion_tokens
         )
-        VALUES (?, ?, ?, ?, ?, ?)
-        ON CONFLICT(id) DO UPDATE SET conversation_id = ?, role = ?, name = ?, content = ?, created_at = ?",
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT(id) DO UPDATE SET role = ?, name = ?, content = ?, created_at = ?, model = ?, prompt_tokens = ?, completion_tokens = ?
+        WHERE messages.conversation_id = excluded.conversation_id",
     )
     .bind(&message.id)
     .bind(&message.conversation_id)
@@ -141,39 +326,178 @@ impl DbServiceFn for DbService {
     .bind(&message.name)
     .bind(&message.content)
     .bind(message.created_at.timestamp())
-    .bind(&message.conversation_id)
+    .bind(&message.model)
+    .bind(message.prompt_tokens)
+    .bind(message.completion_tokens)
     .bind(&message.role)
     .bind(&message.name)
     .bind(&message.content)
     .bind(message.created_at.timestamp())
-    .execute(&self.pool)
+    .bind(&message.model)
+    .bind(message.prompt_tokens)
+    .bind(message.completion_tokens)
+    .execute(executor)
     .await
     .map_err(|source| DbError::Sqlx {
       source,
       table: MESSAGES.to_string(),
     })?;
+    if result.rows_affected() == 0 {
+      return Err(DbError::MessageExists(message.id.clone()));
+    }
+    Ok(())
+  }
+}
+
+#[async_trait::async_trait]
+impl DbServiceFn for DbService {
+  async fn migrate(&self) -> Result<(), DbError> {
+    MIGRATOR.run(&self.pool).await?;
     Ok(())
   }
 
-  async fn list_conversations(&self) -> Result<Vec<Conversation>, DbError> {
-    let conversations = sqlx::query_as::<_, (String, String, i64, i64)>(
-      "SELECT id, title, created_at, updated_at FROM conversations ORDER BY created_at DESC",
+  async fn migration_status(&self) -> Result<Vec<MigrationStatus>, DbError> {
+    let (exists,) = sqlx::query_as::<_, (i64,)>(
+      "SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name = ?",
     )
-    .fetch_all(&self.pool)
+    .bind(SQLX_MIGRATIONS)
+    .fetch_one(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: SQLX_MIGRATIONS.to_string(),
+    })?;
+    let applied = if exists == 0 {
+      vec![]
+    } else {
+      sqlx::query_as::<_, (i64, String, i64)>(
+        "SELECT version, description, CAST(strftime('%s', installed_on) AS INTEGER)
+          FROM _sqlx_migrations WHERE success = 1",
+      )
+      .fetch_all(&self.pool)
+      .await
+      .map_err(|source| DbError::Sqlx {
+        source,
+        table: SQLX_MIGRATIONS.to_string(),
+      })?
+    };
+    Ok(merge_migration_status(&MIGRATOR, applied))
+  }
+
+  async fn backup(&self, path: &Path) -> Result<(), DbError> {
+    sqlx::query("VACUUM INTO ?")
+      .bind(path.display().to_string())
+      .execute(&self.pool)
+      .await
+      .map_err(|source| DbError::Sqlx {
+        source,
+        table: "*".to_string(),
+      })?;
+    Ok(())
+  }
+
+  async fn save_conversation(&self, conversation: &mut Conversation) -> Result<(), DbError> {
+    let mut tx = self.pool.begin().await.map_err(|source| DbError::Sqlx {
+      source,
+      table: CONVERSATIONS.to_string(),
+    })?;
+    if conversation.id.is_empty() {
+      conversation.id = Uuid::new_v4().to_string()
+    } else {
+      // branched messages are retained, only the active messages are replaced
+      sqlx::query("DELETE FROM messages where conversation_id=? AND branch_id IS NULL")
+        .bind(&conversation.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|source| DbError::Sqlx {
+          source,
+          table: MESSAGES.to_string(),
+        })?;
+    }
+    conversation.updated_at = self.time_service.utc_now();
+    sqlx::query(
+      "INSERT INTO conversations
+        (
+          id,
+          title,
+          created_at,
+          updated_at,
+          owner
+        )
+        VALUES (?, ?, ?, ?, ?)
+        ON CONFLICT(id) DO UPDATE SET title = ?, updated_at = ?",
+    )
+    .bind(&conversation.id)
+    .bind(&conversation.title)
+    .bind(conversation.created_at.timestamp())
+    .bind(conversation.updated_at.timestamp())
+    .bind(&conversation.owner)
+    .bind(&conversation.title)
+    .bind(conversation.updated_at.timestamp())
+    .execute(&mut *tx)
     .await
     .map_err(|source| DbError::Sqlx {
       source,
       table: CONVERSATIONS.to_string(),
     })?;
+    for message in &mut conversation.messages {
+      message.conversation_id.clone_from(&conversation.id);
+      self.insert_message(&mut *tx, message).await?;
+    }
+    tx.commit().await.map_err(|source| DbError::Sqlx {
+      source,
+      table: CONVERSATIONS.to_string(),
+    })?;
+    Ok(())
+  }
+
+  async fn save_message(&self, message: &mut Message) -> Result<(), DbError> {
+    self.insert_message(&self.pool, message).await
+  }
+
+  async fn list_conversations(
+    &self,
+    query: &ConversationsQuery,
+  ) -> Result<Vec<Conversation>, DbError> {
+    // id breaks the ties of the sort column, so the pages do not overlap
+    let sql = format!(
+      "SELECT id, title, created_at, updated_at,
+          (SELECT json_group_array(tag) FROM conversation_tags t WHERE t.conversation_id = c.id) AS tags,
+          owner
+        FROM conversations c
+        WHERE (? IS NULL OR id IN (SELECT conversation_id FROM conversation_tags WHERE tag = ?))
+          AND (? IS NULL OR owner = ?)
+        ORDER BY {} DESC, id DESC
+        LIMIT ? OFFSET ?",
+      query.sort.column()
+    );
+    // a negative limit is no limit in sqlite
+    let limit = query.limit.map(i64::from).unwrap_or(-1);
+    let conversations =
+      sqlx::query_as::<_, (String, String, i64, i64, String, Option<String>)>(&sql)
+        .bind(&query.tag)
+        .bind(&query.tag)
+        .bind(&query.owner)
+        .bind(&query.owner)
+        .bind(limit)
+        .bind(query.offset)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|source| DbError::Sqlx {
+          source,
+          table: CONVERSATIONS.to_string(),
+        })?;
 
     let mut result = Vec::new();
-    for (id, title, created_at, updated_at) in conversations {
+    for (id, title, created_at, updated_at, tags, owner) in conversations {
       result.push(Conversation {
         id,
         title,
-        created_at: chrono::DateTime::<Utc>::from_timestamp(created_at, 0).unwrap_or_default(),
-        updated_at: chrono::DateTime::<Utc>::from_timestamp(updated_at, 0).unwrap_or_default(),
+        created_at: from_db_timestamp(created_at),
+        updated_at: from_db_timestamp(updated_at),
+        tags: from_db_tags(&tags),
         messages: Vec::new(),
+        owner,
       });
     }
 
@@ -182,14 +506,17 @@ impl DbServiceFn for DbService {
 
   async fn get_conversation_with_messages(&self, id: &str) -> Result<Conversation, DbError> {
     let messages = sqlx::query_as::<_, Message>(
-      "SELECT id, conversation_id, role, name, content, created_at FROM messages WHERE conversation_id = ?"
+      "SELECT id, conversation_id, role, name, content, created_at, model, prompt_tokens, completion_tokens FROM messages WHERE conversation_id = ? AND branch_id IS NULL ORDER BY rowid"
     )
     .bind(id)
     .fetch_all(&self.pool)
     .await.map_err(|source| DbError::Sqlx { source, table: MESSAGES.to_string() })?;
 
-    let row = sqlx::query_as::<_, (String, String, i64, i64)>(
-      "SELECT id, title, created_at, updated_at FROM conversations WHERE id = ?",
+    let row = sqlx::query_as::<_, (String, String, i64, i64, String, Option<String>)>(
+      "SELECT id, title, created_at, updated_at,
+          (SELECT json_group_array(tag) FROM conversation_tags t WHERE t.conversation_id = c.id) AS tags,
+          owner
+        FROM conversations c WHERE id = ?",
     )
     .bind(id)
     .fetch_one(&self.pool)
@@ -202,15 +529,105 @@ impl DbServiceFn for DbService {
     let conversation = Conversation {
       id: row.0.clone(),
       title: row.1,
-      created_at: chrono::DateTime::<Utc>::from_timestamp(row.2, 0).unwrap_or_default(),
-      updated_at: chrono::DateTime::<Utc>::from_timestamp(row.3, 0).unwrap_or_default(),
+      created_at: from_db_timestamp(row.2),
+      updated_at: from_db_timestamp(row.3),
+      tags: from_db_tags(&row.4),
       messages,
+      owner: row.5,
+    };
+
+    Ok(conversation)
+  }
+
+  async fn edit_message(
+    &self,
+    conversation_id: &str,
+    message_id: &str,
+    content: &str,
+  ) -> Result<Message, DbError> {
+    let mut tx = self.pool.begin().await.map_err(|source| DbError::Sqlx {
+      source,
+      table: MESSAGES.to_string(),
+    })?;
+    let (rowid, role, name) = sqlx::query_as::<_, (i64, String, Option<String>)>(
+      "SELECT rowid, role, name FROM messages WHERE id = ? AND conversation_id = ? AND branch_id IS NULL",
+    )
+    .bind(message_id)
+    .bind(conversation_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: MESSAGES.to_string(),
+    })?;
+    self.branch_from(&mut *tx, conversation_id, rowid).await?;
+    let now = self.time_service.utc_now();
+    let mut message = Message {
+      id: Uuid::new_v4().to_string(),
+      conversation_id: conversation_id.to_string(),
+      role,
+      name,
+      content: Some(content.to_string()),
+      created_at: now,
+      ..Default::default()
     };
+    self.insert_message(&mut *tx, &mut message).await?;
+    self
+      .touch_conversation(&mut *tx, conversation_id, now)
+      .await?;
+    tx.commit().await.map_err(|source| DbError::Sqlx {
+      source,
+      table: MESSAGES.to_string(),
+    })?;
+    Ok(message)
+  }
+
+  async fn branch_messages(&self, conversation_id: &str, message_id: &str) -> Result<(), DbError> {
+    let (rowid,) = sqlx::query_as::<_, (i64,)>(
+      "SELECT rowid FROM messages WHERE id = ? AND conversation_id = ? AND branch_id IS NULL",
+    )
+    .bind(message_id)
+    .bind(conversation_id)
+    .fetch_one(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: MESSAGES.to_string(),
+    })?;
+    self.branch_from(&self.pool, conversation_id, rowid).await?;
+    self
+      .touch_conversation(&self.pool, conversation_id, self.time_service.utc_now())
+      .await
+  }
 
+  async fn fork_conversation(
+    &self,
+    conversation_id: &str,
+    message_id: &str,
+  ) -> Result<Conversation, DbError> {
+    let original = self.get_conversation_with_messages(conversation_id).await?;
+    let mut conversation = forked_conversation(original, message_id, self.time_service.utc_now())?;
+    self.save_conversation(&mut conversation).await?;
     Ok(conversation)
   }
 
   async fn delete_conversations(&self, id: &str) -> Result<(), DbError> {
+    sqlx::query("DELETE FROM conversation_tags where conversation_id=?")
+      .bind(id)
+      .execute(&self.pool)
+      .await
+      .map_err(|source| DbError::Sqlx {
+        source,
+        table: CONVERSATION_TAGS.to_string(),
+      })?;
+    sqlx::query("DELETE FROM share_links where conversation_id=?")
+      .bind(id)
+      .execute(&self.pool)
+      .await
+      .map_err(|source| DbError::Sqlx {
+        source,
+        table: SHARE_LINKS.to_string(),
+      })?;
     sqlx::query("DELETE FROM messages where conversation_id=?")
       .bind(id)
       .execute(&self.pool)
@@ -230,41 +647,776 @@ impl DbServiceFn for DbService {
     Ok(())
   }
 
-  async fn delete_all_conversations(&self) -> Result<(), DbError> {
-    sqlx::query("DELETE FROM messages")
+  async fn delete_all_conversations(&self, owner: Option<&str>) -> Result<(), DbError> {
+    sqlx::query(
+      "DELETE FROM conversation_tags
+        WHERE ? IS NULL OR conversation_id IN (SELECT id FROM conversations WHERE owner = ?)",
+    )
+    .bind(owner)
+    .bind(owner)
+    .execute(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: CONVERSATION_TAGS.to_string(),
+    })?;
+    sqlx::query(
+      "DELETE FROM share_links
+        WHERE ? IS NULL OR conversation_id IN (SELECT id FROM conversations WHERE owner = ?)",
+    )
+    .bind(owner)
+    .bind(owner)
+    .execute(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: SHARE_LINKS.to_string(),
+    })?;
+    sqlx::query(
+      "DELETE FROM messages
+        WHERE ? IS NULL OR conversation_id IN (SELECT id FROM conversations WHERE owner = ?)",
+    )
+    .bind(owner)
+    .bind(owner)
+    .execute(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: MESSAGES.to_string(),
+    })?;
+    sqlx::query("DELETE FROM conversations WHERE ? IS NULL OR owner = ?")
+      .bind(owner)
+      .bind(owner)
       .execute(&self.pool)
       .await
       .map_err(|source| DbError::Sqlx {
         source,
-        table: MESSAGES.to_string(),
+        table: CONVERSATIONS.to_string(),
       })?;
-    sqlx::query("DELETE FROM conversations")
-      .execute(&self.pool)
+    Ok(())
+  }
+
+  async fn create_share_link(
+    &self,
+    conversation_id: &str,
+    expires_in: Duration,
+  ) -> Result<ShareLink, DbError> {
+    sqlx::query_as::<_, (String,)>("SELECT id FROM conversations WHERE id = ?")
+      .bind(conversation_id)
+      .fetch_one(&self.pool)
       .await
       .map_err(|source| DbError::Sqlx {
         source,
         table: CONVERSATIONS.to_string(),
       })?;
+    let now = self.time_service.utc_now();
+    let share_link = ShareLink {
+      token: Uuid::new_v4().simple().to_string(),
+      conversation_id: conversation_id.to_string(),
+      created_at: now,
+      expires_at: now + expires_in,
+    };
+    sqlx::query(
+      "INSERT INTO share_links (token, conversation_id, created_at, expires_at) VALUES (?, ?, ?, ?)",
+    )
+    .bind(&share_link.token)
+    .bind(&share_link.conversation_id)
+    .bind(share_link.created_at.timestamp())
+    .bind(share_link.expires_at.timestamp())
+    .execute(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: SHARE_LINKS.to_string(),
+    })?;
+    Ok(share_link)
+  }
+
+  async fn get_shared_conversation(&self, token: &str) -> Result<Conversation, DbError> {
+    let (conversation_id,) = sqlx::query_as::<_, (String,)>(
+      "SELECT conversation_id FROM share_links WHERE token = ? AND expires_at > ?",
+    )
+    .bind(token)
+    .bind(self.time_service.utc_now().timestamp())
+    .fetch_one(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: SHARE_LINKS.to_string(),
+    })?;
+    let mut conversation = self
+      .get_conversation_with_messages(&conversation_id)
+      .await?;
+    // tags are for the owner to organize the chats, not shared
+    conversation.tags.clear();
+    Ok(conversation)
+  }
+
+  async fn save_usage(&self, record: &mut UsageRecord) -> Result<(), DbError> {
+    record.id = Uuid::new_v4().to_string();
+    record.created_at = self.time_service.utc_now();
+    sqlx::query(
+      "INSERT INTO usage_records (id, created_at, model, user_agent, app_name, prompt_tokens, completion_tokens, latency_ms) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&record.id)
+    .bind(record.created_at.timestamp())
+    .bind(&record.model)
+    .bind(&record.user_agent)
+    .bind(&record.app_name)
+    .bind(record.prompt_tokens)
+    .bind(record.completion_tokens)
+    .bind(record.latency_ms)
+    .execute(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: USAGE_RECORDS.to_string(),
+    })?;
     Ok(())
   }
-}
 
-#[cfg(test)]
-mod test {
-  use super::{DbService, TimeService, TimeServiceFn};
-  use crate::{
-    db::{
-      objs::{ConversationBuilder, MessageBuilder},
-      service::DbServiceFn,
-    },
-    test_utils::db_service,
-  };
-  use chrono::{DateTime, Days, Timelike, Utc};
-  use rstest::rstest;
-  use tempfile::TempDir;
-  use uuid::Uuid;
+  async fn list_client_usage(
+    &self,
+    since: Option<DateTime<Utc>>,
+    limit: u32,
+  ) -> Result<Vec<ClientUsage>, DbError> {
+    let since = since.map(|since| since.timestamp()).unwrap_or(i64::MIN);
+    let clients = sqlx::query_as::<_, ClientUsage>(
+      "SELECT user_agent, app_name, COUNT(*) AS requests, SUM(prompt_tokens) AS prompt_tokens, SUM(completion_tokens) AS completion_tokens
+        FROM usage_records
+        WHERE created_at >= ?
+        GROUP BY user_agent, app_name
+        ORDER BY requests DESC, prompt_tokens + completion_tokens DESC
+        LIMIT ?",
+    )
+    .bind(since)
+    .bind(limit)
+    .fetch_all(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: USAGE_RECORDS.to_string(),
+    })?;
+    Ok(clients)
+  }
 
-  #[rstest]
+  async fn list_usage_stats(
+    &self,
+    period: UsagePeriod,
+    since: Option<DateTime<Utc>>,
+    limit: u32,
+  ) -> Result<Vec<UsageStats>, DbError> {
+    let since = since.map(|since| since.timestamp()).unwrap_or(i64::MIN);
+    let period = match period {
+      UsagePeriod::Hour => "strftime('%Y-%m-%d %H:00', created_at, 'unixepoch')",
+      UsagePeriod::Day => "date(created_at, 'unixepoch')",
+      UsagePeriod::Week => "date(created_at, 'unixepoch', 'weekday 0', '-6 days')",
+      UsagePeriod::Month => "strftime('%Y-%m', created_at, 'unixepoch')",
+    };
+    let stats = sqlx::query_as::<_, UsageStats>(&format!(
+      "SELECT {period} AS period, model, COALESCE(app_name, user_agent) AS client, COUNT(*) AS requests,
+          SUM(prompt_tokens) AS prompt_tokens, SUM(completion_tokens) AS completion_tokens,
+          CAST(AVG(latency_ms) AS INTEGER) AS avg_latency_ms
+        FROM usage_records
+        WHERE created_at >= ?
+        GROUP BY 1, 2, 3
+        ORDER BY period DESC, requests DESC, prompt_tokens + completion_tokens DESC, model, client
+        LIMIT ?"
+    ))
+    .bind(since)
+    .bind(limit)
+    .fetch_all(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: USAGE_RECORDS.to_string(),
+    })?;
+    Ok(stats)
+  }
+
+  async fn list_conversation_usage(
+    &self,
+    since: Option<DateTime<Utc>>,
+    owner: Option<&str>,
+    limit: u32,
+  ) -> Result<Vec<ConversationUsage>, DbError> {
+    let since = since.map(|since| since.timestamp()).unwrap_or(i64::MIN);
+    let conversations = sqlx::query_as::<_, ConversationUsage>(
+      "SELECT c.id AS conversation_id, c.title, COUNT(*) AS replies,
+          COALESCE(SUM(m.prompt_tokens), 0) AS prompt_tokens, COALESCE(SUM(m.completion_tokens), 0) AS completion_tokens
+        FROM messages m
+        JOIN conversations c ON c.id = m.conversation_id
+        WHERE m.created_at >= ? AND (m.prompt_tokens IS NOT NULL OR m.completion_tokens IS NOT NULL)
+          AND (? IS NULL OR c.owner = ?)
+        GROUP BY c.id, c.title
+        ORDER BY prompt_tokens + completion_tokens DESC, c.id
+        LIMIT ?",
+    )
+    .bind(since)
+    .bind(owner)
+    .bind(owner)
+    .bind(limit)
+    .fetch_all(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: MESSAGES.to_string(),
+    })?;
+    Ok(conversations)
+  }
+
+  async fn search_messages(
+    &self,
+    query: &str,
+    owner: Option<&str>,
+    limit: u32,
+  ) -> Result<Vec<MessageMatch>, DbError> {
+    let Some(query) = fts_query(query) else {
+      return Ok(vec![]);
+    };
+    let matches = sqlx::query_as::<_, MessageMatch>(
+      "SELECT c.id AS conversation_id, c.title, m.id AS message_id, m.role,
+          snippet(messages_fts, 0, '<mark>', '</mark>', '…', 16) AS snippet, m.created_at
+        FROM messages_fts
+        JOIN messages m ON m.rowid = messages_fts.rowid
+        JOIN conversations c ON c.id = m.conversation_id
+        WHERE messages_fts MATCH ? AND m.branch_id IS NULL AND (? IS NULL OR c.owner = ?)
+        ORDER BY messages_fts.rank
+        LIMIT ?",
+    )
+    .bind(query)
+    .bind(owner)
+    .bind(owner)
+    .bind(limit)
+    .fetch_all(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: MESSAGES_FTS.to_string(),
+    })?;
+    Ok(matches)
+  }
+
+  async fn tag_conversation(&self, conversation_id: &str, tag: &str) -> Result<(), DbError> {
+    sqlx::query_as::<_, (String,)>("SELECT id FROM conversations WHERE id = ?")
+      .bind(conversation_id)
+      .fetch_one(&self.pool)
+      .await
+      .map_err(|source| DbError::Sqlx {
+        source,
+        table: CONVERSATIONS.to_string(),
+      })?;
+    sqlx::query(
+      "INSERT INTO conversation_tags (conversation_id, tag, created_at) VALUES (?, ?, ?)
+        ON CONFLICT(conversation_id, tag) DO NOTHING",
+    )
+    .bind(conversation_id)
+    .bind(tag)
+    .bind(self.time_service.utc_now().timestamp())
+    .execute(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: CONVERSATION_TAGS.to_string(),
+    })?;
+    Ok(())
+  }
+
+  async fn untag_conversation(&self, conversation_id: &str, tag: &str) -> Result<(), DbError> {
+    sqlx::query("DELETE FROM conversation_tags WHERE conversation_id = ? AND tag = ?")
+      .bind(conversation_id)
+      .bind(tag)
+      .execute(&self.pool)
+      .await
+      .map_err(|source| DbError::Sqlx {
+        source,
+        table: CONVERSATION_TAGS.to_string(),
+      })?;
+    Ok(())
+  }
+
+  async fn save_canary_sample(&self, sample: &mut CanarySample) -> Result<(), DbError> {
+    sample.id = Uuid::new_v4().to_string();
+    sample.created_at = self.time_service.utc_now();
+    sqlx::query(
+      "INSERT INTO canary_samples (id, created_at, model, canary_model, request, output, canary_output, canary_error, diff) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&sample.id)
+    .bind(sample.created_at.timestamp())
+    .bind(&sample.model)
+    .bind(&sample.canary_model)
+    .bind(&sample.request)
+    .bind(&sample.output)
+    .bind(&sample.canary_output)
+    .bind(&sample.canary_error)
+    .bind(&sample.diff)
+    .execute(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: CANARY_SAMPLES.to_string(),
+    })?;
+    Ok(())
+  }
+
+  async fn list_canary_samples(&self, limit: u32) -> Result<Vec<CanarySample>, DbError> {
+    let samples = sqlx::query_as::<_, CanarySample>(
+      "SELECT id, created_at, model, canary_model, request, output, canary_output, canary_error, diff
+        FROM canary_samples
+        ORDER BY created_at DESC, id DESC
+        LIMIT ?",
+    )
+    .bind(limit)
+    .fetch_all(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: CANARY_SAMPLES.to_string(),
+    })?;
+    Ok(samples)
+  }
+
+  async fn create_api_key(
+    &self,
+    name: &str,
+    role: ApiKeyRole,
+    priority: RequestPriority,
+  ) -> Result<(ApiKey, String), DbError> {
+    let existing = sqlx::query_as::<_, (String,)>(
+      "SELECT id FROM api_keys WHERE name = ? AND revoked_at IS NULL",
+    )
+    .bind(name)
+    .fetch_optional(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: API_KEYS.to_string(),
+    })?;
+    if existing.is_some() {
+      return Err(DbError::ApiKeyExists(name.to_string()));
+    }
+    let secret = new_api_key_secret();
+    let api_key = ApiKey {
+      id: Uuid::new_v4().to_string(),
+      name: name.to_string(),
+      role,
+      priority,
+      created_at: self.time_service.utc_now(),
+      revoked_at: None,
+    };
+    sqlx::query(
+      "INSERT INTO api_keys (id, name, role, priority, key_hash, created_at) VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&api_key.id)
+    .bind(&api_key.name)
+    .bind(api_key.role.to_string())
+    .bind(api_key.priority.to_string())
+    .bind(api_key_hash(&secret))
+    .bind(api_key.created_at.timestamp())
+    .execute(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: API_KEYS.to_string(),
+    })?;
+    Ok((api_key, secret))
+  }
+
+  async fn list_api_keys(&self) -> Result<Vec<ApiKey>, DbError> {
+    let rows = sqlx::query_as::<_, ApiKeyRow>(
+      "SELECT id, name, role, priority, created_at, revoked_at FROM api_keys ORDER BY created_at, id",
+    )
+    .fetch_all(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: API_KEYS.to_string(),
+    })?;
+    Ok(rows.into_iter().map(from_api_key_row).collect())
+  }
+
+  async fn revoke_api_key(&self, id: &str) -> Result<(), DbError> {
+    let result =
+      sqlx::query("UPDATE api_keys SET revoked_at = ? WHERE id = ? AND revoked_at IS NULL")
+        .bind(self.time_service.utc_now().timestamp())
+        .bind(id)
+        .execute(&self.pool)
+        .await
+        .map_err(|source| DbError::Sqlx {
+          source,
+          table: API_KEYS.to_string(),
+        })?;
+    if result.rows_affected() == 0 {
+      return Err(DbError::Sqlx {
+        source: sqlx::Error::RowNotFound,
+        table: API_KEYS.to_string(),
+      });
+    }
+    Ok(())
+  }
+
+  async fn find_api_key(&self, secret: &str) -> Result<ApiKey, DbError> {
+    let row = sqlx::query_as::<_, ApiKeyRow>(
+      "SELECT id, name, role, priority, created_at, revoked_at FROM api_keys WHERE key_hash = ? AND revoked_at IS NULL",
+    )
+    .bind(api_key_hash(secret))
+    .fetch_one(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: API_KEYS.to_string(),
+    })?;
+    Ok(from_api_key_row(row))
+  }
+
+  async fn save_audit_entry(&self, entry: &mut AuditEntry) -> Result<(), DbError> {
+    entry.id = Uuid::new_v4().to_string();
+    entry.created_at = self.time_service.utc_now();
+    sqlx::query(
+      "INSERT INTO audit_log (id, created_at, actor, action, target, before_value, after_value)
+        VALUES (?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&entry.id)
+    .bind(entry.created_at.timestamp())
+    .bind(&entry.actor)
+    .bind(entry.action.to_string())
+    .bind(&entry.target)
+    .bind(&entry.before)
+    .bind(&entry.after)
+    .execute(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: AUDIT_LOG.to_string(),
+    })?;
+    Ok(())
+  }
+
+  async fn list_audit_entries(
+    &self,
+    action: Option<AuditAction>,
+    since: Option<DateTime<Utc>>,
+    limit: u32,
+  ) -> Result<Vec<AuditEntry>, DbError> {
+    let since = since.map(|since| since.timestamp()).unwrap_or(i64::MIN);
+    let action = action.map(|action| action.to_string());
+    let rows = sqlx::query_as::<_, AuditRow>(
+      "SELECT id, created_at, actor, action, target, before_value, after_value
+        FROM audit_log
+        WHERE created_at >= ? AND (? IS NULL OR action = ?)
+        ORDER BY created_at DESC, rowid DESC
+        LIMIT ?",
+    )
+    .bind(since)
+    .bind(&action)
+    .bind(&action)
+    .bind(limit)
+    .fetch_all(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: AUDIT_LOG.to_string(),
+    })?;
+    Ok(rows.into_iter().filter_map(from_audit_row).collect())
+  }
+
+  async fn create_download(&self, download: &mut Download) -> Result<(), DbError> {
+    download.id = Uuid::new_v4().to_string();
+    download.created_at = self.time_service.utc_now();
+    download.updated_at = download.created_at;
+    sqlx::query(
+      "INSERT INTO downloads (id, repo, filename, revision, status, downloaded_bytes, total_bytes, error, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&download.id)
+    .bind(&download.repo)
+    .bind(&download.filename)
+    .bind(&download.revision)
+    .bind(download.status.to_string())
+    .bind(download.downloaded_bytes as i64)
+    .bind(download.total_bytes.map(|total| total as i64))
+    .bind(&download.error)
+    .bind(download.created_at.timestamp())
+    .bind(download.updated_at.timestamp())
+    .execute(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: DOWNLOADS.to_string(),
+    })?;
+    Ok(())
+  }
+
+  async fn update_download(&self, download: &mut Download) -> Result<(), DbError> {
+    download.updated_at = self.time_service.utc_now();
+    let result = sqlx::query(
+      "UPDATE downloads SET status = ?, downloaded_bytes = ?, total_bytes = ?, error = ?, updated_at = ?
+        WHERE id = ?",
+    )
+    .bind(download.status.to_string())
+    .bind(download.downloaded_bytes as i64)
+    .bind(download.total_bytes.map(|total| total as i64))
+    .bind(&download.error)
+    .bind(download.updated_at.timestamp())
+    .bind(&download.id)
+    .execute(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: DOWNLOADS.to_string(),
+    })?;
+    if result.rows_affected() == 0 {
+      return Err(DbError::Sqlx {
+        source: sqlx::Error::RowNotFound,
+        table: DOWNLOADS.to_string(),
+      });
+    }
+    Ok(())
+  }
+
+  async fn get_download(&self, id: &str) -> Result<Download, DbError> {
+    let row = sqlx::query_as::<_, DownloadRow>(
+      "SELECT id, repo, filename, revision, status, downloaded_bytes, total_bytes, error, created_at, updated_at
+        FROM downloads WHERE id = ?",
+    )
+    .bind(id)
+    .fetch_one(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: DOWNLOADS.to_string(),
+    })?;
+    Ok(from_download_row(row))
+  }
+
+  async fn list_downloads(&self, limit: u32) -> Result<Vec<Download>, DbError> {
+    let rows = sqlx::query_as::<_, DownloadRow>(
+      "SELECT id, repo, filename, revision, status, downloaded_bytes, total_bytes, error, created_at, updated_at
+        FROM downloads
+        ORDER BY created_at DESC, rowid DESC
+        LIMIT ?",
+    )
+    .bind(limit)
+    .fetch_all(&self.pool)
+    .await
+    .map_err(|source| DbError::Sqlx {
+      source,
+      table: DOWNLOADS.to_string(),
+    })?;
+    Ok(rows.into_iter().map(from_download_row).collect())
+  }
+}
+
+/// (id, name, role, created_at, revoked_at) columns of the api_keys table
+pub(super) type ApiKeyRow = (String, String, String, String, i64, Option<i64>);
+
+pub(super) fn from_api_key_row(
+  (id, name, role, priority, created_at, revoked_at): ApiKeyRow,
+) -> ApiKey {
+  ApiKey {
+    id,
+    name,
+    // an unknown role gets the least access
+    role: role.parse().unwrap_or(ApiKeyRole::Readonly),
+    priority: priority.parse().unwrap_or_default(),
+    created_at: from_db_timestamp(created_at),
+    revoked_at: revoked_at.map(from_db_timestamp),
+  }
+}
+
+/// (id, created_at, actor, action, target, before_value, after_value) columns of the audit_log table
+pub(super) type AuditRow = (
+  String,
+  i64,
+  Option<String>,
+  String,
+  String,
+  Option<String>,
+  Option<String>,
+);
+
+// the actions unknown to this version are skipped
+pub(super) fn from_audit_row(
+  (id, created_at, actor, action, target, before, after): AuditRow,
+) -> Option<AuditEntry> {
+  Some(AuditEntry {
+    id,
+    created_at: from_db_timestamp(created_at),
+    actor,
+    action: action.parse().ok()?,
+    target,
+    before,
+    after,
+  })
+}
+
+/// (id, repo, filename, revision, status, downloaded_bytes, total_bytes, error, created_at,
+/// updated_at) columns of the downloads table
+pub(super) type DownloadRow = (
+  String,
+  String,
+  String,
+  String,
+  String,
+  i64,
+  Option<i64>,
+  Option<String>,
+  i64,
+  i64,
+);
+
+// a download with a status unknown to this version is listed as failed
+pub(super) fn from_download_row(
+  (
+    id,
+    repo,
+    filename,
+    revision,
+    status,
+    downloaded_bytes,
+    total_bytes,
+    error,
+    created_at,
+    updated_at,
+  ): DownloadRow,
+) -> Download {
+  Download {
+    id,
+    repo,
+    filename,
+    revision,
+    status: status.parse().unwrap_or(DownloadStatus::Failed),
+    downloaded_bytes: downloaded_bytes.max(0) as u64,
+    total_bytes: total_bytes.map(|total| total.max(0) as u64),
+    error,
+    created_at: from_db_timestamp(created_at),
+    updated_at: from_db_timestamp(updated_at),
+  }
+}
+
+pub(super) fn new_api_key_secret() -> String {
+  format!("bodhi-{}", Uuid::new_v4().simple())
+}
+
+pub(super) fn api_key_hash(secret: &str) -> String {
+  Sha256::digest(secret.as_bytes())
+    .iter()
+    .map(|byte| format!("{byte:02x}"))
+    .collect()
+}
+
+/// Status of the migrations of the migrator, merged with the (version, description, applied
+/// epoch seconds) rows of the migrations applied to the database
+pub(super) fn merge_migration_status(
+  migrator: &Migrator,
+  mut applied: Vec<(i64, String, i64)>,
+) -> Vec<MigrationStatus> {
+  let mut result = vec![];
+  for migration in migrator
+    .iter()
+    .filter(|migration| migration.migration_type.is_up_migration())
+  {
+    let applied_at = applied
+      .iter()
+      .position(|(version, _, _)| *version == migration.version)
+      .map(|index| from_db_timestamp(applied.remove(index).2));
+    result.push(MigrationStatus {
+      version: migration.version,
+      description: migration.description.to_string(),
+      applied_at,
+      unknown: false,
+    });
+  }
+  result.extend(
+    applied
+      .into_iter()
+      .map(|(version, description, applied_at)| MigrationStatus {
+        version,
+        description,
+        applied_at: Some(from_db_timestamp(applied_at)),
+        unknown: true,
+      }),
+  );
+  result.sort_by_key(|status| status.version);
+  result
+}
+
+/// New conversation with the active messages of the original up to and including the message,
+/// the ids are cleared so the messages are saved as new rows
+pub(super) fn forked_conversation(
+  original: Conversation,
+  message_id: &str,
+  now: DateTime<Utc>,
+) -> Result<Conversation, DbError> {
+  let Some(position) = original
+    .messages
+    .iter()
+    .position(|message| message.id == message_id)
+  else {
+    return Err(DbError::Sqlx {
+      source: sqlx::Error::RowNotFound,
+      table: MESSAGES.to_string(),
+    });
+  };
+  let messages = original
+    .messages
+    .into_iter()
+    .take(position + 1)
+    .map(|message| Message {
+      id: String::new(),
+      conversation_id: String::new(),
+      ..message
+    })
+    .collect();
+  Ok(Conversation {
+    title: original.title,
+    created_at: now,
+    messages,
+    owner: original.owner,
+    ..Default::default()
+  })
+}
+
+// tags are aggregated as a json array in the conversation queries
+pub(super) fn from_db_tags(tags: &str) -> Vec<String> {
+  let mut tags = serde_json::from_str::<Vec<String>>(tags).unwrap_or_default();
+  tags.sort();
+  tags
+}
+
+/// Quotes each term of the user query, so the FTS5 query syntax characters are matched as text
+fn fts_query(query: &str) -> Option<String> {
+  let terms = query
+    .split_whitespace()
+    .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+    .collect::<Vec<_>>();
+  if terms.is_empty() {
+    return None;
+  }
+  Some(format!("{}*", terms.join(" ")))
+}
+
+#[cfg(test)]
+mod test {
+  use super::{api_key_hash, fts_query, DbError, DbService, TimeService, TimeServiceFn, MIGRATOR};
+  use crate::{
+    db::{
+      objs::{
+        ApiKeyRole, AuditAction, AuditEntry, CanarySample, ClientUsage, ConversationBuilder,
+        ConversationSort, ConversationUsage, ConversationsQuery, Download, DownloadStatus,
+        MessageBuilder, UsagePeriod, UsageRecord, UsageStats,
+      },
+      service::DbServiceFn,
+    },
+    objs::RequestPriority,
+    test_utils::db_service,
+  };
+  use chrono::{DateTime, Days, Duration, Timelike, Utc};
+  use rstest::rstest;
+  use tempfile::TempDir;
+  use uuid::Uuid;
+
+  #[rstest]
   #[awt]
   #[tokio::test]
   async fn test_db_service_conversations_create(
@@ -283,7 +1435,9 @@ mod test {
       .updated_at(created)
       .build()?;
     service.save_conversation(&mut conversation.clone()).await?;
-    let convos = service.list_conversations().await?;
+    let convos = service
+      .list_conversations(&ConversationsQuery::default())
+      .await?;
     assert_eq!(1, convos.len());
     conversation.updated_at = now;
     assert_eq!(&conversation, convos.first().unwrap());
@@ -312,7 +1466,9 @@ mod test {
     conversation.title = "new test chat".to_string();
     service.save_conversation(&mut conversation).await?;
 
-    let convos = service.list_conversations().await?;
+    let convos = service
+      .list_conversations(&ConversationsQuery::default())
+      .await?;
     assert_eq!(1, convos.len());
     assert_eq!(&conversation, convos.first().unwrap());
     Ok(())
@@ -331,11 +1487,57 @@ mod test {
     service
       .save_conversation(&mut ConversationBuilder::default().build().unwrap())
       .await?;
-    let convos = service.list_conversations().await?;
+    let convos = service
+      .list_conversations(&ConversationsQuery::default())
+      .await?;
     assert_eq!(2, convos.len());
     Ok(())
   }
 
+  #[rstest]
+  #[case(ConversationsQuery::default(), vec!["c3", "c2", "c1"])]
+  #[case(ConversationsQuery { sort: ConversationSort::UpdatedAt, ..Default::default() }, vec!["c1", "c3", "c2"])]
+  #[case(ConversationsQuery { limit: Some(2), ..Default::default() }, vec!["c3", "c2"])]
+  #[case(ConversationsQuery { limit: Some(2), offset: 2, ..Default::default() }, vec!["c1"])]
+  #[case(ConversationsQuery { sort: ConversationSort::UpdatedAt, limit: Some(1), offset: 1, ..Default::default() }, vec!["c3"])]
+  #[case(ConversationsQuery { offset: 3, ..Default::default() }, vec![])]
+  #[awt]
+  #[tokio::test]
+  async fn test_db_service_list_conversation_paginated(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+    #[case] query: ConversationsQuery,
+    #[case] expected: Vec<&str>,
+  ) -> anyhow::Result<()> {
+    let (_tempdir, now, service) = db_service;
+    for (id, days_ago) in [("c1", 3), ("c2", 2), ("c3", 1)] {
+      let mut convo = ConversationBuilder::default()
+        .id(id)
+        .title(id)
+        .created_at(now.checked_sub_days(Days::new(days_ago)).unwrap())
+        .build()?;
+      service.save_conversation(&mut convo).await?;
+    }
+    // c1 is the most recently updated, c2 the least
+    for (id, updated_at) in [
+      ("c1", now),
+      ("c2", now - Duration::hours(2)),
+      ("c3", now - Duration::hours(1)),
+    ] {
+      sqlx::query("UPDATE conversations SET updated_at = ? WHERE id = ?")
+        .bind(updated_at.timestamp())
+        .bind(id)
+        .execute(&service.pool)
+        .await?;
+    }
+    let convos = service.list_conversations(&query).await?;
+    let ids = convos
+      .iter()
+      .map(|convo| convo.id.as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(expected, ids);
+    Ok(())
+  }
+
   #[rstest]
   #[awt]
   #[tokio::test]
@@ -410,12 +1612,875 @@ mod test {
       .build()
       .unwrap();
     service.save_message(&mut message).await?;
-    service.delete_all_conversations().await?;
-    let convos = service.list_conversations().await?;
+    service.delete_all_conversations(None).await?;
+    let convos = service
+      .list_conversations(&ConversationsQuery::default())
+      .await?;
     assert!(convos.is_empty());
     Ok(())
   }
 
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_db_service_conversation_owner(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_tempdir, _now, service) = db_service;
+    let mut alice = ConversationBuilder::default()
+      .title("alice chat")
+      .owner("alice")
+      .messages(vec![MessageBuilder::default()
+        .role("user")
+        .content("What day comes after Monday?")
+        .build()?])
+      .build()?;
+    service.save_conversation(&mut alice).await?;
+    let mut bob = ConversationBuilder::default()
+      .title("bob chat")
+      .owner("bob")
+      .messages(vec![MessageBuilder::default()
+        .role("user")
+        .content("What day comes before Monday?")
+        .build()?])
+      .build()?;
+    service.save_conversation(&mut bob).await?;
+
+    let query = ConversationsQuery {
+      owner: Some("alice".to_string()),
+      ..Default::default()
+    };
+    let convos = service.list_conversations(&query).await?;
+    assert_eq!(1, convos.len());
+    assert_eq!(alice.id, convos[0].id);
+    assert_eq!(Some("alice".to_string()), convos[0].owner);
+    assert_eq!(
+      2,
+      service
+        .list_conversations(&ConversationsQuery::default())
+        .await?
+        .len()
+    );
+    let matches = service.search_messages("monday", Some("bob"), 10).await?;
+    assert_eq!(1, matches.len());
+    assert_eq!(bob.id, matches[0].conversation_id);
+
+    // the owner is kept when saved again
+    alice.owner = None;
+    service.save_conversation(&mut alice).await?;
+    let from_db = service.get_conversation_with_messages(&alice.id).await?;
+    assert_eq!(Some("alice".to_string()), from_db.owner);
+    let fork = service
+      .fork_conversation(&alice.id, &from_db.messages[0].id)
+      .await?;
+    assert_eq!(Some("alice".to_string()), fork.owner);
+
+    service.delete_all_conversations(Some("alice")).await?;
+    let convos = service
+      .list_conversations(&ConversationsQuery::default())
+      .await?;
+    assert_eq!(1, convos.len());
+    assert_eq!(bob.id, convos[0].id);
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_db_service_edit_message_keeps_branch(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_tempdir, now, service) = db_service;
+    let mut conversation = ConversationBuilder::default()
+      .title("test title")
+      .messages(vec![
+        MessageBuilder::default()
+          .role("user")
+          .content("What day comes after Monday?")
+          .build()?,
+        MessageBuilder::default()
+          .role("assistant")
+          .content("Tuesday")
+          .build()?,
+        MessageBuilder::default()
+          .role("user")
+          .content("And after Tuesday?")
+          .build()?,
+        MessageBuilder::default()
+          .role("assistant")
+          .content("Wednesday")
+          .build()?,
+      ])
+      .build()?;
+    service.save_conversation(&mut conversation).await?;
+    let edited = service
+      .edit_message(
+        &conversation.id,
+        &conversation.messages[2].id,
+        "And after Wednesday?",
+      )
+      .await?;
+    assert_ne!(conversation.messages[2].id, edited.id);
+    assert_eq!("user", edited.role);
+    assert_eq!(now, edited.created_at);
+    let from_db = service
+      .get_conversation_with_messages(&conversation.id)
+      .await?;
+    let contents = from_db
+      .messages
+      .iter()
+      .map(|message| message.content.clone().unwrap_or_default())
+      .collect::<Vec<_>>();
+    assert_eq!(
+      vec![
+        "What day comes after Monday?",
+        "Tuesday",
+        "And after Wednesday?"
+      ],
+      contents
+    );
+    let (branched,) = sqlx::query_as::<_, (i64,)>(
+      "SELECT count(*) FROM messages WHERE conversation_id = ? AND branch_id IS NOT NULL",
+    )
+    .bind(&conversation.id)
+    .fetch_one(&service.pool)
+    .await?;
+    assert_eq!(2, branched);
+    // saving the conversation from the UI does not remove the branch
+    service.save_conversation(&mut from_db.clone()).await?;
+    let (branched,) = sqlx::query_as::<_, (i64,)>(
+      "SELECT count(*) FROM messages WHERE conversation_id = ? AND branch_id IS NOT NULL",
+    )
+    .bind(&conversation.id)
+    .fetch_one(&service.pool)
+    .await?;
+    assert_eq!(2, branched);
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_db_service_fork_conversation(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_tempdir, now, service) = db_service;
+    let mut conversation = ConversationBuilder::default()
+      .title("test title")
+      .messages(vec![
+        MessageBuilder::default()
+          .role("user")
+          .content("What day comes after Monday?")
+          .build()?,
+        MessageBuilder::default()
+          .role("assistant")
+          .content("Tuesday")
+          .model("testalias:instruct")
+          .prompt_tokens(12)
+          .completion_tokens(3)
+          .build()?,
+        MessageBuilder::default()
+          .role("user")
+          .content("And after Tuesday?")
+          .build()?,
+      ])
+      .build()?;
+    service.save_conversation(&mut conversation).await?;
+    service.tag_conversation(&conversation.id, "work").await?;
+    let fork = service
+      .fork_conversation(&conversation.id, &conversation.messages[1].id)
+      .await?;
+    assert_ne!(conversation.id, fork.id);
+    assert_eq!("test title", fork.title);
+    assert_eq!(now, fork.created_at);
+    let from_db = service.get_conversation_with_messages(&fork.id).await?;
+    assert_eq!(fork.messages, from_db.messages);
+    assert!(from_db.tags.is_empty());
+    let copied = from_db
+      .messages
+      .iter()
+      .map(|message| {
+        (
+          message.role.as_str(),
+          message.content.as_deref(),
+          message.prompt_tokens,
+        )
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(
+      vec![
+        ("user", Some("What day comes after Monday?"), None),
+        ("assistant", Some("Tuesday"), Some(12)),
+      ],
+      copied
+    );
+    assert!(from_db
+      .messages
+      .iter()
+      .all(|message| conversation.messages.iter().all(|m| m.id != message.id)));
+    let original = service
+      .get_conversation_with_messages(&conversation.id)
+      .await?;
+    assert_eq!(3, original.messages.len());
+
+    let result = service.fork_conversation(&conversation.id, "unknown").await;
+    assert_eq!(
+      "sqlx_query: no rows returned by a query that expected to return at least one row\ntable: messages",
+      result.unwrap_err().to_string()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_db_service_migration_status(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_tempdir, _now, service) = db_service;
+    let status = service.migration_status().await?;
+    assert_eq!(
+      MIGRATOR
+        .iter()
+        .filter(|m| m.migration_type.is_up_migration())
+        .count(),
+      status.len()
+    );
+    assert!(status
+      .iter()
+      .all(|migration| migration.applied_at.is_some() && !migration.unknown));
+    assert_eq!(
+      (1, "create conversations"),
+      (status[0].version, status[0].description.as_str())
+    );
+
+    sqlx::query(
+      "INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time)
+        VALUES (9999, 'from newer app', 1, x'00', 0)",
+    )
+    .execute(&service.pool)
+    .await?;
+    let status = service.migration_status().await?;
+    let last = status.last().unwrap();
+    assert_eq!((9999, true), (last.version, last.unknown));
+    assert!(service.migrate().await.is_err());
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_db_service_branch_messages(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_tempdir, _now, service) = db_service;
+    let mut conversation = ConversationBuilder::default()
+      .title("test title")
+      .messages(vec![
+        MessageBuilder::default()
+          .role("user")
+          .content("What day comes after Monday?")
+          .build()?,
+        MessageBuilder::default()
+          .role("assistant")
+          .content("Tuesday")
+          .build()?,
+      ])
+      .build()?;
+    service.save_conversation(&mut conversation).await?;
+    service
+      .branch_messages(&conversation.id, &conversation.messages[1].id)
+      .await?;
+    let from_db = service
+      .get_conversation_with_messages(&conversation.id)
+      .await?;
+    assert_eq!(conversation.messages[..1], from_db.messages);
+    let (branched,) = sqlx::query_as::<_, (i64,)>(
+      "SELECT count(*) FROM messages WHERE conversation_id = ? AND branch_id IS NOT NULL",
+    )
+    .bind(&conversation.id)
+    .fetch_one(&service.pool)
+    .await?;
+    assert_eq!(1, branched);
+    let result = service
+      .branch_messages(&conversation.id, &conversation.messages[1].id)
+      .await;
+    assert!(result.is_err());
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_db_service_edit_message_not_found(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_tempdir, _now, service) = db_service;
+    let mut conversation = ConversationBuilder::default().build()?;
+    service.save_conversation(&mut conversation).await?;
+    let result = service
+      .edit_message(&conversation.id, "unknown", "test content")
+      .await;
+    assert_eq!(
+      "sqlx_query: no rows returned by a query that expected to return at least one row\ntable: messages",
+      result.unwrap_err().to_string()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_db_service_share_link(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_tempdir, now, service) = db_service;
+    let mut conversation = ConversationBuilder::default()
+      .title("test title")
+      .messages(vec![MessageBuilder::default()
+        .role("user")
+        .content("What day comes after Monday?")
+        .build()?])
+      .build()?;
+    service.save_conversation(&mut conversation).await?;
+    let share_link = service
+      .create_share_link(&conversation.id, Duration::hours(1))
+      .await?;
+    assert_eq!(conversation.id, share_link.conversation_id);
+    assert_eq!(now + Duration::hours(1), share_link.expires_at);
+    let shared = service.get_shared_conversation(&share_link.token).await?;
+    assert_eq!(conversation.id, shared.id);
+    assert_eq!(1, shared.messages.len());
+    service.delete_conversations(&conversation.id).await?;
+    let result = service.get_shared_conversation(&share_link.token).await;
+    assert!(result.is_err());
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_db_service_share_link_expired(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_tempdir, _now, service) = db_service;
+    let mut conversation = ConversationBuilder::default().build()?;
+    service.save_conversation(&mut conversation).await?;
+    let share_link = service
+      .create_share_link(&conversation.id, Duration::zero())
+      .await?;
+    let result = service.get_shared_conversation(&share_link.token).await;
+    assert_eq!(
+      "sqlx_query: no rows returned by a query that expected to return at least one row\ntable: share_links",
+      result.unwrap_err().to_string()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_db_service_share_link_conversation_not_found(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_tempdir, _now, service) = db_service;
+    let result = service
+      .create_share_link("unknown", Duration::hours(1))
+      .await;
+    assert_eq!(
+      "sqlx_query: no rows returned by a query that expected to return at least one row\ntable: conversations",
+      result.unwrap_err().to_string()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_db_service_conversation_tags(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_tempdir, _now, service) = db_service;
+    for id in ["c1", "c2", "c3"] {
+      let mut convo = ConversationBuilder::default().id(id).title(id).build()?;
+      service.save_conversation(&mut convo).await?;
+    }
+    service.tag_conversation("c1", "work").await?;
+    service.tag_conversation("c1", "personal").await?;
+    service.tag_conversation("c1", "work").await?;
+    service.tag_conversation("c2", "work").await?;
+
+    let query = ConversationsQuery {
+      tag: Some("work".to_string()),
+      ..Default::default()
+    };
+    let convos = service.list_conversations(&query).await?;
+    let tagged = convos
+      .iter()
+      .map(|convo| (convo.id.as_str(), convo.tags.clone()))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      vec![
+        ("c2", vec!["work".to_string()]),
+        ("c1", vec!["personal".to_string(), "work".to_string()]),
+      ],
+      tagged
+    );
+
+    service.untag_conversation("c1", "personal").await?;
+    let convo = service.get_conversation_with_messages("c1").await?;
+    assert_eq!(vec!["work".to_string()], convo.tags);
+    let query = ConversationsQuery {
+      tag: Some("personal".to_string()),
+      ..Default::default()
+    };
+    assert!(service.list_conversations(&query).await?.is_empty());
+
+    service.delete_conversations("c1").await?;
+    let query = ConversationsQuery {
+      tag: Some("work".to_string()),
+      ..Default::default()
+    };
+    assert_eq!(1, service.list_conversations(&query).await?.len());
+
+    let result = service.tag_conversation("unknown", "work").await;
+    assert_eq!(
+      "sqlx_query: no rows returned by a query that expected to return at least one row\ntable: conversations",
+      result.unwrap_err().to_string()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_db_service_list_client_usage(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_tempdir, now, service) = db_service;
+    for (user_agent, app_name, prompt_tokens) in [
+      (Some("curl/8.4.0"), None, 10),
+      (Some("python-requests/2.31"), Some("notes-app"), 20),
+      (Some("python-requests/2.31"), Some("notes-app"), 30),
+      (None, None, 5),
+    ] {
+      let mut record = UsageRecord {
+        model: "testalias:instruct".to_string(),
+        user_agent: user_agent.map(str::to_string),
+        app_name: app_name.map(str::to_string),
+        prompt_tokens,
+        completion_tokens: 1,
+        ..Default::default()
+      };
+      service.save_usage(&mut record).await?;
+      assert!(!record.id.is_empty());
+      assert_eq!(now, record.created_at);
+    }
+    let clients = service.list_client_usage(None, 2).await?;
+    assert_eq!(
+      vec![
+        ClientUsage {
+          user_agent: Some("python-requests/2.31".to_string()),
+          app_name: Some("notes-app".to_string()),
+          requests: 2,
+          prompt_tokens: 50,
+          completion_tokens: 2,
+        },
+        ClientUsage {
+          user_agent: Some("curl/8.4.0".to_string()),
+          app_name: None,
+          requests: 1,
+          prompt_tokens: 10,
+          completion_tokens: 1,
+        },
+      ],
+      clients
+    );
+    let clients = service
+      .list_client_usage(Some(now + Duration::hours(1)), 10)
+      .await?;
+    assert!(clients.is_empty());
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_db_service_list_usage_stats(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_tempdir, now, service) = db_service;
+    for (model, app_name, latency_ms) in [
+      ("testalias:instruct", Some("notes-app"), Some(100)),
+      ("testalias:instruct", Some("notes-app"), Some(300)),
+      ("testalias:instruct", None, None),
+      ("llama3:instruct", Some("notes-app"), Some(50)),
+    ] {
+      let mut record = UsageRecord {
+        model: model.to_string(),
+        user_agent: Some("curl/8.4.0".to_string()),
+        app_name: app_name.map(str::to_string),
+        prompt_tokens: 10,
+        completion_tokens: 2,
+        latency_ms,
+        ..Default::default()
+      };
+      service.save_usage(&mut record).await?;
+    }
+    let day = now.format("%Y-%m-%d").to_string();
+    let stats = service.list_usage_stats(UsagePeriod::Day, None, 10).await?;
+    assert_eq!(
+      vec![
+        UsageStats {
+          period: day.clone(),
+          model: "testalias:instruct".to_string(),
+          client: Some("notes-app".to_string()),
+          requests: 2,
+          prompt_tokens: 20,
+          completion_tokens: 4,
+          avg_latency_ms: Some(200),
+        },
+        UsageStats {
+          period: day.clone(),
+          model: "llama3:instruct".to_string(),
+          client: Some("notes-app".to_string()),
+          requests: 1,
+          prompt_tokens: 10,
+          completion_tokens: 2,
+          avg_latency_ms: Some(50),
+        },
+        UsageStats {
+          period: day,
+          model: "testalias:instruct".to_string(),
+          client: Some("curl/8.4.0".to_string()),
+          requests: 1,
+          prompt_tokens: 10,
+          completion_tokens: 2,
+          avg_latency_ms: None,
+        },
+      ],
+      stats
+    );
+    let stats = service
+      .list_usage_stats(UsagePeriod::Month, None, 1)
+      .await?;
+    assert_eq!(1, stats.len());
+    assert_eq!(now.format("%Y-%m").to_string(), stats[0].period);
+    let stats = service
+      .list_usage_stats(UsagePeriod::Day, Some(now + Duration::hours(1)), 10)
+      .await?;
+    assert!(stats.is_empty());
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_db_service_api_keys(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_tempdir, now, service) = db_service;
+    let (admin, admin_secret) = service
+      .create_api_key("ops", ApiKeyRole::Admin, RequestPriority::Interactive)
+      .await?;
+    let (user, user_secret) = service
+      .create_api_key("alice", ApiKeyRole::User, RequestPriority::Batch)
+      .await?;
+    assert!(admin_secret.starts_with("bodhi-"));
+    assert_ne!(admin_secret, user_secret);
+    assert_eq!((ApiKeyRole::Admin, now), (admin.role, admin.created_at));
+    assert_eq!(RequestPriority::Batch, user.priority);
+    let (key_hash,) = sqlx::query_as::<_, (String,)>("SELECT key_hash FROM api_keys WHERE id = ?")
+      .bind(&admin.id)
+      .fetch_one(&service.pool)
+      .await?;
+    assert_eq!(api_key_hash(&admin_secret), key_hash);
+    assert_eq!(user, service.find_api_key(&user_secret).await?);
+    assert!(service.find_api_key("bodhi-unknown").await.is_err());
+    let result = service
+      .create_api_key("alice", ApiKeyRole::Admin, RequestPriority::Interactive)
+      .await;
+    assert!(matches!(result, Err(DbError::ApiKeyExists(name)) if name == "alice"));
+
+    service.revoke_api_key(&user.id).await?;
+    assert!(service.find_api_key(&user_secret).await.is_err());
+    assert!(service.revoke_api_key(&user.id).await.is_err());
+    let (rotated, _) = service
+      .create_api_key("alice", ApiKeyRole::User, RequestPriority::Batch)
+      .await?;
+    assert_eq!("alice", rotated.name);
+    let keys = service.list_api_keys().await?;
+    assert_eq!(3, keys.len());
+    let revoked = keys
+      .iter()
+      .filter(|key| key.revoked_at == Some(now))
+      .map(|key| key.id.as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(vec![user.id.as_str()], revoked);
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_db_service_audit_log(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_tempdir, now, service) = db_service;
+    let mut setting = AuditEntry {
+      id: String::new(),
+      created_at: DateTime::<Utc>::default(),
+      actor: Some("ops".to_string()),
+      action: AuditAction::SettingUpdate,
+      target: "BODHI_PORT".to_string(),
+      before: Some("1135".to_string()),
+      after: Some("8080".to_string()),
+    };
+    service.save_audit_entry(&mut setting).await?;
+    assert!(!setting.id.is_empty());
+    assert_eq!(now, setting.created_at);
+    let mut key = AuditEntry {
+      id: String::new(),
+      created_at: DateTime::<Utc>::default(),
+      actor: Some("cli".to_string()),
+      action: AuditAction::KeyRevoke,
+      target: "testid".to_string(),
+      before: None,
+      after: None,
+    };
+    service.save_audit_entry(&mut key).await?;
+
+    assert_eq!(
+      vec![key.clone(), setting.clone()],
+      service.list_audit_entries(None, None, 10).await?
+    );
+    assert_eq!(
+      vec![setting],
+      service
+        .list_audit_entries(Some(AuditAction::SettingUpdate), None, 10)
+        .await?
+    );
+    assert_eq!(vec![key], service.list_audit_entries(None, None, 1).await?);
+    assert!(service
+      .list_audit_entries(None, Some(now + Duration::hours(1)), 10)
+      .await?
+      .is_empty());
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_db_service_downloads(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_tempdir, now, service) = db_service;
+    let mut first = Download {
+      repo: "MyFactory/testalias-gguf".to_string(),
+      filename: "testalias.Q8_0.gguf".to_string(),
+      revision: "main".to_string(),
+      ..Default::default()
+    };
+    service.create_download(&mut first).await?;
+    assert!(!first.id.is_empty());
+    assert_eq!(now, first.created_at);
+    let mut second = Download {
+      repo: "MyFactory/testalias-gguf".to_string(),
+      filename: "testalias.Q4_0.gguf".to_string(),
+      revision: "main".to_string(),
+      ..Default::default()
+    };
+    service.create_download(&mut second).await?;
+
+    first.status = DownloadStatus::Paused;
+    first.downloaded_bytes = 1024;
+    first.total_bytes = Some(4096);
+    service.update_download(&mut first).await?;
+    assert_eq!(first, service.get_download(&first.id).await?);
+    assert_eq!(
+      vec![second.clone(), first.clone()],
+      service.list_downloads(10).await?
+    );
+    assert_eq!(vec![second], service.list_downloads(1).await?);
+
+    let mut missing = Download {
+      id: "missing".to_string(),
+      ..first
+    };
+    assert!(matches!(
+      service.update_download(&mut missing).await,
+      Err(DbError::Sqlx {
+        source: sqlx::Error::RowNotFound,
+        ..
+      })
+    ));
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_db_service_message_usage(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_tempdir, now, service) = db_service;
+    let question = MessageBuilder::default()
+      .role("user")
+      .content("What day comes after Monday?")
+      .created_at(now)
+      .build()?;
+    let reply = MessageBuilder::default()
+      .role("assistant")
+      .content("Tuesday")
+      .created_at(now)
+      .model("testalias:instruct")
+      .prompt_tokens(12)
+      .completion_tokens(3)
+      .build()?;
+    let mut convo = ConversationBuilder::default()
+      .title("Days of the week")
+      .messages(vec![question, reply])
+      .build()?;
+    service.save_conversation(&mut convo).await?;
+    let from_db = service.get_conversation_with_messages(&convo.id).await?;
+    assert_eq!(convo.messages, from_db.messages);
+
+    // the replies moved to a branch by an edit are still counted
+    service
+      .edit_message(
+        &convo.id,
+        &convo.messages[0].id,
+        "What day comes after Tuesday?",
+      )
+      .await?;
+    let mut reply = MessageBuilder::default()
+      .conversation_id(convo.id.clone())
+      .role("assistant")
+      .content("Wednesday")
+      .created_at(now)
+      .model("testalias:instruct")
+      .prompt_tokens(14)
+      .completion_tokens(4)
+      .build()?;
+    service.save_message(&mut reply).await?;
+    let usage = service.list_conversation_usage(None, None, 10).await?;
+    assert_eq!(
+      vec![ConversationUsage {
+        conversation_id: convo.id.clone(),
+        title: "Days of the week".to_string(),
+        replies: 2,
+        prompt_tokens: 26,
+        completion_tokens: 7,
+      }],
+      usage
+    );
+    let usage = service
+      .list_conversation_usage(Some(now + Duration::hours(1)), None, 10)
+      .await?;
+    assert!(usage.is_empty());
+    let usage = service
+      .list_conversation_usage(None, Some("alice"), 10)
+      .await?;
+    assert!(usage.is_empty());
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_db_service_canary_samples(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_tempdir, now, service) = db_service;
+    let mut sample = CanarySample {
+      model: "llama3:instruct".to_string(),
+      canary_model: "phi3:mini".to_string(),
+      request: r#"{"model":"llama3:instruct","messages":[]}"#.to_string(),
+      output: "Tuesday".to_string(),
+      canary_output: "Tuesday.".to_string(),
+      canary_error: None,
+      diff: "-Tuesday\n+Tuesday.\n".to_string(),
+      ..Default::default()
+    };
+    service.save_canary_sample(&mut sample).await?;
+    assert!(!sample.id.is_empty());
+    assert_eq!(now, sample.created_at);
+    assert_eq!(vec![sample], service.list_canary_samples(10).await?);
+    assert!(service.list_canary_samples(0).await?.is_empty());
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_db_service_search_messages(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_tempdir, _now, service) = db_service;
+    let mut weekdays = ConversationBuilder::default()
+      .title("Days of the week")
+      .messages(vec![
+        MessageBuilder::default()
+          .role("user")
+          .content("What day comes after Monday?")
+          .build()?,
+        MessageBuilder::default()
+          .role("assistant")
+          .content("Tuesday comes after Monday")
+          .build()?,
+      ])
+      .build()?;
+    service.save_conversation(&mut weekdays).await?;
+    let mut colors = ConversationBuilder::default()
+      .title("Colors")
+      .messages(vec![MessageBuilder::default()
+        .role("user")
+        .content("What color is the sky?")
+        .build()?])
+      .build()?;
+    service.save_conversation(&mut colors).await?;
+
+    let matches = service.search_messages("tuesday", None, 10).await?;
+    assert_eq!(1, matches.len());
+    assert_eq!(weekdays.id, matches[0].conversation_id);
+    assert_eq!("Days of the week", matches[0].title);
+    assert_eq!(weekdays.messages[1].id, matches[0].message_id);
+    assert_eq!("assistant", matches[0].role);
+    assert_eq!(
+      "<mark>Tuesday</mark> comes after Monday",
+      matches[0].snippet
+    );
+
+    let matches = service.search_messages("mond", None, 10).await?;
+    assert_eq!(2, matches.len());
+    let matches = service.search_messages("sky\" OR", None, 10).await?;
+    assert!(matches.is_empty());
+    assert!(service.search_messages("  ", None, 10).await?.is_empty());
+
+    // edited messages are kept as a branch and not matched
+    service
+      .edit_message(&colors.id, &colors.messages[0].id, "What color is grass?")
+      .await?;
+    assert!(service.search_messages("sky", None, 10).await?.is_empty());
+    assert_eq!(1, service.search_messages("grass", None, 10).await?.len());
+
+    service.delete_conversations(&weekdays.id).await?;
+    assert!(service
+      .search_messages("tuesday", None, 10)
+      .await?
+      .is_empty());
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("tuesday", Some(r#""tuesday"*"#))]
+  #[case("after  monday", Some(r#""after" "monday"*"#))]
+  #[case(r#"sky" OR"#, Some(r#""sky""" "OR"*"#))]
+  #[case(" ", None)]
+  fn test_db_service_fts_query(#[case] input: &str, #[case] expected: Option<&str>) {
+    assert_eq!(expected.map(str::to_string), fts_query(input));
+  }
+
   #[test]
   fn test_time_service_utc_now() -> anyhow::Result<()> {
     let now = TimeService.utc_now();
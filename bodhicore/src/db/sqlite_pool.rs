@@ -1,5 +1,6 @@
-use super::DbError;
-use sqlx::SqlitePool;
+use super::{DbError, DbService, DbServiceFn, PgDbService, TimeServiceFn};
+use sqlx::{PgPool, SqlitePool};
+use std::sync::Arc;
 
 pub struct DbPool {}
 
@@ -13,11 +14,51 @@ impl DbPool {
       })?;
     Ok(pool)
   }
+
+  /// Connects to the database of the url, `postgres://` and `postgresql://` urls are served by
+  /// [`PgDbService`], the `sqlite:` urls by [`DbService`]
+  pub async fn connect_service(
+    url: &str,
+    time_service: Arc<dyn TimeServiceFn>,
+  ) -> std::result::Result<Arc<dyn DbServiceFn>, DbError> {
+    if !is_postgres_url(url) {
+      let pool = Self::connect(url).await?;
+      return Ok(Arc::new(DbService::new(pool, time_service)));
+    }
+    let pool = PgPool::connect(url)
+      .await
+      .map_err(|source| DbError::SqlxConnect {
+        source,
+        url: redact_password(url),
+      })?;
+    Ok(Arc::new(PgDbService::new(pool, time_service)))
+  }
+}
+
+fn is_postgres_url(url: &str) -> bool {
+  url.starts_with("postgres://") || url.starts_with("postgresql://")
+}
+
+// the connection errors are logged and shown to the user, without the password of the url
+fn redact_password(url: &str) -> String {
+  let Some((scheme, rest)) = url.split_once("://") else {
+    return url.to_string();
+  };
+  let Some((userinfo, host)) = rest.split_once('@') else {
+    return url.to_string();
+  };
+  match userinfo.split_once(':') {
+    Some((user, _)) => format!("{scheme}://{user}:***@{host}"),
+    None => url.to_string(),
+  }
 }
 
 #[cfg(test)]
 mod test {
-  use super::DbPool;
+  use super::{redact_password, DbPool};
+  use crate::db::TimeService;
+  use rstest::rstest;
+  use std::{fs::File, sync::Arc};
 
   #[tokio::test]
   async fn test_db_pool_raises_error() -> anyhow::Result<()> {
@@ -26,4 +67,34 @@ mod test {
     assert_eq!("sqlx_connect: error returned from database: (code: 14) unable to open database file\nurl: sqlite:non-existing-db.sqlite", pool.unwrap_err().to_string());
     Ok(())
   }
+
+  #[rstest]
+  #[case("sqlite:bodhi.sqlite", "sqlite:bodhi.sqlite")]
+  #[case("postgres://bodhi@db:5432/bodhi", "postgres://bodhi@db:5432/bodhi")]
+  #[case(
+    "postgres://bodhi:secret@db:5432/bodhi",
+    "postgres://bodhi:***@db:5432/bodhi"
+  )]
+  fn test_db_pool_redact_password(#[case] url: &str, #[case] expected: &str) {
+    assert_eq!(expected, redact_password(url));
+  }
+
+  #[tokio::test]
+  async fn test_db_pool_connect_service_sqlite() -> anyhow::Result<()> {
+    let tempdir = tempfile::tempdir()?;
+    let dbpath = tempdir.path().join("testdb.sqlite");
+    File::create(&dbpath)?;
+    let db_service = DbPool::connect_service(
+      &format!("sqlite:{}", dbpath.display()),
+      Arc::new(TimeService),
+    )
+    .await?;
+    db_service.migrate().await?;
+    assert!(db_service
+      .migration_status()
+      .await?
+      .iter()
+      .all(|migration| migration.applied_at.is_some()));
+    Ok(())
+  }
 }
@@ -1,7 +1,7 @@
 use crate::{
   db::DbError,
   oai::OpenAIApiError,
-  objs::ObjError,
+  objs::{Backend, ObjError},
   service::{DataServiceError, HubServiceError},
   shared_rw::ContextError,
 };
@@ -21,8 +21,14 @@ Run `bodhi list -r` to see list of pre-configured model aliases
   AliasNotFound(String),
   #[error("model alias '{0}' already exists. Use --force to overwrite the model alias config")]
   AliasExists(String),
+  #[error("{0} model alias file(s) have problems, fix the problems listed above")]
+  AliasLint(usize),
+  #[error("{0} model alias file(s) are invalid and skipped, the files they use cannot be told apart from the unused ones. Fix the problems listed by `bodhi alias lint` to prune")]
+  PruneSkippedAliases(usize),
   #[error("$HOME directory not found, set home directory using $HOME")]
   HomeDirectory,
+  #[error("the {0} backend is not available in this installation, install the build with {0} support or set BODHI_BACKEND to auto")]
+  BackendUnavailable(Backend),
 
   #[error(transparent)]
   Common(#[from] Common),
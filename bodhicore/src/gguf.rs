@@ -0,0 +1,547 @@
+use crate::error::Common;
+use std::{
+  collections::HashMap,
+  fs::File,
+  io::{self, BufReader, Read},
+  path::Path,
+};
+use thiserror::Error;
+
+pub static GGUF_MAGIC: &[u8; 4] = b"GGUF";
+pub static KEY_TOKENIZER_MODEL: &str = "tokenizer.ggml.model";
+pub static KEY_TOKENIZER_TOKENS: &str = "tokenizer.ggml.tokens";
+pub static KEY_TOKENIZER_TOKEN_TYPE: &str = "tokenizer.ggml.token_type";
+pub static KEY_TOKENIZER_ADD_SPACE_PREFIX: &str = "tokenizer.ggml.add_space_prefix";
+pub static KEY_ARCHITECTURE: &str = "general.architecture";
+
+// context length of llama.cpp when n_ctx is not set
+static DEFAULT_N_CTX: u64 = 512;
+// the KV cache is f16 by default
+static KV_CACHE_TYPE_SIZE: u64 = 2;
+
+// guards against allocating absurd amounts of memory reading a corrupt file
+static MAX_ARRAY_LEN: u64 = 1 << 24;
+static MAX_STRING_LEN: u64 = 1 << 24;
+
+#[derive(Debug, Error)]
+pub enum GGUFError {
+  #[error(transparent)]
+  Common(#[from] Common),
+  #[error("file is not in GGUF format, invalid magic bytes")]
+  InvalidMagic,
+  #[error("GGUF version {0} not supported")]
+  UnsupportedVersion(u32),
+  #[error("invalid GGUF value type {0}")]
+  InvalidValueType(u32),
+  #[error("GGUF value too large, length {0}")]
+  ValueTooLarge(u64),
+  #[error("metadata '{0}' not found in GGUF file")]
+  MissingKey(String),
+  #[error("tokenizer model '{0}' not supported")]
+  UnsupportedTokenizer(String),
+  #[error("token id {0} not found in model vocabulary")]
+  TokenOutOfRange(u32),
+}
+
+type Result<T> = std::result::Result<T, GGUFError>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GGUFValue {
+  U8(u8),
+  I8(i8),
+  U16(u16),
+  I16(i16),
+  U32(u32),
+  I32(i32),
+  F32(f32),
+  Bool(bool),
+  String(String),
+  Array(Vec<GGUFValue>),
+  U64(u64),
+  I64(i64),
+  F64(f64),
+}
+
+impl GGUFValue {
+  pub fn as_str(&self) -> Option<&str> {
+    match self {
+      GGUFValue::String(value) => Some(value),
+      _ => None,
+    }
+  }
+
+  pub fn as_u64(&self) -> Option<u64> {
+    match self {
+      GGUFValue::U8(value) => Some(*value as u64),
+      GGUFValue::U16(value) => Some(*value as u64),
+      GGUFValue::U32(value) => Some(*value as u64),
+      GGUFValue::U64(value) => Some(*value),
+      GGUFValue::I8(value) => u64::try_from(*value).ok(),
+      GGUFValue::I16(value) => u64::try_from(*value).ok(),
+      GGUFValue::I32(value) => u64::try_from(*value).ok(),
+      GGUFValue::I64(value) => u64::try_from(*value).ok(),
+      _ => None,
+    }
+  }
+
+  pub fn as_bool(&self) -> Option<bool> {
+    match self {
+      GGUFValue::Bool(value) => Some(*value),
+      _ => None,
+    }
+  }
+
+  pub fn as_array(&self) -> Option<&Vec<GGUFValue>> {
+    match self {
+      GGUFValue::Array(values) => Some(values),
+      _ => None,
+    }
+  }
+}
+
+/// Key-value metadata from the header of a GGUF model file, tensor data is not read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GGUFMetadata {
+  pub version: u32,
+  pub tensor_count: u64,
+  pub metadata: HashMap<String, GGUFValue>,
+}
+
+impl GGUFMetadata {
+  pub fn read(path: &Path) -> Result<Self> {
+    let io_err = |err: io::Error| Common::IoFile {
+      source: err,
+      path: path.display().to_string(),
+    };
+    let file = File::open(path).map_err(io_err)?;
+    let mut reader = BufReader::new(file);
+    Self::read_from(&mut reader).map_err(|err| match err {
+      ReadError::Io(err) => GGUFError::from(io_err(err)),
+      ReadError::Gguf(err) => err,
+    })
+  }
+
+  fn read_from<R: Read>(reader: &mut R) -> std::result::Result<Self, ReadError> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != GGUF_MAGIC {
+      return Err(GGUFError::InvalidMagic.into());
+    }
+    let version = read_u32(reader)?;
+    if !(2..=3).contains(&version) {
+      return Err(GGUFError::UnsupportedVersion(version).into());
+    }
+    let tensor_count = read_u64(reader)?;
+    let kv_count = read_u64(reader)?;
+    let mut metadata = HashMap::new();
+    for _ in 0..kv_count {
+      let key = read_string(reader)?;
+      let value_type = read_u32(reader)?;
+      let value = read_value(reader, value_type)?;
+      metadata.insert(key, value);
+    }
+    Ok(Self {
+      version,
+      tensor_count,
+      metadata,
+    })
+  }
+
+  pub fn get(&self, key: &str) -> Result<&GGUFValue> {
+    self
+      .metadata
+      .get(key)
+      .ok_or_else(|| GGUFError::MissingKey(key.to_string()))
+  }
+}
+
+/// Approximate memory needed to load a GGUF model, the weights and the KV cache for the context.
+/// The compute buffers of llama.cpp are not included, these are small in comparison.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryEstimate {
+  pub weights: u64,
+  pub kv_cache: u64,
+  pub n_ctx: u64,
+}
+
+impl MemoryEstimate {
+  /// Estimates the memory for the model file at `path` with the `n_ctx` of the params,
+  /// 512 if not set, and the context length the model was trained with if 0
+  pub fn read(path: &Path, n_ctx: Option<i32>) -> Result<Self> {
+    let weights = path
+      .metadata()
+      .map_err(|err| Common::IoFile {
+        source: err,
+        path: path.display().to_string(),
+      })?
+      .len();
+    let metadata = GGUFMetadata::read(path)?;
+    Self::from_metadata(&metadata, weights, n_ctx)
+  }
+
+  fn from_metadata(metadata: &GGUFMetadata, weights: u64, n_ctx: Option<i32>) -> Result<Self> {
+    let arch = metadata
+      .get(KEY_ARCHITECTURE)?
+      .as_str()
+      .ok_or_else(|| GGUFError::MissingKey(KEY_ARCHITECTURE.to_string()))?;
+    let get_u64 = |key: &str| -> Result<u64> {
+      let key = format!("{arch}.{key}");
+      metadata
+        .get(&key)?
+        .as_u64()
+        .ok_or(GGUFError::MissingKey(key))
+    };
+    let n_ctx = match n_ctx {
+      None => DEFAULT_N_CTX,
+      Some(n_ctx) if n_ctx <= 0 => get_u64("context_length")?,
+      Some(n_ctx) => n_ctx as u64,
+    };
+    let n_layer = get_u64("block_count")?;
+    let n_embd = get_u64("embedding_length")?;
+    let n_head = get_u64("attention.head_count")?.max(1);
+    // grouped-query attention caches fewer heads, some models have a head count per layer
+    let n_head_kv = get_u64("attention.head_count_kv").unwrap_or(n_head);
+    let head_dim = get_u64("attention.key_length").unwrap_or(n_embd / n_head);
+    let kv_cache = 2 * n_layer * n_ctx * n_head_kv * head_dim * KV_CACHE_TYPE_SIZE;
+    Ok(Self {
+      weights,
+      kv_cache,
+      n_ctx,
+    })
+  }
+
+  pub fn total(&self) -> u64 {
+    self.weights + self.kv_cache
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TokenizerModel {
+  // sentencepiece, tokens use '▁' for space and <0xXX> for raw bytes
+  Llama,
+  // byte-level BPE, tokens are bytes mapped to printable unicode chars
+  Gpt2,
+}
+
+// token type for control tokens like <s>, </s>, <|eot_id|>
+static TOKEN_TYPE_CONTROL: u64 = 3;
+static TOKEN_TYPE_BYTE: u64 = 6;
+
+/// Model vocabulary loaded from GGUF metadata, used to convert token ids back to text.
+#[derive(Debug, Clone)]
+pub struct Vocab {
+  model: TokenizerModel,
+  tokens: Vec<String>,
+  token_types: Vec<u64>,
+  add_space_prefix: bool,
+}
+
+impl TryFrom<&GGUFMetadata> for Vocab {
+  type Error = GGUFError;
+
+  fn try_from(metadata: &GGUFMetadata) -> Result<Self> {
+    let model = match metadata.get(KEY_TOKENIZER_MODEL)?.as_str() {
+      Some("llama") => TokenizerModel::Llama,
+      Some("gpt2") => TokenizerModel::Gpt2,
+      Some(model) => return Err(GGUFError::UnsupportedTokenizer(model.to_string())),
+      None => return Err(GGUFError::MissingKey(KEY_TOKENIZER_MODEL.to_string())),
+    };
+    let tokens = metadata
+      .get(KEY_TOKENIZER_TOKENS)?
+      .as_array()
+      .ok_or_else(|| GGUFError::MissingKey(KEY_TOKENIZER_TOKENS.to_string()))?
+      .iter()
+      .map(|token| token.as_str().unwrap_or_default().to_string())
+      .collect::<Vec<_>>();
+    let token_types = metadata
+      .metadata
+      .get(KEY_TOKENIZER_TOKEN_TYPE)
+      .and_then(|value| value.as_array())
+      .map(|types| {
+        types
+          .iter()
+          .map(|value| value.as_u64().unwrap_or(1))
+          .collect::<Vec<_>>()
+      })
+      .unwrap_or_default();
+    let add_space_prefix = metadata
+      .metadata
+      .get(KEY_TOKENIZER_ADD_SPACE_PREFIX)
+      .and_then(|value| value.as_bool())
+      .unwrap_or(model == TokenizerModel::Llama);
+    Ok(Self {
+      model,
+      tokens,
+      token_types,
+      add_space_prefix,
+    })
+  }
+}
+
+impl Vocab {
+  /// Converts the token ids to text, control tokens are rendered only if `special` is true.
+  pub fn detokenize(&self, token_ids: &[u32], special: bool) -> Result<String> {
+    let mut bytes = Vec::<u8>::new();
+    for id in token_ids {
+      let token = self
+        .tokens
+        .get(*id as usize)
+        .ok_or(GGUFError::TokenOutOfRange(*id))?;
+      let token_type = self.token_types.get(*id as usize).copied().unwrap_or(1);
+      if token_type == TOKEN_TYPE_CONTROL {
+        if special {
+          bytes.extend_from_slice(token.as_bytes());
+        }
+        continue;
+      }
+      match self.model {
+        TokenizerModel::Llama => {
+          if token_type == TOKEN_TYPE_BYTE {
+            if let Some(byte) = parse_byte_token(token) {
+              bytes.push(byte);
+              continue;
+            }
+          }
+          let piece = token.replace('\u{2581}', " ");
+          let piece = if bytes.is_empty() && self.add_space_prefix {
+            piece.strip_prefix(' ').unwrap_or(&piece).to_string()
+          } else {
+            piece
+          };
+          bytes.extend_from_slice(piece.as_bytes());
+        }
+        TokenizerModel::Gpt2 => {
+          for c in token.chars() {
+            match unicode_to_byte(c) {
+              Some(byte) => bytes.push(byte),
+              None => {
+                let mut buf = [0u8; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+              }
+            }
+          }
+        }
+      }
+    }
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+  }
+}
+
+fn parse_byte_token(token: &str) -> Option<u8> {
+  let hex = token.strip_prefix("<0x")?.strip_suffix('>')?;
+  u8::from_str_radix(hex, 16).ok()
+}
+
+// inverse of the GPT-2 bytes_to_unicode mapping
+fn unicode_to_byte(c: char) -> Option<u8> {
+  let code = c as u32;
+  let printable = |b: u32| {
+    (b'!' as u32..=b'~' as u32).contains(&b)
+      || (0xA1..=0xAC).contains(&b)
+      || (0xAE..=0xFF).contains(&b)
+  };
+  if code < 256 && printable(code) {
+    return Some(code as u8);
+  }
+  let offset = code.checked_sub(256)?;
+  (0u32..256)
+    .filter(|b| !printable(*b))
+    .nth(offset as usize)
+    .map(|b| b as u8)
+}
+
+#[derive(Debug)]
+enum ReadError {
+  Io(io::Error),
+  Gguf(GGUFError),
+}
+
+impl From<io::Error> for ReadError {
+  fn from(value: io::Error) -> Self {
+    ReadError::Io(value)
+  }
+}
+
+impl From<GGUFError> for ReadError {
+  fn from(value: GGUFError) -> Self {
+    ReadError::Gguf(value)
+  }
+}
+
+fn read_bytes<R: Read, const N: usize>(reader: &mut R) -> io::Result<[u8; N]> {
+  let mut buf = [0u8; N];
+  reader.read_exact(&mut buf)?;
+  Ok(buf)
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+  Ok(u32::from_le_bytes(read_bytes(reader)?))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+  Ok(u64::from_le_bytes(read_bytes(reader)?))
+}
+
+fn read_string<R: Read>(reader: &mut R) -> std::result::Result<String, ReadError> {
+  let len = read_u64(reader)?;
+  if len > MAX_STRING_LEN {
+    return Err(GGUFError::ValueTooLarge(len).into());
+  }
+  let mut buf = vec![0u8; len as usize];
+  reader.read_exact(&mut buf)?;
+  Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+fn read_value<R: Read>(
+  reader: &mut R,
+  value_type: u32,
+) -> std::result::Result<GGUFValue, ReadError> {
+  let value = match value_type {
+    0 => GGUFValue::U8(u8::from_le_bytes(read_bytes(reader)?)),
+    1 => GGUFValue::I8(i8::from_le_bytes(read_bytes(reader)?)),
+    2 => GGUFValue::U16(u16::from_le_bytes(read_bytes(reader)?)),
+    3 => GGUFValue::I16(i16::from_le_bytes(read_bytes(reader)?)),
+    4 => GGUFValue::U32(u32::from_le_bytes(read_bytes(reader)?)),
+    5 => GGUFValue::I32(i32::from_le_bytes(read_bytes(reader)?)),
+    6 => GGUFValue::F32(f32::from_le_bytes(read_bytes(reader)?)),
+    7 => GGUFValue::Bool(u8::from_le_bytes(read_bytes(reader)?) != 0),
+    8 => GGUFValue::String(read_string(reader)?),
+    9 => {
+      let item_type = read_u32(reader)?;
+      let len = read_u64(reader)?;
+      if len > MAX_ARRAY_LEN {
+        return Err(GGUFError::ValueTooLarge(len).into());
+      }
+      let mut values = Vec::with_capacity(len as usize);
+      for _ in 0..len {
+        values.push(read_value(reader, item_type)?);
+      }
+      GGUFValue::Array(values)
+    }
+    10 => GGUFValue::U64(u64::from_le_bytes(read_bytes(reader)?)),
+    11 => GGUFValue::I64(i64::from_le_bytes(read_bytes(reader)?)),
+    12 => GGUFValue::F64(f64::from_le_bytes(read_bytes(reader)?)),
+    value_type => return Err(GGUFError::InvalidValueType(value_type).into()),
+  };
+  Ok(value)
+}
+
+#[cfg(test)]
+mod test {
+  use super::{GGUFError, GGUFMetadata, GGUFValue, MemoryEstimate, Vocab};
+  use crate::test_utils::{gguf_strings, gguf_token_types, llama_vocab_metadata, write_gguf};
+  use rstest::rstest;
+  use std::fs;
+  use tempfile::TempDir;
+
+  #[rstest]
+  fn test_gguf_metadata_read() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let path = temp.path().join("model.gguf");
+    write_gguf(&path, llama_vocab_metadata())?;
+    let metadata = GGUFMetadata::read(&path)?;
+    assert_eq!(3, metadata.version);
+    assert_eq!(0, metadata.tensor_count);
+    assert_eq!(
+      Some("llama"),
+      metadata.get("general.architecture")?.as_str()
+    );
+    assert_eq!(Some(4096), metadata.get("llama.context_length")?.as_u64());
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_gguf_metadata_read_invalid_magic() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let path = temp.path().join("model.gguf");
+    fs::write(&path, b"not a gguf file")?;
+    let result = GGUFMetadata::read(&path);
+    assert!(matches!(result, Err(GGUFError::InvalidMagic)));
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(None, 512)]
+  #[case(Some(0), 4096)]
+  #[case(Some(2048), 2048)]
+  fn test_memory_estimate_kv_cache(
+    #[case] n_ctx: Option<i32>,
+    #[case] expected_n_ctx: u64,
+  ) -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let path = temp.path().join("model.gguf");
+    let mut metadata = llama_vocab_metadata();
+    metadata.extend([
+      ("llama.block_count", GGUFValue::U32(32)),
+      ("llama.embedding_length", GGUFValue::U32(4096)),
+      ("llama.attention.head_count", GGUFValue::U32(32)),
+      ("llama.attention.head_count_kv", GGUFValue::U32(8)),
+    ]);
+    write_gguf(&path, metadata)?;
+    let estimate = MemoryEstimate::read(&path, n_ctx)?;
+    assert_eq!(fs::metadata(&path)?.len(), estimate.weights);
+    assert_eq!(expected_n_ctx, estimate.n_ctx);
+    // K and V, for 32 layers of 8 heads of 128 dims in f16
+    assert_eq!(2 * 32 * expected_n_ctx * 8 * 128 * 2, estimate.kv_cache);
+    assert_eq!(estimate.weights + estimate.kv_cache, estimate.total());
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_memory_estimate_missing_metadata() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let path = temp.path().join("model.gguf");
+    write_gguf(&path, llama_vocab_metadata())?;
+    let result = MemoryEstimate::read(&path, None);
+    assert!(matches!(result, Err(GGUFError::MissingKey(key)) if key == "llama.block_count"));
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(vec![1, 4, 5, 6, 7, 3, 2], false, "Hello, world!\n")]
+  #[case(vec![1, 4, 5, 6, 7, 2], true, "<s> Hello, world!</s>")]
+  #[case(vec![6], false, "world")]
+  fn test_vocab_detokenize_llama(
+    #[case] token_ids: Vec<u32>,
+    #[case] special: bool,
+    #[case] expected: &str,
+  ) -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let path = temp.path().join("model.gguf");
+    write_gguf(&path, llama_vocab_metadata())?;
+    let vocab = Vocab::try_from(&GGUFMetadata::read(&path)?)?;
+    assert_eq!(expected, vocab.detokenize(&token_ids, special)?);
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_vocab_detokenize_gpt2() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let path = temp.path().join("model.gguf");
+    write_gguf(
+      &path,
+      vec![
+        (
+          "tokenizer.ggml.model",
+          GGUFValue::String("gpt2".to_string()),
+        ),
+        (
+          "tokenizer.ggml.tokens",
+          gguf_strings(&["Hello", ",", "Ġworld", "!", "Ċ", "<|eot_id|>", "Ã©"]),
+        ),
+        (
+          "tokenizer.ggml.token_type",
+          gguf_token_types(&[1, 1, 1, 1, 1, 3, 1]),
+        ),
+      ],
+    )?;
+    let vocab = Vocab::try_from(&GGUFMetadata::read(&path)?)?;
+    assert_eq!(
+      "Hello, world!\né",
+      vocab.detokenize(&[0, 1, 2, 3, 4, 5, 6], false)?
+    );
+    let result = vocab.detokenize(&[100], false);
+    assert!(matches!(result, Err(GGUFError::TokenOutOfRange(100))));
+    Ok(())
+  }
+}
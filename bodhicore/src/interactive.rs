@@ -1,30 +1,30 @@
 use crate::{
-  db::DbService,
+  backend::{relaunch, select_backend},
+  chat_session::{ChatSession, ReplyStats, SlashCommand},
+  db::{DbPool, DbService, DbServiceFn, TimeService},
   error::{BodhiError, Common},
-  objs::{Alias, ObjError},
+  oai::OpenAIApiError,
+  objs::{Alias, ChatCompletionRequest, ObjError},
   server::{RouterState, RouterStateFn},
   service::{AppServiceFn, HubServiceError},
-  SharedContextRw,
+  SharedContextRw, SharedContextRwFn,
 };
 use async_openai::types::{
-  ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage,
-  ChatCompletionRequestUserMessage, ChatCompletionRequestUserMessageContent,
-  CreateChatCompletionRequestArgs, CreateChatCompletionStreamResponse, Role,
+  ChatCompletionRequestMessage, ChatCompletionRequestUserMessageArgs,
+  CreateChatCompletionRequestArgs, CreateChatCompletionResponse,
 };
 use derive_new::new;
 use dialoguer::{theme::ColorfulTheme, BasicHistory, Input};
 use indicatif::{ProgressBar, ProgressStyle};
 use llama_server_bindings::{disable_llama_log, GptParamsBuilder};
+use serde::Serialize;
+use serde_json::Value;
 use std::{
   io::{self, Write},
   sync::Arc,
-  time::Duration,
-};
-use tokio::{
-  runtime::Builder,
-  sync::{mpsc::channel, Mutex},
-  task::JoinHandle,
+  time::{Duration, Instant},
 };
+use tokio::{runtime::Builder, sync::mpsc::channel, task::JoinHandle};
 
 fn infinite_loading(msg: String) -> ProgressBar {
   let spinner_style = ProgressStyle::with_template("{spinner:.green} {wide_msg}")
@@ -38,13 +38,138 @@ fn infinite_loading(msg: String) -> ProgressBar {
   pb
 }
 
+static MULTILINE_DELIMITER: &str = "\"\"\"";
+
+/// A prompt spanning lines, opened and closed by `"""`, e.g. to paste a code block
+#[derive(Debug, Default, PartialEq)]
+struct MultilineBlock {
+  lines: Vec<String>,
+  closed: bool,
+}
+
+impl MultilineBlock {
+  /// Opens the block if the input starts with `"""`
+  fn open(input: &str) -> Option<Self> {
+    let rest = input.strip_prefix(MULTILINE_DELIMITER)?;
+    let mut block = MultilineBlock::default();
+    if !rest.is_empty() {
+      block.push(rest);
+    }
+    Some(block)
+  }
+
+  fn push(&mut self, line: &str) {
+    match line.strip_suffix(MULTILINE_DELIMITER) {
+      Some(line) => {
+        if !line.is_empty() {
+          self.lines.push(line.to_string());
+        }
+        self.closed = true;
+      }
+      None => self.lines.push(line.to_string()),
+    }
+  }
+
+  fn text(&self) -> String {
+    self.lines.join("\n")
+  }
+}
+
+// reads the lines till the closing `"""` if the input opens a multiline block, returns the input as-is otherwise
+fn read_multiline(input: String) -> dialoguer::Result<String> {
+  let Some(mut block) = MultilineBlock::open(&input) else {
+    return Ok(input);
+  };
+  while !block.closed {
+    let line = Input::<String>::with_theme(&ColorfulTheme::default())
+      .with_prompt("... ")
+      .allow_empty(true)
+      .interact_text()?;
+    block.push(&line);
+  }
+  Ok(block.text())
+}
+
+/// Options of the interactive chat of `bodhi run`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChatOptions {
+  /// saves the chat after every reply
+  pub save: bool,
+  /// id of the saved chat to resume, the new replies are saved to it
+  pub resume: Option<String>,
+  /// prints the tokens and timings after every reply
+  pub stats: bool,
+}
+
+static BENCH_PROMPT_WORDS: &str =
+  "the quick brown fox jumps over the lazy dog while the farmer watches from the old barn";
+
+static BENCH_GENERATION_PROMPT: &str = "Write a long story about a journey across the mountains.";
+
+/// Options of the benchmark run by `bodhi bench`
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchOptions {
+  /// approximate number of prompt tokens to process
+  pub n_prompt: u32,
+  /// number of tokens to generate
+  pub n_gen: u16,
+}
+
+/// Measurements of a single benchmark run of `bodhi bench`
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct BenchResult {
+  pub load_secs: f64,
+  /// resident memory of the process grown by loading the model, only known on linux
+  pub memory_bytes: Option<u64>,
+  pub prompt_tokens: Option<u32>,
+  pub prompt_tokens_per_sec: Option<f64>,
+  pub generated_tokens: i64,
+  pub generated_tokens_per_sec: Option<f64>,
+}
+
+// cycles through the words for the given number of words, most of the words are a single token
+fn bench_prompt(n_prompt: u32) -> String {
+  BENCH_PROMPT_WORDS
+    .split(' ')
+    .cycle()
+    .take(n_prompt as usize)
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+#[cfg(target_os = "linux")]
+fn resident_memory() -> Option<u64> {
+  let status = std::fs::read_to_string("/proc/self/status").ok()?;
+  let kb = status
+    .lines()
+    .find_map(|line| line.strip_prefix("VmRSS:"))?
+    .trim()
+    .trim_end_matches("kB")
+    .trim()
+    .parse::<u64>()
+    .ok()?;
+  Some(kb * 1024)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn resident_memory() -> Option<u64> {
+  None
+}
+
 #[derive(Debug, new)]
 pub struct Interactive {
   alias: Alias,
+  #[new(default)]
+  db_service: Option<Arc<dyn DbServiceFn>>,
+  // saves the chat after every reply
+  #[new(default)]
+  autosave: bool,
+  #[new(default)]
+  stats: bool,
 }
 
 impl Interactive {
-  pub async fn execute(self, service: Arc<dyn AppServiceFn>) -> crate::error::Result<()> {
+  async fn load(&self, service: Arc<dyn AppServiceFn>) -> crate::error::Result<RouterState> {
     let alias = self.alias.clone();
     let model = service
       .hub_service()
@@ -74,110 +199,391 @@ impl Interactive {
       .build()
       .map_err(ObjError::from)?;
     alias.context_params.update(&mut gpt_params);
+    let requested = alias
+      .context_params
+      .backend
+      .unwrap_or_else(|| service.env_service().backend());
+    let backend = select_backend(requested, service.env_service().backend_variant())?;
+    if let Some(variant) = &backend.variant {
+      pb.finish_and_clear();
+      relaunch(backend.backend, variant)?;
+    }
     disable_llama_log();
 
-    let shared_rw = SharedContextRw::new_shared_rw(Some(gpt_params)).await?;
+    let shared_rw =
+      SharedContextRw::new_with_options(Some(gpt_params), false, backend.backend).await?;
+    if alias.context_params.warmup.unwrap_or_default() {
+      if let Err(err) = shared_rw.warmup().await {
+        tracing::warn!(?err, "error warming up the model");
+      }
+    }
     let router_state = RouterState::new(Arc::new(shared_rw), service, Arc::new(DbService::no_op()));
     pb.finish_and_clear();
+    Ok(router_state)
+  }
+
+  /// Chats with the model in the terminal
+  pub async fn execute(
+    mut self,
+    service: Arc<dyn AppServiceFn>,
+    options: ChatOptions,
+  ) -> crate::error::Result<()> {
+    let ChatOptions {
+      save,
+      resume,
+      stats,
+    } = options;
+    self.stats = stats;
+    let mut session = ChatSession::default();
+    if save || resume.is_some() {
+      let db_service = self.db_service(&service).await?;
+      if let Some(id) = resume {
+        session = ChatSession::resume(db_service.get_conversation_with_messages(&id).await?);
+      }
+      self.autosave = true;
+    }
+    let mut router_state = self.load(service.clone()).await?;
+    print!("{}", session.transcript());
     let mut shell_history = BasicHistory::new().max_entries(100).no_duplicates(false);
-    let chat_history = Arc::new(Mutex::new(Vec::<ChatCompletionRequestMessage>::new()));
     loop {
-      if let Ok(user_prompt) = Input::<String>::with_theme(&ColorfulTheme::default())
+      let Ok(user_prompt) = Input::<String>::with_theme(&ColorfulTheme::default())
         .with_prompt(">>> ")
         .history_with(&mut shell_history)
         .interact_text()
-      {
-        if user_prompt.starts_with('/') {
-          match user_prompt.as_str() {
-            "/?" => {
-              println!("/bye: exit the interactive mode");
-              println!("/?: show help");
-              continue;
-            }
-            "/bye" => {
-              break;
-            }
-            _ => {
-              println!("unknown command `{user_prompt}`. type `/?` for list of commands.");
-              continue;
+      else {
+        continue;
+      };
+      if user_prompt.starts_with('/') {
+        match SlashCommand::parse(&user_prompt) {
+          Ok(SlashCommand::Bye) => break,
+          Ok(command) => {
+            if let Err(err) = self
+              .run_command(command, &service, &mut router_state, &mut session)
+              .await
+            {
+              eprintln!("error: {err}");
             }
           }
+          Err(err) => println!("{err}"),
         }
-        self
-          .process_input(&router_state, &user_prompt, chat_history.clone())
-          .await?;
+        continue;
       }
+      let Ok(user_prompt) = read_multiline(user_prompt) else {
+        continue;
+      };
+      session.push_prompt(&user_prompt);
+      self.process_input(&router_state, &mut session).await?;
     }
     let pb = infinite_loading(String::from("Stopping..."));
     router_state.try_stop().await?;
     pb.finish_and_clear();
+    if let (true, Some(id)) = (self.autosave, session.saved_id()) {
+      println!(
+        "saved the chat with id {id}, resume it using `bodhi run {} --resume {id}`",
+        self.alias.alias
+      );
+    }
     Ok(())
   }
 
-  async fn process_input(
-    &self,
-    router_state: &RouterState,
-    input: &str,
-    chat_history: Arc<Mutex<Vec<ChatCompletionRequestMessage>>>,
+  // connects to the chats database on first use
+  async fn db_service(
+    &mut self,
+    service: &Arc<dyn AppServiceFn>,
+  ) -> crate::error::Result<Arc<dyn DbServiceFn>> {
+    if let Some(db_service) = &self.db_service {
+      return Ok(db_service.clone());
+    }
+    let db_service =
+      DbPool::connect_service(&service.env_service().database_url(), Arc::new(TimeService)).await?;
+    db_service.migrate().await?;
+    self.db_service = Some(db_service.clone());
+    Ok(db_service)
+  }
+
+  async fn autosave(&self, session: &mut ChatSession) {
+    let Some(db_service) = &self.db_service else {
+      return;
+    };
+    if let Err(err) = db_service.save_conversation(session.conversation()).await {
+      eprintln!("error saving the chat: {err}");
+    }
+  }
+
+  async fn run_command(
+    &mut self,
+    command: SlashCommand,
+    service: &Arc<dyn AppServiceFn>,
+    router_state: &mut RouterState,
+    session: &mut ChatSession,
   ) -> crate::error::Result<()> {
-    let mut lock = chat_history.lock().await;
-    (*lock).push(ChatCompletionRequestMessage::User(
-      ChatCompletionRequestUserMessage {
-        content: ChatCompletionRequestUserMessageContent::Text(input.to_string()),
-        role: Role::User,
-        name: None,
+    match command {
+      SlashCommand::Help => println!("{}", SlashCommand::HELP),
+      SlashCommand::Bye => {}
+      SlashCommand::Set { param, value } => match session.set_param(&param, &value) {
+        Ok(()) => println!("set {param} to {value}"),
+        Err(err) => println!("{err}"),
       },
-    ));
-    let msgs_clone = (*lock).clone();
-    drop(lock);
-    let model = self.alias.alias.clone();
+      SlashCommand::System(system) => {
+        match system {
+          Some(_) => println!("set the system prompt"),
+          None => println!("removed the system prompt"),
+        }
+        session.system = system;
+      }
+      SlashCommand::Clear => {
+        session.clear();
+        println!("cleared the chat");
+      }
+      SlashCommand::Model(name) => {
+        let Some(alias) = service.data_service().find_alias(&name) else {
+          println!("model alias `{name}` not found, list the aliases using `bodhi list`");
+          return Ok(());
+        };
+        // only one model is kept in memory, the current one is stopped before loading the next
+        router_state.try_stop().await?;
+        let previous = std::mem::replace(&mut self.alias, alias);
+        match self.load(service.clone()).await {
+          Ok(loaded) => {
+            *router_state = loaded;
+            println!("switched to the model alias `{name}`");
+          }
+          Err(err) => {
+            eprintln!("error: {err}");
+            self.alias = previous;
+            *router_state = self.load(service.clone()).await?;
+          }
+        }
+      }
+      SlashCommand::Save => {
+        let db_service = self.db_service(service).await?;
+        let conversation = session.conversation();
+        db_service.save_conversation(conversation).await?;
+        println!(
+          "saved the chat with id {id}, resume it using `bodhi run {} --resume {id}`",
+          self.alias.alias,
+          id = conversation.id
+        );
+      }
+      SlashCommand::Stats => {
+        self.stats = !self.stats;
+        match self.stats {
+          true => println!("showing the stats of the replies"),
+          false => println!("hiding the stats of the replies"),
+        }
+      }
+      SlashCommand::Retry => {
+        if session.retry() {
+          self.process_input(router_state, session).await?;
+        } else {
+          println!("nothing to retry, type a prompt first");
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// Runs a single non-streamed chat completion for the given messages
+  pub async fn complete(
+    self,
+    service: Arc<dyn AppServiceFn>,
+    messages: Vec<ChatCompletionRequestMessage>,
+  ) -> crate::error::Result<CreateChatCompletionResponse> {
     let request = CreateChatCompletionRequestArgs::default()
-      .model(model)
-      .stream(true)
-      .messages(msgs_clone)
+      .model(self.alias.alias.clone())
+      .messages(messages)
       .build()
       .map_err(BodhiError::BuildError)?;
+    self.replay(service, request.into()).await
+  }
+
+  /// Runs the given request as a single non-streamed chat completion using the alias as-is,
+  /// e.g. to reproduce a request from a debug bundle
+  pub async fn replay(
+    self,
+    service: Arc<dyn AppServiceFn>,
+    mut request: ChatCompletionRequest,
+  ) -> crate::error::Result<CreateChatCompletionResponse> {
+    let router_state = self.load(service).await?;
+    request.stream = None;
     let (tx, mut rx) = channel::<String>(100);
-    let handle: JoinHandle<crate::error::Result<()>> =
-      tokio::spawn(async move {
-        let mut deltas = String::new();
-        while let Some(message) = rx.recv().await {
-          let message = if message.starts_with("data: ") {
-            message.strip_prefix("data: ").unwrap()
-          } else {
-            message.as_ref()
-          };
-          let result = serde_json::from_str::<CreateChatCompletionStreamResponse>(message)
-            .map_err(|err| Common::SerdeJsonSerialize {
-              source: err,
-              value: message.to_string(),
-            })?;
-          let delta = result.choices[0]
-            .delta
-            .content
-            .clone()
-            .unwrap_or_default()
-            .to_string();
-          deltas.push_str(&delta);
-          print!("{delta}");
-          _ = io::stdout().flush();
+    let result = router_state
+      .chat_completions_with_alias(request, self.alias.clone(), tx)
+      .await;
+    let message = rx.recv().await;
+    router_state.try_stop().await?;
+    result?;
+    let message = message.ok_or_else(|| {
+      OpenAIApiError::InternalServer("receiver stream abruptly closed".to_string())
+    })?;
+    let response =
+      serde_json::from_str::<CreateChatCompletionResponse>(&message).map_err(|err| {
+        Common::SerdeJsonSerialize {
+          source: err,
+          value: message.clone(),
         }
-        let mut msgs = chat_history.lock().await;
-        (*msgs).push(ChatCompletionRequestMessage::Assistant(
-          ChatCompletionRequestAssistantMessageArgs::default()
-            .content(deltas)
-            .build()
-            .unwrap(),
-        ));
-        Ok(())
-      });
+      })?;
+    Ok(response)
+  }
+
+  /// Loads the model using the context params of the alias, and measures the prompt processing and generation speed
+  pub async fn bench(
+    self,
+    service: Arc<dyn AppServiceFn>,
+    options: BenchOptions,
+  ) -> crate::error::Result<BenchResult> {
+    let memory_before = resident_memory();
+    let start = Instant::now();
+    let router_state = self.load(service).await?;
+    let load_secs = start.elapsed().as_secs_f64();
+    let memory_bytes = resident_memory()
+      .zip(memory_before)
+      .map(|(after, before)| after.saturating_sub(before));
+    let prompt = self.bench_prompt(&router_state, options.n_prompt).await;
+    let generation = match prompt.is_ok() {
+      true => self.bench_generation(&router_state, options.n_gen).await,
+      false => Ok(ReplyStats::default()),
+    };
+    router_state.try_stop().await?;
+    let (prompt_tokens, prompt_tokens_per_sec) = prompt?;
+    let generation = generation?;
+    Ok(BenchResult {
+      load_secs,
+      memory_bytes,
+      prompt_tokens,
+      prompt_tokens_per_sec,
+      generated_tokens: generation.generated_tokens(),
+      generated_tokens_per_sec: generation.tokens_per_sec(),
+    })
+  }
+
+  // generates a single token, so the time taken is mostly spent processing the prompt
+  async fn bench_prompt(
+    &self,
+    router_state: &RouterState,
+    n_prompt: u32,
+  ) -> crate::error::Result<(Option<u32>, Option<f64>)> {
+    let request = self.bench_request(&bench_prompt(n_prompt), 1, false)?;
+    let (tx, mut rx) = channel::<String>(100);
+    let start = Instant::now();
+    router_state
+      .chat_completions_with_alias(request, self.alias.clone(), tx)
+      .await?;
+    let elapsed = start.elapsed();
+    let message = rx.recv().await.ok_or_else(|| {
+      OpenAIApiError::InternalServer("receiver stream abruptly closed".to_string())
+    })?;
+    let response =
+      serde_json::from_str::<CreateChatCompletionResponse>(&message).map_err(|err| {
+        Common::SerdeJsonSerialize {
+          source: err,
+          value: message.clone(),
+        }
+      })?;
+    let prompt_tokens = response.usage.map(|usage| usage.prompt_tokens);
+    let tokens_per_sec = prompt_tokens
+      .filter(|_| !elapsed.is_zero())
+      .map(|tokens| tokens as f64 / elapsed.as_secs_f64());
+    Ok((prompt_tokens, tokens_per_sec))
+  }
+
+  // streams the reply, the time to the first token is excluded from the generation speed
+  async fn bench_generation(
+    &self,
+    router_state: &RouterState,
+    n_gen: u16,
+  ) -> crate::error::Result<ReplyStats> {
+    let mut request = self.bench_request(BENCH_GENERATION_PROMPT, n_gen, true)?;
+    // keeps generating past the end of the story, so all the tokens are generated
+    request
+      .extensions
+      .insert("ignore_eos".to_string(), Value::Bool(true));
+    let (tx, mut rx) = channel::<String>(100);
+    let start = Instant::now();
+    let handle: JoinHandle<ReplyStats> = tokio::spawn(async move {
+      let mut stats = ReplyStats::default();
+      while let Some(message) = rx.recv().await {
+        let message = message.strip_prefix("data: ").unwrap_or(&message);
+        if let Ok(chunk) = serde_json::from_str::<Value>(message) {
+          stats.record(&chunk, start.elapsed());
+        }
+      }
+      stats
+    });
+    let result = router_state
+      .chat_completions_with_alias(request, self.alias.clone(), tx)
+      .await;
+    let stats = handle.await.map_err(|err| Common::Stdlib(Arc::new(err)))?;
+    result?;
+    Ok(stats)
+  }
+
+  #[allow(clippy::result_large_err)]
+  fn bench_request(
+    &self,
+    content: &str,
+    max_tokens: u16,
+    stream: bool,
+  ) -> crate::error::Result<ChatCompletionRequest> {
+    let message = ChatCompletionRequestUserMessageArgs::default()
+      .content(content)
+      .build()
+      .map_err(BodhiError::BuildError)?;
+    let request = CreateChatCompletionRequestArgs::default()
+      .model(self.alias.alias.clone())
+      .messages(vec![ChatCompletionRequestMessage::User(message)])
+      .max_tokens(max_tokens)
+      .stream(stream)
+      .build()
+      .map_err(BodhiError::BuildError)?;
+    Ok(request.into())
+  }
+
+  // streams the reply for the chat, and adds it to the chat once complete
+  async fn process_input(
+    &self,
+    router_state: &RouterState,
+    session: &mut ChatSession,
+  ) -> crate::error::Result<()> {
+    let model = self.alias.alias.clone();
+    let request = session.request(&model)?;
+    let (tx, mut rx) = channel::<String>(100);
+    let start = Instant::now();
+    let handle: JoinHandle<crate::error::Result<(String, ReplyStats)>> = tokio::spawn(async move {
+      let mut deltas = String::new();
+      let mut stats = ReplyStats::default();
+      while let Some(message) = rx.recv().await {
+        let message = message.strip_prefix("data: ").unwrap_or(&message);
+        let chunk =
+          serde_json::from_str::<Value>(message).map_err(|err| Common::SerdeJsonSerialize {
+            source: err,
+            value: message.to_string(),
+          })?;
+        stats.record(&chunk, start.elapsed());
+        let delta = chunk["choices"][0]["delta"]["content"]
+          .as_str()
+          .unwrap_or_default();
+        deltas.push_str(delta);
+        print!("{delta}");
+        _ = io::stdout().flush();
+      }
+      Ok((deltas, stats))
+    });
     let result = router_state.chat_completions(request, tx).await;
-    (handle.await.map_err(|err| Common::Stdlib(Arc::new(err)))?)?;
+    let (deltas, stats) = (handle.await.map_err(|err| Common::Stdlib(Arc::new(err)))?)?;
     match result {
-      Ok(()) => {}
-      Err(err) => eprintln!("error: {err}"),
+      Ok(()) => {
+        session.push_reply(&model, deltas);
+        if self.autosave {
+          self.autosave(session).await;
+        }
+      }
+      Err(err) => eprintln!("error: {err}, type `/retry` to try again"),
     }
     println!();
+    if self.stats {
+      println!("{stats}");
+    }
     Ok(())
   }
 }
@@ -195,19 +601,63 @@ impl InteractiveRuntime {
     InteractiveRuntime {}
   }
 
-  pub fn execute(&self, alias: Alias, service: Arc<dyn AppServiceFn>) -> crate::error::Result<()> {
+  pub fn execute(
+    &self,
+    alias: Alias,
+    service: Arc<dyn AppServiceFn>,
+    options: ChatOptions,
+  ) -> crate::error::Result<()> {
     let runtime = Builder::new_multi_thread()
       .enable_all()
       .build()
       .map_err(Common::Io)?;
-    runtime.block_on(async move { Interactive::new(alias).execute(service).await })?;
+    runtime.block_on(async move { Interactive::new(alias).execute(service, options).await })?;
     Ok(())
   }
+
+  pub fn complete(
+    &self,
+    alias: Alias,
+    service: Arc<dyn AppServiceFn>,
+    messages: Vec<ChatCompletionRequestMessage>,
+  ) -> crate::error::Result<CreateChatCompletionResponse> {
+    let runtime = Builder::new_multi_thread()
+      .enable_all()
+      .build()
+      .map_err(Common::Io)?;
+    runtime.block_on(async move { Interactive::new(alias).complete(service, messages).await })
+  }
+
+  pub fn replay(
+    &self,
+    alias: Alias,
+    service: Arc<dyn AppServiceFn>,
+    request: ChatCompletionRequest,
+  ) -> crate::error::Result<CreateChatCompletionResponse> {
+    let runtime = Builder::new_multi_thread()
+      .enable_all()
+      .build()
+      .map_err(Common::Io)?;
+    runtime.block_on(async move { Interactive::new(alias).replay(service, request).await })
+  }
+
+  pub fn bench(
+    &self,
+    alias: Alias,
+    service: Arc<dyn AppServiceFn>,
+    options: BenchOptions,
+  ) -> crate::error::Result<BenchResult> {
+    let runtime = Builder::new_multi_thread()
+      .enable_all()
+      .build()
+      .map_err(Common::Io)?;
+    runtime.block_on(async move { Interactive::new(alias).bench(service, options).await })
+  }
 }
 
 #[cfg(test)]
 mod test {
-  use super::Interactive;
+  use super::{bench_prompt, ChatOptions, Interactive, MultilineBlock};
   use crate::{
     objs::Alias,
     service::{MockDataService, MockEnvServiceFn, MockHubService},
@@ -244,7 +694,7 @@ mod test {
 
     let service = AppServiceStubMock::new(mock_env_service, mock, MockDataService::new());
     let result = Interactive::new(alias_clone)
-      .execute(Arc::new(service))
+      .execute(Arc::new(service), ChatOptions::default())
       .await;
     assert!(result.is_err());
     assert_eq!(
@@ -254,4 +704,30 @@ Check Huggingface Home is set correctly using environment variable $HF_HOME or u
     );
     Ok(())
   }
+
+  #[rstest]
+  #[case(&["\"\"\"", "fn main() {", "", "  println!(\"hello\");", "}", "\"\"\""], "fn main() {\n\n  println!(\"hello\");\n}")]
+  #[case(&["\"\"\"explain", "this code\"\"\""], "explain\nthis code")]
+  #[case(&["\"\"\"single line\"\"\""], "single line")]
+  fn test_interactive_multiline_block(#[case] lines: &[&str], #[case] expected: &str) {
+    let mut block = MultilineBlock::open(lines[0]).expect("block should open");
+    for line in &lines[1..] {
+      assert!(!block.closed);
+      block.push(line);
+    }
+    assert!(block.closed);
+    assert_eq!(expected, block.text());
+  }
+
+  #[rstest]
+  #[case(3, "the quick brown")]
+  #[case(19, "the quick brown fox jumps over the lazy dog while the farmer watches from the old barn the quick")]
+  fn test_interactive_bench_prompt(#[case] n_prompt: u32, #[case] expected: &str) {
+    assert_eq!(expected, bench_prompt(n_prompt));
+  }
+
+  #[rstest]
+  fn test_interactive_multiline_block_not_opened() {
+    assert_eq!(None, MultilineBlock::open("what day comes after monday?"));
+  }
 }
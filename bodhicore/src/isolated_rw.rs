@@ -0,0 +1,474 @@
+use crate::{
+  error::Common,
+  objs::{Alias, Backend, ChatCompletionRequest, HubFile, RerankRequest},
+  service::TemplateLimits,
+  shared_rw::{ContextError, Result, SharedContextRwFn},
+  worker::{WorkerOp, WorkerParams, WorkerRequest, WorkerResponse},
+};
+use llama_server_bindings::GptParams;
+use std::{
+  collections::HashMap,
+  ffi::OsString,
+  path::PathBuf,
+  process::Stdio,
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex as StdMutex,
+  },
+};
+use tokio::{
+  io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+  process::{Child, ChildStdin, ChildStdout, Command},
+  sync::{
+    mpsc::{
+      channel, unbounded_channel, Sender, UnboundedReceiver, UnboundedSender, WeakUnboundedSender,
+    },
+    Mutex,
+  },
+};
+
+// unbounded, so a request slow to pass on its chunks does not hold up the responses of the others
+type Pending = Arc<StdMutex<HashMap<u64, UnboundedSender<WorkerResponse>>>>;
+
+/// Runs the llama.cpp context in a `bodhi worker` child process, so a crash of llama.cpp does not
+/// take down the server. The worker is restarted after a crash, failing only the requests it was
+/// serving.
+#[derive(Debug)]
+pub struct IsolatedContextRw {
+  program: PathBuf,
+  args: Vec<OsString>,
+  worker: Mutex<Option<Worker>>,
+  next_id: AtomicU64,
+}
+
+#[derive(Debug)]
+struct Worker {
+  child: Child,
+  // the lines written to the stdin of the worker, dropping it closes the stdin
+  requests: UnboundedSender<String>,
+  // the requests waiting for the responses of the worker, by the request id
+  pending: Pending,
+}
+
+// a request sent to the worker, cancelled in the worker if dropped before it is done, e.g. when
+// the request times out or is cancelled
+struct WorkerCall {
+  id: u64,
+  requests: WeakUnboundedSender<String>,
+  running: bool,
+}
+
+impl WorkerCall {
+  fn cancel(&mut self) {
+    if !std::mem::take(&mut self.running) {
+      return;
+    }
+    let Some(requests) = self.requests.upgrade() else {
+      return;
+    };
+    let op = WorkerOp::Cancel { id: self.id };
+    match request_line(self.id, op) {
+      Ok(line) => _ = requests.send(line),
+      Err(err) => tracing::warn!(?err, "error cancelling the request of the worker"),
+    }
+  }
+
+  fn done(&mut self) {
+    self.running = false;
+  }
+}
+
+impl Drop for WorkerCall {
+  fn drop(&mut self) {
+    self.cancel();
+  }
+}
+
+impl IsolatedContextRw {
+  /// Starts the worker using the running binary, loading the model if given
+  pub async fn start(
+    gpt_params: Option<GptParams>,
+    strict_memory: bool,
+    backend: Backend,
+  ) -> Result<Self> {
+    let program = std::env::current_exe().map_err(Common::from)?;
+    let mut args = vec![
+      OsString::from("worker"),
+      OsString::from("--backend"),
+      OsString::from(backend.to_string()),
+    ];
+    if strict_memory {
+      args.push(OsString::from("--strict"));
+    }
+    Self::start_with(program, args, gpt_params).await
+  }
+
+  pub(crate) async fn start_with(
+    program: PathBuf,
+    args: Vec<OsString>,
+    gpt_params: Option<GptParams>,
+  ) -> Result<Self> {
+    let ctx = IsolatedContextRw {
+      program,
+      args,
+      worker: Mutex::new(None),
+      next_id: AtomicU64::new(1),
+    };
+    ctx.reload(gpt_params).await?;
+    Ok(ctx)
+  }
+
+  fn spawn(&self) -> Result<Worker> {
+    let mut child = Command::new(&self.program)
+      .args(&self.args)
+      .stdin(Stdio::piped())
+      .stdout(Stdio::piped())
+      .stderr(Stdio::inherit())
+      .kill_on_drop(true)
+      .spawn()
+      .map_err(|source| Common::IoFile {
+        source,
+        path: self.program.display().to_string(),
+      })?;
+    let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+      return Err(ContextError::Unreachable(
+        "the stdio of the worker should be piped".to_string(),
+      ));
+    };
+    let pending = Pending::default();
+    tokio::spawn(read_responses(stdout, pending.clone()));
+    let (requests, requests_rx) = unbounded_channel::<String>();
+    tokio::spawn(write_requests(stdin, requests_rx));
+    tracing::info!(pid = child.id(), "started the inference worker");
+    Ok(Worker {
+      child,
+      requests,
+      pending,
+    })
+  }
+
+  // sends the request to the worker, starting it if not running, and passes the chunks of the
+  // response to `userdata` till the request is done
+  async fn call(&self, op: WorkerOp, userdata: Option<Sender<String>>) -> Result<()> {
+    let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+    let (tx, mut rx) = unbounded_channel::<WorkerResponse>();
+    let requests = {
+      let mut lock = self.worker.lock().await;
+      let running = match lock.as_mut() {
+        Some(worker) => matches!(worker.child.try_wait(), Ok(None)),
+        None => false,
+      };
+      if !running {
+        *lock = Some(self.spawn()?);
+      }
+      let Some(worker) = lock.as_mut() else {
+        unreachable!("just started the worker");
+      };
+      worker
+        .pending
+        .lock()
+        .map_err(|err| ContextError::Unreachable(err.to_string()))?
+        .insert(id, tx);
+      let line = request_line(id, op)?;
+      let sent = worker.requests.send(line).is_ok();
+      sent.then(|| worker.requests.downgrade())
+    };
+    if let Some(requests) = requests {
+      let mut call = WorkerCall {
+        id,
+        requests,
+        running: true,
+      };
+      while let Some(response) = rx.recv().await {
+        match response {
+          WorkerResponse::Chunk { data, .. } => {
+            if let Some(userdata) = &userdata {
+              // the client disconnected, the worker stops the generation and reports it done
+              if userdata.send(data).await.is_err() {
+                call.cancel();
+              }
+            }
+          }
+          WorkerResponse::Done { result, .. } => {
+            call.done();
+            return result.map_err(ContextError::from);
+          }
+        }
+      }
+      call.done();
+    }
+    self.restart().await;
+    Err(ContextError::WorkerCrashed)
+  }
+
+  // replaces the exited worker, the model is loaded again by the next request for it
+  async fn restart(&self) {
+    let mut lock = self.worker.lock().await;
+    if let Some(worker) = lock.as_mut() {
+      match worker.child.try_wait() {
+        Ok(None) => return,
+        Ok(Some(status)) => tracing::error!(%status, "the inference worker exited, restarting"),
+        Err(err) => tracing::error!(?err, "the inference worker failed, restarting"),
+      }
+    }
+    match self.spawn() {
+      Ok(worker) => *lock = Some(worker),
+      Err(err) => {
+        tracing::error!(?err, "error restarting the inference worker");
+        *lock = None;
+      }
+    }
+  }
+}
+
+fn request_line(id: u64, op: WorkerOp) -> Result<String> {
+  let mut line =
+    serde_json::to_string(&WorkerRequest { id, op }).map_err(Common::SerdeJsonDeserialize)?;
+  line.push('\n');
+  Ok(line)
+}
+
+// writes the requests to the worker till the sender is dropped, closing the stdin of the worker
+async fn write_requests(mut stdin: ChildStdin, mut requests: UnboundedReceiver<String>) {
+  while let Some(line) = requests.recv().await {
+    if let Err(err) = stdin.write_all(line.as_bytes()).await {
+      tracing::warn!(?err, "error writing to the inference worker");
+      return;
+    }
+  }
+}
+
+// dispatches the responses of the worker to the waiting requests, the requests are dropped when
+// the worker exits, failing them
+async fn read_responses(stdout: ChildStdout, pending: Pending) {
+  let mut lines = BufReader::new(stdout).lines();
+  while let Ok(Some(line)) = lines.next_line().await {
+    // llama.cpp may print to stdout, only the lines of the protocol are read
+    let Ok(response) = serde_json::from_str::<WorkerResponse>(&line) else {
+      tracing::debug!(%line, "skipping the output of the worker");
+      continue;
+    };
+    let (id, done) = match &response {
+      WorkerResponse::Chunk { id, .. } => (*id, false),
+      WorkerResponse::Done { id, .. } => (*id, true),
+    };
+    let Ok(mut waiting) = pending.lock() else {
+      return;
+    };
+    let sender = if done {
+      waiting.remove(&id)
+    } else {
+      waiting.get(&id).cloned()
+    };
+    // the request is gone, e.g. timed out, the worker is cancelling it
+    if sender.is_some_and(|sender| sender.send(response).is_err()) {
+      waiting.remove(&id);
+    }
+  }
+  if let Ok(mut pending) = pending.lock() {
+    pending.clear();
+  }
+}
+
+#[async_trait::async_trait]
+impl SharedContextRwFn for IsolatedContextRw {
+  async fn reload(&self, gpt_params: Option<GptParams>) -> Result<()> {
+    let params = gpt_params.as_ref().map(WorkerParams::from);
+    self.call(WorkerOp::Reload { params }, None).await
+  }
+
+  async fn try_stop(&self) -> Result<()> {
+    let mut lock = self.worker.lock().await;
+    if let Some(mut worker) = lock.take() {
+      // closing the stdin stops the worker, after it stops the llama.cpp context
+      drop(worker.requests);
+      if let Err(err) = worker.child.wait().await {
+        tracing::warn!(?err, "error waiting for the inference worker to stop");
+      }
+    }
+    Ok(())
+  }
+
+  async fn force_stop(&self) -> Result<()> {
+    let mut lock = self.worker.lock().await;
+    if let Some(mut worker) = lock.take() {
+      // the requests being served fail once the stdout of the killed worker is closed
+      if let Err(err) = worker.child.kill().await {
+        tracing::warn!(?err, "error killing the inference worker");
+      }
+    }
+    Ok(())
+  }
+
+  async fn has_model(&self) -> bool {
+    matches!(self.get_gpt_params().await, Ok(Some(_)))
+  }
+
+  async fn get_gpt_params(&self) -> Result<Option<GptParams>> {
+    let (tx, mut rx) = channel::<String>(1);
+    self.call(WorkerOp::LoadedParams, Some(tx)).await?;
+    let Some(data) = rx.recv().await else {
+      return Ok(None);
+    };
+    let params =
+      serde_json::from_str::<Option<WorkerParams>>(&data).map_err(Common::SerdeJsonDeserialize)?;
+    Ok(params.map(GptParams::from))
+  }
+
+  async fn warmup(&self) -> Result<()> {
+    self.call(WorkerOp::Warmup, None).await
+  }
+
+  async fn chat_completions(
+    &self,
+    request: ChatCompletionRequest,
+    alias: Alias,
+    model_file: HubFile,
+    tokenizer_file: HubFile,
+    template_limits: TemplateLimits,
+    userdata: Sender<String>,
+  ) -> Result<()> {
+    let op = WorkerOp::ChatCompletions {
+      request,
+      alias,
+      model_file: model_file.path(),
+      tokenizer_file: tokenizer_file.path(),
+      template_limits,
+    };
+    self.call(op, Some(userdata)).await
+  }
+
+  async fn rerank(
+    &self,
+    request: RerankRequest,
+    alias: Alias,
+    model_file: HubFile,
+    userdata: Sender<String>,
+  ) -> Result<()> {
+    let op = WorkerOp::Rerank {
+      request,
+      alias,
+      model_file: model_file.path(),
+    };
+    self.call(op, Some(userdata)).await
+  }
+
+  async fn invalidate_chat_templates(&self, tokenizer_files: Vec<PathBuf>) -> Result<()> {
+    // the templates are compiled by the worker, none are compiled before it is started
+    if self.worker.lock().await.is_none() {
+      return Ok(());
+    }
+    self
+      .call(WorkerOp::InvalidateChatTemplates { tokenizer_files }, None)
+      .await
+  }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+  use super::IsolatedContextRw;
+  use crate::{
+    shared_rw::{ContextError, SharedContextRwFn},
+    worker::WorkerOp,
+  };
+  use rstest::rstest;
+  use std::{ffi::OsString, path::PathBuf, time::Duration};
+  use tokio::sync::mpsc::channel;
+
+  // a fake worker using sh, replying to the requests read from stdin
+  fn sh_worker(script: &str) -> (PathBuf, Vec<OsString>) {
+    (
+      PathBuf::from("/bin/sh"),
+      vec![OsString::from("-c"), OsString::from(script)],
+    )
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_isolated_rw_passes_the_requests_to_the_worker() -> anyhow::Result<()> {
+    let (program, args) = sh_worker(
+      r#"while read line; do
+  id=$(echo "$line" | sed 's/^{"id":\([0-9]*\).*/\1/')
+  echo "llama.cpp log line"
+  echo "{\"type\":\"chunk\",\"id\":$id,\"data\":\"{\\\"model\\\":\\\"/models/llama3.gguf\\\"}\"}"
+  echo "{\"type\":\"done\",\"id\":$id,\"result\":{\"Ok\":null}}"
+done"#,
+    );
+    let ctx = IsolatedContextRw::start_with(program, args, None).await?;
+    let gpt_params = ctx.get_gpt_params().await?.expect("params to be returned");
+    assert_eq!("/models/llama3.gguf", gpt_params.model);
+    assert!(ctx.has_model().await);
+    ctx.try_stop().await?;
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_isolated_rw_fails_the_request_and_restarts_on_crash() -> anyhow::Result<()> {
+    // replies to the first request after the start, and crashes on the next
+    let (program, args) = sh_worker(
+      r#"read line
+id=$(echo "$line" | sed 's/^{"id":\([0-9]*\).*/\1/')
+echo "{\"type\":\"done\",\"id\":$id,\"result\":{\"Ok\":null}}"
+read line
+exit 139"#,
+    );
+    let ctx = IsolatedContextRw::start_with(program, args, None).await?;
+    let result = ctx.warmup().await;
+    assert!(matches!(result, Err(ContextError::WorkerCrashed)));
+    // served by the restarted worker
+    ctx.warmup().await?;
+    ctx.try_stop().await?;
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_isolated_rw_cancels_the_request_of_a_disconnected_client() -> anyhow::Result<()> {
+    // replies to the load on start, sends a chunk of the next request, and reports it done once
+    // it is cancelled
+    let (program, args) = sh_worker(
+      r#"read line
+id=$(echo "$line" | sed 's/^{"id":\([0-9]*\).*/\1/')
+echo "{\"type\":\"done\",\"id\":$id,\"result\":{\"Ok\":null}}"
+read line
+id=$(echo "$line" | sed 's/^{"id":\([0-9]*\).*/\1/')
+echo "{\"type\":\"chunk\",\"id\":$id,\"data\":\"Monday\"}"
+read line
+case "$line" in
+  *'"type":"cancel"'*) echo "{\"type\":\"done\",\"id\":$id,\"result\":{\"Err\":{\"type\":\"failed\",\"message\":\"cancelled\"}}}" ;;
+esac
+while read line; do :; done"#,
+    );
+    let ctx = IsolatedContextRw::start_with(program, args, None).await?;
+    let (tx, rx) = channel::<String>(1);
+    drop(rx);
+    let result =
+      tokio::time::timeout(Duration::from_secs(5), ctx.call(WorkerOp::Warmup, Some(tx))).await?;
+    assert_eq!("cancelled", result.unwrap_err().to_string());
+    ctx.force_stop().await?;
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_isolated_rw_force_stop_fails_the_hung_request() -> anyhow::Result<()> {
+    // replies to the load on start, and never replies to the next request
+    let (program, args) = sh_worker(
+      r#"read line
+id=$(echo "$line" | sed 's/^{"id":\([0-9]*\).*/\1/')
+echo "{\"type\":\"done\",\"id\":$id,\"result\":{\"Ok\":null}}"
+while read line; do :; done"#,
+    );
+    let ctx = std::sync::Arc::new(IsolatedContextRw::start_with(program, args, None).await?);
+    let hung = tokio::spawn({
+      let ctx = ctx.clone();
+      async move { ctx.warmup().await }
+    });
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    ctx.force_stop().await?;
+    let result = hung.await?;
+    assert!(matches!(result, Err(ContextError::WorkerCrashed)));
+    Ok(())
+  }
+}
@@ -1,20 +1,28 @@
+mod backend;
 pub mod bindings;
+mod chat_session;
 pub mod cli;
 pub mod db;
 mod error;
+pub mod gguf;
 pub mod interactive;
+mod isolated_rw;
 mod oai;
 pub mod objs;
 pub mod server;
 pub mod service;
 mod shared_rw;
+mod system_info;
+mod template_compat;
 #[cfg(test)]
 mod test_utils;
 mod tokenizer_config;
 mod utils;
+mod worker;
 
 // TODO: remove exposing of cli methods, rename cli to command package
 pub use cli::*;
 pub use error::BodhiError;
+pub use isolated_rw::IsolatedContextRw;
 pub use objs::Repo;
 pub use shared_rw::{ContextError, SharedContextRw, SharedContextRwFn};
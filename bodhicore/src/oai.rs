@@ -1,18 +1,47 @@
-use crate::shared_rw::ContextError;
+use crate::{objs::AliasFeature, shared_rw::ContextError};
 use axum::{http::StatusCode, response::IntoResponse, Json};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+/// The error of all the API routes, sent as the OpenAI error envelope
+/// `{"error": {"message", "type", "param", "code"}}`
 #[derive(Debug, Error)]
 pub enum OpenAIApiError {
   #[error("{0}")]
   ModelNotFound(String),
+  /// the model alias does not have the feature served by the endpoint
+  #[error("model '{0}' does not support {1}")]
+  ModelUnsupported(String, AliasFeature),
+  #[error("{0}")]
+  NotFound(String),
+  /// no in-flight request with the generation id
+  #[error("{0}")]
+  RequestNotFound(String),
+  /// a request with the generation id is already in flight
+  #[error("{0}")]
+  RequestInFlight(String),
+  #[error("{0}")]
+  BadRequest(String),
+  #[error("{0}")]
+  Unauthorized(String),
+  #[error("{0}")]
+  Forbidden(String),
   #[error("{0}")]
   InternalServer(String),
+  #[error("generation did not complete within {0}s")]
+  Timeout(u64),
+  #[error("generation was cancelled")]
+  Cancelled,
   #[error(transparent)]
   ContextError(#[from] ContextError),
 }
 
+impl OpenAIApiError {
+  pub(crate) fn is_transient(&self) -> bool {
+    matches!(self, OpenAIApiError::ContextError(err) if err.is_transient())
+  }
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct ApiError {
   pub message: String,
@@ -29,19 +58,119 @@ impl ApiError {
       code: "internal_server_error".to_string(),
     }
   }
+
+  /// The error for a response without an error body, e.g. the rejections of the axum extractors
+  pub(crate) fn for_status(status: StatusCode, message: String) -> ApiError {
+    let (r#type, code) = match status {
+      StatusCode::UNAUTHORIZED => ("authentication_error", "invalid_api_key"),
+      StatusCode::FORBIDDEN => ("permission_error", "forbidden"),
+      StatusCode::NOT_FOUND => ("invalid_request_error", "not_found"),
+      status if status.is_client_error() => ("invalid_request_error", "invalid_request"),
+      _ => return ApiError::internal_server(message),
+    };
+    ApiError {
+      message,
+      r#type: r#type.to_string(),
+      param: None,
+      code: code.to_string(),
+    }
+  }
+}
+
+/// The body of the error responses, and of the error events of the streamed responses
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct ApiErrorResponse {
+  pub error: ApiError,
+}
+
+impl ApiErrorResponse {
+  /// The error of an `error: ` message of the generation, the messages not sent as the error
+  /// envelope, e.g. by llama.cpp, are sent as an internal server error
+  pub(crate) fn from_event(payload: &str) -> ApiErrorResponse {
+    let payload = payload.trim();
+    serde_json::from_str::<ApiErrorResponse>(payload).unwrap_or_else(|_| ApiErrorResponse {
+      error: ApiError::internal_server(payload.to_string()),
+    })
+  }
 }
 
 impl From<&OpenAIApiError> for ApiError {
   fn from(value: &OpenAIApiError) -> Self {
     match value {
       OpenAIApiError::ModelNotFound(model) => ApiError {
-        message: format!("The model '{}' does not exist", model),
-        r#type: "model_not_found".to_string(),
+        message: format!(
+          "The model '{model}' does not exist, run `bodhi list` to list the model aliases"
+        ),
+        r#type: "invalid_request_error".to_string(),
         param: Some("model".to_string()),
         code: "model_not_found".to_string(),
       },
+      OpenAIApiError::ModelUnsupported(model, feature) => ApiError {
+        message: format!(
+          "The model '{model}' does not support {feature}, add `{feature}` to the features of the model alias to use it for {feature}"
+        ),
+        r#type: "invalid_request_error".to_string(),
+        param: Some("model".to_string()),
+        code: "model_not_supported".to_string(),
+      },
+      OpenAIApiError::RequestNotFound(id) => ApiError {
+        message: format!("No request in flight with the id '{id}'"),
+        r#type: "invalid_request_error".to_string(),
+        param: Some("id".to_string()),
+        code: "request_not_found".to_string(),
+      },
+      OpenAIApiError::RequestInFlight(id) => ApiError {
+        message: format!(
+          "A request with the id '{id}' is already in flight, set another `x-request-id`"
+        ),
+        r#type: "invalid_request_error".to_string(),
+        param: Some("x-request-id".to_string()),
+        code: "request_in_flight".to_string(),
+      },
+      OpenAIApiError::NotFound(message) => {
+        ApiError::for_status(StatusCode::NOT_FOUND, message.to_string())
+      }
+      OpenAIApiError::BadRequest(message) => {
+        ApiError::for_status(StatusCode::BAD_REQUEST, message.to_string())
+      }
+      OpenAIApiError::Unauthorized(message) => {
+        ApiError::for_status(StatusCode::UNAUTHORIZED, message.to_string())
+      }
+      OpenAIApiError::Forbidden(message) => {
+        ApiError::for_status(StatusCode::FORBIDDEN, message.to_string())
+      }
+      OpenAIApiError::ContextError(ContextError::ContextLengthExceeded {
+        prompt_tokens,
+        n_ctx,
+      }) => ApiError {
+        message: format!(
+          "This model's maximum context length is {n_ctx} tokens, however the prompt has {prompt_tokens} tokens. Reduce the length of the messages."
+        ),
+        r#type: "invalid_request_error".to_string(),
+        param: Some("messages".to_string()),
+        code: "context_length_exceeded".to_string(),
+      },
+      OpenAIApiError::ContextError(ContextError::WorkerCrashed) => ApiError {
+        message: "The inference worker crashed while serving the request, retry the request"
+          .to_string(),
+        r#type: "service_unavailable".to_string(),
+        param: None,
+        code: "worker_crashed".to_string(),
+      },
       OpenAIApiError::ContextError(err) => ApiError::internal_server(err.to_string()),
       OpenAIApiError::InternalServer(err) => ApiError::internal_server(err.to_string()),
+      OpenAIApiError::Timeout(secs) => ApiError {
+        message: format!("The generation did not complete within {secs}s, and was aborted"),
+        r#type: "timeout_error".to_string(),
+        param: None,
+        code: "timeout".to_string(),
+      },
+      OpenAIApiError::Cancelled => ApiError {
+        message: "The generation was cancelled using its request id".to_string(),
+        r#type: "cancelled".to_string(),
+        param: None,
+        code: "cancelled".to_string(),
+      },
     }
   }
 }
@@ -49,7 +178,23 @@ impl From<&OpenAIApiError> for ApiError {
 impl From<&OpenAIApiError> for StatusCode {
   fn from(value: &OpenAIApiError) -> Self {
     match value {
-      OpenAIApiError::ModelNotFound(_) => StatusCode::NOT_FOUND,
+      OpenAIApiError::ModelNotFound(_)
+      | OpenAIApiError::NotFound(_)
+      | OpenAIApiError::RequestNotFound(_) => StatusCode::NOT_FOUND,
+      OpenAIApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+      OpenAIApiError::RequestInFlight(_) => StatusCode::CONFLICT,
+      OpenAIApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+      OpenAIApiError::BadRequest(_)
+      | OpenAIApiError::ModelUnsupported(..)
+      | OpenAIApiError::ContextError(ContextError::ContextLengthExceeded { .. }) => {
+        StatusCode::BAD_REQUEST
+      }
+      OpenAIApiError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+      OpenAIApiError::ContextError(ContextError::WorkerCrashed) => StatusCode::SERVICE_UNAVAILABLE,
+      // as used by nginx, for the requests closed by the client
+      OpenAIApiError::Cancelled => {
+        StatusCode::from_u16(499).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+      }
       OpenAIApiError::ContextError(_) | OpenAIApiError::InternalServer(_) => {
         StatusCode::INTERNAL_SERVER_ERROR
       }
@@ -59,8 +204,95 @@ impl From<&OpenAIApiError> for StatusCode {
 
 impl IntoResponse for OpenAIApiError {
   fn into_response(self) -> axum::response::Response {
-    (StatusCode::from(&self), Json(ApiError::from(&self))).into_response()
+    let error = ApiErrorResponse {
+      error: ApiError::from(&self),
+    };
+    (StatusCode::from(&self), Json(error)).into_response()
+  }
+}
+
+impl From<axum::http::Error> for OpenAIApiError {
+  fn from(value: axum::http::Error) -> Self {
+    OpenAIApiError::InternalServer(value.to_string())
   }
 }
 
 pub type Result<T> = std::result::Result<T, OpenAIApiError>;
+
+#[cfg(test)]
+mod test {
+  use crate::{
+    oai::{ApiError, OpenAIApiError},
+    objs::AliasFeature,
+    shared_rw::ContextError,
+    test_utils::ResponseTestExt,
+  };
+  use axum::{http::StatusCode, response::IntoResponse};
+  use rstest::rstest;
+  use serde_json::{json, Value};
+
+  #[rstest]
+  fn test_oai_context_length_exceeded_is_bad_request() {
+    let err = OpenAIApiError::ContextError(ContextError::ContextLengthExceeded {
+      prompt_tokens: 600,
+      n_ctx: 512,
+    });
+    assert_eq!(StatusCode::BAD_REQUEST, StatusCode::from(&err));
+    assert!(!err.is_transient());
+    assert_eq!(
+      ApiError {
+        message: "This model's maximum context length is 512 tokens, however the prompt has 600 tokens. Reduce the length of the messages.".to_string(),
+        r#type: "invalid_request_error".to_string(),
+        param: Some("messages".to_string()),
+        code: "context_length_exceeded".to_string(),
+      },
+      ApiError::from(&err)
+    );
+  }
+
+  #[rstest]
+  fn test_oai_worker_crashed_is_service_unavailable() {
+    let err = OpenAIApiError::ContextError(ContextError::WorkerCrashed);
+    assert_eq!(StatusCode::SERVICE_UNAVAILABLE, StatusCode::from(&err));
+    assert!(err.is_transient());
+    assert_eq!("worker_crashed", ApiError::from(&err).code);
+  }
+
+  #[rstest]
+  fn test_oai_model_unsupported_is_bad_request() {
+    let err = OpenAIApiError::ModelUnsupported("bge-reranker".to_string(), AliasFeature::Chat);
+    assert_eq!(StatusCode::BAD_REQUEST, StatusCode::from(&err));
+    assert_eq!(
+      ApiError {
+        message: "The model 'bge-reranker' does not support chat, add `chat` to the features of the model alias to use it for chat".to_string(),
+        r#type: "invalid_request_error".to_string(),
+        param: Some("model".to_string()),
+        code: "model_not_supported".to_string(),
+      },
+      ApiError::from(&err)
+    );
+  }
+
+  #[rstest]
+  #[case(OpenAIApiError::NotFound("given record not found in conversations".to_string()), StatusCode::NOT_FOUND, "invalid_request_error", "not_found")]
+  #[case(OpenAIApiError::BadRequest("q should not be empty".to_string()), StatusCode::BAD_REQUEST, "invalid_request_error", "invalid_request")]
+  #[case(OpenAIApiError::Unauthorized("API key is invalid or revoked".to_string()), StatusCode::UNAUTHORIZED, "authentication_error", "invalid_api_key")]
+  #[case(OpenAIApiError::Forbidden("admin role required".to_string()), StatusCode::FORBIDDEN, "permission_error", "forbidden")]
+  #[case(OpenAIApiError::InternalServer("database is locked".to_string()), StatusCode::INTERNAL_SERVER_ERROR, "internal_server_error", "internal_server_error")]
+  #[tokio::test]
+  async fn test_oai_error_response_is_envelope(
+    #[case] err: OpenAIApiError,
+    #[case] status: StatusCode,
+    #[case] r#type: &str,
+    #[case] code: &str,
+  ) -> anyhow::Result<()> {
+    let message = err.to_string();
+    let response = err.into_response();
+    assert_eq!(status, response.status());
+    assert_eq!(
+      json! {{"error": {"message": message, "type": r#type, "param": null, "code": code}}},
+      response.json::<Value>().await?
+    );
+    Ok(())
+  }
+}
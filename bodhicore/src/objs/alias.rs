@@ -1,10 +1,27 @@
 #[allow(unused_imports)]
 use super::{is_default, BuilderError};
-use super::{ChatTemplate, GptContextParams, OAIRequestParams, Repo};
+use super::{
+  ChatTemplate, ChatTemplateId, GptContextParams, OAIRequestParams, ReasoningFormat, Repo,
+};
 use crate::utils::to_safe_filename;
+use clap::ValueEnum;
 use derive_new::new;
 use prettytable::{Cell, Row};
 use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
+
+/// What a model alias can be used for, listed in its `features`. The endpoints only accept the
+/// aliases having the feature they serve.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Display, EnumString)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum AliasFeature {
+  /// `/v1/chat/completions`, `/api/chat` and `bodhi run`
+  Chat,
+  /// the embedding models, not accepted by the chat and rerank endpoints
+  Embedding,
+  /// `/v1/rerank`
+  Rerank,
+}
 
 #[allow(clippy::too_many_arguments)]
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, new)]
@@ -16,6 +33,11 @@ use serde::{Deserialize, Serialize};
     build_fn(error = BuilderError)))]
 pub struct Alias {
   pub alias: String,
+  /// the model alias the fields not set in the file are inherited from, resolved by the data
+  /// service when loading the aliases
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  #[new(default)]
+  pub extends: Option<String>,
   #[serde(skip_serializing_if = "Option::is_none")]
   pub family: Option<String>,
   pub repo: Repo,
@@ -27,6 +49,10 @@ pub struct Alias {
   pub request_params: OAIRequestParams,
   #[serde(default, skip_serializing_if = "is_default")]
   pub context_params: GptContextParams,
+  /// the tags of the reasoning in the generated text, when not the default of the chat template
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  #[new(default)]
+  pub reasoning: Option<ReasoningFormat>,
 }
 
 impl Alias {
@@ -35,6 +61,24 @@ impl Alias {
     let filename = to_safe_filename(&filename);
     format!("{}.yaml", filename)
   }
+
+  pub fn supports(&self, feature: AliasFeature) -> bool {
+    self
+      .features
+      .iter()
+      .any(|value| value.eq_ignore_ascii_case(&feature.to_string()))
+  }
+
+  /// The tags of the reasoning split out of the generated content, `None` for the models that do
+  /// not reason
+  pub fn reasoning_format(&self) -> Option<ReasoningFormat> {
+    match (&self.reasoning, &self.chat_template) {
+      (Some(reasoning), _) => Some(reasoning.clone()),
+      // the template of DeepSeek-R1 opens the reasoning in the generation prompt
+      (None, ChatTemplate::Id(ChatTemplateId::DeepseekR1)) => Some(ReasoningFormat::think(true)),
+      (None, _) => None,
+    }
+  }
 }
 
 impl From<Alias> for Row {
@@ -50,17 +94,17 @@ impl From<Alias> for Row {
   }
 }
 
-// TODO: hard coding for time being
 pub fn default_features() -> Vec<String> {
-  vec!["chat".to_string()]
+  vec![AliasFeature::Chat.to_string()]
 }
 
 #[cfg(test)]
 mod test {
-  use super::Alias;
+  use super::{Alias, AliasFeature};
   use crate::{
     objs::{
       AliasBuilder, ChatTemplate, ChatTemplateId, GptContextParamsBuilder, OAIRequestParamsBuilder,
+      ReasoningFormat,
     },
     Repo,
   };
@@ -146,6 +190,67 @@ context_params:
     assert_eq!(expected, alias.config_filename());
   }
 
+  #[rstest]
+  #[case(vec!["chat"], AliasFeature::Chat, true)]
+  #[case(vec!["Chat", "tools"], AliasFeature::Chat, true)]
+  #[case(vec!["chat"], AliasFeature::Rerank, false)]
+  #[case(vec![], AliasFeature::Chat, false)]
+  fn test_alias_supports(
+    #[case] features: Vec<&str>,
+    #[case] feature: AliasFeature,
+    #[case] expected: bool,
+  ) {
+    let alias = Alias {
+      features: features.into_iter().map(str::to_string).collect(),
+      ..Default::default()
+    };
+    assert_eq!(expected, alias.supports(feature));
+  }
+
+  #[rstest]
+  #[case(ChatTemplate::Id(ChatTemplateId::Llama3), None, None)]
+  #[case(
+    ChatTemplate::Id(ChatTemplateId::DeepseekR1),
+    None,
+    Some(ReasoningFormat::think(true))
+  )]
+  #[case(
+    ChatTemplate::Id(ChatTemplateId::DeepseekR1),
+    Some(ReasoningFormat { start: "<reasoning>".to_string(), end: "</reasoning>".to_string(), in_prompt: false }),
+    Some(ReasoningFormat { start: "<reasoning>".to_string(), end: "</reasoning>".to_string(), in_prompt: false })
+  )]
+  fn test_alias_reasoning_format(
+    #[case] chat_template: ChatTemplate,
+    #[case] reasoning: Option<ReasoningFormat>,
+    #[case] expected: Option<ReasoningFormat>,
+  ) {
+    let alias = Alias {
+      chat_template,
+      reasoning,
+      ..Default::default()
+    };
+    assert_eq!(expected, alias.reasoning_format());
+  }
+
+  #[rstest]
+  fn test_alias_deserialize_reasoning() -> anyhow::Result<()> {
+    let alias = serde_yaml::from_str::<Alias>(
+      r#"alias: qwq:32b
+repo: Qwen/QwQ-32B-GGUF
+filename: qwq-32b-q4_k_m.gguf
+snapshot: main
+features:
+- chat
+chat_template: chatml
+reasoning:
+  start: <think>
+  end: </think>
+"#,
+    )?;
+    assert_eq!(Some(ReasoningFormat::think(false)), alias.reasoning);
+    Ok(())
+  }
+
   #[rstest]
   #[case(
     Alias::default(),
@@ -0,0 +1,301 @@
+use super::{Alias, AliasFeature};
+use serde::Serialize;
+use serde_yaml::Value;
+use std::str::FromStr;
+
+// the fields of the model alias config, with an example value for the required ones
+const ALIAS_FIELDS: &[(&str, Option<&str>)] = &[
+  ("alias", Some("llama3:instruct")),
+  ("extends", None),
+  ("family", None),
+  ("repo", Some("QuantFactory/Meta-Llama-3-8B-Instruct-GGUF")),
+  ("filename", Some("Meta-Llama-3-8B-Instruct.Q8_0.gguf")),
+  ("snapshot", Some("main")),
+  ("features", Some("[chat]")),
+  ("chat_template", Some("llama3")),
+  ("request_params", None),
+  ("context_params", None),
+  ("reasoning", None),
+];
+
+/// A problem in a model alias file, at the line of the field when known
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AliasDiagnostic {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub line: Option<usize>,
+  pub message: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub suggestion: Option<String>,
+}
+
+/// The problems in a model alias file, the file is `skipped` when it cannot be loaded as a model
+/// alias, otherwise the problems are warnings, e.g. an unknown field being ignored
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AliasLint {
+  pub file: String,
+  pub skipped: bool,
+  pub diagnostics: Vec<AliasDiagnostic>,
+}
+
+impl AliasLint {
+  pub fn new(file: String, content: &str) -> Self {
+    let diagnostics = lint_alias_yaml(content);
+    // the aliases extending another alias are checked once the fields are inherited
+    let skipped =
+      field_line(content, "extends").is_none() && serde_yaml::from_str::<Alias>(content).is_err();
+    AliasLint {
+      file,
+      skipped,
+      diagnostics,
+    }
+  }
+
+  pub fn is_valid(&self) -> bool {
+    self.diagnostics.is_empty()
+  }
+
+  /// The alias failed to load, e.g. the alias in `extends` is not found, the reason is added
+  /// unless the problems in the file already explain it
+  pub fn unresolved(&mut self, content: &str, message: String) {
+    self.skipped = true;
+    let extends = field_line(content, "extends");
+    if extends.is_some() || self.diagnostics.is_empty() {
+      self.diagnostics.push(AliasDiagnostic {
+        line: extends,
+        message,
+        suggestion: None,
+      });
+    }
+  }
+}
+
+/// The problems in the yaml of a model alias, empty if it is valid
+pub fn lint_alias_yaml(content: &str) -> Vec<AliasDiagnostic> {
+  let value = match serde_yaml::from_str::<Value>(content) {
+    Ok(value) => value,
+    Err(err) => {
+      return vec![AliasDiagnostic {
+        line: err.location().map(|location| location.line()),
+        message: format!("invalid yaml: {err}"),
+        suggestion: Some("check the indentation and the quoting of the values".to_string()),
+      }]
+    }
+  };
+  let Value::Mapping(fields) = value else {
+    return vec![AliasDiagnostic {
+      line: None,
+      message: "the model alias should be a mapping of the fields".to_string(),
+      suggestion: Some(
+        "start with the name of the alias, e.g. `alias: llama3:instruct`".to_string(),
+      ),
+    }];
+  };
+  let mut diagnostics = Vec::new();
+  for key in fields.keys() {
+    let Some(key) = key.as_str() else {
+      diagnostics.push(AliasDiagnostic {
+        line: None,
+        message: format!("field name {key:?} is not a string"),
+        suggestion: None,
+      });
+      continue;
+    };
+    if ALIAS_FIELDS.iter().any(|(field, _)| *field == key) {
+      continue;
+    }
+    let suggestion = match closest_field(key) {
+      Some(field) => format!("did you mean `{field}`?"),
+      None => format!(
+        "remove it, the fields are {}",
+        ALIAS_FIELDS
+          .iter()
+          .map(|(field, _)| format!("`{field}`"))
+          .collect::<Vec<_>>()
+          .join(", ")
+      ),
+    };
+    diagnostics.push(AliasDiagnostic {
+      line: field_line(content, key),
+      message: format!("unknown field `{key}`, it is ignored"),
+      suggestion: Some(suggestion),
+    });
+  }
+  // the missing fields are inherited from the alias it extends
+  let extends = fields.contains_key("extends");
+  for (field, example) in ALIAS_FIELDS {
+    let Some(example) = example.filter(|_| !extends) else {
+      continue;
+    };
+    if !fields.contains_key(*field) {
+      diagnostics.push(AliasDiagnostic {
+        line: None,
+        message: format!("missing field `{field}`"),
+        suggestion: Some(format!("add `{field}: {example}`")),
+      });
+    }
+  }
+  if let Some(Value::Sequence(features)) = fields.get("features") {
+    for feature in features {
+      let valid = feature
+        .as_str()
+        .is_some_and(|feature| AliasFeature::from_str(feature).is_ok());
+      if !valid {
+        diagnostics.push(AliasDiagnostic {
+          line: field_line(content, "features"),
+          message: format!("unknown feature {}", display_value(feature)),
+          suggestion: Some("use `chat`, `embedding` or `rerank`".to_string()),
+        });
+      }
+    }
+  }
+  // the types of the values, once the fields are right
+  if diagnostics.is_empty() && !extends {
+    if let Err(err) = serde_yaml::from_str::<Alias>(content) {
+      diagnostics.push(AliasDiagnostic {
+        line: err.location().map(|location| location.line()),
+        message: err.to_string(),
+        suggestion: None,
+      });
+    }
+  }
+  diagnostics
+}
+
+// the line of the top level field, 1-based as the serde_yaml locations
+fn field_line(content: &str, field: &str) -> Option<usize> {
+  content
+    .lines()
+    .position(|line| {
+      line
+        .strip_prefix(field)
+        .is_some_and(|rest| rest.trim_start().starts_with(':'))
+    })
+    .map(|index| index + 1)
+}
+
+fn display_value(value: &Value) -> String {
+  match value.as_str() {
+    Some(value) => format!("`{value}`"),
+    None => serde_yaml::to_string(value)
+      .map(|value| format!("`{}`", value.trim()))
+      .unwrap_or_default(),
+  }
+}
+
+// the known field closest to the misspelled one, if close enough to be a typo
+fn closest_field(key: &str) -> Option<&'static str> {
+  let key = key.to_lowercase().replace('-', "_");
+  ALIAS_FIELDS
+    .iter()
+    .map(|(field, _)| (*field, edit_distance(&key, field)))
+    .filter(|(field, distance)| *distance <= 2.max(field.len() / 4))
+    .min_by_key(|(_, distance)| *distance)
+    .map(|(field, _)| field)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+  let b = b.chars().collect::<Vec<_>>();
+  let mut previous = (0..=b.len()).collect::<Vec<_>>();
+  for (i, a_char) in a.chars().enumerate() {
+    let mut current = vec![i + 1; b.len() + 1];
+    for (j, b_char) in b.iter().enumerate() {
+      let substitution = previous[j] + usize::from(a_char != *b_char);
+      current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+    }
+    previous = current;
+  }
+  previous[b.len()]
+}
+
+#[cfg(test)]
+mod test {
+  use super::{lint_alias_yaml, AliasDiagnostic, AliasLint};
+  use rstest::rstest;
+
+  const VALID: &str = r#"alias: testalias:instruct
+repo: MyFactory/testalias-gguf
+filename: testalias.Q8_0.gguf
+snapshot: 5007652f7a641fe7170e0bad4f63839419bd9213
+features:
+- chat
+chat_template: llama3
+"#;
+
+  #[rstest]
+  fn test_alias_lint_valid() {
+    assert_eq!(Vec::<AliasDiagnostic>::new(), lint_alias_yaml(VALID));
+    let lint = AliasLint::new("testalias.yaml".to_string(), VALID);
+    assert!(lint.is_valid());
+    assert!(!lint.skipped);
+  }
+
+  #[rstest]
+  fn test_alias_lint_unknown_and_missing_fields() {
+    let content = VALID.replace("chat_template: llama3", "chat_tempalte: llama3");
+    assert_eq!(
+      vec![
+        AliasDiagnostic {
+          line: Some(7),
+          message: "unknown field `chat_tempalte`, it is ignored".to_string(),
+          suggestion: Some("did you mean `chat_template`?".to_string()),
+        },
+        AliasDiagnostic {
+          line: None,
+          message: "missing field `chat_template`".to_string(),
+          suggestion: Some("add `chat_template: llama3`".to_string()),
+        },
+      ],
+      lint_alias_yaml(&content)
+    );
+    assert!(AliasLint::new("testalias.yaml".to_string(), &content).skipped);
+  }
+
+  #[rstest]
+  fn test_alias_lint_unknown_field_is_a_warning() {
+    let content = format!("{VALID}comment: my favourite model\n");
+    let lint = AliasLint::new("testalias.yaml".to_string(), &content);
+    assert!(!lint.skipped);
+    assert_eq!(1, lint.diagnostics.len());
+    assert_eq!(Some(8), lint.diagnostics[0].line);
+    assert!(lint.diagnostics[0]
+      .suggestion
+      .as_deref()
+      .is_some_and(|suggestion| suggestion.starts_with("remove it")));
+  }
+
+  #[rstest]
+  fn test_alias_lint_extends_inherits_the_missing_fields() {
+    let content = "alias: testalias:creative\nextends: testalias:instruct\nrequest_params:\n  temperature: 1.2\n";
+    let lint = AliasLint::new("testalias--creative.yaml".to_string(), content);
+    assert!(lint.is_valid(), "{:?}", lint.diagnostics);
+    assert!(!lint.skipped);
+  }
+
+  #[rstest]
+  #[case(VALID.replace("- chat", "- chats"), Some(5), "unknown feature `chats`")]
+  #[case(VALID.replace("features:\n- chat", "features: chat"), Some(5), "features: invalid type")]
+  #[case("- alias: testalias:instruct".to_string(), None, "the model alias should be a mapping")]
+  fn test_alias_lint_invalid_values(
+    #[case] content: String,
+    #[case] line: Option<usize>,
+    #[case] message: &str,
+  ) {
+    let diagnostics = lint_alias_yaml(&content);
+    assert_eq!(1, diagnostics.len(), "{diagnostics:?}");
+    assert_eq!(line, diagnostics[0].line);
+    assert!(
+      diagnostics[0].message.starts_with(message),
+      "{}",
+      diagnostics[0].message
+    );
+  }
+
+  #[rstest]
+  fn test_alias_lint_invalid_yaml() {
+    let content = VALID.replace("features:\n- chat", "features: [chat");
+    let diagnostics = lint_alias_yaml(&content);
+    assert_eq!(1, diagnostics.len());
+    assert!(diagnostics[0].line.is_some());
+    assert!(diagnostics[0].message.starts_with("invalid yaml"));
+  }
+}
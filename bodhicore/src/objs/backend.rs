@@ -0,0 +1,101 @@
+use clap::ValueEnum;
+use llama_server_bindings::GptParams;
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
+
+pub static DEFAULT_BACKEND: &str = "auto";
+
+/// The acceleration used by llama.cpp to run the model, set using $BODHI_BACKEND and overridden
+/// by the `backend` in the `context_params` of the alias
+#[derive(
+  Debug,
+  Clone,
+  Copy,
+  PartialEq,
+  Eq,
+  PartialOrd,
+  Default,
+  ValueEnum,
+  Display,
+  EnumString,
+  Serialize,
+  Deserialize,
+)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+  /// the fastest backend supported by the machine, out of the ones available to bodhi
+  #[default]
+  Auto,
+  /// runs on the CPU, without offloading any layers to the GPU
+  Cpu,
+  /// Apple silicon GPUs
+  Metal,
+  /// NVIDIA GPUs
+  Cuda,
+  /// AMD, Intel and NVIDIA GPUs using the Vulkan drivers
+  Vulkan,
+}
+
+impl Backend {
+  /// The GPU backends, in the order of preference when picking one automatically
+  pub const ACCELERATED: [Backend; 3] = [Backend::Metal, Backend::Cuda, Backend::Vulkan];
+
+  /// The backends the llama.cpp bindings of this binary are built with
+  pub fn compiled() -> Vec<Backend> {
+    let mut backends = vec![Backend::Cpu];
+    if cfg!(feature = "metal") {
+      backends.push(Backend::Metal);
+    }
+    if cfg!(feature = "cuda") {
+      backends.push(Backend::Cuda);
+    }
+    if cfg!(feature = "vulkan") {
+      backends.push(Backend::Vulkan);
+    }
+    backends
+  }
+
+  pub fn update(&self, gpt_params: &mut GptParams) {
+    if self == &Backend::Cpu {
+      gpt_params.n_gpu_layers = Some(0);
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Backend, DEFAULT_BACKEND};
+  use llama_server_bindings::GptParams;
+  use rstest::rstest;
+
+  #[rstest]
+  #[case("auto", Backend::Auto)]
+  #[case("cpu", Backend::Cpu)]
+  #[case("CUDA", Backend::Cuda)]
+  #[case("vulkan", Backend::Vulkan)]
+  fn test_backend_parse(#[case] input: &str, #[case] expected: Backend) -> anyhow::Result<()> {
+    assert_eq!(expected, input.parse::<Backend>()?);
+    assert_eq!(Backend::default(), DEFAULT_BACKEND.parse::<Backend>()?);
+    assert!("rocm".parse::<Backend>().is_err());
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Backend::Cpu, Some(0))]
+  #[case(Backend::Cuda, Some(33))]
+  #[case(Backend::Auto, Some(33))]
+  fn test_backend_update_gpt_params(
+    #[case] backend: Backend,
+    #[case] expected: Option<i32>,
+  ) -> anyhow::Result<()> {
+    let mut gpt_params = GptParams {
+      n_gpu_layers: Some(33),
+      ..Default::default()
+    };
+    backend.update(&mut gpt_params);
+    assert_eq!(expected, gpt_params.n_gpu_layers);
+    assert!(Backend::compiled().contains(&Backend::Cpu));
+    Ok(())
+  }
+}
@@ -0,0 +1,138 @@
+use super::RemoteModel;
+use chrono::{serde::ts_milliseconds, DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum CatalogChangeKind {
+  Added,
+  Updated,
+  Removed,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatalogChange {
+  pub kind: CatalogChangeKind,
+  pub alias: String,
+  pub repo: String,
+  pub filename: String,
+  /// `<repo>/<filename>` before the update, if the recommended file changed
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub previous: Option<String>,
+}
+
+/// Changes to the remote models catalog (`$BODHI_HOME/models.yaml`) in a single refresh
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatalogDiff {
+  #[serde(with = "ts_milliseconds")]
+  pub created_at: DateTime<Utc>,
+  pub changes: Vec<CatalogChange>,
+}
+
+impl CatalogDiff {
+  pub fn new(previous: &[RemoteModel], current: &[RemoteModel], created_at: DateTime<Utc>) -> Self {
+    let mut changes = vec![];
+    for model in current {
+      match previous.iter().find(|item| item.alias == model.alias) {
+        None => changes.push(CatalogChange::from_model(CatalogChangeKind::Added, model)),
+        Some(old) if old != model => {
+          let mut change = CatalogChange::from_model(CatalogChangeKind::Updated, model);
+          if old.repo != model.repo || old.filename != model.filename {
+            change.previous = Some(format!("{}/{}", old.repo, old.filename));
+          }
+          changes.push(change);
+        }
+        Some(_) => {}
+      }
+    }
+    for old in previous {
+      if !current.iter().any(|model| model.alias == old.alias) {
+        changes.push(CatalogChange::from_model(CatalogChangeKind::Removed, old));
+      }
+    }
+    CatalogDiff {
+      created_at,
+      changes,
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.changes.is_empty()
+  }
+}
+
+impl CatalogChange {
+  fn from_model(kind: CatalogChangeKind, model: &RemoteModel) -> Self {
+    CatalogChange {
+      kind,
+      alias: model.alias.clone(),
+      repo: model.repo.to_string(),
+      filename: model.filename.clone(),
+      previous: None,
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{CatalogChange, CatalogChangeKind, CatalogDiff};
+  use crate::objs::RemoteModel;
+  use chrono::Utc;
+  use rstest::rstest;
+
+  #[rstest]
+  fn test_catalog_diff() -> anyhow::Result<()> {
+    let llama3 = RemoteModel::llama3();
+    let mut llama3_q4 = RemoteModel::llama3();
+    llama3_q4.filename = "Meta-Llama-3-8B-Instruct.Q4_K_M.gguf".to_string();
+    let mut llama3_features = RemoteModel::llama3();
+    llama3_features.features = vec!["chat".to_string(), "tools".to_string()];
+    let testalias = RemoteModel::testalias();
+    let now = Utc::now();
+
+    let diff = CatalogDiff::new(&[llama3.clone(), testalias.clone()], &[llama3_q4], now);
+    assert_eq!(
+      CatalogDiff {
+        created_at: now,
+        changes: vec![
+          CatalogChange {
+            kind: CatalogChangeKind::Updated,
+            alias: "llama3:instruct".to_string(),
+            repo: "QuantFactory/Meta-Llama-3-8B-Instruct-GGUF".to_string(),
+            filename: "Meta-Llama-3-8B-Instruct.Q4_K_M.gguf".to_string(),
+            previous: Some(
+              "QuantFactory/Meta-Llama-3-8B-Instruct-GGUF/Meta-Llama-3-8B-Instruct.Q8_0.gguf"
+                .to_string()
+            ),
+          },
+          CatalogChange {
+            kind: CatalogChangeKind::Removed,
+            alias: testalias.alias.clone(),
+            repo: testalias.repo.to_string(),
+            filename: testalias.filename.clone(),
+            previous: None,
+          },
+        ],
+      },
+      diff
+    );
+
+    let diff = CatalogDiff::new(&[llama3.clone()], &[llama3_features, testalias], now);
+    let kinds = diff
+      .changes
+      .iter()
+      .map(|change| (change.kind, change.previous.is_some()))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      vec![
+        (CatalogChangeKind::Updated, false),
+        (CatalogChangeKind::Added, false)
+      ],
+      kinds
+    );
+    assert!(CatalogDiff::new(&[llama3.clone()], &[llama3], now).is_empty());
+    Ok(())
+  }
+}
@@ -1,4 +1,5 @@
 use super::{ObjError, Repo};
+use async_openai::types::{CreateChatCompletionRequest, Stop};
 use serde::{Deserialize, Serialize};
 use std::fmt::Display;
 use strum::{AsRefStr, EnumIter};
@@ -28,6 +29,42 @@ pub enum ChatTemplateId {
   CommandR,
   Openchat,
   Tinyllama,
+  Chatml,
+  MistralV3,
+  MistralV7,
+  #[serde(rename = "qwen2.5")]
+  #[strum(serialize = "qwen2.5")]
+  #[value(name = "qwen2.5")]
+  Qwen25,
+  DeepseekV2,
+  DeepseekR1,
+  Hermes2Pro,
+  Hermes3,
+}
+
+impl ChatTemplateId {
+  /// Canonical end-of-turn sequences emitted by models using this template.
+  pub fn stop_sequences(&self) -> &'static [&'static str] {
+    match self {
+      ChatTemplateId::Llama3 => &["<|eot_id|>", "<|end_of_text|>"],
+      ChatTemplateId::Llama2 => &["</s>"],
+      ChatTemplateId::Llama2Legacy => &["</s>", "[INST]"],
+      ChatTemplateId::Phi3 => &["<|end|>", "<|endoftext|>"],
+      ChatTemplateId::Gemma => &["<end_of_turn>"],
+      ChatTemplateId::Deepseek => &["<｜end▁of▁sentence｜>"],
+      ChatTemplateId::CommandR => &["<|END_OF_TURN_TOKEN|>"],
+      ChatTemplateId::Openchat => &["<|eot_id|>", "<|end_of_turn|>"],
+      ChatTemplateId::Tinyllama => &["</s>"],
+      ChatTemplateId::Chatml => &["<|im_end|>"],
+      ChatTemplateId::MistralV3 => &["</s>"],
+      ChatTemplateId::MistralV7 => &["</s>"],
+      ChatTemplateId::Qwen25 => &["<|im_end|>", "<|endoftext|>"],
+      ChatTemplateId::DeepseekV2 => &["<｜end▁of▁sentence｜>"],
+      ChatTemplateId::DeepseekR1 => &["<｜end▁of▁sentence｜>"],
+      ChatTemplateId::Hermes2Pro => &["<|im_end|>"],
+      ChatTemplateId::Hermes3 => &["<|im_end|>"],
+    }
+  }
 }
 
 impl PartialOrd for ChatTemplateId {
@@ -43,6 +80,26 @@ pub enum ChatTemplate {
   Repo(Repo),
 }
 
+impl ChatTemplate {
+  pub fn stop_sequences(&self) -> Vec<String> {
+    match self {
+      ChatTemplate::Id(id) => id.stop_sequences().iter().map(|s| s.to_string()).collect(),
+      ChatTemplate::Repo(_) => vec![],
+    }
+  }
+
+  // stop sequences from the request or the alias take precedence over the template defaults
+  pub fn update(&self, request: &mut CreateChatCompletionRequest) {
+    if request.stop.is_some() {
+      return;
+    }
+    let stop = self.stop_sequences();
+    if !stop.is_empty() {
+      request.stop = Some(Stop::StringArray(stop));
+    }
+  }
+}
+
 impl TryFrom<ChatTemplate> for Repo {
   type Error = ObjError;
 
@@ -59,6 +116,14 @@ impl TryFrom<ChatTemplate> for Repo {
           ChatTemplateId::CommandR => "CohereForAI/c4ai-command-r-plus",
           ChatTemplateId::Openchat => "openchat/openchat-3.6-8b-20240522",
           ChatTemplateId::Tinyllama => "TinyLlama/TinyLlama-1.1B-Chat-v1.0",
+          ChatTemplateId::Chatml => "teknium/OpenHermes-2.5-Mistral-7B",
+          ChatTemplateId::MistralV3 => "mistralai/Mistral-7B-Instruct-v0.3",
+          ChatTemplateId::MistralV7 => "mistralai/Mistral-Large-Instruct-2411",
+          ChatTemplateId::Qwen25 => "Qwen/Qwen2.5-7B-Instruct",
+          ChatTemplateId::DeepseekV2 => "deepseek-ai/DeepSeek-V2.5",
+          ChatTemplateId::DeepseekR1 => "deepseek-ai/DeepSeek-R1-Distill-Qwen-7B",
+          ChatTemplateId::Hermes2Pro => "NousResearch/Hermes-2-Pro-Llama-3-8B",
+          ChatTemplateId::Hermes3 => "NousResearch/Hermes-3-Llama-3.1-8B",
         };
         Repo::try_from(repo)?
       }
@@ -80,8 +145,25 @@ impl Display for ChatTemplate {
 #[cfg(test)]
 mod test {
   use super::{ChatTemplate, ChatTemplateId, Repo};
+  use async_openai::types::{CreateChatCompletionRequestArgs, Stop};
+  use clap::ValueEnum;
   use rstest::rstest;
 
+  #[rstest]
+  #[case("qwen2.5", ChatTemplateId::Qwen25)]
+  #[case("mistral-v7", ChatTemplateId::MistralV7)]
+  #[case("deepseek-r1", ChatTemplateId::DeepseekR1)]
+  #[case("hermes2-pro", ChatTemplateId::Hermes2Pro)]
+  fn test_chat_template_id_names(
+    #[case] name: &str,
+    #[case] expected: ChatTemplateId,
+  ) -> anyhow::Result<()> {
+    assert_eq!(name, expected.to_string());
+    assert_eq!(expected, serde_yaml::from_str::<ChatTemplateId>(name)?);
+    assert_eq!(Ok(expected), ChatTemplateId::from_str(name, false));
+    Ok(())
+  }
+
   #[rstest]
   fn test_chat_template_id_partial_ord() {
     assert!(ChatTemplateId::Llama3.gt(&ChatTemplateId::Llama2));
@@ -126,6 +208,18 @@ mod test {
     "openchat/openchat-3.6-8b-20240522"
   )]
   #[rstest]
+  #[case(
+    ChatTemplate::Id(ChatTemplateId::Chatml),
+    "teknium/OpenHermes-2.5-Mistral-7B"
+  )]
+  #[rstest]
+  #[case(ChatTemplate::Id(ChatTemplateId::Qwen25), "Qwen/Qwen2.5-7B-Instruct")]
+  #[rstest]
+  #[case(
+    ChatTemplate::Id(ChatTemplateId::DeepseekR1),
+    "deepseek-ai/DeepSeek-R1-Distill-Qwen-7B"
+  )]
+  #[rstest]
   #[case(
     ChatTemplate::Repo(Repo::try_from("foo/bar").unwrap()),
     "foo/bar"
@@ -138,4 +232,25 @@ mod test {
     assert_eq!(expected, repo.to_string());
     Ok(())
   }
+
+  #[rstest]
+  #[case(ChatTemplate::Id(ChatTemplateId::Llama3), None, Some(Stop::StringArray(vec!["<|eot_id|>".to_string(), "<|end_of_text|>".to_string()])))]
+  #[case(ChatTemplate::Id(ChatTemplateId::Gemma), None, Some(Stop::StringArray(vec!["<end_of_turn>".to_string()])))]
+  #[case(ChatTemplate::Id(ChatTemplateId::Qwen25), None, Some(Stop::StringArray(vec!["<|im_end|>".to_string(), "<|endoftext|>".to_string()])))]
+  #[case(ChatTemplate::Id(ChatTemplateId::Llama3), Some(Stop::String("\n".to_string())), Some(Stop::String("\n".to_string())))]
+  #[case(ChatTemplate::Repo(Repo::try_from("foo/bar").unwrap()), None, None)]
+  fn test_chat_template_update_applies_stop_sequences(
+    #[case] chat_template: ChatTemplate,
+    #[case] stop: Option<Stop>,
+    #[case] expected: Option<Stop>,
+  ) -> anyhow::Result<()> {
+    let mut request = CreateChatCompletionRequestArgs::default()
+      .model("testalias:instruct")
+      .messages(vec![])
+      .build()?;
+    request.stop = stop;
+    chat_template.update(&mut request);
+    assert_eq!(expected, request.stop);
+    Ok(())
+  }
 }
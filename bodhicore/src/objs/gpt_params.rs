@@ -1,5 +1,6 @@
 #[allow(unused_imports)]
 use crate::objs::BuilderError;
+use crate::objs::{Backend, Truncation};
 use clap::Args;
 use llama_server_bindings::GptParams;
 use serde::{Deserialize, Serialize};
@@ -59,15 +60,53 @@ default: 0"#
   )]
   #[serde(default, skip_serializing_if = "Option::is_none")]
   pub n_keep: Option<i32>,
+
+  #[arg(
+    long,
+    help = r#"number of layers to offload to the GPU
+default: 0"#
+  )]
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub n_gpu_layers: Option<i32>,
+
+  #[arg(
+    long,
+    value_enum,
+    help = r#"what to do when the chat history does not fit in the context
+default: error"#
+  )]
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub truncation: Option<Truncation>,
+
+  #[arg(
+    long,
+    help = r#"generate a token after loading the model on startup, so the first request is fast
+default: false"#
+  )]
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub warmup: Option<bool>,
+
+  #[arg(
+    long,
+    value_enum,
+    help = r#"acceleration to run the model with, overriding $BODHI_BACKEND
+default: $BODHI_BACKEND"#
+  )]
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub backend: Option<Backend>,
 }
 
 impl GptContextParams {
   pub fn update(&self, gpt_params: &mut GptParams) {
-    // gpt_params.n_threads = self.n_threads;
+    gpt_params.n_threads = self.n_threads;
     gpt_params.seed = self.n_seed;
     gpt_params.n_ctx = self.n_ctx;
     gpt_params.n_predict = self.n_predict;
     gpt_params.n_parallel = self.n_parallel;
     gpt_params.n_keep = self.n_keep;
+    gpt_params.n_gpu_layers = self.n_gpu_layers;
+    if let Some(backend) = self.backend {
+      backend.update(gpt_params);
+    }
   }
 }
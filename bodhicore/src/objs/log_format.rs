@@ -0,0 +1,45 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
+
+pub static DEFAULT_LOG_FORMAT: &str = "text";
+
+/// Format of the log lines written to $BODHI_LOGS
+#[derive(
+  Debug, Clone, Copy, PartialEq, Default, ValueEnum, Display, EnumString, Serialize, Deserialize,
+)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+  /// human readable lines
+  #[default]
+  Text,
+  /// one json object per line with the fields of the request span, for log collectors like
+  /// Loki or ELK
+  Json,
+}
+
+#[cfg(test)]
+mod test {
+  use super::{LogFormat, DEFAULT_LOG_FORMAT};
+  use rstest::rstest;
+
+  #[rstest]
+  #[case("text", LogFormat::Text)]
+  #[case("json", LogFormat::Json)]
+  #[case("JSON", LogFormat::Json)]
+  fn test_log_format_parse(#[case] input: &str, #[case] expected: LogFormat) -> anyhow::Result<()> {
+    assert_eq!(expected, input.parse::<LogFormat>()?);
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_log_format_default() -> anyhow::Result<()> {
+    assert_eq!(
+      LogFormat::default(),
+      DEFAULT_LOG_FORMAT.parse::<LogFormat>()?
+    );
+    assert!("yaml".parse::<LogFormat>().is_err());
+    Ok(())
+  }
+}
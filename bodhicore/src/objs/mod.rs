@@ -1,21 +1,41 @@
 mod alias;
+mod alias_lint;
+mod backend;
 mod builder;
+mod catalog;
 mod chat_template;
 mod error;
 mod gpt_params;
 mod hub_file;
+mod log_format;
 mod oai;
+mod pipeline;
+mod priority;
+mod reasoning;
 mod remote_file;
 mod repo;
+mod rerank;
+mod timezone;
+mod truncation;
 mod utils;
 
 pub use alias::*;
+pub use alias_lint::*;
+pub use backend::*;
 pub use builder::BuilderError;
+pub use catalog::*;
 pub use chat_template::{ChatTemplate, ChatTemplateId};
 pub use error::*;
 pub use gpt_params::*;
 pub use hub_file::*;
+pub use log_format::*;
 pub use oai::*;
+pub use pipeline::*;
+pub use priority::*;
+pub use reasoning::*;
 pub use remote_file::*;
 pub use repo::*;
+pub use rerank::*;
+pub use timezone::*;
+pub use truncation::*;
 pub use utils::*;
@@ -1,8 +1,147 @@
 #[allow(unused_imports)]
 use crate::objs::BuilderError;
+use crate::objs::RequestPriority;
 use async_openai::types::{CreateChatCompletionRequest, Stop};
 use clap::Args;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+use std::ops::{Deref, DerefMut};
+
+/// llama.cpp specific request params accepted as vendor extensions to the OpenAI API
+/// - `return_tokens`: stream the generated token ids alongside the text deltas
+/// - `timeout_secs`: abort the generation after the given seconds, can only shorten `BODHI_GENERATION_TIMEOUT_SECS`
+/// - `priority`: `interactive` or `batch`, the batch requests wait while interactive requests are using the model
+/// - `top_k`, `min_p`, `repeat_penalty`, `repeat_last_n`, `typical_p`: the llama.cpp samplers missing in the OpenAI API
+/// - `mirostat`, `mirostat_tau`, `mirostat_eta`: the mirostat sampling of llama.cpp, targeting a constant perplexity
+pub static VENDOR_EXTENSIONS: &[&str] = &[
+  "return_tokens",
+  TIMEOUT_SECS,
+  PRIORITY,
+  TOP_K,
+  MIN_P,
+  REPEAT_PENALTY,
+  REPEAT_LAST_N,
+  TYPICAL_P,
+  MIROSTAT,
+  MIROSTAT_TAU,
+  MIROSTAT_ETA,
+];
+
+static TIMEOUT_SECS: &str = "timeout_secs";
+static PRIORITY: &str = "priority";
+pub static TOP_K: &str = "top_k";
+pub static MIN_P: &str = "min_p";
+pub static REPEAT_PENALTY: &str = "repeat_penalty";
+pub static REPEAT_LAST_N: &str = "repeat_last_n";
+pub static TYPICAL_P: &str = "typical_p";
+pub static MIROSTAT: &str = "mirostat";
+pub static MIROSTAT_TAU: &str = "mirostat_tau";
+pub static MIROSTAT_ETA: &str = "mirostat_eta";
+
+/// OpenAI chat completion request along with the supported vendor extensions,
+/// the extensions are passed through as-is to the llama.cpp server
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChatCompletionRequest {
+  #[serde(flatten)]
+  pub request: CreateChatCompletionRequest,
+  #[serde(flatten)]
+  pub extensions: Map<String, Value>,
+}
+
+impl<'de> Deserialize<'de> for ChatCompletionRequest {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    let mut value = Map::<String, Value>::deserialize(deserializer)?;
+    let extensions = VENDOR_EXTENSIONS
+      .iter()
+      .filter_map(|key| value.remove_entry(*key))
+      .collect::<Map<_, _>>();
+    let request = serde_json::from_value::<CreateChatCompletionRequest>(Value::Object(value))
+      .map_err(serde::de::Error::custom)?;
+    Ok(Self {
+      request,
+      extensions,
+    })
+  }
+}
+
+impl From<CreateChatCompletionRequest> for ChatCompletionRequest {
+  fn from(request: CreateChatCompletionRequest) -> Self {
+    Self {
+      request,
+      extensions: Map::new(),
+    }
+  }
+}
+
+impl ChatCompletionRequest {
+  /// Timeout of the request, from the `timeout_secs` extension
+  pub fn timeout_secs(&self) -> Option<u64> {
+    self.extensions.get(TIMEOUT_SECS).and_then(Value::as_u64)
+  }
+
+  /// Priority of the request, from the `priority` extension, interactive if not set or unknown
+  pub fn priority(&self) -> RequestPriority {
+    self
+      .extensions
+      .get(PRIORITY)
+      .and_then(Value::as_str)
+      .and_then(|priority| priority.parse().ok())
+      .unwrap_or_default()
+  }
+
+  pub fn set_priority(&mut self, priority: RequestPriority) {
+    self
+      .extensions
+      .insert(PRIORITY.to_string(), Value::String(priority.to_string()));
+  }
+
+  /// The OpenAI `logit_bias` map of token id to bias, as the llama.cpp `[[token_id, bias], ...]` entries.
+  /// The bias of -100 bans the token, the same as `false` in llama.cpp.
+  pub fn llama_logit_bias(&self) -> Result<Option<Value>, String> {
+    let Some(logit_bias) = self.logit_bias.as_ref().filter(|bias| !bias.is_empty()) else {
+      return Ok(None);
+    };
+    let mut entries = Vec::<(u32, Value)>::new();
+    for (token, bias) in logit_bias {
+      let token_id = token
+        .parse::<u32>()
+        .map_err(|_| format!("logit_bias key '{token}' should be a token id"))?;
+      let bias = bias
+        .as_f64()
+        .filter(|bias| (-100.0..=100.0).contains(bias))
+        .ok_or_else(|| format!("logit_bias of token {token} should be between -100 and 100"))?;
+      let bias = if bias <= -100.0 {
+        Value::Bool(false)
+      } else {
+        Value::from(bias)
+      };
+      entries.push((token_id, bias));
+    }
+    entries.sort_by_key(|(token_id, _)| *token_id);
+    let entries = entries
+      .into_iter()
+      .map(|(token_id, bias)| Value::Array(vec![Value::from(token_id), bias]))
+      .collect();
+    Ok(Some(Value::Array(entries)))
+  }
+}
+
+impl Deref for ChatCompletionRequest {
+  type Target = CreateChatCompletionRequest;
+
+  fn deref(&self) -> &Self::Target {
+    &self.request
+  }
+}
+
+impl DerefMut for ChatCompletionRequest {
+  fn deref_mut(&mut self) -> &mut Self::Target {
+    &mut self.request
+  }
+}
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default, PartialOrd, Args)]
 #[cfg_attr(test, derive(derive_builder::Builder))]
@@ -66,6 +205,55 @@ default: 1.0 (disabled)"#)]
   )]
   #[serde(default, skip_serializing_if = "Option::is_none")]
   pub user: Option<String>,
+
+  #[arg(long, value_parser = clap::value_parser!(i32).range(0..),
+  help=r#"Limits the next token selection to the K most probable tokens.
+default: 40, 0 (disabled)"#)]
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub top_k: Option<i32>,
+
+  #[arg(long, value_parser = validate_range_0_to_1, help=r#"Number between 0.0 and 1.0.
+The minimum probability for a token to be considered, relative to the probability of the most likely token.
+default: 0.05, 0.0 (disabled)"#)]
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub min_p: Option<f32>,
+
+  #[arg(long, value_parser = validate_range_0_to_2, help=r#"Number between 0.0 and 2.0.
+Penalizes the repeated sequences of tokens, higher values penalize the repetitions more strongly.
+default: 1.0 (disabled)"#)]
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub repeat_penalty: Option<f32>,
+
+  #[arg(long, allow_negative_numbers = true, value_parser = clap::value_parser!(i32).range(-1..),
+  help=r#"Number of the last tokens to consider for penalizing the repetitions.
+default: 64, 0 (disabled), -1 (context size)"#)]
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub repeat_last_n: Option<i32>,
+
+  #[arg(long, value_parser = validate_range_0_to_1, help=r#"Number between 0.0 and 1.0.
+Locally typical sampling, considers the tokens whose probability is close to the expected probability.
+default: 1.0 (disabled)"#)]
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub typical_p: Option<f32>,
+
+  #[arg(long, value_parser = clap::value_parser!(u8).range(0..=2),
+  help=r#"Mirostat sampling, controlling the perplexity of the generated text, 1 for Mirostat and 2 for Mirostat 2.0.
+The other samplers except temperature are ignored when enabled.
+default: 0 (disabled)"#)]
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub mirostat: Option<u8>,
+
+  #[arg(long, value_parser = validate_range_0_to_10, help=r#"Number between 0.0 and 10.0.
+The target entropy of the mirostat sampling, lower values give more focused and coherent text.
+default: 5.0"#)]
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub mirostat_tau: Option<f32>,
+
+  #[arg(long, value_parser = validate_range_0_to_1, help=r#"Number between 0.0 and 1.0.
+The learning rate of the mirostat sampling, how quickly it adapts to the generated text.
+default: 0.1"#)]
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub mirostat_eta: Option<f32>,
 }
 
 fn validate_range_neg_to_pos_2(s: &str) -> Result<f32, String> {
@@ -80,6 +268,12 @@ fn validate_range_0_to_2(s: &str) -> Result<f32, String> {
   validate_range(s, lower, upper)
 }
 
+fn validate_range_0_to_10(s: &str) -> Result<f32, String> {
+  let lower = 0.0;
+  let upper = 10.0;
+  validate_range(s, lower, upper)
+}
+
 fn validate_range_0_to_1(s: &str) -> Result<f32, String> {
   let lower = 0.0;
   let upper = 1.0;
@@ -99,7 +293,21 @@ fn validate_range(s: &str, lower: f32, upper: f32) -> Result<f32, String> {
 }
 
 impl OAIRequestParams {
-  pub fn update(&self, request: &mut CreateChatCompletionRequest) {
+  /// Sets the params not set in the request, the llama.cpp samplers are set as the request extensions
+  pub fn update(&self, request: &mut ChatCompletionRequest) {
+    update_extension_if_none(TOP_K, &self.top_k, &mut request.extensions);
+    update_extension_if_none(MIN_P, &self.min_p, &mut request.extensions);
+    update_extension_if_none(
+      REPEAT_PENALTY,
+      &self.repeat_penalty,
+      &mut request.extensions,
+    );
+    update_extension_if_none(REPEAT_LAST_N, &self.repeat_last_n, &mut request.extensions);
+    update_extension_if_none(TYPICAL_P, &self.typical_p, &mut request.extensions);
+    update_extension_if_none(MIROSTAT, &self.mirostat, &mut request.extensions);
+    update_extension_if_none(MIROSTAT_TAU, &self.mirostat_tau, &mut request.extensions);
+    update_extension_if_none(MIROSTAT_ETA, &self.mirostat_eta, &mut request.extensions);
+    let request = &mut request.request;
     update_if_none(&self.frequency_penalty, &mut request.frequency_penalty);
     update_if_none(&self.max_tokens, &mut request.max_tokens);
     update_if_none(&self.presence_penalty, &mut request.presence_penalty);
@@ -118,3 +326,159 @@ fn update_if_none<T: Clone>(self_param: &Option<T>, request_param: &mut Option<T
     request_param.clone_from(self_param);
   }
 }
+
+fn update_extension_if_none<T: Into<Value> + Clone>(
+  key: &str,
+  self_param: &Option<T>,
+  extensions: &mut Map<String, Value>,
+) {
+  if let Some(value) = self_param {
+    extensions
+      .entry(key)
+      .or_insert_with(|| value.clone().into());
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{ChatCompletionRequest, OAIRequestParams};
+  use crate::objs::RequestPriority;
+  use rstest::rstest;
+  use serde_json::json;
+
+  #[rstest]
+  fn test_chat_completion_request_keeps_vendor_extensions() -> anyhow::Result<()> {
+    let request = serde_json::from_value::<ChatCompletionRequest>(json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+      "return_tokens": true,
+      "unsupported_param": 1,
+    }})?;
+    assert_eq!("testalias:instruct", request.model);
+    assert_eq!(Some(&json!(true)), request.extensions.get("return_tokens"));
+    assert!(!request.extensions.contains_key("unsupported_param"));
+    let value = serde_json::to_value(&request)?;
+    let expected = json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+      "return_tokens": true,
+    }};
+    assert_eq!(expected, value);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(json!(30), Some(30))]
+  #[case(json!("30"), None)]
+  #[case(json!(null), None)]
+  fn test_chat_completion_request_timeout_secs(
+    #[case] timeout_secs: serde_json::Value,
+    #[case] expected: Option<u64>,
+  ) -> anyhow::Result<()> {
+    let request = serde_json::from_value::<ChatCompletionRequest>(json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+      "timeout_secs": timeout_secs,
+    }})?;
+    assert_eq!(expected, request.timeout_secs());
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(json!("batch"), RequestPriority::Batch)]
+  #[case(json!("interactive"), RequestPriority::Interactive)]
+  #[case(json!("urgent"), RequestPriority::Interactive)]
+  fn test_chat_completion_request_priority(
+    #[case] priority: serde_json::Value,
+    #[case] expected: RequestPriority,
+  ) -> anyhow::Result<()> {
+    let mut request = serde_json::from_value::<ChatCompletionRequest>(json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+      "priority": priority,
+    }})?;
+    assert_eq!(expected, request.priority());
+    request.set_priority(RequestPriority::Batch);
+    assert_eq!(RequestPriority::Batch, request.priority());
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_chat_completion_request_keeps_sampler_extensions() -> anyhow::Result<()> {
+    let request = serde_json::from_value::<ChatCompletionRequest>(json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+      "top_k": 40,
+      "min_p": 0.05,
+      "repeat_penalty": 1.1,
+      "repeat_last_n": 64,
+      "typical_p": 0.9,
+    }})?;
+    let value = serde_json::to_value(&request)?;
+    assert_eq!(json!(40), value["top_k"]);
+    assert_eq!(json!(0.05), value["min_p"]);
+    assert_eq!(json!(1.1), value["repeat_penalty"]);
+    assert_eq!(json!(64), value["repeat_last_n"]);
+    assert_eq!(json!(0.9), value["typical_p"]);
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_oai_request_params_update_sets_samplers_not_in_request() -> anyhow::Result<()> {
+    let mut request = serde_json::from_value::<ChatCompletionRequest>(json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+      "top_k": 10,
+    }})?;
+    let params = OAIRequestParams {
+      temperature: Some(0.7),
+      top_k: Some(40),
+      repeat_penalty: Some(1.1),
+      mirostat: Some(2),
+      ..Default::default()
+    };
+    params.update(&mut request);
+    assert_eq!(Some(0.7), request.temperature);
+    assert_eq!(Some(&json!(10)), request.extensions.get("top_k"));
+    assert_eq!(
+      Some(&json!(1.1f32)),
+      request.extensions.get("repeat_penalty")
+    );
+    assert_eq!(Some(&json!(2)), request.extensions.get("mirostat"));
+    assert!(!request.extensions.contains_key("min_p"));
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(json!({}), None)]
+  #[case(json!({"15043": -100, "29871": 5, "100": 0.5}), Some(json!([[100, 0.5], [15043, false], [29871, 5.0]])))]
+  fn test_chat_completion_request_llama_logit_bias(
+    #[case] logit_bias: serde_json::Value,
+    #[case] expected: Option<serde_json::Value>,
+  ) -> anyhow::Result<()> {
+    let request = serde_json::from_value::<ChatCompletionRequest>(json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+      "logit_bias": logit_bias,
+    }})?;
+    assert_eq!(Ok(expected), request.llama_logit_bias());
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(json!({"hello": 5}), "logit_bias key 'hello' should be a token id")]
+  #[case(json!({"15043": -101}), "logit_bias of token 15043 should be between -100 and 100")]
+  #[case(json!({"15043": "ban"}), "logit_bias of token 15043 should be between -100 and 100")]
+  fn test_chat_completion_request_llama_logit_bias_invalid(
+    #[case] logit_bias: serde_json::Value,
+    #[case] expected: &str,
+  ) -> anyhow::Result<()> {
+    let request = serde_json::from_value::<ChatCompletionRequest>(json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+      "logit_bias": logit_bias,
+    }})?;
+    assert_eq!(Err(expected.to_string()), request.llama_logit_bias());
+    Ok(())
+  }
+}
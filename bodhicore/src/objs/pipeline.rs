@@ -0,0 +1,114 @@
+use super::ChatCompletionRequest;
+use async_openai::types::{ChatCompletionRequestMessage, ChatCompletionRequestSystemMessage, Role};
+use serde::{Deserialize, Serialize};
+
+pub static PIPELINE_PREFIX: &str = "pipeline:";
+
+/// Named preset, configured in `$BODHI_HOME/pipelines/<name>.yaml` and used as the model
+/// `pipeline:<name>` in the chat completions request.
+/// Composes a model alias with a system prompt, unknown keys are rejected so a pipeline
+/// expecting a RAG collection, tools or moderation does not silently run without them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pipeline {
+  /// name of the pipeline, taken from the yaml filename
+  #[serde(default, skip_serializing)]
+  pub name: String,
+  /// model alias to run the request with
+  pub alias: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub system_prompt: Option<String>,
+}
+
+impl Pipeline {
+  /// Name of the pipeline if the model is addressed as `pipeline:<name>`
+  pub fn name_of(model: &str) -> Option<&str> {
+    model.strip_prefix(PIPELINE_PREFIX)
+  }
+
+  /// Points the request to the alias of the pipeline, and prepends the system prompt.
+  /// The system prompt is merged into the system message of the request if present,
+  /// as chat templates of some models allow only a single system message.
+  pub fn apply(&self, request: &mut ChatCompletionRequest) {
+    request.model.clone_from(&self.alias);
+    let Some(system_prompt) = &self.system_prompt else {
+      return;
+    };
+    match request.messages.first_mut() {
+      Some(ChatCompletionRequestMessage::System(message)) => {
+        message.content = format!("{system_prompt}\n\n{}", message.content);
+      }
+      _ => request.messages.insert(
+        0,
+        ChatCompletionRequestMessage::System(ChatCompletionRequestSystemMessage {
+          content: system_prompt.clone(),
+          role: Role::System,
+          name: None,
+        }),
+      ),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::Pipeline;
+  use crate::objs::ChatCompletionRequest;
+  use rstest::rstest;
+  use serde_json::{json, Value};
+
+  fn pipeline() -> Pipeline {
+    Pipeline {
+      name: "docs-assistant".to_string(),
+      alias: "testalias:instruct".to_string(),
+      system_prompt: Some("Answer from the docs.".to_string()),
+    }
+  }
+
+  #[rstest]
+  #[case("pipeline:docs-assistant", Some("docs-assistant"))]
+  #[case("testalias:instruct", None)]
+  fn test_pipeline_name_of(#[case] model: &str, #[case] expected: Option<&str>) {
+    assert_eq!(expected, Pipeline::name_of(model));
+  }
+
+  #[rstest]
+  #[case(
+    json! {[{"role": "user", "content": "What is Bodhi?"}]},
+    json! {[
+      {"role": "system", "content": "Answer from the docs."},
+      {"role": "user", "content": "What is Bodhi?"}
+    ]}
+  )]
+  #[case(
+    json! {[
+      {"role": "system", "content": "Be brief."},
+      {"role": "user", "content": "What is Bodhi?"}
+    ]},
+    json! {[
+      {"role": "system", "content": "Answer from the docs.\n\nBe brief."},
+      {"role": "user", "content": "What is Bodhi?"}
+    ]}
+  )]
+  fn test_pipeline_apply(#[case] messages: Value, #[case] expected: Value) -> anyhow::Result<()> {
+    let mut request = serde_json::from_value::<ChatCompletionRequest>(json! {{
+      "model": "pipeline:docs-assistant",
+      "messages": messages
+    }})?;
+    pipeline().apply(&mut request);
+    assert_eq!("testalias:instruct", request.model);
+    assert_eq!(expected, serde_json::to_value(&request.messages)?);
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_pipeline_rejects_unsupported_keys() -> anyhow::Result<()> {
+    let result =
+      serde_yaml::from_str::<Pipeline>("alias: testalias:instruct\nrag:\n  collection: docs\n");
+    assert!(result
+      .unwrap_err()
+      .to_string()
+      .contains("unknown field `rag`"));
+    Ok(())
+  }
+}
@@ -0,0 +1,62 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
+
+/// Scheduling class of an inference request, set by the API key making it, or the
+/// `x-bodhi-priority` header. The batch requests wait while interactive requests are using the
+/// model, so the background jobs only use its idle time.
+#[derive(
+  Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Display, EnumString, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum RequestPriority {
+  /// chats and other requests with a user waiting for the response
+  #[default]
+  Interactive,
+  /// evaluations, batches and other background jobs
+  Batch,
+}
+
+impl RequestPriority {
+  /// The lower of the two priorities, so a request cannot raise the priority of its API key
+  pub fn min(self, other: RequestPriority) -> RequestPriority {
+    if self == RequestPriority::Batch || other == RequestPriority::Batch {
+      RequestPriority::Batch
+    } else {
+      RequestPriority::Interactive
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::RequestPriority;
+  use rstest::rstest;
+
+  #[rstest]
+  #[case(
+    RequestPriority::Interactive,
+    RequestPriority::Interactive,
+    RequestPriority::Interactive
+  )]
+  #[case(
+    RequestPriority::Interactive,
+    RequestPriority::Batch,
+    RequestPriority::Batch
+  )]
+  #[case(
+    RequestPriority::Batch,
+    RequestPriority::Interactive,
+    RequestPriority::Batch
+  )]
+  fn test_request_priority_min(
+    #[case] key: RequestPriority,
+    #[case] request: RequestPriority,
+    #[case] expected: RequestPriority,
+  ) -> anyhow::Result<()> {
+    assert_eq!(expected, key.min(request));
+    assert_eq!(RequestPriority::Batch, "BATCH".parse::<RequestPriority>()?);
+    Ok(())
+  }
+}
@@ -0,0 +1,306 @@
+use super::is_default;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// The tags wrapping the reasoning of the thinking models in the generated text, e.g. `<think>`
+/// and `</think>` of DeepSeek-R1. The reasoning is returned as the `reasoning_content` of the
+/// message, instead of as part of its `content`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ReasoningFormat {
+  pub start: String,
+  pub end: String,
+  /// the chat template opens the reasoning at the end of the prompt, so the generated text
+  /// starts with the reasoning, without the opening tag
+  #[serde(default, skip_serializing_if = "is_default")]
+  pub in_prompt: bool,
+}
+
+impl ReasoningFormat {
+  pub fn think(in_prompt: bool) -> Self {
+    ReasoningFormat {
+      start: "<think>".to_string(),
+      end: "</think>".to_string(),
+      in_prompt,
+    }
+  }
+}
+
+/// Splits the generated text into the reasoning and the content, as it is generated. The text
+/// that may be the start of a tag is held back till the next text tells.
+#[derive(Debug, Clone)]
+pub struct ReasoningParser {
+  format: ReasoningFormat,
+  reasoning: bool,
+  pending: String,
+  // the whitespace after the closing tag is not part of the content
+  trim_content: bool,
+}
+
+impl ReasoningParser {
+  pub fn new(format: ReasoningFormat) -> Self {
+    ReasoningParser {
+      reasoning: format.in_prompt,
+      format,
+      pending: String::new(),
+      trim_content: false,
+    }
+  }
+
+  /// The reasoning and the content in the text generated since the last call
+  pub fn push(&mut self, text: &str) -> (String, String) {
+    let mut reasoning = String::new();
+    let mut content = String::new();
+    let mut text = std::mem::take(&mut self.pending) + text;
+    loop {
+      let tag = if self.reasoning {
+        &self.format.end
+      } else {
+        &self.format.start
+      };
+      let (found, rest) = match text.find(tag.as_str()) {
+        Some(index) => (
+          text[..index].to_string(),
+          Some(text[index + tag.len()..].to_string()),
+        ),
+        None => {
+          let held = partial_tag_len(&text, tag);
+          self.pending = text[text.len() - held..].to_string();
+          (text[..text.len() - held].to_string(), None)
+        }
+      };
+      if self.reasoning {
+        reasoning.push_str(&found);
+      } else {
+        self.push_content(&mut content, &found);
+      }
+      match rest {
+        Some(rest) => {
+          self.trim_content = self.reasoning;
+          self.reasoning = !self.reasoning;
+          text = rest;
+        }
+        None => return (reasoning, content),
+      }
+    }
+  }
+
+  /// The text held back once the generation is done
+  pub fn finish(&mut self) -> (String, String) {
+    let pending = std::mem::take(&mut self.pending);
+    if self.reasoning {
+      (pending, String::new())
+    } else {
+      let mut content = String::new();
+      self.push_content(&mut content, &pending);
+      (String::new(), content)
+    }
+  }
+
+  fn push_content(&mut self, content: &mut String, text: &str) {
+    let text = if self.trim_content {
+      text.trim_start()
+    } else {
+      text
+    };
+    if !text.is_empty() {
+      self.trim_content = false;
+    }
+    content.push_str(text);
+  }
+}
+
+// the length of the longest suffix of the text that is the start of the tag
+fn partial_tag_len(text: &str, tag: &str) -> usize {
+  (1..tag.len().min(text.len() + 1))
+    .rev()
+    .find(|len| {
+      text.is_char_boundary(text.len() - len) && tag.starts_with(&text[text.len() - len..])
+    })
+    .unwrap_or(0)
+}
+
+/// Moves the reasoning out of the `content` of the chat completion responses into their
+/// `reasoning_content`, for the response and each of the chunks of a stream
+#[derive(Debug, Clone)]
+pub struct ReasoningSplitter {
+  format: ReasoningFormat,
+  // by the index of the choice
+  parsers: HashMap<u64, ReasoningParser>,
+}
+
+impl ReasoningSplitter {
+  pub fn new(format: ReasoningFormat) -> Self {
+    ReasoningSplitter {
+      format,
+      parsers: HashMap::new(),
+    }
+  }
+
+  /// The message sent by the context, with the reasoning split out of the content. The messages
+  /// other than the responses and the chunks are returned as is.
+  pub fn split(&mut self, message: String) -> String {
+    let (prefix, data) = match message.strip_prefix("data: ") {
+      Some(data) => ("data: ", data),
+      None => ("", message.as_str()),
+    };
+    let Ok(mut value) = serde_json::from_str::<Value>(data.trim()) else {
+      return message;
+    };
+    let Some(choices) = value.get_mut("choices").and_then(Value::as_array_mut) else {
+      return message;
+    };
+    for choice in choices {
+      let index = choice.get("index").and_then(Value::as_u64).unwrap_or(0);
+      let done = choice
+        .get("finish_reason")
+        .is_some_and(|finish_reason| !finish_reason.is_null());
+      let key = if choice.get("message").is_some() {
+        "message"
+      } else {
+        "delta"
+      };
+      let Some(Value::Object(delta)) = choice.get_mut(key) else {
+        continue;
+      };
+      let parser = self
+        .parsers
+        .entry(index)
+        .or_insert_with(|| ReasoningParser::new(self.format.clone()));
+      let text = delta.get("content").and_then(Value::as_str).unwrap_or("");
+      let (mut reasoning, mut content) = parser.push(text);
+      // the whole response is in the message, and the stream ends with the finish reason
+      if key == "message" || done {
+        let (rest_reasoning, rest_content) = parser.finish();
+        reasoning.push_str(&rest_reasoning);
+        content.push_str(&rest_content);
+      }
+      if !reasoning.is_empty() {
+        delta.insert("reasoning_content".to_string(), Value::String(reasoning));
+        if content.is_empty() {
+          delta.insert("content".to_string(), Value::Null);
+          continue;
+        }
+      }
+      if delta.contains_key("content") || !content.is_empty() {
+        delta.insert("content".to_string(), Value::String(content));
+      }
+    }
+    let Ok(data) = serde_json::to_string(&value) else {
+      return message;
+    };
+    if prefix.is_empty() {
+      data
+    } else {
+      format!("{prefix}{data}\n\n")
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{ReasoningFormat, ReasoningParser, ReasoningSplitter};
+  use rstest::rstest;
+  use serde_json::{json, Value};
+
+  #[rstest]
+  #[case(false, vec!["<think>Monday", " is first</thi", "nk>\n\nTuesday"], "Monday is first", "Tuesday")]
+  #[case(true, vec!["Monday is first", "</", "think>", " Tuesday"], "Monday is first", "Tuesday")]
+  #[case(false, vec!["Tuesday <", "b>comes next</b>"], "", "Tuesday <b>comes next</b>")]
+  #[case(false, vec!["Tuesday <thi"], "", "Tuesday <thi")]
+  #[case(true, vec!["still thinking </thi"], "still thinking </thi", "")]
+  fn test_reasoning_parser_splits_across_chunks(
+    #[case] in_prompt: bool,
+    #[case] chunks: Vec<&str>,
+    #[case] expected_reasoning: &str,
+    #[case] expected_content: &str,
+  ) {
+    let mut parser = ReasoningParser::new(ReasoningFormat::think(in_prompt));
+    let mut reasoning = String::new();
+    let mut content = String::new();
+    for chunk in chunks {
+      let (r, c) = parser.push(chunk);
+      reasoning.push_str(&r);
+      content.push_str(&c);
+    }
+    let (r, c) = parser.finish();
+    reasoning.push_str(&r);
+    content.push_str(&c);
+    assert_eq!(expected_reasoning, reasoning);
+    assert_eq!(expected_content, content);
+  }
+
+  #[rstest]
+  fn test_reasoning_splitter_splits_the_response() -> anyhow::Result<()> {
+    let mut splitter = ReasoningSplitter::new(ReasoningFormat::think(true));
+    let response = json! {{
+      "object": "chat.completion",
+      "choices": [{
+        "index": 0,
+        "message": {"role": "assistant", "content": "Monday is first</think>\n\nTuesday"},
+        "finish_reason": "stop"
+      }]
+    }};
+    let result = splitter.split(response.to_string());
+    let result = serde_json::from_str::<Value>(&result)?;
+    assert_eq!(
+      json! {{"role": "assistant", "content": "Tuesday", "reasoning_content": "Monday is first"}},
+      result["choices"][0]["message"]
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_reasoning_splitter_splits_the_chunks() -> anyhow::Result<()> {
+    let mut splitter = ReasoningSplitter::new(ReasoningFormat::think(true));
+    let chunk = |delta: Value, finish_reason: Value| {
+      let chunk = json! {{
+        "object": "chat.completion.chunk",
+        "choices": [{"index": 0, "delta": delta, "finish_reason": finish_reason}]
+      }};
+      format!("data: {chunk}\n\n")
+    };
+    let deltas = [
+      splitter.split(chunk(
+        json! {{"role": "assistant", "content": "Monday"}},
+        Value::Null,
+      )),
+      splitter.split(chunk(json! {{"content": "</think>\n\nTues"}}, Value::Null)),
+      splitter.split(chunk(json! {{"content": "day"}}, Value::Null)),
+      splitter.split(chunk(json! {{}}, json!("stop"))),
+      splitter.split("data: [DONE]\n\n".to_string()),
+    ];
+    let deltas = deltas[..4]
+      .iter()
+      .map(|message| {
+        let data = message
+          .strip_prefix("data: ")
+          .expect("chunk to be an event")
+          .trim();
+        let value = serde_json::from_str::<Value>(data)?;
+        Ok(value["choices"][0]["delta"].clone())
+      })
+      .collect::<anyhow::Result<Vec<_>>>()?;
+    assert_eq!(
+      vec![
+        json! {{"role": "assistant", "content": null, "reasoning_content": "Monday"}},
+        json! {{"content": "Tues"}},
+        json! {{"content": "day"}},
+        json! {{}},
+      ],
+      deltas
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_reasoning_splitter_passes_the_other_messages() {
+    let mut splitter = ReasoningSplitter::new(ReasoningFormat::think(false));
+    assert_eq!(
+      "data: [DONE]\n\n",
+      splitter.split("data: [DONE]\n\n".to_string())
+    );
+    let error = "error: {\"error\":{\"message\":\"failed\"}}\n\n";
+    assert_eq!(error, splitter.split(error.to_string()));
+  }
+}
@@ -1,10 +1,10 @@
 use super::{gpt_params::GptContextParams, ChatTemplate, OAIRequestParams, Repo};
 use derive_new::new;
 use prettytable::Row;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 #[allow(clippy::too_many_arguments)]
-#[derive(Debug, Deserialize, PartialEq, Clone, PartialOrd, new)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, PartialOrd, new)]
 #[cfg_attr(test, derive(Default))]
 pub struct RemoteModel {
   pub alias: String,
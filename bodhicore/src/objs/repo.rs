@@ -10,6 +10,7 @@ pub static TOKENIZER_CONFIG_JSON: &str = "tokenizer_config.json";
 pub static GGUF_EXTENSION: &str = ".gguf";
 pub static REFS: &str = "refs";
 pub static REFS_MAIN: &str = "refs/main";
+pub static DEFAULT_REVISION: &str = "main";
 pub static REGEX_REPO: Lazy<Regex> =
   Lazy::new(|| Regex::new(r"^[a-zA-Z0-9_.-]+/[a-zA-Z0-9_.-]+$").unwrap());
 
@@ -47,6 +48,20 @@ impl Repo {
   }
 }
 
+/// The snapshot to look up a revision in $HF_HOME, commit shas are snapshots themselves,
+/// branches and tags are resolved using `refs/<revision>`
+pub fn revision_snapshot(revision: &str) -> String {
+  if is_commit_sha(revision) {
+    revision.to_string()
+  } else {
+    format!("{REFS}/{revision}")
+  }
+}
+
+pub fn is_commit_sha(revision: &str) -> bool {
+  revision.len() == 40 && revision.chars().all(|c| c.is_ascii_hexdigit())
+}
+
 impl Display for Repo {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     self.value.fmt(f)
@@ -83,7 +98,7 @@ impl Deref for Repo {
 
 #[cfg(test)]
 mod test {
-  use super::Repo;
+  use super::{revision_snapshot, Repo};
   use anyhow_trace::anyhow_trace;
   use rstest::rstest;
   use validator::Validate;
@@ -113,4 +128,16 @@ mod test {
     );
     Ok(())
   }
+
+  #[rstest]
+  #[case("main", "refs/main")]
+  #[case("v1.0", "refs/v1.0")]
+  #[case("pr/1", "refs/pr/1")]
+  #[case(
+    "e9149a12809580e8602995856f8098ce973d1080",
+    "e9149a12809580e8602995856f8098ce973d1080"
+  )]
+  fn test_repo_revision_snapshot(#[case] revision: &str, #[case] expected: &str) {
+    assert_eq!(expected, revision_snapshot(revision));
+  }
 }
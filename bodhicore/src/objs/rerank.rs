@@ -0,0 +1,238 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Document to rerank, either the text or an object with the text, as accepted by Cohere and Jina
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RerankDocument {
+  Text(String),
+  Object { text: String },
+}
+
+impl RerankDocument {
+  pub fn text(&self) -> &str {
+    match self {
+      RerankDocument::Text(text) => text,
+      RerankDocument::Object { text } => text,
+    }
+  }
+}
+
+/// Request of `POST /v1/rerank`, in the shape used by the Cohere and Jina rerank APIs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RerankRequest {
+  pub model: String,
+  pub query: String,
+  pub documents: Vec<RerankDocument>,
+  /// number of the most relevant documents to return, all the documents if not set
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub top_n: Option<usize>,
+  /// returns the text of the documents along with their scores
+  #[serde(default)]
+  pub return_documents: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RerankResultDocument {
+  pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RerankResult {
+  /// index of the document in the request
+  pub index: usize,
+  pub relevance_score: f64,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub document: Option<RerankResultDocument>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RerankUsage {
+  #[serde(default)]
+  pub total_tokens: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RerankResponse {
+  pub id: String,
+  pub model: String,
+  pub results: Vec<RerankResult>,
+  pub usage: RerankUsage,
+}
+
+/// Scores of the documents, in the order of the documents, as returned by the llama.cpp server context
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RerankScores {
+  pub results: Vec<RerankScore>,
+  #[serde(default)]
+  pub usage: RerankUsage,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RerankScore {
+  pub index: usize,
+  pub relevance_score: f64,
+}
+
+impl RerankRequest {
+  /// Error message if the request cannot be reranked
+  pub fn validate(&self) -> Result<(), String> {
+    if self.query.trim().is_empty() {
+      return Err("query should not be empty".to_string());
+    }
+    if self.documents.is_empty() {
+      return Err("documents should not be empty".to_string());
+    }
+    if self.top_n == Some(0) {
+      return Err("top_n should be greater than 0".to_string());
+    }
+    Ok(())
+  }
+
+  /// Input of the llama.cpp server context, with the documents as plain text
+  pub fn llama_input(&self) -> Value {
+    let documents = self
+      .documents
+      .iter()
+      .map(RerankDocument::text)
+      .collect::<Vec<_>>();
+    json! {{"query": self.query, "documents": documents}}
+  }
+
+  /// Results sorted by the relevance score, most relevant first, limited to `top_n`
+  pub fn into_response(self, id: String, scores: RerankScores) -> RerankResponse {
+    let mut results = scores
+      .results
+      .into_iter()
+      .filter(|score| score.index < self.documents.len())
+      .map(|score| RerankResult {
+        index: score.index,
+        relevance_score: score.relevance_score,
+        document: self.return_documents.then(|| RerankResultDocument {
+          text: self.documents[score.index].text().to_string(),
+        }),
+      })
+      .collect::<Vec<_>>();
+    results.sort_by(|a, b| b.relevance_score.total_cmp(&a.relevance_score));
+    if let Some(top_n) = self.top_n {
+      results.truncate(top_n);
+    }
+    RerankResponse {
+      id,
+      model: self.model,
+      results,
+      usage: scores.usage,
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{RerankDocument, RerankRequest, RerankResultDocument, RerankScore, RerankScores};
+  use rstest::rstest;
+  use serde_json::json;
+
+  fn request(top_n: Option<usize>, return_documents: bool) -> RerankRequest {
+    RerankRequest {
+      model: "bge-reranker:v2".to_string(),
+      query: "What is the capital of France?".to_string(),
+      documents: vec![
+        RerankDocument::Text("Berlin is the capital of Germany".to_string()),
+        RerankDocument::Object {
+          text: "Paris is the capital of France".to_string(),
+        },
+        RerankDocument::Text("France is in Europe".to_string()),
+      ],
+      top_n,
+      return_documents,
+    }
+  }
+
+  fn scores() -> RerankScores {
+    RerankScores {
+      results: vec![
+        RerankScore {
+          index: 0,
+          relevance_score: -2.5,
+        },
+        RerankScore {
+          index: 1,
+          relevance_score: 7.25,
+        },
+        RerankScore {
+          index: 2,
+          relevance_score: 1.5,
+        },
+      ],
+      usage: Default::default(),
+    }
+  }
+
+  #[rstest]
+  fn test_rerank_request_accepts_text_and_object_documents() -> anyhow::Result<()> {
+    let actual = serde_json::from_value::<RerankRequest>(json! {{
+      "model": "bge-reranker:v2",
+      "query": "What is the capital of France?",
+      "documents": [
+        "Berlin is the capital of Germany",
+        {"text": "Paris is the capital of France"},
+        "France is in Europe"
+      ]
+    }})?;
+    assert_eq!(request(None, false), actual);
+    assert_eq!(
+      json! {{
+        "query": "What is the capital of France?",
+        "documents": ["Berlin is the capital of Germany", "Paris is the capital of France", "France is in Europe"]
+      }},
+      actual.llama_input()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(None, vec![1, 2, 0])]
+  #[case(Some(2), vec![1, 2])]
+  #[case(Some(10), vec![1, 2, 0])]
+  fn test_rerank_request_into_response_sorted_by_score(
+    #[case] top_n: Option<usize>,
+    #[case] expected: Vec<usize>,
+  ) {
+    let response = request(top_n, false).into_response("rerank-test".to_string(), scores());
+    let actual = response
+      .results
+      .iter()
+      .map(|result| result.index)
+      .collect::<Vec<_>>();
+    assert_eq!(expected, actual);
+    assert!(response
+      .results
+      .iter()
+      .all(|result| result.document.is_none()));
+    assert_eq!("bge-reranker:v2", response.model);
+  }
+
+  #[rstest]
+  fn test_rerank_request_into_response_returns_documents() {
+    let response = request(Some(1), true).into_response("rerank-test".to_string(), scores());
+    assert_eq!(
+      Some(RerankResultDocument {
+        text: "Paris is the capital of France".to_string()
+      }),
+      response.results[0].document
+    );
+  }
+
+  #[rstest]
+  #[case(json! {{"model": "m", "query": " ", "documents": ["a"]}}, "query should not be empty")]
+  #[case(json! {{"model": "m", "query": "q", "documents": []}}, "documents should not be empty")]
+  #[case(json! {{"model": "m", "query": "q", "documents": ["a"], "top_n": 0}}, "top_n should be greater than 0")]
+  fn test_rerank_request_validate(
+    #[case] input: serde_json::Value,
+    #[case] expected: &str,
+  ) -> anyhow::Result<()> {
+    let request = serde_json::from_value::<RerankRequest>(input)?;
+    assert_eq!(Err(expected.to_string()), request.validate());
+    Ok(())
+  }
+}
@@ -0,0 +1,126 @@
+use super::ObjError;
+use chrono::{DateTime, FixedOffset, Local, Utc};
+use chrono_tz::Tz;
+use std::{fmt::Display, str::FromStr};
+
+pub static DEFAULT_TIMEZONE: &str = "local";
+pub static DISPLAY_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S %:z";
+
+/// Timezone to render the timestamps in for the user, timestamps are always stored in UTC.
+/// Parsed from `local`, `UTC`, an IANA name like `Asia/Kolkata`, or a fixed offset like `+05:30`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DisplayTimezone {
+  /// timezone of the system running bodhi
+  #[default]
+  Local,
+  Utc,
+  Named(Tz),
+  Offset(FixedOffset),
+}
+
+impl DisplayTimezone {
+  pub fn format(&self, timestamp: &DateTime<Utc>, fmt: &str) -> String {
+    match self {
+      DisplayTimezone::Local => timestamp.with_timezone(&Local).format(fmt).to_string(),
+      DisplayTimezone::Utc => timestamp.format(fmt).to_string(),
+      DisplayTimezone::Named(tz) => timestamp.with_timezone(tz).format(fmt).to_string(),
+      DisplayTimezone::Offset(offset) => timestamp.with_timezone(offset).format(fmt).to_string(),
+    }
+  }
+
+  pub fn display(&self, timestamp: &DateTime<Utc>) -> String {
+    self.format(timestamp, DISPLAY_TIME_FORMAT)
+  }
+}
+
+impl FromStr for DisplayTimezone {
+  type Err = ObjError;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    let value = value.trim();
+    if value.eq_ignore_ascii_case(DEFAULT_TIMEZONE) {
+      return Ok(DisplayTimezone::Local);
+    }
+    if value.eq_ignore_ascii_case("utc") || value == "Z" {
+      return Ok(DisplayTimezone::Utc);
+    }
+    if value.starts_with('+') || value.starts_with('-') {
+      return value
+        .parse::<FixedOffset>()
+        .map(DisplayTimezone::Offset)
+        .map_err(|err| ObjError::Conversion {
+          from: value.to_string(),
+          to: "timezone".to_string(),
+          error: err.to_string(),
+        });
+    }
+    value
+      .parse::<Tz>()
+      .map(DisplayTimezone::Named)
+      .map_err(|err| ObjError::Conversion {
+        from: value.to_string(),
+        to: "timezone".to_string(),
+        error: err.to_string(),
+      })
+  }
+}
+
+impl Display for DisplayTimezone {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      DisplayTimezone::Local => write!(f, "{DEFAULT_TIMEZONE}"),
+      DisplayTimezone::Utc => write!(f, "UTC"),
+      DisplayTimezone::Named(tz) => write!(f, "{}", tz.name()),
+      DisplayTimezone::Offset(offset) => write!(f, "{offset}"),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::DisplayTimezone;
+  use chrono::{FixedOffset, TimeZone, Utc};
+  use rstest::rstest;
+
+  #[rstest]
+  #[case("local", DisplayTimezone::Local)]
+  #[case("UTC", DisplayTimezone::Utc)]
+  #[case("utc", DisplayTimezone::Utc)]
+  #[case("Asia/Kolkata", DisplayTimezone::Named(chrono_tz::Asia::Kolkata))]
+  #[case("+05:30", DisplayTimezone::Offset(FixedOffset::east_opt(5 * 3600 + 30 * 60).unwrap()))]
+  fn test_display_timezone_parse(
+    #[case] input: &str,
+    #[case] expected: DisplayTimezone,
+  ) -> anyhow::Result<()> {
+    assert_eq!(expected, input.parse::<DisplayTimezone>()?);
+    assert_eq!(expected, expected.to_string().parse::<DisplayTimezone>()?);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("Mars/Olympus")]
+  #[case("+25:00")]
+  fn test_display_timezone_parse_invalid(#[case] input: &str) -> anyhow::Result<()> {
+    assert!(input.parse::<DisplayTimezone>().is_err());
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(DisplayTimezone::Utc, "2024-05-01 10:30:00 +00:00")]
+  #[case(
+    DisplayTimezone::Named(chrono_tz::Asia::Kolkata),
+    "2024-05-01 16:00:00 +05:30"
+  )]
+  #[case(
+    DisplayTimezone::Named(chrono_tz::America::New_York),
+    "2024-05-01 06:30:00 -04:00"
+  )]
+  fn test_display_timezone_display(
+    #[case] tz: DisplayTimezone,
+    #[case] expected: &str,
+  ) -> anyhow::Result<()> {
+    let timestamp = Utc.with_ymd_and_hms(2024, 5, 1, 10, 30, 0).unwrap();
+    assert_eq!(expected, tz.display(&timestamp));
+    Ok(())
+  }
+}
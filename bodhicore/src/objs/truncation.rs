@@ -0,0 +1,227 @@
+use async_openai::types::{
+  ChatCompletionRequestMessage, ChatCompletionRequestMessageContentPart,
+  ChatCompletionRequestUserMessageContent,
+};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
+
+/// What to do when the chat history, rendered using the chat template, does not fit in the
+/// context of the alias
+#[derive(
+  Debug,
+  Clone,
+  Copy,
+  PartialEq,
+  PartialOrd,
+  Default,
+  ValueEnum,
+  Display,
+  EnumString,
+  Serialize,
+  Deserialize,
+)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum Truncation {
+  /// fail the request with the `context_length_exceeded` error
+  #[default]
+  Error,
+  /// drop the oldest turns after the system prompt, until the history fits
+  DropOldest,
+  /// keep the system prompt, the first turn and the latest turns, replacing the turns in the
+  /// middle with a note of how many messages were left out
+  MiddleOut,
+}
+
+impl Truncation {
+  /// The messages with `dropped` of the turns left out, or None if there are not as many turns
+  /// that can be dropped. A turn is a user message with the messages following it. The leading
+  /// system messages and the latest turn are always kept.
+  pub fn truncate(
+    &self,
+    messages: &[ChatCompletionRequestMessage],
+    dropped: usize,
+  ) -> Option<Vec<ChatCompletionRequestMessage>> {
+    let system_end = messages
+      .iter()
+      .position(|message| !matches!(message, ChatCompletionRequestMessage::System(_)))
+      .unwrap_or(messages.len());
+    let (system, history) = messages.split_at(system_end);
+    let turns = turns(history);
+    match self {
+      Truncation::Error => None,
+      Truncation::DropOldest => {
+        if dropped == 0 || dropped >= turns.len() {
+          return None;
+        }
+        let mut truncated = system.to_vec();
+        truncated.extend(
+          turns[dropped..]
+            .iter()
+            .flat_map(|turn| turn.iter().cloned()),
+        );
+        Some(truncated)
+      }
+      Truncation::MiddleOut => {
+        if dropped == 0 || dropped + 2 > turns.len() {
+          return None;
+        }
+        let omitted = turns[1..=dropped]
+          .iter()
+          .map(|turn| turn.len())
+          .sum::<usize>();
+        let mut truncated = system.to_vec();
+        truncated.extend(turns[0].iter().cloned());
+        let mut kept = turns[dropped + 1..]
+          .iter()
+          .flat_map(|turn| turn.iter().cloned())
+          .collect::<Vec<_>>();
+        if let Some(ChatCompletionRequestMessage::User(message)) = kept.first_mut() {
+          add_note(
+            &mut message.content,
+            &format!("[{omitted} earlier messages were left out to fit the context]"),
+          );
+        }
+        truncated.extend(kept);
+        Some(truncated)
+      }
+    }
+  }
+}
+
+fn turns(history: &[ChatCompletionRequestMessage]) -> Vec<&[ChatCompletionRequestMessage]> {
+  let mut turns = Vec::new();
+  let mut start = 0;
+  for (index, message) in history.iter().enumerate() {
+    if index > start && matches!(message, ChatCompletionRequestMessage::User(_)) {
+      turns.push(&history[start..index]);
+      start = index;
+    }
+  }
+  if start < history.len() {
+    turns.push(&history[start..]);
+  }
+  turns
+}
+
+// the note is added to the user message, as not all the chat templates allow a system message
+// after the first one
+fn add_note(content: &mut ChatCompletionRequestUserMessageContent, note: &str) {
+  match content {
+    ChatCompletionRequestUserMessageContent::Text(text) => {
+      *text = format!("{note}\n\n{text}");
+    }
+    ChatCompletionRequestUserMessageContent::Array(parts) => {
+      if let Ok(part) = serde_json::from_value::<ChatCompletionRequestMessageContentPart>(
+        serde_json::json!({"type": "text", "text": note}),
+      ) {
+        parts.insert(0, part);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::Truncation;
+  use async_openai::types::ChatCompletionRequestMessage;
+  use rstest::rstest;
+  use serde_json::{json, Value};
+
+  fn messages() -> anyhow::Result<Vec<ChatCompletionRequestMessage>> {
+    let messages = json! {[
+      {"role": "system", "content": "You are a helpful assistant."},
+      {"role": "user", "content": "one"},
+      {"role": "assistant", "content": "1"},
+      {"role": "user", "content": "two"},
+      {"role": "assistant", "content": "2"},
+      {"role": "user", "content": "three"},
+      {"role": "assistant", "content": "3"},
+      {"role": "user", "content": "four"}
+    ]};
+    Ok(serde_json::from_value(messages)?)
+  }
+
+  fn contents(messages: Option<Vec<ChatCompletionRequestMessage>>) -> Option<Vec<String>> {
+    messages.map(|messages| {
+      messages
+        .into_iter()
+        .map(|message| {
+          serde_json::to_value(message)
+            .ok()
+            .and_then(|value| {
+              value
+                .get("content")
+                .and_then(Value::as_str)
+                .map(str::to_string)
+            })
+            .unwrap_or_default()
+        })
+        .collect()
+    })
+  }
+
+  #[rstest]
+  #[case(Truncation::Error, 1, None)]
+  #[case(Truncation::DropOldest, 0, None)]
+  #[case(
+    Truncation::DropOldest,
+    1,
+    Some(vec!["You are a helpful assistant.", "two", "2", "three", "3", "four"])
+  )]
+  #[case(
+    Truncation::DropOldest,
+    3,
+    Some(vec!["You are a helpful assistant.", "four"])
+  )]
+  #[case(Truncation::DropOldest, 4, None)]
+  #[case(
+    Truncation::MiddleOut,
+    1,
+    Some(vec![
+      "You are a helpful assistant.",
+      "one",
+      "1",
+      "[2 earlier messages were left out to fit the context]\n\nthree",
+      "3",
+      "four"
+    ])
+  )]
+  #[case(
+    Truncation::MiddleOut,
+    2,
+    Some(vec![
+      "You are a helpful assistant.",
+      "one",
+      "1",
+      "[4 earlier messages were left out to fit the context]\n\nfour"
+    ])
+  )]
+  #[case(Truncation::MiddleOut, 3, None)]
+  fn test_truncation_truncate(
+    #[case] truncation: Truncation,
+    #[case] dropped: usize,
+    #[case] expected: Option<Vec<&str>>,
+  ) -> anyhow::Result<()> {
+    let expected = expected.map(|expected| expected.into_iter().map(str::to_string).collect());
+    assert_eq!(
+      expected,
+      contents(truncation.truncate(&messages()?, dropped))
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("error", Truncation::Error)]
+  #[case("drop-oldest", Truncation::DropOldest)]
+  #[case("middle-out", Truncation::MiddleOut)]
+  fn test_truncation_parse(
+    #[case] input: &str,
+    #[case] expected: Truncation,
+  ) -> anyhow::Result<()> {
+    assert_eq!(expected, input.parse::<Truncation>()?);
+    assert_eq!(expected, serde_yaml::from_str::<Truncation>(input)?);
+    Ok(())
+  }
+}
@@ -0,0 +1,235 @@
+use crate::error::Common;
+use axum::{
+  body::{to_bytes, Body},
+  extract::{Request, State},
+  http::{header, HeaderMap, Method, StatusCode},
+  middleware::Next,
+  response::Response,
+};
+use chrono::{SecondsFormat, Utc};
+use futures_util::StreamExt;
+use serde_json::Value;
+use std::{
+  fs::{File, OpenOptions},
+  io::Write,
+  path::{Path, PathBuf},
+  sync::{Arc, Mutex},
+  time::Instant,
+};
+
+pub static ACCESS_LOG_FILE: &str = "access.log";
+
+/// Access log written to `$BODHI_LOGS/access.log` when `BODHI_ACCESS_LOG` is enabled,
+/// one line per request, separate from the application logs:
+///
+/// `<timestamp> <method> <path> <status> <duration>ms <bytes>B <model> <stream y/n>`
+///
+/// The model is `-` for requests without a json body having a `model`
+#[derive(Debug)]
+pub struct AccessLog {
+  path: PathBuf,
+  file: Mutex<File>,
+}
+
+impl AccessLog {
+  pub fn open(logs_dir: &Path) -> Result<Self, Common> {
+    let path = logs_dir.join(ACCESS_LOG_FILE);
+    let file = OpenOptions::new()
+      .create(true)
+      .append(true)
+      .open(&path)
+      .map_err(|source| Common::IoFile {
+        source,
+        path: path.display().to_string(),
+      })?;
+    Ok(Self {
+      path,
+      file: Mutex::new(file),
+    })
+  }
+
+  fn write(&self, line: &str) {
+    let Ok(mut file) = self.file.lock() else {
+      return;
+    };
+    if let Err(err) = writeln!(file, "{line}") {
+      tracing::warn!(?err, path = %self.path.display(), "error writing the access log");
+    }
+  }
+}
+
+// written when the response body is dropped, so the duration and bytes cover the whole
+// stream, or the part sent before the client disconnected
+struct AccessLogEntry {
+  access_log: Arc<AccessLog>,
+  start: Instant,
+  method: Method,
+  path: String,
+  status: StatusCode,
+  model: Option<String>,
+  stream: bool,
+  bytes: usize,
+}
+
+impl Drop for AccessLogEntry {
+  fn drop(&mut self) {
+    let line = format!(
+      "{} {} {} {} {}ms {}B {} {}",
+      Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+      self.method,
+      self.path,
+      self.status.as_u16(),
+      self.start.elapsed().as_millis(),
+      self.bytes,
+      self.model.as_deref().unwrap_or("-"),
+      if self.stream { "y" } else { "n" },
+    );
+    self.access_log.write(&line);
+  }
+}
+
+pub(crate) async fn access_log_middleware(
+  State(access_log): State<Arc<AccessLog>>,
+  request: Request,
+  next: Next,
+) -> Response {
+  let start = Instant::now();
+  let method = request.method().clone();
+  let path = request.uri().path().to_string();
+  let (request, model) = request_model(request).await;
+  let response = next.run(request).await;
+  let (parts, body) = response.into_parts();
+  let mut entry = AccessLogEntry {
+    access_log,
+    start,
+    method,
+    path,
+    status: parts.status,
+    model,
+    stream: is_stream(&parts.headers),
+    bytes: 0,
+  };
+  let body = body.into_data_stream().map(move |chunk| {
+    if let Ok(chunk) = &chunk {
+      entry.bytes += chunk.len();
+    }
+    chunk
+  });
+  Response::from_parts(parts, Body::from_stream(body))
+}
+
+// reads the `model` from json request bodies, and puts the body back for the handler
+async fn request_model(request: Request) -> (Request, Option<String>) {
+  let is_json = request
+    .headers()
+    .get(header::CONTENT_TYPE)
+    .and_then(|value| value.to_str().ok())
+    .is_some_and(|value| value.starts_with(mime::APPLICATION_JSON.as_ref()));
+  if !is_json {
+    return (request, None);
+  }
+  let (parts, body) = request.into_parts();
+  let bytes = match to_bytes(body, usize::MAX).await {
+    Ok(bytes) => bytes,
+    Err(err) => {
+      tracing::warn!(?err, "error reading the request body for the access log");
+      return (Request::from_parts(parts, Body::empty()), None);
+    }
+  };
+  let model = serde_json::from_slice::<Value>(&bytes)
+    .ok()
+    .and_then(|value| value.get("model")?.as_str().map(str::to_string));
+  (Request::from_parts(parts, Body::from(bytes)), model)
+}
+
+fn is_stream(headers: &HeaderMap) -> bool {
+  headers
+    .get(header::CONTENT_TYPE)
+    .and_then(|value| value.to_str().ok())
+    .is_some_and(|value| {
+      value.starts_with(mime::TEXT_EVENT_STREAM.as_ref())
+        || value.starts_with("application/x-ndjson")
+    })
+}
+
+#[cfg(test)]
+mod test {
+  use super::{access_log_middleware, AccessLog, ACCESS_LOG_FILE};
+  use axum::{
+    body::{to_bytes, Body},
+    http::{header, Request, StatusCode},
+    middleware::from_fn_with_state,
+    response::{sse::Event, IntoResponse, Sse},
+    routing::{get, post},
+    Json, Router,
+  };
+  use futures_util::{stream, StreamExt};
+  use rstest::rstest;
+  use serde_json::Value;
+  use std::{convert::Infallible, fs, sync::Arc};
+  use tempfile::TempDir;
+  use tower::ServiceExt;
+
+  fn router(access_log: Arc<AccessLog>) -> Router {
+    Router::new()
+      .route("/ping", get(|| async { "pong" }))
+      .route(
+        "/echo",
+        post(|Json(body): Json<Value>| async { Json(body) }),
+      )
+      .route(
+        "/stream",
+        post(|| async {
+          let events = stream::iter(["one", "two"])
+            .map(|data| Ok::<_, Infallible>(Event::default().data(data)));
+          Sse::new(events).into_response()
+        }),
+      )
+      .layer(from_fn_with_state(access_log, access_log_middleware))
+  }
+
+  #[rstest]
+  #[case("GET", "/ping", None, "GET /ping 200", "4B - n")]
+  #[case(
+    "POST",
+    "/echo",
+    Some(r#"{"model":"llama3:instruct"}"#),
+    "POST /echo 200",
+    "27B llama3:instruct n"
+  )]
+  #[case(
+    "POST",
+    "/stream",
+    Some(r#"{"model":"llama3:instruct","stream":true}"#),
+    "POST /stream 200",
+    "22B llama3:instruct y"
+  )]
+  #[tokio::test]
+  async fn test_access_log_middleware_writes_line(
+    #[case] method: &str,
+    #[case] path: &str,
+    #[case] body: Option<&str>,
+    #[case] expected_request: &str,
+    #[case] expected_response: &str,
+  ) -> anyhow::Result<()> {
+    let logs_dir = TempDir::new()?;
+    let access_log = Arc::new(AccessLog::open(logs_dir.path())?);
+    let request = Request::builder().method(method).uri(path);
+    let request = match body {
+      Some(body) => request
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))?,
+      None => request.body(Body::empty())?,
+    };
+    let response = router(access_log).oneshot(request).await?;
+    assert_eq!(StatusCode::OK, response.status());
+    to_bytes(response.into_body(), usize::MAX).await?;
+    let content = fs::read_to_string(logs_dir.path().join(ACCESS_LOG_FILE))?;
+    let lines = content.lines().collect::<Vec<_>>();
+    assert_eq!(1, lines.len());
+    let (_timestamp, line) = lines[0].split_once(' ').unwrap();
+    assert!(line.starts_with(expected_request), "{line}");
+    assert!(line.ends_with(expected_response), "{line}");
+    Ok(())
+  }
+}
@@ -0,0 +1,259 @@
+use crate::{
+  objs::{Alias, ObjError, REFS_MAIN, TOKENIZER_CONFIG_JSON},
+  service::{AppServiceFn, HubServiceError},
+  shared_rw::ContextError,
+  tokenizer_config::{ChatMessage, TokenizerConfig},
+  Repo,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+
+#[derive(Debug, thiserror::Error)]
+pub enum AliasCheckError {
+  #[error("file '{filename}' from repo '{repo}' at snapshot '{snapshot}' not found in $HF_HOME")]
+  FileMissing {
+    repo: String,
+    filename: String,
+    snapshot: String,
+  },
+  #[error("chat template failed to render: {0}")]
+  Template(#[from] ContextError),
+  #[error(transparent)]
+  HubService(#[from] HubServiceError),
+  #[error(transparent)]
+  ObjError(#[from] ObjError),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuarantinedAlias {
+  pub alias: String,
+  pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AliasCheckStatus {
+  pub completed: bool,
+  pub quarantined: Vec<QuarantinedAlias>,
+}
+
+/// Model aliases that failed the startup self-check.
+/// Quarantined aliases are not listed in `/v1/models` until their file is changed and passes the
+/// check, or the server is restarted.
+#[derive(Debug, Default)]
+pub struct AliasQuarantine {
+  status: RwLock<AliasCheckStatus>,
+}
+
+impl AliasQuarantine {
+  pub fn status(&self) -> AliasCheckStatus {
+    self
+      .status
+      .read()
+      .map(|status| status.clone())
+      .unwrap_or_default()
+  }
+
+  pub fn is_quarantined(&self, alias: &str) -> bool {
+    self.status.read().is_ok_and(|status| {
+      status
+        .quarantined
+        .iter()
+        .any(|quarantined| quarantined.alias == alias)
+    })
+  }
+
+  // replaces the quarantine of the checked aliases, the removed aliases are not quarantined
+  fn recheck(&self, quarantined: Vec<QuarantinedAlias>, checked: &[&str]) {
+    if let Ok(mut status) = self.status.write() {
+      status
+        .quarantined
+        .retain(|existing| !checked.contains(&existing.alias.as_str()));
+      status.quarantined.extend(quarantined);
+    }
+  }
+
+  fn complete(&self, quarantined: Vec<QuarantinedAlias>) {
+    if let Ok(mut status) = self.status.write() {
+      *status = AliasCheckStatus {
+        completed: true,
+        quarantined,
+      };
+    }
+  }
+}
+
+/// Verifies the model file and tokenizer config of the alias are present in $HF_HOME,
+/// and the chat template renders a sample conversation
+#[allow(clippy::result_large_err)]
+fn check_alias(app_service: &dyn AppServiceFn, alias: &Alias) -> Result<(), AliasCheckError> {
+  let hub_service = app_service.hub_service();
+  if hub_service
+    .find_local_file(&alias.repo, &alias.filename, &alias.snapshot)?
+    .is_none()
+  {
+    return Err(AliasCheckError::FileMissing {
+      repo: alias.repo.to_string(),
+      filename: alias.filename.clone(),
+      snapshot: alias.snapshot.clone(),
+    });
+  }
+  let tokenizer_repo = Repo::try_from(alias.chat_template.clone())?;
+  let Some(tokenizer_file) =
+    hub_service.find_local_file(&tokenizer_repo, TOKENIZER_CONFIG_JSON, REFS_MAIN)?
+  else {
+    return Err(AliasCheckError::FileMissing {
+      repo: tokenizer_repo.to_string(),
+      filename: TOKENIZER_CONFIG_JSON.to_string(),
+      snapshot: REFS_MAIN.to_string(),
+    });
+  };
+  let tokenizer_config = TokenizerConfig::try_from(tokenizer_file)?;
+  let messages = vec![
+    ChatMessage::new(Some("user".to_string()), Some("hello".to_string())),
+    ChatMessage::new(Some("assistant".to_string()), Some("hi".to_string())),
+    ChatMessage::new(Some("user".to_string()), Some("how are you?".to_string())),
+  ];
+  tokenizer_config.apply_chat_template(&messages)?;
+  Ok(())
+}
+
+/// Runs the self-check on all the model aliases, and quarantines the aliases failing it
+pub async fn check_aliases(app_service: Arc<dyn AppServiceFn>, quarantine: Arc<AliasQuarantine>) {
+  let result = tokio::task::spawn_blocking(move || {
+    let aliases = match app_service.data_service().list_aliases() {
+      Ok(aliases) => aliases,
+      Err(err) => {
+        tracing::warn!(
+          ?err,
+          "error listing model aliases for the startup self-check"
+        );
+        vec![]
+      }
+    };
+    let mut quarantined = vec![];
+    for alias in aliases {
+      if let Err(err) = check_alias(app_service.as_ref(), &alias) {
+        tracing::warn!(
+          alias = %alias.alias,
+          %err,
+          "model alias failed the startup self-check, quarantining"
+        );
+        quarantined.push(QuarantinedAlias {
+          alias: alias.alias,
+          reason: err.to_string(),
+        });
+      }
+    }
+    quarantine.complete(quarantined);
+  })
+  .await;
+  if let Err(err) = result {
+    tracing::warn!(?err, "model alias startup self-check did not complete");
+  }
+}
+
+/// Runs the self-check on the model aliases changed since the startup, replacing their quarantine,
+/// and lifts the quarantine of the removed aliases
+pub(crate) fn recheck_aliases(
+  app_service: &dyn AppServiceFn,
+  quarantine: &AliasQuarantine,
+  changed: &[Alias],
+  removed: &[String],
+) {
+  let mut quarantined = vec![];
+  for alias in changed {
+    if let Err(err) = check_alias(app_service, alias) {
+      tracing::warn!(
+        alias = %alias.alias,
+        %err,
+        "changed model alias failed the self-check, quarantining"
+      );
+      quarantined.push(QuarantinedAlias {
+        alias: alias.alias.clone(),
+        reason: err.to_string(),
+      });
+    }
+  }
+  let checked = changed
+    .iter()
+    .map(|alias| alias.alias.as_str())
+    .chain(removed.iter().map(String::as_str))
+    .collect::<Vec<_>>();
+  quarantine.recheck(quarantined, &checked);
+}
+
+#[cfg(test)]
+mod test {
+  use super::{check_aliases, recheck_aliases, AliasQuarantine};
+  use crate::{
+    objs::Alias,
+    service::{AppServiceFn, DataService},
+    test_utils::{app_service_stub, AppServiceTuple},
+  };
+  use rstest::rstest;
+  use std::sync::Arc;
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_check_aliases_quarantines_broken_aliases(
+    app_service_stub: AppServiceTuple,
+  ) -> anyhow::Result<()> {
+    let AppServiceTuple(_temp_bodhi_home, _temp_hf_home, _, _, service) = app_service_stub;
+    service.data_service().save_alias(&Alias::testalias())?;
+    let service: Arc<dyn AppServiceFn> = Arc::new(service);
+    let quarantine = Arc::new(AliasQuarantine::default());
+    assert!(!quarantine.status().completed);
+    check_aliases(service, quarantine.clone()).await;
+    let status = quarantine.status();
+    assert!(status.completed);
+    let quarantined = status
+      .quarantined
+      .iter()
+      .map(|quarantined| quarantined.alias.as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(
+      vec![
+        "llama3:instruct",
+        "testalias-exists:instruct",
+        "tinyllama:instruct"
+      ],
+      quarantined
+    );
+    assert!(!quarantine.is_quarantined("testalias:instruct"));
+    assert!(quarantine.is_quarantined("llama3:instruct"));
+    assert_eq!(
+      "file 'Meta-Llama-3-8B-Instruct.Q8_0.gguf' from repo 'QuantFactory/Meta-Llama-3-8B-Instruct-GGUF' at snapshot '5007652f7a641fe7170e0bad4f63839419bd9213' not found in $HF_HOME",
+      status.quarantined[0].reason
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_recheck_aliases_replaces_the_quarantine_of_the_changed_aliases(
+    app_service_stub: AppServiceTuple,
+  ) -> anyhow::Result<()> {
+    let AppServiceTuple(_temp_bodhi_home, _temp_hf_home, _, _, service) = app_service_stub;
+    let service: Arc<dyn AppServiceFn> = Arc::new(service);
+    let quarantine = Arc::new(AliasQuarantine::default());
+    check_aliases(service.clone(), quarantine.clone()).await;
+    assert!(quarantine.is_quarantined("llama3:instruct"));
+    let broken = Alias {
+      alias: "testalias:instruct".to_string(),
+      filename: "missing.Q8_0.gguf".to_string(),
+      ..Alias::testalias()
+    };
+    recheck_aliases(
+      service.as_ref(),
+      &quarantine,
+      &[broken],
+      &["llama3:instruct".to_string()],
+    );
+    assert!(!quarantine.is_quarantined("llama3:instruct"));
+    assert!(quarantine.is_quarantined("testalias:instruct"));
+    assert!(quarantine.is_quarantined("tinyllama:instruct"));
+    recheck_aliases(service.as_ref(), &quarantine, &[Alias::testalias()], &[]);
+    assert!(!quarantine.is_quarantined("testalias:instruct"));
+    Ok(())
+  }
+}
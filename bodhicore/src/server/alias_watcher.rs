@@ -0,0 +1,269 @@
+use super::{
+  alias_check::{recheck_aliases, AliasQuarantine},
+  response_cache::ResponseCache,
+};
+use crate::{
+  objs::{Alias, REFS_MAIN, TOKENIZER_CONFIG_JSON},
+  service::AppServiceFn,
+  shared_rw::SharedContextRwFn,
+  Repo,
+};
+use notify::{Event, RecursiveMode, Watcher};
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
+use tokio::sync::mpsc::unbounded_channel;
+
+// an editor saving a file triggers several events, handled together once they stop
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// The model aliases added, changed and removed between two loads of $BODHI_HOME/aliases
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct AliasChanges {
+  pub(crate) added: Vec<String>,
+  pub(crate) changed: Vec<String>,
+  pub(crate) removed: Vec<String>,
+}
+
+impl AliasChanges {
+  pub(crate) fn between(
+    previous: &HashMap<String, Alias>,
+    current: &HashMap<String, Alias>,
+  ) -> Self {
+    let mut changes = AliasChanges::default();
+    for (name, alias) in current {
+      match previous.get(name) {
+        None => changes.added.push(name.clone()),
+        Some(previous) if previous != alias => changes.changed.push(name.clone()),
+        Some(_) => {}
+      }
+    }
+    changes.removed = previous
+      .keys()
+      .filter(|name| !current.contains_key(*name))
+      .cloned()
+      .collect();
+    changes.added.sort();
+    changes.changed.sort();
+    changes.removed.sort();
+    changes
+  }
+
+  pub(crate) fn is_empty(&self) -> bool {
+    self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+  }
+}
+
+/// Reloads the model aliases when the files in $BODHI_HOME/aliases are edited, added or removed,
+/// till the server stops. The changed aliases are checked again, and the chat templates they use
+/// are compiled again by the next requests, and the cached responses are cleared.
+pub async fn watch_aliases(
+  app_service: Arc<dyn AppServiceFn>,
+  ctx: Arc<dyn SharedContextRwFn>,
+  quarantine: Arc<AliasQuarantine>,
+  response_cache: Arc<ResponseCache>,
+) {
+  let aliases_dir = app_service.env_service().aliases_dir();
+  let (tx, mut rx) = unbounded_channel::<()>();
+  let watcher = notify::recommended_watcher(move |event: notify::Result<Event>| match event {
+    Ok(event) if !event.kind.is_access() => {
+      let _ = tx.send(());
+    }
+    Ok(_) => {}
+    Err(err) => tracing::warn!(?err, "error watching the model alias files"),
+  });
+  let mut watcher = match watcher {
+    Ok(watcher) => watcher,
+    Err(err) => {
+      tracing::warn!(
+        ?err,
+        "error starting the watcher of the model alias files, the changes are picked up on restart"
+      );
+      return;
+    }
+  };
+  if let Err(err) = watcher.watch(&aliases_dir, RecursiveMode::NonRecursive) {
+    tracing::warn!(
+      ?err,
+      aliases_dir = %aliases_dir.display(),
+      "error watching the model alias files, the changes are picked up on restart"
+    );
+    return;
+  }
+  let mut aliases = load_aliases(app_service.as_ref());
+  while rx.recv().await.is_some() {
+    tokio::time::sleep(DEBOUNCE).await;
+    while rx.try_recv().is_ok() {}
+    let current = load_aliases(app_service.as_ref());
+    let changes = AliasChanges::between(&aliases, &current);
+    if !changes.is_empty() {
+      tracing::info!(
+        added = ?changes.added,
+        changed = ?changes.changed,
+        removed = ?changes.removed,
+        "reloading the changed model aliases"
+      );
+      response_cache.clear();
+      reload(
+        &app_service,
+        ctx.as_ref(),
+        &quarantine,
+        &aliases,
+        &current,
+        &changes,
+      )
+      .await;
+    }
+    aliases = current;
+  }
+}
+
+fn load_aliases(app_service: &dyn AppServiceFn) -> HashMap<String, Alias> {
+  match app_service.data_service().list_aliases() {
+    Ok(aliases) => aliases
+      .into_iter()
+      .map(|alias| (alias.alias.clone(), alias))
+      .collect(),
+    Err(err) => {
+      tracing::warn!(?err, "error listing the model aliases");
+      HashMap::new()
+    }
+  }
+}
+
+pub(crate) async fn reload(
+  app_service: &Arc<dyn AppServiceFn>,
+  ctx: &dyn SharedContextRwFn,
+  quarantine: &Arc<AliasQuarantine>,
+  previous: &HashMap<String, Alias>,
+  current: &HashMap<String, Alias>,
+  changes: &AliasChanges,
+) {
+  // the templates of both the previous and the current chat template of the changed aliases
+  let previous_aliases = changes
+    .changed
+    .iter()
+    .chain(&changes.removed)
+    .filter_map(|name| previous.get(name));
+  let changed = changes
+    .added
+    .iter()
+    .chain(&changes.changed)
+    .filter_map(|name| current.get(name).cloned())
+    .collect::<Vec<_>>();
+  let mut tokenizer_files = previous_aliases
+    .chain(&changed)
+    .filter_map(|alias| tokenizer_file(app_service.as_ref(), alias))
+    .collect::<Vec<_>>();
+  tokenizer_files.sort();
+  tokenizer_files.dedup();
+  if !tokenizer_files.is_empty() {
+    if let Err(err) = ctx.invalidate_chat_templates(tokenizer_files).await {
+      tracing::warn!(
+        ?err,
+        "error dropping the chat templates of the changed model aliases"
+      );
+    }
+  }
+  let app_service = app_service.clone();
+  let quarantine = quarantine.clone();
+  let removed = changes.removed.clone();
+  let result = tokio::task::spawn_blocking(move || {
+    recheck_aliases(app_service.as_ref(), &quarantine, &changed, &removed);
+  })
+  .await;
+  if let Err(err) = result {
+    tracing::warn!(
+      ?err,
+      "self-check of the changed model aliases did not complete"
+    );
+  }
+}
+
+fn tokenizer_file(app_service: &dyn AppServiceFn, alias: &Alias) -> Option<PathBuf> {
+  let repo = Repo::try_from(alias.chat_template.clone()).ok()?;
+  let tokenizer_file = app_service
+    .hub_service()
+    .find_local_file(&repo, TOKENIZER_CONFIG_JSON, REFS_MAIN)
+    .ok()??;
+  Some(tokenizer_file.path())
+}
+
+#[cfg(test)]
+mod test {
+  use super::{reload, AliasChanges};
+  use crate::{
+    objs::{Alias, ChatTemplate, ChatTemplateId},
+    server::AliasQuarantine,
+    service::AppServiceFn,
+    test_utils::{app_service_stub, AppServiceTuple, MockSharedContext},
+  };
+  use mockall::predicate::function;
+  use rstest::rstest;
+  use std::{collections::HashMap, sync::Arc};
+
+  fn by_name(aliases: Vec<Alias>) -> HashMap<String, Alias> {
+    aliases
+      .into_iter()
+      .map(|alias| (alias.alias.clone(), alias))
+      .collect()
+  }
+
+  #[rstest]
+  fn test_alias_changes_between() {
+    let previous = by_name(vec![
+      Alias::llama3(),
+      Alias::testalias(),
+      Alias::test_alias_exists(),
+    ]);
+    let edited = Alias {
+      chat_template: ChatTemplate::Id(ChatTemplateId::Chatml),
+      ..Alias::testalias()
+    };
+    let added = Alias {
+      alias: "testalias:creative".to_string(),
+      ..Alias::testalias()
+    };
+    let current = by_name(vec![Alias::llama3(), edited, added]);
+    assert_eq!(
+      AliasChanges {
+        added: vec!["testalias:creative".to_string()],
+        changed: vec!["testalias:instruct".to_string()],
+        removed: vec!["testalias-exists:instruct".to_string()],
+      },
+      AliasChanges::between(&previous, &current)
+    );
+    assert!(AliasChanges::between(&current, &current).is_empty());
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_reload_drops_the_chat_templates_and_rechecks_the_changed_aliases(
+    app_service_stub: AppServiceTuple,
+  ) -> anyhow::Result<()> {
+    let AppServiceTuple(_temp_bodhi_home, _temp_hf_home, _, _, service) = app_service_stub;
+    let service: Arc<dyn AppServiceFn> = Arc::new(service);
+    let previous = by_name(vec![Alias::testalias()]);
+    let broken = Alias {
+      filename: "missing.Q8_0.gguf".to_string(),
+      ..Alias::testalias()
+    };
+    let current = by_name(vec![broken]);
+    let mut ctx = MockSharedContext::new();
+    ctx
+      .expect_invalidate_chat_templates()
+      .with(function(|tokenizer_files: &Vec<std::path::PathBuf>| {
+        tokenizer_files.len() == 1
+          && tokenizer_files[0].ends_with("tokenizer_config.json")
+          && tokenizer_files[0]
+            .display()
+            .to_string()
+            .contains("models--meta-llama--Meta-Llama-3-8B-Instruct")
+      }))
+      .times(1)
+      .return_once(|_| Ok(()));
+    let quarantine = Arc::new(AliasQuarantine::default());
+    let changes = AliasChanges::between(&previous, &current);
+    reload(&service, &ctx, &quarantine, &previous, &current, &changes).await;
+    assert!(quarantine.is_quarantined("testalias:instruct"));
+    Ok(())
+  }
+}
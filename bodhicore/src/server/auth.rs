@@ -0,0 +1,236 @@
+use super::RouterStateFn;
+use crate::{
+  db::{objs::ApiKeyRole, DbError},
+  oai::OpenAIApiError,
+};
+use axum::{
+  extract::{Request, State},
+  http::{header::AUTHORIZATION, Method},
+  middleware::Next,
+  response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+
+// probes are open to all, the share links are protected by their own token
+static PUBLIC_PATHS: &[&str] = &["/ping", "/health", "/ready"];
+static PUBLIC_PREFIXES: &[&str] = &["/share/"];
+// model alias management and downloads, server settings, the audit log and the users queue
+static ADMIN_PREFIXES: &[&str] = &[
+  "/api/ui/models",
+  "/api/ui/downloads",
+  "/api/ui/pull",
+  "/api/ui/settings",
+  "/api/ui/audit",
+  "/api/ui/queue",
+];
+
+/// Role of the API key needed for the request, `None` for the routes open to all.
+/// Reads need the readonly role, the other requests need the user role, except the
+/// alias management, downloads, settings, audit log and queue routes needing the admin role.
+pub(crate) fn required_role(method: &Method, path: &str) -> Option<ApiKeyRole> {
+  if method == Method::OPTIONS
+    || PUBLIC_PATHS.contains(&path)
+    || PUBLIC_PREFIXES
+      .iter()
+      .any(|prefix| path.starts_with(prefix))
+  {
+    return None;
+  }
+  if ADMIN_PREFIXES.iter().any(|prefix| path.starts_with(prefix)) {
+    return Some(ApiKeyRole::Admin);
+  }
+  if method == Method::GET || method == Method::HEAD {
+    Some(ApiKeyRole::Readonly)
+  } else {
+    Some(ApiKeyRole::User)
+  }
+}
+
+/// Checks the `Authorization: Bearer <key>` header against the API keys when `BODHI_AUTH`
+/// is enabled, the [`crate::db::objs::ApiKey`] is added to the request extensions
+pub(crate) async fn auth_middleware(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  mut request: Request,
+  next: Next,
+) -> Response {
+  let Some(required) = required_role(request.method(), request.uri().path()) else {
+    return next.run(request).await;
+  };
+  let secret = request
+    .headers()
+    .get(AUTHORIZATION)
+    .and_then(|value| value.to_str().ok())
+    .and_then(|value| value.strip_prefix("Bearer "))
+    .map(|secret| secret.trim().to_string());
+  let Some(secret) = secret else {
+    return OpenAIApiError::Unauthorized(
+      "API key is required, send it as `Authorization: Bearer <key>`".to_string(),
+    )
+    .into_response();
+  };
+  let api_key = match state.db_service().find_api_key(&secret).await {
+    Ok(api_key) => api_key,
+    Err(DbError::Sqlx {
+      source: sqlx::Error::RowNotFound,
+      ..
+    }) => {
+      return OpenAIApiError::Unauthorized("API key is invalid or revoked".to_string())
+        .into_response()
+    }
+    Err(err) => return OpenAIApiError::from(err).into_response(),
+  };
+  if api_key.role < required {
+    return OpenAIApiError::Forbidden(format!(
+      "API key '{}' has the {} role, {} {} needs the {required} role",
+      api_key.name,
+      api_key.role,
+      request.method(),
+      request.uri().path(),
+    ))
+    .into_response();
+  }
+  request.extensions_mut().insert(api_key);
+  next.run(request).await
+}
+
+#[cfg(test)]
+mod test {
+  use super::{auth_middleware, required_role};
+  use crate::{
+    db::{objs::ApiKeyRole, DbService, DbServiceFn},
+    objs::RequestPriority,
+    server::{RouterState, RouterStateFn},
+    service::MockAppServiceFn,
+    test_utils::{db_service, MockSharedContext},
+  };
+  use axum::{
+    body::Body,
+    http::{header::AUTHORIZATION, Method, Request, StatusCode},
+    middleware::from_fn_with_state,
+    routing::{get, post},
+    Router,
+  };
+  use chrono::{DateTime, Utc};
+  use rstest::rstest;
+  use std::sync::Arc;
+  use tempfile::TempDir;
+  use tower::ServiceExt;
+
+  #[rstest]
+  #[case(Method::GET, "/ping", None)]
+  #[case(Method::GET, "/health", None)]
+  #[case(Method::GET, "/share/token", None)]
+  #[case(Method::OPTIONS, "/v1/chat/completions", None)]
+  #[case(Method::GET, "/v1/models", Some(ApiKeyRole::Readonly))]
+  #[case(Method::GET, "/api/ui/chats", Some(ApiKeyRole::Readonly))]
+  #[case(Method::POST, "/v1/chat/completions", Some(ApiKeyRole::User))]
+  #[case(Method::DELETE, "/api/ui/chats/testid", Some(ApiKeyRole::User))]
+  #[case(Method::PATCH, "/api/ui/models", Some(ApiKeyRole::Admin))]
+  #[case(
+    Method::DELETE,
+    "/api/ui/models/llama3:instruct",
+    Some(ApiKeyRole::Admin)
+  )]
+  #[case(Method::GET, "/api/ui/settings", Some(ApiKeyRole::Admin))]
+  #[case(Method::GET, "/api/ui/downloads", Some(ApiKeyRole::Admin))]
+  #[case(Method::POST, "/api/ui/pull", Some(ApiKeyRole::Admin))]
+  #[case(Method::GET, "/api/ui/audit", Some(ApiKeyRole::Admin))]
+  #[case(Method::GET, "/api/ui/queue", Some(ApiKeyRole::Admin))]
+  fn test_auth_required_role(
+    #[case] method: Method,
+    #[case] path: &str,
+    #[case] expected: Option<ApiKeyRole>,
+  ) {
+    assert_eq!(expected, required_role(&method, path));
+  }
+
+  #[rstest]
+  #[case(ApiKeyRole::Readonly, Method::GET, "/v1/models", StatusCode::OK)]
+  #[case(
+    ApiKeyRole::Readonly,
+    Method::POST,
+    "/v1/chat/completions",
+    StatusCode::FORBIDDEN
+  )]
+  #[case(ApiKeyRole::User, Method::POST, "/v1/chat/completions", StatusCode::OK)]
+  #[case(
+    ApiKeyRole::User,
+    Method::GET,
+    "/api/ui/settings",
+    StatusCode::FORBIDDEN
+  )]
+  #[case(ApiKeyRole::Admin, Method::GET, "/api/ui/settings", StatusCode::OK)]
+  #[awt]
+  #[tokio::test]
+  async fn test_auth_middleware_checks_role(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+    #[case] role: ApiKeyRole,
+    #[case] method: Method,
+    #[case] path: &str,
+    #[case] expected: StatusCode,
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let (_, secret) = db_service
+      .create_api_key("test", role, RequestPriority::Interactive)
+      .await?;
+    let response = test_router(db_service)
+      .oneshot(
+        Request::builder()
+          .method(method)
+          .uri(path)
+          .header(AUTHORIZATION, format!("Bearer {secret}"))
+          .body(Body::empty())?,
+      )
+      .await?;
+    assert_eq!(expected, response.status());
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_auth_middleware_rejects_missing_and_revoked_keys(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let (api_key, secret) = db_service
+      .create_api_key("test", ApiKeyRole::Admin, RequestPriority::Interactive)
+      .await?;
+    db_service.revoke_api_key(&api_key.id).await?;
+    let router = test_router(db_service);
+    let response = router
+      .clone()
+      .oneshot(Request::get("/v1/models").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+    let response = router
+      .clone()
+      .oneshot(
+        Request::get("/v1/models")
+          .header(AUTHORIZATION, format!("Bearer {secret}"))
+          .body(Body::empty())?,
+      )
+      .await?;
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+    let response = router
+      .oneshot(Request::get("/ping").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    Ok(())
+  }
+
+  fn test_router(db_service: DbService) -> Router {
+    let state: Arc<dyn RouterStateFn> = Arc::new(RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(MockAppServiceFn::new()),
+      Arc::new(db_service),
+    ));
+    Router::new()
+      .route("/ping", get(|| async { "pong" }))
+      .route("/v1/models", get(|| async { "models" }))
+      .route("/v1/chat/completions", post(|| async { "chat" }))
+      .route("/api/ui/settings", get(|| async { "settings" }))
+      .layer(from_fn_with_state(state.clone(), auth_middleware))
+      .with_state(state)
+  }
+}
@@ -0,0 +1,759 @@
+use super::RouterStateFn;
+use crate::{
+  error::Common,
+  oai::{ApiError, OpenAIApiError},
+  objs::{ChatCompletionRequest, RequestPriority},
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{
+  collections::HashSet,
+  fs,
+  path::{Path, PathBuf},
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+use tokio::sync::mpsc::channel;
+use uuid::Uuid;
+
+pub static BATCHES_DIR: &str = "batches";
+pub static BATCH_ENDPOINT: &str = "/v1/chat/completions";
+pub static PURPOSE_BATCH: &str = "batch";
+pub static PURPOSE_BATCH_OUTPUT: &str = "batch_output";
+static FILES_DIR: &str = "files";
+// how often a batch waiting for the interactive requests checks if it is cancelled
+static CANCEL_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+// serializes the updates of the batch status, by the batch runner and the cancel requests
+static BATCH_UPDATE_LOCK: Mutex<()> = Mutex::new(());
+// ids of the batches run by this server process
+static RUNNING_BATCHES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// File uploaded using `POST /v1/files`, or written by a batch with its results
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileObject {
+  pub id: String,
+  pub object: String,
+  pub bytes: usize,
+  pub created_at: i64,
+  pub filename: String,
+  pub purpose: String,
+  /// name of the API key that uploaded the file when `BODHI_AUTH` is enabled, the file is
+  /// visible only to the keys with the name
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub owner: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchStatus {
+  InProgress,
+  Completed,
+  Failed,
+  Cancelling,
+  Cancelled,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BatchRequestCounts {
+  pub total: usize,
+  pub completed: usize,
+  pub failed: usize,
+}
+
+/// Batch of chat completions created using `POST /v1/batches`, a subset of the OpenAI Batch API
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Batch {
+  pub id: String,
+  pub object: String,
+  pub endpoint: String,
+  pub input_file_id: String,
+  pub completion_window: String,
+  pub status: BatchStatus,
+  #[serde(default)]
+  pub output_file_id: Option<String>,
+  #[serde(default)]
+  pub error_file_id: Option<String>,
+  pub created_at: i64,
+  #[serde(default)]
+  pub in_progress_at: Option<i64>,
+  #[serde(default)]
+  pub completed_at: Option<i64>,
+  #[serde(default)]
+  pub failed_at: Option<i64>,
+  #[serde(default)]
+  pub cancelled_at: Option<i64>,
+  pub request_counts: BatchRequestCounts,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub metadata: Option<Map<String, Value>>,
+  /// name of the API key that created the batch when `BODHI_AUTH` is enabled, the batch and its
+  /// result files are visible only to the keys with the name
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub owner: Option<String>,
+}
+
+/// Line of the batch input file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatchRequestInput {
+  pub custom_id: String,
+  pub method: String,
+  pub url: String,
+  pub body: ChatCompletionRequest,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatchResponse {
+  pub status_code: u16,
+  pub request_id: String,
+  pub body: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatchError {
+  pub code: String,
+  pub message: String,
+}
+
+/// Line of the batch output file, or of its error file if the request failed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatchRequestOutput {
+  pub id: String,
+  pub custom_id: String,
+  pub response: Option<BatchResponse>,
+  pub error: Option<BatchError>,
+}
+
+/// Parses and validates the lines of the batch input file, the error has the line number
+pub(crate) fn parse_batch_input(content: &[u8]) -> Result<Vec<BatchRequestInput>, String> {
+  let content = std::str::from_utf8(content).map_err(|_| "input file is not utf-8".to_string())?;
+  let mut custom_ids = HashSet::<String>::new();
+  let mut requests = Vec::<BatchRequestInput>::new();
+  for (index, line) in content.lines().enumerate() {
+    if line.trim().is_empty() {
+      continue;
+    }
+    let line_no = index + 1;
+    let request = serde_json::from_str::<BatchRequestInput>(line)
+      .map_err(|err| format!("line {line_no}: {err}"))?;
+    if request.method != "POST" || request.url != BATCH_ENDPOINT {
+      return Err(format!(
+        "line {line_no}: only 'POST {BATCH_ENDPOINT}' is supported"
+      ));
+    }
+    if !custom_ids.insert(request.custom_id.clone()) {
+      return Err(format!(
+        "line {line_no}: duplicate custom_id '{}'",
+        request.custom_id
+      ));
+    }
+    requests.push(request);
+  }
+  if requests.is_empty() {
+    return Err("input file has no requests".to_string());
+  }
+  Ok(requests)
+}
+
+/// Files and batches, stored in `$BODHI_HOME/batches/`
+#[derive(Debug, Clone)]
+pub struct BatchStore {
+  dir: PathBuf,
+}
+
+impl BatchStore {
+  pub fn new(bodhi_home: &Path) -> Self {
+    Self {
+      dir: bodhi_home.join(BATCHES_DIR),
+    }
+  }
+
+  pub fn create_file(
+    &self,
+    filename: &str,
+    purpose: &str,
+    content: &[u8],
+    owner: Option<String>,
+  ) -> Result<FileObject, Common> {
+    let file = FileObject {
+      id: format!("file-{}", Uuid::new_v4().simple()),
+      object: "file".to_string(),
+      bytes: content.len(),
+      created_at: Utc::now().timestamp(),
+      filename: filename.to_string(),
+      purpose: purpose.to_string(),
+      owner,
+    };
+    let files_dir = self.dir.join(FILES_DIR);
+    fs::create_dir_all(&files_dir).map_err(|source| Common::IoDir {
+      source,
+      path: files_dir.display().to_string(),
+    })?;
+    write_file(&files_dir.join(format!("{}.jsonl", file.id)), content)?;
+    write_json(&files_dir.join(format!("{}.json", file.id)), &file)?;
+    Ok(file)
+  }
+
+  pub fn get_file(&self, id: &str) -> Result<Option<FileObject>, Common> {
+    read_json(&self.dir.join(FILES_DIR), id)
+  }
+
+  pub fn file_content(&self, id: &str) -> Result<Option<Vec<u8>>, Common> {
+    if !is_valid_id(id) {
+      return Ok(None);
+    }
+    let path = self.dir.join(FILES_DIR).join(format!("{id}.jsonl"));
+    match fs::read(&path) {
+      Ok(content) => Ok(Some(content)),
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+      Err(source) => Err(Common::IoFile {
+        source,
+        path: path.display().to_string(),
+      }),
+    }
+  }
+
+  pub fn save_batch(&self, batch: &Batch) -> Result<(), Common> {
+    fs::create_dir_all(&self.dir).map_err(|source| Common::IoDir {
+      source,
+      path: self.dir.display().to_string(),
+    })?;
+    write_json(&self.dir.join(format!("{}.json", batch.id)), batch)
+  }
+
+  pub fn get_batch(&self, id: &str) -> Result<Option<Batch>, Common> {
+    read_json(&self.dir, id)
+  }
+
+  /// Batches, the most recent first
+  pub fn list_batches(&self) -> Result<Vec<Batch>, Common> {
+    let mut batches = fs::read_dir(&self.dir)
+      .into_iter()
+      .flatten()
+      .filter_map(|entry| entry.ok())
+      .filter_map(|entry| {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let id = name.strip_suffix(".json")?.to_string();
+        self.get_batch(&id).ok().flatten()
+      })
+      .collect::<Vec<_>>();
+    batches.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    Ok(batches)
+  }
+
+  /// Applies the update to the stored batch, and returns the updated batch
+  pub fn update_batch(
+    &self,
+    id: &str,
+    update: impl FnOnce(&mut Batch),
+  ) -> Result<Option<Batch>, Common> {
+    let _lock = BATCH_UPDATE_LOCK.lock();
+    let Some(mut batch) = self.get_batch(id)? else {
+      return Ok(None);
+    };
+    update(&mut batch);
+    self.save_batch(&batch)?;
+    Ok(Some(batch))
+  }
+}
+
+// the ids are generated by the store, anything else could escape the batches dir
+fn is_valid_id(id: &str) -> bool {
+  !id.is_empty()
+    && id
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn write_file(path: &Path, content: &[u8]) -> Result<(), Common> {
+  fs::write(path, content).map_err(|source| Common::IoFile {
+    source,
+    path: path.display().to_string(),
+  })
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<(), Common> {
+  let content =
+    serde_json::to_string_pretty(value).map_err(|source| Common::SerdeJsonSerialize {
+      source,
+      value: path.display().to_string(),
+    })?;
+  write_file(path, content.as_bytes())
+}
+
+fn read_json<T: for<'de> Deserialize<'de>>(dir: &Path, id: &str) -> Result<Option<T>, Common> {
+  if !is_valid_id(id) {
+    return Ok(None);
+  }
+  let path = dir.join(format!("{id}.json"));
+  let content = match fs::read_to_string(&path) {
+    Ok(content) => content,
+    Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+    Err(source) => {
+      return Err(Common::IoFile {
+        source,
+        path: path.display().to_string(),
+      })
+    }
+  };
+  Ok(Some(serde_json::from_str::<T>(&content)?))
+}
+
+/// Creates the batch for the requests of the input file, to be run with [`run_batch`]
+pub(crate) fn new_batch(
+  input_file_id: &str,
+  completion_window: &str,
+  total: usize,
+  metadata: Option<Map<String, Value>>,
+  owner: Option<String>,
+) -> Batch {
+  let now = Utc::now().timestamp();
+  Batch {
+    id: format!("batch_{}", Uuid::new_v4().simple()),
+    object: "batch".to_string(),
+    endpoint: BATCH_ENDPOINT.to_string(),
+    input_file_id: input_file_id.to_string(),
+    completion_window: completion_window.to_string(),
+    status: BatchStatus::InProgress,
+    output_file_id: None,
+    error_file_id: None,
+    created_at: now,
+    in_progress_at: Some(now),
+    completed_at: None,
+    failed_at: None,
+    cancelled_at: None,
+    request_counts: BatchRequestCounts {
+      total,
+      ..Default::default()
+    },
+    metadata,
+    owner,
+  }
+}
+
+// true if the batch was cancelled, or removed, and should stop
+fn is_cancelled(store: &BatchStore, batch_id: &str) -> bool {
+  !matches!(
+    store.get_batch(batch_id),
+    Ok(Some(Batch {
+      status: BatchStatus::InProgress,
+      ..
+    }))
+  )
+}
+
+/// Runs the requests of the batch one at a time, at the batch priority: before each request, waits
+/// till no interactive request is queued for the model. The results are written as the output and
+/// error files of the batch once all the requests have run, or the batch is cancelled.
+pub(crate) async fn run_batch(
+  state: Arc<dyn RouterStateFn>,
+  store: BatchStore,
+  batch_id: String,
+  requests: Vec<BatchRequestInput>,
+) {
+  let _running = RunningBatch::start(&batch_id);
+  let mut outputs = Vec::<String>::new();
+  let mut errors = Vec::<String>::new();
+  let mut cancelled = false;
+  for input in requests {
+    let monitor = state.inference_monitor();
+    while tokio::time::timeout(CANCEL_CHECK_INTERVAL, monitor.yield_to_interactive())
+      .await
+      .is_err()
+    {
+      if is_cancelled(&store, &batch_id) {
+        break;
+      }
+    }
+    if is_cancelled(&store, &batch_id) {
+      cancelled = true;
+      break;
+    }
+    let output = run_request(state.clone(), input).await;
+    let failed = output.error.is_some();
+    let line = match serde_json::to_string(&output) {
+      Ok(line) => line,
+      Err(err) => {
+        tracing::warn!(?err, "error serializing the batch output");
+        continue;
+      }
+    };
+    if failed {
+      errors.push(line);
+    } else {
+      outputs.push(line);
+    }
+    let updated = store.update_batch(&batch_id, |batch| match failed {
+      true => batch.request_counts.failed += 1,
+      false => batch.request_counts.completed += 1,
+    });
+    if let Err(err) = updated {
+      tracing::warn!(?err, batch_id, "error updating the batch progress");
+    }
+  }
+  // the result files are of the owner of the batch
+  let owner = store
+    .get_batch(&batch_id)
+    .ok()
+    .flatten()
+    .and_then(|batch| batch.owner);
+  let output_file_id = write_results(&store, &batch_id, "output", &outputs, &owner);
+  let error_file_id = write_results(&store, &batch_id, "errors", &errors, &owner);
+  let updated = store.update_batch(&batch_id, |batch| {
+    let now = Some(Utc::now().timestamp());
+    batch.output_file_id = output_file_id;
+    batch.error_file_id = error_file_id;
+    if cancelled {
+      batch.status = BatchStatus::Cancelled;
+      batch.cancelled_at = now;
+    } else {
+      batch.status = BatchStatus::Completed;
+      batch.completed_at = now;
+    }
+  });
+  match updated {
+    Ok(_) => tracing::info!(batch_id, cancelled, "batch finished"),
+    Err(err) => tracing::warn!(?err, batch_id, "error saving the finished batch"),
+  }
+}
+
+fn write_results(
+  store: &BatchStore,
+  batch_id: &str,
+  kind: &str,
+  lines: &[String],
+  owner: &Option<String>,
+) -> Option<String> {
+  if lines.is_empty() {
+    return None;
+  }
+  let content = format!("{}\n", lines.join("\n"));
+  let filename = format!("{batch_id}_{kind}.jsonl");
+  match store.create_file(
+    &filename,
+    PURPOSE_BATCH_OUTPUT,
+    content.as_bytes(),
+    owner.clone(),
+  ) {
+    Ok(file) => Some(file.id),
+    Err(err) => {
+      tracing::warn!(?err, batch_id, "error writing the batch results");
+      None
+    }
+  }
+}
+
+async fn run_request(
+  state: Arc<dyn RouterStateFn>,
+  input: BatchRequestInput,
+) -> BatchRequestOutput {
+  let mut request = input.body;
+  request.stream = Some(false);
+  request.set_priority(RequestPriority::Batch);
+  let (tx, mut rx) = channel::<String>(100);
+  let result = state.chat_completions(request, tx).await;
+  let message = rx.recv().await;
+  let request_id = Uuid::new_v4().to_string();
+  let id = format!("batch_req_{}", Uuid::new_v4().simple());
+  let body = match (result, message) {
+    (Ok(()), Some(message)) => serde_json::from_str::<Value>(&message)
+      .map_err(|err| OpenAIApiError::InternalServer(err.to_string())),
+    (Ok(()), None) => Err(OpenAIApiError::InternalServer(
+      "receiver stream abruptly closed".to_string(),
+    )),
+    (Err(err), _) => Err(err),
+  };
+  match body {
+    Ok(body) => BatchRequestOutput {
+      id,
+      custom_id: input.custom_id,
+      response: Some(BatchResponse {
+        status_code: 200,
+        request_id,
+        body,
+      }),
+      error: None,
+    },
+    Err(err) => {
+      let error = ApiError::from(&err);
+      BatchRequestOutput {
+        id,
+        custom_id: input.custom_id,
+        response: None,
+        error: Some(BatchError {
+          code: error.code,
+          message: error.message,
+        }),
+      }
+    }
+  }
+}
+
+/// Marks the batch failed if it is not finished but not running either,
+/// as the server was stopped while running it
+pub(crate) fn check_interrupted(store: &BatchStore, batch: Batch) -> Result<Batch, Common> {
+  if !matches!(
+    batch.status,
+    BatchStatus::InProgress | BatchStatus::Cancelling
+  ) || is_running(&batch.id)
+  {
+    return Ok(batch);
+  }
+  let updated = store.update_batch(&batch.id, |batch| {
+    batch.status = BatchStatus::Failed;
+    batch.failed_at = Some(Utc::now().timestamp());
+  })?;
+  Ok(updated.unwrap_or(batch))
+}
+
+fn is_running(batch_id: &str) -> bool {
+  RUNNING_BATCHES
+    .lock()
+    .map(|running| running.iter().any(|id| id == batch_id))
+    .unwrap_or(true)
+}
+
+// registers the batch as running till dropped
+struct RunningBatch(String);
+
+impl RunningBatch {
+  fn start(batch_id: &str) -> Self {
+    if let Ok(mut running) = RUNNING_BATCHES.lock() {
+      running.push(batch_id.to_string());
+    }
+    Self(batch_id.to_string())
+  }
+}
+
+impl Drop for RunningBatch {
+  fn drop(&mut self) {
+    if let Ok(mut running) = RUNNING_BATCHES.lock() {
+      running.retain(|id| id != &self.0);
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{
+    check_interrupted, new_batch, parse_batch_input, run_batch, BatchRequestOutput, BatchStatus,
+    BatchStore,
+  };
+  use crate::{
+    oai::OpenAIApiError, objs::RequestPriority, server::InferenceMonitor,
+    test_utils::MockRouterState,
+  };
+  use rstest::rstest;
+  use serde_json::json;
+  use std::sync::Arc;
+  use tempfile::TempDir;
+  use tokio::sync::mpsc::Sender;
+
+  fn input_line(custom_id: &str, content: &str) -> String {
+    json! {{
+      "custom_id": custom_id,
+      "method": "POST",
+      "url": "/v1/chat/completions",
+      "body": {
+        "model": "testalias:instruct",
+        "messages": [{"role": "user", "content": content}]
+      }
+    }}
+    .to_string()
+  }
+
+  #[rstest]
+  fn test_parse_batch_input() -> anyhow::Result<()> {
+    let content = format!(
+      "{}\n\n{}\n",
+      input_line("req-1", "What day comes after Monday?"),
+      input_line("req-2", "What day comes after Tuesday?")
+    );
+    let requests = parse_batch_input(content.as_bytes()).map_err(anyhow::Error::msg)?;
+    assert_eq!(
+      vec!["req-1", "req-2"],
+      requests
+        .iter()
+        .map(|request| request.custom_id.as_str())
+        .collect::<Vec<_>>()
+    );
+    assert_eq!("testalias:instruct", requests[0].body.model);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("", "input file has no requests")]
+  #[case("not json", "line 1: expected ident at line 1 column 2")]
+  #[case(
+    &json! {{"custom_id": "req-1", "method": "POST", "url": "/v1/embeddings", "body": {"model": "m", "messages": []}}}.to_string(),
+    "line 1: only 'POST /v1/chat/completions' is supported"
+  )]
+  fn test_parse_batch_input_invalid(#[case] content: &str, #[case] expected: &str) {
+    assert_eq!(
+      Err(expected.to_string()),
+      parse_batch_input(content.as_bytes())
+    );
+  }
+
+  #[rstest]
+  fn test_parse_batch_input_duplicate_custom_id() {
+    let content = format!(
+      "{}\n{}\n",
+      input_line("req-1", "a"),
+      input_line("req-1", "b")
+    );
+    assert_eq!(
+      Err("line 2: duplicate custom_id 'req-1'".to_string()),
+      parse_batch_input(content.as_bytes())
+    );
+  }
+
+  #[rstest]
+  fn test_batch_store_files_and_batches() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let store = BatchStore::new(temp.path());
+    let file = store.create_file("requests.jsonl", "batch", b"{}\n", None)?;
+    assert_eq!(Some(file.clone()), store.get_file(&file.id)?);
+    assert_eq!(Some(b"{}\n".to_vec()), store.file_content(&file.id)?);
+    assert_eq!(None, store.get_file("file-notexists")?);
+    assert_eq!(None, store.file_content("../../settings")?);
+    let batch = new_batch(&file.id, "24h", 2, None, None);
+    store.save_batch(&batch)?;
+    let updated = store.update_batch(&batch.id, |batch| batch.status = BatchStatus::Cancelling)?;
+    assert_eq!(
+      Some(BatchStatus::Cancelling),
+      updated.map(|batch| batch.status)
+    );
+    assert_eq!(
+      vec![batch.id.clone()],
+      store
+        .list_batches()?
+        .into_iter()
+        .map(|batch| batch.id)
+        .collect::<Vec<_>>()
+    );
+    assert_eq!(None, store.update_batch("batch_notexists", |_| {})?);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_run_batch_writes_output_and_error_files() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let store = BatchStore::new(temp.path());
+    let content = format!(
+      "{}\n{}\n",
+      input_line("req-1", "What day comes after Monday?"),
+      input_line("req-2", "fail")
+    );
+    let requests = parse_batch_input(content.as_bytes()).map_err(anyhow::Error::msg)?;
+    let batch = new_batch(
+      "file-input",
+      "24h",
+      requests.len(),
+      None,
+      Some("alice".to_string()),
+    );
+    store.save_batch(&batch)?;
+    let mut router_state = MockRouterState::new();
+    let monitor = Arc::new(InferenceMonitor::default());
+    router_state
+      .expect_inference_monitor()
+      .returning(move || monitor.clone());
+    router_state
+      .expect_chat_completions()
+      .times(2)
+      .returning(|request, sender: Sender<String>| {
+        assert_eq!(Some(false), request.stream);
+        assert_eq!(RequestPriority::Batch, request.priority());
+        let messages = serde_json::to_string(&request.messages).unwrap();
+        if messages.contains("fail") {
+          return Err(OpenAIApiError::ModelNotFound(
+            "testalias:instruct".to_string(),
+          ));
+        }
+        let response = json! {{"id": "testid", "object": "chat.completion", "choices": []}};
+        _ = sender.try_send(response.to_string());
+        Ok(())
+      });
+    run_batch(
+      Arc::new(router_state),
+      store.clone(),
+      batch.id.clone(),
+      requests,
+    )
+    .await;
+    let batch = store.get_batch(&batch.id)?.expect("batch should exist");
+    assert_eq!(BatchStatus::Completed, batch.status);
+    assert_eq!(1, batch.request_counts.completed);
+    assert_eq!(1, batch.request_counts.failed);
+    let output_file_id = batch.output_file_id.expect("output file should be set");
+    assert_eq!(
+      Some("alice".to_string()),
+      store.get_file(&output_file_id)?.and_then(|file| file.owner)
+    );
+    let output = store
+      .file_content(&output_file_id)?
+      .expect("output file should exist");
+    let output = serde_json::from_slice::<BatchRequestOutput>(&output)?;
+    assert_eq!("req-1", output.custom_id);
+    assert_eq!(
+      json! {"testid"},
+      output.response.expect("response should be set").body["id"]
+    );
+    let errors = store
+      .file_content(&batch.error_file_id.expect("error file should be set"))?
+      .expect("error file should exist");
+    let errors = serde_json::from_slice::<BatchRequestOutput>(&errors)?;
+    assert_eq!("req-2", errors.custom_id);
+    assert_eq!(
+      "model_not_found",
+      errors.error.expect("error should be set").code
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_run_batch_stops_when_cancelled() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let store = BatchStore::new(temp.path());
+    let content = format!("{}\n", input_line("req-1", "What day comes after Monday?"));
+    let requests = parse_batch_input(content.as_bytes()).map_err(anyhow::Error::msg)?;
+    let batch = new_batch("file-input", "24h", requests.len(), None, None);
+    store.save_batch(&batch)?;
+    store.update_batch(&batch.id, |batch| batch.status = BatchStatus::Cancelling)?;
+    let mut router_state = MockRouterState::new();
+    let monitor = Arc::new(InferenceMonitor::default());
+    router_state
+      .expect_inference_monitor()
+      .returning(move || monitor.clone());
+    router_state.expect_chat_completions().never();
+    run_batch(
+      Arc::new(router_state),
+      store.clone(),
+      batch.id.clone(),
+      requests,
+    )
+    .await;
+    let batch = store.get_batch(&batch.id)?.expect("batch should exist");
+    assert_eq!(BatchStatus::Cancelled, batch.status);
+    assert!(batch.cancelled_at.is_some());
+    assert_eq!(None, batch.output_file_id);
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_check_interrupted_marks_batch_not_running_failed() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let store = BatchStore::new(temp.path());
+    let batch = new_batch("file-input", "24h", 1, None, None);
+    store.save_batch(&batch)?;
+    let id = batch.id.clone();
+    let batch = check_interrupted(&store, batch)?;
+    assert_eq!(BatchStatus::Failed, batch.status);
+    assert!(batch.failed_at.is_some());
+    assert_eq!(Some(batch), store.get_batch(&id)?);
+    Ok(())
+  }
+}
@@ -0,0 +1,114 @@
+use super::RouterStateFn;
+use crate::{
+  db::objs::CanarySample,
+  objs::{ChatCompletionRequest, RequestPriority},
+};
+use serde_json::Value;
+use similar::TextDiff;
+use std::sync::Arc;
+use tokio::sync::mpsc::channel;
+
+/// Content generated by the model in a chat completion response, or in a chunk of the stream
+pub(crate) fn completion_content(message: &str) -> Option<String> {
+  let data = message.strip_prefix("data: ").unwrap_or(message);
+  let value = serde_json::from_str::<Value>(data.trim()).ok()?;
+  let choice = value.get("choices")?.get(0)?;
+  let content = choice
+    .get("message")
+    .or_else(|| choice.get("delta"))?
+    .get("content")?
+    .as_str()?;
+  Some(content.to_string())
+}
+
+/// Unified diff of the output of the model against the output of the canary model
+pub(crate) fn output_diff(
+  model: &str,
+  output: &str,
+  canary_model: &str,
+  canary_output: &str,
+) -> String {
+  TextDiff::from_lines(output, canary_output)
+    .unified_diff()
+    .header(model, canary_model)
+    .to_string()
+}
+
+// runs the request on the canary model alias once the client has the response,
+// and saves both the outputs for review using `bodhi eval canary report`
+pub(crate) async fn run_canary(
+  state: Arc<dyn RouterStateFn>,
+  mut request: ChatCompletionRequest,
+  canary_model: String,
+  output: String,
+) {
+  let model = request.model.clone();
+  let request_json = serde_json::to_string(&request).unwrap_or_default();
+  request.model.clone_from(&canary_model);
+  request.stream = Some(false);
+  // runs in the background, without delaying the interactive requests
+  request.set_priority(RequestPriority::Batch);
+  let (tx, mut rx) = channel::<String>(100);
+  let collector = tokio::spawn(async move {
+    let mut content = String::new();
+    while let Some(message) = rx.recv().await {
+      if let Some(text) = completion_content(&message) {
+        content.push_str(&text);
+      }
+    }
+    content
+  });
+  let result = state.chat_completions(request, tx).await;
+  let canary_output = collector.await.unwrap_or_default();
+  let mut sample = CanarySample {
+    diff: output_diff(&model, &output, &canary_model, &canary_output),
+    model,
+    canary_model,
+    request: request_json,
+    output,
+    canary_output,
+    canary_error: result.err().map(|err| err.to_string()),
+    ..Default::default()
+  };
+  if let Err(err) = state.db_service().save_canary_sample(&mut sample).await {
+    tracing::warn!(?err, "error saving the canary sample");
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{completion_content, output_diff};
+  use rstest::rstest;
+
+  #[rstest]
+  #[case(
+    r#"{"choices":[{"index":0,"message":{"role":"assistant","content":"Tuesday."}}]}"#,
+    Some("Tuesday.")
+  )]
+  #[case(
+    "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\" Tues\"}}]}\n\n",
+    Some(" Tues")
+  )]
+  #[case(
+    "data: {\"choices\":[{\"finish_reason\":\"stop\",\"index\":0,\"delta\":{}}]}\n\n",
+    None
+  )]
+  #[case("data: [DONE]\n\n", None)]
+  fn test_canary_completion_content(#[case] message: &str, #[case] expected: Option<&str>) {
+    assert_eq!(expected.map(str::to_string), completion_content(message));
+  }
+
+  #[rstest]
+  fn test_canary_output_diff() {
+    let diff = output_diff(
+      "llama3:instruct",
+      "Monday\nTuesday\n",
+      "phi3:mini",
+      "Monday\nWednesday\n",
+    );
+    assert_eq!(
+      "--- llama3:instruct\n+++ phi3:mini\n@@ -1,2 +1,2 @@\n Monday\n-Tuesday\n+Wednesday\n",
+      diff
+    );
+  }
+}
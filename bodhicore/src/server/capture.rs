@@ -0,0 +1,213 @@
+use crate::{
+  error::Common,
+  objs::{Alias, ChatCompletionRequest, HubFile},
+  service::TemplateLimits,
+  tokenizer_config::TokenizerConfig,
+  utils::to_safe_filename,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
+
+pub static DEBUG_DIR: &str = "debug";
+static LOGS_TAIL_LINES: usize = 200;
+
+/// Self-contained snapshot of a failed chat completion, written with `bodhi serve --capture-on-error`
+/// and reproduced with `bodhi replay <bundle>`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DebugBundle {
+  pub version: String,
+  pub created_at: DateTime<Utc>,
+  pub error: String,
+  pub request: ChatCompletionRequest,
+  /// alias config as yaml, same as in `$BODHI_HOME/aliases`
+  pub alias: String,
+  pub model_file: PathBuf,
+  pub tokenizer_file: PathBuf,
+  pub template_limits: TemplateLimits,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub rendered_prompt: Option<String>,
+  #[serde(default)]
+  pub logs_tail: Vec<String>,
+}
+
+impl DebugBundle {
+  #[allow(clippy::too_many_arguments)]
+  pub(crate) fn capture(
+    request: ChatCompletionRequest,
+    alias: &Alias,
+    model_file: PathBuf,
+    tokenizer_file: PathBuf,
+    template_limits: TemplateLimits,
+    error: String,
+    logs_dir: &Path,
+    created_at: DateTime<Utc>,
+  ) -> Result<Self, Common> {
+    let rendered_prompt = render_prompt(&request, alias, &tokenizer_file, &template_limits);
+    Ok(DebugBundle {
+      version: env!("CARGO_PKG_VERSION").to_string(),
+      created_at,
+      error,
+      request,
+      alias: serde_yaml::to_string(alias)?,
+      model_file,
+      tokenizer_file,
+      template_limits,
+      rendered_prompt,
+      logs_tail: logs_tail(logs_dir, LOGS_TAIL_LINES),
+    })
+  }
+
+  pub fn read(path: &Path) -> Result<Self, Common> {
+    let content = fs::read_to_string(path).map_err(|source| Common::IoFile {
+      source,
+      path: path.display().to_string(),
+    })?;
+    let bundle = serde_json::from_str::<DebugBundle>(&content)?;
+    Ok(bundle)
+  }
+
+  pub fn alias(&self) -> Result<Alias, Common> {
+    let alias = serde_yaml::from_str::<Alias>(&self.alias)?;
+    Ok(alias)
+  }
+
+  /// Writes the bundle to `$BODHI_HOME/debug/` and returns the path of the bundle file
+  pub(crate) fn write(&self, bodhi_home: &Path) -> Result<PathBuf, Common> {
+    let debug_dir = bodhi_home.join(DEBUG_DIR);
+    fs::create_dir_all(&debug_dir).map_err(|source| Common::IoDir {
+      source,
+      path: debug_dir.display().to_string(),
+    })?;
+    let model = self.request.request.model.replace(':', "--");
+    let filename = format!(
+      "{}-{}.json",
+      self.created_at.format("%Y%m%dT%H%M%S%3f"),
+      to_safe_filename(&model)
+    );
+    let path = debug_dir.join(filename);
+    let content =
+      serde_json::to_string_pretty(self).map_err(|source| Common::SerdeJsonSerialize {
+        source,
+        value: self.request.request.model.clone(),
+      })?;
+    fs::write(&path, content).map_err(|source| Common::IoFile {
+      source,
+      path: path.display().to_string(),
+    })?;
+    Ok(path)
+  }
+}
+
+fn render_prompt(
+  request: &ChatCompletionRequest,
+  alias: &Alias,
+  tokenizer_file: &Path,
+  template_limits: &TemplateLimits,
+) -> Option<String> {
+  let tokenizer_config = HubFile::try_from(tokenizer_file.to_path_buf())
+    .and_then(TokenizerConfig::try_from)
+    .ok()?;
+  let mut request = request.clone();
+  alias.request_params.update(&mut request);
+  alias.chat_template.update(&mut request);
+  tokenizer_config
+    .render_chat_template(&request.messages, template_limits)
+    .ok()
+}
+
+/// Last lines of the most recently written log file in the logs dir
+fn logs_tail(logs_dir: &Path, lines: usize) -> Vec<String> {
+  let latest = fs::read_dir(logs_dir)
+    .into_iter()
+    .flatten()
+    .filter_map(|entry| entry.ok())
+    .filter(|entry| entry.file_name().to_string_lossy().starts_with("bodhi.log"))
+    .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+    .max();
+  let Some((_, path)) = latest else {
+    return vec![];
+  };
+  let Ok(content) = fs::read_to_string(path) else {
+    return vec![];
+  };
+  let all_lines = content.lines().collect::<Vec<_>>();
+  all_lines[all_lines.len().saturating_sub(lines)..]
+    .iter()
+    .map(|line| line.to_string())
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+  use super::{logs_tail, DebugBundle, DEBUG_DIR};
+  use crate::{
+    objs::{Alias, ChatCompletionRequest},
+    service::TemplateLimits,
+  };
+  use chrono::{TimeZone, Utc};
+  use rstest::rstest;
+  use serde_json::json;
+  use std::{fs, path::PathBuf};
+  use tempfile::TempDir;
+
+  #[rstest]
+  fn test_debug_bundle_write_and_read() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let logs_dir = temp.path().join("logs");
+    fs::create_dir_all(&logs_dir)?;
+    fs::write(
+      logs_dir.join("bodhi.log.2024-05-01"),
+      "line 1\nline 2\nerror loading model\n",
+    )?;
+    let request = serde_json::from_value::<ChatCompletionRequest>(json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}]
+    }})?;
+    let bundle = DebugBundle::capture(
+      request.clone(),
+      &Alias::testalias(),
+      PathBuf::from("/tmp/testalias.Q8_0.gguf"),
+      PathBuf::from("/tmp/tokenizer_config.json"),
+      TemplateLimits::default(),
+      "error loading model".to_string(),
+      &logs_dir,
+      Utc.with_ymd_and_hms(2024, 5, 1, 10, 30, 0).unwrap(),
+    )?;
+    assert_eq!(None, bundle.rendered_prompt);
+    assert_eq!(
+      vec!["line 1", "line 2", "error loading model"],
+      bundle.logs_tail
+    );
+    let path = bundle.write(temp.path())?;
+    assert_eq!(
+      temp
+        .path()
+        .join(DEBUG_DIR)
+        .join("20240501T103000000-testalias--instruct.json"),
+      path
+    );
+    let read = DebugBundle::read(&path)?;
+    assert_eq!(bundle, read);
+    assert_eq!(request, read.request);
+    assert_eq!(Alias::testalias(), read.alias()?);
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_debug_bundle_logs_tail_keeps_last_lines() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let content = (1..=10)
+      .map(|i| format!("line {i}"))
+      .collect::<Vec<_>>()
+      .join("\n");
+    fs::write(temp.path().join("bodhi.log"), content)?;
+    fs::write(temp.path().join("other.txt"), "not a log")?;
+    assert_eq!(vec!["line 9", "line 10"], logs_tail(temp.path(), 2));
+    assert!(logs_tail(&temp.path().join("missing"), 2).is_empty());
+    Ok(())
+  }
+}
@@ -0,0 +1,624 @@
+use crate::{
+  db::{
+    objs::{Download, DownloadStatus},
+    DbError, DbServiceFn,
+  },
+  objs::Repo,
+  service::{AppServiceFn, DownloadProgress, DownloadSignal, HubServiceError},
+};
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+use tokio::sync::{
+  broadcast,
+  mpsc::{unbounded_channel, UnboundedSender},
+  watch, Semaphore,
+};
+
+// the queued or running downloads, by their id, with the sender of their pause or cancel signal
+type Running = HashMap<String, watch::Sender<DownloadSignal>>;
+
+// the progress is saved and sent to the listeners at most this often
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+// the events not yet received by a slow listener, the older ones are dropped for it
+const EVENTS_CAPACITY: usize = 256;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DownloadError {
+  #[error(transparent)]
+  Db(#[from] DbError),
+  #[error(transparent)]
+  Hub(#[from] HubServiceError),
+  #[error("download '{id}' is {status}, it cannot be {action}")]
+  InvalidState {
+    id: String,
+    status: DownloadStatus,
+    action: &'static str,
+  },
+}
+
+/// Pulls the model files into $HF_HOME in the background, one at a time in the order they are
+/// queued. The downloads and their progress are saved in the database, and sent to the listeners
+/// of [`DownloadManager::subscribe`] as they change.
+#[derive(Debug)]
+pub struct DownloadManager {
+  app_service: Arc<dyn AppServiceFn>,
+  db_service: Arc<dyn DbServiceFn>,
+  // the queued downloads wait for the permit of the running one
+  permit: Arc<Semaphore>,
+  // held while a download is checked and queued, paused or cancelled, so the checks hold till done
+  running: tokio::sync::Mutex<Running>,
+  events: broadcast::Sender<Download>,
+}
+
+impl DownloadManager {
+  pub fn new(app_service: Arc<dyn AppServiceFn>, db_service: Arc<dyn DbServiceFn>) -> Self {
+    let (events, _) = broadcast::channel(EVENTS_CAPACITY);
+    Self {
+      app_service,
+      db_service,
+      permit: Arc::new(Semaphore::new(1)),
+      running: tokio::sync::Mutex::new(HashMap::new()),
+      events,
+    }
+  }
+
+  /// The downloads as they change, from the time subscribed
+  pub fn subscribe(&self) -> broadcast::Receiver<Download> {
+    self.events.subscribe()
+  }
+
+  /// Queues the download of the file, or returns the download of the same file already queued
+  /// or running
+  pub async fn enqueue(
+    self: &Arc<Self>,
+    repo: &Repo,
+    filename: &str,
+    revision: &str,
+  ) -> Result<Download, DownloadError> {
+    let mut running = self.running.lock().await;
+    for id in running.keys() {
+      let download = self.db_service.get_download(id).await?;
+      if download.repo == repo.as_str()
+        && download.filename == filename
+        && download.revision == revision
+      {
+        return Ok(download);
+      }
+    }
+    let mut download = Download {
+      repo: repo.to_string(),
+      filename: filename.to_string(),
+      revision: revision.to_string(),
+      status: DownloadStatus::Queued,
+      ..Default::default()
+    };
+    self.db_service.create_download(&mut download).await?;
+    let signal_rx = register(&mut running, &download.id);
+    drop(running);
+    self.spawn(download.clone(), signal_rx);
+    Ok(download)
+  }
+
+  /// Most recent downloads first
+  pub async fn list(&self, limit: u32) -> Result<Vec<Download>, DownloadError> {
+    let running = self.running.lock().await;
+    let mut downloads = vec![];
+    for download in self.db_service.list_downloads(limit).await? {
+      downloads.push(self.check_interrupted(&running, download).await?);
+    }
+    Ok(downloads)
+  }
+
+  /// Fails with `RowNotFound` if the download does not exist
+  pub async fn get(&self, id: &str) -> Result<Download, DownloadError> {
+    let running = self.running.lock().await;
+    let download = self.db_service.get_download(id).await?;
+    self.check_interrupted(&running, download).await
+  }
+
+  /// Stops the queued or running download, keeping the part downloaded so far
+  pub async fn pause(&self, id: &str) -> Result<Download, DownloadError> {
+    let running = self.running.lock().await;
+    let download = self.db_service.get_download(id).await?;
+    let download = self.check_interrupted(&running, download).await?;
+    if !signal(&running, id, DownloadSignal::Pause) {
+      return Err(invalid_state(&download, "paused"));
+    }
+    Ok(download)
+  }
+
+  /// Queues the paused or failed download again, continuing from the part downloaded so far
+  pub async fn resume(self: &Arc<Self>, id: &str) -> Result<Download, DownloadError> {
+    let mut running = self.running.lock().await;
+    let mut download = self.stopped(&running, id, "resumed").await?;
+    let signal_rx = register(&mut running, &download.id);
+    download.status = DownloadStatus::Queued;
+    download.error = None;
+    self.save(&mut download).await;
+    drop(running);
+    self.spawn(download.clone(), signal_rx);
+    Ok(download)
+  }
+
+  /// Stops the download and removes the part downloaded so far
+  pub async fn cancel(&self, id: &str) -> Result<Download, DownloadError> {
+    let running = self.running.lock().await;
+    if signal(&running, id, DownloadSignal::Cancel) {
+      return Ok(self.db_service.get_download(id).await?);
+    }
+    let mut download = self.stopped(&running, id, "cancelled").await?;
+    let app_service = self.app_service.clone();
+    let (repo, filename, revision) = (
+      download.repo.clone(),
+      download.filename.clone(),
+      download.revision.clone(),
+    );
+    let removed = tokio::task::spawn_blocking(move || {
+      let repo = Repo::try_from(repo)?;
+      app_service
+        .hub_service()
+        .remove_incomplete(&repo, &filename, &revision)
+    })
+    .await;
+    match removed {
+      Ok(result) => result?,
+      Err(err) => {
+        tracing::warn!(?err, download_id = %download.id, "error removing the downloaded part");
+      }
+    }
+    download.status = DownloadStatus::Cancelled;
+    download.downloaded_bytes = 0;
+    self.save(&mut download).await;
+    Ok(download)
+  }
+
+  // the paused or failed download, fails if it is queued, running or done
+  async fn stopped(
+    &self,
+    running: &Running,
+    id: &str,
+    action: &'static str,
+  ) -> Result<Download, DownloadError> {
+    let download = self.db_service.get_download(id).await?;
+    let download = self.check_interrupted(running, download).await?;
+    if running.contains_key(id)
+      || !matches!(
+        download.status,
+        DownloadStatus::Paused | DownloadStatus::Failed
+      )
+    {
+      return Err(invalid_state(&download, action));
+    }
+    Ok(download)
+  }
+
+  // a queued or running download not run by this server was interrupted by a restart, it is
+  // marked paused so it can be resumed
+  async fn check_interrupted(
+    &self,
+    running: &Running,
+    mut download: Download,
+  ) -> Result<Download, DownloadError> {
+    if !matches!(
+      download.status,
+      DownloadStatus::Queued | DownloadStatus::Downloading
+    ) || running.contains_key(&download.id)
+    {
+      return Ok(download);
+    }
+    download.status = DownloadStatus::Paused;
+    self.db_service.update_download(&mut download).await?;
+    Ok(download)
+  }
+
+  fn spawn(self: &Arc<Self>, download: Download, signal_rx: watch::Receiver<DownloadSignal>) {
+    let manager = self.clone();
+    tokio::spawn(async move {
+      let mut download = manager.run(download, signal_rx).await;
+      // saved before it is unregistered, so it is not taken as interrupted in between
+      if let Err(err) = manager.db_service.update_download(&mut download).await {
+        tracing::warn!(?err, download_id = %download.id, "error saving the download");
+      }
+      manager.running.lock().await.remove(&download.id);
+      let _ = manager.events.send(download);
+    });
+  }
+
+  // the download once finished or stopped, not saved yet
+  async fn run(
+    &self,
+    mut download: Download,
+    mut signal_rx: watch::Receiver<DownloadSignal>,
+  ) -> Download {
+    let permit = tokio::select! {
+      permit = self.permit.clone().acquire_owned() => permit.ok(),
+      _ = signal_rx.wait_for(|signal| *signal != DownloadSignal::Continue) => None,
+    };
+    let Some(_permit) = permit else {
+      download.status = stopped_status(*signal_rx.borrow());
+      return download;
+    };
+    download.status = DownloadStatus::Downloading;
+    self.save(&mut download).await;
+
+    let (progress_tx, mut progress_rx) = unbounded_channel::<(u64, Option<u64>)>();
+    let app_service = self.app_service.clone();
+    let (repo, filename, revision) = (
+      download.repo.clone(),
+      download.filename.clone(),
+      download.revision.clone(),
+    );
+    let progress = ProgressReporter::new(signal_rx.clone(), progress_tx);
+    let handle = tokio::task::spawn_blocking(move || {
+      let repo = Repo::try_from(repo)?;
+      app_service
+        .hub_service()
+        .download_with_progress(&repo, &filename, &revision, &progress)
+    });
+    while let Some((downloaded, total)) = progress_rx.recv().await {
+      download.downloaded_bytes = downloaded;
+      download.total_bytes = total;
+      self.save(&mut download).await;
+    }
+    match handle.await {
+      Ok(Ok(hub_file)) => {
+        download.status = DownloadStatus::Completed;
+        if let Some(size) = hub_file.size {
+          download.downloaded_bytes = size;
+          download.total_bytes = Some(size);
+        }
+      }
+      Ok(Err(HubServiceError::DownloadStopped { .. })) => {
+        download.status = stopped_status(*signal_rx.borrow());
+        if download.status == DownloadStatus::Cancelled {
+          download.downloaded_bytes = 0;
+        }
+      }
+      Ok(Err(err)) => {
+        tracing::warn!(?err, download_id = %download.id, "error downloading the model file");
+        download.status = DownloadStatus::Failed;
+        download.error = Some(err.to_string());
+      }
+      Err(err) => {
+        download.status = DownloadStatus::Failed;
+        download.error = Some(err.to_string());
+      }
+    }
+    download
+  }
+
+  // the download runs in the background, so an error saving it is only logged
+  async fn save(&self, download: &mut Download) {
+    if let Err(err) = self.db_service.update_download(download).await {
+      tracing::warn!(?err, download_id = %download.id, "error saving the download");
+    }
+    let _ = self.events.send(download.clone());
+  }
+}
+
+// registers the download as queued or running, so it can be paused or cancelled
+fn register(running: &mut Running, id: &str) -> watch::Receiver<DownloadSignal> {
+  let (signal_tx, signal_rx) = watch::channel(DownloadSignal::Continue);
+  running.insert(id.to_string(), signal_tx);
+  signal_rx
+}
+
+// signals the queued or running download, false if it is not queued or running
+fn signal(running: &Running, id: &str, signal: DownloadSignal) -> bool {
+  match running.get(id) {
+    Some(signal_tx) => {
+      signal_tx.send_replace(signal);
+      true
+    }
+    None => false,
+  }
+}
+
+fn stopped_status(signal: DownloadSignal) -> DownloadStatus {
+  match signal {
+    DownloadSignal::Cancel => DownloadStatus::Cancelled,
+    _ => DownloadStatus::Paused,
+  }
+}
+
+fn invalid_state(download: &Download, action: &'static str) -> DownloadError {
+  DownloadError::InvalidState {
+    id: download.id.clone(),
+    status: download.status,
+    action,
+  }
+}
+
+// sends the progress of the download running on the blocking thread to its task, and tells the
+// download to stop once paused or cancelled
+struct ProgressReporter {
+  signal_rx: watch::Receiver<DownloadSignal>,
+  progress_tx: UnboundedSender<(u64, Option<u64>)>,
+  last_sent: Mutex<Option<Instant>>,
+}
+
+impl ProgressReporter {
+  fn new(
+    signal_rx: watch::Receiver<DownloadSignal>,
+    progress_tx: UnboundedSender<(u64, Option<u64>)>,
+  ) -> Self {
+    Self {
+      signal_rx,
+      progress_tx,
+      last_sent: Mutex::new(None),
+    }
+  }
+}
+
+impl DownloadProgress for ProgressReporter {
+  fn on_progress(&self, downloaded: u64, total: Option<u64>) -> DownloadSignal {
+    if let Ok(mut last_sent) = self.last_sent.lock() {
+      let done = total.is_some_and(|total| downloaded >= total);
+      if done || last_sent.is_none_or(|sent| sent.elapsed() >= PROGRESS_INTERVAL) {
+        let _ = self.progress_tx.send((downloaded, total));
+        *last_sent = Some(Instant::now());
+      }
+    }
+    *self.signal_rx.borrow()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{DownloadError, DownloadManager};
+  use crate::{
+    db::{
+      objs::{Download, DownloadStatus},
+      DbService, DbServiceFn,
+    },
+    objs::{HubFile, Repo},
+    service::{
+      AppServiceFn, DownloadSignal, HubServiceError, MockDataService, MockEnvServiceFn,
+      MockHubService,
+    },
+    test_utils::{db_service, AppServiceStubMock},
+  };
+  use chrono::{DateTime, Utc};
+  use mockall::predicate::eq;
+  use rstest::rstest;
+  use std::{sync::Arc, time::Duration};
+  use tempfile::TempDir;
+  use tokio::sync::broadcast::Receiver;
+
+  fn manager(hub_service: MockHubService, db_service: DbService) -> Arc<DownloadManager> {
+    let app_service: Arc<dyn AppServiceFn> = Arc::new(AppServiceStubMock::new(
+      MockEnvServiceFn::new(),
+      hub_service,
+      MockDataService::new(),
+    ));
+    Arc::new(DownloadManager::new(app_service, Arc::new(db_service)))
+  }
+
+  async fn wait_for(events: &mut Receiver<Download>, status: DownloadStatus) -> Download {
+    loop {
+      let download = tokio::time::timeout(Duration::from_secs(5), events.recv())
+        .await
+        .expect("download event not received")
+        .expect("download events closed");
+      if download.status == status {
+        return download;
+      }
+    }
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_download_manager_completes_the_download(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_tempdir, _now, db_service) = db_service;
+    let mut hub_service = MockHubService::new();
+    hub_service
+      .expect_download_with_progress()
+      .times(1)
+      .returning(|_, _, _, progress| {
+        progress.on_progress(11, Some(22));
+        progress.on_progress(22, Some(22));
+        Ok(HubFile::testalias())
+      });
+    let manager = manager(hub_service, db_service);
+    let mut events = manager.subscribe();
+    let download = manager
+      .enqueue(&Repo::testalias(), "testalias.Q8_0.gguf", "main")
+      .await?;
+    assert_eq!(DownloadStatus::Queued, download.status);
+    let completed = wait_for(&mut events, DownloadStatus::Completed).await;
+    assert_eq!(
+      (22, Some(22)),
+      (completed.downloaded_bytes, completed.total_bytes)
+    );
+    assert_eq!(completed, manager.get(&download.id).await?);
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_download_manager_records_the_failure(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_tempdir, _now, db_service) = db_service;
+    let mut hub_service = MockHubService::new();
+    hub_service
+      .expect_download_with_progress()
+      .times(1)
+      .returning(|_, _, _, _| {
+        Err(HubServiceError::NoMatchingFiles {
+          pattern: "testalias.Q8_0.gguf".to_string(),
+          repo: "MyFactory/testalias-gguf".to_string(),
+        })
+      });
+    let manager = manager(hub_service, db_service);
+    let mut events = manager.subscribe();
+    manager
+      .enqueue(&Repo::testalias(), "testalias.Q8_0.gguf", "main")
+      .await?;
+    let failed = wait_for(&mut events, DownloadStatus::Failed).await;
+    assert_eq!(
+      Some("no files matching 'testalias.Q8_0.gguf' found in huggingface repo 'MyFactory/testalias-gguf'"),
+      failed.error.as_deref()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_download_manager_pause_and_resume(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_tempdir, _now, db_service) = db_service;
+    let mut hub_service = MockHubService::new();
+    let mut seq = mockall::Sequence::new();
+    hub_service
+      .expect_download_with_progress()
+      .times(1)
+      .in_sequence(&mut seq)
+      .returning(|repo, filename, _, progress| loop {
+        if progress.on_progress(11, Some(22)) != DownloadSignal::Continue {
+          return Err(HubServiceError::DownloadStopped {
+            repo: repo.to_string(),
+            filename: filename.to_string(),
+          });
+        }
+        std::thread::sleep(Duration::from_millis(10));
+      });
+    hub_service
+      .expect_download_with_progress()
+      .times(1)
+      .in_sequence(&mut seq)
+      .returning(|_, _, _, _| Ok(HubFile::testalias()));
+    let manager = manager(hub_service, db_service);
+    let mut events = manager.subscribe();
+    let download = manager
+      .enqueue(&Repo::testalias(), "testalias.Q8_0.gguf", "main")
+      .await?;
+    wait_for(&mut events, DownloadStatus::Downloading).await;
+    manager.pause(&download.id).await?;
+    let paused = wait_for(&mut events, DownloadStatus::Paused).await;
+    assert_eq!(11, paused.downloaded_bytes);
+    assert!(matches!(
+      manager.pause(&download.id).await,
+      Err(DownloadError::InvalidState { .. })
+    ));
+    manager.resume(&download.id).await?;
+    wait_for(&mut events, DownloadStatus::Completed).await;
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_download_manager_marks_interrupted_downloads_paused(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_tempdir, _now, db_service) = db_service;
+    let mut interrupted = Download {
+      repo: "MyFactory/testalias-gguf".to_string(),
+      filename: "testalias.Q8_0.gguf".to_string(),
+      revision: "main".to_string(),
+      status: DownloadStatus::Downloading,
+      downloaded_bytes: 11,
+      ..Default::default()
+    };
+    db_service.create_download(&mut interrupted).await?;
+    let mut hub_service = MockHubService::new();
+    hub_service
+      .expect_remove_incomplete()
+      .with(eq(Repo::testalias()), eq("testalias.Q8_0.gguf"), eq("main"))
+      .times(1)
+      .returning(|_, _, _| Ok(()));
+    let manager = manager(hub_service, db_service);
+    let download = manager.get(&interrupted.id).await?;
+    assert_eq!(DownloadStatus::Paused, download.status);
+    let cancelled = manager.cancel(&interrupted.id).await?;
+    assert_eq!(
+      (DownloadStatus::Cancelled, 0),
+      (cancelled.status, cancelled.downloaded_bytes)
+    );
+    assert_eq!(vec![cancelled], manager.list(10).await?);
+    assert!(matches!(
+      manager.cancel(&interrupted.id).await,
+      Err(DownloadError::InvalidState { .. })
+    ));
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_download_manager_queues_the_file_once(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_tempdir, _now, db_service) = db_service;
+    let mut hub_service = MockHubService::new();
+    hub_service
+      .expect_download_with_progress()
+      .times(1)
+      .returning(|repo, filename, _, progress| loop {
+        if progress.on_progress(11, Some(22)) != DownloadSignal::Continue {
+          return Err(HubServiceError::DownloadStopped {
+            repo: repo.to_string(),
+            filename: filename.to_string(),
+          });
+        }
+        std::thread::sleep(Duration::from_millis(10));
+      });
+    let manager = manager(hub_service, db_service);
+    let mut events = manager.subscribe();
+    let repo = Repo::testalias();
+    let (first, second) = tokio::join!(
+      manager.enqueue(&repo, "testalias.Q8_0.gguf", "main"),
+      manager.enqueue(&repo, "testalias.Q8_0.gguf", "main"),
+    );
+    let download = first?;
+    assert_eq!(download.id, second?.id);
+    assert_eq!(1, manager.list(10).await?.len());
+    manager.pause(&download.id).await?;
+    wait_for(&mut events, DownloadStatus::Paused).await;
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_download_manager_resumes_once(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_tempdir, _now, db_service) = db_service;
+    let mut paused = Download {
+      repo: "MyFactory/testalias-gguf".to_string(),
+      filename: "testalias.Q8_0.gguf".to_string(),
+      revision: "main".to_string(),
+      status: DownloadStatus::Paused,
+      downloaded_bytes: 11,
+      ..Default::default()
+    };
+    db_service.create_download(&mut paused).await?;
+    let mut hub_service = MockHubService::new();
+    hub_service
+      .expect_download_with_progress()
+      .times(1)
+      .returning(|_, _, _, _| Ok(HubFile::testalias()));
+    let manager = manager(hub_service, db_service);
+    let mut events = manager.subscribe();
+    let (first, second) = tokio::join!(manager.resume(&paused.id), manager.resume(&paused.id));
+    assert_eq!(DownloadStatus::Queued, first?.status);
+    assert!(matches!(second, Err(DownloadError::InvalidState { .. })));
+    wait_for(&mut events, DownloadStatus::Completed).await;
+    assert!(matches!(
+      manager.resume(&paused.id).await,
+      Err(DownloadError::InvalidState { .. })
+    ));
+    Ok(())
+  }
+}
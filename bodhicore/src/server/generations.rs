@@ -0,0 +1,266 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+  collections::HashMap,
+  future::Future,
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+  },
+  time::Instant,
+};
+use strum::Display;
+use tokio::sync::watch;
+
+/// The chat completions in flight, by their generation id, so they can be cancelled
+/// using `POST /v1/requests/{id}/cancel`, and listed using `GET /api/ui/queue`
+#[derive(Debug, Default)]
+pub struct Generations {
+  next_seq: AtomicU64,
+  in_flight: Mutex<HashMap<String, InFlight>>,
+}
+
+#[derive(Debug)]
+struct InFlight {
+  seq: u64,
+  cancel_tx: watch::Sender<bool>,
+  alias: String,
+  /// name of the API key of the request when `BODHI_AUTH` is enabled
+  owner: Option<String>,
+  started_at: DateTime<Utc>,
+  start: Instant,
+  tokens: Arc<AtomicU64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Display, Serialize, Deserialize)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum GenerationStatus {
+  /// waiting for the model to be free, or processing the prompt
+  Queued,
+  Generating,
+}
+
+/// A chat completion in flight, as listed by `GET /api/ui/queue`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueuedGeneration {
+  /// the generation id, to cancel it using `POST /v1/requests/{id}/cancel`
+  pub id: String,
+  pub alias: String,
+  pub status: GenerationStatus,
+  pub started_at: DateTime<Utc>,
+  pub age_ms: u64,
+  /// tokens generated so far, the non-streamed completions report them once done
+  pub tokens: u64,
+}
+
+impl Generations {
+  /// Registers the generation of the owner till the returned guard is dropped. Returns None if a
+  /// generation with the id is already in flight, it is not replaced
+  pub(crate) fn start(
+    self: &Arc<Self>,
+    id: &str,
+    alias: &str,
+    owner: Option<String>,
+  ) -> Option<GenerationGuard> {
+    let mut in_flight = self.in_flight.lock().ok()?;
+    if in_flight.contains_key(id) {
+      return None;
+    }
+    let seq = self.next_seq.fetch_add(1, Ordering::SeqCst);
+    let (cancel_tx, cancel_rx) = watch::channel(false);
+    let tokens = Arc::new(AtomicU64::new(0));
+    in_flight.insert(
+      id.to_string(),
+      InFlight {
+        seq,
+        cancel_tx,
+        alias: alias.to_string(),
+        owner,
+        started_at: Utc::now(),
+        start: Instant::now(),
+        tokens: tokens.clone(),
+      },
+    );
+    Some(GenerationGuard {
+      generations: self.clone(),
+      id: id.to_string(),
+      cancelled: cancel_rx,
+      tokens,
+    })
+  }
+
+  /// The generations in flight, the oldest first
+  pub fn list(&self) -> Vec<QueuedGeneration> {
+    let Ok(in_flight) = self.in_flight.lock() else {
+      return vec![];
+    };
+    let mut generations = in_flight
+      .iter()
+      .map(|(id, generation)| {
+        let tokens = generation.tokens.load(Ordering::SeqCst);
+        let status = if tokens == 0 {
+          GenerationStatus::Queued
+        } else {
+          GenerationStatus::Generating
+        };
+        (
+          generation.seq,
+          QueuedGeneration {
+            id: id.clone(),
+            alias: generation.alias.clone(),
+            status,
+            started_at: generation.started_at,
+            age_ms: generation.start.elapsed().as_millis() as u64,
+            tokens,
+          },
+        )
+      })
+      .collect::<Vec<_>>();
+    generations.sort_by_key(|(seq, _)| *seq);
+    generations
+      .into_iter()
+      .map(|(_, generation)| generation)
+      .collect()
+  }
+
+  /// Cancels the generation, only of the owner if `owner` is set. Returns false if no generation
+  /// with the id is in flight, or it is of another owner
+  pub fn cancel(&self, id: &str, owner: Option<&str>) -> bool {
+    match self.in_flight.lock() {
+      Ok(in_flight) => match in_flight.get(id) {
+        Some(generation) if owner.is_none() || generation.owner.as_deref() == owner => {
+          generation.cancel_tx.send_replace(true);
+          true
+        }
+        _ => false,
+      },
+      Err(_) => false,
+    }
+  }
+
+  pub fn is_in_flight(&self, id: &str) -> bool {
+    self
+      .in_flight
+      .lock()
+      .map(|in_flight| in_flight.contains_key(id))
+      .unwrap_or(false)
+  }
+}
+
+pub(crate) struct GenerationGuard {
+  generations: Arc<Generations>,
+  id: String,
+  cancelled: watch::Receiver<bool>,
+  tokens: Arc<AtomicU64>,
+}
+
+impl GenerationGuard {
+  /// Counter of the tokens generated so far, listed by `GET /api/ui/queue`
+  pub(crate) fn tokens(&self) -> Arc<AtomicU64> {
+    self.tokens.clone()
+  }
+
+  /// Resolves once the generation is cancelled
+  pub(crate) fn cancelled(&self) -> impl Future<Output = ()> + Send + 'static {
+    let mut cancelled = self.cancelled.clone();
+    async move {
+      if cancelled.wait_for(|cancelled| *cancelled).await.is_err() {
+        std::future::pending::<()>().await;
+      }
+    }
+  }
+}
+
+impl Drop for GenerationGuard {
+  fn drop(&mut self) {
+    if let Ok(mut in_flight) = self.generations.in_flight.lock() {
+      in_flight.remove(&self.id);
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{GenerationStatus, Generations};
+  use rstest::rstest;
+  use std::{
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+  };
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_generations_cancel_in_flight() -> anyhow::Result<()> {
+    let generations = Arc::new(Generations::default());
+    let guard = generations
+      .start("req-1", "llama3:instruct", None)
+      .expect("generation should start");
+    assert!(generations.is_in_flight("req-1"));
+    assert!(generations.cancel("req-1", None));
+    tokio::time::timeout(Duration::from_secs(1), guard.cancelled()).await?;
+    drop(guard);
+    assert!(!generations.is_in_flight("req-1"));
+    assert!(!generations.cancel("req-1", None));
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_generations_cancel_only_of_owner() {
+    let generations = Arc::new(Generations::default());
+    let _guard = generations
+      .start("req-1", "llama3:instruct", Some("alice".to_string()))
+      .expect("generation should start");
+    assert!(!generations.cancel("req-1", Some("bob")));
+    assert!(generations.cancel("req-1", Some("alice")));
+    assert!(generations.cancel("req-1", None));
+  }
+
+  #[rstest]
+  fn test_generations_start_rejects_id_in_flight() {
+    let generations = Arc::new(Generations::default());
+    let first = generations
+      .start("req-1", "llama3:instruct", Some("alice".to_string()))
+      .expect("generation should start");
+    assert!(generations
+      .start("req-1", "tinyllama:instruct", Some("bob".to_string()))
+      .is_none());
+    assert!(!generations.cancel("req-1", Some("bob")));
+    assert_eq!("llama3:instruct", generations.list()[0].alias);
+    drop(first);
+    assert!(!generations.is_in_flight("req-1"));
+    assert!(generations
+      .start("req-1", "llama3:instruct", None)
+      .is_some());
+  }
+
+  #[rstest]
+  fn test_generations_list_oldest_first() {
+    let generations = Arc::new(Generations::default());
+    let first = generations
+      .start("req-1", "llama3:instruct", None)
+      .expect("generation should start");
+    let _second = generations
+      .start("req-2", "tinyllama:instruct", None)
+      .expect("generation should start");
+    first.tokens().fetch_add(3, Ordering::SeqCst);
+    let listed = generations.list();
+    assert_eq!(
+      vec![
+        ("req-1", "llama3:instruct", GenerationStatus::Generating, 3),
+        ("req-2", "tinyllama:instruct", GenerationStatus::Queued, 0),
+      ],
+      listed
+        .iter()
+        .map(|generation| (
+          generation.id.as_str(),
+          generation.alias.as_str(),
+          generation.status,
+          generation.tokens
+        ))
+        .collect::<Vec<_>>()
+    );
+    drop(first);
+    assert_eq!(1, generations.list().len());
+  }
+}
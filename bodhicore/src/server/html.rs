@@ -0,0 +1,108 @@
+use crate::db::objs::Conversation;
+
+static STYLE: &str = r#"
+body { font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", sans-serif; max-width: 48rem; margin: 2rem auto; padding: 0 1rem; color: #1f2937; }
+h1 { font-size: 1.5rem; }
+.meta { color: #6b7280; font-size: 0.875rem; }
+.message { border-radius: 0.5rem; padding: 0.75rem 1rem; margin: 0.75rem 0; white-space: pre-wrap; }
+.role { font-weight: 600; font-size: 0.75rem; text-transform: uppercase; color: #6b7280; margin-bottom: 0.25rem; }
+.user { background: #eff6ff; }
+.assistant { background: #f3f4f6; }
+.system { background: #fefce8; }
+"#;
+
+/// Renders the conversation as a standalone read-only HTML page
+pub(crate) fn conversation_html(conversation: &Conversation) -> String {
+  let title = if conversation.title.is_empty() {
+    "Untitled chat"
+  } else {
+    &conversation.title
+  };
+  let messages = conversation
+    .messages
+    .iter()
+    .map(|message| {
+      format!(
+        "<div class=\"message {}\"><div class=\"role\">{}</div>{}</div>\n",
+        escape_html(&message.role),
+        escape_html(&message.role),
+        escape_html(message.content.as_deref().unwrap_or_default())
+      )
+    })
+    .collect::<String>();
+  format!(
+    r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title}</title>
+<style>{STYLE}</style>
+</head>
+<body>
+<h1>{title}</h1>
+<p class="meta">Shared from Bodhi, {created_at}</p>
+{messages}</body>
+</html>
+"#,
+    title = escape_html(title),
+    created_at = conversation.created_at.format("%Y-%m-%d %H:%M UTC"),
+  )
+}
+
+pub(crate) fn escape_html(input: &str) -> String {
+  let mut output = String::with_capacity(input.len());
+  for c in input.chars() {
+    match c {
+      '&' => output.push_str("&amp;"),
+      '<' => output.push_str("&lt;"),
+      '>' => output.push_str("&gt;"),
+      '"' => output.push_str("&quot;"),
+      '\'' => output.push_str("&#39;"),
+      c => output.push(c),
+    }
+  }
+  output
+}
+
+#[cfg(test)]
+mod test {
+  use super::{conversation_html, escape_html};
+  use crate::db::objs::{ConversationBuilder, MessageBuilder};
+  use rstest::rstest;
+
+  #[rstest]
+  #[case("plain text", "plain text")]
+  #[case(
+    "<script>alert('x')</script>",
+    "&lt;script&gt;alert(&#39;x&#39;)&lt;/script&gt;"
+  )]
+  #[case(r#"a & "b""#, "a &amp; &quot;b&quot;")]
+  fn test_html_escape(#[case] input: &str, #[case] expected: &str) {
+    assert_eq!(expected, escape_html(input));
+  }
+
+  #[rstest]
+  fn test_html_conversation() -> anyhow::Result<()> {
+    let conversation = ConversationBuilder::default()
+      .title("Days <of> week")
+      .messages(vec![
+        MessageBuilder::default()
+          .role("user")
+          .content("What day comes after Monday?")
+          .build()?,
+        MessageBuilder::default()
+          .role("assistant")
+          .content("<b>Tuesday</b>")
+          .build()?,
+      ])
+      .build()?;
+    let html = conversation_html(&conversation);
+    assert!(html.contains("<title>Days &lt;of&gt; week</title>"));
+    assert!(html.contains(
+      r#"<div class="message user"><div class="role">user</div>What day comes after Monday?</div>"#
+    ));
+    assert!(html.contains("&lt;b&gt;Tuesday&lt;/b&gt;"));
+    Ok(())
+  }
+}
@@ -0,0 +1,345 @@
+use crate::objs::RequestPriority;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    RwLock,
+  },
+  time::{Duration, Instant},
+};
+use tokio::sync::Notify;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LastInference {
+  pub alias: String,
+  pub finished_at: DateTime<Utc>,
+  pub latency_ms: u64,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub error: Option<String>,
+}
+
+/// A hung or failed context force-stopped and reloaded by the watchdog
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WatchdogIncident {
+  pub at: DateTime<Utc>,
+  pub reason: String,
+  /// path of the model file reloaded, None if no model was loaded
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub model: Option<String>,
+  /// false if stopping or reloading the context failed, the server is then degraded
+  pub recovered: bool,
+}
+
+/// Tracks the inference requests of the server, reported by `/ready`,
+/// and the incidents of the watchdog, reported by `/health`
+#[derive(Debug)]
+pub struct InferenceMonitor {
+  started_at: Instant,
+  in_flight: AtomicUsize,
+  interactive: AtomicUsize,
+  // notified when the last interactive request is done, waking the waiting batch requests one
+  // at a time
+  interactive_done: Notify,
+  // the requests getting their response in a single chunk once generated, not checked for stalls
+  unstreamed: AtomicUsize,
+  last: RwLock<Option<LastInference>>,
+  // the last time a running request got a chunk from the context
+  last_progress: RwLock<Instant>,
+  // the failure of the context seen by a request, not yet handled by the watchdog
+  context_error: RwLock<Option<String>>,
+  incidents: AtomicUsize,
+  last_incident: RwLock<Option<WatchdogIncident>>,
+}
+
+impl Default for InferenceMonitor {
+  fn default() -> Self {
+    Self {
+      started_at: Instant::now(),
+      in_flight: AtomicUsize::new(0),
+      interactive: AtomicUsize::new(0),
+      interactive_done: Notify::new(),
+      unstreamed: AtomicUsize::new(0),
+      last: RwLock::new(None),
+      last_progress: RwLock::new(Instant::now()),
+      context_error: RwLock::new(None),
+      incidents: AtomicUsize::new(0),
+      last_incident: RwLock::new(None),
+    }
+  }
+}
+
+impl InferenceMonitor {
+  pub fn uptime_secs(&self) -> u64 {
+    self.started_at.elapsed().as_secs()
+  }
+
+  /// Number of inference requests running, or waiting for the model to be free, the batch
+  /// requests are counted once done waiting for the interactive ones
+  pub fn queue_depth(&self) -> usize {
+    self.in_flight.load(Ordering::SeqCst)
+  }
+
+  /// Number of the interactive requests running, or waiting for the model to be free,
+  /// the batch requests wait till there are none
+  pub fn interactive_depth(&self) -> usize {
+    self.interactive.load(Ordering::SeqCst)
+  }
+
+  /// Waits till no interactive request is using the model, for a batch request to use its
+  /// idle time
+  pub(crate) async fn yield_to_interactive(&self) {
+    let mut woken = false;
+    loop {
+      // created before the check, so the notification of the last request done is not missed
+      let done = self.interactive_done.notified();
+      if self.interactive_depth() == 0 {
+        // wakes the next waiting batch request, to check again for an interactive one started
+        // meanwhile
+        if woken {
+          self.interactive_done.notify_one();
+        }
+        return;
+      }
+      done.await;
+      woken = true;
+    }
+  }
+
+  pub fn last(&self) -> Option<LastInference> {
+    self.last.read().ok().and_then(|last| last.clone())
+  }
+
+  /// Number of contexts force-stopped and reloaded by the watchdog since the start
+  pub fn incidents(&self) -> usize {
+    self.incidents.load(Ordering::SeqCst)
+  }
+
+  pub fn last_incident(&self) -> Option<WatchdogIncident> {
+    self
+      .last_incident
+      .read()
+      .ok()
+      .and_then(|last_incident| last_incident.clone())
+  }
+
+  /// Counts the request as in flight, `streamed` if the context sends the chunks as the tokens
+  /// are generated, instead of the whole response once done
+  pub(crate) fn start(
+    &self,
+    alias: &str,
+    priority: RequestPriority,
+    streamed: bool,
+  ) -> InferenceGuard<'_> {
+    // the wait for progress starts with the first request, the queued ones wait for it
+    if self.in_flight.fetch_add(1, Ordering::SeqCst) == 0 {
+      self.progress();
+    }
+    if priority == RequestPriority::Interactive {
+      self.interactive.fetch_add(1, Ordering::SeqCst);
+    }
+    if !streamed {
+      self.unstreamed.fetch_add(1, Ordering::SeqCst);
+    }
+    InferenceGuard {
+      monitor: self,
+      alias: alias.to_string(),
+      priority,
+      streamed,
+      start: Instant::now(),
+    }
+  }
+
+  /// Records a chunk generated by the context for a running request
+  pub(crate) fn progress(&self) {
+    if let Ok(mut last_progress) = self.last_progress.write() {
+      *last_progress = Instant::now();
+    }
+  }
+
+  /// The time since the last progress, if the requests have been running without any progress
+  /// for longer than `timeout`. Not checked while an unstreamed request is in flight, it sends no
+  /// chunk till its whole response is generated, which can take long on CPU.
+  pub(crate) fn stalled(&self, timeout: Duration) -> Option<Duration> {
+    if self.queue_depth() == 0 || self.unstreamed.load(Ordering::SeqCst) > 0 {
+      return None;
+    }
+    let elapsed = self.last_progress.read().ok()?.elapsed();
+    (elapsed >= timeout).then_some(elapsed)
+  }
+
+  /// Records a failure of the context itself, e.g. a crash of the inference worker, for the
+  /// watchdog to reload the model
+  pub(crate) fn context_failed(&self, error: String) {
+    if let Ok(mut context_error) = self.context_error.write() {
+      *context_error = Some(error);
+    }
+  }
+
+  pub(crate) fn take_context_error(&self) -> Option<String> {
+    self
+      .context_error
+      .write()
+      .ok()
+      .and_then(|mut context_error| context_error.take())
+  }
+
+  pub(crate) fn record_incident(&self, incident: WatchdogIncident) {
+    self.incidents.fetch_add(1, Ordering::SeqCst);
+    if let Ok(mut last_incident) = self.last_incident.write() {
+      *last_incident = Some(incident);
+    }
+    // the requests stuck on the stopped context are not reported again
+    self.progress();
+  }
+}
+
+// counts the request as in flight until dropped, also when the request is cancelled
+pub(crate) struct InferenceGuard<'a> {
+  monitor: &'a InferenceMonitor,
+  alias: String,
+  priority: RequestPriority,
+  streamed: bool,
+  start: Instant,
+}
+
+impl InferenceGuard<'_> {
+  pub(crate) fn finish(self, error: Option<String>) {
+    let last = LastInference {
+      alias: self.alias.clone(),
+      finished_at: Utc::now(),
+      latency_ms: self.start.elapsed().as_millis() as u64,
+      error,
+    };
+    if let Ok(mut current) = self.monitor.last.write() {
+      *current = Some(last);
+    }
+  }
+}
+
+impl Drop for InferenceGuard<'_> {
+  fn drop(&mut self) {
+    self.monitor.in_flight.fetch_sub(1, Ordering::SeqCst);
+    if self.priority == RequestPriority::Interactive
+      && self.monitor.interactive.fetch_sub(1, Ordering::SeqCst) == 1
+    {
+      self.monitor.interactive_done.notify_one();
+    }
+    if !self.streamed {
+      self.monitor.unstreamed.fetch_sub(1, Ordering::SeqCst);
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{InferenceMonitor, WatchdogIncident};
+  use crate::objs::RequestPriority;
+  use chrono::Utc;
+  use rstest::rstest;
+  use std::time::Duration;
+
+  #[rstest]
+  fn test_inference_monitor_tracks_queue_depth_and_last() {
+    let monitor = InferenceMonitor::default();
+    let first = monitor.start("llama3:instruct", RequestPriority::Interactive, true);
+    let second = monitor.start("tinyllama:instruct", RequestPriority::Interactive, true);
+    assert_eq!(2, monitor.queue_depth());
+    drop(first);
+    assert_eq!(1, monitor.queue_depth());
+    assert_eq!(None, monitor.last());
+    second.finish(Some("test error".to_string()));
+    assert_eq!(0, monitor.queue_depth());
+    let last = monitor.last().expect("last inference should be recorded");
+    assert_eq!("tinyllama:instruct", last.alias);
+    assert_eq!(Some("test error".to_string()), last.error);
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_inference_monitor_batch_yields_to_interactive() -> anyhow::Result<()> {
+    let monitor = InferenceMonitor::default();
+    tokio::time::timeout(Duration::from_secs(1), monitor.yield_to_interactive()).await?;
+    let _batch = monitor.start("llama3:instruct", RequestPriority::Batch, true);
+    assert_eq!(0, monitor.interactive_depth());
+    let interactive = monitor.start("llama3:instruct", RequestPriority::Interactive, true);
+    assert_eq!((2, 1), (monitor.queue_depth(), monitor.interactive_depth()));
+    let waiting = tokio::time::timeout(Duration::from_millis(100), monitor.yield_to_interactive());
+    assert!(waiting.await.is_err());
+    let waiting = monitor.yield_to_interactive();
+    tokio::pin!(waiting);
+    assert!(futures_util::poll!(&mut waiting).is_pending());
+    drop(interactive);
+    tokio::time::timeout(Duration::from_millis(100), waiting).await?;
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_inference_monitor_wakes_batch_requests_one_at_a_time() -> anyhow::Result<()> {
+    let monitor = InferenceMonitor::default();
+    let interactive = monitor.start("llama3:instruct", RequestPriority::Interactive, true);
+    let first = monitor.yield_to_interactive();
+    let second = monitor.yield_to_interactive();
+    tokio::pin!(first);
+    tokio::pin!(second);
+    assert!(futures_util::poll!(&mut first).is_pending());
+    assert!(futures_util::poll!(&mut second).is_pending());
+    drop(interactive);
+    assert!(futures_util::poll!(&mut second).is_pending());
+    let interactive = monitor.start("llama3:instruct", RequestPriority::Interactive, true);
+    assert!(futures_util::poll!(&mut first).is_pending());
+    drop(interactive);
+    assert!(futures_util::poll!(&mut second).is_ready());
+    tokio::time::timeout(Duration::from_millis(100), first).await?;
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_inference_monitor_detects_stalled_requests() {
+    let monitor = InferenceMonitor::default();
+    assert_eq!(None, monitor.stalled(Duration::ZERO));
+    let inference = monitor.start("llama3:instruct", RequestPriority::Interactive, true);
+    assert_eq!(None, monitor.stalled(Duration::from_secs(60)));
+    assert!(monitor.stalled(Duration::ZERO).is_some());
+    monitor.record_incident(WatchdogIncident {
+      at: Utc::now(),
+      reason: "no progress".to_string(),
+      model: None,
+      recovered: true,
+    });
+    assert_eq!(1, monitor.incidents());
+    assert_eq!(
+      "no progress",
+      monitor
+        .last_incident()
+        .expect("incident to be recorded")
+        .reason
+    );
+    drop(inference);
+    assert_eq!(None, monitor.stalled(Duration::ZERO));
+  }
+
+  #[rstest]
+  fn test_inference_monitor_skips_stall_check_of_unstreamed_requests() {
+    let monitor = InferenceMonitor::default();
+    let streamed = monitor.start("llama3:instruct", RequestPriority::Interactive, true);
+    let unstreamed = monitor.start("llama3:instruct", RequestPriority::Batch, false);
+    assert_eq!(None, monitor.stalled(Duration::ZERO));
+    drop(unstreamed);
+    assert!(monitor.stalled(Duration::ZERO).is_some());
+    drop(streamed);
+    assert_eq!(None, monitor.stalled(Duration::ZERO));
+  }
+
+  #[rstest]
+  fn test_inference_monitor_takes_context_error_once() {
+    let monitor = InferenceMonitor::default();
+    monitor.context_failed("worker crashed".to_string());
+    assert_eq!(
+      Some("worker crashed".to_string()),
+      monitor.take_context_error()
+    );
+    assert_eq!(None, monitor.take_context_error());
+  }
+}
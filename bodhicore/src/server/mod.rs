@@ -1,14 +1,65 @@
+mod access_log;
+mod alias_check;
+mod alias_watcher;
+mod auth;
+mod batches;
+mod canary;
+mod capture;
+mod downloads;
+mod generations;
+mod html;
+mod inference_monitor;
+mod response_cache;
 mod router_state;
 mod routes;
+mod routes_aliases;
+mod routes_audit;
+mod routes_batches;
 mod routes_chat;
+mod routes_downloads;
+mod routes_health;
+mod routes_info;
 mod routes_models;
+mod routes_ollama;
+mod routes_requests;
+mod routes_rerank;
+mod routes_settings;
+mod routes_share;
+mod routes_static;
+mod routes_status;
+mod routes_tokens;
 mod routes_ui;
+mod routes_usage;
+mod routes_validate;
 #[allow(clippy::module_inception)]
 mod server;
 mod shutdown;
+#[cfg(unix)]
+mod systemd;
+#[cfg(unix)]
+mod uds;
 mod utils;
+mod watchdog;
+pub use crate::server::access_log::{AccessLog, ACCESS_LOG_FILE};
+pub use crate::server::alias_check::{
+  check_aliases, AliasCheckStatus, AliasQuarantine, QuarantinedAlias,
+};
+pub use crate::server::alias_watcher::watch_aliases;
+pub use crate::server::batches::{
+  Batch, BatchRequestCounts, BatchRequestInput, BatchRequestOutput, BatchStatus, BatchStore,
+  FileObject, BATCHES_DIR,
+};
+pub use crate::server::capture::{DebugBundle, DEBUG_DIR};
+pub use crate::server::downloads::{DownloadError, DownloadManager};
+pub use crate::server::generations::{GenerationStatus, Generations, QueuedGeneration};
+pub use crate::server::inference_monitor::{InferenceMonitor, LastInference, WatchdogIncident};
+pub use crate::server::response_cache::ResponseCache;
 pub use crate::server::router_state::{RouterState, RouterStateFn};
 pub use crate::server::routes::build_routes;
+pub use crate::server::routes_static::static_dir_router;
 pub use crate::server::server::*;
 pub use crate::server::shutdown::shutdown_signal;
+#[cfg(unix)]
+pub use crate::server::systemd::{activated_listener, notify_ready, notify_stopping};
 pub use crate::server::utils::AxumRequestExt;
+pub use crate::server::watchdog::run_watchdog;
@@ -0,0 +1,242 @@
+use crate::{
+  objs::{Alias, ChatCompletionRequest},
+  service::ResponseCacheSettings,
+};
+use std::{
+  collections::{HashMap, VecDeque},
+  sync::Mutex,
+  time::{Duration, Instant},
+};
+
+/// Responses of the deterministic chat completions, by the request they were generated for,
+/// configured using the `response_cache` section of $BODHI_HOME/settings.yaml
+#[derive(Debug, Default)]
+pub struct ResponseCache {
+  settings: ResponseCacheSettings,
+  entries: Mutex<CacheEntries>,
+}
+
+#[derive(Debug, Default)]
+struct CacheEntries {
+  responses: HashMap<String, (Instant, String)>,
+  // keys in the order they were inserted, the oldest first
+  order: VecDeque<String>,
+}
+
+impl ResponseCache {
+  pub fn new(settings: ResponseCacheSettings) -> Self {
+    Self {
+      settings,
+      entries: Mutex::new(CacheEntries::default()),
+    }
+  }
+
+  /// If the response of the request can be cached, only for the non-streamed requests with
+  /// `temperature: 0` and a single choice
+  pub(crate) fn caches(&self, request: &ChatCompletionRequest) -> bool {
+    self.settings.enabled
+      && !request.stream.unwrap_or(false)
+      && request.temperature == Some(0.0)
+      && request.n.unwrap_or(1) == 1
+  }
+
+  /// Key of the request if its response can be cached. The key is the whole request, with its
+  /// pipeline applied, and the config of the alias serving it, so it covers the messages, the
+  /// sampling params, the seed, and the model file, params and chat template of the alias.
+  pub(crate) fn key(&self, request: &ChatCompletionRequest, alias: &Alias) -> Option<String> {
+    if !self.caches(request) {
+      return None;
+    }
+    serde_json::to_string(&(request, alias)).ok()
+  }
+
+  /// Drops all the cached responses, when the model aliases change
+  pub fn clear(&self) {
+    if let Ok(mut entries) = self.entries.lock() {
+      entries.responses.clear();
+      entries.order.clear();
+    }
+  }
+
+  /// The cached response, if it has not expired
+  pub(crate) fn get(&self, key: &str) -> Option<String> {
+    let ttl = Duration::from_secs(self.settings.ttl_secs);
+    let mut entries = self.entries.lock().ok()?;
+    match entries.responses.get(key) {
+      Some((inserted, response)) if inserted.elapsed() < ttl => Some(response.clone()),
+      Some(_) => {
+        entries.remove(key);
+        None
+      }
+      None => None,
+    }
+  }
+
+  /// Caches the response, evicting the oldest responses over `max_entries`
+  pub(crate) fn put(&self, key: String, response: String) {
+    if self.settings.max_entries == 0 {
+      return;
+    }
+    let Ok(mut entries) = self.entries.lock() else {
+      return;
+    };
+    entries.remove(&key);
+    entries.order.push_back(key.clone());
+    entries.responses.insert(key, (Instant::now(), response));
+    while entries.order.len() > self.settings.max_entries {
+      if let Some(oldest) = entries.order.pop_front() {
+        entries.responses.remove(&oldest);
+      }
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self
+      .entries
+      .lock()
+      .map(|entries| entries.responses.len())
+      .unwrap_or_default()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+}
+
+impl CacheEntries {
+  fn remove(&mut self, key: &str) {
+    if self.responses.remove(key).is_some() {
+      self.order.retain(|existing| existing != key);
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::ResponseCache;
+  use crate::{
+    objs::{Alias, ChatCompletionRequest, GptContextParams},
+    service::ResponseCacheSettings,
+  };
+  use rstest::rstest;
+  use serde_json::json;
+
+  fn cache(ttl_secs: u64, max_entries: usize) -> ResponseCache {
+    ResponseCache::new(ResponseCacheSettings {
+      enabled: true,
+      ttl_secs,
+      max_entries,
+    })
+  }
+
+  fn request(extra: serde_json::Value) -> anyhow::Result<ChatCompletionRequest> {
+    let mut request = json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+      "temperature": 0.0,
+      "seed": 42
+    }};
+    if let (Some(request), Some(extra)) = (request.as_object_mut(), extra.as_object()) {
+      request.extend(extra.clone());
+    }
+    Ok(serde_json::from_value(request)?)
+  }
+
+  #[rstest]
+  #[case(json! {{}}, true)]
+  #[case(json! {{"n": 1, "stream": false}}, true)]
+  #[case(json! {{"temperature": 0.7}}, false)]
+  #[case(json! {{"temperature": null}}, false)]
+  #[case(json! {{"stream": true}}, false)]
+  #[case(json! {{"n": 2}}, false)]
+  fn test_response_cache_key_only_for_deterministic_requests(
+    #[case] extra: serde_json::Value,
+    #[case] expected: bool,
+  ) -> anyhow::Result<()> {
+    assert_eq!(
+      expected,
+      cache(60, 10)
+        .key(&request(extra)?, &Alias::testalias())
+        .is_some()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_response_cache_key_disabled_by_default() -> anyhow::Result<()> {
+    assert_eq!(
+      None,
+      ResponseCache::default().key(&request(json! {{}})?, &Alias::testalias())
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_response_cache_key_covers_the_seed() -> anyhow::Result<()> {
+    let cache = cache(60, 10);
+    let alias = Alias::testalias();
+    let key = cache.key(&request(json! {{}})?, &alias);
+    assert_eq!(key, cache.key(&request(json! {{}})?, &alias));
+    assert_ne!(key, cache.key(&request(json! {{"seed": 7}})?, &alias));
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_response_cache_key_covers_the_alias_config() -> anyhow::Result<()> {
+    let cache = cache(60, 10);
+    let request = request(json! {{}})?;
+    let alias = Alias::testalias();
+    let changed = Alias {
+      context_params: GptContextParams {
+        n_ctx: Some(4096),
+        ..Default::default()
+      },
+      ..Alias::testalias()
+    };
+    assert_ne!(cache.key(&request, &alias), cache.key(&request, &changed));
+    let pinned = Alias {
+      snapshot: "0123456789abcdef".to_string(),
+      ..Alias::testalias()
+    };
+    assert_ne!(cache.key(&request, &alias), cache.key(&request, &pinned));
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_response_cache_clear() {
+    let cache = cache(60, 10);
+    cache.put("key".to_string(), "response".to_string());
+    cache.clear();
+    assert!(cache.is_empty());
+    assert_eq!(None, cache.get("key"));
+  }
+
+  #[rstest]
+  fn test_response_cache_get_and_put() {
+    let cache = cache(60, 10);
+    assert_eq!(None, cache.get("key"));
+    cache.put("key".to_string(), "response".to_string());
+    assert_eq!(Some("response".to_string()), cache.get("key"));
+  }
+
+  #[rstest]
+  fn test_response_cache_expires_after_ttl() {
+    let cache = cache(0, 10);
+    cache.put("key".to_string(), "response".to_string());
+    assert_eq!(None, cache.get("key"));
+    assert!(cache.is_empty());
+  }
+
+  #[rstest]
+  fn test_response_cache_evicts_the_oldest() {
+    let cache = cache(60, 2);
+    cache.put("first".to_string(), "1".to_string());
+    cache.put("second".to_string(), "2".to_string());
+    cache.put("first".to_string(), "1".to_string());
+    cache.put("third".to_string(), "3".to_string());
+    assert_eq!(2, cache.len());
+    assert_eq!(None, cache.get("second"));
+    assert_eq!(Some("1".to_string()), cache.get("first"));
+    assert_eq!(Some("3".to_string()), cache.get("third"));
+  }
+}
@@ -1,15 +1,31 @@
+use super::{
+  alias_check::AliasQuarantine,
+  capture::DebugBundle,
+  downloads::DownloadManager,
+  generations::Generations,
+  inference_monitor::{InferenceGuard, InferenceMonitor},
+  response_cache::ResponseCache,
+};
 use crate::{
   db::DbServiceFn,
   oai::OpenAIApiError,
-  objs::{REFS_MAIN, TOKENIZER_CONFIG_JSON},
-  service::AppServiceFn,
-  shared_rw::SharedContextRwFn,
+  objs::{
+    Alias, AliasFeature, ChatCompletionRequest, HubFile, Pipeline, ReasoningFormat,
+    ReasoningSplitter, RequestPriority, RerankRequest, RerankResponse, RerankScores, REFS_MAIN,
+    TOKENIZER_CONFIG_JSON,
+  },
+  service::{AppServiceFn, TemplateLimits},
+  shared_rw::{ContextError, SharedContextRwFn},
   Repo,
 };
-use async_openai::types::CreateChatCompletionRequest;
 use axum::async_trait;
-use std::sync::Arc;
-use tokio::sync::mpsc::Sender;
+use chrono::Utc;
+use std::{path::PathBuf, sync::Arc};
+use tokio::{
+  sync::mpsc::{channel, Sender},
+  task::JoinHandle,
+};
+use uuid::Uuid;
 
 #[async_trait]
 pub trait RouterStateFn: Send + Sync {
@@ -17,11 +33,27 @@ pub trait RouterStateFn: Send + Sync {
 
   fn db_service(&self) -> Arc<dyn DbServiceFn>;
 
+  fn quarantine(&self) -> Arc<AliasQuarantine>;
+
+  fn inference_monitor(&self) -> Arc<InferenceMonitor>;
+
+  fn generations(&self) -> Arc<Generations>;
+
+  fn response_cache(&self) -> Arc<ResponseCache>;
+
+  fn downloads(&self) -> Arc<DownloadManager>;
+
+  /// Path of the model file loaded in the context, if any
+  async fn loaded_model(&self) -> Option<String>;
+
   async fn chat_completions(
     &self,
-    request: CreateChatCompletionRequest,
+    request: ChatCompletionRequest,
     userdata: Sender<String>,
   ) -> crate::oai::Result<()>;
+
+  /// Scores the documents of the request against its query, using the reranker model alias
+  async fn rerank(&self, request: RerankRequest) -> crate::oai::Result<RerankResponse>;
 }
 
 #[derive(Debug, Clone)]
@@ -29,6 +61,12 @@ pub struct RouterState {
   pub(crate) ctx: Arc<dyn SharedContextRwFn>,
   pub(crate) app_service: Arc<dyn AppServiceFn>,
   pub(crate) db_service: Arc<dyn DbServiceFn>,
+  pub(crate) quarantine: Arc<AliasQuarantine>,
+  pub(crate) inference_monitor: Arc<InferenceMonitor>,
+  pub(crate) generations: Arc<Generations>,
+  pub(crate) response_cache: Arc<ResponseCache>,
+  pub(crate) downloads: Arc<DownloadManager>,
+  pub(crate) capture_on_error: bool,
 }
 
 impl RouterState {
@@ -37,12 +75,42 @@ impl RouterState {
     app_service: Arc<dyn AppServiceFn>,
     db_service: Arc<dyn DbServiceFn>,
   ) -> Self {
+    let downloads = Arc::new(DownloadManager::new(
+      app_service.clone(),
+      db_service.clone(),
+    ));
     Self {
       ctx,
       app_service,
       db_service,
+      quarantine: Arc::new(AliasQuarantine::default()),
+      inference_monitor: Arc::new(InferenceMonitor::default()),
+      generations: Arc::new(Generations::default()),
+      response_cache: Arc::new(ResponseCache::default()),
+      downloads,
+      capture_on_error: false,
     }
   }
+
+  pub(crate) fn with_quarantine(mut self, quarantine: Arc<AliasQuarantine>) -> Self {
+    self.quarantine = quarantine;
+    self
+  }
+
+  pub(crate) fn with_inference_monitor(mut self, inference_monitor: Arc<InferenceMonitor>) -> Self {
+    self.inference_monitor = inference_monitor;
+    self
+  }
+
+  pub(crate) fn with_response_cache(mut self, response_cache: Arc<ResponseCache>) -> Self {
+    self.response_cache = response_cache;
+    self
+  }
+
+  pub(crate) fn with_capture_on_error(mut self, capture_on_error: bool) -> Self {
+    self.capture_on_error = capture_on_error;
+    self
+  }
 }
 
 #[async_trait]
@@ -55,25 +123,118 @@ impl RouterStateFn for RouterState {
     self.db_service.clone()
   }
 
+  fn quarantine(&self) -> Arc<AliasQuarantine> {
+    self.quarantine.clone()
+  }
+
+  fn inference_monitor(&self) -> Arc<InferenceMonitor> {
+    self.inference_monitor.clone()
+  }
+
+  fn generations(&self) -> Arc<Generations> {
+    self.generations.clone()
+  }
+
+  fn response_cache(&self) -> Arc<ResponseCache> {
+    self.response_cache.clone()
+  }
+
+  fn downloads(&self) -> Arc<DownloadManager> {
+    self.downloads.clone()
+  }
+
+  async fn loaded_model(&self) -> Option<String> {
+    match self.ctx.get_gpt_params().await {
+      Ok(gpt_params) => gpt_params.map(|gpt_params| gpt_params.model),
+      Err(err) => {
+        tracing::warn!(?err, "error reading the params of the loaded model");
+        None
+      }
+    }
+  }
+
+  #[tracing::instrument(skip_all, fields(model = %request.model))]
   async fn chat_completions(
     &self,
-    request: CreateChatCompletionRequest,
+    mut request: ChatCompletionRequest,
     userdata: Sender<String>,
   ) -> crate::oai::Result<()> {
+    if let Some(name) = Pipeline::name_of(&request.model) {
+      let pipeline = self
+        .app_service
+        .data_service()
+        .find_pipeline(name)
+        .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))?
+        .ok_or_else(|| OpenAIApiError::ModelNotFound(request.model.clone()))?;
+      pipeline.apply(&mut request);
+    }
+    // the alias is resolved first, so a request for an unknown model fails as not found
     let Some(alias) = self.app_service.data_service().find_alias(&request.model) else {
-      return Err(crate::oai::OpenAIApiError::ModelNotFound(request.model));
+      return Err(OpenAIApiError::ModelNotFound(request.request.model));
     };
-    let model_file = self
-      .app_service
-      .hub_service()
-      .find_local_file(&alias.repo, &alias.filename, &alias.snapshot)
-      .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))?;
-    let Some(model_file) = model_file else {
-      return Err(OpenAIApiError::InternalServer(format!(
-        "file required by LLM model not found in huggingface cache: filename: '{}', repo: '{}'",
-        alias.filename, alias.repo
-      )));
+    request
+      .llama_logit_bias()
+      .map_err(OpenAIApiError::BadRequest)?;
+    self
+      .chat_completions_with_alias(request, alias, userdata)
+      .await
+  }
+
+  #[tracing::instrument(skip_all, fields(model = %request.model))]
+  async fn rerank(&self, request: RerankRequest) -> crate::oai::Result<RerankResponse> {
+    request.validate().map_err(OpenAIApiError::BadRequest)?;
+    let Some(alias) = self.app_service.data_service().find_alias(&request.model) else {
+      return Err(OpenAIApiError::ModelNotFound(request.model));
     };
+    if !alias.supports(AliasFeature::Rerank) {
+      return Err(OpenAIApiError::ModelUnsupported(
+        request.model,
+        AliasFeature::Rerank,
+      ));
+    }
+    let model_file = self.model_file(&alias)?;
+    let (tx, mut rx) = channel::<String>(1);
+    let inference = self
+      .inference_monitor
+      .start(&alias.alias, RequestPriority::Interactive, false);
+    let (tx, progress) = self.with_progress(tx, None);
+    let result = self
+      .ctx
+      .rerank(request.clone(), alias, model_file, tx)
+      .await;
+    let _ = progress.await;
+    self.finish_inference(inference, &result);
+    result.map_err(OpenAIApiError::ContextError)?;
+    let message = rx.recv().await.ok_or_else(|| {
+      OpenAIApiError::InternalServer("receiver stream abruptly closed".to_string())
+    })?;
+    let scores = serde_json::from_str::<RerankScores>(&message)
+      .map_err(|err| OpenAIApiError::InternalServer(format!("invalid rerank scores: {err}")))?;
+    Ok(request.into_response(format!("rerank-{}", Uuid::new_v4()), scores))
+  }
+}
+
+impl RouterState {
+  pub async fn try_stop(&self) -> crate::error::Result<()> {
+    self.ctx.try_stop().await?;
+    Ok(())
+  }
+
+  /// Runs the chat completion using the given alias, instead of looking it up by the request model
+  #[tracing::instrument(skip_all, fields(alias = %alias.alias))]
+  pub async fn chat_completions_with_alias(
+    &self,
+    request: ChatCompletionRequest,
+    alias: Alias,
+    userdata: Sender<String>,
+  ) -> crate::oai::Result<()> {
+    if !alias.supports(AliasFeature::Chat) {
+      return Err(OpenAIApiError::ModelUnsupported(
+        alias.alias,
+        AliasFeature::Chat,
+      ));
+    }
+    let model_file = self.model_file(&alias)?;
     let tokenizer_repo = Repo::try_from(alias.chat_template.clone())
       .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))?;
     let tokenizer_file = self
@@ -87,19 +248,138 @@ impl RouterStateFn for RouterState {
         TOKENIZER_CONFIG_JSON, tokenizer_repo
       )));
     };
-    self
+    let template_limits = self.app_service.env_service().template_limits();
+    let captured = self.capture_on_error.then(|| {
+      (
+        request.clone(),
+        alias.clone(),
+        model_file.path(),
+        tokenizer_file.path(),
+      )
+    });
+    let priority = request.priority();
+    let streamed = request.stream.unwrap_or(false);
+    // a batch request is counted once no interactive request is in flight, not checked for stalls
+    // while waiting for them
+    if priority == RequestPriority::Batch {
+      self.inference_monitor.yield_to_interactive().await;
+    }
+    let inference = self
+      .inference_monitor
+      .start(&alias.alias, priority, streamed);
+    let (userdata, progress) = self.with_progress(userdata, alias.reasoning_format());
+    let result = self
       .ctx
-      .chat_completions(request, alias, model_file, tokenizer_file, userdata)
-      .await
-      .map_err(OpenAIApiError::ContextError)?;
+      .chat_completions(
+        request,
+        alias,
+        model_file,
+        tokenizer_file,
+        template_limits,
+        userdata,
+      )
+      .await;
+    let _ = progress.await;
+    self.finish_inference(inference, &result);
+    if let (Err(err), Some((request, alias, model_file, tokenizer_file))) = (&result, captured) {
+      self
+        .capture_debug_bundle(
+          request,
+          alias,
+          model_file,
+          tokenizer_file,
+          template_limits,
+          err.to_string(),
+        )
+        .await;
+    }
+    result.map_err(OpenAIApiError::ContextError)?;
     Ok(())
   }
-}
 
-impl RouterState {
-  pub async fn try_stop(&self) -> crate::error::Result<()> {
-    self.ctx.try_stop().await?;
-    Ok(())
+  // passes the chunks generated by the context to `userdata`, recording the progress of the
+  // request for the watchdog, completes once the context drops the returned sender. The
+  // reasoning of the thinking models is moved out of the content of the chunks.
+  fn with_progress(
+    &self,
+    userdata: Sender<String>,
+    reasoning: Option<ReasoningFormat>,
+  ) -> (Sender<String>, JoinHandle<()>) {
+    let (tx, mut rx) = channel::<String>(100);
+    let monitor = self.inference_monitor.clone();
+    let mut splitter = reasoning.map(ReasoningSplitter::new);
+    let forward = tokio::spawn(async move {
+      while let Some(chunk) = rx.recv().await {
+        monitor.progress();
+        let chunk = match splitter.as_mut() {
+          Some(splitter) => splitter.split(chunk),
+          None => chunk,
+        };
+        // the client disconnected, closing the receiver stops the generation
+        if userdata.send(chunk).await.is_err() {
+          return;
+        }
+      }
+    });
+    (tx, forward)
+  }
+
+  fn finish_inference(&self, inference: InferenceGuard<'_>, result: &crate::shared_rw::Result<()>) {
+    if let Err(err) = result {
+      if err.is_context_failure() {
+        self.inference_monitor.context_failed(err.to_string());
+      }
+    }
+    inference.finish(result.as_ref().err().map(ContextError::to_string));
+  }
+
+  fn model_file(&self, alias: &Alias) -> crate::oai::Result<HubFile> {
+    let model_file = self
+      .app_service
+      .hub_service()
+      .find_local_file(&alias.repo, &alias.filename, &alias.snapshot)
+      .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))?;
+    model_file.ok_or_else(|| {
+      OpenAIApiError::InternalServer(format!(
+        "file required by LLM model not found in huggingface cache: filename: '{}', repo: '{}'",
+        alias.filename, alias.repo
+      ))
+    })
+  }
+
+  async fn capture_debug_bundle(
+    &self,
+    request: ChatCompletionRequest,
+    alias: Alias,
+    model_file: PathBuf,
+    tokenizer_file: PathBuf,
+    template_limits: TemplateLimits,
+    error: String,
+  ) {
+    let env_service = self.app_service.env_service();
+    let bodhi_home = env_service.bodhi_home();
+    let logs_dir = env_service.logs_dir();
+    let result = tokio::task::spawn_blocking(move || {
+      DebugBundle::capture(
+        request,
+        &alias,
+        model_file,
+        tokenizer_file,
+        template_limits,
+        error,
+        &logs_dir,
+        Utc::now(),
+      )
+      .and_then(|bundle| bundle.write(&bodhi_home))
+    })
+    .await;
+    match result {
+      Ok(Ok(path)) => {
+        tracing::info!(path = %path.display(), "captured debug bundle for the failed request")
+      }
+      Ok(Err(err)) => tracing::warn!(?err, "error capturing debug bundle for the failed request"),
+      Err(err) => tracing::warn!(?err, "error capturing debug bundle for the failed request"),
+    }
   }
 }
 
@@ -107,24 +387,30 @@ impl RouterState {
 mod test {
   use super::RouterState;
   use crate::{
-    oai::ApiError,
-    objs::{Alias, HubFile, REFS_MAIN, TOKENIZER_CONFIG_JSON},
-    server::RouterStateFn,
-    service::{MockDataService, MockEnvServiceFn, MockHubService},
+    oai::{ApiError, ApiErrorResponse},
+    objs::{
+      Alias, ChatCompletionRequest, HubFile, Pipeline, ReasoningFormat, RerankRequest, REFS_MAIN,
+      TOKENIZER_CONFIG_JSON,
+    },
+    server::{
+      capture::{DebugBundle, DEBUG_DIR},
+      RouterStateFn,
+    },
+    service::{MockDataService, MockEnvServiceFn, MockHubService, TemplateLimits},
     shared_rw::ContextError,
     test_utils::{
       test_channel, AppServiceStubMock, MockDbService, MockSharedContext, ResponseTestExt,
     },
     Repo,
   };
-  use async_openai::types::CreateChatCompletionRequest;
   use axum::http::StatusCode;
   use axum::response::{IntoResponse, Response};
   use llama_server_bindings::LlamaCppError;
   use mockall::predicate::{always, eq};
   use rstest::rstest;
-  use serde_json::json;
-  use std::sync::Arc;
+  use serde_json::{json, Value};
+  use std::{fs, sync::Arc};
+  use tempfile::TempDir;
 
   #[rstest]
   #[tokio::test]
@@ -145,7 +431,7 @@ mod test {
       Arc::new(service),
       Arc::new(MockDbService::new()),
     );
-    let request = serde_json::from_value::<CreateChatCompletionRequest>(json! {{
+    let request = serde_json::from_value::<ChatCompletionRequest>(json! {{
       "model": "not-found",
       "messages": [
         {"role": "user", "content": "What day comes after Monday?"}
@@ -156,10 +442,11 @@ mod test {
     assert!(result.is_err());
     let response: Response = result.unwrap_err().into_response();
     assert_eq!(StatusCode::NOT_FOUND, response.status());
-    let response: ApiError = response.json_obj().await?;
+    let response = response.json_obj::<ApiErrorResponse>().await?.error;
     let expected = ApiError {
-      message: "The model 'not-found' does not exist".to_string(),
-      r#type: "model_not_found".to_string(),
+      message: "The model 'not-found' does not exist, run `bodhi list` to list the model aliases"
+        .to_string(),
+      r#type: "invalid_request_error".to_string(),
       param: Some("model".to_string()),
       code: "model_not_found".to_string(),
     };
@@ -167,6 +454,86 @@ mod test {
     Ok(())
   }
 
+  #[rstest]
+  #[tokio::test]
+  async fn test_router_state_chat_completions_model_unsupported() -> anyhow::Result<()> {
+    let mut mock_data_service = MockDataService::default();
+    mock_data_service
+      .expect_find_alias()
+      .with(eq("bge-reranker"))
+      .return_once(|_| {
+        Some(Alias {
+          alias: "bge-reranker".to_string(),
+          features: vec!["rerank".to_string()],
+          ..Alias::testalias()
+        })
+      });
+    let service = AppServiceStubMock::new(
+      MockEnvServiceFn::new(),
+      MockHubService::new(),
+      mock_data_service,
+    );
+    let state = RouterState::new(
+      Arc::new(MockSharedContext::default()),
+      Arc::new(service),
+      Arc::new(MockDbService::new()),
+    );
+    let request = serde_json::from_value::<ChatCompletionRequest>(json! {{
+      "model": "bge-reranker",
+      "messages": [
+        {"role": "user", "content": "What day comes after Monday?"}
+      ]
+    }})?;
+    let (tx, _rx) = test_channel();
+    let response = state
+      .chat_completions(request, tx)
+      .await
+      .unwrap_err()
+      .into_response();
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    let response = response.json_obj::<ApiErrorResponse>().await?.error;
+    assert_eq!("model_not_supported", response.code);
+    assert_eq!(Some("model".to_string()), response.param);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_router_state_chat_completions_invalid_logit_bias() -> anyhow::Result<()> {
+    let mut mock_data_service = MockDataService::default();
+    mock_data_service
+      .expect_find_alias()
+      .with(eq("testalias:instruct"))
+      .return_once(|_| Some(Alias::testalias()));
+    let service = AppServiceStubMock::new(
+      MockEnvServiceFn::new(),
+      MockHubService::new(),
+      mock_data_service,
+    );
+    let state = RouterState::new(
+      Arc::new(MockSharedContext::default()),
+      Arc::new(service),
+      Arc::new(MockDbService::new()),
+    );
+    let request = serde_json::from_value::<ChatCompletionRequest>(json! {{
+      "model": "testalias:instruct",
+      "messages": [
+        {"role": "user", "content": "What day comes after Monday?"}
+      ],
+      "logit_bias": {"Tuesday": -100}
+    }})?;
+    let (tx, _rx) = test_channel();
+    let result = state.chat_completions(request, tx).await;
+    let response: Response = result.unwrap_err().into_response();
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    let response = response.json_obj::<ApiErrorResponse>().await?.error;
+    assert_eq!(
+      "logit_bias key 'Tuesday' should be a token id",
+      response.message
+    );
+    Ok(())
+  }
+
   #[rstest]
   #[tokio::test]
   async fn test_router_state_chat_completions_delegate_to_context_with_alias() -> anyhow::Result<()>
@@ -191,7 +558,7 @@ mod test {
       .with(eq(Repo::llama3()), eq(TOKENIZER_CONFIG_JSON), eq(REFS_MAIN))
       .return_once(|_, _, _| Ok(Some(HubFile::llama3_tokenizer())));
     let mut mock_ctx = MockSharedContext::default();
-    let request = serde_json::from_value::<CreateChatCompletionRequest>(json! {{
+    let request = serde_json::from_value::<ChatCompletionRequest>(json! {{
       "model": "testalias:instruct",
       "messages": [
         {"role": "user", "content": "What day comes after Monday?"}
@@ -204,21 +571,195 @@ mod test {
         eq(Alias::testalias()),
         eq(HubFile::testalias()),
         eq(HubFile::llama3_tokenizer()),
+        eq(TemplateLimits::default()),
         always(),
       )
-      .return_once(|_, _, _, _, _| Ok(()));
-    let service =
-      AppServiceStubMock::new(MockEnvServiceFn::new(), mock_hub_service, mock_data_service);
+      .return_once(|_, _, _, _, _, _| Ok(()));
+    let mut mock_env_service = MockEnvServiceFn::new();
+    mock_env_service
+      .expect_template_limits()
+      .return_const(TemplateLimits::default());
+    let service = AppServiceStubMock::new(mock_env_service, mock_hub_service, mock_data_service);
+    let state = RouterState::new(
+      Arc::new(mock_ctx),
+      Arc::new(service),
+      Arc::new(MockDbService::new()),
+    );
+    let (tx, _rx) = test_channel();
+    state.chat_completions(request, tx).await?;
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_router_state_chat_completions_splits_reasoning() -> anyhow::Result<()> {
+    let alias = Alias {
+      reasoning: Some(ReasoningFormat::think(false)),
+      ..Alias::testalias()
+    };
+    let mut mock_hub_service = MockHubService::new();
+    mock_hub_service
+      .expect_find_local_file()
+      .with(
+        eq(alias.repo.clone()),
+        eq(alias.filename.clone()),
+        eq(alias.snapshot.clone()),
+      )
+      .return_once(|_, _, _| Ok(Some(HubFile::testalias())));
+    mock_hub_service
+      .expect_find_local_file()
+      .with(eq(Repo::llama3()), eq(TOKENIZER_CONFIG_JSON), eq(REFS_MAIN))
+      .return_once(|_, _, _| Ok(Some(HubFile::llama3_tokenizer())));
+    let mut mock_ctx = MockSharedContext::default();
+    mock_ctx
+      .expect_chat_completions()
+      .return_once(|_, _, _, _, _, userdata| {
+        let response = json! {{
+          "object": "chat.completion",
+          "choices": [{
+            "index": 0,
+            "message": {"role": "assistant", "content": "<think>Monday is first</think>\n\nTuesday"},
+            "finish_reason": "stop"
+          }]
+        }};
+        userdata
+          .try_send(response.to_string())
+          .expect("response to be sent");
+        Ok(())
+      });
+    let mut mock_env_service = MockEnvServiceFn::new();
+    mock_env_service
+      .expect_template_limits()
+      .return_const(TemplateLimits::default());
+    let service = AppServiceStubMock::new(
+      mock_env_service,
+      mock_hub_service,
+      MockDataService::default(),
+    );
+    let state = RouterState::new(
+      Arc::new(mock_ctx),
+      Arc::new(service),
+      Arc::new(MockDbService::new()),
+    );
+    let request = serde_json::from_value::<ChatCompletionRequest>(json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}]
+    }})?;
+    let (tx, mut rx) = test_channel();
+    state
+      .chat_completions_with_alias(request, alias, tx)
+      .await?;
+    let response = rx.recv().await.expect("response to be received");
+    let response = serde_json::from_str::<Value>(&response)?;
+    assert_eq!(
+      json! {{"role": "assistant", "content": "Tuesday", "reasoning_content": "Monday is first"}},
+      response["choices"][0]["message"]
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_router_state_chat_completions_with_pipeline() -> anyhow::Result<()> {
+    let mut mock_data_service = MockDataService::default();
+    mock_data_service
+      .expect_find_pipeline()
+      .with(eq("docs-assistant"))
+      .return_once(|_| {
+        Ok(Some(Pipeline {
+          name: "docs-assistant".to_string(),
+          alias: "testalias:instruct".to_string(),
+          system_prompt: Some("Answer from the docs.".to_string()),
+        }))
+      });
+    mock_data_service
+      .expect_find_alias()
+      .with(eq("testalias:instruct"))
+      .return_once(|_| Some(Alias::testalias()));
+    let mut mock_hub_service = MockHubService::new();
+    mock_hub_service
+      .expect_find_local_file()
+      .with(always(), eq("testalias.Q8_0.gguf"), always())
+      .return_once(|_, _, _| Ok(Some(HubFile::testalias())));
+    mock_hub_service
+      .expect_find_local_file()
+      .with(eq(Repo::llama3()), eq(TOKENIZER_CONFIG_JSON), eq(REFS_MAIN))
+      .return_once(|_, _, _| Ok(Some(HubFile::llama3_tokenizer())));
+    let expected = serde_json::from_value::<ChatCompletionRequest>(json! {{
+      "model": "testalias:instruct",
+      "messages": [
+        {"role": "system", "content": "Answer from the docs."},
+        {"role": "user", "content": "What is Bodhi?"}
+      ]
+    }})?;
+    let mut mock_ctx = MockSharedContext::default();
+    mock_ctx
+      .expect_chat_completions()
+      .with(
+        eq(expected),
+        eq(Alias::testalias()),
+        always(),
+        always(),
+        always(),
+        always(),
+      )
+      .return_once(|_, _, _, _, _, _| Ok(()));
+    let mut mock_env_service = MockEnvServiceFn::new();
+    mock_env_service
+      .expect_template_limits()
+      .return_const(TemplateLimits::default());
+    let service = AppServiceStubMock::new(mock_env_service, mock_hub_service, mock_data_service);
     let state = RouterState::new(
       Arc::new(mock_ctx),
       Arc::new(service),
       Arc::new(MockDbService::new()),
     );
+    let request = serde_json::from_value::<ChatCompletionRequest>(json! {{
+      "model": "pipeline:docs-assistant",
+      "messages": [{"role": "user", "content": "What is Bodhi?"}]
+    }})?;
     let (tx, _rx) = test_channel();
     state.chat_completions(request, tx).await?;
     Ok(())
   }
 
+  #[rstest]
+  #[tokio::test]
+  async fn test_router_state_chat_completions_pipeline_not_found() -> anyhow::Result<()> {
+    let mut mock_data_service = MockDataService::default();
+    mock_data_service
+      .expect_find_pipeline()
+      .with(eq("unknown"))
+      .return_once(|_| Ok(None));
+    let service = AppServiceStubMock::new(
+      MockEnvServiceFn::new(),
+      MockHubService::new(),
+      mock_data_service,
+    );
+    let state = RouterState::new(
+      Arc::new(MockSharedContext::default()),
+      Arc::new(service),
+      Arc::new(MockDbService::new()),
+    );
+    let request = serde_json::from_value::<ChatCompletionRequest>(json! {{
+      "model": "pipeline:unknown",
+      "messages": [{"role": "user", "content": "What is Bodhi?"}]
+    }})?;
+    let (tx, _rx) = test_channel();
+    let response = state
+      .chat_completions(request, tx)
+      .await
+      .unwrap_err()
+      .into_response();
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+    let response = response.json_obj::<ApiErrorResponse>().await?.error;
+    assert_eq!(
+      "The model 'pipeline:unknown' does not exist, run `bodhi list` to list the model aliases",
+      response.message
+    );
+    Ok(())
+  }
+
   #[rstest]
   #[tokio::test]
   async fn test_router_state_chat_completions_returns_context_err() -> anyhow::Result<()> {
@@ -242,7 +783,7 @@ mod test {
       .with(eq(Repo::llama3()), eq(TOKENIZER_CONFIG_JSON), eq(REFS_MAIN))
       .return_once(|_, _, _| Ok(Some(HubFile::llama3_tokenizer())));
     let mut mock_ctx = MockSharedContext::default();
-    let request = serde_json::from_value::<CreateChatCompletionRequest>(json! {{
+    let request = serde_json::from_value::<ChatCompletionRequest>(json! {{
       "model": "testalias:instruct",
       "messages": [
         {"role": "user", "content": "What day comes after Monday?"}
@@ -256,15 +797,19 @@ mod test {
         eq(Alias::testalias()),
         eq(HubFile::testalias()),
         eq(HubFile::llama3_tokenizer()),
+        eq(TemplateLimits::default()),
         always(),
       )
-      .return_once(|_, _, _, _, _| {
+      .return_once(|_, _, _, _, _, _| {
         Err(ContextError::BodhiError(
           LlamaCppError::BodhiServerChatCompletion("test error".to_string()),
         ))
       });
-    let service =
-      AppServiceStubMock::new(MockEnvServiceFn::new(), mock_hub_service, mock_data_service);
+    let mut mock_env_service = MockEnvServiceFn::new();
+    mock_env_service
+      .expect_template_limits()
+      .return_const(TemplateLimits::default());
+    let service = AppServiceStubMock::new(mock_env_service, mock_hub_service, mock_data_service);
     let state = RouterState::new(
       Arc::new(mock_ctx),
       Arc::new(service),
@@ -272,6 +817,18 @@ mod test {
     );
     let result = state.chat_completions(request, tx).await;
     assert!(result.is_err());
+    assert_eq!(0, state.inference_monitor.queue_depth());
+    let last = state
+      .inference_monitor
+      .last()
+      .expect("last inference should be recorded");
+    assert_eq!("testalias:instruct", last.alias);
+    assert_eq!(
+      Some("bodhi_server_chat_completion: test error".to_string()),
+      last.error
+    );
+    // the llama.cpp error fails the request only, the watchdog does not reload the model
+    assert_eq!(None, state.inference_monitor.take_context_error());
     let response = result.unwrap_err().into_response();
     assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
     assert_eq!(
@@ -281,8 +838,170 @@ mod test {
         param: None,
         code: "internal_server_error".to_string()
       },
-      response.json::<ApiError>().await?
+      response.json::<ApiErrorResponse>().await?.error
     );
     Ok(())
   }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_router_state_chat_completions_captures_debug_bundle_on_error() -> anyhow::Result<()>
+  {
+    let temp_home = TempDir::new()?;
+    let testalias = Alias::testalias();
+    let mut mock_hub_service = MockHubService::new();
+    mock_hub_service
+      .expect_find_local_file()
+      .with(
+        eq(testalias.repo),
+        eq(testalias.filename),
+        eq(testalias.snapshot),
+      )
+      .return_once(|_, _, _| Ok(Some(HubFile::testalias())));
+    mock_hub_service
+      .expect_find_local_file()
+      .with(eq(Repo::llama3()), eq(TOKENIZER_CONFIG_JSON), eq(REFS_MAIN))
+      .return_once(|_, _, _| Ok(Some(HubFile::llama3_tokenizer())));
+    let mut mock_ctx = MockSharedContext::default();
+    mock_ctx
+      .expect_chat_completions()
+      .return_once(|_, _, _, _, _, _| {
+        Err(ContextError::BodhiError(
+          LlamaCppError::BodhiServerChatCompletion("test error".to_string()),
+        ))
+      });
+    let mut mock_env_service = MockEnvServiceFn::new();
+    mock_env_service
+      .expect_template_limits()
+      .return_const(TemplateLimits::default());
+    mock_env_service
+      .expect_bodhi_home()
+      .return_const(temp_home.path().to_path_buf());
+    mock_env_service
+      .expect_logs_dir()
+      .return_const(temp_home.path().join("logs"));
+    let service =
+      AppServiceStubMock::new(mock_env_service, mock_hub_service, MockDataService::new());
+    let state = RouterState::new(
+      Arc::new(mock_ctx),
+      Arc::new(service),
+      Arc::new(MockDbService::new()),
+    )
+    .with_capture_on_error(true);
+    let request = serde_json::from_value::<ChatCompletionRequest>(json! {{
+      "model": "testalias:instruct",
+      "messages": [
+        {"role": "user", "content": "What day comes after Monday?"}
+      ]
+    }})?;
+    let (tx, _rx) = test_channel();
+    let result = state
+      .chat_completions_with_alias(request.clone(), Alias::testalias(), tx)
+      .await;
+    assert!(result.is_err());
+    let bundles = fs::read_dir(temp_home.path().join(DEBUG_DIR))?
+      .map(|entry| entry.map(|entry| entry.path()))
+      .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(1, bundles.len());
+    let bundle = DebugBundle::read(&bundles[0])?;
+    assert_eq!("bodhi_server_chat_completion: test error", bundle.error);
+    assert_eq!(request, bundle.request);
+    assert_eq!(Alias::testalias(), bundle.alias()?);
+    assert_eq!(HubFile::testalias().path(), bundle.model_file);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_router_state_rerank_sorts_the_scores() -> anyhow::Result<()> {
+    let reranker = Alias {
+      features: vec!["rerank".to_string()],
+      ..Alias::testalias()
+    };
+    let mut mock_data_service = MockDataService::default();
+    mock_data_service
+      .expect_find_alias()
+      .with(eq("testalias:instruct"))
+      .return_once({
+        let reranker = reranker.clone();
+        move |_| Some(reranker)
+      });
+    let mut mock_hub_service = MockHubService::default();
+    mock_hub_service
+      .expect_find_local_file()
+      .return_once(|_, _, _| Ok(Some(HubFile::testalias())));
+    let mut mock_ctx = MockSharedContext::default();
+    mock_ctx
+      .expect_rerank()
+      .with(always(), eq(reranker), always(), always())
+      .return_once(|_, _, _, tx| {
+        tokio::spawn(async move {
+          let scores = json! {{"results": [
+            {"index": 0, "relevance_score": -1.5},
+            {"index": 1, "relevance_score": 4.0}
+          ], "usage": {"total_tokens": 24}}};
+          tx.send(scores.to_string()).await
+        });
+        Ok(())
+      });
+    let service =
+      AppServiceStubMock::new(MockEnvServiceFn::new(), mock_hub_service, mock_data_service);
+    let state = RouterState::new(
+      Arc::new(mock_ctx),
+      Arc::new(service),
+      Arc::new(MockDbService::new()),
+    );
+    let request = serde_json::from_value::<RerankRequest>(json! {{
+      "model": "testalias:instruct",
+      "query": "What is the capital of France?",
+      "documents": ["Berlin is the capital of Germany", "Paris is the capital of France"],
+      "return_documents": true
+    }})?;
+    let response = state.rerank(request).await?;
+    assert!(response.id.starts_with("rerank-"));
+    assert_eq!(
+      json! {[
+        {"index": 1, "relevance_score": 4.0, "document": {"text": "Paris is the capital of France"}},
+        {"index": 0, "relevance_score": -1.5, "document": {"text": "Berlin is the capital of Germany"}}
+      ]},
+      serde_json::to_value(&response.results)?
+    );
+    assert_eq!(24, response.usage.total_tokens);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("not-found", json! {["Paris is the capital of France"]}, StatusCode::NOT_FOUND)]
+  #[case("testalias:instruct", json! {[]}, StatusCode::BAD_REQUEST)]
+  #[case("testalias:instruct", json! {["Paris is the capital of France"]}, StatusCode::BAD_REQUEST)]
+  #[tokio::test]
+  async fn test_router_state_rerank_invalid_request(
+    #[case] model: &str,
+    #[case] documents: serde_json::Value,
+    #[case] status: StatusCode,
+  ) -> anyhow::Result<()> {
+    let mut mock_data_service = MockDataService::default();
+    // the chat alias does not have the rerank feature
+    mock_data_service
+      .expect_find_alias()
+      .returning(|model| (model == "testalias:instruct").then(Alias::testalias));
+    let service = AppServiceStubMock::new(
+      MockEnvServiceFn::new(),
+      MockHubService::new(),
+      mock_data_service,
+    );
+    let state = RouterState::new(
+      Arc::new(MockSharedContext::default()),
+      Arc::new(service),
+      Arc::new(MockDbService::new()),
+    );
+    let request = serde_json::from_value::<RerankRequest>(json! {{
+      "model": model,
+      "query": "What is the capital of France?",
+      "documents": documents
+    }})?;
+    let result = state.rerank(request).await;
+    assert_eq!(status, result.unwrap_err().into_response().status());
+    Ok(())
+  }
 }
@@ -1,32 +1,121 @@
 use super::{
   super::{db::DbServiceFn, service::AppServiceFn, SharedContextRwFn},
-  router_state::RouterState,
+  access_log::{access_log_middleware, AccessLog},
+  alias_check::AliasQuarantine,
+  auth::auth_middleware,
+  inference_monitor::InferenceMonitor,
+  response_cache::ResponseCache,
+  router_state::{RouterState, RouterStateFn},
+  routes_aliases::aliases_router,
+  routes_audit::audit_router,
+  routes_batches::batches_router,
   routes_chat::chat_completions_handler,
+  routes_downloads::downloads_router,
+  routes_health::health_router,
+  routes_info::info_router,
   routes_models::{oai_model_handler, oai_models_handler},
+  routes_ollama::ollama_router,
+  routes_requests::{cancel_request_handler, requests_router},
+  routes_rerank::rerank_handler,
+  routes_settings::settings_router,
+  routes_share::share_router,
+  routes_status::status_router,
+  routes_tokens::tokens_router,
   routes_ui::chats_router,
+  routes_usage::usage_router,
+  routes_validate::validate_router,
+  utils::error_envelope,
 };
 use axum::{
+  http::Request,
+  middleware::{from_fn_with_state, map_response},
   routing::{get, post},
   Router,
 };
 use std::sync::Arc;
+use tower_http::compression::{
+  predicate::{NotForContentType, Predicate},
+  CompressionLayer, DefaultPredicate,
+};
 use tower_http::cors::{Any, CorsLayer};
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
 use tower_http::trace::TraceLayer;
+use tracing::Span;
 
 pub fn build_routes(
   ctx: Arc<dyn SharedContextRwFn>,
   app_service: Arc<dyn AppServiceFn>,
   db_service: Arc<dyn DbServiceFn>,
+  quarantine: Arc<AliasQuarantine>,
+  inference_monitor: Arc<InferenceMonitor>,
+  response_cache: Arc<ResponseCache>,
+  capture_on_error: bool,
   static_router: Option<Router>,
 ) -> Router {
-  let state = RouterState::new(ctx, app_service, db_service);
-  let api_router = Router::new().merge(chats_router());
-  let router = Router::new()
+  let routes = app_service.env_service().route_settings();
+  let auth = app_service.env_service().auth();
+  let access_log = if app_service.env_service().access_log() {
+    match AccessLog::open(&app_service.env_service().logs_dir()) {
+      Ok(access_log) => Some(access_log),
+      Err(err) => {
+        tracing::warn!(?err, "error opening the access log, skipping it");
+        None
+      }
+    }
+  } else {
+    None
+  };
+  let state: Arc<dyn RouterStateFn> = Arc::new(
+    RouterState::new(ctx, app_service, db_service)
+      .with_quarantine(quarantine)
+      .with_inference_monitor(inference_monitor)
+      .with_response_cache(response_cache)
+      .with_capture_on_error(capture_on_error),
+  );
+  let mut api_router = Router::new();
+  if routes.ui_api {
+    api_router = api_router
+      .merge(chats_router())
+      .merge(tokens_router())
+      .merge(usage_router())
+      .merge(status_router())
+      .merge(info_router())
+      .merge(validate_router());
+  }
+  if routes.admin {
+    api_router = api_router
+      .merge(aliases_router())
+      .merge(downloads_router())
+      .merge(requests_router())
+      .merge(settings_router())
+      .merge(audit_router());
+  }
+  let mut router = Router::new()
     .route("/ping", get(|| async { "pong" }))
-    .nest("/api/ui", api_router)
-    .route("/v1/models", get(oai_models_handler))
-    .route("/v1/models/:id", get(oai_model_handler))
-    .route("/v1/chat/completions", post(chat_completions_handler))
+    .merge(health_router())
+    .nest("/api/ui", api_router);
+  if routes.ui_api {
+    router = router.merge(share_router());
+  }
+  if routes.openai_api {
+    router = router
+      .route("/v1/models", get(oai_models_handler))
+      .route("/v1/models/:id", get(oai_model_handler))
+      .route("/v1/chat/completions", post(chat_completions_handler))
+      .route("/v1/rerank", post(rerank_handler))
+      .route("/v1/requests/:id/cancel", post(cancel_request_handler))
+      .merge(batches_router());
+  }
+  if routes.ollama_api {
+    router = router.merge(ollama_router());
+  }
+  // the playground is merged later, so the static files are served without a key
+  if auth {
+    router = router.layer(from_fn_with_state(state.clone(), auth_middleware));
+  }
+  let router = router
+    // the rejections of the extractors are plain text, sent as the errors of the handlers instead
+    .layer(map_response(error_envelope))
     .layer(
       CorsLayer::new()
         .allow_origin(Any)
@@ -34,12 +123,234 @@ pub fn build_routes(
         .allow_headers(Any)
         .allow_credentials(false),
     )
-    .layer(TraceLayer::new_for_http())
-    .with_state(Arc::new(state));
-  let router = if let Some(static_router) = static_router {
-    router.merge(static_router)
-  } else {
-    router
+    // the default predicate skips the small responses and the SSE streams,
+    // the ndjson streams of the ollama api are skipped as well, so the chunks are not held back
+    .layer(CompressionLayer::new().compress_when(
+      DefaultPredicate::new().and(NotForContentType::const_new("application/x-ndjson")),
+    ))
+    .layer(PropagateRequestIdLayer::x_request_id())
+    .layer(TraceLayer::new_for_http().make_span_with(request_span))
+    .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+    .with_state(state);
+  let router = match static_router {
+    Some(static_router) if routes.playground => router.merge(static_router),
+    _ => router,
+  };
+  match access_log {
+    Some(access_log) => router.layer(from_fn_with_state(
+      Arc::new(access_log),
+      access_log_middleware,
+    )),
+    None => router,
+  }
+}
+
+// the request id is set by the outer layer, so every log line of the request carries it
+fn request_span<B>(request: &Request<B>) -> Span {
+  let request_id = request
+    .headers()
+    .get("x-request-id")
+    .and_then(|value| value.to_str().ok())
+    .unwrap_or_default();
+  tracing::info_span!(
+    "request",
+    method = %request.method(),
+    uri = %request.uri(),
+    request_id = %request_id,
+  )
+}
+
+#[cfg(test)]
+mod test {
+  use super::build_routes;
+  use crate::{
+    server::{AliasQuarantine, InferenceMonitor, ResponseCache},
+    service::{
+      MockDataService, MockEnvServiceFn, MockHubService, ResponseCacheSettings, RouteSettings,
+    },
+    test_utils::{AppServiceStubMock, MockDbService, MockSharedContext},
   };
-  router
+  use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    routing::get,
+    Router,
+  };
+  use rstest::rstest;
+  use std::sync::Arc;
+  use tower::ServiceExt;
+
+  #[rstest]
+  #[case(RouteSettings::default(), "/api/ui/settings", StatusCode::OK)]
+  #[case(RouteSettings { admin: false, ..Default::default() }, "/api/ui/settings", StatusCode::NOT_FOUND)]
+  #[case(RouteSettings { admin: false, ..Default::default() }, "/api/ui/audit", StatusCode::NOT_FOUND)]
+  #[case(RouteSettings { admin: false, ..Default::default() }, "/api/ui/models/whats-new", StatusCode::NOT_FOUND)]
+  #[case(RouteSettings { admin: false, ..Default::default() }, "/api/ui/downloads", StatusCode::NOT_FOUND)]
+  #[case(RouteSettings { admin: false, ..Default::default() }, "/api/ui/queue", StatusCode::NOT_FOUND)]
+  #[case(RouteSettings { openai_api: false, ..Default::default() }, "/v1/models", StatusCode::NOT_FOUND)]
+  #[case(RouteSettings { playground: false, ..Default::default() }, "/index.html", StatusCode::NOT_FOUND)]
+  #[case(RouteSettings { playground: true, ..Default::default() }, "/index.html", StatusCode::OK)]
+  #[case(RouteSettings { ui_api: false, admin: false, openai_api: false, playground: false, ollama_api: false }, "/ping", StatusCode::OK)]
+  #[case(RouteSettings { ui_api: false, admin: false, openai_api: false, playground: false, ollama_api: false }, "/health", StatusCode::OK)]
+  #[case(RouteSettings::default(), "/api/ui/status", StatusCode::OK)]
+  #[case(RouteSettings { ui_api: false, ..Default::default() }, "/api/ui/status", StatusCode::NOT_FOUND)]
+  #[case(RouteSettings::default(), "/api/tags", StatusCode::NOT_FOUND)]
+  #[case(RouteSettings { ollama_api: true, ..Default::default() }, "/api/tags", StatusCode::OK)]
+  #[tokio::test]
+  async fn test_build_routes_route_settings(
+    #[case] routes: RouteSettings,
+    #[case] path: &str,
+    #[case] expected: StatusCode,
+  ) -> anyhow::Result<()> {
+    let mut env_service = MockEnvServiceFn::new();
+    env_service.expect_route_settings().return_const(routes);
+    env_service.expect_access_log().return_const(false);
+    env_service.expect_auth().return_const(false);
+    env_service.expect_list_settings().returning(Vec::new);
+    let mut data_service = MockDataService::new();
+    data_service.expect_list_aliases().returning(|| Ok(vec![]));
+    let app_service = AppServiceStubMock::new(env_service, MockHubService::new(), data_service);
+    let static_router = Router::new().route("/index.html", get(|| async { "playground" }));
+    let router = build_routes(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(app_service),
+      Arc::new(MockDbService::new()),
+      Arc::new(AliasQuarantine::default()),
+      Arc::new(InferenceMonitor::default()),
+      Arc::new(ResponseCache::new(ResponseCacheSettings::default())),
+      false,
+      Some(static_router),
+    );
+    let response = router
+      .oneshot(Request::get(path).body(Body::empty())?)
+      .await?;
+    assert_eq!(expected, response.status());
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(None)]
+  #[case(Some("client-request-1"))]
+  #[tokio::test]
+  async fn test_build_routes_sets_request_id(
+    #[case] request_id: Option<&str>,
+  ) -> anyhow::Result<()> {
+    let mut env_service = MockEnvServiceFn::new();
+    env_service
+      .expect_route_settings()
+      .return_const(RouteSettings::default());
+    env_service.expect_access_log().return_const(false);
+    env_service.expect_auth().return_const(false);
+    env_service.expect_list_settings().returning(Vec::new);
+    let mut data_service = MockDataService::new();
+    data_service.expect_list_aliases().returning(|| Ok(vec![]));
+    let app_service = AppServiceStubMock::new(env_service, MockHubService::new(), data_service);
+    let router = build_routes(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(app_service),
+      Arc::new(MockDbService::new()),
+      Arc::new(AliasQuarantine::default()),
+      Arc::new(InferenceMonitor::default()),
+      Arc::new(ResponseCache::new(ResponseCacheSettings::default())),
+      false,
+      None,
+    );
+    let mut request = Request::get("/ping");
+    if let Some(request_id) = request_id {
+      request = request.header("x-request-id", request_id);
+    }
+    let response = router.oneshot(request.body(Body::empty())?).await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let header = response
+      .headers()
+      .get("x-request-id")
+      .expect("x-request-id should be set")
+      .to_str()?;
+    match request_id {
+      Some(request_id) => assert_eq!(request_id, header),
+      None => assert!(!header.is_empty()),
+    }
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Some("gzip"), Some("gzip"))]
+  #[case(Some("br"), Some("br"))]
+  #[case(Some("identity"), None)]
+  #[case(None, None)]
+  #[tokio::test]
+  async fn test_build_routes_compresses_json_responses(
+    #[case] accept_encoding: Option<&str>,
+    #[case] expected: Option<&str>,
+  ) -> anyhow::Result<()> {
+    let mut env_service = MockEnvServiceFn::new();
+    env_service
+      .expect_route_settings()
+      .return_const(RouteSettings::default());
+    env_service.expect_access_log().return_const(false);
+    env_service.expect_auth().return_const(false);
+    env_service.expect_list_settings().returning(Vec::new);
+    let mut data_service = MockDataService::new();
+    data_service.expect_list_aliases().returning(|| Ok(vec![]));
+    let app_service = AppServiceStubMock::new(env_service, MockHubService::new(), data_service);
+    let router = build_routes(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(app_service),
+      Arc::new(MockDbService::new()),
+      Arc::new(AliasQuarantine::default()),
+      Arc::new(InferenceMonitor::default()),
+      Arc::new(ResponseCache::new(ResponseCacheSettings::default())),
+      false,
+      None,
+    );
+    let mut request = Request::get("/health");
+    if let Some(accept_encoding) = accept_encoding {
+      request = request.header(header::ACCEPT_ENCODING, accept_encoding);
+    }
+    let response = router.oneshot(request.body(Body::empty())?).await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let content_encoding = response
+      .headers()
+      .get(header::CONTENT_ENCODING)
+      .map(|value| value.to_str())
+      .transpose()?;
+    assert_eq!(expected, content_encoding);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("/v1/models", StatusCode::UNAUTHORIZED)]
+  #[case("/api/ui/chats", StatusCode::UNAUTHORIZED)]
+  #[case("/ping", StatusCode::OK)]
+  #[case("/index.html", StatusCode::OK)]
+  #[tokio::test]
+  async fn test_build_routes_auth_requires_key(
+    #[case] path: &str,
+    #[case] expected: StatusCode,
+  ) -> anyhow::Result<()> {
+    let mut env_service = MockEnvServiceFn::new();
+    env_service
+      .expect_route_settings()
+      .return_const(RouteSettings::default());
+    env_service.expect_access_log().return_const(false);
+    env_service.expect_auth().return_const(true);
+    let app_service =
+      AppServiceStubMock::new(env_service, MockHubService::new(), MockDataService::new());
+    let static_router = Router::new().route("/index.html", get(|| async { "playground" }));
+    let router = build_routes(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(app_service),
+      Arc::new(MockDbService::new()),
+      Arc::new(AliasQuarantine::default()),
+      Arc::new(InferenceMonitor::default()),
+      Arc::new(ResponseCache::new(ResponseCacheSettings::default())),
+      false,
+      Some(static_router),
+    );
+    let response = router
+      .oneshot(Request::get(path).body(Body::empty())?)
+      .await?;
+    assert_eq!(expected, response.status());
+    Ok(())
+  }
 }
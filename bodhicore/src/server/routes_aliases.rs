@@ -0,0 +1,684 @@
+use super::{routes_audit::record_audit, RouterStateFn};
+use crate::{
+  cli::{Command, CreateCommand},
+  db::objs::{ApiKey, AuditAction},
+  error::BodhiError,
+  oai::OpenAIApiError,
+  objs::{
+    Alias, AliasFeature, CatalogDiff, ChatTemplate, GptContextParams, OAIRequestParams,
+    GGUF_EXTENSION,
+  },
+  service::AppServiceFn,
+};
+use axum::{
+  extract::{Path as UrlPath, State},
+  http::StatusCode,
+  response::Json,
+  routing::{get, patch, put},
+  Extension, Router,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{str::FromStr, sync::Arc};
+
+pub fn aliases_router() -> Router<Arc<dyn RouterStateFn>> {
+  Router::new()
+    .route(
+      "/models",
+      patch(ui_models_update_handler).post(ui_model_create_handler),
+    )
+    .route("/models/whats-new", get(ui_models_whats_new_handler))
+    .route(
+      "/models/:alias",
+      put(ui_model_replace_handler).delete(ui_model_delete_handler),
+    )
+}
+
+/// The model alias config, as given to `bodhi create`. The model file and the tokenizer config
+/// are pulled into $HF_HOME when not already there.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AliasRequest {
+  pub repo: String,
+  pub filename: String,
+  /// the in-built chat template id, or the repo having the tokenizer_config.json
+  pub chat_template: ChatTemplate,
+  #[serde(default)]
+  pub family: Option<String>,
+  #[serde(default = "default_features")]
+  pub features: Vec<String>,
+  #[serde(default)]
+  pub request_params: OAIRequestParams,
+  #[serde(default)]
+  pub context_params: GptContextParams,
+}
+
+fn default_features() -> Vec<String> {
+  vec![AliasFeature::Chat.to_string()]
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AliasCreateRequest {
+  pub alias: String,
+  #[serde(flatten)]
+  pub config: AliasRequest,
+}
+
+/// Selects the model aliases to update, all the given conditions should match.
+/// An empty filter selects all the model aliases.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AliasFilter {
+  pub aliases: Vec<String>,
+  pub family: Option<String>,
+  pub repo: Option<String>,
+}
+
+impl AliasFilter {
+  fn matches(&self, alias: &Alias) -> bool {
+    (self.aliases.is_empty() || self.aliases.contains(&alias.alias))
+      && self
+        .family
+        .as_ref()
+        .is_none_or(|family| alias.family.as_ref() == Some(family))
+      && self
+        .repo
+        .as_ref()
+        .is_none_or(|repo| alias.repo.as_str() == repo)
+  }
+}
+
+/// The params are applied as a JSON merge patch, fields set to `null` are removed from the alias
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AliasesUpdateRequest {
+  pub filter: AliasFilter,
+  pub request_params: Map<String, Value>,
+  pub context_params: Map<String, Value>,
+  pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AliasUpdate {
+  pub alias: String,
+  pub file: String,
+  pub request_params: OAIRequestParams,
+  pub context_params: GptContextParams,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AliasesUpdateResponse {
+  pub dry_run: bool,
+  pub updated: Vec<AliasUpdate>,
+}
+
+async fn ui_models_update_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  api_key: Option<Extension<ApiKey>>,
+  Json(request): Json<AliasesUpdateRequest>,
+) -> Result<Json<AliasesUpdateResponse>, OpenAIApiError> {
+  if request.request_params.is_empty() && request.context_params.is_empty() {
+    return Err(OpenAIApiError::BadRequest(
+      "either request_params or context_params should be provided".to_string(),
+    ));
+  }
+  let data_service = state.app_service().data_service();
+  let mut updated = vec![];
+  for mut alias in data_service.list_aliases()? {
+    if !request.filter.matches(&alias) {
+      continue;
+    }
+    let request_params = merge_params(&alias.request_params, &request.request_params)?;
+    let context_params = merge_params(&alias.context_params, &request.context_params)?;
+    if request_params == alias.request_params && context_params == alias.context_params {
+      continue;
+    }
+    let before = alias_params_json(&alias);
+    alias.request_params = request_params;
+    alias.context_params = context_params;
+    if !request.dry_run {
+      data_service.save_alias(&alias)?;
+      record_audit(
+        state.db_service().as_ref(),
+        api_key.as_ref().map(|Extension(api_key)| api_key),
+        AuditAction::AliasUpdate,
+        &alias.alias,
+        before,
+        alias_params_json(&alias),
+      )
+      .await;
+    }
+    updated.push(AliasUpdate {
+      file: alias.config_filename(),
+      alias: alias.alias,
+      request_params: alias.request_params,
+      context_params: alias.context_params,
+    });
+  }
+  Ok(Json(AliasesUpdateResponse {
+    dry_run: request.dry_run,
+    updated,
+  }))
+}
+
+/// Changes to the pre-configured model aliases on upgrades, newest first
+async fn ui_models_whats_new_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+) -> Result<Json<Vec<CatalogDiff>>, OpenAIApiError> {
+  let changes = state.app_service().data_service().list_catalog_changes()?;
+  Ok(Json(changes))
+}
+
+async fn ui_model_create_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  api_key: Option<Extension<ApiKey>>,
+  Json(request): Json<AliasCreateRequest>,
+) -> Result<(StatusCode, Json<Alias>), OpenAIApiError> {
+  let app_service = state.app_service();
+  if app_service
+    .data_service()
+    .find_alias(&request.alias)
+    .is_some()
+  {
+    return Err(OpenAIApiError::BadRequest(format!(
+      "model alias '{}' already exists, use PUT /api/ui/models/{} to replace it",
+      request.alias, request.alias
+    )));
+  }
+  let alias = build_alias(app_service.clone(), request.alias, request.config).await?;
+  app_service.data_service().save_alias(&alias)?;
+  record_audit(
+    state.db_service().as_ref(),
+    api_key.as_ref().map(|Extension(api_key)| api_key),
+    AuditAction::AliasCreate,
+    &alias.alias,
+    None,
+    alias_json(&alias),
+  )
+  .await;
+  Ok((StatusCode::CREATED, Json(alias)))
+}
+
+/// Replaces the config of an existing model alias, like `bodhi create --force` without pulling
+/// the files already in $HF_HOME again
+async fn ui_model_replace_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  api_key: Option<Extension<ApiKey>>,
+  UrlPath(alias): UrlPath<String>,
+  Json(request): Json<AliasRequest>,
+) -> Result<Json<Alias>, OpenAIApiError> {
+  let app_service = state.app_service();
+  let Some(existing) = app_service.data_service().find_alias(&alias) else {
+    return Err(alias_not_found(&alias));
+  };
+  let alias = build_alias(app_service.clone(), alias, request).await?;
+  app_service.data_service().save_alias(&alias)?;
+  record_audit(
+    state.db_service().as_ref(),
+    api_key.as_ref().map(|Extension(api_key)| api_key),
+    AuditAction::AliasUpdate,
+    &alias.alias,
+    alias_json(&existing),
+    alias_json(&alias),
+  )
+  .await;
+  Ok(Json(alias))
+}
+
+/// Removes the model alias like `bodhi rm`, the model files are left in $HF_HOME
+async fn ui_model_delete_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  api_key: Option<Extension<ApiKey>>,
+  UrlPath(alias): UrlPath<String>,
+) -> Result<(), OpenAIApiError> {
+  let data_service = state.app_service().data_service();
+  let Some(existing) = data_service.find_alias(&alias) else {
+    return Err(alias_not_found(&alias));
+  };
+  data_service.delete_alias(&alias)?;
+  record_audit(
+    state.db_service().as_ref(),
+    api_key.as_ref().map(|Extension(api_key)| api_key),
+    AuditAction::AliasDelete,
+    &alias,
+    alias_json(&existing),
+    None,
+  )
+  .await;
+  Ok(())
+}
+
+// validates the config same as the `bodhi create` args, and pulls the files off the async runtime
+async fn build_alias(
+  app_service: Arc<dyn AppServiceFn>,
+  alias: String,
+  request: AliasRequest,
+) -> Result<Alias, OpenAIApiError> {
+  if !request.filename.ends_with(GGUF_EXTENSION) {
+    return Err(OpenAIApiError::BadRequest(format!(
+      "filename '{}' is not supported, only GGUF file extension supported",
+      request.filename
+    )));
+  }
+  let features = request
+    .features
+    .iter()
+    .map(|feature| {
+      AliasFeature::from_str(feature)
+        .map_err(|_| OpenAIApiError::BadRequest(format!("unknown feature '{feature}'")))
+    })
+    .collect::<Result<Vec<_>, _>>()?;
+  let (chat_template, tokenizer_config) = match request.chat_template {
+    ChatTemplate::Id(id) => (Some(id), None),
+    ChatTemplate::Repo(repo) => (None, Some(repo.to_string())),
+  };
+  let command = CreateCommand::try_from(Command::Create {
+    alias,
+    repo: Some(request.repo),
+    filename: Some(request.filename),
+    file: None,
+    chat_template,
+    tokenizer_config,
+    family: request.family,
+    features,
+    force: false,
+    oai_request_params: request.request_params,
+    context_params: request.context_params,
+  })
+  .map_err(|err| OpenAIApiError::BadRequest(err.to_string()))?;
+  tokio::task::spawn_blocking(move || command.build_alias(app_service.as_ref()))
+    .await
+    .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))?
+    .map_err(|err| match err {
+      BodhiError::HubServiceError(err) if err.is_not_found() => {
+        OpenAIApiError::NotFound(err.to_string())
+      }
+      err @ BodhiError::ObjError(_) => OpenAIApiError::BadRequest(err.to_string()),
+      err => OpenAIApiError::InternalServer(err.to_string()),
+    })
+}
+
+fn alias_not_found(alias: &str) -> OpenAIApiError {
+  OpenAIApiError::NotFound(format!("model alias '{alias}' not found"))
+}
+
+// the whole alias config as recorded in the audit log
+fn alias_json(alias: &Alias) -> Option<String> {
+  serde_json::to_string(alias).ok()
+}
+
+// the params of the alias as recorded in the audit log
+fn alias_params_json(alias: &Alias) -> Option<String> {
+  serde_json::to_string(&serde_json::json! {{
+    "request_params": alias.request_params,
+    "context_params": alias.context_params,
+  }})
+  .ok()
+}
+
+fn merge_params<T>(params: &T, patch: &Map<String, Value>) -> Result<T, OpenAIApiError>
+where
+  T: Serialize + DeserializeOwned,
+{
+  let mut merged = match serde_json::to_value(params) {
+    Ok(Value::Object(map)) => map,
+    Ok(_) => Map::new(),
+    Err(err) => return Err(OpenAIApiError::InternalServer(err.to_string())),
+  };
+  for (key, value) in patch {
+    match value {
+      Value::Null => merged.remove(key),
+      value => merged.insert(key.clone(), value.clone()),
+    };
+  }
+  let result = serde_json::from_value::<T>(Value::Object(merged))
+    .map_err(|err| OpenAIApiError::BadRequest(format!("invalid params: {err}")))?;
+  // params are serialized without the unset or empty fields, so an unknown key does not survive the round trip
+  let roundtrip =
+    serde_json::to_value(&result).map_err(|err| OpenAIApiError::InternalServer(err.to_string()))?;
+  if let Some(key) = patch
+    .iter()
+    .filter(|(_, value)| !value.is_null() && value.as_array().is_none_or(|v| !v.is_empty()))
+    .map(|(key, _)| key)
+    .find(|key| roundtrip.get(key.as_str()).is_none())
+  {
+    return Err(OpenAIApiError::BadRequest(format!("unknown param '{key}'")));
+  }
+  Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+  use super::{aliases_router, AliasesUpdateResponse};
+  use crate::{
+    db::objs::AuditAction,
+    objs::{
+      Alias, CatalogChange, CatalogChangeKind, CatalogDiff, ChatTemplate, ChatTemplateId, Repo,
+    },
+    service::{AppServiceFn, DataService, MockDataService, MockEnvServiceFn, MockHubService},
+    test_utils::{
+      app_service_stub, AppServiceStubMock, AppServiceTuple, MockDbService, MockRouterState,
+      RequestTestExt, ResponseTestExt,
+    },
+  };
+  use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+  };
+  use chrono::{TimeZone, Utc};
+  use rstest::rstest;
+  use serde_json::{json, Value};
+  use std::sync::Arc;
+  use tower::ServiceExt;
+
+  fn router_state(app_service: Arc<dyn AppServiceFn>) -> MockRouterState {
+    let mut router_state = MockRouterState::new();
+    router_state
+      .expect_app_service()
+      .returning(move || app_service.clone());
+    router_state
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_aliases_routes_update_by_family(
+    app_service_stub: AppServiceTuple,
+  ) -> anyhow::Result<()> {
+    let AppServiceTuple(_temp_bodhi_home, _temp_hf_home, _, _, service) = app_service_stub;
+    let service: Arc<dyn AppServiceFn> = Arc::new(service);
+    let mut db_service = MockDbService::new();
+    db_service
+      .expect_save_audit_entry()
+      .withf(|entry| {
+        entry.action == AuditAction::AliasUpdate
+          && entry.target == "llama3:instruct"
+          && entry
+            .after
+            .as_deref()
+            .is_some_and(|after| after.contains(r#""n_threads":8"#))
+      })
+      .times(1)
+      .returning(|_| Ok(()));
+    let db_service = Arc::new(db_service);
+    let mut router_state = router_state(service.clone());
+    router_state
+      .expect_db_service()
+      .returning(move || db_service.clone());
+    let router = aliases_router().with_state(Arc::new(router_state));
+    let response = router
+      .oneshot(Request::patch("/models").json(json! {{
+        "filter": {"family": "llama3"},
+        "request_params": {"temperature": 0.5},
+        "context_params": {"n_threads": 8}
+      }})?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response = response.json::<AliasesUpdateResponse>().await?;
+    assert!(!response.dry_run);
+    assert_eq!(1, response.updated.len());
+    assert_eq!("llama3--instruct.yaml", response.updated[0].file);
+    let alias = service
+      .data_service()
+      .find_alias("llama3:instruct")
+      .expect("alias should exist");
+    assert_eq!(Some(0.5), alias.request_params.temperature);
+    assert_eq!(Some(8), alias.context_params.n_threads);
+    let other = service
+      .data_service()
+      .find_alias("tinyllama:instruct")
+      .expect("alias should exist");
+    assert_eq!(None, other.context_params.n_threads);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_aliases_routes_update_dry_run(
+    app_service_stub: AppServiceTuple,
+  ) -> anyhow::Result<()> {
+    let AppServiceTuple(_temp_bodhi_home, _temp_hf_home, _, _, service) = app_service_stub;
+    let service: Arc<dyn AppServiceFn> = Arc::new(service);
+    let router = aliases_router().with_state(Arc::new(router_state(service.clone())));
+    let response = router
+      .oneshot(Request::patch("/models").json(json! {{
+        "context_params": {"n_threads": 4},
+        "dry_run": true
+      }})?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response = response.json::<AliasesUpdateResponse>().await?;
+    assert!(response.dry_run);
+    let files = response
+      .updated
+      .iter()
+      .map(|update| update.file.as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(
+      vec![
+        "llama3--instruct.yaml",
+        "testalias-exists--instruct.yaml",
+        "tinyllama--instruct.yaml"
+      ],
+      files
+    );
+    for alias in service.data_service().list_aliases()? {
+      assert_eq!(None, alias.context_params.n_threads);
+    }
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(json! {{"filter": {"family": "llama3"}}}, "either request_params or context_params should be provided")]
+  #[case(json! {{"request_params": {"temprature": 0.5}}}, "unknown param 'temprature'")]
+  #[tokio::test]
+  async fn test_aliases_routes_update_invalid(
+    app_service_stub: AppServiceTuple,
+    #[case] request: Value,
+    #[case] error: &str,
+  ) -> anyhow::Result<()> {
+    let AppServiceTuple(_temp_bodhi_home, _temp_hf_home, _, _, service) = app_service_stub;
+    let router = aliases_router().with_state(Arc::new(router_state(Arc::new(service))));
+    let response = router
+      .oneshot(Request::patch("/models").json(request)?)
+      .await?;
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    assert_eq!(
+      json! {error},
+      response.json::<Value>().await?["error"]["message"]
+    );
+    Ok(())
+  }
+
+  fn audited_router_state(
+    app_service: Arc<dyn AppServiceFn>,
+    action: AuditAction,
+    target: &'static str,
+  ) -> MockRouterState {
+    let mut db_service = MockDbService::new();
+    db_service
+      .expect_save_audit_entry()
+      .withf(move |entry| entry.action == action && entry.target == target)
+      .times(1)
+      .returning(|_| Ok(()));
+    let db_service = Arc::new(db_service);
+    let mut router_state = router_state(app_service);
+    router_state
+      .expect_db_service()
+      .returning(move || db_service.clone());
+    router_state
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_aliases_routes_create(app_service_stub: AppServiceTuple) -> anyhow::Result<()> {
+    let AppServiceTuple(_temp_bodhi_home, _temp_hf_home, _, _, service) = app_service_stub;
+    let service: Arc<dyn AppServiceFn> = Arc::new(service);
+    let router_state = audited_router_state(
+      service.clone(),
+      AuditAction::AliasCreate,
+      "testalias:creative",
+    );
+    let router = aliases_router().with_state(Arc::new(router_state));
+    let response = router
+      .oneshot(Request::post("/models").json(json! {{
+        "alias": "testalias:creative",
+        "repo": "MyFactory/testalias-gguf",
+        "filename": "testalias.Q8_0.gguf",
+        "chat_template": "llama3",
+        "family": "testalias",
+        "request_params": {"temperature": 0.9}
+      }})?)
+      .await?;
+    assert_eq!(StatusCode::CREATED, response.status());
+    let alias = response.json::<Alias>().await?;
+    assert_eq!("testalias:creative", alias.alias);
+    assert_eq!(vec!["chat".to_string()], alias.features);
+    assert_eq!(Some(0.9), alias.request_params.temperature);
+    let saved = service
+      .data_service()
+      .find_alias("testalias:creative")
+      .expect("alias should exist");
+    assert_eq!(alias, saved);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(
+    json! {{"alias": "llama3:instruct", "repo": "MyFactory/testalias-gguf", "filename": "testalias.Q8_0.gguf", "chat_template": "llama3"}},
+    "model alias 'llama3:instruct' already exists, use PUT /api/ui/models/llama3:instruct to replace it"
+  )]
+  #[case(
+    json! {{"alias": "testalias:creative", "repo": "MyFactory/testalias-gguf", "filename": "testalias.Q8_0.bin", "chat_template": "llama3"}},
+    "filename 'testalias.Q8_0.bin' is not supported, only GGUF file extension supported"
+  )]
+  #[case(
+    json! {{"alias": "testalias:creative", "repo": "MyFactory/testalias-gguf", "filename": "testalias.Q8_0.gguf", "chat_template": "llama3", "features": ["embed"]}},
+    "unknown feature 'embed'"
+  )]
+  #[tokio::test]
+  async fn test_aliases_routes_create_invalid(
+    app_service_stub: AppServiceTuple,
+    #[case] request: Value,
+    #[case] error: &str,
+  ) -> anyhow::Result<()> {
+    let AppServiceTuple(_temp_bodhi_home, _temp_hf_home, _, _, service) = app_service_stub;
+    let router = aliases_router().with_state(Arc::new(router_state(Arc::new(service))));
+    let response = router
+      .oneshot(Request::post("/models").json(request)?)
+      .await?;
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    assert_eq!(
+      json! {error},
+      response.json::<Value>().await?["error"]["message"]
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_aliases_routes_replace(app_service_stub: AppServiceTuple) -> anyhow::Result<()> {
+    let AppServiceTuple(_temp_bodhi_home, _temp_hf_home, _, _, service) = app_service_stub;
+    let service: Arc<dyn AppServiceFn> = Arc::new(service);
+    let router_state =
+      audited_router_state(service.clone(), AuditAction::AliasUpdate, "llama3:instruct");
+    let router = aliases_router().with_state(Arc::new(router_state));
+    let response = router
+      .oneshot(Request::put("/models/llama3:instruct").json(json! {{
+        "repo": "MyFactory/testalias-gguf",
+        "filename": "testalias.Q8_0.gguf",
+        "chat_template": "meta-llama/Meta-Llama-3-8B-Instruct",
+        "context_params": {"n_ctx": 4096}
+      }})?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let alias = service
+      .data_service()
+      .find_alias("llama3:instruct")
+      .expect("alias should exist");
+    assert_eq!("MyFactory/testalias-gguf", alias.repo.as_str());
+    assert_eq!("testalias.Q8_0.gguf", alias.filename);
+    assert_eq!(
+      ChatTemplate::Repo(Repo::try_from("meta-llama/Meta-Llama-3-8B-Instruct")?),
+      alias.chat_template
+    );
+    assert_eq!(Some(4096), alias.context_params.n_ctx);
+    assert_eq!(alias, response.json::<Alias>().await?);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_aliases_routes_replace_not_found(
+    app_service_stub: AppServiceTuple,
+  ) -> anyhow::Result<()> {
+    let AppServiceTuple(_temp_bodhi_home, _temp_hf_home, _, _, service) = app_service_stub;
+    let router = aliases_router().with_state(Arc::new(router_state(Arc::new(service))));
+    let response = router
+      .oneshot(Request::put("/models/phi3:mini").json(json! {{
+        "repo": "MyFactory/testalias-gguf",
+        "filename": "testalias.Q8_0.gguf",
+        "chat_template": ChatTemplateId::Phi3
+      }})?)
+      .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+    assert_eq!(
+      json! {"model alias 'phi3:mini' not found"},
+      response.json::<Value>().await?["error"]["message"]
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_aliases_routes_delete(app_service_stub: AppServiceTuple) -> anyhow::Result<()> {
+    let AppServiceTuple(_temp_bodhi_home, _temp_hf_home, _, _, service) = app_service_stub;
+    let service: Arc<dyn AppServiceFn> = Arc::new(service);
+    let router_state = audited_router_state(
+      service.clone(),
+      AuditAction::AliasDelete,
+      "tinyllama:instruct",
+    );
+    let router = aliases_router().with_state(Arc::new(router_state));
+    let response = router
+      .oneshot(Request::delete("/models/tinyllama:instruct").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(
+      None,
+      service.data_service().find_alias("tinyllama:instruct")
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_aliases_routes_whats_new() -> anyhow::Result<()> {
+    let diff = CatalogDiff {
+      created_at: Utc.with_ymd_and_hms(2024, 5, 1, 10, 30, 0).unwrap(),
+      changes: vec![CatalogChange {
+        kind: CatalogChangeKind::Added,
+        alias: "phi3:mini".to_string(),
+        repo: "microsoft/Phi-3-mini-4k-instruct-gguf".to_string(),
+        filename: "Phi-3-mini-4k-instruct-q4.gguf".to_string(),
+        previous: None,
+      }],
+    };
+    let mut data_service = MockDataService::new();
+    let changes = vec![diff.clone()];
+    data_service
+      .expect_list_catalog_changes()
+      .return_once(move || Ok(changes));
+    let service: Arc<dyn AppServiceFn> = Arc::new(AppServiceStubMock::new(
+      MockEnvServiceFn::new(),
+      MockHubService::new(),
+      data_service,
+    ));
+    let router = aliases_router().with_state(Arc::new(router_state(service)));
+    let response = router
+      .oneshot(Request::get("/models/whats-new").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response = response.json::<Vec<CatalogDiff>>().await?;
+    assert_eq!(vec![diff], response);
+    Ok(())
+  }
+}
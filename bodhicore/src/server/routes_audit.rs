@@ -0,0 +1,171 @@
+use super::RouterStateFn;
+use crate::{
+  db::{
+    objs::{ApiKey, AuditAction, AuditEntry},
+    DbServiceFn,
+  },
+  oai::OpenAIApiError,
+};
+use axum::{
+  extract::{Query, State},
+  response::Json,
+  routing::get,
+  Router,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::sync::Arc;
+
+const DEFAULT_AUDIT_LIMIT: u32 = 100;
+const MAX_AUDIT_LIMIT: u32 = 1000;
+
+pub fn audit_router() -> Router<Arc<dyn RouterStateFn>> {
+  Router::new().route("/audit", get(ui_audit_handler))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditQuery {
+  /// only the entries of the action, e.g. `setting_update`
+  pub action: Option<AuditAction>,
+  /// only the entries since the given time, in milliseconds since epoch
+  pub since: Option<i64>,
+  pub limit: Option<u32>,
+}
+
+/// Lists the audit log, the most recent entries first
+async fn ui_audit_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  Query(query): Query<AuditQuery>,
+) -> Result<Json<Vec<AuditEntry>>, OpenAIApiError> {
+  let since = match query.since {
+    Some(since) => Some(
+      DateTime::<Utc>::from_timestamp_millis(since).ok_or_else(|| {
+        OpenAIApiError::BadRequest(format!("invalid value '{since}' for 'since'"))
+      })?,
+    ),
+    None => None,
+  };
+  let limit = query
+    .limit
+    .unwrap_or(DEFAULT_AUDIT_LIMIT)
+    .clamp(1, MAX_AUDIT_LIMIT);
+  let entries = state
+    .db_service()
+    .list_audit_entries(query.action, since, limit)
+    .await?;
+  Ok(Json(entries))
+}
+
+/// Records the admin action in the audit log, with the name of the API key of the request as
+/// the actor. The action is already done, so an error recording it is only logged.
+pub(crate) async fn record_audit(
+  db_service: &dyn DbServiceFn,
+  api_key: Option<&ApiKey>,
+  action: AuditAction,
+  target: &str,
+  before: Option<String>,
+  after: Option<String>,
+) {
+  let mut entry = AuditEntry {
+    id: String::new(),
+    created_at: DateTime::<Utc>::default(),
+    actor: api_key.map(|api_key| api_key.name.clone()),
+    action,
+    target: target.to_string(),
+    before,
+    after,
+  };
+  if let Err(err) = db_service.save_audit_entry(&mut entry).await {
+    tracing::warn!(?err, ?entry, "error recording the audit entry");
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{audit_router, record_audit};
+  use crate::{
+    db::{
+      objs::{ApiKey, AuditAction, AuditEntry},
+      DbService,
+    },
+    server::RouterState,
+    service::MockAppServiceFn,
+    test_utils::{db_service, MockSharedContext, ResponseTestExt},
+  };
+  use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+  };
+  use chrono::{DateTime, Utc};
+  use rstest::rstest;
+  use std::sync::Arc;
+  use tempfile::TempDir;
+  use tower::ServiceExt;
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_audit_routes_list(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_temp, now, db_service) = db_service;
+    let api_key = ApiKey {
+      name: "ops".to_string(),
+      ..Default::default()
+    };
+    record_audit(
+      &db_service,
+      Some(&api_key),
+      AuditAction::SettingUpdate,
+      "BODHI_PORT",
+      Some("1135".to_string()),
+      Some("8080".to_string()),
+    )
+    .await;
+    record_audit(
+      &db_service,
+      None,
+      AuditAction::AliasUpdate,
+      "llama3:instruct",
+      None,
+      None,
+    )
+    .await;
+    let router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(MockAppServiceFn::new()),
+      Arc::new(db_service),
+    );
+    let router = audit_router().with_state(Arc::new(router_state));
+
+    let response = router
+      .clone()
+      .oneshot(Request::get("/audit").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let entries = response.json::<Vec<AuditEntry>>().await?;
+    let targets = entries
+      .iter()
+      .map(|entry| entry.target.as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(vec!["llama3:instruct", "BODHI_PORT"], targets);
+
+    let entries = router
+      .clone()
+      .oneshot(Request::get("/audit?action=setting_update").body(Body::empty())?)
+      .await?
+      .json::<Vec<AuditEntry>>()
+      .await?;
+    assert_eq!(1, entries.len());
+    assert_eq!(Some("ops".to_string()), entries[0].actor);
+    assert_eq!(now, entries[0].created_at);
+    assert_eq!(Some("1135".to_string()), entries[0].before);
+    assert_eq!(Some("8080".to_string()), entries[0].after);
+
+    let response = router
+      .oneshot(Request::get("/audit?action=unknown").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    Ok(())
+  }
+}
@@ -0,0 +1,525 @@
+use super::{
+  batches::{
+    check_interrupted, new_batch, parse_batch_input, run_batch, Batch, BatchStatus, BatchStore,
+    FileObject, BATCH_ENDPOINT, PURPOSE_BATCH,
+  },
+  routes_ui::chat_owner,
+  RouterStateFn,
+};
+use crate::{
+  db::objs::ApiKey,
+  error::Common,
+  oai::{ApiError, ApiErrorResponse, OpenAIApiError},
+};
+use axum::{
+  extract::{Multipart, Path, State},
+  http::{header, StatusCode},
+  response::{IntoResponse, Response},
+  routing::{get, post},
+  Extension, Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::sync::Arc;
+use tracing::Instrument;
+
+pub fn batches_router() -> Router<Arc<dyn RouterStateFn>> {
+  Router::new()
+    .route("/v1/files", post(create_file_handler))
+    .route("/v1/files/:id", get(get_file_handler))
+    .route("/v1/files/:id/content", get(file_content_handler))
+    .route(
+      "/v1/batches",
+      post(create_batch_handler).get(list_batches_handler),
+    )
+    .route("/v1/batches/:id", get(get_batch_handler))
+    .route("/v1/batches/:id/cancel", post(cancel_batch_handler))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CreateBatchRequest {
+  pub input_file_id: String,
+  pub endpoint: String,
+  pub completion_window: String,
+  #[serde(default)]
+  pub metadata: Option<Map<String, Value>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatchList {
+  pub object: String,
+  pub data: Vec<Batch>,
+  pub has_more: bool,
+}
+
+fn batch_store(state: &Arc<dyn RouterStateFn>) -> BatchStore {
+  BatchStore::new(&state.app_service().env_service().bodhi_home())
+}
+
+fn internal_error(err: Common) -> OpenAIApiError {
+  OpenAIApiError::InternalServer(err.to_string())
+}
+
+// the files and batches of the other owners are not found, same as the missing ones, all of them
+// are accessible when the request has no owner
+fn is_owned(owner: &Option<String>, of: &Option<String>) -> bool {
+  owner.is_none() || owner == of
+}
+
+fn owned_file(
+  store: &BatchStore,
+  id: &str,
+  owner: &Option<String>,
+) -> Result<Option<FileObject>, OpenAIApiError> {
+  let file = store.get_file(id).map_err(internal_error)?;
+  Ok(file.filter(|file| is_owned(owner, &file.owner)))
+}
+
+fn owned_batch(
+  store: &BatchStore,
+  id: &str,
+  owner: &Option<String>,
+) -> Result<Option<Batch>, OpenAIApiError> {
+  let batch = store.get_batch(id).map_err(internal_error)?;
+  Ok(batch.filter(|batch| is_owned(owner, &batch.owner)))
+}
+
+fn not_found(kind: &str, id: &str) -> Response {
+  let error = ApiError {
+    message: format!("No {kind} found with the id '{id}'"),
+    r#type: "invalid_request_error".to_string(),
+    param: Some("id".to_string()),
+    code: format!("{kind}_not_found"),
+  };
+  (StatusCode::NOT_FOUND, Json(ApiErrorResponse { error })).into_response()
+}
+
+/// Uploads the batch input file, as the `file` field of a multipart form with the `purpose` field
+async fn create_file_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  api_key: Option<Extension<ApiKey>>,
+  mut multipart: Multipart,
+) -> Result<Json<FileObject>, OpenAIApiError> {
+  let mut purpose = None::<String>;
+  let mut file = None::<(String, Vec<u8>)>;
+  while let Some(field) = multipart
+    .next_field()
+    .await
+    .map_err(|err| OpenAIApiError::BadRequest(err.to_string()))?
+  {
+    match field.name() {
+      Some("purpose") => {
+        let value = field
+          .text()
+          .await
+          .map_err(|err| OpenAIApiError::BadRequest(err.to_string()))?;
+        purpose = Some(value);
+      }
+      Some("file") => {
+        let filename = field.file_name().unwrap_or("input.jsonl").to_string();
+        let content = field
+          .bytes()
+          .await
+          .map_err(|err| OpenAIApiError::BadRequest(err.to_string()))?;
+        file = Some((filename, content.to_vec()));
+      }
+      _ => {}
+    }
+  }
+  let Some((filename, content)) = file else {
+    return Err(OpenAIApiError::BadRequest(
+      "'file' field is required".to_string(),
+    ));
+  };
+  if purpose.as_deref() != Some(PURPOSE_BATCH) {
+    return Err(OpenAIApiError::BadRequest(format!(
+      "only the '{PURPOSE_BATCH}' purpose is supported"
+    )));
+  }
+  parse_batch_input(&content).map_err(OpenAIApiError::BadRequest)?;
+  let file = batch_store(&state)
+    .create_file(&filename, PURPOSE_BATCH, &content, chat_owner(api_key))
+    .map_err(internal_error)?;
+  Ok(Json(file))
+}
+
+async fn get_file_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  api_key: Option<Extension<ApiKey>>,
+  Path(id): Path<String>,
+) -> Result<Response, OpenAIApiError> {
+  match owned_file(&batch_store(&state), &id, &chat_owner(api_key))? {
+    Some(file) => Ok(Json(file).into_response()),
+    None => Ok(not_found("file", &id)),
+  }
+}
+
+async fn file_content_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  api_key: Option<Extension<ApiKey>>,
+  Path(id): Path<String>,
+) -> Result<Response, OpenAIApiError> {
+  let store = batch_store(&state);
+  if owned_file(&store, &id, &chat_owner(api_key))?.is_none() {
+    return Ok(not_found("file", &id));
+  }
+  match store.file_content(&id).map_err(internal_error)? {
+    Some(content) => Ok(([(header::CONTENT_TYPE, "application/jsonl")], content).into_response()),
+    None => Ok(not_found("file", &id)),
+  }
+}
+
+/// Creates the batch for the input file, and runs it in the background
+async fn create_batch_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  api_key: Option<Extension<ApiKey>>,
+  Json(request): Json<CreateBatchRequest>,
+) -> Result<Response, OpenAIApiError> {
+  if request.endpoint != BATCH_ENDPOINT {
+    return Err(OpenAIApiError::BadRequest(format!(
+      "only the '{BATCH_ENDPOINT}' endpoint is supported"
+    )));
+  }
+  let owner = chat_owner(api_key);
+  let store = batch_store(&state);
+  if owned_file(&store, &request.input_file_id, &owner)?.is_none() {
+    return Ok(not_found("file", &request.input_file_id));
+  }
+  let Some(content) = store
+    .file_content(&request.input_file_id)
+    .map_err(internal_error)?
+  else {
+    return Ok(not_found("file", &request.input_file_id));
+  };
+  let requests = parse_batch_input(&content).map_err(OpenAIApiError::BadRequest)?;
+  let batch = new_batch(
+    &request.input_file_id,
+    &request.completion_window,
+    requests.len(),
+    request.metadata,
+    owner,
+  );
+  store.save_batch(&batch).map_err(internal_error)?;
+  tracing::info!(batch_id = %batch.id, requests = requests.len(), "starting the batch");
+  tokio::spawn(run_batch(state, store, batch.id.clone(), requests).in_current_span());
+  Ok(Json(batch).into_response())
+}
+
+async fn list_batches_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  api_key: Option<Extension<ApiKey>>,
+) -> Result<Json<BatchList>, OpenAIApiError> {
+  let owner = chat_owner(api_key);
+  let store = batch_store(&state);
+  let data = store
+    .list_batches()
+    .map_err(internal_error)?
+    .into_iter()
+    .filter(|batch| is_owned(&owner, &batch.owner))
+    .map(|batch| check_interrupted(&store, batch))
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(internal_error)?;
+  Ok(Json(BatchList {
+    object: "list".to_string(),
+    data,
+    has_more: false,
+  }))
+}
+
+async fn get_batch_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  api_key: Option<Extension<ApiKey>>,
+  Path(id): Path<String>,
+) -> Result<Response, OpenAIApiError> {
+  let store = batch_store(&state);
+  match owned_batch(&store, &id, &chat_owner(api_key))? {
+    Some(batch) => {
+      let batch = check_interrupted(&store, batch).map_err(internal_error)?;
+      Ok(Json(batch).into_response())
+    }
+    None => Ok(not_found("batch", &id)),
+  }
+}
+
+/// Stops the batch after its running request, the results so far are written to its output file
+async fn cancel_batch_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  api_key: Option<Extension<ApiKey>>,
+  Path(id): Path<String>,
+) -> Result<Response, OpenAIApiError> {
+  let store = batch_store(&state);
+  if owned_batch(&store, &id, &chat_owner(api_key))?.is_none() {
+    return Ok(not_found("batch", &id));
+  }
+  let updated = store
+    .update_batch(&id, |batch| {
+      if batch.status == BatchStatus::InProgress {
+        batch.status = BatchStatus::Cancelling;
+      }
+    })
+    .map_err(internal_error)?;
+  match updated {
+    Some(batch) => Ok(Json(batch).into_response()),
+    None => Ok(not_found("batch", &id)),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{batches_router, BatchList};
+  use crate::{
+    db::objs::ApiKey,
+    server::{
+      batches::{new_batch, BatchStore},
+      Batch, BatchStatus, FileObject, InferenceMonitor,
+    },
+    service::{MockDataService, MockEnvServiceFn, MockHubService},
+    test_utils::{AppServiceStubMock, MockRouterState, RequestTestExt, ResponseTestExt},
+  };
+  use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Extension,
+  };
+  use rstest::rstest;
+  use serde_json::{json, Value};
+  use std::{path::Path, sync::Arc};
+  use tempfile::TempDir;
+  use tower::ServiceExt;
+
+  static BOUNDARY: &str = "bodhi-test-boundary";
+
+  fn router_state(bodhi_home: &Path) -> MockRouterState {
+    let bodhi_home = bodhi_home.to_path_buf();
+    let mut env_service = MockEnvServiceFn::new();
+    env_service
+      .expect_bodhi_home()
+      .returning(move || bodhi_home.clone());
+    let app_service = Arc::new(AppServiceStubMock::new(
+      env_service,
+      MockHubService::new(),
+      MockDataService::new(),
+    ));
+    let mut router_state = MockRouterState::new();
+    router_state
+      .expect_app_service()
+      .returning(move || app_service.clone());
+    let monitor = Arc::new(InferenceMonitor::default());
+    router_state
+      .expect_inference_monitor()
+      .returning(move || monitor.clone());
+    router_state
+  }
+
+  fn upload_request(purpose: &str, content: &str) -> anyhow::Result<Request<Body>> {
+    let body = format!(
+      "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"purpose\"\r\n\r\n{purpose}\r\n\
+--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"requests.jsonl\"\r\n\
+Content-Type: application/jsonl\r\n\r\n{content}\r\n--{BOUNDARY}--\r\n"
+    );
+    let request = Request::post("/v1/files")
+      .header(
+        header::CONTENT_TYPE,
+        format!("multipart/form-data; boundary={BOUNDARY}"),
+      )
+      .body(Body::from(body))?;
+    Ok(request)
+  }
+
+  fn input_content() -> String {
+    json! {{
+      "custom_id": "req-1",
+      "method": "POST",
+      "url": "/v1/chat/completions",
+      "body": {
+        "model": "testalias:instruct",
+        "messages": [{"role": "user", "content": "What day comes after Monday?"}]
+      }
+    }}
+    .to_string()
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_batches_router_uploads_file() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let router = batches_router().with_state(Arc::new(router_state(temp.path())));
+    let response = router
+      .clone()
+      .oneshot(upload_request("batch", &input_content())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let file = response.json::<FileObject>().await?;
+    assert_eq!("requests.jsonl", file.filename);
+    assert_eq!("batch", file.purpose);
+    let response = router
+      .oneshot(Request::get(format!("/v1/files/{}/content", file.id)).body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(input_content(), response.text().await?);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("batch", "not json".to_string(), "line 1: expected ident at line 1 column 2")]
+  #[case("fine-tune", input_content(), "only the 'batch' purpose is supported")]
+  #[tokio::test]
+  async fn test_batches_router_upload_invalid_file(
+    #[case] purpose: &str,
+    #[case] content: String,
+    #[case] expected: &str,
+  ) -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let response = batches_router()
+      .with_state(Arc::new(router_state(temp.path())))
+      .oneshot(upload_request(purpose, &content)?)
+      .await?;
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    assert_eq!(
+      json! {expected},
+      response.json::<Value>().await?["error"]["message"]
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_batches_router_create_batch_input_file_not_found() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let response = batches_router()
+      .with_state(Arc::new(router_state(temp.path())))
+      .oneshot(Request::post("/v1/batches").json(json! {{
+        "input_file_id": "file-notexists",
+        "endpoint": "/v1/chat/completions",
+        "completion_window": "24h"
+      }})?)
+      .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+    assert_eq!(
+      json! {"file_not_found"},
+      response.json::<Value>().await?["error"]["code"]
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_batches_router_get_interrupted_batch_failed() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let batch = new_batch("file-input", "24h", 1, None, None);
+    BatchStore::new(temp.path()).save_batch(&batch)?;
+    let response = batches_router()
+      .with_state(Arc::new(router_state(temp.path())))
+      .oneshot(Request::get(format!("/v1/batches/{}", batch.id)).body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let batch = response.json::<Batch>().await?;
+    assert_eq!(BatchStatus::Failed, batch.status);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_batches_router_cancel_batch() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let batch = new_batch("file-input", "24h", 1, None, None);
+    BatchStore::new(temp.path()).save_batch(&batch)?;
+    let router = batches_router().with_state(Arc::new(router_state(temp.path())));
+    let response = router
+      .clone()
+      .oneshot(Request::post(format!("/v1/batches/{}/cancel", batch.id)).body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(
+      BatchStatus::Cancelling,
+      response.json::<Batch>().await?.status
+    );
+    let response = router
+      .oneshot(Request::post("/v1/batches/batch_notexists/cancel").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("bob", StatusCode::NOT_FOUND, 0)]
+  #[case("alice", StatusCode::OK, 1)]
+  #[tokio::test]
+  async fn test_batches_router_files_and_batches_only_of_owner(
+    #[case] name: &str,
+    #[case] status: StatusCode,
+    #[case] listed: usize,
+  ) -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let store = BatchStore::new(temp.path());
+    let file = store.create_file(
+      "requests.jsonl",
+      "batch",
+      input_content().as_bytes(),
+      Some("alice".to_string()),
+    )?;
+    let batch = new_batch(&file.id, "24h", 1, None, Some("alice".to_string()));
+    store.save_batch(&batch)?;
+    // the auth middleware adds the API key of the request
+    let api_key = ApiKey {
+      name: name.to_string(),
+      ..Default::default()
+    };
+    let router = batches_router()
+      .layer(Extension(api_key))
+      .with_state(Arc::new(router_state(temp.path())));
+    for path in [
+      format!("/v1/files/{}", file.id),
+      format!("/v1/files/{}/content", file.id),
+      format!("/v1/batches/{}", batch.id),
+    ] {
+      let response = router
+        .clone()
+        .oneshot(Request::get(&path).body(Body::empty())?)
+        .await?;
+      assert_eq!(status, response.status(), "{path}");
+    }
+    let response = router
+      .clone()
+      .oneshot(Request::post(format!("/v1/batches/{}/cancel", batch.id)).body(Body::empty())?)
+      .await?;
+    assert_eq!(status, response.status());
+    let response = router
+      .oneshot(Request::get("/v1/batches").body(Body::empty())?)
+      .await?;
+    assert_eq!(listed, response.json::<BatchList>().await?.data.len());
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_batches_router_create_batch_input_file_of_other_owner_not_found(
+  ) -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let file = BatchStore::new(temp.path()).create_file(
+      "requests.jsonl",
+      "batch",
+      input_content().as_bytes(),
+      Some("alice".to_string()),
+    )?;
+    let api_key = ApiKey {
+      name: "bob".to_string(),
+      ..Default::default()
+    };
+    let response = batches_router()
+      .layer(Extension(api_key))
+      .with_state(Arc::new(router_state(temp.path())))
+      .oneshot(Request::post("/v1/batches").json(json! {{
+        "input_file_id": file.id,
+        "endpoint": "/v1/chat/completions",
+        "completion_window": "24h"
+      }})?)
+      .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+    assert_eq!(
+      json! {"file_not_found"},
+      response.json::<Value>().await?["error"]["code"]
+    );
+    Ok(())
+  }
+}
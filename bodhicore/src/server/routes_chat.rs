@@ -1,73 +1,500 @@
-use super::RouterStateFn;
-use crate::oai::OpenAIApiError;
-use async_openai::types::CreateChatCompletionRequest;
+use super::{
+  canary::{completion_content, run_canary},
+  generations::GenerationGuard,
+  RouterStateFn,
+};
+use crate::{
+  db::objs::{ApiKey, UsageRecord},
+  oai::{ApiError, ApiErrorResponse, OpenAIApiError},
+  objs::{Alias, AliasFeature, ChatCompletionRequest, Pipeline, RequestPriority},
+  service::EnvServiceFn,
+};
 use axum::{
   body::Body,
   extract::State,
-  http::{header, HeaderValue, StatusCode},
+  http::{header, HeaderMap, HeaderValue, StatusCode},
   response::{sse::Event, IntoResponse, Response, Sse},
-  Json,
+  Extension, Json,
 };
 use futures_util::StreamExt;
-use std::{convert::Infallible, sync::Arc};
+use rand::Rng;
+use serde_json::Value;
+use std::{
+  convert::Infallible,
+  sync::{
+    atomic::{AtomicU8, Ordering},
+    Arc,
+  },
+  time::{Duration, Instant},
+};
+use tokio::sync::mpsc::{channel, Receiver, Sender};
 use tokio_stream::wrappers::ReceiverStream;
+use tracing::Instrument;
+use uuid::Uuid;
+
+pub(crate) const HEADER_BODHI_RETRIES: &str = "x-bodhi-retries";
+/// Id of the generation, to cancel it using `POST /v1/requests/{id}/cancel`
+pub(crate) const HEADER_BODHI_GENERATION_ID: &str = "x-bodhi-generation-id";
+/// Optional header for clients to declare their app name, recorded in the usage ledger
+pub(crate) const HEADER_CLIENT_NAME: &str = "x-client-name";
+/// `hit` or `miss` for the requests that can be served from the response cache
+pub(crate) const HEADER_BODHI_CACHE: &str = "x-bodhi-cache";
+/// `interactive` or `batch`, the batch requests are served after the interactive ones.
+/// The request cannot raise the priority of its API key.
+pub(crate) const HEADER_BODHI_PRIORITY: &str = "x-bodhi-priority";
+
+/// Number of times a chat completions request was retried by the server,
+/// attached as a response extension for middlewares and logging.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChatCompletionsRetries(pub u8);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct RetryPolicy {
+  retries: u8,
+  backoff: Duration,
+}
+
+impl RetryPolicy {
+  fn from_env(env_service: &dyn EnvServiceFn) -> Self {
+    Self {
+      retries: env_service.chat_retries(),
+      backoff: Duration::from_millis(env_service.chat_retry_backoff_ms()),
+    }
+  }
+
+  fn backoff(&self, attempt: u8) -> Duration {
+    self
+      .backoff
+      .saturating_mul(2u32.saturating_pow(attempt as u32))
+  }
+}
+
+/// Timeout of the chat completion, the request can only shorten the configured timeout
+fn generation_timeout(configured_secs: u64, requested_secs: Option<u64>) -> Option<Duration> {
+  let secs = match (configured_secs, requested_secs.filter(|secs| *secs > 0)) {
+    (0, requested) => requested,
+    (configured, Some(requested)) => Some(configured.min(requested)),
+    (configured, None) => Some(configured),
+  };
+  secs.map(Duration::from_secs)
+}
+
+// retries the request only for transient backend failures, and only if nothing has been sent to the client yet.
+// On timeout or cancellation, the generation is aborted by closing its channel, same as when the client disconnects.
+async fn chat_completions_with_retry(
+  state: Arc<dyn RouterStateFn>,
+  request: ChatCompletionRequest,
+  userdata: Sender<String>,
+  retries: Arc<AtomicU8>,
+  timeout: Option<Duration>,
+  generation: GenerationGuard,
+) -> crate::oai::Result<()> {
+  let deadline = timeout.map(|timeout| tokio::time::Instant::now() + timeout);
+  let timeout_secs = timeout.unwrap_or_default().as_secs();
+  let mut policy: Option<RetryPolicy> = None;
+  let mut attempt: u8 = 0;
+  loop {
+    let (tx, mut rx) = channel::<String>(100);
+    let forward_to = userdata.clone();
+    let cancelled = generation.cancelled();
+    let tokens = generation.tokens();
+    // returns whether a message was sent, and the error if the generation was aborted
+    let forwarder = tokio::spawn(async move {
+      tokio::pin!(cancelled);
+      let mut sent = false;
+      loop {
+        let next = async {
+          tokio::select! {
+            message = recv_until_closed(&mut rx, &forward_to) => Ok(message),
+            _ = &mut cancelled => Err(OpenAIApiError::Cancelled),
+          }
+        };
+        let next = match deadline {
+          Some(deadline) => tokio::time::timeout_at(deadline, next)
+            .await
+            .unwrap_or(Err(OpenAIApiError::Timeout(timeout_secs))),
+          None => next.await,
+        };
+        let message = match next {
+          Ok(Some(message)) => message,
+          Ok(None) => break,
+          Err(err) => return (sent, Some(err)),
+        };
+        match message_usage(&message) {
+          Some((_, completion_tokens)) => tokens.store(completion_tokens as u64, Ordering::SeqCst),
+          None if completion_content(&message).is_some() => {
+            tokens.fetch_add(1, Ordering::SeqCst);
+          }
+          None => {}
+        }
+        sent = true;
+        if forward_to.send(message).await.is_err() {
+          break;
+        }
+      }
+      (sent, None)
+    });
+    let result = state.chat_completions(request.clone(), tx).await;
+    let (sent, aborted) = forwarder.await.unwrap_or((true, None));
+    if let Some(err) = aborted {
+      tracing::warn!(?err, "aborted the chat completion");
+      return send_error_event(&request, &userdata, sent, err).await;
+    }
+    let err = match result {
+      Ok(()) => return Ok(()),
+      // the client disconnected, nothing to retry for
+      Err(_) if userdata.is_closed() => return Ok(()),
+      Err(err) if !sent && err.is_transient() => err,
+      Err(err) => return send_error_event(&request, &userdata, sent, err).await,
+    };
+    let policy = *policy
+      .get_or_insert_with(|| RetryPolicy::from_env(state.app_service().env_service().as_ref()));
+    if attempt >= policy.retries {
+      tracing::warn!(
+        ?err,
+        retries = attempt,
+        "chat completions failed after retries"
+      );
+      return Err(err);
+    }
+    let backoff = policy.backoff(attempt);
+    attempt += 1;
+    retries.store(attempt, Ordering::SeqCst);
+    tracing::warn!(
+      ?err,
+      retry = attempt,
+      backoff_ms = backoff.as_millis() as u64,
+      "transient error in chat completions, retrying"
+    );
+    tokio::time::sleep(backoff).await;
+  }
+}
+
+// the streamed responses that have started with `200 OK` get the error as an event of the
+// stream, sent to the client as the OpenAI error envelope followed by `[DONE]`
+async fn send_error_event(
+  request: &ChatCompletionRequest,
+  userdata: &Sender<String>,
+  sent: bool,
+  err: OpenAIApiError,
+) -> crate::oai::Result<()> {
+  if !sent || !request.stream.unwrap_or(false) {
+    return Err(err);
+  }
+  let event = ApiErrorResponse {
+    error: ApiError::from(&err),
+  };
+  let event =
+    serde_json::to_string(&event).map_err(|err| OpenAIApiError::InternalServer(err.to_string()))?;
+  _ = userdata.send(format!("error: {event}\n\n")).await;
+  Ok(())
+}
+
+// the events sent for a message of the generation, the stream ends with the first error, so the
+// streaming SDKs raise it instead of waiting for more chunks
+fn sse_events(msg: &str) -> (Vec<Event>, bool) {
+  if let Some(payload) = msg.strip_prefix("error: ") {
+    let error = ApiErrorResponse::from_event(payload);
+    let error = serde_json::to_string(&error).unwrap_or_default();
+    let events = vec![
+      Event::default().data(error),
+      Event::default().data("[DONE]"),
+    ];
+    return (events, true);
+  }
+  let data = match msg.strip_prefix("data: ") {
+    Some(data) => data.trim_end(),
+    None => {
+      tracing::error!(msg, "unknown event type raised from bodhi_server");
+      msg
+    }
+  };
+  (vec![Event::default().data(data)], false)
+}
+
+// receives the next message, or None once the downstream receiver is dropped as the client disconnected,
+// the caller then drops the upstream receiver, which stops the generation feeding it
+async fn recv_until_closed(
+  rx: &mut Receiver<String>,
+  downstream: &Sender<String>,
+) -> Option<String> {
+  tokio::select! {
+    message = rx.recv() => message,
+    _ = downstream.closed() => {
+      tracing::info!("client disconnected, cancelling the chat completion");
+      None
+    }
+  }
+}
+
+fn header_value(headers: &HeaderMap, name: impl header::AsHeaderName) -> Option<String> {
+  headers
+    .get(name)
+    .and_then(|value| value.to_str().ok())
+    .map(str::to_string)
+}
+
+/// token usage from a chat completion response, or from the final chunk of a stream
+fn message_usage(message: &str) -> Option<(i64, i64)> {
+  let data = message.strip_prefix("data: ").unwrap_or(message);
+  let value = serde_json::from_str::<Value>(data.trim()).ok()?;
+  let usage = value.get("usage")?;
+  Some((
+    usage.get("prompt_tokens")?.as_i64()?,
+    usage.get("completion_tokens")?.as_i64()?,
+  ))
+}
+
+// forwards the messages to the client, and once the completion ends, records the request
+// in the usage ledger if a response was sent. If the request is sampled for the canary,
+// runs it on the canary model alias in the background.
+async fn forward_and_record_usage(
+  state: Arc<dyn RouterStateFn>,
+  start: Instant,
+  mut record: UsageRecord,
+  canary: Option<(String, ChatCompletionRequest)>,
+  mut rx: Receiver<String>,
+  tx: Sender<String>,
+) {
+  let mut sent = false;
+  let mut output = String::new();
+  while let Some(message) = recv_until_closed(&mut rx, &tx).await {
+    if let Some((prompt_tokens, completion_tokens)) = message_usage(&message) {
+      record.prompt_tokens = prompt_tokens;
+      record.completion_tokens = completion_tokens;
+    }
+    if canary.is_some() {
+      if let Some(content) = completion_content(&message) {
+        output.push_str(&content);
+      }
+    }
+    sent = true;
+    if tx.send(message).await.is_err() {
+      break;
+    }
+  }
+  // stops the generation before recording the usage, if the client disconnected
+  drop(rx);
+  if !sent {
+    return;
+  }
+  record.latency_ms = Some(start.elapsed().as_millis() as i64);
+  tracing::info!(
+    alias = %record.model,
+    latency_ms = record.latency_ms,
+    prompt_tokens = record.prompt_tokens,
+    completion_tokens = record.completion_tokens,
+    "chat completion"
+  );
+  if let Err(err) = state.db_service().save_usage(&mut record).await {
+    tracing::warn!(?err, "error saving the usage record");
+  }
+  if let Some((canary_model, request)) = canary {
+    tokio::spawn(run_canary(state, request, canary_model, output));
+  }
+}
+
+// the request with its pipeline applied and the alias serving it, for the response cache. None
+// if the alias is not found or does not support chat, the request then fails while generating.
+fn resolve_alias(
+  state: &dyn RouterStateFn,
+  request: &ChatCompletionRequest,
+) -> Option<(ChatCompletionRequest, Alias)> {
+  let data_service = state.app_service().data_service();
+  let mut request = request.clone();
+  if let Some(name) = Pipeline::name_of(&request.model) {
+    data_service.find_pipeline(name).ok()??.apply(&mut request);
+  }
+  let alias = data_service.find_alias(&request.model)?;
+  alias
+    .supports(AliasFeature::Chat)
+    .then_some((request, alias))
+}
+
+// the responses served from the cache are recorded in the usage ledger like the generated ones
+async fn record_cached_usage(
+  state: &dyn RouterStateFn,
+  start: Instant,
+  mut record: UsageRecord,
+  message: &str,
+) {
+  if let Some((prompt_tokens, completion_tokens)) = message_usage(message) {
+    record.prompt_tokens = prompt_tokens;
+    record.completion_tokens = completion_tokens;
+  }
+  record.latency_ms = Some(start.elapsed().as_millis() as i64);
+  if let Err(err) = state.db_service().save_usage(&mut record).await {
+    tracing::warn!(?err, "error saving the usage record");
+  }
+}
 
 // TODO: custom Json extractor to dispatch OpenAIError response for bad request
 pub(crate) async fn chat_completions_handler(
   State(state): State<Arc<dyn RouterStateFn>>,
-  Json(request): Json<CreateChatCompletionRequest>,
+  api_key: Option<Extension<ApiKey>>,
+  headers: HeaderMap,
+  Json(mut request): Json<ChatCompletionRequest>,
 ) -> Result<Response, OpenAIApiError> {
+  let start = Instant::now();
   let stream = request.stream.unwrap_or(false);
-  let (tx, mut rx) = tokio::sync::mpsc::channel::<String>(100);
-  let handle = tokio::spawn(async move { state.chat_completions(request, tx).await });
-  if !stream {
-    if let Some(message) = rx.recv().await {
-      drop(rx);
-      _ = handle.await;
-      let response = Response::builder()
-        .status(StatusCode::OK)
-        .header(
-          header::CONTENT_TYPE,
-          HeaderValue::from_static(mime::APPLICATION_JSON.as_ref()),
-        )
-        .body(Body::from(message))
-        .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))?;
-      Ok(response)
-    } else {
-      Err(OpenAIApiError::InternalServer(
+  let record = UsageRecord {
+    model: request.model.clone(),
+    user_agent: header_value(&headers, header::USER_AGENT),
+    app_name: header_value(&headers, HEADER_CLIENT_NAME),
+    ..Default::default()
+  };
+  let response_cache = state.response_cache();
+  let cache_key = if response_cache.caches(&request) {
+    resolve_alias(state.as_ref(), &request)
+      .and_then(|(resolved, alias)| response_cache.key(&resolved, &alias))
+  } else {
+    None
+  };
+  if let Some(message) = cache_key.as_deref().and_then(|key| response_cache.get(key)) {
+    tracing::info!(alias = %request.model, "chat completion served from the response cache");
+    record_cached_usage(state.as_ref(), start, record, &message).await;
+    return Response::builder()
+      .status(StatusCode::OK)
+      .header(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(mime::APPLICATION_JSON.as_ref()),
+      )
+      .header(HEADER_BODHI_CACHE, "hit")
+      .body(Body::from(message))
+      .map_err(|err| OpenAIApiError::InternalServer(err.to_string()));
+  }
+  let requested = header_value(&headers, HEADER_BODHI_PRIORITY)
+    .and_then(|priority| priority.parse().ok())
+    .unwrap_or(request.priority());
+  let owner = api_key
+    .as_ref()
+    .map(|Extension(api_key)| api_key.name.clone());
+  let key_priority = api_key
+    .map(|Extension(api_key)| api_key.priority)
+    .unwrap_or_default();
+  // only set for batch, leaving the interactive requests as sent
+  if key_priority.min(requested) == RequestPriority::Batch {
+    request.set_priority(RequestPriority::Batch);
+  }
+  let canary = state
+    .app_service()
+    .env_service()
+    .canary_settings()
+    .sample(&request.model, rand::thread_rng().gen_range(0..100))
+    .map(|canary_model| (canary_model.to_string(), request.clone()));
+  let (tx, mut rx) = channel::<String>(100);
+  let (completion_tx, completion_rx) = channel::<String>(100);
+  let retries = Arc::new(AtomicU8::new(0));
+  let timeout = generation_timeout(
+    state.app_service().env_service().generation_timeout_secs(),
+    request.timeout_secs(),
+  );
+  // the request id is the generation id, clients can set it using the `x-request-id` header
+  // to cancel a non-streamed request before its response, an id already in flight is rejected
+  let generation_id =
+    header_value(&headers, "x-request-id").unwrap_or_else(|| Uuid::new_v4().to_string());
+  let generation = state
+    .generations()
+    .start(&generation_id, &request.model, owner)
+    .ok_or_else(|| OpenAIApiError::RequestInFlight(generation_id.clone()))?;
+  // the spawned tasks log within the request span, so their lines carry the request id
+  let usage_handle = tokio::spawn(
+    forward_and_record_usage(state.clone(), start, record, canary, completion_rx, tx)
+      .in_current_span(),
+  );
+  let handle = tokio::spawn(
+    chat_completions_with_retry(
+      state,
+      request,
+      completion_tx,
+      retries.clone(),
+      timeout,
+      generation,
+    )
+    .in_current_span(),
+  );
+  // the requests failing before the first message, e.g. for an unknown model, get the error with
+  // its status code, the streamed responses start only after it
+  let Some(message) = rx.recv().await else {
+    return match handle.await {
+      Ok(Err(err)) => Err(err),
+      _ => Err(OpenAIApiError::InternalServer(
         "receiver stream abruptly closed".to_string(),
-      ))
+      )),
+    };
+  };
+  if !stream {
+    drop(rx);
+    let result = handle.await;
+    _ = usage_handle.await;
+    let retries = retries.load(Ordering::SeqCst);
+    let mut builder = Response::builder()
+      .status(StatusCode::OK)
+      .header(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(mime::APPLICATION_JSON.as_ref()),
+      )
+      .header(HEADER_BODHI_RETRIES, HeaderValue::from(retries as u16))
+      .header(HEADER_BODHI_GENERATION_ID, generation_id);
+    if let Some(key) = cache_key {
+      builder = builder.header(HEADER_BODHI_CACHE, "miss");
+      // only the completed generations are cached, not the ones cancelled or timed out
+      if matches!(result, Ok(Ok(()))) {
+        response_cache.put(key, message.clone());
+      }
     }
+    let mut response = builder
+      .body(Body::from(message))
+      .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))?;
+    response
+      .extensions_mut()
+      .insert(ChatCompletionsRetries(retries));
+    Ok(response)
   } else {
     // TODO: not open up the response, but proxy it directly
-    let stream = ReceiverStream::new(rx).map::<Result<Event, Infallible>, _>(move |msg| {
-      let data = if msg.starts_with("data: ") {
-        msg
-          .strip_prefix("data: ")
-          .unwrap()
-          .strip_suffix("\n\n")
-          .unwrap()
-      } else if msg.starts_with("error: ") {
-        msg
-          .strip_prefix("error: ")
-          .unwrap()
-          .strip_suffix("\n\n")
-          .unwrap()
+    let mut failed = false;
+    let messages = futures_util::stream::once(async { message }).chain(ReceiverStream::new(rx));
+    let stream = messages.flat_map(move |msg| {
+      let events = if failed {
+        vec![]
       } else {
-        tracing::error!(msg, "unknown event type raised from bodhi_server");
-        &msg
+        let (events, error) = sse_events(&msg);
+        failed = error;
+        events
       };
-      Ok(Event::default().data(data))
+      futures_util::stream::iter(events.into_iter().map(Ok::<Event, Infallible>))
     });
-    Ok(Sse::new(stream).into_response())
+    let mut response = Sse::new(stream).into_response();
+    if let Ok(generation_id) = HeaderValue::from_str(&generation_id) {
+      response
+        .headers_mut()
+        .insert(HEADER_BODHI_GENERATION_ID, generation_id);
+    }
+    Ok(response)
   }
 }
 
 #[cfg(test)]
 mod test {
   use crate::{
-    server::routes_chat::chat_completions_handler,
-    test_utils::{MockRouterState, RequestTestExt, ResponseTestExt},
+    db::objs::{ApiKey, CanarySample, UsageRecord},
+    oai::OpenAIApiError,
+    objs::{Alias, ChatCompletionRequest, RequestPriority},
+    server::{
+      routes_chat::{
+        chat_completions_handler, generation_timeout, HEADER_BODHI_CACHE,
+        HEADER_BODHI_GENERATION_ID, HEADER_BODHI_PRIORITY, HEADER_BODHI_RETRIES,
+        HEADER_CLIENT_NAME,
+      },
+      Generations, ResponseCache,
+    },
+    service::{
+      CanarySettings, MockDataService, MockEnvServiceFn, MockHubService, ResponseCacheSettings,
+    },
+    shared_rw::ContextError,
+    test_utils::{
+      AppServiceStubMock, MockDbService, MockRouterState, RequestTestExt, ResponseTestExt,
+    },
   };
   use anyhow_trace::anyhow_trace;
   use async_openai::types::{
@@ -75,20 +502,87 @@ mod test {
     CreateChatCompletionRequestArgs, CreateChatCompletionResponse,
     CreateChatCompletionStreamResponse,
   };
-  use axum::{extract::Request, routing::post, Router};
-  use mockall::predicate::always;
+  use axum::{extract::Request, http::header::USER_AGENT, routing::post, Extension, Router};
+  use llama_server_bindings::LlamaCppError;
+  use mockall::{
+    predicate::{always, eq},
+    Sequence,
+  };
   use reqwest::StatusCode;
   use rstest::rstest;
-  use serde_json::json;
-  use std::sync::Arc;
-  use tokio::sync::mpsc::Sender;
+  use serde_json::{json, Value};
+  use std::{sync::Arc, time::Duration};
+  use tokio::sync::{
+    mpsc::{channel, Sender},
+    oneshot,
+  };
   use tower::ServiceExt;
 
+  fn router_state_with_env(env_service: MockEnvServiceFn) -> MockRouterState {
+    router_state_with_generations(env_service, Arc::new(Generations::default()))
+  }
+
+  fn router_state_with_generations(
+    env_service: MockEnvServiceFn,
+    generations: Arc<Generations>,
+  ) -> MockRouterState {
+    router_state_with_cache(
+      env_service,
+      MockDataService::new(),
+      generations,
+      Arc::new(ResponseCache::default()),
+    )
+  }
+
+  fn router_state_with_cache(
+    env_service: MockEnvServiceFn,
+    data_service: MockDataService,
+    generations: Arc<Generations>,
+    response_cache: Arc<ResponseCache>,
+  ) -> MockRouterState {
+    let app_service = Arc::new(AppServiceStubMock::new(
+      env_service,
+      MockHubService::new(),
+      data_service,
+    ));
+    let mut router_state = MockRouterState::new();
+    router_state
+      .expect_app_service()
+      .returning(move || app_service.clone());
+    router_state
+      .expect_generations()
+      .returning(move || generations.clone());
+    router_state
+      .expect_response_cache()
+      .returning(move || response_cache.clone());
+    router_state
+  }
+
+  fn router_state() -> MockRouterState {
+    let mut env_service = MockEnvServiceFn::new();
+    env_service
+      .expect_canary_settings()
+      .returning(CanarySettings::default);
+    env_service
+      .expect_generation_timeout_secs()
+      .return_const(0u64);
+    router_state_with_env(env_service)
+  }
+
+  fn expect_save_usage(router_state: &mut MockRouterState) {
+    let mut db_service = MockDbService::new();
+    db_service.expect_save_usage().returning(|_| Ok(()));
+    let db_service = Arc::new(db_service);
+    router_state
+      .expect_db_service()
+      .returning(move || db_service.clone());
+  }
+
   #[rstest]
   #[tokio::test]
   #[anyhow_trace]
   async fn test_routes_chat_completions_non_stream() -> anyhow::Result<()> {
-    let mut router_state = MockRouterState::new();
+    let mut router_state = router_state();
     let request = CreateChatCompletionRequestArgs::default()
       .model("testalias:instruct")
       .messages(vec![ChatCompletionRequestMessage::User(
@@ -121,6 +615,7 @@ mod test {
         tokio::spawn(async move { sender.send(response).await });
         Ok(())
       });
+    expect_save_usage(&mut router_state);
     let app = Router::new()
       .route("/v1/chat/completions", post(chat_completions_handler))
       .with_state(Arc::new(router_state));
@@ -144,11 +639,158 @@ mod test {
     Ok(())
   }
 
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_chat_completions_serves_deterministic_requests_from_cache(
+  ) -> anyhow::Result<()> {
+    let mut data_service = MockDataService::new();
+    data_service
+      .expect_find_alias()
+      .with(eq("testalias:instruct"))
+      .times(2)
+      .returning(|_| Some(Alias::testalias()));
+    let (mut router_state, response_cache) = router_state_with_enabled_cache(data_service);
+    router_state
+      .expect_chat_completions()
+      .times(1)
+      .return_once(|_, sender: Sender<String>| {
+        tokio::spawn(async move { sender.send(cached_response()).await });
+        Ok(())
+      });
+    let mut db_service = MockDbService::new();
+    db_service
+      .expect_save_usage()
+      .withf(|record| record.prompt_tokens == 12 && record.completion_tokens == 3)
+      .times(2)
+      .returning(|_| Ok(()));
+    let db_service = Arc::new(db_service);
+    router_state
+      .expect_db_service()
+      .returning(move || db_service.clone());
+    let app = Router::new()
+      .route("/v1/chat/completions", post(chat_completions_handler))
+      .with_state(Arc::new(router_state));
+    let request = deterministic_request();
+    let response = app
+      .clone()
+      .oneshot(Request::post("/v1/chat/completions").json(request.clone())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!("miss", response.headers().get(HEADER_BODHI_CACHE).unwrap());
+    let response = app
+      .oneshot(Request::post("/v1/chat/completions").json(request)?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!("hit", response.headers().get(HEADER_BODHI_CACHE).unwrap());
+    let result: CreateChatCompletionResponse = response.json().await?;
+    assert_eq!(
+      Some("Tuesday"),
+      result.choices[0].message.content.as_deref()
+    );
+    assert_eq!(1, response_cache.len());
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_chat_completions_does_not_serve_from_cache_if_alias_changed(
+  ) -> anyhow::Result<()> {
+    let mut data_service = MockDataService::new();
+    let mut seq = Sequence::new();
+    data_service
+      .expect_find_alias()
+      .times(1)
+      .in_sequence(&mut seq)
+      .returning(|_| Some(Alias::testalias()));
+    data_service
+      .expect_find_alias()
+      .times(1)
+      .in_sequence(&mut seq)
+      .returning(|_| {
+        Some(
+          Alias::test_alias_instruct_builder()
+            .filename("testalias.Q4_0.gguf".to_string())
+            .build()
+            .unwrap(),
+        )
+      });
+    let (mut router_state, response_cache) = router_state_with_enabled_cache(data_service);
+    router_state
+      .expect_chat_completions()
+      .times(2)
+      .returning(|_, sender: Sender<String>| {
+        tokio::spawn(async move { sender.send(cached_response()).await });
+        Ok(())
+      });
+    expect_save_usage(&mut router_state);
+    let app = Router::new()
+      .route("/v1/chat/completions", post(chat_completions_handler))
+      .with_state(Arc::new(router_state));
+    for _ in 0..2 {
+      let response = app
+        .clone()
+        .oneshot(Request::post("/v1/chat/completions").json(deterministic_request())?)
+        .await?;
+      assert_eq!(StatusCode::OK, response.status());
+      assert_eq!("miss", response.headers().get(HEADER_BODHI_CACHE).unwrap());
+    }
+    assert_eq!(2, response_cache.len());
+    Ok(())
+  }
+
+  fn router_state_with_enabled_cache(
+    data_service: MockDataService,
+  ) -> (MockRouterState, Arc<ResponseCache>) {
+    let mut env_service = MockEnvServiceFn::new();
+    env_service
+      .expect_canary_settings()
+      .returning(CanarySettings::default);
+    env_service
+      .expect_generation_timeout_secs()
+      .return_const(0u64);
+    let response_cache = Arc::new(ResponseCache::new(ResponseCacheSettings {
+      enabled: true,
+      ..Default::default()
+    }));
+    let router_state = router_state_with_cache(
+      env_service,
+      data_service,
+      Arc::new(Generations::default()),
+      response_cache.clone(),
+    );
+    (router_state, response_cache)
+  }
+
+  fn deterministic_request() -> Value {
+    json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+      "temperature": 0.0,
+    }}
+  }
+
+  fn cached_response() -> String {
+    json! {{
+      "id": "testid",
+      "model": "testalias:instruct",
+      "choices": [{
+        "index": 0,
+        "message": {"role": "assistant", "content": "Tuesday"},
+      }],
+      "created": 1704067200,
+      "object": "chat.completion",
+      "usage": {"prompt_tokens": 12, "completion_tokens": 3, "total_tokens": 15},
+    }}
+    .to_string()
+  }
+
   #[rstest]
   #[tokio::test]
   #[anyhow_trace]
   async fn test_routes_chat_completions_stream() -> anyhow::Result<()> {
-    let mut router_state = MockRouterState::new();
+    let mut router_state = router_state();
     let request = CreateChatCompletionRequestArgs::default()
       .model("testalias:instruct")
       .stream(true)
@@ -194,6 +836,7 @@ mod test {
         });
         Ok(())
       });
+    expect_save_usage(&mut router_state);
     let app = Router::new()
       .route("/v1/chat/completions", post(chat_completions_handler))
       .with_state(Arc::new(router_state));
@@ -218,4 +861,750 @@ mod test {
     assert_eq!("  After Monday, the next day is Tuesday.", content);
     Ok(())
   }
+
+  fn transient_error() -> OpenAIApiError {
+    OpenAIApiError::ContextError(ContextError::BodhiError(
+      LlamaCppError::BodhiServerChatCompletion(
+        "decode: failed to find free space in the KV cache".to_string(),
+      ),
+    ))
+  }
+
+  fn router_state_with_retries(retries: u8) -> MockRouterState {
+    let mut env_service = MockEnvServiceFn::new();
+    env_service.expect_chat_retries().return_const(retries);
+    env_service
+      .expect_chat_retry_backoff_ms()
+      .return_const(0u64);
+    env_service
+      .expect_canary_settings()
+      .returning(CanarySettings::default);
+    env_service
+      .expect_generation_timeout_secs()
+      .return_const(0u64);
+    router_state_with_env(env_service)
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_chat_completions_retries_transient_error() -> anyhow::Result<()> {
+    let mut router_state = router_state_with_retries(2);
+    let request = CreateChatCompletionRequestArgs::default()
+      .model("testalias:instruct")
+      .messages(vec![ChatCompletionRequestMessage::User(
+        ChatCompletionRequestUserMessageArgs::default()
+          .content("What day comes after Monday?")
+          .build()?,
+      )])
+      .build()?;
+    router_state
+      .expect_chat_completions()
+      .with(always(), always())
+      .times(1)
+      .return_once(|_, _| Err(transient_error()));
+    router_state
+      .expect_chat_completions()
+      .with(always(), always())
+      .times(1)
+      .return_once(|_, sender: Sender<String>| {
+        let response = json! {{
+          "id": "testid",
+          "model": "testalias:instruct",
+          "choices": [
+            {
+              "index": 0,
+              "message": {
+                "role": "assistant",
+                "content": "The day that comes after Monday is Tuesday."
+              },
+            }],
+          "created": 1704067200,
+          "object": "chat.completion",
+        }}
+        .to_string();
+        tokio::spawn(async move { sender.send(response).await });
+        Ok(())
+      });
+    expect_save_usage(&mut router_state);
+    let app = Router::new()
+      .route("/v1/chat/completions", post(chat_completions_handler))
+      .with_state(Arc::new(router_state));
+    let response = app
+      .oneshot(Request::post("/v1/chat/completions").json(request).unwrap())
+      .await
+      .unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(
+      "1",
+      response
+        .headers()
+        .get(HEADER_BODHI_RETRIES)
+        .unwrap()
+        .to_str()?
+    );
+    let result: CreateChatCompletionResponse = response.json().await.unwrap();
+    assert_eq!(
+      "The day that comes after Monday is Tuesday.",
+      result
+        .choices
+        .first()
+        .unwrap()
+        .message
+        .content
+        .as_ref()
+        .unwrap()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_chat_completions_returns_error_after_retries_exhausted() -> anyhow::Result<()>
+  {
+    let mut router_state = router_state_with_retries(1);
+    let request = CreateChatCompletionRequestArgs::default()
+      .model("testalias:instruct")
+      .messages(vec![ChatCompletionRequestMessage::User(
+        ChatCompletionRequestUserMessageArgs::default()
+          .content("What day comes after Monday?")
+          .build()?,
+      )])
+      .build()?;
+    router_state
+      .expect_chat_completions()
+      .with(always(), always())
+      .times(2)
+      .returning(|_, _| Err(transient_error()));
+    let app = Router::new()
+      .route("/v1/chat/completions", post(chat_completions_handler))
+      .with_state(Arc::new(router_state));
+    let response = app
+      .oneshot(Request::post("/v1/chat/completions").json(request).unwrap())
+      .await
+      .unwrap();
+    assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+    let result: serde_json::Value = response.json().await.unwrap();
+    assert_eq!("internal_server_error", result["error"]["code"]);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_chat_completions_does_not_retry_request_error() -> anyhow::Result<()> {
+    let mut router_state = router_state_with_retries(2);
+    let request = CreateChatCompletionRequestArgs::default()
+      .model("testalias:instruct")
+      .messages(vec![ChatCompletionRequestMessage::User(
+        ChatCompletionRequestUserMessageArgs::default()
+          .content("What day comes after Monday?")
+          .build()?,
+      )])
+      .build()?;
+    router_state
+      .expect_chat_completions()
+      .with(always(), always())
+      .times(1)
+      .return_once(|_, _| {
+        Err(OpenAIApiError::ContextError(ContextError::BodhiError(
+          LlamaCppError::BodhiServerChatCompletion("invalid logit_bias".to_string()),
+        )))
+      });
+    let app = Router::new()
+      .route("/v1/chat/completions", post(chat_completions_handler))
+      .with_state(Arc::new(router_state));
+    let response = app
+      .oneshot(Request::post("/v1/chat/completions").json(request).unwrap())
+      .await
+      .unwrap();
+    assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_chat_completions_records_usage() -> anyhow::Result<()> {
+    let mut router_state = router_state();
+    let request = CreateChatCompletionRequestArgs::default()
+      .model("testalias:instruct")
+      .messages(vec![ChatCompletionRequestMessage::User(
+        ChatCompletionRequestUserMessageArgs::default()
+          .content("What day comes after Monday?")
+          .build()?,
+      )])
+      .build()?;
+    router_state
+      .expect_chat_completions()
+      .with(always(), always())
+      .return_once(|_, sender: Sender<String>| {
+        let response = json! {{
+          "id": "testid",
+          "model": "testalias:instruct",
+          "choices": [
+            {
+              "index": 0,
+              "message": {
+                "role": "assistant",
+                "content": "Tuesday."
+              },
+            }],
+          "created": 1704067200,
+          "object": "chat.completion",
+          "usage": {"prompt_tokens": 15, "completion_tokens": 3, "total_tokens": 18},
+        }}
+        .to_string();
+        tokio::spawn(async move { sender.send(response).await });
+        Ok(())
+      });
+    let mut db_service = MockDbService::new();
+    db_service
+      .expect_save_usage()
+      .withf(|record: &UsageRecord| {
+        record.latency_ms.is_some()
+          && record
+            == &UsageRecord {
+              model: "testalias:instruct".to_string(),
+              user_agent: Some("notes-app/1.0".to_string()),
+              app_name: Some("notes".to_string()),
+              prompt_tokens: 15,
+              completion_tokens: 3,
+              latency_ms: record.latency_ms,
+              ..Default::default()
+            }
+      })
+      .times(1)
+      .returning(|_| Ok(()));
+    let db_service = Arc::new(db_service);
+    router_state
+      .expect_db_service()
+      .returning(move || db_service.clone());
+    let app = Router::new()
+      .route("/v1/chat/completions", post(chat_completions_handler))
+      .with_state(Arc::new(router_state));
+    let request = Request::post("/v1/chat/completions")
+      .header(USER_AGENT, "notes-app/1.0")
+      .header(HEADER_CLIENT_NAME, "notes")
+      .json(request)?;
+    let response = app.oneshot(request).await?;
+    assert_eq!(StatusCode::OK, response.status());
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(RequestPriority::Interactive, Some("batch"), RequestPriority::Batch)]
+  #[case(RequestPriority::Batch, Some("interactive"), RequestPriority::Batch)]
+  #[case(
+    RequestPriority::Interactive,
+    Some("urgent"),
+    RequestPriority::Interactive
+  )]
+  #[case(RequestPriority::Interactive, None, RequestPriority::Interactive)]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_chat_completions_sets_priority(
+    #[case] key_priority: RequestPriority,
+    #[case] header: Option<&str>,
+    #[case] expected: RequestPriority,
+  ) -> anyhow::Result<()> {
+    let mut router_state = router_state();
+    expect_save_usage(&mut router_state);
+    let request = CreateChatCompletionRequestArgs::default()
+      .model("testalias:instruct")
+      .messages(vec![ChatCompletionRequestMessage::User(
+        ChatCompletionRequestUserMessageArgs::default()
+          .content("What day comes after Monday?")
+          .build()?,
+      )])
+      .build()?;
+    router_state
+      .expect_chat_completions()
+      .withf(move |request, _| request.priority() == expected)
+      .return_once(|_, sender: Sender<String>| {
+        tokio::spawn(async move { sender.send(completion_response("Tuesday.")).await });
+        Ok(())
+      });
+    // the auth middleware adds the API key of the request
+    let api_key = ApiKey {
+      name: "batch-jobs".to_string(),
+      priority: key_priority,
+      ..Default::default()
+    };
+    let app = Router::new()
+      .route("/v1/chat/completions", post(chat_completions_handler))
+      .layer(Extension(api_key))
+      .with_state(Arc::new(router_state));
+    let mut builder = Request::post("/v1/chat/completions");
+    if let Some(header) = header {
+      builder = builder.header(HEADER_BODHI_PRIORITY, header);
+    }
+    let response = app.oneshot(builder.json(request)?).await?;
+    assert_eq!(StatusCode::OK, response.status());
+    Ok(())
+  }
+
+  fn completion_response(content: &str) -> String {
+    json! {{
+      "id": "testid",
+      "model": "testalias:instruct",
+      "choices": [
+        {
+          "index": 0,
+          "message": {
+            "role": "assistant",
+            "content": content
+          },
+        }],
+      "created": 1704067200,
+      "object": "chat.completion",
+    }}
+    .to_string()
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_chat_completions_runs_canary_after_responding() -> anyhow::Result<()> {
+    let mut env_service = MockEnvServiceFn::new();
+    env_service
+      .expect_canary_settings()
+      .returning(|| CanarySettings {
+        alias: Some("phi3:mini".to_string()),
+        percent: 100,
+      });
+    env_service
+      .expect_generation_timeout_secs()
+      .return_const(0u64);
+    let mut router_state = router_state_with_env(env_service);
+    let request = CreateChatCompletionRequestArgs::default()
+      .model("testalias:instruct")
+      .messages(vec![ChatCompletionRequestMessage::User(
+        ChatCompletionRequestUserMessageArgs::default()
+          .content("What day comes after Monday?")
+          .build()?,
+      )])
+      .build()?;
+    router_state
+      .expect_chat_completions()
+      .withf(|request: &ChatCompletionRequest, _| request.model == "testalias:instruct")
+      .times(1)
+      .return_once(|_, sender: Sender<String>| {
+        tokio::spawn(async move { sender.send(completion_response("Tuesday\n")).await });
+        Ok(())
+      });
+    router_state
+      .expect_chat_completions()
+      .withf(|request: &ChatCompletionRequest, _| {
+        request.model == "phi3:mini" && request.stream == Some(false)
+      })
+      .times(1)
+      .return_once(|_, sender: Sender<String>| {
+        tokio::spawn(async move { sender.send(completion_response("Wednesday\n")).await });
+        Ok(())
+      });
+    let (saved_tx, mut saved_rx) = channel::<CanarySample>(1);
+    let mut db_service = MockDbService::new();
+    db_service.expect_save_usage().returning(|_| Ok(()));
+    db_service
+      .expect_save_canary_sample()
+      .times(1)
+      .returning(move |sample| {
+        saved_tx.try_send(sample.clone()).unwrap();
+        Ok(())
+      });
+    let db_service = Arc::new(db_service);
+    router_state
+      .expect_db_service()
+      .returning(move || db_service.clone());
+    let app = Router::new()
+      .route("/v1/chat/completions", post(chat_completions_handler))
+      .with_state(Arc::new(router_state));
+    let response = app
+      .oneshot(Request::post("/v1/chat/completions").json(request)?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let sample = tokio::time::timeout(Duration::from_secs(5), saved_rx.recv())
+      .await?
+      .unwrap();
+    assert_eq!("testalias:instruct", sample.model);
+    assert_eq!("phi3:mini", sample.canary_model);
+    assert_eq!("Tuesday\n", sample.output);
+    assert_eq!("Wednesday\n", sample.canary_output);
+    assert_eq!(None, sample.canary_error);
+    assert_eq!(
+      "--- testalias:instruct\n+++ phi3:mini\n@@ -1 +1 @@\n-Tuesday\n+Wednesday\n",
+      sample.diff
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_chat_completions_cancel_on_disconnect() -> anyhow::Result<()> {
+    let mut router_state = router_state();
+    let request = CreateChatCompletionRequestArgs::default()
+      .model("testalias:instruct")
+      .stream(true)
+      .messages(vec![ChatCompletionRequestMessage::User(
+        ChatCompletionRequestUserMessageArgs::default()
+          .content("What day comes after Monday?")
+          .build()?,
+      )])
+      .build()?;
+    let (closed_tx, closed_rx) = oneshot::channel::<()>();
+    router_state
+      .expect_chat_completions()
+      .with(always(), always())
+      .return_once(move |_, sender: Sender<String>| {
+        tokio::spawn(async move {
+          let chunk = json! {{
+            "id": "testid-0",
+            "model": "testalias:instruct",
+            "choices": [{"index": 0, "delta": {"role": "assistant", "content": " After"}}],
+            "created": 1704067200,
+            "object": "chat.completion.chunk",
+          }};
+          _ = sender.send(format!("data: {chunk}\n\n")).await;
+          // the generation checks the sender is closed before generating the next token
+          sender.closed().await;
+          _ = closed_tx.send(());
+        });
+        Ok(())
+      });
+    expect_save_usage(&mut router_state);
+    let app = Router::new()
+      .route("/v1/chat/completions", post(chat_completions_handler))
+      .with_state(Arc::new(router_state));
+    let response = app
+      .oneshot(Request::post("/v1/chat/completions").json(request)?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    drop(response);
+    tokio::time::timeout(Duration::from_secs(1), closed_rx).await??;
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(0, None, None)]
+  #[case(0, Some(30), Some(30))]
+  #[case(60, None, Some(60))]
+  #[case(60, Some(30), Some(30))]
+  #[case(60, Some(120), Some(60))]
+  #[case(60, Some(0), Some(60))]
+  fn test_routes_chat_generation_timeout(
+    #[case] configured: u64,
+    #[case] requested: Option<u64>,
+    #[case] expected: Option<u64>,
+  ) {
+    assert_eq!(
+      expected.map(Duration::from_secs),
+      generation_timeout(configured, requested)
+    );
+  }
+
+  fn router_state_with_timeout(timeout_secs: u64) -> MockRouterState {
+    let mut env_service = MockEnvServiceFn::new();
+    env_service
+      .expect_canary_settings()
+      .returning(CanarySettings::default);
+    env_service
+      .expect_generation_timeout_secs()
+      .return_const(timeout_secs);
+    router_state_with_env(env_service)
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_chat_completions_non_stream_timeout() -> anyhow::Result<()> {
+    let mut router_state = router_state_with_timeout(0);
+    router_state
+      .expect_chat_completions()
+      .with(always(), always())
+      .return_once(|_, sender: Sender<String>| {
+        // a runaway generation, stopped only once its channel is closed
+        tokio::spawn(async move { sender.closed().await });
+        Ok(())
+      });
+    let app = Router::new()
+      .route("/v1/chat/completions", post(chat_completions_handler))
+      .with_state(Arc::new(router_state));
+    let request = json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+      "timeout_secs": 1,
+    }};
+    let response = app
+      .oneshot(Request::post("/v1/chat/completions").json(request)?)
+      .await?;
+    assert_eq!(StatusCode::GATEWAY_TIMEOUT, response.status());
+    let error = response.json::<serde_json::Value>().await?;
+    assert_eq!("timeout", error["error"]["code"]);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_chat_completions_stream_timeout() -> anyhow::Result<()> {
+    let mut router_state = router_state_with_timeout(1);
+    router_state
+      .expect_chat_completions()
+      .with(always(), always())
+      .return_once(|_, sender: Sender<String>| {
+        tokio::spawn(async move {
+          let chunk = json! {{
+            "id": "testid-0",
+            "model": "testalias:instruct",
+            "choices": [{"index": 0, "delta": {"role": "assistant", "content": " After"}}],
+            "created": 1704067200,
+            "object": "chat.completion.chunk",
+          }};
+          _ = sender.send(format!("data: {chunk}\n\n")).await;
+          sender.closed().await;
+        });
+        Ok(())
+      });
+    expect_save_usage(&mut router_state);
+    let app = Router::new()
+      .route("/v1/chat/completions", post(chat_completions_handler))
+      .with_state(Arc::new(router_state));
+    let request = json! {{
+      "model": "testalias:instruct",
+      "stream": true,
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+    }};
+    let response = app
+      .oneshot(Request::post("/v1/chat/completions").json(request)?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let text = response.text().await?;
+    let events = text
+      .lines()
+      .filter_map(|line| line.strip_prefix("data: "))
+      .collect::<Vec<_>>();
+    assert_eq!(3, events.len());
+    assert!(events[0].contains(" After"));
+    let error = serde_json::from_str::<serde_json::Value>(events[1])?;
+    assert_eq!("timeout", error["error"]["code"]);
+    assert_eq!("[DONE]", events[2]);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_chat_completions_cancel_by_request_id() -> anyhow::Result<()> {
+    let mut env_service = MockEnvServiceFn::new();
+    env_service
+      .expect_canary_settings()
+      .returning(CanarySettings::default);
+    env_service
+      .expect_generation_timeout_secs()
+      .return_const(0u64);
+    let generations = Arc::new(Generations::default());
+    let mut router_state = router_state_with_generations(env_service, generations.clone());
+    let (closed_tx, closed_rx) = oneshot::channel::<()>();
+    router_state
+      .expect_chat_completions()
+      .with(always(), always())
+      .return_once(move |_, sender: Sender<String>| {
+        tokio::spawn(async move {
+          let chunk = json! {{
+            "id": "testid-0",
+            "model": "testalias:instruct",
+            "choices": [{"index": 0, "delta": {"role": "assistant", "content": " After"}}],
+            "created": 1704067200,
+            "object": "chat.completion.chunk",
+          }};
+          _ = sender.send(format!("data: {chunk}\n\n")).await;
+          sender.closed().await;
+          _ = closed_tx.send(());
+        });
+        Ok(())
+      });
+    expect_save_usage(&mut router_state);
+    let app = Router::new()
+      .route("/v1/chat/completions", post(chat_completions_handler))
+      .with_state(Arc::new(router_state));
+    let request = json! {{
+      "model": "testalias:instruct",
+      "stream": true,
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+    }};
+    let response = app
+      .oneshot(
+        Request::post("/v1/chat/completions")
+          .header("x-request-id", "req-1")
+          .json(request)?,
+      )
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(
+      "req-1",
+      response
+        .headers()
+        .get(HEADER_BODHI_GENERATION_ID)
+        .expect("generation id header should be set")
+        .to_str()?
+    );
+    assert!(generations.cancel("req-1", None));
+    let text = response.text().await?;
+    let events = text
+      .lines()
+      .filter_map(|line| line.strip_prefix("data: "))
+      .collect::<Vec<_>>();
+    assert_eq!(3, events.len());
+    let error = serde_json::from_str::<serde_json::Value>(events[1])?;
+    assert_eq!("cancelled", error["error"]["code"]);
+    assert_eq!("[DONE]", events[2]);
+    tokio::time::timeout(Duration::from_secs(1), closed_rx).await??;
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_chat_completions_rejects_request_id_in_flight() -> anyhow::Result<()> {
+    let mut env_service = MockEnvServiceFn::new();
+    env_service
+      .expect_canary_settings()
+      .returning(CanarySettings::default);
+    env_service
+      .expect_generation_timeout_secs()
+      .return_const(0u64);
+    let generations = Arc::new(Generations::default());
+    let _running = generations
+      .start("req-1", "testalias:instruct", Some("alice".to_string()))
+      .expect("generation should start");
+    let mut router_state = router_state_with_generations(env_service, generations.clone());
+    router_state.expect_chat_completions().never();
+    let app = Router::new()
+      .route("/v1/chat/completions", post(chat_completions_handler))
+      .with_state(Arc::new(router_state));
+    let request = json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+    }};
+    let response = app
+      .oneshot(
+        Request::post("/v1/chat/completions")
+          .header("x-request-id", "req-1")
+          .json(request)?,
+      )
+      .await?;
+    assert_eq!(StatusCode::CONFLICT, response.status());
+    let error = response.json::<serde_json::Value>().await?;
+    assert_eq!("request_in_flight", error["error"]["code"]);
+    assert!(generations.cancel("req-1", Some("alice")));
+    Ok(())
+  }
+
+  #[rstest]
+  #[case::failed_mid_stream(
+    None,
+    Err(ContextError::BodhiError(LlamaCppError::BodhiServerChatCompletion("test error".to_string()))),
+    "internal_server_error",
+    "bodhi_server_chat_completion: test error"
+  )]
+  #[case::error_message_of_llama_cpp(
+    Some("error: failed to decode the batch\n\n"),
+    Ok(()),
+    "internal_server_error",
+    "failed to decode the batch"
+  )]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_chat_completions_stream_error_event(
+    #[case] error_message: Option<&'static str>,
+    #[case] result: Result<(), ContextError>,
+    #[case] code: &str,
+    #[case] message: &str,
+  ) -> anyhow::Result<()> {
+    let mut router_state = router_state();
+    router_state
+      .expect_chat_completions()
+      .with(always(), always())
+      .return_once(move |_, sender: Sender<String>| {
+        let chunk = json! {{
+          "id": "testid-0",
+          "model": "testalias:instruct",
+          "choices": [{"index": 0, "delta": {"role": "assistant", "content": " After"}}],
+          "created": 1704067200,
+          "object": "chat.completion.chunk",
+        }};
+        _ = sender.try_send(format!("data: {chunk}\n\n"));
+        if let Some(error_message) = error_message {
+          _ = sender.try_send(error_message.to_string());
+          _ = sender.try_send(format!("data: {chunk}\n\n"));
+        }
+        result.map_err(OpenAIApiError::from)
+      });
+    expect_save_usage(&mut router_state);
+    let app = Router::new()
+      .route("/v1/chat/completions", post(chat_completions_handler))
+      .with_state(Arc::new(router_state));
+    let request = json! {{
+      "model": "testalias:instruct",
+      "stream": true,
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+    }};
+    let response = app
+      .oneshot(Request::post("/v1/chat/completions").json(request)?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let text = response.text().await?;
+    let events = text
+      .lines()
+      .filter_map(|line| line.strip_prefix("data: "))
+      .collect::<Vec<_>>();
+    assert_eq!(3, events.len());
+    assert!(events[0].contains(" After"));
+    let error = serde_json::from_str::<serde_json::Value>(events[1])?;
+    assert_eq!(code, error["error"]["code"]);
+    assert_eq!(message, error["error"]["message"]);
+    assert_eq!("[DONE]", events[2]);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(true)]
+  #[case(false)]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_chat_completions_model_not_found(
+    #[case] stream: bool,
+  ) -> anyhow::Result<()> {
+    let mut router_state = router_state();
+    router_state
+      .expect_chat_completions()
+      .with(always(), always())
+      .return_once(|request, _| Err(OpenAIApiError::ModelNotFound(request.model)));
+    let app = Router::new()
+      .route("/v1/chat/completions", post(chat_completions_handler))
+      .with_state(Arc::new(router_state));
+    let request = json! {{
+      "model": "llama3:foo",
+      "stream": stream,
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+    }};
+    let response = app
+      .oneshot(Request::post("/v1/chat/completions").json(request)?)
+      .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+    let error = response.json::<serde_json::Value>().await?;
+    assert_eq!(
+      json! {{"error": {
+        "message": "The model 'llama3:foo' does not exist, run `bodhi list` to list the model aliases",
+        "type": "invalid_request_error",
+        "param": "model",
+        "code": "model_not_found",
+      }}},
+      error
+    );
+    Ok(())
+  }
 }
@@ -0,0 +1,515 @@
+use super::{routes_audit::record_audit, RouterStateFn};
+use crate::{
+  db::objs::{ApiKey, AuditAction, Download, DownloadStatus},
+  oai::OpenAIApiError,
+  objs::{Repo, DEFAULT_REVISION},
+};
+use axum::{
+  extract::{Path, Query, State},
+  http::StatusCode,
+  response::{
+    sse::{Event, KeepAlive},
+    Json, Sse,
+  },
+  routing::{get, post},
+  Extension, Router,
+};
+use futures_util::{future::ready, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::{convert::Infallible, sync::Arc};
+use tokio::sync::broadcast::error::RecvError;
+
+const DEFAULT_DOWNLOADS_LIMIT: u32 = 100;
+const MAX_DOWNLOADS_LIMIT: u32 = 1000;
+
+pub fn downloads_router() -> Router<Arc<dyn RouterStateFn>> {
+  Router::new()
+    .route(
+      "/downloads",
+      get(ui_downloads_handler).post(ui_download_create_handler),
+    )
+    .route("/downloads/events", get(ui_downloads_events_handler))
+    .route("/downloads/:id", get(ui_download_handler))
+    .route("/downloads/:id/events", get(ui_download_events_handler))
+    .route("/downloads/:id/pause", post(ui_download_pause_handler))
+    .route("/downloads/:id/resume", post(ui_download_resume_handler))
+    .route("/downloads/:id/cancel", post(ui_download_cancel_handler))
+    .route("/pull", post(ui_pull_handler))
+}
+
+/// The file to pull into $HF_HOME, at the `main` revision if not given
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DownloadRequest {
+  pub repo: String,
+  pub filename: String,
+  #[serde(default)]
+  pub revision: Option<String>,
+}
+
+/// The file to pull at the `main` revision, for the pull button of the UI
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PullRequest {
+  pub repo: String,
+  pub filename: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PullResponse {
+  pub id: String,
+}
+
+/// Progress of a download, the same as the progress bar of `bodhi pull`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadProgressEvent {
+  pub id: String,
+  pub filename: String,
+  pub status: DownloadStatus,
+  pub downloaded_bytes: u64,
+  pub total_bytes: Option<u64>,
+  /// `None` till the size of the file is known
+  pub percent: Option<u8>,
+}
+
+impl From<&Download> for DownloadProgressEvent {
+  fn from(download: &Download) -> Self {
+    let percent = match download.total_bytes {
+      _ if download.status == DownloadStatus::Completed => Some(100),
+      Some(total) if total > 0 => Some((download.downloaded_bytes.min(total) * 100 / total) as u8),
+      _ => None,
+    };
+    DownloadProgressEvent {
+      id: download.id.clone(),
+      filename: download.filename.clone(),
+      status: download.status,
+      downloaded_bytes: download.downloaded_bytes,
+      total_bytes: download.total_bytes,
+      percent,
+    }
+  }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct DownloadsQuery {
+  pub limit: Option<u32>,
+}
+
+/// Lists the downloads, the most recent first
+async fn ui_downloads_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  Query(query): Query<DownloadsQuery>,
+) -> Result<Json<Vec<Download>>, OpenAIApiError> {
+  let limit = query
+    .limit
+    .unwrap_or(DEFAULT_DOWNLOADS_LIMIT)
+    .clamp(1, MAX_DOWNLOADS_LIMIT);
+  let downloads = state.downloads().list(limit).await?;
+  Ok(Json(downloads))
+}
+
+/// Queues the download, it runs in the background once the downloads queued before it are done
+async fn ui_download_create_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  api_key: Option<Extension<ApiKey>>,
+  Json(request): Json<DownloadRequest>,
+) -> Result<(StatusCode, Json<Download>), OpenAIApiError> {
+  let download = queue_download(&state, api_key.as_ref().map(|Extension(k)| k), request).await?;
+  Ok((StatusCode::CREATED, Json(download)))
+}
+
+/// Queues the pull of the file like `POST /api/ui/downloads`, returning the id of its download
+async fn ui_pull_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  api_key: Option<Extension<ApiKey>>,
+  Json(request): Json<PullRequest>,
+) -> Result<(StatusCode, Json<PullResponse>), OpenAIApiError> {
+  let request = DownloadRequest {
+    repo: request.repo,
+    filename: request.filename,
+    revision: None,
+  };
+  let download = queue_download(&state, api_key.as_ref().map(|Extension(k)| k), request).await?;
+  Ok((StatusCode::CREATED, Json(PullResponse { id: download.id })))
+}
+
+async fn ui_download_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  Path(id): Path<String>,
+) -> Result<Json<Download>, OpenAIApiError> {
+  Ok(Json(state.downloads().get(&id).await?))
+}
+
+async fn ui_download_pause_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  Path(id): Path<String>,
+) -> Result<Json<Download>, OpenAIApiError> {
+  Ok(Json(state.downloads().pause(&id).await?))
+}
+
+async fn ui_download_resume_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  Path(id): Path<String>,
+) -> Result<Json<Download>, OpenAIApiError> {
+  Ok(Json(state.downloads().resume(&id).await?))
+}
+
+async fn ui_download_cancel_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  Path(id): Path<String>,
+) -> Result<Json<Download>, OpenAIApiError> {
+  Ok(Json(state.downloads().cancel(&id).await?))
+}
+
+/// Sends the downloads as they change, as `download` events
+async fn ui_downloads_events_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+  let events = state.downloads().subscribe();
+  let stream = futures_util::stream::unfold(events, |mut events| async move {
+    loop {
+      match events.recv().await {
+        Ok(download) => return Some((Ok(download_event(&download)), events)),
+        // a slow listener skips the events it missed, the next event has the latest progress
+        Err(RecvError::Lagged(_)) => continue,
+        Err(RecvError::Closed) => return None,
+      }
+    }
+  });
+  Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+/// Sends the progress of the download, starting with its current progress, till it is completed,
+/// failed or cancelled
+async fn ui_download_events_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  Path(id): Path<String>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, OpenAIApiError> {
+  let downloads = state.downloads();
+  // subscribed before reading the download, so the progress in between is not missed
+  let events = downloads.subscribe();
+  let download = downloads.get(&id).await?;
+  let updates = futures_util::stream::unfold(events, move |mut events| {
+    let id = id.clone();
+    async move {
+      loop {
+        match events.recv().await {
+          Ok(download) if download.id == id => return Some((download, events)),
+          Ok(_) | Err(RecvError::Lagged(_)) => continue,
+          Err(RecvError::Closed) => return None,
+        }
+      }
+    }
+  });
+  let stream = futures_util::stream::once(ready(download))
+    .chain(updates)
+    .scan(false, |finished, download| {
+      if *finished {
+        return ready(None);
+      }
+      *finished = download.status.is_finished();
+      let progress = DownloadProgressEvent::from(&download);
+      let data = serde_json::to_string(&progress).unwrap_or_default();
+      ready(Some(Ok(Event::default().data(data))))
+    });
+  Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+pub(crate) async fn queue_download(
+  state: &Arc<dyn RouterStateFn>,
+  api_key: Option<&ApiKey>,
+  request: DownloadRequest,
+) -> Result<Download, OpenAIApiError> {
+  let repo =
+    Repo::try_from(request.repo).map_err(|err| OpenAIApiError::BadRequest(err.to_string()))?;
+  if request.filename.trim().is_empty() {
+    return Err(OpenAIApiError::BadRequest(
+      "filename should not be empty".to_string(),
+    ));
+  }
+  let revision = request
+    .revision
+    .unwrap_or_else(|| DEFAULT_REVISION.to_string());
+  let download = state
+    .downloads()
+    .enqueue(&repo, &request.filename, &revision)
+    .await?;
+  record_audit(
+    state.db_service().as_ref(),
+    api_key,
+    AuditAction::ModelPull,
+    &format!("{}/{}", download.repo, download.filename),
+    None,
+    serde_json::to_string(&download).ok(),
+  )
+  .await;
+  Ok(download)
+}
+
+pub(crate) fn download_event(download: &Download) -> Event {
+  Event::default()
+    .event("download")
+    .data(serde_json::to_string(download).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod test {
+  use super::{downloads_router, DownloadProgressEvent, PullResponse};
+  use crate::{
+    db::{
+      objs::{AuditAction, Download, DownloadStatus},
+      DbService,
+    },
+    objs::HubFile,
+    server::DownloadManager,
+    service::{AppServiceFn, MockDataService, MockEnvServiceFn, MockHubService},
+    test_utils::{
+      db_service, AppServiceStubMock, MockDbService, MockRouterState, RequestTestExt,
+      ResponseTestExt,
+    },
+  };
+  use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+  };
+  use chrono::{DateTime, Utc};
+  use rstest::rstest;
+  use serde_json::{json, Value};
+  use std::sync::Arc;
+  use tempfile::TempDir;
+  use tower::ServiceExt;
+
+  fn router_state(hub_service: MockHubService, db_service: DbService) -> MockRouterState {
+    let app_service: Arc<dyn AppServiceFn> = Arc::new(AppServiceStubMock::new(
+      MockEnvServiceFn::new(),
+      hub_service,
+      MockDataService::new(),
+    ));
+    let downloads = Arc::new(DownloadManager::new(app_service, Arc::new(db_service)));
+    let mut router_state = MockRouterState::new();
+    router_state
+      .expect_downloads()
+      .returning(move || downloads.clone());
+    router_state
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_downloads_routes_create(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_tempdir, _now, db_service) = db_service;
+    let mut hub_service = MockHubService::new();
+    hub_service
+      .expect_download_with_progress()
+      .returning(|_, _, _, _| Ok(HubFile::testalias()));
+    let mut audit_db = MockDbService::new();
+    audit_db
+      .expect_save_audit_entry()
+      .withf(|entry| {
+        entry.action == AuditAction::ModelPull
+          && entry.target == "MyFactory/testalias-gguf/testalias.Q8_0.gguf"
+      })
+      .times(1)
+      .returning(|_| Ok(()));
+    let audit_db = Arc::new(audit_db);
+    let mut router_state = router_state(hub_service, db_service);
+    router_state
+      .expect_db_service()
+      .returning(move || audit_db.clone());
+    let router = downloads_router().with_state(Arc::new(router_state));
+    let response = router
+      .clone()
+      .oneshot(Request::post("/downloads").json(json! {{
+        "repo": "MyFactory/testalias-gguf",
+        "filename": "testalias.Q8_0.gguf"
+      }})?)
+      .await?;
+    assert_eq!(StatusCode::CREATED, response.status());
+    let download = response.json::<Download>().await?;
+    assert_eq!("main", download.revision);
+    assert_eq!(DownloadStatus::Queued, download.status);
+
+    let response = router
+      .oneshot(Request::get("/downloads").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let downloads = response.json::<Vec<Download>>().await?;
+    assert_eq!(
+      vec![download.id],
+      downloads.into_iter().map(|d| d.id).collect::<Vec<_>>()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(json! {{"repo": "testalias-gguf", "filename": "testalias.Q8_0.gguf"}})]
+  #[case(json! {{"repo": "MyFactory/testalias-gguf", "filename": " "}})]
+  #[awt]
+  #[tokio::test]
+  async fn test_downloads_routes_create_invalid(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+    #[case] request: Value,
+  ) -> anyhow::Result<()> {
+    let (_tempdir, _now, db_service) = db_service;
+    let router =
+      downloads_router().with_state(Arc::new(router_state(MockHubService::new(), db_service)));
+    let response = router
+      .oneshot(Request::post("/downloads").json(request)?)
+      .await?;
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Request::get("/downloads/missing"), StatusCode::NOT_FOUND)]
+  #[case(Request::post("/downloads/missing/pause"), StatusCode::NOT_FOUND)]
+  #[case(Request::get("/downloads/missing/events"), StatusCode::NOT_FOUND)]
+  #[awt]
+  #[tokio::test]
+  async fn test_downloads_routes_not_found(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+    #[case] request: axum::http::request::Builder,
+    #[case] status: StatusCode,
+  ) -> anyhow::Result<()> {
+    let (_tempdir, _now, db_service) = db_service;
+    let router =
+      downloads_router().with_state(Arc::new(router_state(MockHubService::new(), db_service)));
+    let response = router.oneshot(request.body(Body::empty())?).await?;
+    assert_eq!(status, response.status());
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_downloads_routes_resume_completed(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_tempdir, _now, db_service) = db_service;
+    let mut completed = Download {
+      repo: "MyFactory/testalias-gguf".to_string(),
+      filename: "testalias.Q8_0.gguf".to_string(),
+      revision: "main".to_string(),
+      status: DownloadStatus::Completed,
+      ..Default::default()
+    };
+    crate::db::DbServiceFn::create_download(&db_service, &mut completed).await?;
+    let router =
+      downloads_router().with_state(Arc::new(router_state(MockHubService::new(), db_service)));
+    let response = router
+      .oneshot(Request::post(format!("/downloads/{}/resume", completed.id)).body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    assert_eq!(
+      json! {format!("download '{}' is completed, it cannot be resumed", completed.id)},
+      response.json::<Value>().await?["error"]["message"]
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_downloads_routes_pull(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_tempdir, _now, db_service) = db_service;
+    let mut hub_service = MockHubService::new();
+    hub_service
+      .expect_download_with_progress()
+      .returning(|_, _, _, _| Ok(HubFile::testalias()));
+    let mut audit_db = MockDbService::new();
+    audit_db
+      .expect_save_audit_entry()
+      .withf(|entry| entry.action == AuditAction::ModelPull)
+      .times(1)
+      .returning(|_| Ok(()));
+    let audit_db = Arc::new(audit_db);
+    let mut router_state = router_state(hub_service, db_service);
+    router_state
+      .expect_db_service()
+      .returning(move || audit_db.clone());
+    let router = downloads_router().with_state(Arc::new(router_state));
+    let response = router
+      .clone()
+      .oneshot(Request::post("/pull").json(json! {{
+        "repo": "MyFactory/testalias-gguf",
+        "filename": "testalias.Q8_0.gguf"
+      }})?)
+      .await?;
+    assert_eq!(StatusCode::CREATED, response.status());
+    let PullResponse { id } = response.json::<PullResponse>().await?;
+
+    let response = router
+      .oneshot(Request::get(format!("/downloads/{id}/events")).body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let events = response.sse::<DownloadProgressEvent>().await?;
+    let last = events.last().expect("progress events not sent");
+    assert_eq!(id, last.id);
+    assert_eq!(DownloadStatus::Completed, last.status);
+    assert_eq!(Some(100), last.percent);
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_downloads_routes_events_of_finished_download(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_tempdir, _now, db_service) = db_service;
+    let mut failed = Download {
+      repo: "MyFactory/testalias-gguf".to_string(),
+      filename: "testalias.Q8_0.gguf".to_string(),
+      revision: "main".to_string(),
+      status: DownloadStatus::Failed,
+      downloaded_bytes: 512,
+      total_bytes: Some(2048),
+      error: Some("connection reset".to_string()),
+      ..Default::default()
+    };
+    crate::db::DbServiceFn::create_download(&db_service, &mut failed).await?;
+    let router =
+      downloads_router().with_state(Arc::new(router_state(MockHubService::new(), db_service)));
+    let response = router
+      .oneshot(Request::get(format!("/downloads/{}/events", failed.id)).body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let events = response.sse::<DownloadProgressEvent>().await?;
+    assert_eq!(
+      vec![DownloadProgressEvent {
+        id: failed.id.clone(),
+        filename: "testalias.Q8_0.gguf".to_string(),
+        status: DownloadStatus::Failed,
+        downloaded_bytes: 512,
+        total_bytes: Some(2048),
+        percent: Some(25),
+      }],
+      events
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(DownloadStatus::Downloading, 0, None, None)]
+  #[case(DownloadStatus::Downloading, 0, Some(0), None)]
+  #[case(DownloadStatus::Downloading, 1023, Some(2048), Some(49))]
+  #[case(DownloadStatus::Paused, 4096, Some(2048), Some(100))]
+  #[case(DownloadStatus::Completed, 2048, None, Some(100))]
+  fn test_download_progress_event_percent(
+    #[case] status: DownloadStatus,
+    #[case] downloaded_bytes: u64,
+    #[case] total_bytes: Option<u64>,
+    #[case] percent: Option<u8>,
+  ) {
+    let download = Download {
+      status,
+      downloaded_bytes,
+      total_bytes,
+      ..Default::default()
+    };
+    assert_eq!(percent, DownloadProgressEvent::from(&download).percent);
+  }
+}
@@ -0,0 +1,212 @@
+use super::{
+  inference_monitor::{LastInference, WatchdogIncident},
+  RouterStateFn,
+};
+use axum::{
+  extract::State,
+  http::StatusCode,
+  response::{IntoResponse, Json, Response},
+  routing::get,
+  Router,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+pub fn health_router() -> Router<Arc<dyn RouterStateFn>> {
+  Router::new()
+    .route("/health", get(health_handler))
+    .route("/ready", get(ready_handler))
+}
+
+/// Liveness of the server, `degraded` if the watchdog failed to reload the last hung or failed
+/// context, the server then needs a restart
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthResponse {
+  pub status: String,
+  pub version: String,
+  pub uptime_secs: u64,
+  /// number of contexts reloaded by the watchdog since the start
+  pub watchdog_incidents: usize,
+  pub last_incident: Option<WatchdogIncident>,
+}
+
+/// Readiness of the server, `ready` once the startup self-check of the aliases has completed.
+/// The model is loaded on the first request, so a ready server can have no model loaded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReadyResponse {
+  pub ready: bool,
+  pub model_loaded: bool,
+  /// path of the loaded model file
+  pub model: Option<String>,
+  pub queue_depth: usize,
+  pub last_inference: Option<LastInference>,
+}
+
+async fn health_handler(State(state): State<Arc<dyn RouterStateFn>>) -> Json<HealthResponse> {
+  let monitor = state.inference_monitor();
+  let last_incident = monitor.last_incident();
+  let status = match &last_incident {
+    Some(incident) if !incident.recovered => "degraded",
+    _ => "ok",
+  };
+  Json(HealthResponse {
+    status: status.to_string(),
+    version: env!("CARGO_PKG_VERSION").to_string(),
+    uptime_secs: monitor.uptime_secs(),
+    watchdog_incidents: monitor.incidents(),
+    last_incident,
+  })
+}
+
+async fn ready_handler(State(state): State<Arc<dyn RouterStateFn>>) -> Response {
+  let monitor = state.inference_monitor();
+  let model = state.loaded_model().await;
+  let response = ReadyResponse {
+    ready: state.quarantine().status().completed,
+    model_loaded: model.is_some(),
+    model,
+    queue_depth: monitor.queue_depth(),
+    last_inference: monitor.last(),
+  };
+  let status = if response.ready {
+    StatusCode::OK
+  } else {
+    StatusCode::SERVICE_UNAVAILABLE
+  };
+  (status, Json(response)).into_response()
+}
+
+#[cfg(test)]
+mod test {
+  use super::{health_router, HealthResponse, ReadyResponse};
+  use crate::{
+    objs::RequestPriority,
+    server::{check_aliases, RouterState, WatchdogIncident},
+    service::{AppServiceFn, MockDataService, MockEnvServiceFn, MockHubService},
+    test_utils::{AppServiceStubMock, MockDbService, MockSharedContext, ResponseTestExt},
+  };
+  use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+  };
+  use chrono::Utc;
+  use llama_server_bindings::GptParamsBuilder;
+  use rstest::rstest;
+  use std::sync::Arc;
+  use tower::ServiceExt;
+
+  fn router_state(model: Option<&str>) -> anyhow::Result<RouterState> {
+    let gpt_params = match model {
+      Some(model) => Some(
+        GptParamsBuilder::default()
+          .model(model.to_string())
+          .build()?,
+      ),
+      None => None,
+    };
+    let mut ctx = MockSharedContext::new();
+    ctx
+      .expect_get_gpt_params()
+      .return_once(move || Ok(gpt_params));
+    let mut data_service = MockDataService::new();
+    data_service.expect_list_aliases().returning(|| Ok(vec![]));
+    let service: Arc<dyn AppServiceFn> = Arc::new(AppServiceStubMock::new(
+      MockEnvServiceFn::new(),
+      MockHubService::new(),
+      data_service,
+    ));
+    Ok(RouterState::new(
+      Arc::new(ctx),
+      service,
+      Arc::new(MockDbService::new()),
+    ))
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_health_routes_health() -> anyhow::Result<()> {
+    let response = health_router()
+      .with_state(Arc::new(router_state(None)?))
+      .oneshot(Request::get("/health").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response = response.json::<HealthResponse>().await?;
+    assert_eq!("ok", response.status);
+    assert_eq!(env!("CARGO_PKG_VERSION"), response.version);
+    assert_eq!(0, response.watchdog_incidents);
+    assert_eq!(None, response.last_incident);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_health_routes_health_degraded_after_failed_reload() -> anyhow::Result<()> {
+    let router_state = router_state(None)?;
+    router_state
+      .inference_monitor
+      .record_incident(WatchdogIncident {
+        at: Utc::now(),
+        reason: "the context failed: the inference worker exited".to_string(),
+        model: Some("/models/llama3.gguf".to_string()),
+        recovered: false,
+      });
+    let response = health_router()
+      .with_state(Arc::new(router_state))
+      .oneshot(Request::get("/health").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response = response.json::<HealthResponse>().await?;
+    assert_eq!("degraded", response.status);
+    assert_eq!(1, response.watchdog_incidents);
+    let last_incident = response
+      .last_incident
+      .expect("last incident should be reported");
+    assert_eq!(Some("/models/llama3.gguf".to_string()), last_incident.model);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_health_routes_ready_before_alias_check() -> anyhow::Result<()> {
+    let response = health_router()
+      .with_state(Arc::new(router_state(None)?))
+      .oneshot(Request::get("/ready").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::SERVICE_UNAVAILABLE, response.status());
+    let response = response.json::<ReadyResponse>().await?;
+    assert!(!response.ready);
+    assert!(!response.model_loaded);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_health_routes_ready_with_model_loaded() -> anyhow::Result<()> {
+    let router_state = router_state(Some("/models/llama3.gguf"))?;
+    check_aliases(
+      router_state.app_service.clone(),
+      router_state.quarantine.clone(),
+    )
+    .await;
+    router_state
+      .inference_monitor
+      .start("llama3:instruct", RequestPriority::Interactive, true)
+      .finish(None);
+    let response = health_router()
+      .with_state(Arc::new(router_state))
+      .oneshot(Request::get("/ready").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response = response.json::<ReadyResponse>().await?;
+    assert!(response.ready);
+    assert!(response.model_loaded);
+    assert_eq!(Some("/models/llama3.gguf".to_string()), response.model);
+    assert_eq!(0, response.queue_depth);
+    let last_inference = response
+      .last_inference
+      .expect("last inference should be reported");
+    assert_eq!("llama3:instruct", last_inference.alias);
+    assert_eq!(None, last_inference.error);
+    Ok(())
+  }
+}
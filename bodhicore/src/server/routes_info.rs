@@ -0,0 +1,57 @@
+use super::RouterStateFn;
+use crate::system_info::SystemInfo;
+use axum::{extract::State, response::Json, routing::get, Router};
+use std::sync::Arc;
+
+pub fn info_router() -> Router<Arc<dyn RouterStateFn>> {
+  Router::new().route("/info", get(ui_info_handler))
+}
+
+async fn ui_info_handler(State(state): State<Arc<dyn RouterStateFn>>) -> Json<SystemInfo> {
+  Json(SystemInfo::collect(
+    state.app_service().env_service().as_ref(),
+  ))
+}
+
+#[cfg(test)]
+mod test {
+  use super::info_router;
+  use crate::{
+    server::RouterState,
+    test_utils::{
+      app_service_stub, AppServiceTuple, MockDbService, MockSharedContext, ResponseTestExt,
+    },
+  };
+  use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+  };
+  use rstest::rstest;
+  use serde_json::Value;
+  use std::sync::Arc;
+  use tower::ServiceExt;
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_info_routes_reports_version_and_paths(
+    app_service_stub: AppServiceTuple,
+  ) -> anyhow::Result<()> {
+    let AppServiceTuple(_temp_bodhi_home, temp_hf_home, bodhi_home, _, service) = app_service_stub;
+    let router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(service),
+      Arc::new(MockDbService::new()),
+    );
+    let response = info_router()
+      .with_state(Arc::new(router_state))
+      .oneshot(Request::get("/info").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response = response.json::<Value>().await?;
+    assert_eq!(env!("CARGO_PKG_VERSION"), response["version"]);
+    assert_eq!(bodhi_home.display().to_string(), response["bodhi_home"]);
+    let hf_home = response["hf_home"].as_str().unwrap_or_default();
+    assert!(hf_home.starts_with(&temp_hf_home.path().display().to_string()));
+    Ok(())
+  }
+}
@@ -10,12 +10,14 @@ use std::{fs, sync::Arc, time::UNIX_EPOCH};
 pub(crate) async fn oai_models_handler(
   State(state): State<Arc<dyn RouterStateFn>>,
 ) -> Result<Json<ListModelResponse>, OpenAIApiError> {
+  let quarantine = state.quarantine();
   let models = state
     .app_service()
     .data_service()
     .list_aliases()
     .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))?
     .into_iter()
+    .filter(|alias| !quarantine.is_quarantined(&alias.alias))
     .map(|alias| to_oai_model(state.clone(), alias))
     .collect::<Vec<_>>();
   Ok(Json(ListModelResponse {
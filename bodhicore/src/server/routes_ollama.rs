@@ -0,0 +1,696 @@
+use super::RouterStateFn;
+use crate::{
+  oai::{ApiErrorResponse, OpenAIApiError},
+  objs::{Alias, ChatCompletionRequest, OAIRequestParams},
+  service::DataServiceError,
+};
+use async_openai::types::{
+  ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage,
+  ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs,
+  CreateChatCompletionRequestArgs, Stop,
+};
+use axum::{
+  body::Body,
+  extract::State,
+  http::{header, HeaderValue, StatusCode},
+  response::{IntoResponse, Response},
+  routing::{get, post},
+  Json, Router,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{convert::Infallible, fs, sync::Arc};
+use tokio::sync::mpsc::channel;
+use tokio_stream::wrappers::ReceiverStream;
+
+static APPLICATION_NDJSON: &str = "application/x-ndjson";
+
+/// Ollama compatible APIs, so clients that only speak Ollama can use the Bodhi model aliases
+pub fn ollama_router() -> Router<Arc<dyn RouterStateFn>> {
+  Router::new()
+    .route("/api/tags", get(ollama_tags_handler))
+    .route("/api/show", post(ollama_show_handler))
+    .route("/api/chat", post(ollama_chat_handler))
+    .route("/api/generate", post(ollama_generate_handler))
+}
+
+/// Ollama clients read the errors as `{"error": "<message>"}`, in place of the OpenAI envelope
+#[derive(Debug)]
+struct OllamaError(OpenAIApiError);
+
+impl From<OpenAIApiError> for OllamaError {
+  fn from(value: OpenAIApiError) -> Self {
+    OllamaError(value)
+  }
+}
+
+impl From<DataServiceError> for OllamaError {
+  fn from(value: DataServiceError) -> Self {
+    OllamaError(OpenAIApiError::from(value))
+  }
+}
+
+impl From<axum::http::Error> for OllamaError {
+  fn from(value: axum::http::Error) -> Self {
+    OllamaError(OpenAIApiError::from(value))
+  }
+}
+
+impl IntoResponse for OllamaError {
+  fn into_response(self) -> Response {
+    let status = StatusCode::from(&self.0);
+    (status, Json(json! {{"error": self.0.to_string()}})).into_response()
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OllamaMessage {
+  pub role: String,
+  pub content: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+pub struct OllamaOptions {
+  pub temperature: Option<f32>,
+  pub top_p: Option<f32>,
+  pub num_predict: Option<i32>,
+  pub seed: Option<i64>,
+  pub stop: Option<Vec<String>>,
+  pub frequency_penalty: Option<f32>,
+  pub presence_penalty: Option<f32>,
+  pub top_k: Option<i32>,
+  pub min_p: Option<f32>,
+  pub repeat_penalty: Option<f32>,
+  pub repeat_last_n: Option<i32>,
+  pub typical_p: Option<f32>,
+  pub mirostat: Option<u8>,
+  pub mirostat_tau: Option<f32>,
+  pub mirostat_eta: Option<f32>,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct OllamaChatRequest {
+  pub model: String,
+  pub messages: Vec<OllamaMessage>,
+  pub stream: Option<bool>,
+  pub options: Option<OllamaOptions>,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct OllamaGenerateRequest {
+  pub model: String,
+  #[serde(default)]
+  pub prompt: String,
+  pub system: Option<String>,
+  pub stream: Option<bool>,
+  pub options: Option<OllamaOptions>,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct OllamaShowRequest {
+  #[serde(alias = "name")]
+  pub model: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OllamaModelDetails {
+  pub format: String,
+  pub family: String,
+  pub families: Vec<String>,
+  pub parameter_size: String,
+  pub quantization_level: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OllamaModel {
+  pub name: String,
+  pub model: String,
+  pub modified_at: String,
+  pub size: u64,
+  pub digest: String,
+  pub details: OllamaModelDetails,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OllamaModelsResponse {
+  pub models: Vec<OllamaModel>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OllamaShowResponse {
+  pub modelfile: String,
+  pub parameters: String,
+  pub template: String,
+  pub details: OllamaModelDetails,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OllamaFormat {
+  Chat,
+  Generate,
+}
+
+impl OllamaFormat {
+  fn response(&self, model: &str, content: &str) -> Value {
+    let created_at = Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true);
+    match self {
+      OllamaFormat::Chat => json! {{
+        "model": model,
+        "created_at": created_at,
+        "message": {"role": "assistant", "content": content},
+        "done": false,
+      }},
+      OllamaFormat::Generate => json! {{
+        "model": model,
+        "created_at": created_at,
+        "response": content,
+        "done": false,
+      }},
+    }
+  }
+
+  /// final response with `done: true`, using the finish reason and token usage from the OpenAI response
+  fn done(&self, model: &str, content: &str, oai_response: &Value) -> Value {
+    let mut response = self.response(model, content);
+    response["done"] = json!(true);
+    response["done_reason"] = oai_response["choices"][0]["finish_reason"].clone();
+    if let Some(usage) = oai_response.get("usage").filter(|usage| usage.is_object()) {
+      response["prompt_eval_count"] = usage["prompt_tokens"].clone();
+      response["eval_count"] = usage["completion_tokens"].clone();
+    }
+    response
+  }
+}
+
+async fn ollama_tags_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+) -> Result<Json<OllamaModelsResponse>, OllamaError> {
+  let models = state
+    .app_service()
+    .data_service()
+    .list_aliases()?
+    .into_iter()
+    .map(|alias| to_ollama_model(state.clone(), alias))
+    .collect::<Vec<_>>();
+  Ok(Json(OllamaModelsResponse { models }))
+}
+
+async fn ollama_show_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  Json(request): Json<OllamaShowRequest>,
+) -> Result<Json<OllamaShowResponse>, OllamaError> {
+  let alias = find_alias(state.clone(), &request.model)?;
+  let parameters = match serde_json::to_value(&alias.request_params) {
+    Ok(Value::Object(params)) => params
+      .into_iter()
+      .flat_map(|(key, value)| match value {
+        Value::Array(values) => values
+          .into_iter()
+          .map(|value| format!("{key} {}", to_param(value)))
+          .collect::<Vec<_>>(),
+        value => vec![format!("{key} {}", to_param(value))],
+      })
+      .collect::<Vec<_>>()
+      .join("\n"),
+    _ => String::new(),
+  };
+  Ok(Json(OllamaShowResponse {
+    modelfile: format!(
+      "# Modelfile generated by bodhi for alias '{}'\nFROM {}/{}\n",
+      alias.alias, alias.repo, alias.filename
+    ),
+    parameters,
+    template: alias.chat_template.to_string(),
+    details: to_ollama_details(&alias),
+  }))
+}
+
+async fn ollama_chat_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  Json(request): Json<OllamaChatRequest>,
+) -> Result<Response, OllamaError> {
+  let messages = request
+    .messages
+    .into_iter()
+    .map(to_oai_message)
+    .collect::<Result<Vec<_>, _>>()?;
+  let stream = request.stream.unwrap_or(true);
+  let oai_request = to_oai_request(&request.model, messages, stream, request.options)?;
+  ollama_completion(state, oai_request, OllamaFormat::Chat).await
+}
+
+async fn ollama_generate_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  Json(request): Json<OllamaGenerateRequest>,
+) -> Result<Response, OllamaError> {
+  let mut messages = Vec::new();
+  if let Some(system) = request.system {
+    messages.push(to_oai_message(OllamaMessage {
+      role: "system".to_string(),
+      content: system,
+    })?);
+  }
+  messages.push(to_oai_message(OllamaMessage {
+    role: "user".to_string(),
+    content: request.prompt,
+  })?);
+  let stream = request.stream.unwrap_or(true);
+  let oai_request = to_oai_request(&request.model, messages, stream, request.options)?;
+  ollama_completion(state, oai_request, OllamaFormat::Generate).await
+}
+
+async fn ollama_completion(
+  state: Arc<dyn RouterStateFn>,
+  request: ChatCompletionRequest,
+  format: OllamaFormat,
+) -> Result<Response, OllamaError> {
+  let model = request.model.clone();
+  find_alias(state.clone(), &model)?;
+  let stream = request.stream.unwrap_or(false);
+  let (tx, mut rx) = channel::<String>(100);
+  let handle = tokio::spawn(async move { state.chat_completions(request, tx).await });
+  if !stream {
+    let Some(message) = rx.recv().await else {
+      return match handle.await {
+        Ok(Err(err)) => Err(err.into()),
+        _ => {
+          Err(OpenAIApiError::InternalServer("receiver stream abruptly closed".to_string()).into())
+        }
+      };
+    };
+    drop(rx);
+    _ = handle.await;
+    let response = serde_json::from_str::<Value>(&message)
+      .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))?;
+    let content = response["choices"][0]["message"]["content"]
+      .as_str()
+      .unwrap_or_default();
+    return Ok(Json(format.done(&model, content, &response)).into_response());
+  }
+  let stream = ReceiverStream::new(rx).map::<Result<String, Infallible>, _>(move |msg| {
+    let line = if let Some(error) = msg.strip_prefix("error: ") {
+      json! {{"error": ApiErrorResponse::from_event(error).error.message}}
+    } else {
+      let data = msg.strip_prefix("data: ").unwrap_or(&msg).trim_end();
+      match serde_json::from_str::<Value>(data) {
+        Ok(chunk) => {
+          let content = chunk["choices"][0]["delta"]["content"]
+            .as_str()
+            .unwrap_or_default();
+          if chunk["choices"][0]["finish_reason"].is_string() {
+            format.done(&model, content, &chunk)
+          } else {
+            format.response(&model, content)
+          }
+        }
+        Err(err) => {
+          tracing::error!(?err, msg, "unknown event type raised from bodhi_server");
+          json! {{"error": err.to_string()}}
+        }
+      }
+    };
+    Ok(format!("{line}\n"))
+  });
+  let response = Response::builder()
+    .status(StatusCode::OK)
+    .header(
+      header::CONTENT_TYPE,
+      HeaderValue::from_static(APPLICATION_NDJSON),
+    )
+    .body(Body::from_stream(stream))?;
+  Ok(response)
+}
+
+fn find_alias(state: Arc<dyn RouterStateFn>, model: &str) -> Result<Alias, OpenAIApiError> {
+  state
+    .app_service()
+    .data_service()
+    .find_alias(model)
+    .ok_or_else(|| OpenAIApiError::NotFound(format!("model '{model}' not found")))
+}
+
+fn to_oai_message(message: OllamaMessage) -> Result<ChatCompletionRequestMessage, OpenAIApiError> {
+  let OllamaMessage { role, content } = message;
+  let message = match role.as_str() {
+    "system" => ChatCompletionRequestSystemMessageArgs::default()
+      .content(content)
+      .build()
+      .map(ChatCompletionRequestMessage::System),
+    "user" => ChatCompletionRequestUserMessageArgs::default()
+      .content(content)
+      .build()
+      .map(ChatCompletionRequestMessage::User),
+    "assistant" => ChatCompletionRequestAssistantMessageArgs::default()
+      .content(content)
+      .build()
+      .map(ChatCompletionRequestMessage::Assistant),
+    role => {
+      return Err(OpenAIApiError::BadRequest(format!(
+        "unsupported message role '{role}'"
+      )))
+    }
+  };
+  message.map_err(|err| OpenAIApiError::BadRequest(err.to_string()))
+}
+
+fn to_oai_request(
+  model: &str,
+  messages: Vec<ChatCompletionRequestMessage>,
+  stream: bool,
+  options: Option<OllamaOptions>,
+) -> Result<ChatCompletionRequest, OpenAIApiError> {
+  let options = options.unwrap_or_default();
+  let mut builder = CreateChatCompletionRequestArgs::default();
+  builder.model(model).messages(messages).stream(stream);
+  if let Some(temperature) = options.temperature {
+    builder.temperature(temperature);
+  }
+  if let Some(top_p) = options.top_p {
+    builder.top_p(top_p);
+  }
+  if let Some(num_predict) = options.num_predict.and_then(|n| u16::try_from(n).ok()) {
+    builder.max_tokens(num_predict);
+  }
+  if let Some(seed) = options.seed {
+    builder.seed(seed);
+  }
+  if let Some(stop) = options.stop.filter(|stop| !stop.is_empty()) {
+    builder.stop(Stop::StringArray(stop));
+  }
+  if let Some(frequency_penalty) = options.frequency_penalty {
+    builder.frequency_penalty(frequency_penalty);
+  }
+  if let Some(presence_penalty) = options.presence_penalty {
+    builder.presence_penalty(presence_penalty);
+  }
+  let request = builder
+    .build()
+    .map_err(|err| OpenAIApiError::BadRequest(err.to_string()))?;
+  // the llama.cpp samplers are passed as the request extensions, same as for the OpenAI API
+  let samplers = OAIRequestParams {
+    top_k: options.top_k,
+    min_p: options.min_p,
+    repeat_penalty: options.repeat_penalty,
+    repeat_last_n: options.repeat_last_n,
+    typical_p: options.typical_p,
+    mirostat: options.mirostat,
+    mirostat_tau: options.mirostat_tau,
+    mirostat_eta: options.mirostat_eta,
+    ..Default::default()
+  };
+  let mut request = ChatCompletionRequest::from(request);
+  samplers.update(&mut request);
+  Ok(request)
+}
+
+fn to_ollama_model(state: Arc<dyn RouterStateFn>, alias: Alias) -> OllamaModel {
+  let hub_file = state
+    .app_service()
+    .hub_service()
+    .find_local_file(&alias.repo, &alias.filename, &alias.snapshot)
+    .ok()
+    .flatten();
+  let modified_at = hub_file
+    .as_ref()
+    .and_then(|hub_file| fs::metadata(hub_file.path()).ok())
+    .and_then(|metadata| metadata.modified().ok())
+    .map(DateTime::<Utc>::from)
+    .unwrap_or_default()
+    .to_rfc3339_opts(SecondsFormat::Micros, true);
+  OllamaModel {
+    name: alias.alias.clone(),
+    model: alias.alias.clone(),
+    modified_at,
+    size: hub_file
+      .as_ref()
+      .and_then(|hub_file| hub_file.size)
+      .unwrap_or_default(),
+    digest: hub_file
+      .map(|hub_file| hub_file.snapshot)
+      .unwrap_or_else(|| alias.snapshot.clone()),
+    details: to_ollama_details(&alias),
+  }
+}
+
+fn to_ollama_details(alias: &Alias) -> OllamaModelDetails {
+  let family = alias.family.clone().unwrap_or_default();
+  let quantization_level = alias
+    .filename
+    .trim_end_matches(".gguf")
+    .rsplit_once('.')
+    .map(|(_, quant)| quant.to_string())
+    .unwrap_or_default();
+  OllamaModelDetails {
+    format: "gguf".to_string(),
+    families: if family.is_empty() {
+      vec![]
+    } else {
+      vec![family.clone()]
+    },
+    family,
+    parameter_size: String::new(),
+    quantization_level,
+  }
+}
+
+fn to_param(value: Value) -> String {
+  match value {
+    Value::String(value) => format!("{value:?}"),
+    value => value.to_string(),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::ollama_router;
+  use crate::{
+    objs::{Alias, HubFile},
+    service::{MockDataService, MockEnvServiceFn, MockHubService},
+    test_utils::{AppServiceStubMock, MockRouterState, RequestTestExt, ResponseTestExt},
+  };
+  use axum::{
+    body::Body,
+    http::{header::CONTENT_TYPE, Request, StatusCode},
+    Router,
+  };
+  use mockall::predicate::always;
+  use rstest::rstest;
+  use serde_json::{json, Value};
+  use std::sync::Arc;
+  use tokio::sync::mpsc::Sender;
+  use tower::ServiceExt;
+
+  fn router_state(hub_service: MockHubService) -> MockRouterState {
+    let mut data_service = MockDataService::new();
+    data_service
+      .expect_find_alias()
+      .returning(|alias| (alias == "testalias:instruct").then(Alias::testalias));
+    data_service
+      .expect_list_aliases()
+      .returning(|| Ok(vec![Alias::testalias()]));
+    let app_service = Arc::new(AppServiceStubMock::new(
+      MockEnvServiceFn::new(),
+      hub_service,
+      data_service,
+    ));
+    let mut router_state = MockRouterState::new();
+    router_state
+      .expect_app_service()
+      .returning(move || app_service.clone());
+    router_state
+  }
+
+  fn app(router_state: MockRouterState) -> Router {
+    ollama_router().with_state(Arc::new(router_state))
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_ollama_routes_tags() -> anyhow::Result<()> {
+    let mut hub_service = MockHubService::new();
+    hub_service
+      .expect_find_local_file()
+      .returning(|_, _, _| Ok(Some(HubFile::testalias())));
+    let response = app(router_state(hub_service))
+      .oneshot(Request::get("/api/tags").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response = response.json::<Value>().await?;
+    let model = &response["models"][0];
+    assert_eq!("testalias:instruct", model["name"]);
+    assert_eq!(22, model["size"]);
+    assert_eq!(
+      json! {{
+        "format": "gguf",
+        "family": "testalias",
+        "families": ["testalias"],
+        "parameter_size": "",
+        "quantization_level": "Q8_0",
+      }},
+      model["details"]
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_ollama_routes_show() -> anyhow::Result<()> {
+    let response = app(router_state(MockHubService::new()))
+      .oneshot(Request::post("/api/show").json(json! {{"name": "testalias:instruct"}})?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response = response.json::<Value>().await?;
+    assert_eq!("llama3", response["template"]);
+    assert!(response["modelfile"]
+      .as_str()
+      .unwrap()
+      .contains("FROM MyFactory/testalias-gguf/testalias.Q8_0.gguf"));
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("/api/chat", json! {{"model": "unknown:model", "messages": []}})]
+  #[case("/api/generate", json! {{"model": "unknown:model", "prompt": "hi"}})]
+  #[case("/api/show", json! {{"model": "unknown:model"}})]
+  #[tokio::test]
+  async fn test_ollama_routes_model_not_found(
+    #[case] path: &str,
+    #[case] request: Value,
+  ) -> anyhow::Result<()> {
+    let response = app(router_state(MockHubService::new()))
+      .oneshot(Request::post(path).json(request)?)
+      .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+    assert_eq!(
+      json! {{"error": "model 'unknown:model' not found"}},
+      response.json::<Value>().await?
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_ollama_routes_chat_non_stream() -> anyhow::Result<()> {
+    let mut router_state = router_state(MockHubService::new());
+    router_state
+      .expect_chat_completions()
+      .withf(|request, _| {
+        request.model == "testalias:instruct"
+          && request.stream == Some(false)
+          && request.temperature == Some(0.5)
+          && request.max_tokens == Some(64)
+          && request.extensions.get("top_k") == Some(&json!(40))
+      })
+      .return_once(|_, sender: Sender<String>| {
+        let response = json! {{
+          "id": "testid",
+          "model": "testalias:instruct",
+          "choices": [{
+            "index": 0,
+            "message": {"role": "assistant", "content": "Tuesday."},
+            "finish_reason": "stop",
+          }],
+          "created": 1704067200,
+          "object": "chat.completion",
+          "usage": {"prompt_tokens": 15, "completion_tokens": 3, "total_tokens": 18},
+        }}
+        .to_string();
+        tokio::spawn(async move { sender.send(response).await });
+        Ok(())
+      });
+    let request = json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+      "stream": false,
+      "options": {"temperature": 0.5, "num_predict": 64, "top_k": 40},
+    }};
+    let response = app(router_state)
+      .oneshot(Request::post("/api/chat").json(request)?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response = response.json::<Value>().await?;
+    assert_eq!(
+      json! {{"role": "assistant", "content": "Tuesday."}},
+      response["message"]
+    );
+    assert_eq!(json!(true), response["done"]);
+    assert_eq!("stop", response["done_reason"]);
+    assert_eq!(15, response["prompt_eval_count"]);
+    assert_eq!(3, response["eval_count"]);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_ollama_routes_generate_stream() -> anyhow::Result<()> {
+    let mut router_state = router_state(MockHubService::new());
+    router_state
+      .expect_chat_completions()
+      .with(always(), always())
+      .return_once(|_, sender: Sender<String>| {
+        tokio::spawn(async move {
+          for content in ["Tues", "day"] {
+            let chunk = json! {{
+              "id": "testid",
+              "model": "testalias:instruct",
+              "choices": [{"index": 0, "delta": {"role": "assistant", "content": content}}],
+              "created": 1704067200,
+              "object": "chat.completion.chunk",
+            }};
+            _ = sender.send(format!("data: {chunk}\n\n")).await;
+          }
+          let end = json! {{
+            "id": "testid",
+            "model": "testalias:instruct",
+            "choices": [{"index": 0, "delta": {}, "finish_reason": "stop"}],
+            "created": 1704067200,
+            "object": "chat.completion.chunk",
+            "usage": {"prompt_tokens": 15, "completion_tokens": 2, "total_tokens": 17},
+          }};
+          _ = sender.send(format!("data: {end}\n\n")).await;
+        });
+        Ok(())
+      });
+    let request = json! {{"model": "testalias:instruct", "prompt": "What day comes after Monday?"}};
+    let response = app(router_state)
+      .oneshot(Request::post("/api/generate").json(request)?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(
+      "application/x-ndjson",
+      response.headers()[CONTENT_TYPE].to_str()?
+    );
+    let lines = response
+      .text()
+      .await?
+      .lines()
+      .map(serde_json::from_str::<Value>)
+      .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(3, lines.len());
+    let content = lines
+      .iter()
+      .map(|line| line["response"].as_str().unwrap())
+      .collect::<String>();
+    assert_eq!("Tuesday", content);
+    assert_eq!(json!(false), lines[0]["done"]);
+    assert_eq!(json!(true), lines[2]["done"]);
+    assert_eq!(2, lines[2]["eval_count"]);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_ollama_routes_chat_invalid_role() -> anyhow::Result<()> {
+    let request = json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "tool", "content": "{}"}],
+    }};
+    let response = app(router_state(MockHubService::new()))
+      .oneshot(Request::post("/api/chat").json(request)?)
+      .await?;
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    Ok(())
+  }
+}
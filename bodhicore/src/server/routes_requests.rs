@@ -0,0 +1,174 @@
+use super::{generations::QueuedGeneration, RouterStateFn};
+use crate::{
+  db::objs::{ApiKey, ApiKeyRole},
+  oai::OpenAIApiError,
+};
+use axum::{
+  extract::{Path, State},
+  response::Json,
+  routing::{get, post},
+  Extension, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+pub fn requests_router() -> Router<Arc<dyn RouterStateFn>> {
+  Router::new()
+    .route("/queue", get(ui_queue_handler))
+    .route("/requests/:id/cancel", post(cancel_request_handler))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueueResponse {
+  pub generations: Vec<QueuedGeneration>,
+}
+
+/// Lists the chat completions in flight, the oldest first, to find the ones to cancel
+async fn ui_queue_handler(State(state): State<Arc<dyn RouterStateFn>>) -> Json<QueueResponse> {
+  Json(QueueResponse {
+    generations: state.generations().list(),
+  })
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CancelResponse {
+  pub id: String,
+  pub cancelled: bool,
+}
+
+/// Aborts the in-flight chat completion with the generation id, the `x-request-id` of its request.
+/// With `BODHI_AUTH` enabled, only the generations of the API key's name are cancelled, the admin
+/// role cancels any generation. The generations of others are not found.
+pub(crate) async fn cancel_request_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  api_key: Option<Extension<ApiKey>>,
+  Path(id): Path<String>,
+) -> Result<Json<CancelResponse>, OpenAIApiError> {
+  let owner = match api_key {
+    Some(Extension(api_key)) if api_key.role != ApiKeyRole::Admin => Some(api_key.name),
+    _ => None,
+  };
+  if !state.generations().cancel(&id, owner.as_deref()) {
+    return Err(OpenAIApiError::RequestNotFound(id));
+  }
+  tracing::info!(generation_id = %id, "cancelling the chat completion on request");
+  Ok(Json(CancelResponse {
+    id,
+    cancelled: true,
+  }))
+}
+
+#[cfg(test)]
+mod test {
+  use super::{requests_router, CancelResponse, QueueResponse};
+  use crate::{
+    db::objs::{ApiKey, ApiKeyRole},
+    server::{GenerationStatus, Generations},
+    test_utils::{MockRouterState, ResponseTestExt},
+  };
+  use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Extension,
+  };
+  use rstest::rstest;
+  use serde_json::Value;
+  use std::{sync::Arc, time::Duration};
+  use tower::ServiceExt;
+
+  fn router_state(generations: Arc<Generations>) -> MockRouterState {
+    let mut router_state = MockRouterState::new();
+    router_state
+      .expect_generations()
+      .returning(move || generations.clone());
+    router_state
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_requests_router_cancels_in_flight_generation() -> anyhow::Result<()> {
+    let generations = Arc::new(Generations::default());
+    let guard = generations
+      .start("req-1", "llama3:instruct", None)
+      .expect("generation should start");
+    let response = requests_router()
+      .with_state(Arc::new(router_state(generations)))
+      .oneshot(Request::post("/requests/req-1/cancel").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(
+      CancelResponse {
+        id: "req-1".to_string(),
+        cancelled: true
+      },
+      response.json::<CancelResponse>().await?
+    );
+    tokio::time::timeout(Duration::from_secs(1), guard.cancelled()).await?;
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("bob", ApiKeyRole::User, StatusCode::NOT_FOUND)]
+  #[case("alice", ApiKeyRole::User, StatusCode::OK)]
+  #[case("ops", ApiKeyRole::Admin, StatusCode::OK)]
+  #[tokio::test]
+  async fn test_requests_router_cancels_only_generation_of_owner(
+    #[case] name: &str,
+    #[case] role: ApiKeyRole,
+    #[case] status: StatusCode,
+  ) -> anyhow::Result<()> {
+    let generations = Arc::new(Generations::default());
+    let _guard = generations
+      .start("req-1", "llama3:instruct", Some("alice".to_string()))
+      .expect("generation should start");
+    // the auth middleware adds the API key of the request
+    let api_key = ApiKey {
+      name: name.to_string(),
+      role,
+      ..Default::default()
+    };
+    let response = requests_router()
+      .layer(Extension(api_key))
+      .with_state(Arc::new(router_state(generations)))
+      .oneshot(Request::post("/requests/req-1/cancel").body(Body::empty())?)
+      .await?;
+    assert_eq!(status, response.status());
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_requests_router_lists_queue() -> anyhow::Result<()> {
+    let generations = Arc::new(Generations::default());
+    let _guard = generations
+      .start("req-1", "llama3:instruct", None)
+      .expect("generation should start");
+    let response = requests_router()
+      .with_state(Arc::new(router_state(generations)))
+      .oneshot(Request::get("/queue").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response = response.json::<QueueResponse>().await?;
+    assert_eq!(1, response.generations.len());
+    let generation = &response.generations[0];
+    assert_eq!("req-1", generation.id);
+    assert_eq!("llama3:instruct", generation.alias);
+    assert_eq!(GenerationStatus::Queued, generation.status);
+    assert_eq!(0, generation.tokens);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_requests_router_cancel_unknown_id_not_found() -> anyhow::Result<()> {
+    let generations = Arc::new(Generations::default());
+    let response = requests_router()
+      .with_state(Arc::new(router_state(generations)))
+      .oneshot(Request::post("/requests/unknown/cancel").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+    let error = response.json::<Value>().await?;
+    assert_eq!("request_not_found", error["error"]["code"]);
+    Ok(())
+  }
+}
@@ -0,0 +1,98 @@
+use super::RouterStateFn;
+use crate::{
+  oai::OpenAIApiError,
+  objs::{RerankRequest, RerankResponse},
+};
+use axum::{extract::State, Json};
+use std::sync::Arc;
+
+pub(crate) async fn rerank_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  Json(request): Json<RerankRequest>,
+) -> Result<Json<RerankResponse>, OpenAIApiError> {
+  let response = state.rerank(request).await?;
+  Ok(Json(response))
+}
+
+#[cfg(test)]
+mod test {
+  use super::rerank_handler;
+  use crate::{
+    oai::OpenAIApiError,
+    objs::{RerankResponse, RerankResult, RerankUsage},
+    test_utils::{MockRouterState, RequestTestExt, ResponseTestExt},
+  };
+  use axum::{
+    http::{Request, StatusCode},
+    routing::post,
+    Router,
+  };
+  use rstest::rstest;
+  use serde_json::{json, Value};
+  use std::sync::Arc;
+  use tower::ServiceExt;
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_rerank_handler_returns_scores() -> anyhow::Result<()> {
+    let mut router_state = MockRouterState::new();
+    router_state
+      .expect_rerank()
+      .withf(|request| request.query == "What is the capital of France?")
+      .return_once(|request| {
+        Ok(RerankResponse {
+          id: "rerank-test".to_string(),
+          model: request.model,
+          results: vec![RerankResult {
+            index: 1,
+            relevance_score: 4.0,
+            document: None,
+          }],
+          usage: RerankUsage { total_tokens: 24 },
+        })
+      });
+    let response = Router::new()
+      .route("/v1/rerank", post(rerank_handler))
+      .with_state(Arc::new(router_state))
+      .oneshot(Request::post("/v1/rerank").json(json! {{
+        "model": "bge-reranker:v2",
+        "query": "What is the capital of France?",
+        "documents": ["Berlin is the capital of Germany", {"text": "Paris is the capital of France"}],
+        "top_n": 1
+      }})?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(
+      json! {{
+        "id": "rerank-test",
+        "model": "bge-reranker:v2",
+        "results": [{"index": 1, "relevance_score": 4.0}],
+        "usage": {"total_tokens": 24}
+      }},
+      response.json::<Value>().await?
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_rerank_handler_model_not_found() -> anyhow::Result<()> {
+    let mut router_state = MockRouterState::new();
+    router_state
+      .expect_rerank()
+      .return_once(|request| Err(OpenAIApiError::ModelNotFound(request.model)));
+    let response = Router::new()
+      .route("/v1/rerank", post(rerank_handler))
+      .with_state(Arc::new(router_state))
+      .oneshot(Request::post("/v1/rerank").json(json! {{
+        "model": "not-found",
+        "query": "What is the capital of France?",
+        "documents": ["Paris is the capital of France"]
+      }})?)
+      .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+    let response = response.json::<Value>().await?;
+    assert_eq!(json! {"model_not_found"}, response["error"]["code"]);
+    Ok(())
+  }
+}
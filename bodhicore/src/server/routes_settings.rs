@@ -0,0 +1,209 @@
+use super::{routes_audit::record_audit, RouterStateFn};
+use crate::{
+  db::objs::{ApiKey, AuditAction},
+  oai::OpenAIApiError,
+  service::SettingInfo,
+};
+use axum::{extract::State, response::Json, routing::get, Extension, Router};
+use serde_json::Value;
+use std::{collections::HashMap, sync::Arc};
+
+pub fn settings_router() -> Router<Arc<dyn RouterStateFn>> {
+  Router::new().route(
+    "/settings",
+    get(ui_settings_handler).put(ui_settings_update_handler),
+  )
+}
+
+async fn ui_settings_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+) -> Result<Json<Vec<SettingInfo>>, OpenAIApiError> {
+  let settings = state.app_service().env_service().list_settings();
+  Ok(Json(settings))
+}
+
+/// Updates the settings, the changed settings are recorded in the audit log
+async fn ui_settings_update_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  api_key: Option<Extension<ApiKey>>,
+  Json(values): Json<HashMap<String, Value>>,
+) -> Result<Json<Vec<SettingInfo>>, OpenAIApiError> {
+  let values = values
+    .into_iter()
+    .map(|(key, value)| match value {
+      Value::String(value) => (key, value),
+      value => (key, value.to_string()),
+    })
+    .collect::<HashMap<_, _>>();
+  let env_service = state.app_service().env_service();
+  let before = env_service.list_settings();
+  let settings = env_service.update_settings(&values)?;
+  let db_service = state.db_service();
+  for setting in settings
+    .iter()
+    .filter(|setting| values.contains_key(&setting.key))
+  {
+    let previous = before
+      .iter()
+      .find(|previous| previous.key == setting.key)
+      .map(|previous| previous.value.clone());
+    if previous.as_ref() == Some(&setting.value) {
+      continue;
+    }
+    record_audit(
+      db_service.as_ref(),
+      api_key.as_ref().map(|Extension(api_key)| api_key),
+      AuditAction::SettingUpdate,
+      &setting.key,
+      previous,
+      Some(setting.value.clone()),
+    )
+    .await;
+  }
+  Ok(Json(settings))
+}
+
+#[cfg(test)]
+mod test {
+  use super::settings_router;
+  use crate::{
+    db::objs::AuditAction,
+    service::{
+      DataServiceError, MockDataService, MockEnvServiceFn, MockHubService, SettingInfo,
+      SettingSource, BODHI_PORT,
+    },
+    test_utils::{
+      AppServiceStubMock, MockDbService, MockRouterState, RequestTestExt, ResponseTestExt,
+    },
+  };
+  use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+  };
+  use mockall::predicate::eq;
+  use rstest::rstest;
+  use serde_json::{json, Value};
+  use std::{collections::HashMap, sync::Arc};
+  use tower::ServiceExt;
+
+  fn port_setting(value: &str, source: SettingSource) -> SettingInfo {
+    SettingInfo {
+      key: BODHI_PORT.to_string(),
+      value: value.to_string(),
+      default_value: "1135".to_string(),
+      source,
+      editable: true,
+      requires_restart: true,
+    }
+  }
+
+  fn router_state(env_service: MockEnvServiceFn, db_service: MockDbService) -> MockRouterState {
+    let app_service = Arc::new(AppServiceStubMock::new(
+      env_service,
+      MockHubService::new(),
+      MockDataService::new(),
+    ));
+    let db_service = Arc::new(db_service);
+    let mut router_state = MockRouterState::new();
+    router_state
+      .expect_app_service()
+      .returning(move || app_service.clone());
+    router_state
+      .expect_db_service()
+      .returning(move || db_service.clone());
+    router_state
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_settings_routes_list() -> anyhow::Result<()> {
+    let mut env_service = MockEnvServiceFn::new();
+    env_service
+      .expect_list_settings()
+      .return_once(|| vec![port_setting("1135", SettingSource::Default)]);
+    let router =
+      settings_router().with_state(Arc::new(router_state(env_service, MockDbService::new())));
+    let response = router
+      .oneshot(Request::get("/settings").body(Body::empty()).unwrap())
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response = response.json::<Value>().await?;
+    let expected = json! {[{
+      "key": "BODHI_PORT",
+      "value": "1135",
+      "default_value": "1135",
+      "source": "default",
+      "editable": true,
+      "requires_restart": true,
+    }]};
+    assert_eq!(expected, response);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_settings_routes_update() -> anyhow::Result<()> {
+    let mut env_service = MockEnvServiceFn::new();
+    env_service
+      .expect_list_settings()
+      .return_once(|| vec![port_setting("1135", SettingSource::Default)]);
+    env_service
+      .expect_update_settings()
+      .with(eq(HashMap::from([(
+        BODHI_PORT.to_string(),
+        "8080".to_string(),
+      )])))
+      .return_once(|_| Ok(vec![port_setting("8080", SettingSource::SettingsFile)]));
+    let mut db_service = MockDbService::new();
+    db_service
+      .expect_save_audit_entry()
+      .withf(|entry| {
+        entry.action == AuditAction::SettingUpdate
+          && entry.target == BODHI_PORT
+          && entry.actor.is_none()
+          && entry.before.as_deref() == Some("1135")
+          && entry.after.as_deref() == Some("8080")
+      })
+      .times(1)
+      .returning(|_| Ok(()));
+    let router = settings_router().with_state(Arc::new(router_state(env_service, db_service)));
+    let response = router
+      .oneshot(Request::put("/settings").json(json! {{"BODHI_PORT": 8080}})?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response = response.json::<Vec<SettingInfo>>().await?;
+    assert_eq!(
+      vec![port_setting("8080", SettingSource::SettingsFile)],
+      response
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_settings_routes_update_invalid() -> anyhow::Result<()> {
+    let mut env_service = MockEnvServiceFn::new();
+    env_service
+      .expect_list_settings()
+      .return_once(|| vec![port_setting("1135", SettingSource::Default)]);
+    env_service.expect_update_settings().return_once(|_| {
+      Err(DataServiceError::SettingInvalid {
+        key: BODHI_PORT.to_string(),
+        value: "abc".to_string(),
+        reason: "port should be a number between 1 and 65535".to_string(),
+      })
+    });
+    let router =
+      settings_router().with_state(Arc::new(router_state(env_service, MockDbService::new())));
+    let response = router
+      .oneshot(Request::put("/settings").json(json! {{"BODHI_PORT": "abc"}})?)
+      .await?;
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    let response = response.json::<Value>().await?;
+    assert_eq!(
+      json! {"invalid value 'abc' for setting 'BODHI_PORT': port should be a number between 1 and 65535"},
+      response["error"]["message"]
+    );
+    Ok(())
+  }
+}
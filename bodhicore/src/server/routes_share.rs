@@ -0,0 +1,101 @@
+use super::{html::conversation_html, RouterStateFn};
+use crate::{db::DbError, oai::OpenAIApiError};
+use axum::{
+  extract::{Path as UrlPath, State},
+  http::StatusCode,
+  response::{Html, IntoResponse, Response},
+  routing::get,
+  Router,
+};
+use std::sync::Arc;
+
+/// Read-only conversation pages for the links created using `POST /api/ui/chats/:id/share`
+pub fn share_router() -> Router<Arc<dyn RouterStateFn>> {
+  Router::new().route("/share/:token", get(share_handler))
+}
+
+async fn share_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  UrlPath(token): UrlPath<String>,
+) -> Result<Response, OpenAIApiError> {
+  match state.db_service().get_shared_conversation(&token).await {
+    Ok(conversation) => Ok(Html(conversation_html(&conversation)).into_response()),
+    Err(DbError::Sqlx {
+      source: sqlx::Error::RowNotFound,
+      ..
+    }) => Ok(
+      (
+        StatusCode::NOT_FOUND,
+        Html("<!DOCTYPE html><html><body><p>This share link has expired or does not exist.</p></body></html>"),
+      )
+        .into_response(),
+    ),
+    Err(err) => Err(err.into()),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::share_router;
+  use crate::{
+    db::{objs::ConversationBuilder, DbService, DbServiceFn},
+    server::RouterState,
+    service::MockAppServiceFn,
+    test_utils::{db_service, MockSharedContext, ResponseTestExt},
+  };
+  use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+  };
+  use chrono::{DateTime, Duration, Utc};
+  use rstest::rstest;
+  use std::sync::Arc;
+  use tempfile::TempDir;
+  use tower::ServiceExt;
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_share_routes_renders_conversation(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let mut convo = ConversationBuilder::default().title("test title").build()?;
+    db_service.save_conversation(&mut convo).await?;
+    let share_link = db_service
+      .create_share_link(&convo.id, Duration::hours(1))
+      .await?;
+    let router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(MockAppServiceFn::new()),
+      Arc::new(db_service),
+    );
+    let response = share_router()
+      .with_state(Arc::new(router_state))
+      .oneshot(Request::get(format!("/share/{}", share_link.token)).body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    assert!(response.text().await?.contains("<h1>test title</h1>"));
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_share_routes_unknown_token(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(MockAppServiceFn::new()),
+      Arc::new(db_service),
+    );
+    let response = share_router()
+      .with_state(Arc::new(router_state))
+      .oneshot(Request::get("/share/unknown").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+    Ok(())
+  }
+}
@@ -0,0 +1,47 @@
+use axum::Router;
+use std::path::Path;
+use tower_http::services::{ServeDir, ServeFile};
+
+/// Serves the web UI from the directory, in place of the UI built into the app.
+/// Paths not matching a file get the `index.html`, so the client side routes of the UI work on reload.
+pub fn static_dir_router(dir: &Path) -> Router {
+  let static_service = ServeDir::new(dir)
+    .append_index_html_on_directories(true)
+    .fallback(ServeFile::new(dir.join("index.html")));
+  Router::new().fallback_service(static_service)
+}
+
+#[cfg(test)]
+mod test {
+  use super::static_dir_router;
+  use crate::test_utils::ResponseTestExt;
+  use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+  };
+  use rstest::rstest;
+  use std::fs;
+  use tempfile::TempDir;
+  use tower::ServiceExt;
+
+  #[rstest]
+  #[case("/", "<html>home</html>")]
+  #[case("/index.html", "<html>home</html>")]
+  #[case("/app.js", "console.log('bodhi');")]
+  #[case("/chat/1234", "<html>home</html>")]
+  #[tokio::test]
+  async fn test_static_dir_router_serves_files_with_spa_fallback(
+    #[case] path: &str,
+    #[case] expected: &str,
+  ) -> anyhow::Result<()> {
+    let ui_dir = TempDir::new()?;
+    fs::write(ui_dir.path().join("index.html"), "<html>home</html>")?;
+    fs::write(ui_dir.path().join("app.js"), "console.log('bodhi');")?;
+    let response = static_dir_router(ui_dir.path())
+      .oneshot(Request::get(path).body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(expected, response.text().await?);
+    Ok(())
+  }
+}
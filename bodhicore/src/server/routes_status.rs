@@ -0,0 +1,78 @@
+use super::{alias_check::AliasCheckStatus, RouterStateFn};
+use axum::{extract::State, response::Json, routing::get, Router};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+pub fn status_router() -> Router<Arc<dyn RouterStateFn>> {
+  Router::new().route("/status", get(ui_status_handler))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatusResponse {
+  pub version: String,
+  pub alias_check: AliasCheckStatus,
+}
+
+async fn ui_status_handler(State(state): State<Arc<dyn RouterStateFn>>) -> Json<StatusResponse> {
+  Json(StatusResponse {
+    version: env!("CARGO_PKG_VERSION").to_string(),
+    alias_check: state.quarantine().status(),
+  })
+}
+
+#[cfg(test)]
+mod test {
+  use super::status_router;
+  use crate::{
+    server::{check_aliases, RouterState},
+    service::AppServiceFn,
+    test_utils::{
+      app_service_stub, AppServiceTuple, MockDbService, MockSharedContext, ResponseTestExt,
+    },
+  };
+  use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+  };
+  use rstest::rstest;
+  use serde_json::{json, Value};
+  use std::sync::Arc;
+  use tower::ServiceExt;
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_status_routes_lists_quarantined_aliases(
+    app_service_stub: AppServiceTuple,
+  ) -> anyhow::Result<()> {
+    let AppServiceTuple(_temp_bodhi_home, _temp_hf_home, _, _, service) = app_service_stub;
+    let service: Arc<dyn AppServiceFn> = Arc::new(service);
+    let router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      service.clone(),
+      Arc::new(MockDbService::new()),
+    );
+    check_aliases(service, router_state.quarantine.clone()).await;
+    let response = status_router()
+      .with_state(Arc::new(router_state))
+      .oneshot(Request::get("/status").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response = response.json::<Value>().await?;
+    assert_eq!(json! {true}, response["alias_check"]["completed"]);
+    let quarantined = response["alias_check"]["quarantined"]
+      .as_array()
+      .expect("quarantined should be an array")
+      .iter()
+      .filter_map(|quarantined| quarantined["alias"].as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(
+      vec![
+        "llama3:instruct",
+        "testalias-exists:instruct",
+        "tinyllama:instruct"
+      ],
+      quarantined
+    );
+    Ok(())
+  }
+}
@@ -0,0 +1,203 @@
+use super::RouterStateFn;
+use crate::{
+  gguf::{GGUFMetadata, Vocab},
+  oai::OpenAIApiError,
+};
+use axum::{extract::State, response::Json, routing::post, Router};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+  collections::HashMap,
+  path::{Path, PathBuf},
+  sync::{Arc, Mutex},
+};
+
+// the vocabs by model file path, the files in a snapshot do not change so the vocab is never stale
+static VOCABS: Lazy<Mutex<HashMap<PathBuf, Arc<Vocab>>>> = Lazy::new(Default::default);
+
+pub fn tokens_router() -> Router<Arc<dyn RouterStateFn>> {
+  Router::new().route("/detokenize", post(ui_detokenize_handler))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DetokenizeRequest {
+  pub model: String,
+  pub tokens: Vec<u32>,
+  #[serde(default)]
+  pub special: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DetokenizeResponse {
+  pub content: String,
+}
+
+async fn ui_detokenize_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  Json(request): Json<DetokenizeRequest>,
+) -> Result<Json<DetokenizeResponse>, OpenAIApiError> {
+  let app_service = state.app_service();
+  let alias = app_service
+    .data_service()
+    .find_alias(&request.model)
+    .ok_or_else(|| {
+      OpenAIApiError::NotFound(format!("model alias '{}' not found", request.model))
+    })?;
+  let local_file = app_service
+    .hub_service()
+    .find_local_file(&alias.repo, &alias.filename, &alias.snapshot)
+    .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))?
+    .ok_or_else(|| {
+      OpenAIApiError::NotFound(format!(
+        "model file '{}' for alias '{}' not found in huggingface cache",
+        alias.filename, alias.alias
+      ))
+    })?;
+  let content = tokio::task::spawn_blocking(move || -> Result<String, OpenAIApiError> {
+    let vocab = load_vocab(&local_file.path())?;
+    Ok(vocab.detokenize(&request.tokens, request.special)?)
+  })
+  .await
+  .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))??;
+  Ok(Json(DetokenizeResponse { content }))
+}
+
+fn load_vocab(path: &Path) -> Result<Arc<Vocab>, OpenAIApiError> {
+  if let Some(vocab) = VOCABS.lock().unwrap().get(path) {
+    return Ok(vocab.clone());
+  }
+  let metadata = GGUFMetadata::read(path)?;
+  let vocab = Arc::new(Vocab::try_from(&metadata)?);
+  VOCABS
+    .lock()
+    .unwrap()
+    .insert(path.to_path_buf(), vocab.clone());
+  Ok(vocab)
+}
+
+#[cfg(test)]
+mod test {
+  use super::tokens_router;
+  use crate::{
+    objs::{Alias, HubFile},
+    service::{MockDataService, MockEnvServiceFn, MockHubService},
+    test_utils::{
+      llama_vocab_metadata, write_gguf, AppServiceStubMock, MockRouterState, RequestTestExt,
+      ResponseTestExt,
+    },
+  };
+  use axum::http::{Request, StatusCode};
+  use rstest::rstest;
+  use serde_json::{json, Value};
+  use std::{fs, sync::Arc};
+  use tempfile::TempDir;
+  use tower::ServiceExt;
+
+  fn router_state(data_service: MockDataService, hub_service: MockHubService) -> MockRouterState {
+    let app_service = Arc::new(AppServiceStubMock::new(
+      MockEnvServiceFn::new(),
+      hub_service,
+      data_service,
+    ));
+    let mut router_state = MockRouterState::new();
+    router_state
+      .expect_app_service()
+      .returning(move || app_service.clone());
+    router_state
+  }
+
+  #[rstest]
+  #[case(json! {{"model": "testalias:instruct", "tokens": [1, 4, 5, 6, 7, 2]}}, "Hello, world!")]
+  #[case(json! {{"model": "testalias:instruct", "tokens": [1, 4, 2], "special": true}}, "<s> Hello</s>")]
+  #[tokio::test]
+  async fn test_tokens_routes_detokenize(
+    #[case] input: Value,
+    #[case] expected: &str,
+  ) -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let hub_file = HubFile::testalias_builder()
+      .hf_cache(temp.path().to_path_buf())
+      .build()?;
+    fs::create_dir_all(hub_file.path().parent().unwrap())?;
+    write_gguf(&hub_file.path(), llama_vocab_metadata())?;
+    let mut data_service = MockDataService::new();
+    data_service
+      .expect_find_alias()
+      .return_once(|_| Some(Alias::testalias()));
+    let mut hub_service = MockHubService::new();
+    hub_service
+      .expect_find_local_file()
+      .return_once(move |_, _, _| Ok(Some(hub_file)));
+    let router = tokens_router().with_state(Arc::new(router_state(data_service, hub_service)));
+    let response = router
+      .oneshot(Request::post("/detokenize").json(input)?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response = response.json::<Value>().await?;
+    assert_eq!(json! {{"content": expected}}, response);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_tokens_routes_detokenize_reuses_the_loaded_vocab() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let hub_file = HubFile::testalias_builder()
+      .hf_cache(temp.path().to_path_buf())
+      .build()?;
+    fs::create_dir_all(hub_file.path().parent().unwrap())?;
+    write_gguf(&hub_file.path(), llama_vocab_metadata())?;
+    let mut data_service = MockDataService::new();
+    data_service
+      .expect_find_alias()
+      .times(2)
+      .returning(|_| Some(Alias::testalias()));
+    let mut hub_service = MockHubService::new();
+    let hf_cache = temp.path().to_path_buf();
+    hub_service
+      .expect_find_local_file()
+      .times(2)
+      .returning(move |_, _, _| {
+        Ok(Some(
+          HubFile::testalias_builder()
+            .hf_cache(hf_cache.clone())
+            .build()
+            .unwrap(),
+        ))
+      });
+    let router = tokens_router().with_state(Arc::new(router_state(data_service, hub_service)));
+    let input = json! {{"model": "testalias:instruct", "tokens": [1, 4, 5, 6, 7, 2]}};
+    let response = router
+      .clone()
+      .oneshot(Request::post("/detokenize").json(input.clone())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    fs::remove_file(hub_file.path())?;
+    let response = router
+      .oneshot(Request::post("/detokenize").json(input)?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response = response.json::<Value>().await?;
+    assert_eq!(json! {{"content": "Hello, world!"}}, response);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_tokens_routes_detokenize_alias_not_found() -> anyhow::Result<()> {
+    let mut data_service = MockDataService::new();
+    data_service.expect_find_alias().return_once(|_| None);
+    let router =
+      tokens_router().with_state(Arc::new(router_state(data_service, MockHubService::new())));
+    let response = router
+      .oneshot(Request::post("/detokenize").json(json! {{"model": "unknown", "tokens": [1]}})?)
+      .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+    let response = response.json::<Value>().await?;
+    assert_eq!(
+      json! {"model alias 'unknown' not found"},
+      response["error"]["message"]
+    );
+    Ok(())
+  }
+}
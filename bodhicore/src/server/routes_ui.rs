@@ -1,54 +1,331 @@
-use super::{utils::ApiError, RouterStateFn};
-use crate::db::objs::Conversation;
+use super::RouterStateFn;
+use crate::{
+  db::{
+    export_conversation, import_conversations,
+    objs::{ApiKey, Conversation, ConversationsQuery, Message, MessageMatch, ShareLink},
+    DbError, DbServiceFn, ExportFormat,
+  },
+  oai::OpenAIApiError,
+  utils::to_safe_filename,
+};
+use async_openai::types::{
+  ChatCompletionRequestMessage, CreateChatCompletionRequestArgs, CreateChatCompletionResponse,
+};
 use axum::{
   body::Body,
-  extract::{Path as UrlPath, State},
-  http::{header::LOCATION, status::StatusCode, Response},
+  extract::{DefaultBodyLimit, Path as UrlPath, Query, State},
+  http::{
+    header::{CONTENT_DISPOSITION, CONTENT_TYPE, LOCATION},
+    status::StatusCode,
+    Response,
+  },
   response::Json,
-  routing::{delete, get, post},
-  Router,
+  routing::{delete, get, patch, post},
+  Extension, Router,
 };
+use chrono::{serde::ts_milliseconds, DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::sync::Arc;
+use tokio::sync::mpsc::channel;
 
 pub fn chats_router() -> Router<Arc<dyn RouterStateFn>> {
   Router::new()
     .route("/chats", get(ui_chats_handler))
     .route("/chats", delete(ui_chats_delete_handler))
+    .route(
+      "/chats/import",
+      post(ui_chats_import_handler).layer(DefaultBodyLimit::max(IMPORT_MAX_BYTES)),
+    )
+    .route("/chats/search", get(ui_chats_search_handler))
     .route("/chats/:id", get(ui_chat_handler))
     .route("/chats/:id", post(ui_chat_new_handler))
     .route("/chats/:id", delete(ui_chat_delete_handler))
+    .route(
+      "/chats/:id/messages/:msg_id",
+      patch(ui_chat_message_edit_handler),
+    )
+    .route(
+      "/chats/:id/messages/:msg_id/regenerate",
+      post(ui_chat_message_regenerate_handler),
+    )
+    .route("/chats/:id/fork", post(ui_chat_fork_handler))
+    .route("/chats/:id/share", post(ui_chat_share_handler))
+    .route("/chats/:id/tags", post(ui_chat_tag_handler))
+    .route("/chats/:id/tags/:tag", delete(ui_chat_untag_handler))
+    .route("/chats/:id/export", get(ui_chat_export_handler))
+}
+
+pub static DEFAULT_SHARE_EXPIRES_IN_SECS: u64 = 24 * 60 * 60;
+pub static MAX_SHARE_EXPIRES_IN_SECS: u64 = 30 * 24 * 60 * 60;
+// ChatGPT exports with the full history run into tens of MBs
+pub static IMPORT_MAX_BYTES: usize = 256 * 1024 * 1024;
+pub static DEFAULT_SEARCH_LIMIT: u32 = 50;
+pub static MAX_SEARCH_LIMIT: u32 = 200;
+pub static MAX_CHATS_LIMIT: u32 = 200;
+pub static MAX_TAG_CHARS: usize = 64;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct EditMessageRequest {
+  pub content: String,
+  /// generate a new assistant reply for the edited message
+  #[serde(default)]
+  pub regenerate: bool,
+  /// model alias to use for regenerating the reply
+  #[serde(default)]
+  pub model: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct RegenerateRequest {
+  /// model alias to generate the reply with, defaults to the model of the replaced reply
+  #[serde(default)]
+  pub model: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ForkRequest {
+  /// last message to copy into the new chat
+  pub message_id: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ShareRequest {
+  /// seconds the link is valid for, defaults to a day, at most 30 days
+  #[serde(default)]
+  pub expires_in_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ShareResponse {
+  pub token: String,
+  pub url: String,
+  #[serde(rename = "expiresAt", with = "ts_milliseconds")]
+  pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ExportQuery {
+  /// `markdown` or `json`, defaults to `markdown`
+  #[serde(default)]
+  pub format: ExportFormat,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct SearchQuery {
+  /// words to search for in the messages, the last word is matched as a prefix
+  #[serde(default)]
+  pub q: String,
+  /// maximum number of matching messages, defaults to 50, at most 200
+  #[serde(default)]
+  pub limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TagRequest {
+  pub tag: String,
+}
+
+/// Chat with the messages matching the search, chats with the best match first
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChatSearchResult {
+  pub id: String,
+  pub title: String,
+  pub matches: Vec<SearchSnippet>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchSnippet {
+  pub message_id: String,
+  pub role: String,
+  /// excerpt of the message, with the matched words wrapped in `<mark>`
+  pub snippet: String,
+  #[serde(with = "ts_milliseconds")]
+  pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ImportedChat {
+  pub id: String,
+  pub title: String,
+  pub messages: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ImportResponse {
+  pub imported: Vec<ImportedChat>,
+}
+
+impl From<ShareLink> for ShareResponse {
+  fn from(value: ShareLink) -> Self {
+    ShareResponse {
+      url: format!("/share/{}", value.token),
+      token: value.token,
+      expires_at: value.expires_at,
+    }
+  }
+}
+
+/// Owner of the chats of the request, the name of its API key when `BODHI_AUTH` is enabled.
+/// The chats of all the owners are accessible when not set.
+pub(crate) fn chat_owner(api_key: Option<Extension<ApiKey>>) -> Option<String> {
+  api_key.map(|Extension(api_key)| api_key.name)
+}
+
+fn chat_not_found() -> OpenAIApiError {
+  OpenAIApiError::NotFound("given record not found in conversations".to_string())
+}
+
+/// Chat with the messages, a chat of another owner is not found same as a missing chat,
+/// so the ids of the chats of others are not revealed
+async fn owned_chat(
+  db_service: &dyn DbServiceFn,
+  id: &str,
+  owner: &Option<String>,
+) -> Result<Conversation, OpenAIApiError> {
+  let convo = db_service.get_conversation_with_messages(id).await?;
+  if owner.is_some() && convo.owner != *owner {
+    return Err(chat_not_found());
+  }
+  Ok(convo)
 }
 
+/// Fails if the chat exists and is owned by another owner, before the chat is saved
+async fn check_chat_owner(
+  db_service: &dyn DbServiceFn,
+  id: &str,
+  owner: &Option<String>,
+) -> Result<(), OpenAIApiError> {
+  if owner.is_none() || id.is_empty() {
+    return Ok(());
+  }
+  match db_service.get_conversation_with_messages(id).await {
+    Ok(convo) if convo.owner != *owner => Err(chat_not_found()),
+    Ok(_)
+    | Err(DbError::Sqlx {
+      source: sqlx::Error::RowNotFound,
+      ..
+    }) => Ok(()),
+    Err(err) => Err(err.into()),
+  }
+}
+
+/// Lists the chats newest first, sorted by `sort` (`created_at` or `updated_at`),
+/// a page of the chats is returned when `limit` and `offset` are given
 async fn ui_chats_handler(
   State(state): State<Arc<dyn RouterStateFn>>,
-) -> Result<Json<Vec<Conversation>>, ApiError> {
-  let convos = state.db_service().list_conversations().await?;
+  api_key: Option<Extension<ApiKey>>,
+  Query(mut query): Query<ConversationsQuery>,
+) -> Result<Json<Vec<Conversation>>, OpenAIApiError> {
+  if query
+    .limit
+    .is_some_and(|limit| limit == 0 || limit > MAX_CHATS_LIMIT)
+  {
+    return Err(OpenAIApiError::BadRequest(format!(
+      "limit should be between 1 and {MAX_CHATS_LIMIT}"
+    )));
+  }
+  query.owner = chat_owner(api_key);
+  let convos = state.db_service().list_conversations(&query).await?;
   Ok(Json(convos))
 }
 
-async fn ui_chat_handler(
+async fn ui_chats_search_handler(
   State(state): State<Arc<dyn RouterStateFn>>,
-  UrlPath(id): UrlPath<String>,
-) -> Result<Json<Conversation>, ApiError> {
-  let convo = state
+  api_key: Option<Extension<ApiKey>>,
+  Query(query): Query<SearchQuery>,
+) -> Result<Json<Vec<ChatSearchResult>>, OpenAIApiError> {
+  if query.q.trim().is_empty() {
+    return Err(OpenAIApiError::BadRequest(
+      "q should not be empty".to_string(),
+    ));
+  }
+  let limit = query.limit.unwrap_or(DEFAULT_SEARCH_LIMIT);
+  if limit == 0 || limit > MAX_SEARCH_LIMIT {
+    return Err(OpenAIApiError::BadRequest(format!(
+      "limit should be between 1 and {MAX_SEARCH_LIMIT}"
+    )));
+  }
+  let owner = chat_owner(api_key);
+  let matches = state
     .db_service()
-    .get_conversation_with_messages(&id)
+    .search_messages(&query.q, owner.as_deref(), limit)
     .await?;
+  Ok(Json(group_by_chat(matches)))
+}
+
+fn group_by_chat(matches: Vec<MessageMatch>) -> Vec<ChatSearchResult> {
+  let mut results = Vec::<ChatSearchResult>::new();
+  for item in matches {
+    let snippet = SearchSnippet {
+      message_id: item.message_id,
+      role: item.role,
+      snippet: item.snippet,
+      created_at: item.created_at,
+    };
+    match results
+      .iter_mut()
+      .find(|result| result.id == item.conversation_id)
+    {
+      Some(result) => result.matches.push(snippet),
+      None => results.push(ChatSearchResult {
+        id: item.conversation_id,
+        title: item.title,
+        matches: vec![snippet],
+      }),
+    }
+  }
+  results
+}
+
+async fn ui_chats_import_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  api_key: Option<Extension<ApiKey>>,
+  Json(request): Json<Value>,
+) -> Result<(StatusCode, Json<ImportResponse>), OpenAIApiError> {
+  let convos = import_conversations(request, Utc::now())
+    .map_err(|err| OpenAIApiError::BadRequest(err.to_string()))?;
+  let owner = chat_owner(api_key);
+  let db_service = state.db_service();
+  for convo in &convos {
+    check_chat_owner(db_service.as_ref(), &convo.id, &owner).await?;
+  }
+  let mut imported = vec![];
+  for mut convo in convos {
+    convo.owner.clone_from(&owner);
+    db_service.save_conversation(&mut convo).await?;
+    imported.push(ImportedChat {
+      id: convo.id,
+      title: convo.title,
+      messages: convo.messages.len(),
+    });
+  }
+  Ok((StatusCode::CREATED, Json(ImportResponse { imported })))
+}
+
+async fn ui_chat_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  api_key: Option<Extension<ApiKey>>,
+  UrlPath(id): UrlPath<String>,
+) -> Result<Json<Conversation>, OpenAIApiError> {
+  let convo = owned_chat(state.db_service().as_ref(), &id, &chat_owner(api_key)).await?;
   Ok(Json(convo))
 }
 
 async fn ui_chat_new_handler(
   State(state): State<Arc<dyn RouterStateFn>>,
+  api_key: Option<Extension<ApiKey>>,
   UrlPath(id): UrlPath<String>,
   Json(mut conversation): Json<Conversation>,
-) -> Result<Response<Body>, ApiError> {
+) -> Result<Response<Body>, OpenAIApiError> {
   if !conversation.id.eq(&id) {
     conversation.id = id;
   }
-  state
-    .db_service()
-    .save_conversation(&mut conversation)
-    .await?;
+  let db_service = state.db_service();
+  conversation.owner = chat_owner(api_key);
+  check_chat_owner(db_service.as_ref(), &conversation.id, &conversation.owner).await?;
+  db_service.save_conversation(&mut conversation).await?;
   let response = Response::builder()
     .status(StatusCode::CREATED)
     .header(LOCATION, format!("/chats/{}", conversation.id))
@@ -58,40 +335,285 @@ async fn ui_chat_new_handler(
 
 async fn ui_chats_delete_handler(
   State(state): State<Arc<dyn RouterStateFn>>,
-) -> Result<(), ApiError> {
-  state.db_service().delete_all_conversations().await?;
+  api_key: Option<Extension<ApiKey>>,
+) -> Result<(), OpenAIApiError> {
+  let owner = chat_owner(api_key);
+  state
+    .db_service()
+    .delete_all_conversations(owner.as_deref())
+    .await?;
   Ok(())
 }
 
 async fn ui_chat_delete_handler(
   State(state): State<Arc<dyn RouterStateFn>>,
+  api_key: Option<Extension<ApiKey>>,
   UrlPath(id): UrlPath<String>,
-) -> Result<(), ApiError> {
-  state.db_service().delete_conversations(&id).await?;
+) -> Result<(), OpenAIApiError> {
+  let db_service = state.db_service();
+  owned_chat(db_service.as_ref(), &id, &chat_owner(api_key)).await?;
+  db_service.delete_conversations(&id).await?;
+  Ok(())
+}
+
+async fn ui_chat_tag_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  api_key: Option<Extension<ApiKey>>,
+  UrlPath(id): UrlPath<String>,
+  Json(request): Json<TagRequest>,
+) -> Result<(), OpenAIApiError> {
+  let tag = request.tag.trim();
+  if tag.is_empty() || tag.chars().count() > MAX_TAG_CHARS {
+    return Err(OpenAIApiError::BadRequest(format!(
+      "tag should be between 1 and {MAX_TAG_CHARS} characters"
+    )));
+  }
+  let db_service = state.db_service();
+  owned_chat(db_service.as_ref(), &id, &chat_owner(api_key)).await?;
+  db_service.tag_conversation(&id, tag).await?;
+  Ok(())
+}
+
+async fn ui_chat_untag_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  api_key: Option<Extension<ApiKey>>,
+  UrlPath((id, tag)): UrlPath<(String, String)>,
+) -> Result<(), OpenAIApiError> {
+  let db_service = state.db_service();
+  owned_chat(db_service.as_ref(), &id, &chat_owner(api_key)).await?;
+  db_service.untag_conversation(&id, &tag).await?;
   Ok(())
 }
 
+async fn ui_chat_message_edit_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  api_key: Option<Extension<ApiKey>>,
+  UrlPath((id, msg_id)): UrlPath<(String, String)>,
+  Json(request): Json<EditMessageRequest>,
+) -> Result<Json<Conversation>, OpenAIApiError> {
+  let db_service = state.db_service();
+  let convo = owned_chat(db_service.as_ref(), &id, &chat_owner(api_key)).await?;
+  let message = convo
+    .messages
+    .iter()
+    .find(|message| message.id == msg_id)
+    .ok_or_else(|| {
+      OpenAIApiError::NotFound(format!("message '{msg_id}' not found in chat '{id}'"))
+    })?;
+  if message.role != "user" {
+    return Err(OpenAIApiError::BadRequest(format!(
+      "only user messages can be edited, message '{msg_id}' has role '{}'",
+      message.role
+    )));
+  }
+  if request.regenerate && request.model.is_none() {
+    return Err(OpenAIApiError::BadRequest(
+      "model is required to regenerate the reply".to_string(),
+    ));
+  }
+  db_service
+    .edit_message(&id, &msg_id, &request.content)
+    .await?;
+  let mut convo = db_service.get_conversation_with_messages(&id).await?;
+  if let Some(model) = request.model.filter(|_| request.regenerate) {
+    let mut reply = regenerate_reply(state.clone(), &model, &convo.messages).await?;
+    reply.conversation_id.clone_from(&convo.id);
+    db_service.save_message(&mut reply).await?;
+    convo.messages.push(reply);
+  }
+  Ok(Json(convo))
+}
+
+async fn ui_chat_message_regenerate_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  api_key: Option<Extension<ApiKey>>,
+  UrlPath((id, msg_id)): UrlPath<(String, String)>,
+  request: Option<Json<RegenerateRequest>>,
+) -> Result<Json<Conversation>, OpenAIApiError> {
+  let db_service = state.db_service();
+  let convo = owned_chat(db_service.as_ref(), &id, &chat_owner(api_key)).await?;
+  let position = convo
+    .messages
+    .iter()
+    .position(|message| message.id == msg_id)
+    .ok_or_else(|| {
+      OpenAIApiError::NotFound(format!("message '{msg_id}' not found in chat '{id}'"))
+    })?;
+  let message = &convo.messages[position];
+  // for a user message its reply is regenerated, for an assistant message the message itself
+  let (context, replaced) = match message.role.as_str() {
+    "user" => (position + 1, convo.messages.get(position + 1)),
+    "assistant" => (position, Some(message)),
+    role => {
+      return Err(OpenAIApiError::BadRequest(format!(
+        "only user and assistant messages can be regenerated, message '{msg_id}' has role '{role}'"
+      )))
+    }
+  };
+  let model = request
+    .and_then(|Json(request)| request.model)
+    .or_else(|| replaced.and_then(|message| message.model.clone()))
+    .ok_or_else(|| {
+      OpenAIApiError::BadRequest("model is required to regenerate the reply".to_string())
+    })?;
+  // the reply is generated before branching, so the chat is unchanged if the generation fails
+  let mut reply = regenerate_reply(state.clone(), &model, &convo.messages[..context]).await?;
+  if let Some(replaced) = replaced {
+    db_service.branch_messages(&id, &replaced.id).await?;
+  }
+  reply.conversation_id.clone_from(&id);
+  db_service.save_message(&mut reply).await?;
+  let convo = db_service.get_conversation_with_messages(&id).await?;
+  Ok(Json(convo))
+}
+
+async fn ui_chat_fork_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  api_key: Option<Extension<ApiKey>>,
+  UrlPath(id): UrlPath<String>,
+  Json(request): Json<ForkRequest>,
+) -> Result<(StatusCode, Json<Conversation>), OpenAIApiError> {
+  let db_service = state.db_service();
+  owned_chat(db_service.as_ref(), &id, &chat_owner(api_key)).await?;
+  let convo = db_service
+    .fork_conversation(&id, &request.message_id)
+    .await?;
+  Ok((StatusCode::CREATED, Json(convo)))
+}
+
+async fn ui_chat_share_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  api_key: Option<Extension<ApiKey>>,
+  UrlPath(id): UrlPath<String>,
+  request: Option<Json<ShareRequest>>,
+) -> Result<(StatusCode, Json<ShareResponse>), OpenAIApiError> {
+  let expires_in_secs = request
+    .and_then(|Json(request)| request.expires_in_secs)
+    .unwrap_or(DEFAULT_SHARE_EXPIRES_IN_SECS);
+  if expires_in_secs == 0 || expires_in_secs > MAX_SHARE_EXPIRES_IN_SECS {
+    return Err(OpenAIApiError::BadRequest(format!(
+      "expires_in_secs should be between 1 and {MAX_SHARE_EXPIRES_IN_SECS}"
+    )));
+  }
+  let db_service = state.db_service();
+  owned_chat(db_service.as_ref(), &id, &chat_owner(api_key)).await?;
+  let share_link = db_service
+    .create_share_link(&id, Duration::seconds(expires_in_secs as i64))
+    .await?;
+  Ok((StatusCode::CREATED, Json(ShareResponse::from(share_link))))
+}
+
+async fn ui_chat_export_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  api_key: Option<Extension<ApiKey>>,
+  UrlPath(id): UrlPath<String>,
+  Query(query): Query<ExportQuery>,
+) -> Result<Response<Body>, OpenAIApiError> {
+  let convo = owned_chat(state.db_service().as_ref(), &id, &chat_owner(api_key)).await?;
+  let timezone = state.app_service().env_service().timezone();
+  let content = export_conversation(&convo, query.format, &timezone)
+    .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))?;
+  let name = if convo.title.trim().is_empty() {
+    convo.id.as_str()
+  } else {
+    convo.title.as_str()
+  };
+  let filename = format!("{}.{}", to_safe_filename(name), query.format.extension());
+  let response = Response::builder()
+    .status(StatusCode::OK)
+    .header(CONTENT_TYPE, query.format.content_type())
+    .header(
+      CONTENT_DISPOSITION,
+      format!("attachment; filename=\"{filename}\""),
+    )
+    .body(Body::from(content))?;
+  Ok(response)
+}
+
+async fn regenerate_reply(
+  state: Arc<dyn RouterStateFn>,
+  model: &str,
+  messages: &[Message],
+) -> Result<Message, OpenAIApiError> {
+  let messages = messages
+    .iter()
+    .map(|message| {
+      serde_json::from_value::<ChatCompletionRequestMessage>(serde_json::json! {{
+        "role": message.role,
+        "content": message.content,
+      }})
+      .map_err(|err| OpenAIApiError::BadRequest(err.to_string()))
+    })
+    .collect::<Result<Vec<_>, _>>()?;
+  let request = CreateChatCompletionRequestArgs::default()
+    .model(model)
+    .messages(messages)
+    .build()
+    .map_err(|err| OpenAIApiError::BadRequest(err.to_string()))?;
+  let (tx, mut rx) = channel::<String>(100);
+  let handle = tokio::spawn(async move { state.chat_completions(request.into(), tx).await });
+  let Some(response) = rx.recv().await else {
+    return match handle.await {
+      Ok(Err(err)) => Err(err),
+      _ => Err(OpenAIApiError::InternalServer(
+        "receiver stream abruptly closed".to_string(),
+      )),
+    };
+  };
+  drop(rx);
+  _ = handle.await;
+  let response = serde_json::from_str::<CreateChatCompletionResponse>(&response)
+    .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))?;
+  let usage = response.usage;
+  let content = response
+    .choices
+    .into_iter()
+    .next()
+    .and_then(|choice| choice.message.content);
+  Ok(Message {
+    role: "assistant".to_string(),
+    content,
+    created_at: Utc::now(),
+    model: Some(model.to_string()),
+    prompt_tokens: usage.as_ref().map(|usage| i64::from(usage.prompt_tokens)),
+    completion_tokens: usage.map(|usage| i64::from(usage.completion_tokens)),
+    ..Default::default()
+  })
+}
+
 #[cfg(test)]
 mod test {
-  use super::chats_router;
+  use super::{chats_router, ChatSearchResult, ImportResponse, ImportedChat, ShareResponse};
   use crate::{
     db::{
-      objs::{Conversation, ConversationBuilder, MessageBuilder},
+      objs::{
+        ApiKey, Conversation, ConversationBuilder, ConversationsQuery, Message, MessageBuilder,
+      },
       DbService, DbServiceFn,
     },
+    objs::DisplayTimezone,
     server::RouterState,
-    service::MockAppServiceFn,
-    test_utils::{db_service, MockSharedContext, RequestTestExt, ResponseTestExt},
+    service::{MockAppServiceFn, MockDataService, MockEnvServiceFn, MockHubService},
+    test_utils::{
+      db_service, testdb, AppServiceStubMock, MockRouterState, MockSharedContext, MockTimeService,
+      RequestTestExt, ResponseTestExt,
+    },
   };
   use axum::{
     body::Body,
-    http::{Request, StatusCode},
+    http::{
+      header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+      Request, StatusCode,
+    },
+    Extension,
   };
-  use chrono::{DateTime, Utc};
+  use chrono::{DateTime, Days, Duration, Utc};
   use rstest::rstest;
-  use serde_json::Value;
+  use serde_json::{json, Value};
+  use sqlx::SqlitePool;
   use std::sync::Arc;
   use tempfile::TempDir;
+  use tokio::sync::mpsc::Sender;
   use tower::ServiceExt;
   use uuid::Uuid;
   use validator::ValidateLength;
@@ -99,22 +621,569 @@ mod test {
   #[rstest]
   #[awt]
   #[tokio::test]
-  async fn test_chat_routes_index(
+  async fn test_chat_routes_index(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_temp, now, db_service) = db_service;
+    let mut convo_1 = ConversationBuilder::default()
+      .title("test title 1")
+      .created_at(now)
+      .messages(vec![MessageBuilder::default()
+        .role("user")
+        .content("test content")
+        .build()?])
+      .build()?;
+    let mut convo_2 = ConversationBuilder::default()
+      .title("test title 2")
+      .created_at(now.checked_sub_days(Days::new(1)).unwrap())
+      .build()?;
+    db_service.save_conversation(&mut convo_1).await?;
+    db_service.save_conversation(&mut convo_2).await?;
+    let router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(MockAppServiceFn::new()),
+      Arc::new(db_service),
+    );
+    let router = chats_router().with_state(Arc::new(router_state));
+    let response = router
+      .oneshot(Request::get("/chats").body(Body::empty()).unwrap())
+      .await?
+      .json::<Value>()
+      .await?;
+    assert_eq!(2, response.as_array().length().unwrap());
+    let expected_1: Value = serde_json::from_str(&format!(
+      r#"{{"id":"{}","title":"test title 1","createdAt":{},"messages":[]}}"#,
+      convo_1.id,
+      convo_1.created_at.timestamp_millis()
+    ))?;
+    assert_eq!(expected_1, response[0]);
+    let expected_2: Value = serde_json::from_str(&format!(
+      r#"{{"id":"{}","title":"test title 2","createdAt":{},"messages":[]}}"#,
+      convo_2.id,
+      convo_2.created_at.timestamp_millis()
+    ))?;
+    assert_eq!(expected_2, response[1]);
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_chat_routes_filtered_by_owner(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let mut alice = ConversationBuilder::default()
+      .title("alice chat")
+      .owner("alice")
+      .messages(vec![MessageBuilder::default()
+        .role("user")
+        .content("What day comes after Monday?")
+        .build()?])
+      .build()?;
+    db_service.save_conversation(&mut alice).await?;
+    let mut bob = ConversationBuilder::default()
+      .title("bob chat")
+      .owner("bob")
+      .messages(vec![MessageBuilder::default()
+        .role("user")
+        .content("What day comes before Monday?")
+        .build()?])
+      .build()?;
+    db_service.save_conversation(&mut bob).await?;
+    let db_service = Arc::new(db_service);
+    let router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(MockAppServiceFn::new()),
+      db_service.clone(),
+    );
+    // the auth middleware adds the API key of the request
+    let api_key = ApiKey {
+      name: "bob".to_string(),
+      ..Default::default()
+    };
+    let router = chats_router()
+      .layer(Extension(api_key))
+      .with_state(Arc::new(router_state));
+
+    let response = router
+      .clone()
+      .oneshot(Request::get("/chats").body(Body::empty())?)
+      .await?
+      .json::<Vec<Conversation>>()
+      .await?;
+    assert_eq!(1, response.len());
+    assert_eq!(bob.id, response[0].id);
+    let response = router
+      .clone()
+      .oneshot(Request::get("/chats/search?q=monday").body(Body::empty())?)
+      .await?
+      .json::<Vec<ChatSearchResult>>()
+      .await?;
+    assert_eq!(1, response.len());
+    assert_eq!(bob.id, response[0].id);
+    for request in [
+      Request::get(format!("/chats/{}", alice.id)).body(Body::empty())?,
+      Request::delete(format!("/chats/{}", alice.id)).body(Body::empty())?,
+      Request::post(format!("/chats/{}", alice.id)).json(json! {{
+        "title": "taken over",
+        "messages": []
+      }})?,
+    ] {
+      let response = router.clone().oneshot(request).await?;
+      assert_eq!(StatusCode::NOT_FOUND, response.status());
+    }
+
+    let response = router
+      .clone()
+      .oneshot(Request::delete("/chats").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let convos = db_service
+      .list_conversations(&ConversationsQuery::default())
+      .await?;
+    assert_eq!(1, convos.len());
+    assert_eq!(alice.id, convos[0].id);
+    assert_eq!("alice chat", convos[0].title);
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_chat_routes_new_chat_does_not_take_over_messages_of_other_owner(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let mut alice = ConversationBuilder::default()
+      .title("alice chat")
+      .owner("alice")
+      .messages(vec![MessageBuilder::default()
+        .role("user")
+        .content("What day comes after Monday?")
+        .build()?])
+      .build()?;
+    db_service.save_conversation(&mut alice).await?;
+    let message_id = alice.messages[0].id.clone();
+    let db_service = Arc::new(db_service);
+    let router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(MockAppServiceFn::new()),
+      db_service.clone(),
+    );
+    let api_key = ApiKey {
+      name: "bob".to_string(),
+      ..Default::default()
+    };
+    let router = chats_router()
+      .layer(Extension(api_key))
+      .with_state(Arc::new(router_state));
+    let response = router
+      .oneshot(Request::post("/chats/bobchat").json(json! {{
+        "title": "bob chat",
+        "messages": [{
+          "id": message_id,
+          "conversation_id": alice.id,
+          "role": "user",
+          "content": "taken over"
+        }]
+      }})?)
+      .await?;
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    let from_db = db_service.get_conversation_with_messages(&alice.id).await?;
+    assert_eq!(1, from_db.messages.len());
+    assert_eq!(
+      Some("What day comes after Monday?".to_string()),
+      from_db.messages[0].content
+    );
+    assert!(db_service
+      .get_conversation_with_messages("bobchat")
+      .await
+      .is_err());
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("/chats?limit=2", vec!["chat 3", "chat 2"])]
+  #[case("/chats?limit=2&offset=2", vec!["chat 1"])]
+  #[case("/chats?sort=updated_at&limit=1", vec!["chat 1"])]
+  #[awt]
+  #[tokio::test]
+  async fn test_chat_routes_index_paginated(
+    #[future] testdb: (TempDir, SqlitePool),
+    #[case] uri: &str,
+    #[case] expected: Vec<&str>,
+  ) -> anyhow::Result<()> {
+    let (_temp, pool) = testdb;
+    let now = Utc::now();
+    let mut times = (0..4)
+      .map(|hour| now + Duration::hours(hour))
+      .collect::<Vec<_>>()
+      .into_iter();
+    let mut time_service = MockTimeService::new();
+    time_service
+      .expect_utc_now()
+      .returning(move || times.next().unwrap());
+    let db_service = DbService::new(pool, Arc::new(time_service));
+    let mut convos = vec![];
+    for (title, days_ago) in [("chat 1", 3), ("chat 2", 2), ("chat 3", 1)] {
+      let mut convo = ConversationBuilder::default()
+        .title(title)
+        .created_at(now.checked_sub_days(Days::new(days_ago)).unwrap())
+        .build()?;
+      db_service.save_conversation(&mut convo).await?;
+      convos.push(convo);
+    }
+    // bumps chat 1 to the most recently updated
+    db_service.save_conversation(&mut convos[0]).await?;
+    let router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(MockAppServiceFn::new()),
+      Arc::new(db_service),
+    );
+    let router = chats_router().with_state(Arc::new(router_state));
+    let response = router
+      .oneshot(Request::get(uri).body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response = response.json::<Vec<Conversation>>().await?;
+    let titles = response
+      .iter()
+      .map(|convo| convo.title.as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(expected, titles);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("/chats?limit=0")]
+  #[case("/chats?limit=201")]
+  #[case("/chats?sort=title")]
+  #[awt]
+  #[tokio::test]
+  async fn test_chat_routes_index_invalid(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+    #[case] uri: &str,
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(MockAppServiceFn::new()),
+      Arc::new(db_service),
+    );
+    let router = chats_router().with_state(Arc::new(router_state));
+    let response = router
+      .oneshot(Request::get(uri).body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    Ok(())
+  }
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_chat_routes_tags(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let mut work = ConversationBuilder::default().title("work chat").build()?;
+    let mut personal = ConversationBuilder::default()
+      .title("personal chat")
+      .build()?;
+    db_service.save_conversation(&mut work).await?;
+    db_service.save_conversation(&mut personal).await?;
+    let router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(MockAppServiceFn::new()),
+      Arc::new(db_service),
+    );
+    let router = chats_router().with_state(Arc::new(router_state));
+    for (id, tag) in [
+      (&work.id, " work "),
+      (&work.id, "bodhi"),
+      (&personal.id, "bodhi"),
+    ] {
+      let response = router
+        .clone()
+        .oneshot(Request::post(&format!("/chats/{id}/tags")).json(json! {{"tag": tag}})?)
+        .await?;
+      assert_eq!(StatusCode::OK, response.status());
+    }
+    let response = router
+      .clone()
+      .oneshot(Request::get("/chats?tag=work").body(Body::empty())?)
+      .await?
+      .json::<Vec<Conversation>>()
+      .await?;
+    assert_eq!(1, response.len());
+    assert_eq!(work.id, response[0].id);
+    assert_eq!(
+      vec!["bodhi".to_string(), "work".to_string()],
+      response[0].tags
+    );
+
+    let response = router
+      .clone()
+      .oneshot(Request::delete(&format!("/chats/{}/tags/bodhi", personal.id)).body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response = router
+      .clone()
+      .oneshot(Request::get("/chats?tag=bodhi").body(Body::empty())?)
+      .await?
+      .json::<Vec<Conversation>>()
+      .await?;
+    assert_eq!(1, response.len());
+    assert_eq!(work.id, response[0].id);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("testid", json! {{"tag": " "}}, StatusCode::BAD_REQUEST)]
+  #[case("testid", json! {{"tag": "a".repeat(65)}}, StatusCode::BAD_REQUEST)]
+  #[case("unknown", json! {{"tag": "work"}}, StatusCode::NOT_FOUND)]
+  #[awt]
+  #[tokio::test]
+  async fn test_chat_routes_tag_invalid(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+    #[case] id: &str,
+    #[case] request: Value,
+    #[case] status: StatusCode,
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let mut convo = ConversationBuilder::default()
+      .id("testid")
+      .title("test chat")
+      .build()?;
+    db_service.save_conversation(&mut convo).await?;
+    let router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(MockAppServiceFn::new()),
+      Arc::new(db_service),
+    );
+    let router = chats_router().with_state(Arc::new(router_state));
+    let response = router
+      .oneshot(Request::post(&format!("/chats/{id}/tags")).json(request)?)
+      .await?;
+    assert_eq!(status, response.status());
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_chat_routes_get(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let mut convo = ConversationBuilder::default().title("test title").build()?;
+    let message_1 = MessageBuilder::default()
+      .conversation_id(&convo.id)
+      .role("user")
+      .content("test content")
+      .build()?;
+    let message_2 = MessageBuilder::default()
+      .conversation_id(&convo.id)
+      .role("assistant")
+      .content("test reply")
+      .build()?;
+    convo.messages.push(message_1);
+    convo.messages.push(message_2);
+    db_service.save_conversation(&mut convo).await?;
+    let router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(MockAppServiceFn::new()),
+      Arc::new(db_service),
+    );
+    let router = chats_router().with_state(Arc::new(router_state));
+    let response = router
+      .oneshot(
+        Request::get(&format!("/chats/{}", &convo.id))
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await?
+      .json::<Value>()
+      .await?;
+    let expected = format!(
+      r#"{{"id":"{}","title":"test title","messages":[{{"id":"{}","role":"user","content":"test content"}},{{"id":"{}","role":"assistant","content":"test reply"}}]}}"#,
+      convo.id, convo.messages[0].id, convo.messages[1].id
+    );
+    let expected = serde_json::from_str::<Value>(&expected)?;
+    assert_eq!(expected, response);
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_chat_routes_share(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_temp, now, db_service) = db_service;
+    let mut convo = ConversationBuilder::default().title("test title").build()?;
+    db_service.save_conversation(&mut convo).await?;
+    let router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(MockAppServiceFn::new()),
+      Arc::new(db_service),
+    );
+    let router = chats_router().with_state(Arc::new(router_state));
+    let response = router
+      .oneshot(
+        Request::post(format!("/chats/{}/share", convo.id))
+          .json(json! {{"expires_in_secs": 3600}})?,
+      )
+      .await?;
+    assert_eq!(StatusCode::CREATED, response.status());
+    let response = response.json::<ShareResponse>().await?;
+    assert_eq!(format!("/share/{}", response.token), response.url);
+    assert_eq!(now + chrono::Duration::hours(1), response.expires_at);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(json! {{"expires_in_secs": 0}}, StatusCode::BAD_REQUEST)]
+  #[case(json! {{"expires_in_secs": 31 * 24 * 60 * 60}}, StatusCode::BAD_REQUEST)]
+  #[awt]
+  #[tokio::test]
+  async fn test_chat_routes_share_invalid(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+    #[case] request: Value,
+    #[case] expected: StatusCode,
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(MockAppServiceFn::new()),
+      Arc::new(db_service),
+    );
+    let router = chats_router().with_state(Arc::new(router_state));
+    let response = router
+      .oneshot(Request::post("/chats/testid/share").json(request)?)
+      .await?;
+    assert_eq!(expected, response.status());
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_chat_routes_share_chat_not_found(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(MockAppServiceFn::new()),
+      Arc::new(db_service),
+    );
+    let router = chats_router().with_state(Arc::new(router_state));
+    let response = router
+      .oneshot(
+        Request::post("/chats/unknown/share")
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_chat_routes_import(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let db_service = Arc::new(db_service);
+    let router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(MockAppServiceFn::new()),
+      db_service.clone(),
+    );
+    let router = chats_router().with_state(Arc::new(router_state));
+    let response = router
+      .oneshot(Request::post("/chats/import").json(json! {[{
+        "id": "testid",
+        "title": "test title",
+        "createdAt": 1714559400000i64,
+        "messages": [
+          {"id": "msg-1", "role": "user", "content": "What day comes after Monday?", "createdAt": 1714559400000i64},
+          {"id": "msg-2", "role": "assistant", "content": "Tuesday", "createdAt": 1714559410000i64}
+        ]
+      }]})?)
+      .await?;
+    assert_eq!(StatusCode::CREATED, response.status());
+    assert_eq!(
+      ImportResponse {
+        imported: vec![ImportedChat {
+          id: "testid".to_string(),
+          title: "test title".to_string(),
+          messages: 2,
+        }]
+      },
+      response.json::<ImportResponse>().await?
+    );
+    let convo = db_service.get_conversation_with_messages("testid").await?;
+    assert_eq!(
+      DateTime::<Utc>::from_timestamp(1714559400, 0).unwrap(),
+      convo.created_at
+    );
+    assert_eq!(2, convo.messages.len());
+    assert_eq!(Some("Tuesday".to_string()), convo.messages[1].content);
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_chat_routes_import_unknown_format(
     #[future] db_service: (TempDir, DateTime<Utc>, DbService),
   ) -> anyhow::Result<()> {
     let (_temp, _now, db_service) = db_service;
-    let mut convo_1 = ConversationBuilder::default()
-      .title("test title 1")
+    let router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(MockAppServiceFn::new()),
+      Arc::new(db_service),
+    );
+    let router = chats_router().with_state(Arc::new(router_state));
+    let response = router
+      .oneshot(Request::post("/chats/import").json(json! {{"title": "no messages"}})?)
+      .await?;
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_chat_routes_search(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let mut convo = ConversationBuilder::default()
+      .title("Days of the week")
+      .messages(vec![
+        MessageBuilder::default()
+          .role("user")
+          .content("What day comes after Monday?")
+          .build()?,
+        MessageBuilder::default()
+          .role("assistant")
+          .content("Tuesday comes after Monday")
+          .build()?,
+      ])
+      .build()?;
+    db_service.save_conversation(&mut convo).await?;
+    let mut other = ConversationBuilder::default()
+      .title("Colors")
       .messages(vec![MessageBuilder::default()
         .role("user")
-        .content("test content")
+        .content("What color is the sky?")
         .build()?])
       .build()?;
-    let mut convo_2 = ConversationBuilder::default()
-      .title("test title 2")
-      .build()?;
-    db_service.save_conversation(&mut convo_1).await?;
-    db_service.save_conversation(&mut convo_2).await?;
+    db_service.save_conversation(&mut other).await?;
     let router_state = RouterState::new(
       Arc::new(MockSharedContext::new()),
       Arc::new(MockAppServiceFn::new()),
@@ -122,66 +1191,114 @@ mod test {
     );
     let router = chats_router().with_state(Arc::new(router_state));
     let response = router
-      .oneshot(Request::get("/chats").body(Body::empty()).unwrap())
-      .await?
-      .json::<Value>()
+      .oneshot(Request::get("/chats/search?q=monday").body(Body::empty())?)
       .await?;
-    assert_eq!(2, response.as_array().length().unwrap());
-    let expected_1: Value = serde_json::from_str(&format!(
-      r#"{{"id":"{}","title":"test title 1","messages":[]}}"#,
-      convo_1.id
-    ))?;
-    assert_eq!(expected_1, response[0]);
-    let expected_2: Value = serde_json::from_str(&format!(
-      r#"{{"id":"{}","title":"test title 2","messages":[]}}"#,
-      convo_2.id
-    ))?;
-    assert_eq!(expected_2, response[1]);
+    assert_eq!(StatusCode::OK, response.status());
+    let results = response.json::<Vec<ChatSearchResult>>().await?;
+    assert_eq!(1, results.len());
+    assert_eq!(convo.id, results[0].id);
+    assert_eq!("Days of the week", results[0].title);
+    assert_eq!(2, results[0].matches.len());
+    assert!(results[0]
+      .matches
+      .iter()
+      .all(|item| item.snippet.contains("<mark>Monday</mark>")));
     Ok(())
   }
 
   #[rstest]
+  #[case("/chats/search")]
+  #[case("/chats/search?q=%20")]
+  #[case("/chats/search?q=monday&limit=0")]
+  #[case("/chats/search?q=monday&limit=201")]
   #[awt]
   #[tokio::test]
-  async fn test_chat_routes_get(
+  async fn test_chat_routes_search_invalid(
     #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+    #[case] uri: &str,
   ) -> anyhow::Result<()> {
     let (_temp, _now, db_service) = db_service;
-    let mut convo = ConversationBuilder::default().title("test title").build()?;
-    let message_1 = MessageBuilder::default()
-      .conversation_id(&convo.id)
-      .role("user")
-      .content("test content")
-      .build()?;
-    let message_2 = MessageBuilder::default()
-      .conversation_id(&convo.id)
-      .role("assistant")
-      .content("test reply")
+    let router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(MockAppServiceFn::new()),
+      Arc::new(db_service),
+    );
+    let router = chats_router().with_state(Arc::new(router_state));
+    let response = router
+      .oneshot(Request::get(uri).body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("", "text/markdown; charset=utf-8", "Daysoftheweek.md")]
+  #[case("?format=markdown", "text/markdown; charset=utf-8", "Daysoftheweek.md")]
+  #[case("?format=json", "application/json", "Daysoftheweek.json")]
+  #[awt]
+  #[tokio::test]
+  async fn test_chat_routes_export(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+    #[case] query: &str,
+    #[case] content_type: &str,
+    #[case] filename: &str,
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let mut convo = ConversationBuilder::default()
+      .title("Days of the week")
+      .messages(vec![MessageBuilder::default()
+        .role("user")
+        .content("What day comes after Monday?")
+        .build()?])
       .build()?;
-    convo.messages.push(message_1);
-    convo.messages.push(message_2);
     db_service.save_conversation(&mut convo).await?;
+    let mut env_service = MockEnvServiceFn::new();
+    env_service
+      .expect_timezone()
+      .return_const(DisplayTimezone::Named(chrono_tz::Asia::Kolkata));
+    let app_service =
+      AppServiceStubMock::new(env_service, MockHubService::new(), MockDataService::new());
     let router_state = RouterState::new(
       Arc::new(MockSharedContext::new()),
-      Arc::new(MockAppServiceFn::new()),
+      Arc::new(app_service),
       Arc::new(db_service),
     );
     let router = chats_router().with_state(Arc::new(router_state));
     let response = router
-      .oneshot(
-        Request::get(&format!("/chats/{}", &convo.id))
-          .body(Body::empty())
-          .unwrap(),
-      )
-      .await?
-      .json::<Value>()
+      .oneshot(Request::get(format!("/chats/{}/export{query}", convo.id)).body(Body::empty())?)
       .await?;
-    let expected = format!(
-      r#"{{"id":"{}","title":"test title","messages":[{{"role":"user","content":"test content"}},{{"role":"assistant","content":"test reply"}}]}}"#,
-      convo.id
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(content_type, response.headers()[CONTENT_TYPE].to_str()?);
+    assert_eq!(
+      format!("attachment; filename=\"{filename}\""),
+      response.headers()[CONTENT_DISPOSITION].to_str()?
     );
-    let expected = serde_json::from_str::<Value>(&expected)?;
-    assert_eq!(expected, response);
+    let text = response.text().await?;
+    assert!(text.contains("What day comes after Monday?"));
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("/chats/unknown/export", StatusCode::NOT_FOUND)]
+  #[case("/chats/unknown/export?format=pdf", StatusCode::BAD_REQUEST)]
+  #[awt]
+  #[tokio::test]
+  async fn test_chat_routes_export_invalid(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+    #[case] uri: &str,
+    #[case] expected: StatusCode,
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(MockAppServiceFn::new()),
+      Arc::new(db_service),
+    );
+    let router = chats_router().with_state(Arc::new(router_state));
+    let response = router
+      .oneshot(Request::get(uri).body(Body::empty())?)
+      .await?;
+    assert_eq!(expected, response.status());
     Ok(())
   }
 
@@ -257,7 +1374,9 @@ mod test {
       .oneshot(Request::delete("/chats").body(Body::empty()).unwrap())
       .await?;
     assert_eq!(StatusCode::OK, response.status());
-    let convos = db_service.list_conversations().await?;
+    let convos = db_service
+      .list_conversations(&ConversationsQuery::default())
+      .await?;
     assert!(convos.is_empty());
     Ok(())
   }
@@ -285,8 +1404,9 @@ mod test {
       .await?;
     assert_eq!(StatusCode::NOT_FOUND, response.status());
     let err = response.json::<Value>().await?;
-    let expected =
-      serde_json::from_str::<Value>(r#"{"error":"given record not found in conversations"}"#)?;
+    let expected = serde_json::from_str::<Value>(
+      r#"{"error":{"message":"given record not found in conversations","type":"invalid_request_error","param":null,"code":"not_found"}}"#,
+    )?;
     assert_eq!(expected, err);
     Ok(())
   }
@@ -414,4 +1534,321 @@ mod test {
     );
     Ok(())
   }
+
+  async fn saved_conversation(db_service: &DbService) -> anyhow::Result<Conversation> {
+    let mut convo = ConversationBuilder::default()
+      .title("test title")
+      .messages(vec![
+        MessageBuilder::default()
+          .role("user")
+          .content("What day comes after Monday?")
+          .build()?,
+        MessageBuilder::default()
+          .role("assistant")
+          .content("Tuesday")
+          .build()?,
+      ])
+      .build()?;
+    db_service.save_conversation(&mut convo).await?;
+    Ok(convo)
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_chat_routes_fork(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let convo = saved_conversation(&db_service).await?;
+    let router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(MockAppServiceFn::new()),
+      Arc::new(db_service),
+    );
+    let router = chats_router().with_state(Arc::new(router_state));
+    let response = router
+      .clone()
+      .oneshot(
+        Request::post(&format!("/chats/{}/fork", convo.id))
+          .json(json! {{"message_id": convo.messages[0].id}})?,
+      )
+      .await?;
+    assert_eq!(StatusCode::CREATED, response.status());
+    let fork = response.json::<Conversation>().await?;
+    assert_ne!(convo.id, fork.id);
+    let response = router
+      .clone()
+      .oneshot(Request::get(&format!("/chats/{}", fork.id)).body(Body::empty())?)
+      .await?
+      .json::<Value>()
+      .await?;
+    assert_eq!(
+      json! {[{"id": fork.messages[0].id, "role": "user", "content": "What day comes after Monday?"}]},
+      response["messages"]
+    );
+
+    let response = router
+      .oneshot(
+        Request::post(&format!("/chats/{}/fork", convo.id))
+          .json(json! {{"message_id": "unknown"}})?,
+      )
+      .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_chat_routes_edit_message(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let convo = saved_conversation(&db_service).await?;
+    let router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(MockAppServiceFn::new()),
+      Arc::new(db_service),
+    );
+    let router = chats_router().with_state(Arc::new(router_state));
+    let response = router
+      .oneshot(
+        Request::patch(&format!(
+          "/chats/{}/messages/{}",
+          convo.id, convo.messages[0].id
+        ))
+        .json(json! {{"content": "What day comes after Tuesday?"}})?,
+      )
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response = response.json::<Conversation>().await?;
+    assert_eq!(1, response.messages.len());
+    let message = response.messages.first().unwrap();
+    assert_ne!(convo.messages[0].id, message.id);
+    assert_eq!("user", message.role);
+    assert_eq!(
+      Some("What day comes after Tuesday?".to_string()),
+      message.content
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_chat_routes_edit_message_regenerate(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let convo = saved_conversation(&db_service).await?;
+    let db_service: Arc<dyn DbServiceFn> = Arc::new(db_service);
+    let mut router_state = MockRouterState::new();
+    let db_clone = db_service.clone();
+    router_state
+      .expect_db_service()
+      .returning(move || db_clone.clone());
+    router_state
+      .expect_chat_completions()
+      .withf(|request, _| {
+        request.model == "testalias:instruct"
+          && request.messages.len() == 1
+          && request.stream.is_none()
+      })
+      .return_once(|_, sender: Sender<String>| {
+        let response = json! {{
+          "id": "testid",
+          "model": "testalias:instruct",
+          "choices": [{
+            "index": 0,
+            "message": {"role": "assistant", "content": "Wednesday"},
+          }],
+          "created": 1704067200,
+          "object": "chat.completion",
+          "usage": {"prompt_tokens": 12, "completion_tokens": 3, "total_tokens": 15},
+        }}
+        .to_string();
+        tokio::spawn(async move { sender.send(response).await });
+        Ok(())
+      });
+    let router = chats_router().with_state(Arc::new(router_state));
+    let response = router
+      .oneshot(
+        Request::patch(&format!(
+          "/chats/{}/messages/{}",
+          convo.id, convo.messages[0].id
+        ))
+        .json(json! {{
+          "content": "What day comes after Tuesday?",
+          "regenerate": true,
+          "model": "testalias:instruct",
+        }})?,
+      )
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response = response.json::<Conversation>().await?;
+    let contents = response
+      .messages
+      .iter()
+      .map(|message| (message.role.as_str(), message.content.as_deref()))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      vec![
+        ("user", Some("What day comes after Tuesday?")),
+        ("assistant", Some("Wednesday"))
+      ],
+      contents
+    );
+    let from_db = db_service.get_conversation_with_messages(&convo.id).await?;
+    let ids = |messages: &[Message]| messages.iter().map(|m| m.id.clone()).collect::<Vec<_>>();
+    assert_eq!(ids(&response.messages), ids(&from_db.messages));
+    let reply = &from_db.messages[1];
+    assert_eq!(
+      (Some("testalias:instruct"), Some(12), Some(3)),
+      (
+        reply.model.as_deref(),
+        reply.prompt_tokens,
+        reply.completion_tokens
+      )
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(1, json! {{"content": "updated"}}, StatusCode::BAD_REQUEST)]
+  #[case(0, json! {{"content": "updated", "regenerate": true}}, StatusCode::BAD_REQUEST)]
+  #[awt]
+  #[tokio::test]
+  async fn test_chat_routes_edit_message_invalid(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+    #[case] index: usize,
+    #[case] input: Value,
+    #[case] status: StatusCode,
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let convo = saved_conversation(&db_service).await?;
+    let router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(MockAppServiceFn::new()),
+      Arc::new(db_service),
+    );
+    let router = chats_router().with_state(Arc::new(router_state));
+    let response = router
+      .oneshot(
+        Request::patch(&format!(
+          "/chats/{}/messages/{}",
+          convo.id, convo.messages[index].id
+        ))
+        .json(input)?,
+      )
+      .await?;
+    assert_eq!(status, response.status());
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_chat_routes_regenerate_reply_with_model_of_reply(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let mut convo = ConversationBuilder::default()
+      .title("test title")
+      .messages(vec![
+        MessageBuilder::default()
+          .role("user")
+          .content("What day comes after Monday?")
+          .build()?,
+        MessageBuilder::default()
+          .role("assistant")
+          .content("Tuesday")
+          .model("testalias:instruct")
+          .build()?,
+      ])
+      .build()?;
+    db_service.save_conversation(&mut convo).await?;
+    let db_service: Arc<dyn DbServiceFn> = Arc::new(db_service);
+    let mut router_state = MockRouterState::new();
+    let db_clone = db_service.clone();
+    router_state
+      .expect_db_service()
+      .returning(move || db_clone.clone());
+    router_state
+      .expect_chat_completions()
+      .withf(|request, _| request.model == "testalias:instruct" && request.messages.len() == 1)
+      .return_once(|_, sender: Sender<String>| {
+        let response = json! {{
+          "id": "testid",
+          "model": "testalias:instruct",
+          "choices": [{
+            "index": 0,
+            "message": {"role": "assistant", "content": "Tuesday, it is."},
+          }],
+          "created": 1704067200,
+          "object": "chat.completion",
+        }}
+        .to_string();
+        tokio::spawn(async move { sender.send(response).await });
+        Ok(())
+      });
+    let router = chats_router().with_state(Arc::new(router_state));
+    let response = router
+      .oneshot(
+        Request::post(&format!(
+          "/chats/{}/messages/{}/regenerate",
+          convo.id, convo.messages[1].id
+        ))
+        .body(Body::empty())?,
+      )
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response = response.json::<Conversation>().await?;
+    let contents = response
+      .messages
+      .iter()
+      .map(|message| (message.role.as_str(), message.content.as_deref()))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      vec![
+        ("user", Some("What day comes after Monday?")),
+        ("assistant", Some("Tuesday, it is."))
+      ],
+      contents
+    );
+    assert_eq!(convo.messages[0].id, response.messages[0].id);
+    assert_ne!(convo.messages[1].id, response.messages[1].id);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Some(0), StatusCode::BAD_REQUEST)]
+  #[case(Some(1), StatusCode::BAD_REQUEST)]
+  #[case(None, StatusCode::NOT_FOUND)]
+  #[awt]
+  #[tokio::test]
+  async fn test_chat_routes_regenerate_reply_invalid(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+    #[case] index: Option<usize>,
+    #[case] status: StatusCode,
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let convo = saved_conversation(&db_service).await?;
+    let router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(MockAppServiceFn::new()),
+      Arc::new(db_service),
+    );
+    // the saved replies do not have the model, and no model is passed
+    let msg_id = index.map_or("unknown", |index| convo.messages[index].id.as_str());
+    let router = chats_router().with_state(Arc::new(router_state));
+    let response = router
+      .oneshot(
+        Request::post(&format!("/chats/{}/messages/{msg_id}/regenerate", convo.id))
+          .body(Body::empty())?,
+      )
+      .await?;
+    assert_eq!(status, response.status());
+    Ok(())
+  }
 }
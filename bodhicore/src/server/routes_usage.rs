@@ -0,0 +1,315 @@
+use super::{routes_ui::chat_owner, RouterStateFn};
+use crate::{
+  db::objs::{ApiKey, ClientUsage, ConversationUsage, UsagePeriod, UsageStats},
+  oai::OpenAIApiError,
+};
+use axum::{
+  extract::{Query, State},
+  response::Json,
+  routing::get,
+  Extension, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::sync::Arc;
+
+const DEFAULT_USAGE_LIMIT: u32 = 10;
+const MAX_USAGE_LIMIT: u32 = 100;
+
+pub fn usage_router() -> Router<Arc<dyn RouterStateFn>> {
+  Router::new()
+    .route("/usage", get(ui_usage_handler))
+    .route("/usage/clients", get(ui_usage_clients_handler))
+    .route("/usage/stats", get(ui_usage_stats_handler))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct UsageQuery {
+  /// only the usage since the given time, in milliseconds since epoch
+  pub since: Option<i64>,
+  pub limit: Option<u32>,
+  /// period to aggregate the usage stats by, defaults to day
+  pub period: Option<UsagePeriod>,
+}
+
+impl UsageQuery {
+  fn since(&self) -> Result<Option<DateTime<Utc>>, OpenAIApiError> {
+    match self.since {
+      Some(since) => DateTime::<Utc>::from_timestamp_millis(since)
+        .map(Some)
+        .ok_or_else(|| OpenAIApiError::BadRequest(format!("invalid value '{since}' for 'since'"))),
+      None => Ok(None),
+    }
+  }
+
+  fn limit(&self) -> u32 {
+    self
+      .limit
+      .unwrap_or(DEFAULT_USAGE_LIMIT)
+      .clamp(1, MAX_USAGE_LIMIT)
+  }
+}
+
+/// Token usage of the conversations, only of the chats of the API key's owner with `BODHI_AUTH`
+/// enabled
+async fn ui_usage_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  api_key: Option<Extension<ApiKey>>,
+  Query(query): Query<UsageQuery>,
+) -> Result<Json<Vec<ConversationUsage>>, OpenAIApiError> {
+  let owner = chat_owner(api_key);
+  let conversations = state
+    .db_service()
+    .list_conversation_usage(query.since()?, owner.as_deref(), query.limit())
+    .await?;
+  Ok(Json(conversations))
+}
+
+async fn ui_usage_clients_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  Query(query): Query<UsageQuery>,
+) -> Result<Json<Vec<ClientUsage>>, OpenAIApiError> {
+  let clients = state
+    .db_service()
+    .list_client_usage(query.since()?, query.limit())
+    .await?;
+  Ok(Json(clients))
+}
+
+async fn ui_usage_stats_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  Query(query): Query<UsageQuery>,
+) -> Result<Json<Vec<UsageStats>>, OpenAIApiError> {
+  let stats = state
+    .db_service()
+    .list_usage_stats(
+      query.period.unwrap_or_default(),
+      query.since()?,
+      query.limit(),
+    )
+    .await?;
+  Ok(Json(stats))
+}
+
+#[cfg(test)]
+mod test {
+  use super::usage_router;
+  use crate::{
+    db::{
+      objs::{ApiKey, ConversationBuilder, MessageBuilder, UsageRecord},
+      DbService, DbServiceFn,
+    },
+    server::RouterState,
+    service::MockAppServiceFn,
+    test_utils::{db_service, MockSharedContext, ResponseTestExt},
+  };
+  use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Extension,
+  };
+  use chrono::{DateTime, Duration, Utc};
+  use rstest::rstest;
+  use serde_json::{json, Value};
+  use std::sync::Arc;
+  use tempfile::TempDir;
+  use tower::ServiceExt;
+
+  #[rstest]
+  #[case("/usage/clients", json! {[
+    {"userAgent": "curl/8.4.0", "appName": null, "requests": 2, "promptTokens": 20, "completionTokens": 4},
+    {"userAgent": "python-requests/2.31", "appName": "notes", "requests": 1, "promptTokens": 10, "completionTokens": 2},
+  ]})]
+  #[case("/usage/clients?limit=1", json! {[
+    {"userAgent": "curl/8.4.0", "appName": null, "requests": 2, "promptTokens": 20, "completionTokens": 4},
+  ]})]
+  #[awt]
+  #[tokio::test]
+  async fn test_usage_routes_clients(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+    #[case] path: &str,
+    #[case] expected: Value,
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    for (user_agent, app_name) in [
+      ("curl/8.4.0", None),
+      ("python-requests/2.31", Some("notes")),
+      ("curl/8.4.0", None),
+    ] {
+      let mut record = UsageRecord {
+        model: "testalias:instruct".to_string(),
+        user_agent: Some(user_agent.to_string()),
+        app_name: app_name.map(str::to_string),
+        prompt_tokens: 10,
+        completion_tokens: 2,
+        ..Default::default()
+      };
+      db_service.save_usage(&mut record).await?;
+    }
+    let router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(MockAppServiceFn::new()),
+      Arc::new(db_service),
+    );
+    let response = usage_router()
+      .with_state(Arc::new(router_state))
+      .oneshot(Request::get(path).body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(expected, response.json::<Value>().await?);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("/usage/stats", StatusCode::OK)]
+  #[case("/usage/stats?period=month", StatusCode::OK)]
+  #[case("/usage/stats?period=fortnight", StatusCode::BAD_REQUEST)]
+  #[awt]
+  #[tokio::test]
+  async fn test_usage_routes_stats(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+    #[case] path: &str,
+    #[case] status: StatusCode,
+  ) -> anyhow::Result<()> {
+    let (_temp, now, db_service) = db_service;
+    let mut record = UsageRecord {
+      model: "testalias:instruct".to_string(),
+      app_name: Some("notes".to_string()),
+      prompt_tokens: 10,
+      completion_tokens: 2,
+      latency_ms: Some(120),
+      ..Default::default()
+    };
+    db_service.save_usage(&mut record).await?;
+    let router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(MockAppServiceFn::new()),
+      Arc::new(db_service),
+    );
+    let response = usage_router()
+      .with_state(Arc::new(router_state))
+      .oneshot(Request::get(path).body(Body::empty())?)
+      .await?;
+    assert_eq!(status, response.status());
+    if status == StatusCode::OK {
+      let period = if path.ends_with("month") {
+        now.format("%Y-%m").to_string()
+      } else {
+        now.format("%Y-%m-%d").to_string()
+      };
+      assert_eq!(
+        json! {[{
+          "period": period,
+          "model": "testalias:instruct",
+          "client": "notes",
+          "requests": 1,
+          "promptTokens": 10,
+          "completionTokens": 2,
+          "avgLatencyMs": 120,
+        }]},
+        response.json::<Value>().await?
+      );
+    }
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_usage_routes_clients_since(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_temp, now, db_service) = db_service;
+    let mut record = UsageRecord {
+      model: "testalias:instruct".to_string(),
+      ..Default::default()
+    };
+    db_service.save_usage(&mut record).await?;
+    let router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(MockAppServiceFn::new()),
+      Arc::new(db_service),
+    );
+    let since = (now + Duration::hours(1)).timestamp_millis();
+    let response = usage_router()
+      .with_state(Arc::new(router_state))
+      .oneshot(Request::get(format!("/usage/clients?since={since}")).body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(json! {[]}, response.json::<Value>().await?);
+    Ok(())
+  }
+
+  #[rstest]
+  #[awt]
+  #[tokio::test]
+  async fn test_usage_routes_conversations(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let mut ids = vec![];
+    for (title, owner, replies) in [
+      ("Days of the week", "alice", vec![(10, 2), (20, 4)]),
+      ("Months", "bob", vec![(5, 1)]),
+    ] {
+      let mut messages = vec![MessageBuilder::default()
+        .role("user")
+        .content("What day comes after Monday?")
+        .build()?];
+      for (prompt_tokens, completion_tokens) in replies {
+        messages.push(
+          MessageBuilder::default()
+            .role("assistant")
+            .content("Tuesday")
+            .model("testalias:instruct")
+            .prompt_tokens(prompt_tokens)
+            .completion_tokens(completion_tokens)
+            .build()?,
+        );
+      }
+      let mut convo = ConversationBuilder::default()
+        .title(title)
+        .owner(owner)
+        .messages(messages)
+        .build()?;
+      db_service.save_conversation(&mut convo).await?;
+      ids.push(convo.id);
+    }
+    let router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(MockAppServiceFn::new()),
+      Arc::new(db_service),
+    );
+    let router_state = Arc::new(router_state);
+    let response = usage_router()
+      .with_state(router_state.clone())
+      .oneshot(Request::get("/usage").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(
+      json! {[
+        {"conversationId": ids[0], "title": "Days of the week", "replies": 2, "promptTokens": 30, "completionTokens": 6},
+        {"conversationId": ids[1], "title": "Months", "replies": 1, "promptTokens": 5, "completionTokens": 1},
+      ]},
+      response.json::<Value>().await?
+    );
+    // the auth middleware adds the API key of the request
+    let api_key = ApiKey {
+      name: "bob".to_string(),
+      ..Default::default()
+    };
+    let response = usage_router()
+      .layer(Extension(api_key))
+      .with_state(router_state)
+      .oneshot(Request::get("/usage").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(
+      json! {[
+        {"conversationId": ids[1], "title": "Months", "replies": 1, "promptTokens": 5, "completionTokens": 1},
+      ]},
+      response.json::<Value>().await?
+    );
+    Ok(())
+  }
+}
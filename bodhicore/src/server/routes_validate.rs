@@ -0,0 +1,189 @@
+use super::RouterStateFn;
+use crate::{
+  oai::OpenAIApiError,
+  tokenizer_config::{ChatMessage, ChatTemplateVersions, TemplateDiagnostic, TokenizerConfig},
+};
+use axum::{extract::State, response::Json, routing::post, Router};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+pub fn validate_router() -> Router<Arc<dyn RouterStateFn>> {
+  Router::new().route("/validate/template", post(ui_validate_template_handler))
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ValidateTemplateRequest {
+  pub chat_template: String,
+  pub bos_token: Option<String>,
+  pub eos_token: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConversationResult {
+  pub conversation: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub output: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub error: Option<TemplateDiagnostic>,
+}
+
+/// The template is valid if it renders all the canned conversations
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValidateTemplateResponse {
+  pub valid: bool,
+  pub results: Vec<ConversationResult>,
+}
+
+fn canned_conversations() -> Vec<(&'static str, Vec<ChatMessage>)> {
+  let message =
+    |role: &str, content: &str| ChatMessage::new(Some(role.to_string()), Some(content.to_string()));
+  vec![
+    (
+      "single_turn",
+      vec![message("user", "What day comes after Monday?")],
+    ),
+    (
+      "system_prompt",
+      vec![
+        message("system", "You are a helpful assistant."),
+        message("user", "What day comes after Monday?"),
+      ],
+    ),
+    (
+      "multi_turn",
+      vec![
+        message("user", "What day comes after Monday?"),
+        message("assistant", "Tuesday"),
+        message("user", "And after that?"),
+      ],
+    ),
+  ]
+}
+
+async fn ui_validate_template_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  Json(request): Json<ValidateTemplateRequest>,
+) -> Result<Json<ValidateTemplateResponse>, OpenAIApiError> {
+  if request.chat_template.trim().is_empty() {
+    return Err(OpenAIApiError::BadRequest(
+      "chat_template should not be empty".to_string(),
+    ));
+  }
+  let limits = state.app_service().env_service().template_limits();
+  let config = TokenizerConfig::new(
+    ChatTemplateVersions::Single(request.chat_template),
+    request.bos_token,
+    request.eos_token,
+  );
+  let results = tokio::task::spawn_blocking(move || {
+    canned_conversations()
+      .into_iter()
+      .map(
+        |(conversation, messages)| match config.render_chat_template(&messages, &limits) {
+          Ok(output) => ConversationResult {
+            conversation: conversation.to_string(),
+            output: Some(output),
+            error: None,
+          },
+          Err(err) => ConversationResult {
+            conversation: conversation.to_string(),
+            output: None,
+            error: Some(TemplateDiagnostic::from(&err)),
+          },
+        },
+      )
+      .collect::<Vec<_>>()
+  })
+  .await
+  .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))?;
+  Ok(Json(ValidateTemplateResponse {
+    valid: results.iter().all(|result| result.error.is_none()),
+    results,
+  }))
+}
+
+#[cfg(test)]
+mod test {
+  use super::{validate_router, ValidateTemplateResponse};
+  use crate::{
+    service::{MockDataService, MockEnvServiceFn, MockHubService, TemplateLimits},
+    test_utils::{AppServiceStubMock, MockRouterState, RequestTestExt, ResponseTestExt},
+  };
+  use axum::{
+    http::{Request, StatusCode},
+    Router,
+  };
+  use rstest::rstest;
+  use serde_json::{json, Value};
+  use std::sync::Arc;
+  use tower::ServiceExt;
+
+  fn router() -> Router {
+    let mut env_service = MockEnvServiceFn::new();
+    env_service
+      .expect_template_limits()
+      .return_const(TemplateLimits::default());
+    let app_service = Arc::new(AppServiceStubMock::new(
+      env_service,
+      MockHubService::new(),
+      MockDataService::new(),
+    ));
+    let mut router_state = MockRouterState::new();
+    router_state
+      .expect_app_service()
+      .returning(move || app_service.clone());
+    validate_router().with_state(Arc::new(router_state))
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_validate_routes_template_renders_conversations() -> anyhow::Result<()> {
+    let response = router()
+      .oneshot(Request::post("/validate/template").json(json! {{
+        "chat_template": "{{ bos_token }}{% for message in messages %}{{ message['role'] }}: {{ message['content'] }}\n{% endfor %}",
+        "bos_token": "<s>"
+      }})?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response = response.json::<ValidateTemplateResponse>().await?;
+    assert!(response.valid);
+    assert_eq!(3, response.results.len());
+    assert_eq!(
+      Some("<s>user: What day comes after Monday?\n".to_string()),
+      response.results[0].output
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_validate_routes_template_returns_diagnostics() -> anyhow::Result<()> {
+    let response = router()
+      .oneshot(Request::post("/validate/template").json(json! {{
+        "chat_template": "{% for message in messages %}\n{{ message['content'] }\n{% endfor %}"
+      }})?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response = response.json::<Value>().await?;
+    assert_eq!(json! {false}, response["valid"]);
+    let error = &response["results"][0]["error"];
+    assert_eq!(json! {"syntax_error"}, error["kind"]);
+    assert_eq!(json! {2}, error["line"]);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_validate_routes_template_empty() -> anyhow::Result<()> {
+    let response = router()
+      .oneshot(Request::post("/validate/template").json(json! {{"chat_template": " "}})?)
+      .await?;
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    assert_eq!(
+      json! {"chat_template should not be empty"},
+      response.json::<Value>().await?["error"]["message"]
+    );
+    Ok(())
+  }
+}
@@ -1,5 +1,7 @@
-use crate::error::Common;
+use crate::{error::Common, service::DEFAULT_DRAIN_TIMEOUT_SECS};
 use axum::Router;
+use futures_util::future::BoxFuture;
+use std::{future::IntoFuture, io, path::PathBuf, time::Duration};
 use tokio::{
   net::TcpListener,
   sync::oneshot::{self, Receiver, Sender},
@@ -12,8 +14,20 @@ use tokio::{
 pub struct Server {
   host: String,
   port: u16,
-  ready: Sender<()>,
+  port_range: u16,
+  ready: Sender<u16>,
   shutdown_rx: Receiver<()>,
+  drain_timeout: Duration,
+  uds: Option<PathBuf>,
+  activated: Option<ActivatedListener>,
+}
+
+/// Listening socket passed in by the service manager, e.g. with systemd socket activation
+#[derive(Debug)]
+pub enum ActivatedListener {
+  Tcp(std::net::TcpListener),
+  #[cfg(unix)]
+  Unix(std::os::unix::net::UnixListener),
 }
 
 #[async_trait::async_trait]
@@ -22,15 +36,16 @@ pub trait ShutdownCallback: Send + Sync {
 }
 
 /// ServerHandle encapuslates the handles to start, listen to when server is ready, and request shutdown for a running server
+/// The ready receiver gets the port the server is listening on
 pub struct ServerHandle {
   pub server: Server,
   pub shutdown: oneshot::Sender<()>,
-  pub ready_rx: oneshot::Receiver<()>,
+  pub ready_rx: oneshot::Receiver<u16>,
 }
 
 pub fn build_server_handle(host: &str, port: u16) -> ServerHandle {
   let (shutdown, shutdown_rx) = oneshot::channel::<()>();
-  let (ready, ready_rx) = oneshot::channel::<()>();
+  let (ready, ready_rx) = oneshot::channel::<u16>();
   let server = Server::new(host, port, ready, shutdown_rx);
   ServerHandle {
     server,
@@ -40,15 +55,43 @@ pub fn build_server_handle(host: &str, port: u16) -> ServerHandle {
 }
 
 impl Server {
-  fn new(host: &str, port: u16, ready: Sender<()>, shutdown_rx: Receiver<()>) -> Self {
+  fn new(host: &str, port: u16, ready: Sender<u16>, shutdown_rx: Receiver<()>) -> Self {
     Self {
       host: host.to_string(),
       port,
+      port_range: 0,
       ready,
       shutdown_rx,
+      drain_timeout: Duration::from_secs(DEFAULT_DRAIN_TIMEOUT_SECS),
+      uds: None,
+      activated: None,
     }
   }
 
+  /// Time to let the in-flight requests finish on shutdown, before closing their connections
+  pub fn with_drain_timeout(mut self, drain_timeout: Duration) -> Self {
+    self.drain_timeout = drain_timeout;
+    self
+  }
+
+  /// When the port is in use, try up to `port_range` subsequent ports
+  pub fn with_port_range(mut self, port_range: u16) -> Self {
+    self.port_range = port_range;
+    self
+  }
+
+  /// Listen on the unix domain socket at the given path, instead of the host and port
+  pub fn with_uds(mut self, uds: Option<PathBuf>) -> Self {
+    self.uds = uds;
+    self
+  }
+
+  /// Serve on the already listening socket, instead of binding the host and port or uds
+  pub fn with_activated_listener(mut self, activated: Option<ActivatedListener>) -> Self {
+    self.activated = activated;
+    self
+  }
+
   pub async fn start_new(
     self,
     app: Router,
@@ -57,13 +100,17 @@ impl Server {
     let Server {
       host,
       port,
+      port_range,
       ready,
       shutdown_rx,
+      drain_timeout,
+      uds,
+      activated,
     } = self;
-    let addr = format!("{}:{}", host, port);
-    let listener = TcpListener::bind(&addr).await.map_err(Common::Io)?;
-    tracing::info!(addr = addr, "server started");
-    let axum_server = axum::serve(listener, app).with_graceful_shutdown(async move {
+    let uds = if activated.is_some() { None } else { uds };
+    let (draining, draining_rx) = oneshot::channel::<()>();
+    // on shutdown, stops accepting new connections, and waits for the in-flight requests
+    let signal = async move {
       match shutdown_rx.await {
         Ok(()) => {
           tracing::info!("received signal to shutdown the server");
@@ -75,25 +122,107 @@ impl Server {
           );
         }
       };
-      if let Some(callback) = callback {
-        (*callback).shutdown().await;
+      _ = draining.send(());
+    };
+    let mut bound_port = port;
+    let mut axum_server: BoxFuture<'static, io::Result<()>> = match (activated, &uds) {
+      (Some(ActivatedListener::Tcp(listener)), _) => {
+        listener.set_nonblocking(true).map_err(Common::Io)?;
+        let listener = TcpListener::from_std(listener).map_err(Common::Io)?;
+        bound_port = listener.local_addr().map_err(Common::Io)?.port();
+        tracing::info!("server started on the socket passed by the service manager");
+        Box::pin(
+          axum::serve(listener, app)
+            .with_graceful_shutdown(signal)
+            .into_future(),
+        )
+      }
+      #[cfg(unix)]
+      (Some(ActivatedListener::Unix(listener)), _) => {
+        listener.set_nonblocking(true).map_err(Common::Io)?;
+        let listener = tokio::net::UnixListener::from_std(listener).map_err(Common::Io)?;
+        tracing::info!("server started on the socket passed by the service manager");
+        Box::pin(super::uds::serve_uds(listener, app, signal))
+      }
+      #[cfg(unix)]
+      (None, Some(path)) => {
+        let listener = super::uds::bind_uds(path).map_err(|source| Common::IoFile {
+          source,
+          path: path.display().to_string(),
+        })?;
+        tracing::info!(uds = %path.display(), "server started");
+        Box::pin(super::uds::serve_uds(listener, app, signal))
+      }
+      #[cfg(not(unix))]
+      (None, Some(path)) => {
+        let message = format!("unix domain sockets are not supported: {}", path.display());
+        return Err(Common::Io(io::Error::new(io::ErrorKind::Unsupported, message)).into());
       }
-    });
-    if ready.send(()).is_err() {
+      (None, None) => {
+        let listener = bind_tcp(&host, port, port_range).await?;
+        bound_port = listener.local_addr().map_err(Common::Io)?.port();
+        tracing::info!(addr = format!("{host}:{bound_port}"), "server started");
+        Box::pin(
+          axum::serve(listener, app)
+            .with_graceful_shutdown(signal)
+            .into_future(),
+        )
+      }
+    };
+    if ready.send(bound_port).is_err() {
       tracing::warn!("ready receiver dropped before start signal notified")
     };
-    axum_server.await.map_err(Common::Io)?;
+    let result = tokio::select! {
+      result = &mut axum_server => result,
+      Ok(()) = draining_rx => {
+        tracing::info!(?drain_timeout, "waiting for the in-flight requests to finish");
+        match tokio::time::timeout(drain_timeout, &mut axum_server).await {
+          Ok(result) => result,
+          Err(_) => {
+            tracing::warn!("in-flight requests did not finish within the drain timeout");
+            Ok(())
+          }
+        }
+      }
+    };
+    // the llama context is stopped only after the in-flight generations are done
+    if let Some(callback) = callback {
+      (*callback).shutdown().await;
+    }
+    if let Some(path) = uds {
+      _ = std::fs::remove_file(path);
+    }
+    result.map_err(Common::Io)?;
     Ok(())
   }
 }
 
+// binds the first port not in use, from the port to the port + port_range
+async fn bind_tcp(host: &str, port: u16, port_range: u16) -> crate::error::Result<TcpListener> {
+  let last = port.saturating_add(port_range);
+  let mut candidate = port;
+  loop {
+    match TcpListener::bind(format!("{host}:{candidate}")).await {
+      Ok(listener) => return Ok(listener),
+      Err(err) if err.kind() == io::ErrorKind::AddrInUse && candidate < last => {
+        tracing::info!(port = candidate, "port in use, trying the next port");
+        candidate += 1;
+      }
+      Err(err) => return Err(Common::Io(err).into()),
+    }
+  }
+}
+
 #[cfg(test)]
 mod test {
-  use super::{build_server_handle, ServerHandle, ShutdownCallback};
+  use super::{build_server_handle, ActivatedListener, ServerHandle, ShutdownCallback};
   use anyhow::anyhow;
   use axum::{routing::get, Router};
   use reqwest::StatusCode;
-  use std::sync::{Arc, Mutex};
+  use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+  };
 
   struct ShutdownTestCallback {
     callback: Arc<Mutex<bool>>,
@@ -143,4 +272,152 @@ mod test {
     assert!(response.is_err());
     Ok(())
   }
+
+  #[tokio::test]
+  pub async fn test_server_shutdown_drains_in_flight_requests() -> anyhow::Result<()> {
+    let host = "localhost".to_string();
+    let port = rand::random::<u16>() % 65535;
+    let ServerHandle {
+      server,
+      shutdown,
+      ready_rx,
+    } = build_server_handle(&host, port);
+    let app = Router::new().route(
+      "/slow",
+      get(|| async {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        "done"
+      }),
+    );
+    let callback_received = Arc::new(Mutex::new(false));
+    let callback = ShutdownTestCallback {
+      callback: callback_received.clone(),
+    };
+    let join_handle = tokio::spawn(
+      server
+        .with_drain_timeout(Duration::from_secs(5))
+        .start_new(app, Some(Box::new(callback))),
+    );
+    ready_rx.await?;
+    let request = tokio::spawn(
+      reqwest::Client::new()
+        .get(format!("http://{host}:{port}/slow"))
+        .send(),
+    );
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    shutdown
+      .send(())
+      .map_err(|_| anyhow!("shutdown send failed"))?;
+    let response = request.await??;
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!("done", response.text().await?);
+    (join_handle.await?)?;
+    assert!(*callback_received.lock().unwrap());
+    Ok(())
+  }
+
+  #[cfg(unix)]
+  #[tokio::test]
+  pub async fn test_server_start_stop_on_uds() -> anyhow::Result<()> {
+    use tokio::{
+      io::{AsyncReadExt, AsyncWriteExt},
+      net::UnixStream,
+    };
+
+    let temp_dir = tempfile::TempDir::new()?;
+    let uds = temp_dir.path().join("bodhi.sock");
+    let ServerHandle {
+      server,
+      shutdown,
+      ready_rx,
+    } = build_server_handle("localhost", 0);
+    let app = Router::new().route("/ping", get(|| async { (StatusCode::OK, "pong") }));
+    let join_handle = tokio::spawn(server.with_uds(Some(uds.clone())).start_new(app, None));
+    ready_rx.await?;
+    let mut stream = UnixStream::connect(&uds).await?;
+    stream
+      .write_all(b"GET /ping HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+      .await?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+    assert!(response.ends_with("pong"), "{response}");
+    shutdown
+      .send(())
+      .map_err(|_| anyhow!("shutdown send failed"))?;
+    (join_handle.await?)?;
+    assert!(!uds.exists());
+    Ok(())
+  }
+
+  #[tokio::test]
+  pub async fn test_server_start_falls_back_to_next_free_port() -> anyhow::Result<()> {
+    let busy = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let port = busy.local_addr()?.port();
+    let ServerHandle {
+      server,
+      shutdown,
+      ready_rx,
+    } = build_server_handle("127.0.0.1", port);
+    let app = Router::new().route("/ping", get(|| async { (StatusCode::OK, "pong") }));
+    let join_handle = tokio::spawn(server.with_port_range(10).start_new(app, None));
+    let bound_port = ready_rx.await?;
+    assert!(
+      bound_port > port,
+      "{bound_port} should be after the busy port {port}"
+    );
+    let response = reqwest::Client::new()
+      .get(format!("http://127.0.0.1:{bound_port}/ping"))
+      .send()
+      .await?
+      .text()
+      .await?;
+    assert_eq!("pong", response);
+    shutdown
+      .send(())
+      .map_err(|_| anyhow!("shutdown send failed"))?;
+    (join_handle.await?)?;
+    Ok(())
+  }
+
+  #[tokio::test]
+  pub async fn test_server_start_fails_when_port_in_use() -> anyhow::Result<()> {
+    let busy = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let port = busy.local_addr()?.port();
+    let ServerHandle { server, .. } = build_server_handle("127.0.0.1", port);
+    let app = Router::new().route("/ping", get(|| async { (StatusCode::OK, "pong") }));
+    let result = server.start_new(app, None).await;
+    assert!(result.is_err());
+    Ok(())
+  }
+
+  #[tokio::test]
+  pub async fn test_server_start_on_activated_listener() -> anyhow::Result<()> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let ServerHandle {
+      server,
+      shutdown,
+      ready_rx,
+    } = build_server_handle("localhost", 0);
+    let app = Router::new().route("/ping", get(|| async { (StatusCode::OK, "pong") }));
+    let join_handle = tokio::spawn(
+      server
+        .with_activated_listener(Some(ActivatedListener::Tcp(listener)))
+        .start_new(app, None),
+    );
+    ready_rx.await?;
+    let response = reqwest::Client::new()
+      .get(format!("http://{addr}/ping"))
+      .send()
+      .await?
+      .text()
+      .await?;
+    assert_eq!("pong", response);
+    shutdown
+      .send(())
+      .map_err(|_| anyhow!("shutdown send failed"))?;
+    (join_handle.await?)?;
+    Ok(())
+  }
 }
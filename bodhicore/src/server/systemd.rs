@@ -0,0 +1,58 @@
+use super::ActivatedListener;
+use sd_notify::NotifyState;
+use std::{
+  net::TcpListener,
+  os::{
+    fd::{FromRawFd, IntoRawFd},
+    unix::net::UnixListener,
+  },
+  time::Duration,
+};
+
+/// The first socket passed with systemd socket activation (`LISTEN_FDS`), if any
+pub fn activated_listener() -> Option<ActivatedListener> {
+  let mut fds = match sd_notify::listen_fds() {
+    Ok(fds) => fds,
+    Err(err) => {
+      tracing::debug!(?err, "no sockets passed by systemd");
+      return None;
+    }
+  };
+  let fd = fds.next()?;
+  // SAFETY: the fd is passed to this process by systemd, and owned by the listener from here on
+  let listener = unsafe { UnixListener::from_raw_fd(fd) };
+  if listener.local_addr().is_ok() {
+    return Some(ActivatedListener::Unix(listener));
+  }
+  // SAFETY: same fd, not a unix socket, released by the unix listener above
+  let listener = unsafe { TcpListener::from_raw_fd(listener.into_raw_fd()) };
+  Some(ActivatedListener::Tcp(listener))
+}
+
+/// Notifies systemd the server is ready, and keeps pinging the watchdog if `WatchdogSec` is set.
+/// Does nothing when not running as a systemd service.
+pub fn notify_ready() {
+  if let Err(err) = sd_notify::notify(false, &[NotifyState::Ready]) {
+    tracing::warn!(?err, "error notifying systemd the server is ready");
+  }
+  let mut usec = 0;
+  if sd_notify::watchdog_enabled(false, &mut usec) {
+    // pings at half the timeout, as recommended by sd_watchdog_enabled(3)
+    let interval = Duration::from_micros(usec / 2);
+    tokio::spawn(async move {
+      let mut ticker = tokio::time::interval(interval);
+      loop {
+        ticker.tick().await;
+        if let Err(err) = sd_notify::notify(false, &[NotifyState::Watchdog]) {
+          tracing::warn!(?err, "error pinging the systemd watchdog");
+        }
+      }
+    });
+  }
+}
+
+pub fn notify_stopping() {
+  if let Err(err) = sd_notify::notify(false, &[NotifyState::Stopping]) {
+    tracing::warn!(?err, "error notifying systemd the server is stopping");
+  }
+}
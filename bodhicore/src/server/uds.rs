@@ -0,0 +1,67 @@
+use axum::{extract::Request, Router};
+use hyper::body::Incoming;
+use hyper_util::{
+  rt::{TokioExecutor, TokioIo},
+  server::conn::auto::Builder,
+};
+use std::{future::Future, io, path::Path};
+use tokio::{net::UnixListener, sync::watch};
+use tower::Service;
+
+/// Binds the unix domain socket, replacing the socket file left behind by a previous run
+pub(crate) fn bind_uds(path: &Path) -> io::Result<UnixListener> {
+  if path.exists() {
+    std::fs::remove_file(path)?;
+  }
+  UnixListener::bind(path)
+}
+
+// same as `axum::serve(..).with_graceful_shutdown(..)`, which only accepts a tcp listener.
+// On the signal, stops accepting connections, and resolves once the open connections are done.
+pub(crate) async fn serve_uds<F>(listener: UnixListener, app: Router, signal: F) -> io::Result<()>
+where
+  F: Future<Output = ()> + Send + 'static,
+{
+  let (signal_tx, signal_rx) = watch::channel(());
+  let (close_tx, close_rx) = watch::channel(());
+  tokio::pin!(signal);
+  loop {
+    let socket = tokio::select! {
+      result = listener.accept() => match result {
+        Ok((socket, _)) => socket,
+        Err(err) => {
+          tracing::warn!(?err, "error accepting connection on the unix domain socket");
+          continue;
+        }
+      },
+      _ = &mut signal => break,
+    };
+    let app = app.clone();
+    let mut signal_rx = signal_rx.clone();
+    let close_rx = close_rx.clone();
+    tokio::spawn(async move {
+      let hyper_service =
+        hyper::service::service_fn(move |request: Request<Incoming>| app.clone().call(request));
+      let builder = Builder::new(TokioExecutor::new());
+      let connection = builder.serve_connection_with_upgrades(TokioIo::new(socket), hyper_service);
+      tokio::pin!(connection);
+      loop {
+        tokio::select! {
+          result = connection.as_mut() => {
+            if let Err(err) = result {
+              tracing::debug!(?err, "error serving connection on the unix domain socket");
+            }
+            break;
+          }
+          _ = signal_rx.changed() => connection.as_mut().graceful_shutdown(),
+        }
+      }
+      drop(close_rx);
+    });
+  }
+  drop(listener);
+  drop(close_rx);
+  _ = signal_tx.send(());
+  close_tx.closed().await;
+  Ok(())
+}
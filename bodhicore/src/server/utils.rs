@@ -1,15 +1,21 @@
 use crate::{
   db::DbError,
   error::{BodhiError, Common},
+  gguf::GGUFError,
+  oai::{ApiError, ApiErrorResponse, OpenAIApiError},
+  server::DownloadError,
+  service::DataServiceError,
 };
 use axum::{
-  body::Body,
-  http::{header::CONTENT_TYPE, request::Builder, Request, StatusCode},
+  body::{to_bytes, Body},
+  http::{
+    header::{CONTENT_LENGTH, CONTENT_TYPE},
+    request::Builder,
+    Request,
+  },
   response::{IntoResponse, Response},
   Json,
 };
-use serde::{Deserialize, Serialize};
-use thiserror::Error;
 
 pub trait AxumRequestExt {
   #[allow(clippy::result_large_err)]
@@ -25,57 +31,146 @@ impl AxumRequestExt for Builder {
   }
 }
 
-// TODO - have internal log message, and external user message
-#[derive(Debug, Error)]
-pub(crate) enum ApiError {
-  #[error("{0}")]
-  ServerError(String),
-  #[error("{0}")]
-  NotFound(String),
-  #[error(transparent)]
-  Axum(#[from] axum::http::Error),
-}
-
-impl From<DbError> for ApiError {
+impl From<DbError> for OpenAIApiError {
   fn from(value: DbError) -> Self {
     match value {
       DbError::Sqlx { source, table } => match source {
         sqlx::Error::RowNotFound => {
-          ApiError::NotFound(format!("given record not found in {}", table))
+          OpenAIApiError::NotFound(format!("given record not found in {}", table))
         }
-        err => ApiError::ServerError(err.to_string()),
+        err => OpenAIApiError::InternalServer(err.to_string()),
       },
-      DbError::SqlxConnect { source, url } => ApiError::ServerError(format!(
+      DbError::SqlxConnect { source, url } => OpenAIApiError::InternalServer(format!(
         "not able to connect to database at {url}, error: {source}",
       )),
-      DbError::Migrate(err) => ApiError::ServerError(err.to_string()),
+      DbError::Migrate(err) => OpenAIApiError::InternalServer(err.to_string()),
+      err @ (DbError::Unsupported(_) | DbError::ApiKeyExists(_) | DbError::MessageExists(_)) => {
+        OpenAIApiError::BadRequest(err.to_string())
+      }
+    }
+  }
+}
+
+impl From<DataServiceError> for OpenAIApiError {
+  fn from(value: DataServiceError) -> Self {
+    match value {
+      err @ (DataServiceError::SettingNotFound(_)
+      | DataServiceError::SettingReadOnly(_)
+      | DataServiceError::SettingInvalid { .. }) => OpenAIApiError::BadRequest(err.to_string()),
+      err => OpenAIApiError::InternalServer(err.to_string()),
+    }
+  }
+}
+
+impl From<DownloadError> for OpenAIApiError {
+  fn from(value: DownloadError) -> Self {
+    match value {
+      DownloadError::Db(err) => err.into(),
+      DownloadError::Hub(err) => OpenAIApiError::InternalServer(err.to_string()),
+      err @ DownloadError::InvalidState { .. } => OpenAIApiError::BadRequest(err.to_string()),
     }
   }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
-pub struct ApiErrorResponse {
-  error: String,
+impl From<GGUFError> for OpenAIApiError {
+  fn from(value: GGUFError) -> Self {
+    match value {
+      err @ GGUFError::TokenOutOfRange(_) => OpenAIApiError::BadRequest(err.to_string()),
+      err => OpenAIApiError::InternalServer(err.to_string()),
+    }
+  }
+}
+
+// the rejections of the axum extractors, and the routes not found, are sent as plain text
+const MAX_REJECTION_BYTES: usize = 64 * 1024;
+
+/// Sends the error responses not sent as json, e.g. the rejections of the extractors for an
+/// invalid body, in the OpenAI error envelope like the errors of the handlers
+pub(crate) async fn error_envelope(response: Response) -> Response {
+  let status = response.status();
+  let is_json = response
+    .headers()
+    .get(CONTENT_TYPE)
+    .and_then(|value| value.to_str().ok())
+    .is_some_and(|value| value.starts_with(mime::APPLICATION_JSON.as_ref()));
+  if !(status.is_client_error() || status.is_server_error()) || is_json {
+    return response;
+  }
+  let (mut parts, body) = response.into_parts();
+  let message = match to_bytes(body, MAX_REJECTION_BYTES).await {
+    Ok(bytes) if !bytes.is_empty() => String::from_utf8_lossy(&bytes).trim().to_string(),
+    _ => status
+      .canonical_reason()
+      .unwrap_or("request failed")
+      .to_string(),
+  };
+  parts.headers.remove(CONTENT_LENGTH);
+  parts.headers.remove(CONTENT_TYPE);
+  let error = ApiErrorResponse {
+    error: ApiError::for_status(status, message),
+  };
+  (parts, Json(error)).into_response()
 }
 
-impl IntoResponse for ApiError {
-  fn into_response(self) -> Response {
-    match self {
-      ApiError::ServerError(error) => (
-        StatusCode::INTERNAL_SERVER_ERROR,
-        Json(ApiErrorResponse { error }),
+#[cfg(test)]
+mod test {
+  use super::error_envelope;
+  use crate::{oai::ApiErrorResponse, test_utils::ResponseTestExt};
+  use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    middleware::map_response,
+    routing::post,
+    Json, Router,
+  };
+  use rstest::rstest;
+  use serde_json::{json, Value};
+  use tower::ServiceExt;
+
+  #[rstest]
+  #[case("/chats", "{", StatusCode::BAD_REQUEST, "invalid_request")]
+  #[case("/unknown", "{}", StatusCode::NOT_FOUND, "not_found")]
+  #[tokio::test]
+  async fn test_error_envelope_wraps_plain_errors(
+    #[case] path: &str,
+    #[case] body: &str,
+    #[case] status: StatusCode,
+    #[case] code: &str,
+  ) -> anyhow::Result<()> {
+    let router = Router::new()
+      .route(
+        "/chats",
+        post(|Json(value): Json<Value>| async { Json(value) }),
       )
-        .into_response(),
-      ApiError::NotFound(error) => {
-        (StatusCode::NOT_FOUND, Json(ApiErrorResponse { error })).into_response()
-      }
-      ApiError::Axum(err) => (
-        StatusCode::INTERNAL_SERVER_ERROR,
-        Json(ApiErrorResponse {
-          error: err.to_string(),
-        }),
+      .layer(map_response(error_envelope));
+    let response = router
+      .oneshot(
+        Request::post(path)
+          .header("content-type", "application/json")
+          .body(Body::from(body.to_string()))?,
       )
-        .into_response(),
-    }
+      .await?;
+    assert_eq!(status, response.status());
+    let response = response.json::<ApiErrorResponse>().await?;
+    assert_eq!(code, response.error.code);
+    assert!(!response.error.message.is_empty());
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_error_envelope_skips_json_responses() -> anyhow::Result<()> {
+    let router = Router::new()
+      .route(
+        "/chats",
+        post(|| async { (StatusCode::CONFLICT, Json(json! {{"id": "testid"}})) }),
+      )
+      .layer(map_response(error_envelope));
+    let response = router
+      .oneshot(Request::post("/chats").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::CONFLICT, response.status());
+    assert_eq!(json! {{"id": "testid"}}, response.json::<Value>().await?);
+    Ok(())
   }
 }
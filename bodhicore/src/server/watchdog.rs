@@ -0,0 +1,156 @@
+use super::inference_monitor::{InferenceMonitor, WatchdogIncident};
+use crate::shared_rw::SharedContextRwFn;
+use chrono::Utc;
+use std::{sync::Arc, time::Duration};
+use tokio::time::timeout;
+
+// how often the watchdog checks the running requests, at most
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+// the context hung in the process may never release the lock needed to stop it
+const STOP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Stops the context and reloads the model when the running requests get no token for `stall`,
+/// or a request reports a failure of the context, till the server stops
+pub async fn run_watchdog(
+  ctx: Arc<dyn SharedContextRwFn>,
+  monitor: Arc<InferenceMonitor>,
+  stall: Duration,
+) {
+  let mut interval = tokio::time::interval(CHECK_INTERVAL.min(stall));
+  loop {
+    interval.tick().await;
+    check(ctx.as_ref(), &monitor, stall).await;
+  }
+}
+
+pub(crate) async fn check(
+  ctx: &dyn SharedContextRwFn,
+  monitor: &InferenceMonitor,
+  stall: Duration,
+) -> Option<WatchdogIncident> {
+  let reason = match (monitor.take_context_error(), monitor.stalled(stall)) {
+    (Some(error), _) => format!("the context failed: {error}"),
+    (None, Some(elapsed)) => format!(
+      "no token generated for {}s by the {} running requests",
+      elapsed.as_secs(),
+      monitor.queue_depth()
+    ),
+    (None, None) => return None,
+  };
+  tracing::error!(%reason, "watchdog is reloading the model");
+  let incident = recover(ctx, reason).await;
+  if !incident.recovered {
+    tracing::error!(
+      "watchdog failed reloading the model, run the server with BODHI_ISOLATE_INFERENCE=true to recover from the hung generations"
+    );
+  }
+  monitor.record_incident(incident.clone());
+  Some(incident)
+}
+
+async fn recover(ctx: &dyn SharedContextRwFn, reason: String) -> WatchdogIncident {
+  let gpt_params = match timeout(STOP_TIMEOUT, ctx.get_gpt_params()).await {
+    Ok(Ok(gpt_params)) => gpt_params,
+    Ok(Err(err)) => {
+      tracing::warn!(?err, "error reading the params of the loaded model");
+      None
+    }
+    Err(_) => None,
+  };
+  let model = gpt_params
+    .as_ref()
+    .map(|gpt_params| gpt_params.model.clone());
+  let recovered = match timeout(STOP_TIMEOUT, ctx.force_stop()).await {
+    Ok(Ok(())) => match ctx.reload(gpt_params).await {
+      Ok(()) => true,
+      Err(err) => {
+        tracing::error!(?err, "error reloading the model");
+        false
+      }
+    },
+    Ok(Err(err)) => {
+      tracing::error!(?err, "error stopping the context");
+      false
+    }
+    Err(_) => false,
+  };
+  WatchdogIncident {
+    at: Utc::now(),
+    reason,
+    model,
+    recovered,
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::check;
+  use crate::{
+    objs::RequestPriority, server::inference_monitor::InferenceMonitor, shared_rw::ContextError,
+    test_utils::MockSharedContext,
+  };
+  use llama_server_bindings::GptParamsBuilder;
+  use mockall::predicate::eq;
+  use rstest::rstest;
+  use std::time::Duration;
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_watchdog_skips_healthy_context() {
+    let monitor = InferenceMonitor::default();
+    let _inference = monitor.start("llama3:instruct", RequestPriority::Interactive, true);
+    let ctx = MockSharedContext::new();
+    assert_eq!(None, check(&ctx, &monitor, Duration::from_secs(60)).await);
+    assert_eq!(0, monitor.incidents());
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_watchdog_reloads_stalled_context() -> anyhow::Result<()> {
+    let monitor = InferenceMonitor::default();
+    let _inference = monitor.start("llama3:instruct", RequestPriority::Interactive, true);
+    let gpt_params = GptParamsBuilder::default()
+      .model("/models/llama3.gguf".to_string())
+      .build()?;
+    let mut ctx = MockSharedContext::new();
+    ctx.expect_get_gpt_params().return_once({
+      let gpt_params = gpt_params.clone();
+      move || Ok(Some(gpt_params))
+    });
+    ctx.expect_force_stop().return_once(|| Ok(()));
+    ctx
+      .expect_reload()
+      .with(eq(Some(gpt_params)))
+      .return_once(|_| Ok(()));
+    let incident = check(&ctx, &monitor, Duration::ZERO)
+      .await
+      .expect("incident to be recorded");
+    assert!(incident.recovered);
+    assert_eq!(Some("/models/llama3.gguf".to_string()), incident.model);
+    assert!(incident.reason.starts_with("no token generated"));
+    assert_eq!(1, monitor.incidents());
+    assert_eq!(Some(incident), monitor.last_incident());
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_watchdog_reports_failed_reload() {
+    let monitor = InferenceMonitor::default();
+    monitor.context_failed("the inference worker exited".to_string());
+    let mut ctx = MockSharedContext::new();
+    ctx.expect_get_gpt_params().return_once(|| Ok(None));
+    ctx.expect_force_stop().return_once(|| Ok(()));
+    ctx
+      .expect_reload()
+      .return_once(|_| Err(ContextError::WorkerCrashed));
+    let incident = check(&ctx, &monitor, Duration::from_secs(60))
+      .await
+      .expect("incident to be recorded");
+    assert!(!incident.recovered);
+    assert_eq!(
+      "the context failed: the inference worker exited",
+      incident.reason
+    );
+  }
+}
@@ -1,9 +1,11 @@
-use super::{ALIASES_DIR, MODELS_YAML};
+use super::{ALIASES_DIR, CATALOGS_DIR, CATALOG_CHANGES_YAML, MODELS_YAML, PIPELINES_DIR};
 use crate::{
   error::Common,
-  objs::{Alias, RemoteModel},
+  objs::{lint_alias_yaml, Alias, AliasLint, CatalogDiff, Pipeline, RemoteModel},
+  utils::to_safe_filename,
 };
 use derive_new::new;
+use serde_yaml::Value;
 use std::{collections::HashMap, fmt::Debug, fs, io, path::PathBuf};
 
 #[derive(Debug, thiserror::Error)]
@@ -34,6 +36,22 @@ $BODHI_HOME might not have been initialized. Run `bodhi init` to setup $BODHI_HO
   AliasNotExists(String),
   #[error("alias '{0}' already exists in $BODHI_HOME/aliases")]
   AliasExists(String),
+  #[error("alias '{alias}' extends '{extends}', which is not found in $BODHI_HOME/aliases")]
+  AliasExtendsNotFound { alias: String, extends: String },
+  #[error("alias '{alias}' extends itself, through {chain}")]
+  AliasExtendsCycle { alias: String, chain: String },
+  #[error("setting '{0}' not found")]
+  SettingNotFound(String),
+  #[error("setting '{0}' is read-only, set it using environment variable ${0}")]
+  SettingReadOnly(String),
+  #[error("invalid value '{value}' for setting '{key}': {reason}")]
+  SettingInvalid {
+    key: String,
+    value: String,
+    reason: String,
+  },
+  #[error("failed to fetch the model catalog from '{url}': {reason}")]
+  CatalogFetch { url: String, reason: String },
 }
 
 type Result<T> = std::result::Result<T, DataServiceError>;
@@ -42,10 +60,16 @@ type Result<T> = std::result::Result<T, DataServiceError>;
 pub trait DataService: std::fmt::Debug {
   fn list_aliases(&self) -> Result<Vec<Alias>>;
 
+  /// Problems in the model alias files of `$BODHI_HOME/aliases`, the files failing to load are
+  /// skipped by `list_aliases`
+  fn lint_aliases(&self) -> Result<Vec<AliasLint>>;
+
   fn save_alias(&self, alias: &Alias) -> Result<PathBuf>;
 
   fn find_alias(&self, alias: &str) -> Option<Alias>;
 
+  /// Pre-configured model aliases of `$BODHI_HOME/models.yaml`, and of the catalogs in `$BODHI_HOME/catalogs`.
+  /// A catalog model alias replaces the pre-configured model alias with the same name.
   fn list_remote_models(&self) -> Result<Vec<RemoteModel>>;
 
   fn find_remote_model(&self, alias: &str) -> Result<Option<RemoteModel>>;
@@ -55,6 +79,15 @@ pub trait DataService: std::fmt::Debug {
   fn delete_alias(&self, alias: &str) -> Result<()>;
 
   fn alias_filename(&self, alias: &str) -> Result<PathBuf>;
+
+  /// Pipeline configured in `$BODHI_HOME/pipelines/<name>.yaml`, `None` if it does not exist
+  fn find_pipeline(&self, name: &str) -> Result<Option<Pipeline>>;
+
+  /// Changes to the remote models catalog on upgrades, most recent first
+  fn list_catalog_changes(&self) -> Result<Vec<CatalogDiff>>;
+
+  /// Validates and saves the catalog as `$BODHI_HOME/catalogs/<name>.yaml`, returns the number of model aliases in it
+  fn save_catalog(&self, name: &str, contents: &str) -> Result<usize>;
 }
 
 #[derive(Debug, Clone, PartialEq, new)]
@@ -70,6 +103,14 @@ impl LocalDataService {
   fn models_yaml(&self) -> PathBuf {
     self.bodhi_home.join(MODELS_YAML)
   }
+
+  fn pipelines_dir(&self) -> PathBuf {
+    self.bodhi_home.join(PIPELINES_DIR)
+  }
+
+  fn catalogs_dir(&self) -> PathBuf {
+    self.bodhi_home.join(CATALOGS_DIR)
+  }
 }
 
 impl DataService for LocalDataService {
@@ -95,6 +136,22 @@ impl DataService for LocalDataService {
     Ok(result)
   }
 
+  fn lint_aliases(&self) -> Result<Vec<AliasLint>> {
+    let lints = self
+      ._load_aliases()?
+      .into_iter()
+      .map(|loaded| {
+        let mut lint = AliasLint::new(loaded.filename, &loaded.content);
+        match loaded.alias {
+          Ok(_) => lint.skipped = false,
+          Err(err) => lint.unresolved(&loaded.content, err.to_string()),
+        }
+        lint
+      })
+      .collect::<Vec<_>>();
+    Ok(lints)
+  }
+
   fn find_alias(&self, alias: &str) -> Option<Alias> {
     self
       .list_aliases()
@@ -115,12 +172,16 @@ impl DataService for LocalDataService {
       source: err,
       path: models_file.display().to_string(),
     })?;
-    let models = serde_yaml::from_str::<Vec<RemoteModel>>(&content).map_err(|err| {
+    let mut models = serde_yaml::from_str::<Vec<RemoteModel>>(&content).map_err(|err| {
       Common::SerdeYamlSerialize {
         source: err,
         filename: models_file.display().to_string(),
       }
     })?;
+    for model in self._list_catalog_models() {
+      models.retain(|existing| existing.alias != model.alias);
+      models.push(model);
+    }
     Ok(models)
   }
 
@@ -160,56 +221,254 @@ impl DataService for LocalDataService {
     );
     Ok(result)
   }
-}
 
-impl LocalDataService {
-  fn _list_aliases(&self) -> Result<HashMap<String, Alias>> {
-    {
-      let aliases_dir = self.aliases_dir();
-      let yaml_files = fs::read_dir(&aliases_dir).map_err(|err| Common::IoFile {
+  fn find_pipeline(&self, name: &str) -> Result<Option<Pipeline>> {
+    if name.is_empty() || to_safe_filename(name) != name {
+      return Ok(None);
+    }
+    let filename = self.pipelines_dir().join(format!("{name}.yaml"));
+    if !filename.exists() {
+      return Ok(None);
+    }
+    let content = fs::read_to_string(&filename).map_err(|err| Common::IoFile {
+      source: err,
+      path: filename.display().to_string(),
+    })?;
+    let mut pipeline =
+      serde_yaml::from_str::<Pipeline>(&content).map_err(|err| Common::SerdeYamlSerialize {
         source: err,
-        path: aliases_dir.display().to_string(),
+        filename: filename.display().to_string(),
       })?;
-      let yaml_files = yaml_files
-        .filter_map(|entry| {
-          let file_path = entry.ok()?.path();
-          if let Some(extension) = file_path.extension() {
-            if extension == "yaml" || extension == "yml" {
-              Some(file_path)
-            } else {
-              None
-            }
+    pipeline.name = name.to_string();
+    Ok(Some(pipeline))
+  }
+
+  fn list_catalog_changes(&self) -> Result<Vec<CatalogDiff>> {
+    let filename = self.bodhi_home.join(CATALOG_CHANGES_YAML);
+    if !filename.exists() {
+      return Ok(vec![]);
+    }
+    let content = fs::read_to_string(&filename).map_err(|err| Common::IoFile {
+      source: err,
+      path: filename.display().to_string(),
+    })?;
+    let mut changes = serde_yaml::from_str::<Vec<CatalogDiff>>(&content).map_err(|err| {
+      Common::SerdeYamlSerialize {
+        source: err,
+        filename: filename.display().to_string(),
+      }
+    })?;
+    changes.reverse();
+    Ok(changes)
+  }
+
+  fn save_catalog(&self, name: &str, contents: &str) -> Result<usize> {
+    let filename = format!("{}.yaml", to_safe_filename(name));
+    let models = serde_yaml::from_str::<Vec<RemoteModel>>(contents).map_err(|err| {
+      Common::SerdeYamlSerialize {
+        source: err,
+        filename: filename.clone(),
+      }
+    })?;
+    let catalogs_dir = self.catalogs_dir();
+    fs::create_dir_all(&catalogs_dir).map_err(|err| DataServiceError::DirCreate {
+      source: err,
+      path: catalogs_dir.display().to_string(),
+    })?;
+    let catalog_file = catalogs_dir.join(filename);
+    fs::write(&catalog_file, contents).map_err(|err| Common::IoFile {
+      source: err,
+      path: catalog_file.display().to_string(),
+    })?;
+    Ok(models.len())
+  }
+}
+
+impl LocalDataService {
+  // model aliases of the catalogs in $BODHI_HOME/catalogs in filename order, skipping the invalid catalogs
+  fn _list_catalog_models(&self) -> Vec<RemoteModel> {
+    let Ok(entries) = fs::read_dir(self.catalogs_dir()) else {
+      return vec![];
+    };
+    let mut catalog_files = entries
+      .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+      .filter(|path| {
+        path
+          .extension()
+          .is_some_and(|extension| extension == "yaml" || extension == "yml")
+      })
+      .collect::<Vec<_>>();
+    catalog_files.sort();
+    let mut models = vec![];
+    for catalog_file in catalog_files {
+      let filename = catalog_file.display().to_string();
+      let content = match fs::read_to_string(&catalog_file) {
+        Ok(content) => content,
+        Err(err) => {
+          tracing::warn!(filename, ?err, "Error reading model catalog YAML file");
+          continue;
+        }
+      };
+      match serde_yaml::from_str::<Vec<RemoteModel>>(&content) {
+        Ok(catalog) => models.extend(catalog),
+        Err(err) => {
+          let err = Common::SerdeYamlDeserialize(err);
+          tracing::warn!(
+            filename,
+            ?err,
+            "Error deserializing model catalog YAML file"
+          );
+        }
+      }
+    }
+    models
+  }
+
+  fn alias_files(&self) -> Result<Vec<PathBuf>> {
+    let aliases_dir = self.aliases_dir();
+    let yaml_files = fs::read_dir(&aliases_dir).map_err(|err| Common::IoFile {
+      source: err,
+      path: aliases_dir.display().to_string(),
+    })?;
+    let mut yaml_files = yaml_files
+      .filter_map(|entry| {
+        let file_path = entry.ok()?.path();
+        if let Some(extension) = file_path.extension() {
+          if extension == "yaml" || extension == "yml" {
+            Some(file_path)
           } else {
             None
           }
-        })
-        .collect::<Vec<_>>();
-      let aliases = yaml_files
-        .into_iter()
-        .filter_map(|yaml_file| {
-          let filename = yaml_file.clone().display().to_string();
-          match fs::read_to_string(yaml_file) {
-            Ok(content) => match serde_yaml::from_str::<Alias>(&content) {
-              Ok(alias) => Some((filename, alias)),
-              Err(err) => {
-                let err = Common::SerdeYamlDeserialize(err);
-                tracing::warn!(filename, ?err, "Error deserializing model alias YAML file",);
-                None
-              }
-            },
-            Err(err) => {
-              let err = Common::IoFile {
-                source: err,
-                path: filename,
-              };
-              tracing::warn!(?err, "Error reading model alias YAML file");
-              None
-            }
+        } else {
+          None
+        }
+      })
+      .collect::<Vec<_>>();
+    yaml_files.sort();
+    Ok(yaml_files)
+  }
+
+  // the model alias files with the alias loaded from each, the `extends` of the alias resolved
+  fn _load_aliases(&self) -> Result<Vec<LoadedAlias>> {
+    let mut files = Vec::new();
+    for yaml_file in self.alias_files()? {
+      let filename = yaml_file.display().to_string();
+      match fs::read_to_string(&yaml_file) {
+        Ok(content) => files.push((filename, content)),
+        Err(err) => {
+          let err = Common::IoFile {
+            source: err,
+            path: filename,
+          };
+          tracing::warn!(?err, "Error reading model alias YAML file");
+        }
+      }
+    }
+    let documents = files
+      .iter()
+      .map(|(_, content)| serde_yaml::from_str::<Value>(content).ok())
+      .collect::<Vec<_>>();
+    let by_alias = documents
+      .iter()
+      .flatten()
+      .filter_map(|document| Some((document.get("alias")?.as_str()?, document)))
+      .collect::<HashMap<_, _>>();
+    let loaded = files
+      .into_iter()
+      .zip(documents.iter())
+      .map(|((filename, content), document)| {
+        let alias = match document {
+          Some(document) => {
+            resolve_extends(document, &by_alias, &mut Vec::new()).and_then(|document| {
+              Ok(serde_yaml::from_value::<Alias>(document).map_err(Common::from)?)
+            })
           }
-        })
-        .collect::<HashMap<_, _>>();
-      Ok(aliases)
+          None => Ok(serde_yaml::from_str::<Alias>(&content).map_err(Common::from)?),
+        };
+        LoadedAlias {
+          filename,
+          content,
+          alias,
+        }
+      })
+      .collect();
+    Ok(loaded)
+  }
+
+  fn _list_aliases(&self) -> Result<HashMap<String, Alias>> {
+    let aliases = self
+      ._load_aliases()?
+      .into_iter()
+      .filter_map(|loaded| match loaded.alias {
+        Ok(alias) => Some((loaded.filename, alias)),
+        Err(err) => {
+          let diagnostics = lint_alias_yaml(&loaded.content);
+          tracing::warn!(
+            filename = loaded.filename,
+            ?err,
+            ?diagnostics,
+            "Error loading model alias YAML file, skipping it, run `bodhi alias lint` for the details"
+          );
+          None
+        }
+      })
+      .collect::<HashMap<_, _>>();
+    Ok(aliases)
+  }
+}
+
+struct LoadedAlias {
+  filename: String,
+  content: String,
+  alias: Result<Alias>,
+}
+
+// the fields of the alias merged over the fields of the aliases it extends, the nested mappings
+// like `request_params` merged field by field
+fn resolve_extends(
+  document: &Value,
+  by_alias: &HashMap<&str, &Value>,
+  chain: &mut Vec<String>,
+) -> Result<Value> {
+  let alias = document
+    .get("alias")
+    .and_then(Value::as_str)
+    .unwrap_or_default()
+    .to_string();
+  let Some(extends) = document.get("extends").and_then(Value::as_str) else {
+    return Ok(document.clone());
+  };
+  chain.push(alias.clone());
+  if chain.iter().any(|visited| visited == extends) {
+    chain.push(extends.to_string());
+    return Err(DataServiceError::AliasExtendsCycle {
+      alias: chain[0].clone(),
+      chain: chain.join(" -> "),
+    });
+  }
+  let Some(parent) = by_alias.get(extends) else {
+    return Err(DataServiceError::AliasExtendsNotFound {
+      alias,
+      extends: extends.to_string(),
+    });
+  };
+  let parent = resolve_extends(parent, by_alias, chain)?;
+  Ok(merge_yaml(parent, document.clone()))
+}
+
+fn merge_yaml(base: Value, overrides: Value) -> Value {
+  match (base, overrides) {
+    (Value::Mapping(mut base), Value::Mapping(overrides)) => {
+      for (key, value) in overrides {
+        let value = match base.remove(&key) {
+          Some(base_value) => merge_yaml(base_value, value),
+          None => value,
+        };
+        base.insert(key, value);
+      }
+      Value::Mapping(base)
     }
+    (_, overrides) => overrides,
   }
 }
 
@@ -217,10 +476,14 @@ impl LocalDataService {
 mod test {
   use super::DataService;
   use crate::{
-    objs::{Alias, RemoteModel},
+    objs::{
+      Alias, CatalogChange, CatalogChangeKind, CatalogDiff, GptContextParamsBuilder,
+      OAIRequestParamsBuilder, Pipeline, RemoteModel,
+    },
     test_utils::{data_service, DataServiceTuple},
   };
   use anyhow_trace::anyhow_trace;
+  use chrono::{TimeZone, Utc};
   use rstest::rstest;
   use std::fs;
 
@@ -296,6 +559,67 @@ filename='{models_file}'"#
     Ok(())
   }
 
+  #[rstest]
+  fn test_local_data_service_list_remote_models_with_catalogs(
+    data_service: DataServiceTuple,
+  ) -> anyhow::Result<()> {
+    let DataServiceTuple(_temp_dir, bodhi_home, service) = data_service;
+    let catalogs_dir = bodhi_home.join("catalogs");
+    fs::create_dir_all(&catalogs_dir)?;
+    fs::write(
+      catalogs_dir.join("team.yaml"),
+      r#"
+- alias: testalias:instruct
+  family: testalias
+  repo: MyFactory/testalias-gguf
+  filename: testalias.Q4_0.gguf
+  features:
+    - chat
+  chat_template: llama3
+- alias: teammodel:instruct
+  family: teammodel
+  repo: MyFactory/teammodel-gguf
+  filename: teammodel.Q8_0.gguf
+  features:
+    - chat
+  chat_template: llama3
+"#,
+    )?;
+    fs::write(catalogs_dir.join("corrupt.yaml"), "- family: missing alias")?;
+    let models = service.list_remote_models()?;
+    assert_eq!(7, models.len());
+    assert!(models.contains(&RemoteModel::llama3()));
+    let testalias = service.find_remote_model("testalias:instruct")?.unwrap();
+    assert_eq!("testalias.Q4_0.gguf", testalias.filename);
+    assert!(service.find_remote_model("teammodel:instruct")?.is_some());
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_local_data_service_save_catalog(data_service: DataServiceTuple) -> anyhow::Result<()> {
+    let DataServiceTuple(_temp_dir, bodhi_home, service) = data_service;
+    let contents = r#"- alias: teammodel:instruct
+  family: teammodel
+  repo: MyFactory/teammodel-gguf
+  filename: teammodel.Q8_0.gguf
+  features:
+    - chat
+  chat_template: llama3
+"#;
+    assert_eq!(
+      1,
+      service.save_catalog("models.example.com/team", contents)?
+    );
+    assert_eq!(
+      contents,
+      fs::read_to_string(bodhi_home.join("catalogs/models.example.com--team.yaml"))?
+    );
+    let result = service.save_catalog("invalid", "- family: missing alias");
+    assert!(result.is_err());
+    assert!(!bodhi_home.join("catalogs/invalid.yaml").exists());
+    Ok(())
+  }
+
   #[rstest]
   fn test_local_data_service_find_alias(data_service: DataServiceTuple) -> anyhow::Result<()> {
     let DataServiceTuple(_temp, _, service) = data_service;
@@ -315,6 +639,114 @@ filename='{models_file}'"#
     Ok(())
   }
 
+  #[rstest]
+  fn test_local_data_service_lint_aliases_skips_invalid(
+    data_service: DataServiceTuple,
+  ) -> anyhow::Result<()> {
+    let DataServiceTuple(_temp, bodhi_home, service) = data_service;
+    fs::write(
+      bodhi_home.join("aliases").join("invalid--instruct.yaml"),
+      "alias: invalid:instruct\nrepo: MyFactory/invalid-gguf\n",
+    )?;
+    assert_eq!(3, service.list_aliases()?.len());
+    let lints = service.lint_aliases()?;
+    assert_eq!(4, lints.len());
+    let invalid = lints
+      .iter()
+      .filter(|lint| !lint.is_valid())
+      .collect::<Vec<_>>();
+    assert_eq!(1, invalid.len());
+    assert!(invalid[0].skipped);
+    assert!(invalid[0].file.ends_with("invalid--instruct.yaml"));
+    assert_eq!(
+      vec![
+        "missing field `filename`",
+        "missing field `snapshot`",
+        "missing field `features`",
+        "missing field `chat_template`"
+      ],
+      invalid[0]
+        .diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.message.as_str())
+        .collect::<Vec<_>>()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_local_data_service_list_aliases_resolves_extends(
+    data_service: DataServiceTuple,
+  ) -> anyhow::Result<()> {
+    let DataServiceTuple(_temp, bodhi_home, service) = data_service;
+    fs::write(
+      bodhi_home.join("aliases").join("llama3--creative.yaml"),
+      r#"alias: llama3:creative
+extends: llama3:instruct
+request_params:
+  temperature: 1.2
+"#,
+    )?;
+    fs::write(
+      bodhi_home
+        .join("aliases")
+        .join("llama3--creative-long.yaml"),
+      r#"alias: llama3:creative-long
+extends: llama3:creative
+context_params:
+  n_ctx: 8192
+"#,
+    )?;
+    let alias = service
+      .find_alias("llama3:creative-long")
+      .expect("alias to be resolved");
+    let expected = Alias {
+      alias: "llama3:creative-long".to_string(),
+      extends: Some("llama3:creative".to_string()),
+      request_params: OAIRequestParamsBuilder::default()
+        .temperature(1.2)
+        .build()?,
+      context_params: GptContextParamsBuilder::default().n_ctx(8192).build()?,
+      ..Alias::llama3()
+    };
+    assert_eq!(expected, alias);
+    assert_eq!(5, service.list_aliases()?.len());
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(
+    "alias: llama3:a\nextends: llama3:b\n",
+    "alias: llama3:b\nextends: llama3:a\n",
+    "alias 'llama3:a' extends itself, through llama3:a -> llama3:b -> llama3:a"
+  )]
+  #[case(
+    "alias: llama3:a\nextends: llama3:b\n",
+    "alias: llama3:b\nextends: llama3:missing\n",
+    "alias 'llama3:b' extends 'llama3:missing', which is not found in $BODHI_HOME/aliases"
+  )]
+  fn test_local_data_service_extends_skips_unresolved(
+    data_service: DataServiceTuple,
+    #[case] a: &str,
+    #[case] b: &str,
+    #[case] expected: &str,
+  ) -> anyhow::Result<()> {
+    let DataServiceTuple(_temp, bodhi_home, service) = data_service;
+    fs::write(bodhi_home.join("aliases").join("llama3--a.yaml"), a)?;
+    fs::write(bodhi_home.join("aliases").join("llama3--b.yaml"), b)?;
+    assert_eq!(3, service.list_aliases()?.len());
+    let lint = service
+      .lint_aliases()?
+      .into_iter()
+      .find(|lint| lint.file.ends_with("llama3--a.yaml"))
+      .expect("lint of llama3--a.yaml");
+    assert!(lint.skipped);
+    assert_eq!(1, lint.diagnostics.len());
+    assert_eq!(Some(2), lint.diagnostics[0].line);
+    assert_eq!(expected, lint.diagnostics[0].message);
+    Ok(())
+  }
+
   #[rstest]
   fn test_local_data_service_delete_alias(data_service: DataServiceTuple) -> anyhow::Result<()> {
     let DataServiceTuple(_temp, bodhi_home, service) = data_service;
@@ -358,4 +790,62 @@ filename='{models_file}'"#
     assert_eq!(expected, new_alias);
     Ok(())
   }
+
+  #[rstest]
+  fn test_local_data_service_find_pipeline(data_service: DataServiceTuple) -> anyhow::Result<()> {
+    let DataServiceTuple(_temp, bodhi_home, service) = data_service;
+    assert_eq!(None, service.find_pipeline("docs-assistant")?);
+    let pipelines_dir = bodhi_home.join("pipelines");
+    fs::create_dir_all(&pipelines_dir)?;
+    fs::write(
+      pipelines_dir.join("docs-assistant.yaml"),
+      "alias: tinyllama:instruct\nsystem_prompt: Answer from the docs.\n",
+    )?;
+    fs::write(
+      pipelines_dir.join("with-tools.yaml"),
+      "alias: tinyllama:instruct\ntools: [read_file]\n",
+    )?;
+    assert_eq!(
+      Some(Pipeline {
+        name: "docs-assistant".to_string(),
+        alias: "tinyllama:instruct".to_string(),
+        system_prompt: Some("Answer from the docs.".to_string()),
+      }),
+      service.find_pipeline("docs-assistant")?
+    );
+    assert!(service.find_pipeline("with-tools").is_err());
+    assert_eq!(
+      None,
+      service.find_pipeline("../aliases/tinyllama--instruct")?
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_local_data_service_list_catalog_changes(
+    data_service: DataServiceTuple,
+  ) -> anyhow::Result<()> {
+    let DataServiceTuple(_temp, bodhi_home, service) = data_service;
+    assert!(service.list_catalog_changes()?.is_empty());
+    let older = CatalogDiff {
+      created_at: Utc.with_ymd_and_hms(2024, 5, 1, 10, 30, 0).unwrap(),
+      changes: vec![CatalogChange {
+        kind: CatalogChangeKind::Added,
+        alias: "phi3:mini".to_string(),
+        repo: "microsoft/Phi-3-mini-4k-instruct-gguf".to_string(),
+        filename: "Phi-3-mini-4k-instruct-q4.gguf".to_string(),
+        previous: None,
+      }],
+    };
+    let newer = CatalogDiff {
+      created_at: Utc.with_ymd_and_hms(2024, 6, 1, 10, 30, 0).unwrap(),
+      changes: vec![],
+    };
+    fs::write(
+      bodhi_home.join("catalog_changes.yaml"),
+      serde_yaml::to_string(&vec![older.clone(), newer.clone()])?,
+    )?;
+    assert_eq!(vec![newer, older], service.list_catalog_changes()?);
+    Ok(())
+  }
 }
@@ -1,14 +1,48 @@
-use std::{env::VarError, path::PathBuf};
+use std::{
+  collections::HashMap,
+  env::VarError,
+  path::{Path, PathBuf},
+};
 
-#[derive(Debug, Clone, Default)]
-pub struct EnvWrapper {}
+/// Snapshot of the environment variables and the home directory.
+/// The services read the variables from the snapshot, so the process environment is not
+/// read or modified once the wrapper is constructed.
+#[derive(Debug, Clone)]
+pub struct EnvWrapper {
+  vars: HashMap<String, String>,
+  home_dir: Option<PathBuf>,
+}
+
+impl Default for EnvWrapper {
+  /// Snapshot of the environment of the current process
+  fn default() -> Self {
+    let vars = std::env::vars_os()
+      .filter_map(|(key, value)| Some((key.into_string().ok()?, value.into_string().ok()?)))
+      .collect();
+    Self::new(vars, dirs::home_dir())
+  }
+}
 
 impl EnvWrapper {
+  /// Environment with only the given variables, isolated from the process environment
+  pub fn new(vars: HashMap<String, String>, home_dir: Option<PathBuf>) -> Self {
+    Self { vars, home_dir }
+  }
+
   pub fn var(&self, key: &str) -> Result<String, VarError> {
-    std::env::var(key)
+    self.vars.get(key).cloned().ok_or(VarError::NotPresent)
   }
 
   pub fn home_dir(&self) -> Option<PathBuf> {
-    dirs::home_dir()
+    self.home_dir.clone()
+  }
+
+  /// Adds the variables from the dotenv file, variables already set take precedence
+  pub fn load_dotenv(&mut self, path: &Path) -> Result<(), dotenv::Error> {
+    for item in dotenv::from_path_iter(path)? {
+      let (key, value) = item?;
+      self.vars.entry(key).or_insert(value);
+    }
+    Ok(())
   }
 }
@@ -1,9 +1,14 @@
-use crate::objs::{HubFile, ObjError, Repo, REFS, REFS_MAIN};
-use hf_hub::{api::sync::ApiError, Cache};
+use super::{ProxyConfig, DEFAULT_HF_ENDPOINT};
+use crate::{
+  error::Common,
+  objs::{HubFile, ObjError, Repo, REFS, REFS_MAIN},
+};
+use hf_hub::{api::sync::ApiError, Cache, RepoType};
 use std::{
   fmt::{Debug, Formatter},
   fs,
-  path::PathBuf,
+  io::{Read, Write},
+  path::{Path, PathBuf},
 };
 use walkdir::WalkDir;
 
@@ -24,7 +29,7 @@ Go to https://huggingface.co/{repo} to request access to the model and try again
   },
   #[error(
     r#"{source}
-You are not logged in to huggingface using CLI `huggingface-cli login`.
+You are not logged in to huggingface using CLI `bodhi login`.
 So either the huggingface repo '{repo}' does not exists, or is private, or requires request access.
 Go to https://huggingface.co/{repo} to request access, login via CLI, and then try again.
 "#
@@ -34,8 +39,6 @@ Go to https://huggingface.co/{repo} to request access, login via CLI, and then t
     source: ApiError,
     repo: String,
   },
-  #[error("only files from refs/main supported")]
-  OnlyRefsMainSupported,
   #[error(transparent)]
   ObjError(#[from] ObjError),
   #[error(
@@ -46,20 +49,120 @@ Check Huggingface Home is set correctly using environment variable $HF_HOME or u
 
   #[error("chat_template not found in tokenizer_config.json")]
   ChatTemplate,
+
+  #[error(
+    "huggingface token is invalid or expired, create a new access token at https://huggingface.co/settings/tokens"
+  )]
+  InvalidToken,
+
+  #[error("header '{header}' missing in huggingface response for '{url}'")]
+  MissingHeader { header: String, url: String },
+
+  #[error("no files matching '{pattern}' found in huggingface repo '{repo}'")]
+  NoMatchingFiles { pattern: String, repo: String },
+
+  #[error("download of '{filename}' from huggingface repo '{repo}' was stopped")]
+  DownloadStopped { repo: String, filename: String },
+
+  #[error(transparent)]
+  Common(#[from] Common),
+}
+
+impl HubServiceError {
+  /// The file or the repo is not found on huggingface
+  pub fn is_not_found(&self) -> bool {
+    match self {
+      HubServiceError::ApiError(ApiError::RequestError(err)) => {
+        matches!(**err, ureq::Error::Status(404, _))
+      }
+      _ => false,
+    }
+  }
 }
 
 type Result<T> = std::result::Result<T, HubServiceError>;
 
+/// What a download does after reporting its progress
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DownloadSignal {
+  Continue,
+  /// stops the download keeping the downloaded part, so it can be resumed
+  Pause,
+  /// stops the download and removes the downloaded part
+  Cancel,
+}
+
+/// Receives the progress of [`HubService::download_with_progress`], the bytes downloaded
+/// include the ones downloaded before the download was resumed
+pub trait DownloadProgress: Send + Sync {
+  fn on_progress(&self, downloaded: u64, total: Option<u64>) -> DownloadSignal;
+}
+
 #[cfg_attr(test, mockall::automock)]
 pub trait HubService: std::fmt::Debug {
-  fn download(&self, repo: &Repo, filename: &str, force: bool) -> Result<HubFile>;
+  /// Downloads the file at the given revision - a branch, tag or commit sha, into $HF_HOME.
+  /// The returned file has the snapshot resolved to the commit sha of the revision.
+  fn download(&self, repo: &Repo, filename: &str, revision: &str, force: bool) -> Result<HubFile>;
+
+  /// Downloads the file like [`HubService::download`] if not already in $HF_HOME, reporting the
+  /// progress, and continuing from the part downloaded before it was paused, if any.
+  /// Fails with `DownloadStopped` once the progress returns a pause or cancel signal.
+  fn download_with_progress(
+    &self,
+    repo: &Repo,
+    filename: &str,
+    revision: &str,
+    progress: &dyn DownloadProgress,
+  ) -> Result<HubFile>;
+
+  /// Removes the part of the file downloaded by [`HubService::download_with_progress`] before it
+  /// was paused or failed, if any
+  fn remove_incomplete(&self, repo: &Repo, filename: &str, revision: &str) -> Result<()>;
 
   fn list_local_models(&self) -> Vec<HubFile>;
 
+  /// Lists the filenames in the huggingface repo at the given revision
+  fn list_repo_files(&self, repo: &Repo, revision: &str) -> Result<Vec<String>>;
+
+  /// Commit sha the revision of the huggingface repo resolves to for the file, without downloading it
+  fn latest_snapshot(&self, repo: &Repo, filename: &str, revision: &str) -> Result<String>;
+
   fn find_local_file(&self, repo: &Repo, filename: &str, snapshot: &str)
     -> Result<Option<HubFile>>;
 
   fn model_file_path(&self, repo: &Repo, filename: &str, snapshot: &str) -> PathBuf;
+
+  /// Adds a file from outside the cache as `repo/filename` at the given snapshot, and points refs/main to it.
+  /// The file is symlinked in place, or copied into the cache if `copy` is true.
+  fn link_local_file(
+    &self,
+    path: &Path,
+    repo: &Repo,
+    filename: &str,
+    snapshot: &str,
+    copy: bool,
+  ) -> Result<HubFile>;
+
+  /// Validates the token with huggingface, and returns the username the token belongs to
+  fn whoami(&self, token: &str) -> Result<String>;
+
+  /// Saves the token to $HF_HOME/token, readable only by the current user
+  fn save_token(&self, token: &str) -> Result<PathBuf>;
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct WhoAmI {
+  name: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct RepoInfo {
+  siblings: Vec<RepoSibling>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct RepoSibling {
+  rfilename: String,
 }
 
 impl HfHubService {
@@ -78,16 +181,61 @@ impl HfHubService {
 }
 
 impl HubService for HfHubService {
-  fn download(&self, repo: &Repo, filename: &str, force: bool) -> Result<HubFile> {
-    let hf_repo = self.cache.repo(hf_hub::Repo::model(repo.to_string()));
+  fn download(&self, repo: &Repo, filename: &str, revision: &str, force: bool) -> Result<HubFile> {
+    let hf_repo = self.cache.repo(hf_hub::Repo::with_revision(
+      repo.to_string(),
+      RepoType::Model,
+      revision.to_string(),
+    ));
     let from_cache = hf_repo.get(filename);
     let path = match from_cache {
       Some(path) if !force => path,
-      Some(_) | None => self.download_sync(repo, filename)?,
+      Some(_) | None => self.download_sync(repo, filename, revision)?,
+    };
+    let result = HubFile::try_from(path)?;
+    Ok(result)
+  }
+
+  fn download_with_progress(
+    &self,
+    repo: &Repo,
+    filename: &str,
+    revision: &str,
+    progress: &dyn DownloadProgress,
+  ) -> Result<HubFile> {
+    let hf_repo = self.cache.repo(hf_hub::Repo::with_revision(
+      repo.to_string(),
+      RepoType::Model,
+      revision.to_string(),
+    ));
+    let path = match hf_repo.get(filename) {
+      Some(path) => path,
+      None => {
+        tracing::info!("Downloading from repo {repo}, file {filename}, revision {revision}:");
+        // hf_hub does not report the progress or resume the downloads, so always downloaded directly
+        let proxy = self.proxy.proxy_for(self.endpoint_host());
+        self.download_direct(proxy, repo, filename, revision, Some(progress))?
+      }
     };
     let result = HubFile::try_from(path)?;
     Ok(result)
   }
+  fn remove_incomplete(&self, repo: &Repo, filename: &str, revision: &str) -> Result<()> {
+    let proxy = self.proxy.proxy_for(self.endpoint_host());
+    let (_, _, blob_path) = self.resolve_blob(proxy, repo.as_str(), filename, revision)?;
+    let incomplete_path = blob_path.with_extension("incomplete");
+    match fs::remove_file(&incomplete_path) {
+      Ok(()) => Ok(()),
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+      Err(source) => Err(
+        Common::IoFile {
+          source,
+          path: incomplete_path.display().to_string(),
+        }
+        .into(),
+      ),
+    }
+  }
 
   fn list_local_models(&self) -> Vec<HubFile> {
     let cache = self.hf_cache();
@@ -114,6 +262,66 @@ impl HubService for HfHubService {
       .collect::<Vec<_>>()
   }
 
+  fn list_repo_files(&self, repo: &Repo, revision: &str) -> Result<Vec<String>> {
+    let request_err = |err: ureq::Error| {
+      self.map_download_err(&repo.to_string(), ApiError::RequestError(Box::new(err)))
+    };
+    let mut builder = ureq::AgentBuilder::new();
+    if let Some(proxy) = self.proxy.proxy_for(self.endpoint_host()) {
+      let proxy = ureq::Proxy::new(proxy).map_err(request_err)?;
+      builder = builder.proxy(proxy);
+    }
+    let url = format!(
+      "{}/api/models/{repo}/revision/{}",
+      self.endpoint,
+      revision.replace('/', "%2F")
+    );
+    let mut request = builder.build().get(&url);
+    if let Some(token) = &self.token {
+      request = request.set("Authorization", &format!("Bearer {token}"));
+    }
+    let repo_info = request
+      .call()
+      .map_err(request_err)?
+      .into_json::<RepoInfo>()
+      .map_err(Common::Io)?;
+    Ok(
+      repo_info
+        .siblings
+        .into_iter()
+        .map(|sibling| sibling.rfilename)
+        .collect(),
+    )
+  }
+
+  fn latest_snapshot(&self, repo: &Repo, filename: &str, revision: &str) -> Result<String> {
+    let request_err = |err: ureq::Error| {
+      self.map_download_err(&repo.to_string(), ApiError::RequestError(Box::new(err)))
+    };
+    let mut builder = ureq::AgentBuilder::new().redirects(0);
+    if let Some(proxy) = self.proxy.proxy_for(self.endpoint_host()) {
+      let proxy = ureq::Proxy::new(proxy).map_err(request_err)?;
+      builder = builder.proxy(proxy);
+    }
+    let url = format!(
+      "{}/{repo}/resolve/{}/{filename}",
+      self.endpoint,
+      revision.replace('/', "%2F")
+    );
+    let mut request = builder.build().head(&url);
+    if let Some(token) = &self.token {
+      request = request.set("Authorization", &format!("Bearer {token}"));
+    }
+    let response = request.call().map_err(request_err)?;
+    response
+      .header("x-repo-commit")
+      .map(|commit| commit.to_string())
+      .ok_or_else(|| HubServiceError::MissingHeader {
+        header: "x-repo-commit".to_string(),
+        url,
+      })
+  }
+
   fn find_local_file(
     &self,
     repo: &Repo,
@@ -121,9 +329,6 @@ impl HubService for HfHubService {
     snapshot: &str,
   ) -> Result<Option<HubFile>> {
     let snapshot = if snapshot.starts_with(REFS) {
-      if !snapshot.eq(REFS_MAIN) {
-        return Err(HubServiceError::OnlyRefsMainSupported);
-      }
       let refs_file = self.hf_cache().join(repo.path()).join(snapshot);
       if !refs_file.exists() {
         return Ok(None);
@@ -183,6 +388,127 @@ impl HubService for HfHubService {
       .join(snapshot)
       .join(filename)
   }
+
+  fn link_local_file(
+    &self,
+    path: &Path,
+    repo: &Repo,
+    filename: &str,
+    snapshot: &str,
+    copy: bool,
+  ) -> Result<HubFile> {
+    let metadata = fs::metadata(path).map_err(|source| Common::IoFile {
+      source,
+      path: path.display().to_string(),
+    })?;
+    let target = self.model_file_path(repo, filename, snapshot);
+    if let Some(parent) = target.parent() {
+      fs::create_dir_all(parent).map_err(|source| Common::IoDir {
+        source,
+        path: parent.display().to_string(),
+      })?;
+    }
+    if target.symlink_metadata().is_ok() {
+      fs::remove_file(&target).map_err(|source| Common::IoFile {
+        source,
+        path: target.display().to_string(),
+      })?;
+    }
+    let result = if copy {
+      fs::copy(path, &target).map(|_| ())
+    } else {
+      fs::canonicalize(path).and_then(|path| symlink(&path, &target))
+    };
+    result.map_err(|source| Common::IoFile {
+      source,
+      path: target.display().to_string(),
+    })?;
+    let refs_main = self.hf_cache().join(repo.path()).join(REFS_MAIN);
+    if let Some(parent) = refs_main.parent() {
+      fs::create_dir_all(parent).map_err(|source| Common::IoDir {
+        source,
+        path: parent.display().to_string(),
+      })?;
+    }
+    fs::write(&refs_main, snapshot).map_err(|source| Common::IoFile {
+      source,
+      path: refs_main.display().to_string(),
+    })?;
+    Ok(HubFile::new(
+      self.hf_cache(),
+      repo.clone(),
+      filename.to_string(),
+      snapshot.to_string(),
+      Some(metadata.len()),
+    ))
+  }
+
+  fn whoami(&self, token: &str) -> Result<String> {
+    let mut builder = ureq::AgentBuilder::new();
+    if let Some(proxy) = self.proxy.proxy_for(self.endpoint_host()) {
+      let proxy = ureq::Proxy::new(proxy).map_err(|err| ApiError::RequestError(Box::new(err)))?;
+      builder = builder.proxy(proxy);
+    }
+    let url = format!("{}/api/whoami-v2", self.endpoint);
+    let response = builder
+      .build()
+      .get(&url)
+      .set("Authorization", &format!("Bearer {token}"))
+      .call();
+    match response {
+      Ok(response) => {
+        let whoami = response.into_json::<WhoAmI>().map_err(Common::Io)?;
+        Ok(whoami.name)
+      }
+      Err(ureq::Error::Status(401, _)) => Err(HubServiceError::InvalidToken),
+      Err(err) => Err(ApiError::RequestError(Box::new(err)).into()),
+    }
+  }
+
+  fn save_token(&self, token: &str) -> Result<PathBuf> {
+    let path = self.cache.token_path();
+    if let Some(parent) = path.parent() {
+      fs::create_dir_all(parent).map_err(|source| Common::IoDir {
+        source,
+        path: parent.display().to_string(),
+      })?;
+    }
+    write_private(&path, token.trim()).map_err(|source| Common::IoFile {
+      source,
+      path: path.display().to_string(),
+    })?;
+    Ok(path)
+  }
+}
+
+#[cfg(unix)]
+fn write_private(path: &Path, content: &str) -> std::io::Result<()> {
+  use std::{io::Write, os::unix::fs::OpenOptionsExt, os::unix::fs::PermissionsExt};
+
+  let mut file = fs::OpenOptions::new()
+    .write(true)
+    .create(true)
+    .truncate(true)
+    .mode(0o600)
+    .open(path)?;
+  // mode is only applied when creating the file, restrict an existing file as well
+  file.set_permissions(fs::Permissions::from_mode(0o600))?;
+  file.write_all(content.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &Path, content: &str) -> std::io::Result<()> {
+  fs::write(path, content)
+}
+
+#[cfg(unix)]
+fn symlink(original: &Path, link: &Path) -> std::io::Result<()> {
+  std::os::unix::fs::symlink(original, link)
+}
+
+#[cfg(windows)]
+fn symlink(original: &Path, link: &Path) -> std::io::Result<()> {
+  std::os::windows::fs::symlink_file(original, link)
 }
 
 #[derive(Clone)]
@@ -190,6 +516,8 @@ pub struct HfHubService {
   cache: Cache,
   progress_bar: bool,
   token: Option<String>,
+  proxy: ProxyConfig,
+  endpoint: String,
 }
 
 impl Debug for HfHubService {
@@ -203,6 +531,8 @@ impl Debug for HfHubService {
       .field("cache", &self.cache.path())
       .field("progress_bar", &self.progress_bar)
       .field("token", &token_display)
+      .field("proxy", &self.proxy)
+      .field("endpoint", &self.endpoint)
       .finish()
   }
 }
@@ -213,6 +543,8 @@ impl HfHubService {
       cache: Cache::new(hf_cache),
       progress_bar,
       token,
+      proxy: ProxyConfig::default(),
+      endpoint: DEFAULT_HF_ENDPOINT.to_string(),
     }
   }
 
@@ -222,6 +554,8 @@ impl HfHubService {
       cache,
       progress_bar,
       token,
+      proxy: ProxyConfig::default(),
+      endpoint: DEFAULT_HF_ENDPOINT.to_string(),
     }
   }
 
@@ -232,6 +566,8 @@ impl HfHubService {
       cache,
       progress_bar,
       token,
+      proxy: ProxyConfig::default(),
+      endpoint: DEFAULT_HF_ENDPOINT.to_string(),
     }
   }
 
@@ -239,55 +575,337 @@ impl HfHubService {
     self.progress_bar = progress_bar;
   }
 
-  fn download_sync(&self, repo: &str, filename: &str) -> Result<PathBuf> {
-    use hf_hub::api::sync::{ApiBuilder, ApiError};
+  pub fn proxy(&mut self, proxy: ProxyConfig) {
+    self.proxy = proxy;
+  }
+
+  /// Use a huggingface mirror instead of https://huggingface.co, e.g. `https://hf-mirror.com`
+  pub fn endpoint(&mut self, endpoint: &str) {
+    self.endpoint = endpoint.trim_end_matches('/').to_string();
+  }
+
+  fn endpoint_host(&self) -> &str {
+    let endpoint = self
+      .endpoint
+      .split_once("://")
+      .map(|(_, rest)| rest)
+      .unwrap_or(&self.endpoint);
+    endpoint.split('/').next().unwrap_or_default()
+  }
 
+  fn download_sync(&self, repo: &str, filename: &str, revision: &str) -> Result<PathBuf> {
+    use hf_hub::api::sync::ApiBuilder;
+
+    tracing::info!("Downloading from repo {repo}, file {filename}, revision {revision}:");
+    let proxy = self.proxy.proxy_for(self.endpoint_host());
+    if proxy.is_some() || self.endpoint != DEFAULT_HF_ENDPOINT {
+      return self.download_direct(proxy, repo, filename, revision, None);
+    }
     let api = ApiBuilder::from_cache(self.cache.clone())
       .with_progress(self.progress_bar)
       .with_token(self.token.clone())
       .build()?;
-    tracing::info!("Downloading from repo {repo}, file {filename}:");
-    let path = match api.model(repo.to_string()).download(filename) {
-      Ok(path) => path,
-      Err(err) => {
-        let err = match err {
-          ApiError::RequestError(ureq_err) => match *ureq_err {
-            ureq::Error::Status(status, response) if status == 403 => {
-              HubServiceError::GatedAccess {
-                source: ApiError::RequestError(Box::new(ureq::Error::Status(status, response))),
-                repo: repo.to_string(),
-              }
-            }
-            ureq::Error::Status(status, response) if self.token.is_none() && status == 401 => {
-              HubServiceError::MayBeNotExists {
-                source: ApiError::RequestError(Box::new(ureq::Error::Status(status, response))),
-                repo: repo.to_string(),
-              }
-            }
-            ureq_err => ApiError::RequestError(Box::new(ureq_err)).into(),
-          },
-          _ => err.into(),
-        };
-        return Err(err);
+    let path = api
+      .repo(hf_hub::Repo::with_revision(
+        repo.to_string(),
+        RepoType::Model,
+        revision.to_string(),
+      ))
+      .download(filename)
+      .map_err(|err| self.map_download_err(repo, err))?;
+    Ok(path)
+  }
+
+  fn map_download_err(&self, repo: &str, err: ApiError) -> HubServiceError {
+    match err {
+      ApiError::RequestError(ureq_err) => match *ureq_err {
+        ureq::Error::Status(status, response) if status == 403 => HubServiceError::GatedAccess {
+          source: ApiError::RequestError(Box::new(ureq::Error::Status(status, response))),
+          repo: repo.to_string(),
+        },
+        ureq::Error::Status(status, response) if self.token.is_none() && status == 401 => {
+          HubServiceError::MayBeNotExists {
+            source: ApiError::RequestError(Box::new(ureq::Error::Status(status, response))),
+            repo: repo.to_string(),
+          }
+        }
+        ureq_err => ApiError::RequestError(Box::new(ureq_err)).into(),
+      },
+      _ => err.into(),
+    }
+  }
+
+  // hf_hub does not allow configuring the http client or the endpoint, so downloads behind
+  // a proxy or from a mirror are done using ureq directly, maintaining the same $HF_HOME cache layout
+  fn download_direct(
+    &self,
+    proxy: Option<&str>,
+    repo: &str,
+    filename: &str,
+    revision: &str,
+    progress: Option<&dyn DownloadProgress>,
+  ) -> Result<PathBuf> {
+    let request_err =
+      |err: ureq::Error| self.map_download_err(repo, ApiError::RequestError(Box::new(err)));
+    let (url, commit, blob_path) = self.resolve_blob(proxy, repo, filename, revision)?;
+    let repo_dir = self
+      .hf_cache()
+      .join(hf_hub::Repo::model(repo.to_string()).folder_name());
+    if !blob_path.exists() {
+      // only the downloads reporting the progress are resumed, the others start over
+      let offset = match progress {
+        Some(_) => fs::metadata(blob_path.with_extension("incomplete"))
+          .map(|metadata| metadata.len())
+          .unwrap_or_default(),
+        None => 0,
+      };
+      let agent = self.agent_builder(proxy, repo)?.build();
+      let mut request = agent.get(&url);
+      if let Some(authorization) = self.authorization() {
+        request = request.set("Authorization", &authorization);
+      }
+      if offset > 0 {
+        request = request.set("Range", &format!("bytes={offset}-"));
+      }
+      let response = request.call().map_err(request_err)?;
+      // the whole file is sent if the range is not supported
+      let offset = if response.status() == 206 { offset } else { 0 };
+      let signal = self
+        .write_blob(response, &blob_path, filename, offset, progress)
+        .map_err(|err| Common::IoFile {
+          source: err,
+          path: blob_path.display().to_string(),
+        })?;
+      if signal != DownloadSignal::Continue {
+        return Err(HubServiceError::DownloadStopped {
+          repo: repo.to_string(),
+          filename: filename.to_string(),
+        });
+      }
+    }
+    let snapshot_path = repo_dir.join("snapshots").join(&commit).join(filename);
+    link_snapshot(
+      &repo_dir,
+      &blob_path,
+      &snapshot_path,
+      filename,
+      revision,
+      &commit,
+    )
+    .map_err(|err| Common::IoFile {
+      source: err,
+      path: snapshot_path.display().to_string(),
+    })?;
+    Ok(snapshot_path)
+  }
+
+  fn agent_builder(&self, proxy: Option<&str>, repo: &str) -> Result<ureq::AgentBuilder> {
+    let builder = ureq::AgentBuilder::new();
+    match proxy {
+      Some(proxy) => {
+        let proxy = ureq::Proxy::new(proxy)
+          .map_err(|err| self.map_download_err(repo, ApiError::RequestError(Box::new(err))))?;
+        Ok(builder.proxy(proxy))
       }
+      None => Ok(builder),
+    }
+  }
+
+  fn authorization(&self) -> Option<String> {
+    self.token.as_ref().map(|token| format!("Bearer {token}"))
+  }
+
+  // the url of the file at the revision, the commit sha the revision resolves to, and the blob
+  // of the file in the $HF_HOME cache, from the response headers of the file
+  fn resolve_blob(
+    &self,
+    proxy: Option<&str>,
+    repo: &str,
+    filename: &str,
+    revision: &str,
+  ) -> Result<(String, String, PathBuf)> {
+    let url = format!(
+      "{}/{repo}/resolve/{}/{filename}",
+      self.endpoint,
+      revision.replace('/', "%2F")
+    );
+    let no_redirect_agent = self.agent_builder(proxy, repo)?.redirects(0).build();
+    let mut request = no_redirect_agent.head(&url);
+    if let Some(authorization) = self.authorization() {
+      request = request.set("Authorization", &authorization);
+    }
+    let metadata = request
+      .call()
+      .map_err(|err| self.map_download_err(repo, ApiError::RequestError(Box::new(err))))?;
+    let header = |name: &str| {
+      metadata
+        .header(name)
+        .map(|value| value.trim_start_matches("W/").trim_matches('"').to_string())
+        .ok_or_else(|| HubServiceError::MissingHeader {
+          header: name.to_string(),
+          url: url.clone(),
+        })
     };
-    Ok(path)
+    let commit = header("x-repo-commit")?;
+    let etag = header("x-linked-etag").or_else(|_| header("etag"))?;
+    let blob_path = self
+      .hf_cache()
+      .join(hf_hub::Repo::model(repo.to_string()).folder_name())
+      .join("blobs")
+      .join(&etag);
+    Ok((url, commit, blob_path))
+  }
+
+  // writes the response to the blob from the given offset, the blob is left incomplete when
+  // the progress signals to stop
+  fn write_blob(
+    &self,
+    response: ureq::Response,
+    blob_path: &Path,
+    filename: &str,
+    offset: u64,
+    progress: Option<&dyn DownloadProgress>,
+  ) -> std::io::Result<DownloadSignal> {
+    let size = response
+      .header("content-length")
+      .and_then(|value| value.parse::<u64>().ok());
+    if let Some(parent) = blob_path.parent() {
+      fs::create_dir_all(parent)?;
+    }
+    let incomplete_path = blob_path.with_extension("incomplete");
+    let mut file = if offset > 0 {
+      fs::OpenOptions::new().append(true).open(&incomplete_path)?
+    } else {
+      fs::File::create(&incomplete_path)?
+    };
+    let mut reader = response.into_reader();
+    if let Some(progress) = progress {
+      let total = size.map(|size| size + offset);
+      let signal = copy_with_progress(&mut reader, &mut file, offset, total, progress)?;
+      match signal {
+        DownloadSignal::Continue => {}
+        DownloadSignal::Pause => return Ok(signal),
+        DownloadSignal::Cancel => {
+          drop(file);
+          fs::remove_file(&incomplete_path)?;
+          return Ok(signal);
+        }
+      }
+    } else if self.progress_bar {
+      let progress_bar = indicatif::ProgressBar::new(size.unwrap_or_default());
+      progress_bar.set_message(filename.to_string());
+      std::io::copy(&mut progress_bar.wrap_read(reader), &mut file)?;
+      progress_bar.finish();
+    } else {
+      std::io::copy(&mut reader, &mut file)?;
+    }
+    fs::rename(&incomplete_path, blob_path)?;
+    Ok(DownloadSignal::Continue)
+  }
+}
+
+const COPY_CHUNK_SIZE: usize = 64 * 1024;
+
+// copies the response reporting the progress after each chunk, till the progress signals to stop
+fn copy_with_progress(
+  reader: &mut impl Read,
+  file: &mut fs::File,
+  offset: u64,
+  total: Option<u64>,
+  progress: &dyn DownloadProgress,
+) -> std::io::Result<DownloadSignal> {
+  let mut buf = vec![0; COPY_CHUNK_SIZE];
+  let mut downloaded = offset;
+  loop {
+    let signal = progress.on_progress(downloaded, total);
+    // the file downloaded completely is kept, even if stopped right then
+    if total.is_some_and(|total| downloaded >= total) {
+      return Ok(DownloadSignal::Continue);
+    }
+    if signal != DownloadSignal::Continue {
+      return Ok(signal);
+    }
+    let read = match reader.read(&mut buf) {
+      Ok(0) => return Ok(DownloadSignal::Continue),
+      Ok(read) => read,
+      Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+      Err(err) => return Err(err),
+    };
+    file.write_all(&buf[..read])?;
+    downloaded += read as u64;
+  }
+}
+
+// links snapshots/<commit>/<filename> to blobs/<etag>, and points refs/<revision> to the commit
+fn link_snapshot(
+  repo_dir: &Path,
+  blob_path: &Path,
+  snapshot_path: &Path,
+  filename: &str,
+  revision: &str,
+  commit: &str,
+) -> std::io::Result<()> {
+  if let Some(parent) = snapshot_path.parent() {
+    fs::create_dir_all(parent)?;
+  }
+  if snapshot_path.exists() {
+    fs::remove_file(snapshot_path)?;
+  }
+  link_blob(blob_path, snapshot_path, filename)?;
+  let refs_path = repo_dir.join(REFS).join(revision);
+  if let Some(parent) = refs_path.parent() {
+    fs::create_dir_all(parent)?;
   }
+  fs::write(refs_path, commit)
+}
+
+#[cfg(unix)]
+fn link_blob(blob_path: &Path, snapshot_path: &Path, filename: &str) -> std::io::Result<()> {
+  let depth = Path::new(filename).components().count() + 1;
+  let relative = (0..depth).fold(PathBuf::new(), |path, _| path.join(".."));
+  let blob_name = blob_path.file_name().unwrap_or_default();
+  std::os::unix::fs::symlink(relative.join("blobs").join(blob_name), snapshot_path)
+}
+
+#[cfg(not(unix))]
+fn link_blob(blob_path: &Path, snapshot_path: &Path, _filename: &str) -> std::io::Result<()> {
+  fs::copy(blob_path, snapshot_path).map(|_| ())
 }
 
 #[cfg(test)]
 mod test {
-  use super::{HfHubService, HubService};
+  use super::{DownloadProgress, DownloadSignal, HfHubService, HubService, HubServiceError};
   use crate::{
-    objs::{HubFile, Repo, REFS_MAIN},
+    objs::{HubFile, Repo, DEFAULT_REVISION, REFS_MAIN},
     test_utils::{
       hf_test_token_allowed, hf_test_token_public, hub_service, temp_hf_home, HubServiceTuple,
     },
   };
   use rstest::rstest;
-  use std::fs;
+  use std::{fs, sync::Mutex};
   use tempfile::TempDir;
 
+  struct RecordedProgress {
+    signal: DownloadSignal,
+    updates: Mutex<Vec<(u64, Option<u64>)>>,
+  }
+
+  impl RecordedProgress {
+    fn new(signal: DownloadSignal) -> Self {
+      Self {
+        signal,
+        updates: Mutex::new(vec![]),
+      }
+    }
+  }
+
+  impl DownloadProgress for RecordedProgress {
+    fn on_progress(&self, downloaded: u64, total: Option<u64>) -> DownloadSignal {
+      self.updates.lock().unwrap().push((downloaded, total));
+      self.signal
+    }
+  }
+
   #[rstest]
   #[case(None)]
   #[case(hf_test_token_public())]
@@ -300,6 +918,7 @@ mod test {
     let local_model_file = service.download(
       &Repo::try_from("amir36/test-model-repo")?,
       "tokenizer_config.json",
+      DEFAULT_REVISION,
       false,
     )?;
     assert!(local_model_file.path().exists());
@@ -318,9 +937,86 @@ mod test {
     Ok(())
   }
 
+  #[rstest]
+  fn test_hf_hub_service_download_with_progress(temp_hf_home: TempDir) -> anyhow::Result<()> {
+    let hf_cache = temp_hf_home.path().join("huggingface/hub");
+    let service = HfHubService::new(hf_cache, false, None);
+    let repo = Repo::try_from("amir36/test-model-repo")?;
+    let progress = RecordedProgress::new(DownloadSignal::Continue);
+    let local_model_file = service.download_with_progress(
+      &repo,
+      "tokenizer_config.json",
+      DEFAULT_REVISION,
+      &progress,
+    )?;
+    assert!(local_model_file.path().exists());
+    assert_eq!(
+      Some(&(22, Some(22))),
+      progress.updates.lock().unwrap().last()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_hf_hub_service_download_with_progress_cancelled(
+    temp_hf_home: TempDir,
+  ) -> anyhow::Result<()> {
+    let hf_cache = temp_hf_home.path().join("huggingface/hub");
+    let service = HfHubService::new(hf_cache.clone(), false, None);
+    let repo = Repo::try_from("amir36/test-model-repo")?;
+    let progress = RecordedProgress::new(DownloadSignal::Cancel);
+    let result =
+      service.download_with_progress(&repo, "tokenizer_config.json", DEFAULT_REVISION, &progress);
+    assert!(matches!(
+      result,
+      Err(HubServiceError::DownloadStopped { .. })
+    ));
+    assert_eq!(vec![(0, Some(22))], *progress.updates.lock().unwrap());
+    let blobs = fs::read_dir(hf_cache.join("models--amir36--test-model-repo/blobs"))?.count();
+    assert_eq!(0, blobs);
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_hf_hub_service_remove_incomplete_of_paused_download(
+    temp_hf_home: TempDir,
+  ) -> anyhow::Result<()> {
+    let hf_cache = temp_hf_home.path().join("huggingface/hub");
+    let service = HfHubService::new(hf_cache.clone(), false, None);
+    let repo = Repo::try_from("amir36/test-model-repo")?;
+    let progress = RecordedProgress::new(DownloadSignal::Pause);
+    let result =
+      service.download_with_progress(&repo, "tokenizer_config.json", DEFAULT_REVISION, &progress);
+    assert!(matches!(
+      result,
+      Err(HubServiceError::DownloadStopped { .. })
+    ));
+    let blobs_dir = hf_cache.join("models--amir36--test-model-repo/blobs");
+    assert_eq!(1, fs::read_dir(&blobs_dir)?.count());
+    service.remove_incomplete(&repo, "tokenizer_config.json", DEFAULT_REVISION)?;
+    assert_eq!(0, fs::read_dir(&blobs_dir)?.count());
+    service.remove_incomplete(&repo, "tokenizer_config.json", DEFAULT_REVISION)?;
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_hf_hub_service_download_public_file_at_revision(
+    temp_hf_home: TempDir,
+  ) -> anyhow::Result<()> {
+    let hf_cache = temp_hf_home.path().join("huggingface/hub");
+    let service = HfHubService::new(hf_cache.clone(), false, None);
+    let repo = Repo::try_from("amir36/test-model-repo")?;
+    let revision = "f7d5db77208ab98318b45cba4a48fc33a47fe4f6";
+    let local_model_file = service.download(&repo, "tokenizer_config.json", revision, false)?;
+    assert_eq!(revision, local_model_file.snapshot);
+    let cached = service.find_local_file(&repo, "tokenizer_config.json", revision)?;
+    assert_eq!(Some(local_model_file), cached);
+    Ok(())
+  }
+
   #[rstest]
   #[case(None, r#"request error: https://huggingface.co/amir36/test-gated-repo/resolve/main/tokenizer_config.json: status code 401
-You are not logged in to huggingface using CLI `huggingface-cli login`.
+You are not logged in to huggingface using CLI `bodhi login`.
 So either the huggingface repo 'amir36/test-gated-repo' does not exists, or is private, or requires request access.
 Go to https://huggingface.co/amir36/test-gated-repo to request access, login via CLI, and then try again.
 "#)]
@@ -338,6 +1034,7 @@ Go to https://huggingface.co/amir36/test-gated-repo to request access to the mod
     let local_model_file = service.download(
       &Repo::try_from("amir36/test-gated-repo")?,
       "tokenizer_config.json",
+      DEFAULT_REVISION,
       false,
     );
     assert!(local_model_file.is_err());
@@ -356,6 +1053,7 @@ Go to https://huggingface.co/amir36/test-gated-repo to request access to the mod
     let local_model_file = service.download(
       &Repo::try_from("amir36/test-gated-repo")?,
       "tokenizer_config.json",
+      DEFAULT_REVISION,
       false,
     )?;
     let path = local_model_file.path();
@@ -389,6 +1087,33 @@ Go to https://huggingface.co/amir36/test-gated-repo to request access to the mod
     Ok(())
   }
 
+  #[rstest]
+  #[case(false)]
+  #[case(true)]
+  fn test_hf_hub_service_link_local_file(
+    hub_service: HubServiceTuple,
+    #[case] copy: bool,
+  ) -> anyhow::Result<()> {
+    let HubServiceTuple(_temp, _, service) = hub_service;
+    let temp_dir = TempDir::new()?;
+    let path = temp_dir.path().join("mymodel.Q4_0.gguf");
+    fs::write(&path, "GGUF model")?;
+    let repo = Repo::try_from("local/mymodel")?;
+    let hub_file =
+      service.link_local_file(&path, &repo, "mymodel.Q4_0.gguf", "0123456789abcdef", copy)?;
+    assert_eq!(Some(10), hub_file.size);
+    assert_eq!("GGUF model", fs::read_to_string(hub_file.path())?);
+    assert_eq!(
+      !copy,
+      hub_file.path().symlink_metadata()?.file_type().is_symlink()
+    );
+    let local_file = service
+      .find_local_file(&repo, "mymodel.Q4_0.gguf", REFS_MAIN)?
+      .unwrap();
+    assert_eq!(hub_file, local_file);
+    Ok(())
+  }
+
   #[rstest]
   fn test_hf_hub_service_find_local_model_not_present(
     hub_service: HubServiceTuple,
@@ -403,24 +1128,32 @@ Go to https://huggingface.co/amir36/test-gated-repo to request access to the mod
   }
 
   #[rstest]
-  fn test_hf_hub_service_find_local_model_err_on_non_main_refs(
+  fn test_hf_hub_service_find_local_file_resolves_non_main_refs(
     hub_service: HubServiceTuple,
   ) -> anyhow::Result<()> {
-    let HubServiceTuple(_temp, _, service) = hub_service;
+    let HubServiceTuple(_temp, hf_cache, service) = hub_service;
     let repo = Repo::try_from("meta-llama/Llama-2-70b-chat-hf")?;
     let filename = "tokenizer_config.json";
-    let result = service.find_local_file(&repo, filename, "refs/custom");
-    assert!(result.is_err());
+    assert!(service
+      .find_local_file(&repo, filename, "refs/v1")?
+      .is_none());
+    fs::write(
+      hf_cache.join(repo.path()).join("refs/v1"),
+      "9ff8b00464fc439a64bb374769dec3dd627be1c2",
+    )?;
+    let local_model_file = service
+      .find_local_file(&repo, filename, "refs/v1")?
+      .unwrap();
     assert_eq!(
-      "only files from refs/main supported",
-      result.unwrap_err().to_string()
+      "this is version 1\n",
+      fs::read_to_string(local_model_file.path())?
     );
     Ok(())
   }
 
   #[rstest]
   #[case(None, r#"request error: https://huggingface.co/amir36/not-exists/resolve/main/tokenizer_config.json: status code 401
-You are not logged in to huggingface using CLI `huggingface-cli login`.
+You are not logged in to huggingface using CLI `bodhi login`.
 So either the huggingface repo 'amir36/not-exists' does not exists, or is private, or requires request access.
 Go to https://huggingface.co/amir36/not-exists to request access, login via CLI, and then try again.
 "#)]
@@ -436,6 +1169,7 @@ Go to https://huggingface.co/amir36/not-exists to request access, login via CLI,
     let local_model_file = service.download(
       &Repo::try_from("amir36/not-exists")?,
       "tokenizer_config.json",
+      DEFAULT_REVISION,
       false,
     );
     assert!(local_model_file.is_err());
@@ -472,4 +1206,66 @@ Go to https://huggingface.co/amir36/not-exists to request access, login via CLI,
     assert_eq!(&expected_1, models.first().unwrap());
     Ok(())
   }
+
+  #[rstest]
+  fn test_hf_hub_service_save_token(temp_hf_home: TempDir) -> anyhow::Result<()> {
+    let hf_cache = temp_hf_home.path().join("huggingface/hub");
+    let service = HfHubService::new(hf_cache, false, None);
+    let path = service.save_token(" hf_testtoken\n")?;
+    assert_eq!(temp_hf_home.path().join("huggingface/token"), path);
+    assert_eq!("hf_testtoken", fs::read_to_string(&path)?);
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      assert_eq!(0o600, fs::metadata(&path)?.permissions().mode() & 0o777);
+    }
+    let service =
+      HfHubService::new_from_hf_cache(temp_hf_home.path().join("huggingface/hub"), false);
+    assert!(format!("{service:?}").contains("token: \"****\""));
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_hf_hub_service_latest_snapshot(temp_hf_home: TempDir) -> anyhow::Result<()> {
+    let service = HfHubService::new(temp_hf_home.path().join("huggingface/hub"), false, None);
+    let snapshot = service.latest_snapshot(
+      &Repo::try_from("amir36/test-model-repo")?,
+      "tokenizer_config.json",
+      DEFAULT_REVISION,
+    )?;
+    assert_eq!("f7d5db77208ab98318b45cba4a48fc33a47fe4f6", snapshot);
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_hf_hub_service_list_repo_files(temp_hf_home: TempDir) -> anyhow::Result<()> {
+    let service = HfHubService::new(temp_hf_home.path().join("huggingface/hub"), false, None);
+    let files =
+      service.list_repo_files(&Repo::try_from("amir36/test-model-repo")?, DEFAULT_REVISION)?;
+    assert!(files.contains(&"tokenizer_config.json".to_string()));
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_hf_hub_service_whoami_invalid_token(temp_hf_home: TempDir) -> anyhow::Result<()> {
+    let service = HfHubService::new(temp_hf_home.path().join("huggingface/hub"), false, None);
+    let result = service.whoami("hf_invalidtoken");
+    assert!(matches!(result, Err(HubServiceError::InvalidToken)));
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("https://huggingface.co", "huggingface.co")]
+  #[case("https://hf-mirror.com/", "hf-mirror.com")]
+  #[case("http://localhost:8080/hf", "localhost:8080")]
+  fn test_hf_hub_service_endpoint_host(
+    temp_hf_home: TempDir,
+    #[case] endpoint: &str,
+    #[case] expected: &str,
+  ) -> anyhow::Result<()> {
+    let mut service = HfHubService::new(temp_hf_home.path().join("huggingface/hub"), false, None);
+    service.endpoint(endpoint);
+    assert_eq!(expected, service.endpoint_host());
+    Ok(())
+  }
 }
@@ -3,8 +3,12 @@ mod data_service;
 pub mod env_wrapper;
 mod hub_service;
 mod env_service;
+mod proxy;
+mod test_env;
 
 pub use app_service::*;
 pub use data_service::*;
 pub use hub_service::*;
 pub use env_service::*;
+pub use proxy::*;
+pub use test_env::*;
@@ -0,0 +1,148 @@
+use super::env_wrapper::EnvWrapper;
+
+pub static HTTPS_PROXY: &str = "HTTPS_PROXY";
+pub static HTTP_PROXY: &str = "HTTP_PROXY";
+pub static ALL_PROXY: &str = "ALL_PROXY";
+pub static NO_PROXY: &str = "NO_PROXY";
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProxyConfig {
+  proxy: Option<String>,
+  no_proxy: Vec<String>,
+}
+
+impl ProxyConfig {
+  pub fn new(proxy: Option<String>, no_proxy: Option<String>) -> Self {
+    let proxy = proxy.filter(|proxy| !proxy.trim().is_empty());
+    let no_proxy = no_proxy
+      .unwrap_or_default()
+      .split(',')
+      .map(|host| host.trim().to_lowercase())
+      .filter(|host| !host.is_empty())
+      .collect::<Vec<_>>();
+    Self { proxy, no_proxy }
+  }
+
+  /// Proxy configuration using the explicit `proxy` if provided, otherwise from
+  /// the $HTTPS_PROXY, $HTTP_PROXY, $ALL_PROXY and $NO_PROXY environment variables.
+  pub fn from_env(proxy: Option<String>, env_wrapper: &EnvWrapper) -> Self {
+    let proxy = proxy.or_else(|| {
+      [HTTPS_PROXY, HTTP_PROXY, ALL_PROXY]
+        .into_iter()
+        .find_map(|key| env_var(env_wrapper, key))
+    });
+    Self::new(proxy, env_var(env_wrapper, NO_PROXY))
+  }
+
+  /// Returns the proxy to use for the given host, `None` if no proxy is configured
+  /// or the host is excluded using $NO_PROXY.
+  pub fn proxy_for(&self, host: &str) -> Option<&str> {
+    let proxy = self.proxy.as_deref()?;
+    let host = host.to_lowercase();
+    let host = host.split(':').next().unwrap_or_default();
+    let excluded = self.no_proxy.iter().any(|pattern| {
+      let pattern = pattern.split(':').next().unwrap_or_default();
+      let domain = pattern.trim_start_matches("*.").trim_start_matches('.');
+      pattern == "*" || host == domain || host.ends_with(&format!(".{domain}"))
+    });
+    if excluded {
+      None
+    } else {
+      Some(proxy)
+    }
+  }
+}
+
+fn env_var(env_wrapper: &EnvWrapper, key: &str) -> Option<String> {
+  env_wrapper
+    .var(key)
+    .or_else(|_| env_wrapper.var(&key.to_lowercase()))
+    .ok()
+    .filter(|value| !value.trim().is_empty())
+}
+
+#[cfg(test)]
+mod test {
+  use super::ProxyConfig;
+  use crate::service::env_wrapper::EnvWrapper;
+  use rstest::rstest;
+  use std::collections::HashMap;
+
+  #[rstest]
+  #[case(None, None, "huggingface.co", None)]
+  #[case(
+    Some("http://proxy:3128"),
+    None,
+    "huggingface.co",
+    Some("http://proxy:3128")
+  )]
+  #[case(
+    Some("http://proxy:3128"),
+    Some("localhost,127.0.0.1"),
+    "huggingface.co",
+    Some("http://proxy:3128")
+  )]
+  #[case(
+    Some("http://proxy:3128"),
+    Some("localhost, huggingface.co"),
+    "huggingface.co",
+    None
+  )]
+  #[case(
+    Some("http://proxy:3128"),
+    Some(".huggingface.co"),
+    "cdn-lfs.huggingface.co",
+    None
+  )]
+  #[case(
+    Some("http://proxy:3128"),
+    Some("*.huggingface.co"),
+    "HuggingFace.co",
+    None
+  )]
+  #[case(Some("http://proxy:3128"), Some("*"), "huggingface.co", None)]
+  #[case(
+    Some("http://proxy:3128"),
+    Some("face.co"),
+    "huggingface.co",
+    Some("http://proxy:3128")
+  )]
+  #[case(Some(" "), None, "huggingface.co", None)]
+  fn test_proxy_config_proxy_for(
+    #[case] proxy: Option<&str>,
+    #[case] no_proxy: Option<&str>,
+    #[case] host: &str,
+    #[case] expected: Option<&str>,
+  ) -> anyhow::Result<()> {
+    let config = ProxyConfig::new(proxy.map(str::to_string), no_proxy.map(str::to_string));
+    assert_eq!(expected, config.proxy_for(host));
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(&[], None, None)]
+  #[case(
+    &[("http_proxy", "http://proxy:3128"), ("no_proxy", "huggingface.co")],
+    Some("http://proxy:3128"),
+    None
+  )]
+  #[case(
+    &[("HTTPS_PROXY", "http://secure:3128"), ("HTTP_PROXY", "http://proxy:3128")],
+    Some("http://secure:3128"),
+    Some("http://secure:3128")
+  )]
+  fn test_proxy_config_from_env(
+    #[case] vars: &[(&str, &str)],
+    #[case] expected_other: Option<&str>,
+    #[case] expected_hf: Option<&str>,
+  ) -> anyhow::Result<()> {
+    let vars = vars
+      .iter()
+      .map(|(key, value)| (key.to_string(), value.to_string()))
+      .collect::<HashMap<_, _>>();
+    let config = ProxyConfig::from_env(None, &EnvWrapper::new(vars, None));
+    assert_eq!(expected_other, config.proxy_for("example.com"));
+    assert_eq!(expected_hf, config.proxy_for("huggingface.co"));
+    Ok(())
+  }
+}
@@ -0,0 +1,135 @@
+use super::{
+  env_wrapper::EnvWrapper, DataServiceError, EnvService, BODHI_HOME, HF_HOME, SETTINGS_YAML,
+};
+use crate::error::Common;
+use std::{
+  collections::HashMap,
+  fs,
+  path::{Path, PathBuf},
+  sync::Arc,
+};
+use tempfile::TempDir;
+
+/// Builds an [`EnvService`] with $BODHI_HOME and $HF_HOME in a new temporary directory,
+/// for the tests of the applications embedding bodhicore.
+/// The environment has only the variables given to the builder, and the process environment
+/// is not read or modified, so the tests using it can run in parallel.
+#[derive(Debug, Default)]
+pub struct TestEnvBuilder {
+  vars: HashMap<String, String>,
+  dotenv: Option<String>,
+  settings_yaml: Option<String>,
+}
+
+impl TestEnvBuilder {
+  pub fn var(mut self, key: &str, value: &str) -> Self {
+    self.vars.insert(key.to_string(), value.to_string());
+    self
+  }
+
+  /// Contents of $BODHI_HOME/.env
+  pub fn dotenv(mut self, contents: &str) -> Self {
+    self.dotenv = Some(contents.to_string());
+    self
+  }
+
+  /// Contents of $BODHI_HOME/settings.yaml
+  pub fn settings_yaml(mut self, contents: &str) -> Self {
+    self.settings_yaml = Some(contents.to_string());
+    self
+  }
+
+  /// Sets up the homes the same way as the app on startup
+  pub fn build(self) -> Result<TestEnv, DataServiceError> {
+    let temp_dir = tempfile::tempdir().map_err(Common::from)?;
+    let mut vars = self.vars;
+    vars.insert(
+      BODHI_HOME.to_string(),
+      temp_dir.path().join("bodhi").display().to_string(),
+    );
+    vars.insert(
+      HF_HOME.to_string(),
+      temp_dir.path().join("huggingface").display().to_string(),
+    );
+    let env_wrapper = EnvWrapper::new(vars, Some(temp_dir.path().to_path_buf()));
+    let mut env_service = EnvService::new(env_wrapper);
+    let bodhi_home = env_service.setup_bodhi_home()?;
+    for (filename, contents) in [(".env", self.dotenv), (SETTINGS_YAML, self.settings_yaml)] {
+      let Some(contents) = contents else {
+        continue;
+      };
+      let path = bodhi_home.join(filename);
+      fs::write(&path, contents).map_err(|source| Common::IoFile {
+        source,
+        path: path.display().to_string(),
+      })?;
+    }
+    env_service.load_dotenv();
+    env_service.setup_hf_cache()?;
+    env_service.setup_logs_dir()?;
+    Ok(TestEnv {
+      temp_dir,
+      bodhi_home,
+      env_service: Arc::new(env_service),
+    })
+  }
+}
+
+/// Isolated environment created by [`TestEnvBuilder`], the temporary directory is removed on drop
+#[derive(Debug)]
+pub struct TestEnv {
+  temp_dir: TempDir,
+  bodhi_home: PathBuf,
+  env_service: Arc<EnvService>,
+}
+
+impl TestEnv {
+  pub fn root(&self) -> &Path {
+    self.temp_dir.path()
+  }
+
+  pub fn bodhi_home(&self) -> &Path {
+    &self.bodhi_home
+  }
+
+  pub fn env_service(&self) -> Arc<EnvService> {
+    self.env_service.clone()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::TestEnvBuilder;
+  use crate::{
+    objs::DisplayTimezone,
+    service::{EnvServiceFn, BODHI_PORT},
+  };
+  use rstest::rstest;
+
+  #[rstest]
+  fn test_test_env_builder_creates_isolated_homes() -> anyhow::Result<()> {
+    let first = TestEnvBuilder::default()
+      .var(BODHI_PORT, "8080")
+      .dotenv("BODHI_PORT=9090\nBODHI_TIMEZONE=utc\nTEST_ENV_BUILDER=dotenv\n")
+      .settings_yaml("routes:\n  ollama_api: true\n")
+      .build()?;
+    let second = TestEnvBuilder::default().build()?;
+
+    let env_service = first.env_service();
+    assert_eq!(first.root().join("bodhi"), env_service.bodhi_home());
+    assert_eq!(first.root().join("huggingface"), env_service.hf_home());
+    assert!(env_service.hf_cache().exists());
+    assert!(env_service.db_path().exists());
+    assert!(env_service.logs_dir().starts_with(first.bodhi_home()));
+    assert_eq!(8080, env_service.port());
+    assert_eq!(DisplayTimezone::Utc, env_service.timezone());
+    assert!(env_service.route_settings().ollama_api);
+    assert!(std::env::var("TEST_ENV_BUILDER").is_err());
+
+    let env_service = second.env_service();
+    assert_ne!(first.root(), second.root());
+    assert_eq!(1135, env_service.port());
+    assert!(!env_service.route_settings().ollama_api);
+    Ok(())
+  }
+}
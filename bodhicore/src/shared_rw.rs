@@ -3,25 +3,42 @@ use llama_server_bindings::BodhiServerContext;
 #[cfg(test)]
 use crate::test_utils::MockBodhiServerContext as BodhiServerContext;
 
-use validator::{Validate, ValidationErrors};
 use crate::error::Common;
-use crate::objs::{Alias, HubFile, ObjError};
+use crate::gguf::MemoryEstimate;
+use crate::objs::{
+  Alias, Backend, ChatCompletionRequest, HubFile, ObjError, RerankRequest, Truncation,
+};
 use crate::service::DataServiceError;
 use tokio::sync::mpsc::Sender;
-use crate::tokenizer_config::TokenizerConfig;
-use async_openai::types::CreateChatCompletionRequest;
+use crate::service::TemplateLimits;
+use crate::tokenizer_config::{ChatTemplateCache, CompiledChatTemplate, TemplateError};
+use crate::utils::human_size;
+use async_openai::types::ChatCompletionRequestMessage;
 use llama_server_bindings::{LlamaCppError, GptParams, GptParamsBuilder, GptParamsBuilderError};
 use std::ffi::{c_char, c_void};
+use std::path::{Path, PathBuf};
 use std::slice;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use thiserror::Error;
 use tokio::sync::RwLock;
+use validator::ValidationErrors;
+
+// the errors of llama.cpp running out of memory for the batch, the memory is freed once the other
+// requests are done
+static OUT_OF_MEMORY_ERRORS: &[&str] =
+  &["failed to find free space in the KV cache", "out of memory"];
 
 #[derive(Debug)]
 pub struct SharedContextRw {
   ctx: RwLock<Option<BodhiServerContext>>,
+  // refuse to load the models needing more memory than available, instead of warning
+  strict_memory: bool,
+  // picked on startup, `cpu` keeps all the models loaded later off the GPU
+  backend: Backend,
+  // the chat templates are parsed once, not for each request
+  templates: ChatTemplateCache,
 }
 
 #[derive(Debug, Error)]
@@ -40,10 +57,47 @@ pub enum ContextError {
   Validation(#[from] ValidationErrors),
   #[error(transparent)]
   Minijina(#[from] minijinja::Error),
+  #[error(transparent)]
+  Template(#[from] TemplateError),
+  #[error("the prompt has {prompt_tokens} tokens, more than the context length of {n_ctx} tokens")]
+  ContextLengthExceeded { prompt_tokens: usize, n_ctx: usize },
+  #[error("model '{model}' needs about {required} of memory, more than the {available} available, reduce the n_ctx or use a smaller quantization")]
+  InsufficientMemory {
+    model: String,
+    required: String,
+    available: String,
+  },
+  #[error("the inference worker exited while serving the request, and was restarted")]
+  WorkerCrashed,
+  #[error("{0}")]
+  Worker(String),
   #[error("{0}")]
   Unreachable(String),
 }
 
+impl ContextError {
+  /// Failures which may succeed when the same request is attempted again, the crash of the
+  /// inference worker restarted since, or llama.cpp running out of memory for the batch. The
+  /// other llama.cpp errors, e.g. invalid params, fail the same way again.
+  pub(crate) fn is_transient(&self) -> bool {
+    match self {
+      ContextError::WorkerCrashed => true,
+      ContextError::BodhiError(err) => {
+        let message = err.to_string();
+        OUT_OF_MEMORY_ERRORS.iter().any(|oom| message.contains(oom))
+      }
+      _ => false,
+    }
+  }
+
+  /// Failures of the context itself, instead of the request, after which the watchdog reloads
+  /// the model. The llama.cpp errors are failures of the request, e.g. invalid params, the
+  /// context keeps serving the other requests.
+  pub(crate) fn is_context_failure(&self) -> bool {
+    matches!(self, ContextError::WorkerCrashed)
+  }
+}
+
 pub type Result<T> = std::result::Result<T, ContextError>;
 
 unsafe extern "C" fn callback_stream(
@@ -61,8 +115,10 @@ unsafe extern "C" fn callback_stream(
   let sender = userdata.0.clone();
   let receiver_status = userdata.1.clone();
 
-  if !receiver_status.load(Ordering::SeqCst) {
-      return 0;
+  // the client has disconnected, returning 0 stops the generation
+  if sender.is_closed() || !receiver_status.load(Ordering::SeqCst) {
+    tracing::info!("receiver closed, stopping the generation");
+    return 0;
   }
 
   tokio::spawn(async move {
@@ -74,22 +130,55 @@ unsafe extern "C" fn callback_stream(
   size
 }
 
+// the tokens generated to warm up the model are not sent anywhere
+unsafe extern "C" fn callback_discard(
+  _contents: *const c_char,
+  size: usize,
+  _callback_userdata: *mut c_void,
+) -> usize {
+  size
+}
+
 #[async_trait::async_trait]
 pub trait SharedContextRwFn: std::fmt::Debug + Send + Sync {
   async fn reload(&self, gpt_params: Option<GptParams>) -> Result<()>;
 
   async fn try_stop(&self) -> Result<()>;
 
+  /// Stops the context without waiting for the running requests, used by the watchdog to recover
+  /// a hung context. The context loaded in the process can only be stopped once the running
+  /// requests finish, same as [`SharedContextRwFn::try_stop`].
+  async fn force_stop(&self) -> Result<()>;
+
   async fn has_model(&self) -> bool;
 
   async fn get_gpt_params(&self) -> Result<Option<GptParams>>;
 
+  /// Generates a single token using the loaded model, so the weights are paged in and the
+  /// GPU kernels are compiled before the first request
+  async fn warmup(&self) -> Result<()>;
+
   async fn chat_completions(
     &self,
-    mut request: CreateChatCompletionRequest,
+    mut request: ChatCompletionRequest,
     alias: Alias,
     model_file: HubFile,
     tokenizer_file: HubFile,
+    template_limits: TemplateLimits,
+    userdata: Sender<String>,
+  ) -> Result<()>;
+
+  /// Drops the chat templates compiled from the tokenizer files, e.g. once the model aliases
+  /// using them are changed, so the next requests compile them again
+  async fn invalidate_chat_templates(&self, tokenizer_files: Vec<PathBuf>) -> Result<()>;
+
+  /// Scores the documents against the query using the reranker model,
+  /// the scores are sent to `userdata` as a single JSON message
+  async fn rerank(
+    &self,
+    request: RerankRequest,
+    alias: Alias,
+    model_file: HubFile,
     userdata: Sender<String>,
   ) -> Result<()>;
 }
@@ -99,8 +188,22 @@ impl SharedContextRw {
   where
     Self: Sized,
   {
+    Self::new_with_options(gpt_params, false, Backend::Auto).await
+  }
+
+  /// Same as [`SharedContextRw::new_shared_rw`], with `strict_memory` failing the loading of
+  /// the models estimated to need more memory than available, instead of logging a warning,
+  /// and the models loaded using the `backend` picked on startup
+  pub async fn new_with_options(
+    gpt_params: Option<GptParams>,
+    strict_memory: bool,
+    backend: Backend,
+  ) -> Result<Self> {
     let ctx = SharedContextRw {
       ctx: RwLock::new(None),
+      strict_memory,
+      backend,
+      templates: ChatTemplateCache::default(),
     };
     ctx.reload(gpt_params).await?;
     Ok(ctx)
@@ -114,12 +217,15 @@ impl SharedContextRwFn for SharedContextRw {
     lock.as_ref().is_some()
   }
 
+  #[tracing::instrument(skip_all)]
   async fn reload(&self, gpt_params: Option<GptParams>) -> crate::shared_rw::Result<()> {
     let mut lock = self.ctx.write().await;
     try_stop_with(&mut lock)?;
-    let Some(gpt_params) = gpt_params else {
+    let Some(mut gpt_params) = gpt_params else {
       return Ok(());
     };
+    self.backend.update(&mut gpt_params);
+    check_memory(&gpt_params, self.strict_memory)?;
     let ctx = BodhiServerContext::new(gpt_params)?;
     *lock = Some(ctx);
     let Some(ctx) = lock.as_ref() else {
@@ -140,6 +246,11 @@ impl SharedContextRwFn for SharedContextRw {
     Ok(())
   }
 
+  async fn force_stop(&self) -> crate::shared_rw::Result<()> {
+    // llama.cpp cannot be interrupted mid-generation in the process, waits for the lock instead
+    self.try_stop().await
+  }
+
   async fn get_gpt_params(&self) -> crate::shared_rw::Result<Option<GptParams>> {
     let lock = self.ctx.read().await;
     if let Some(opt) = lock.as_ref() {
@@ -149,33 +260,58 @@ impl SharedContextRwFn for SharedContextRw {
     }
   }
 
+  async fn warmup(&self) -> crate::shared_rw::Result<()> {
+    let lock = self.ctx.read().await;
+    let Some(ctx) = lock.as_ref() else {
+      return Ok(());
+    };
+    let input = serde_json::json!({"prompt": "hello", "n_predict": 1}).to_string();
+    let started = Instant::now();
+    ctx.completions(&input, "", Some(callback_discard), std::ptr::null_mut())?;
+    tracing::info!(
+      elapsed_ms = started.elapsed().as_millis() as u64,
+      "warmed up the model"
+    );
+    Ok(())
+  }
+
+  #[tracing::instrument(skip_all, fields(model_file = %model_file.filename))]
   async fn chat_completions(
     &self,
-    mut request: CreateChatCompletionRequest,
+    mut request: ChatCompletionRequest,
     alias: Alias,
     model_file: HubFile,
     tokenizer_file: HubFile,
+    template_limits: TemplateLimits,
     userdata: Sender<String>,
   ) -> crate::shared_rw::Result<()> {
     let lock = self.ctx.read().await;
+    // the client disconnected while waiting for the context
+    if userdata.is_closed() {
+      tracing::info!("receiver closed, skipping the generation");
+      return Ok(());
+    }
     let ctx = lock.as_ref();
     let loaded_model = ctx.map(|ctx| ctx.get_gpt_params().model.clone());
     let request_model = model_file.path().display().to_string();
-    let chat_template: TokenizerConfig = TokenizerConfig::try_from(tokenizer_file)?;
-    chat_template.validate()?;
+    let chat_template = self
+      .templates
+      .get_or_compile(tokenizer_file, &template_limits)?;
     alias.request_params.update(&mut request);
-    let prompt = chat_template.apply_chat_template(&request.messages)?;
-    let mut input_value = serde_json::to_value(request).map_err(Common::SerdeJsonDeserialize)?;
-    input_value["prompt"] = serde_json::Value::String(prompt);
-    let input = serde_json::to_string(&input_value).map_err(Common::SerdeJsonDeserialize)?;
+    alias.chat_template.update(&mut request);
+    let truncation = alias.context_params.truncation.unwrap_or_default();
     let callback_userdata = (userdata, Arc::new(AtomicBool::new(true)));
     match ModelLoadStrategy::choose(&loaded_model, &request_model) {
       ModelLoadStrategy::Continue => {
-        ctx
-          .ok_or_else(||ContextError::Unreachable(
-            "context should not be None".to_string(),
-          ))?
-          .completions(&input, "", Some(callback_stream), &callback_userdata as *const _ as *mut _)?;
+        let ctx =
+          ctx.ok_or_else(|| ContextError::Unreachable("context should not be None".to_string()))?;
+        let input = completions_input(ctx, request, &chat_template, truncation)?;
+        ctx.completions(
+          &input,
+          "",
+          Some(callback_stream),
+          &callback_userdata as *const _ as *mut _,
+        )?;
         Ok(())
       }
       ModelLoadStrategy::DropAndLoad => {
@@ -184,11 +320,16 @@ impl SharedContextRwFn for SharedContextRw {
         alias.context_params.update(&mut new_gpt_params);
         self.reload(Some(new_gpt_params)).await?;
         let lock = self.ctx.read().await;
-        let ctx = lock.as_ref();
-        ctx.ok_or_else(||ContextError::Unreachable(
-          "context should not be None".to_string(),
-        ))?
-        .completions(&input, "", Some(callback_stream), &callback_userdata as *const _ as *mut _)?;
+        let ctx = lock
+          .as_ref()
+          .ok_or_else(|| ContextError::Unreachable("context should not be None".to_string()))?;
+        let input = completions_input(ctx, request, &chat_template, truncation)?;
+        ctx.completions(
+          &input,
+          "",
+          Some(callback_stream),
+          &callback_userdata as *const _ as *mut _,
+        )?;
         Ok(())
       }
       ModelLoadStrategy::Load => {
@@ -199,15 +340,172 @@ impl SharedContextRwFn for SharedContextRw {
         drop(lock);
         self.reload(Some(new_gpt_params)).await?;
         let lock = self.ctx.read().await;
-        let ctx = lock.as_ref();
-        ctx.ok_or_else(||ContextError::Unreachable(
-          "context should not be None".to_string(),
-        ))?
-        .completions(&input, "", Some(callback_stream), &callback_userdata as *const _ as *mut _)?;
+        let ctx = lock
+          .as_ref()
+          .ok_or_else(|| ContextError::Unreachable("context should not be None".to_string()))?;
+        let input = completions_input(ctx, request, &chat_template, truncation)?;
+        ctx.completions(
+          &input,
+          "",
+          Some(callback_stream),
+          &callback_userdata as *const _ as *mut _,
+        )?;
         Ok(())
       },
     }
   }
+
+  #[tracing::instrument(skip_all, fields(model_file = %model_file.filename))]
+  async fn rerank(
+    &self,
+    request: RerankRequest,
+    alias: Alias,
+    model_file: HubFile,
+    userdata: Sender<String>,
+  ) -> crate::shared_rw::Result<()> {
+    let input =
+      serde_json::to_string(&request.llama_input()).map_err(Common::SerdeJsonDeserialize)?;
+    let request_model = model_file.path().display().to_string();
+    let callback_userdata = (userdata, Arc::new(AtomicBool::new(true)));
+    let lock = self.ctx.read().await;
+    let loaded_model = lock.as_ref().map(|ctx| ctx.get_gpt_params().model.clone());
+    let lock = match ModelLoadStrategy::choose(&loaded_model, &request_model) {
+      ModelLoadStrategy::Continue => lock,
+      ModelLoadStrategy::DropAndLoad | ModelLoadStrategy::Load => {
+        drop(lock);
+        // the reranker models score the query and document pairs, instead of generating tokens
+        let mut new_gpt_params = GptParamsBuilder::default().model(request_model).build()?;
+        alias.context_params.update(&mut new_gpt_params);
+        new_gpt_params.reranking = true;
+        self.reload(Some(new_gpt_params)).await?;
+        self.ctx.read().await
+      }
+    };
+    lock
+      .as_ref()
+      .ok_or_else(|| ContextError::Unreachable("context should not be None".to_string()))?
+      .rerank(
+        &input,
+        Some(callback_stream),
+        &callback_userdata as *const _ as *mut _,
+      )?;
+    Ok(())
+  }
+
+  async fn invalidate_chat_templates(
+    &self,
+    tokenizer_files: Vec<PathBuf>,
+  ) -> crate::shared_rw::Result<()> {
+    self.templates.invalidate(&tokenizer_files);
+    Ok(())
+  }
+}
+
+/// The llama.cpp completions input for the request, with the prompt rendered using the chat template
+fn completions_input(
+  ctx: &BodhiServerContext,
+  mut request: ChatCompletionRequest,
+  chat_template: &CompiledChatTemplate,
+  truncation: Truncation,
+) -> Result<String> {
+  let prompt = render_prompt(ctx, &mut request, chat_template, truncation)?;
+  // validated by the router, an invalid map is passed as-is for the other callers
+  let logit_bias = request.llama_logit_bias().ok().flatten();
+  let mut input_value = serde_json::to_value(request).map_err(Common::SerdeJsonDeserialize)?;
+  input_value["prompt"] = serde_json::Value::String(prompt);
+  if let Some(logit_bias) = logit_bias {
+    input_value["logit_bias"] = logit_bias;
+  }
+  let input = serde_json::to_string(&input_value).map_err(Common::SerdeJsonDeserialize)?;
+  Ok(input)
+}
+
+/// Renders the messages of the request using the chat template. If the prompt does not fit in
+/// the context of a slot, the turns of the chat history are dropped as per the `truncation`
+/// of the alias, until it fits.
+fn render_prompt(
+  ctx: &BodhiServerContext,
+  request: &mut ChatCompletionRequest,
+  chat_template: &CompiledChatTemplate,
+  truncation: Truncation,
+) -> Result<String> {
+  let render = |messages: &[ChatCompletionRequestMessage]| {
+    tracing::info_span!("render_chat_template").in_scope(|| chat_template.render(messages))
+  };
+  let prompt = render(&request.messages)?;
+  let Some(n_ctx) = slot_context_length(ctx) else {
+    return Ok(prompt);
+  };
+  let prompt_tokens = ctx.tokenize(&prompt, false)?.len();
+  if prompt_tokens < n_ctx {
+    return Ok(prompt);
+  }
+  let mut dropped = 1;
+  while let Some(messages) = truncation.truncate(&request.messages, dropped) {
+    let prompt = render(&messages)?;
+    if ctx.tokenize(&prompt, false)?.len() < n_ctx {
+      tracing::info!(
+        %truncation, dropped, prompt_tokens, n_ctx,
+        "truncated the chat history to fit the context"
+      );
+      request.request.messages = messages;
+      return Ok(prompt);
+    }
+    dropped += 1;
+  }
+  Err(ContextError::ContextLengthExceeded {
+    prompt_tokens,
+    n_ctx,
+  })
+}
+
+/// Context length of a slot, as llama.cpp divides the `n_ctx` context length evenly between
+/// the `n_parallel` slots. None if `n_ctx` is 0 or unset, for the context length the model
+/// was trained with, known only to llama.cpp.
+fn slot_context_length(ctx: &BodhiServerContext) -> Option<usize> {
+  let gpt_params = ctx.get_gpt_params();
+  let n_ctx = gpt_params.n_ctx.filter(|n_ctx| *n_ctx > 0)?;
+  Some((n_ctx / gpt_params.n_parallel.unwrap_or(1).max(1)) as usize)
+}
+
+/// Checks the memory estimated from the GGUF metadata against the available memory, so a
+/// model too large fails with an error, or a warning, instead of the OOM killer taking down
+/// the process mid-load. On Apple silicon the GPU shares the same memory, the VRAM of the
+/// discrete GPUs is not checked.
+fn check_memory(gpt_params: &GptParams, strict: bool) -> Result<()> {
+  let estimate = match MemoryEstimate::read(Path::new(&gpt_params.model), gpt_params.n_ctx) {
+    Ok(estimate) => estimate,
+    Err(err) => {
+      tracing::debug!(
+        ?err,
+        model = %gpt_params.model,
+        "cannot estimate the memory needed by the model"
+      );
+      return Ok(());
+    }
+  };
+  let mut system = sysinfo::System::new();
+  system.refresh_memory();
+  let available = system.available_memory();
+  // 0 when the platform does not report it
+  if available == 0 || estimate.total() <= available {
+    return Ok(());
+  }
+  let err = ContextError::InsufficientMemory {
+    model: gpt_params.model.clone(),
+    required: human_size(estimate.total()),
+    available: human_size(available),
+  };
+  if strict {
+    return Err(err);
+  }
+  tracing::warn!(
+    weights = estimate.weights,
+    kv_cache = estimate.kv_cache,
+    n_ctx = estimate.n_ctx,
+    "{err}"
+  );
+  Ok(())
 }
 
 fn try_stop_with(
@@ -247,15 +545,24 @@ impl ModelLoadStrategy {
 #[cfg(test)]
 mod test {
   use crate::{
-    objs::{Alias, HubFile},
-    shared_rw::{ModelLoadStrategy, SharedContextRw, SharedContextRwFn},
-    test_utils::{hf_cache, test_channel, MockBodhiServerContext},
+    gguf::GGUFValue,
+    objs::{
+      Alias, Backend, ChatCompletionRequest, GptContextParams, HubFile, RerankRequest, Truncation,
+    },
+    service::TemplateLimits,
+    shared_rw::{
+      check_memory, ContextError, ModelLoadStrategy, SharedContextRw, SharedContextRwFn,
+    },
+    test_utils::{
+      hf_cache, llama_vocab_metadata, test_channel, write_gguf, MockBodhiServerContext,
+    },
   };
   use anyhow::anyhow;
   use anyhow_trace::anyhow_trace;
-  use async_openai::types::{CreateChatCompletionRequest, CreateChatCompletionResponse};
+  use async_openai::types::CreateChatCompletionResponse;
   use llama_server_bindings::{
     bindings::llama_server_disable_logging, disable_llama_log, GptParams, GptParamsBuilder,
+    LlamaCppError,
   };
   use mockall::predicate::{always, eq};
   use rstest::{fixture, rstest};
@@ -457,7 +764,7 @@ mod test {
       .unwrap();
     let mut mock = MockBodhiServerContext::default();
     let expected_input =
-      "{\"messages\":[{\"content\":\"What day comes after Monday?\",\"role\":\"user\"}],\"model\":\"testalias:instruct\",\"prompt\":\"<|begin_of_text|><|start_header_id|>user<|end_header_id|>\\n\\nWhat day comes after Monday?<|eot_id|><|start_header_id|>assistant<|end_header_id|>\\n\\n\"}";
+      "{\"messages\":[{\"content\":\"What day comes after Monday?\",\"role\":\"user\"}],\"model\":\"testalias:instruct\",\"prompt\":\"<|begin_of_text|><|start_header_id|>user<|end_header_id|>\\n\\nWhat day comes after Monday?<|eot_id|><|start_header_id|>assistant<|end_header_id|>\\n\\n\",\"stop\":[\"<|eot_id|>\",\"<|end_of_text|>\"]}";
     mock.expect_init().with().return_once(|| Ok(()));
     mock.expect_start_event_loop().with().return_once(|| Ok(()));
     mock
@@ -466,19 +773,75 @@ mod test {
       .return_once(|_, _, _, _| Ok(()));
     let gpt_params = GptParamsBuilder::default().model(model_filepath).build()?;
     let gpt_params_cl = gpt_params.clone();
-    mock.expect_get_gpt_params().return_once(move || gpt_params_cl);
+    mock
+      .expect_get_gpt_params()
+      .returning(move || gpt_params_cl.clone());
 
     let ctx = MockBodhiServerContext::new_context();
     ctx.expect().with(eq(gpt_params.clone())).return_once(move |_| Ok(mock));
 
     let shared_ctx = SharedContextRw::new_shared_rw(Some(gpt_params)).await?;
-    let request = serde_json::from_value::<CreateChatCompletionRequest>(json! {{
+    let request = serde_json::from_value::<ChatCompletionRequest>(json! {{
       "model": "testalias:instruct",
       "messages": [{"role": "user", "content": "What day comes after Monday?"}]
     }})?;
     let (tx, _rx) = test_channel();
     shared_ctx
-      .chat_completions(request, Alias::testalias(), model_file, tokenizer_file, tx)
+      .chat_completions(
+        request,
+        Alias::testalias(),
+        model_file,
+        tokenizer_file,
+        TemplateLimits::default(),
+        tx,
+      )
+      .await?;
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[serial(BodhiServerContext)]
+  #[anyhow_trace]
+  async fn test_rerank_continue_strategy(hf_cache: (TempDir, PathBuf)) -> anyhow::Result<()> {
+    let (_temp, hf_cache) = hf_cache;
+    let model_file = HubFile::testalias_builder()
+      .hf_cache(hf_cache.clone())
+      .build()
+      .unwrap();
+    let model_filepath = model_file.path().display().to_string();
+    let mut mock = MockBodhiServerContext::default();
+    mock.expect_init().with().return_once(|| Ok(()));
+    mock.expect_start_event_loop().with().return_once(|| Ok(()));
+    mock
+      .expect_rerank()
+      .with(
+        eq("{\"documents\":[\"Paris is the capital of France\"],\"query\":\"What is the capital of France?\"}"),
+        always(),
+        always(),
+      )
+      .return_once(|_, _, _| Ok(()));
+    let gpt_params = GptParamsBuilder::default().model(model_filepath).build()?;
+    let gpt_params_cl = gpt_params.clone();
+    mock
+      .expect_get_gpt_params()
+      .return_once(move || gpt_params_cl);
+
+    let ctx = MockBodhiServerContext::new_context();
+    ctx
+      .expect()
+      .with(eq(gpt_params.clone()))
+      .return_once(move |_| Ok(mock));
+
+    let shared_ctx = SharedContextRw::new_shared_rw(Some(gpt_params)).await?;
+    let request = serde_json::from_value::<RerankRequest>(json! {{
+      "model": "testalias:instruct",
+      "query": "What is the capital of France?",
+      "documents": ["Paris is the capital of France"]
+    }})?;
+    let (tx, _rx) = test_channel();
+    shared_ctx
+      .rerank(request, Alias::testalias(), model_file, tx)
       .await?;
     Ok(())
   }
@@ -502,29 +865,140 @@ mod test {
       .unwrap();
     let mut mock = MockBodhiServerContext::default();
     let expected_input = 
-      "{\"messages\":[{\"content\":\"What day comes after Monday?\",\"role\":\"user\"}],\"model\":\"testalias:instruct\",\"prompt\":\"<|begin_of_text|><|start_header_id|>user<|end_header_id|>\\n\\nWhat day comes after Monday?<|eot_id|><|start_header_id|>assistant<|end_header_id|>\\n\\n\"}";
+      "{\"messages\":[{\"content\":\"What day comes after Monday?\",\"role\":\"user\"}],\"model\":\"testalias:instruct\",\"prompt\":\"<|begin_of_text|><|start_header_id|>user<|end_header_id|>\\n\\nWhat day comes after Monday?<|eot_id|><|start_header_id|>assistant<|end_header_id|>\\n\\n\",\"stop\":[\"<|eot_id|>\",\"<|end_of_text|>\"]}";
     mock.expect_init().with().return_once(|| Ok(()));
     mock.expect_start_event_loop().with().return_once(|| Ok(()));
     mock
       .expect_completions()
       .with(eq(expected_input), eq(""), always(), always())
       .return_once(|_, _, _, _| Ok(()));
+    let gpt_params = GptParams {
+      model: model_filepath,
+      ..Default::default()
+    };
+    let gpt_params_cl = gpt_params.clone();
+    mock
+      .expect_get_gpt_params()
+      .return_once(move || gpt_params_cl);
 
     let ctx = MockBodhiServerContext::new_context();
-    ctx.expect().with(eq(GptParams{model: model_filepath, ..Default::default()})).return_once(move |_| Ok(mock));
+    ctx
+      .expect()
+      .with(eq(gpt_params))
+      .return_once(move |_| Ok(mock));
 
     let shared_ctx = SharedContextRw::new_shared_rw(None).await?;
-    let request = serde_json::from_value::<CreateChatCompletionRequest>(json! {{
+    let request = serde_json::from_value::<ChatCompletionRequest>(json! {{
       "model": "testalias:instruct",
       "messages": [{"role": "user", "content": "What day comes after Monday?"}]
     }})?;
     let (tx, _rx) = test_channel();
     shared_ctx
-      .chat_completions(request, Alias::testalias(), model_file, tokenizer_file, tx)
+      .chat_completions(
+        request,
+        Alias::testalias(),
+        model_file,
+        tokenizer_file,
+        TemplateLimits::default(),
+        tx,
+      )
       .await?;
     Ok(())
   }
 
+  #[rstest]
+  fn test_check_memory_refuses_too_large_model_if_strict() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let path = temp.path().join("model.gguf");
+    let mut metadata = llama_vocab_metadata();
+    metadata.extend([
+      ("llama.block_count", GGUFValue::U32(32)),
+      ("llama.embedding_length", GGUFValue::U32(4096)),
+      ("llama.attention.head_count", GGUFValue::U32(32)),
+    ]);
+    write_gguf(&path, metadata)?;
+    let gpt_params = GptParams {
+      model: path.display().to_string(),
+      n_ctx: Some(i32::MAX),
+      ..Default::default()
+    };
+    check_memory(&gpt_params, false)?;
+    let result = check_memory(&gpt_params, true);
+    assert!(matches!(
+      result,
+      Err(ContextError::InsufficientMemory { model, .. }) if model == gpt_params.model
+    ));
+    let gpt_params = GptParams {
+      n_ctx: Some(16),
+      ..gpt_params
+    };
+    check_memory(&gpt_params, true)?;
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[serial(BodhiServerContext)]
+  #[anyhow_trace]
+  async fn test_shared_rw_warmup_generates_a_token() -> anyhow::Result<()> {
+    let mut mock = MockBodhiServerContext::default();
+    mock.expect_init().with().return_once(|| Ok(()));
+    mock.expect_start_event_loop().with().return_once(|| Ok(()));
+    mock
+      .expect_completions()
+      .with(
+        eq(r#"{"n_predict":1,"prompt":"hello"}"#),
+        eq(""),
+        always(),
+        always(),
+      )
+      .times(1)
+      .return_once(|_, _, _, _| Ok(()));
+    let gpt_params = GptParams {
+      model: "testalias.Q8_0.gguf".to_string(),
+      ..Default::default()
+    };
+    let ctx = MockBodhiServerContext::new_context();
+    ctx
+      .expect()
+      .with(eq(gpt_params.clone()))
+      .return_once(move |_| Ok(mock));
+
+    let shared_ctx = SharedContextRw::new_shared_rw(Some(gpt_params)).await?;
+    shared_ctx.warmup().await?;
+    SharedContextRw::new_shared_rw(None).await?.warmup().await?;
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[serial(BodhiServerContext)]
+  #[anyhow_trace]
+  async fn test_shared_rw_cpu_backend_loads_without_gpu_layers() -> anyhow::Result<()> {
+    let mut mock = MockBodhiServerContext::default();
+    mock.expect_init().with().return_once(|| Ok(()));
+    mock.expect_start_event_loop().with().return_once(|| Ok(()));
+    let gpt_params = GptParams {
+      model: "testalias.Q8_0.gguf".to_string(),
+      n_gpu_layers: Some(99),
+      ..Default::default()
+    };
+    let expected = GptParams {
+      n_gpu_layers: Some(0),
+      ..gpt_params.clone()
+    };
+    let ctx = MockBodhiServerContext::new_context();
+    ctx
+      .expect()
+      .with(eq(expected))
+      .return_once(move |_| Ok(mock));
+
+    let shared_ctx =
+      SharedContextRw::new_with_options(Some(gpt_params), false, Backend::Cpu).await?;
+    assert!(shared_ctx.has_model().await);
+    Ok(())
+  }
+
   #[rstest]
   #[tokio::test]
   #[serial(BodhiServerContext)]
@@ -543,10 +1017,12 @@ mod test {
     loaded_ctx.expect_start_event_loop().with().return_once(|| Ok(()));
     let loaded_params = GptParamsBuilder::default().model(loaded_model_filepath).build()?;
     let loaded_params_cl = loaded_params.clone();
-    loaded_ctx.expect_get_gpt_params().return_once(move || loaded_params_cl);
+    loaded_ctx
+      .expect_get_gpt_params()
+      .returning(move || loaded_params_cl.clone());
     loaded_ctx.expect_stop().with().return_once(|| Ok(()));
     let expected_input =
-      "{\"messages\":[{\"content\":\"What day comes after Monday?\",\"role\":\"user\"}],\"model\":\"fakemodel:instruct\",\"prompt\":\"<|begin_of_text|><|start_header_id|>user<|end_header_id|>\\n\\nWhat day comes after Monday?<|eot_id|><|start_header_id|>assistant<|end_header_id|>\\n\\n\"}";
+      "{\"messages\":[{\"content\":\"What day comes after Monday?\",\"role\":\"user\"}],\"model\":\"fakemodel:instruct\",\"prompt\":\"<|begin_of_text|><|start_header_id|>user<|end_header_id|>\\n\\nWhat day comes after Monday?<|eot_id|><|start_header_id|>assistant<|end_header_id|>\\n\\n\",\"stop\":[\"<|eot_id|>\",\"<|end_of_text|>\"]}";
     loaded_ctx
       .expect_completions()
       .with(eq(expected_input), eq(""), always(), always())
@@ -572,13 +1048,204 @@ mod test {
       .unwrap();
 
     let shared_ctx = SharedContextRw::new_shared_rw(Some(loaded_params)).await?;
-    let request = serde_json::from_value::<CreateChatCompletionRequest>(json! {{
+    let request = serde_json::from_value::<ChatCompletionRequest>(json! {{
       "model": "fakemodel:instruct",
       "messages": [{"role": "user", "content": "What day comes after Monday?"}]
     }})?;
     let (tx, _rx) = test_channel();
     shared_ctx
-      .chat_completions(request, Alias::testalias(), loaded_model, tokenizer_file, tx)
+      .chat_completions(
+        request,
+        Alias::testalias(),
+        loaded_model,
+        tokenizer_file,
+        TemplateLimits::default(),
+        tx,
+      )
       .await?;
     Ok(())
-  }}
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[serial(BodhiServerContext)]
+  #[anyhow_trace]
+  async fn test_chat_completions_fails_if_prompt_exceeds_context_length(
+    hf_cache: (TempDir, PathBuf),
+  ) -> anyhow::Result<()> {
+    let (_temp, hf_cache) = hf_cache;
+    let model_file = HubFile::testalias_builder()
+      .hf_cache(hf_cache.clone())
+      .build()
+      .unwrap();
+    let model_filepath = model_file.path().display().to_string();
+    let tokenizer_file = HubFile::testalias_tokenizer_builder()
+      .hf_cache(hf_cache.clone())
+      .build()
+      .unwrap();
+    let mut mock = MockBodhiServerContext::default();
+    mock.expect_init().with().return_once(|| Ok(()));
+    mock.expect_start_event_loop().with().return_once(|| Ok(()));
+    mock
+      .expect_tokenize()
+      .with(always(), eq(false))
+      .return_once(|_, _| Ok(vec![1; 600]));
+    mock.expect_completions().never();
+    let gpt_params = GptParams {
+      model: model_filepath,
+      n_ctx: Some(2048),
+      n_parallel: Some(4),
+      ..Default::default()
+    };
+    let gpt_params_cl = gpt_params.clone();
+    mock
+      .expect_get_gpt_params()
+      .returning(move || gpt_params_cl.clone());
+
+    let ctx = MockBodhiServerContext::new_context();
+    ctx
+      .expect()
+      .with(eq(gpt_params.clone()))
+      .return_once(move |_| Ok(mock));
+
+    let shared_ctx = SharedContextRw::new_shared_rw(Some(gpt_params)).await?;
+    let request = serde_json::from_value::<ChatCompletionRequest>(json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}]
+    }})?;
+    let (tx, _rx) = test_channel();
+    let result = shared_ctx
+      .chat_completions(
+        request,
+        Alias::testalias(),
+        model_file,
+        tokenizer_file,
+        TemplateLimits::default(),
+        tx,
+      )
+      .await;
+    assert!(matches!(
+      result,
+      Err(ContextError::ContextLengthExceeded {
+        prompt_tokens: 600,
+        n_ctx: 512
+      })
+    ));
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[serial(BodhiServerContext)]
+  #[anyhow_trace]
+  async fn test_chat_completions_truncates_history_to_fit_context_length(
+    hf_cache: (TempDir, PathBuf),
+  ) -> anyhow::Result<()> {
+    let (_temp, hf_cache) = hf_cache;
+    let model_file = HubFile::testalias_builder()
+      .hf_cache(hf_cache.clone())
+      .build()
+      .unwrap();
+    let model_filepath = model_file.path().display().to_string();
+    let tokenizer_file = HubFile::testalias_tokenizer_builder()
+      .hf_cache(hf_cache.clone())
+      .build()
+      .unwrap();
+    let mut mock = MockBodhiServerContext::default();
+    let expected_input =
+      "{\"messages\":[{\"content\":\"What day comes after Monday?\",\"role\":\"user\"}],\"model\":\"testalias:instruct\",\"prompt\":\"<|begin_of_text|><|start_header_id|>user<|end_header_id|>\\n\\nWhat day comes after Monday?<|eot_id|><|start_header_id|>assistant<|end_header_id|>\\n\\n\",\"stop\":[\"<|eot_id|>\",\"<|end_of_text|>\"]}";
+    mock.expect_init().with().return_once(|| Ok(()));
+    mock.expect_start_event_loop().with().return_once(|| Ok(()));
+    // a token per character of the prompt
+    mock
+      .expect_tokenize()
+      .returning(|prompt, _| Ok(vec![1; prompt.len()]));
+    mock
+      .expect_completions()
+      .with(eq(expected_input), eq(""), always(), always())
+      .return_once(|_, _, _, _| Ok(()));
+    let gpt_params = GptParams {
+      model: model_filepath,
+      n_ctx: Some(2048),
+      n_parallel: Some(4),
+      ..Default::default()
+    };
+    let gpt_params_cl = gpt_params.clone();
+    mock
+      .expect_get_gpt_params()
+      .returning(move || gpt_params_cl.clone());
+
+    let ctx = MockBodhiServerContext::new_context();
+    ctx
+      .expect()
+      .with(eq(gpt_params.clone()))
+      .return_once(move |_| Ok(mock));
+
+    let shared_ctx = SharedContextRw::new_shared_rw(Some(gpt_params)).await?;
+    let story = "Tell me a long story.".repeat(30);
+    let request = serde_json::from_value::<ChatCompletionRequest>(json! {{
+      "model": "testalias:instruct",
+      "messages": [
+        {"role": "user", "content": story},
+        {"role": "assistant", "content": "Once upon a time"},
+        {"role": "user", "content": "What day comes after Monday?"}
+      ]
+    }})?;
+    let alias = Alias {
+      context_params: GptContextParams {
+        truncation: Some(Truncation::DropOldest),
+        ..Default::default()
+      },
+      ..Alias::testalias()
+    };
+    let (tx, _rx) = test_channel();
+    shared_ctx
+      .chat_completions(
+        request,
+        alias,
+        model_file,
+        tokenizer_file,
+        TemplateLimits::default(),
+        tx,
+      )
+      .await?;
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(ContextError::WorkerCrashed, true)]
+  #[case(
+    ContextError::BodhiError(LlamaCppError::BodhiServerChatCompletion(
+      "invalid logit_bias".to_string()
+    )),
+    false
+  )]
+  #[case(
+    ContextError::ContextLengthExceeded {
+      prompt_tokens: 600,
+      n_ctx: 512
+    },
+    false
+  )]
+  fn test_context_error_is_context_failure(#[case] err: ContextError, #[case] expected: bool) {
+    assert_eq!(expected, err.is_context_failure());
+  }
+
+  #[rstest]
+  #[case(ContextError::WorkerCrashed, true)]
+  #[case(
+    ContextError::BodhiError(LlamaCppError::BodhiServerChatCompletion(
+      "decode: failed to find free space in the KV cache".to_string()
+    )),
+    true
+  )]
+  #[case(
+    ContextError::BodhiError(LlamaCppError::BodhiServerChatCompletion(
+      "invalid logit_bias".to_string()
+    )),
+    false
+  )]
+  fn test_context_error_is_transient(#[case] err: ContextError, #[case] expected: bool) {
+    assert_eq!(expected, err.is_transient());
+  }
+}
@@ -0,0 +1,221 @@
+use crate::{bindings::llama_system_info, service::EnvServiceFn, utils::human_size};
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+
+/// System and runtime details for the bug reports and the about page of the UI,
+/// shown by `bodhi info` and `GET /api/ui/info`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SystemInfo {
+  pub version: String,
+  pub os: String,
+  pub arch: String,
+  pub cpus: usize,
+  pub cpu_features: Vec<String>,
+  pub total_memory: u64,
+  pub available_memory: u64,
+  pub gpus: Vec<GpuInfo>,
+  /// commit of the llama.cpp server bindings, pinning the llama.cpp version
+  pub bindings_commit: String,
+  /// the features llama.cpp was built with, as reported by llama.cpp
+  pub llama_cpp_system_info: String,
+  pub bodhi_home: String,
+  pub hf_home: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GpuInfo {
+  pub name: String,
+  /// memory of the GPU in bytes, None if shared with the CPU, as on Apple silicon
+  pub vram: Option<u64>,
+}
+
+impl SystemInfo {
+  pub fn collect(env_service: &dyn EnvServiceFn) -> Self {
+    let mut system = sysinfo::System::new();
+    system.refresh_memory();
+    SystemInfo {
+      version: env!("CARGO_PKG_VERSION").to_string(),
+      os: std::env::consts::OS.to_string(),
+      arch: std::env::consts::ARCH.to_string(),
+      cpus: std::thread::available_parallelism()
+        .map(|cpus| cpus.get())
+        .unwrap_or(1),
+      cpu_features: cpu_features(),
+      total_memory: system.total_memory(),
+      available_memory: system.available_memory(),
+      gpus: gpus(),
+      bindings_commit: env!("BODHI_BINDINGS_COMMIT").to_string(),
+      llama_cpp_system_info: llama_system_info(),
+      bodhi_home: env_service.bodhi_home().display().to_string(),
+      hf_home: env_service.hf_home().display().to_string(),
+    }
+  }
+
+  pub fn to_text(&self) -> String {
+    let join_or_none = |values: Vec<String>| match values.is_empty() {
+      true => "none".to_string(),
+      false => values.join(", "),
+    };
+    let gpus = self
+      .gpus
+      .iter()
+      .map(|gpu| match gpu.vram {
+        Some(vram) => format!("{} ({})", gpu.name, human_size(vram)),
+        None => format!("{} (shared memory)", gpu.name),
+      })
+      .collect::<Vec<_>>();
+    [
+      ("bodhi version", self.version.clone()),
+      ("os", format!("{} ({})", self.os, self.arch)),
+      ("cpus", self.cpus.to_string()),
+      ("cpu features", join_or_none(self.cpu_features.clone())),
+      (
+        "memory",
+        format!(
+          "{} available of {}",
+          human_size(self.available_memory),
+          human_size(self.total_memory)
+        ),
+      ),
+      ("gpus", join_or_none(gpus)),
+      ("bindings commit", self.bindings_commit.clone()),
+      ("llama.cpp", self.llama_cpp_system_info.clone()),
+      ("BODHI_HOME", self.bodhi_home.clone()),
+      ("HF_HOME", self.hf_home.clone()),
+    ]
+    .into_iter()
+    .map(|(key, value)| format!("{key}: {value}\n"))
+    .collect()
+  }
+}
+
+// the SIMD extensions the llama.cpp CPU kernels are built to use
+fn cpu_features() -> Vec<String> {
+  #[allow(unused_mut)]
+  let mut features = Vec::<&str>::new();
+  #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+  {
+    let detected = [
+      ("avx", is_x86_feature_detected!("avx")),
+      ("avx2", is_x86_feature_detected!("avx2")),
+      ("avx512f", is_x86_feature_detected!("avx512f")),
+      ("fma", is_x86_feature_detected!("fma")),
+      ("f16c", is_x86_feature_detected!("f16c")),
+    ];
+    features.extend(detected.iter().filter(|(_, on)| *on).map(|(name, _)| *name));
+  }
+  #[cfg(target_arch = "aarch64")]
+  {
+    let detected = [
+      ("neon", std::arch::is_aarch64_feature_detected!("neon")),
+      (
+        "dotprod",
+        std::arch::is_aarch64_feature_detected!("dotprod"),
+      ),
+      ("fp16", std::arch::is_aarch64_feature_detected!("fp16")),
+      ("sve", std::arch::is_aarch64_feature_detected!("sve")),
+    ];
+    features.extend(detected.iter().filter(|(_, on)| *on).map(|(name, _)| *name));
+  }
+  features.into_iter().map(str::to_string).collect()
+}
+
+fn gpus() -> Vec<GpuInfo> {
+  if cfg!(all(target_os = "macos", target_arch = "aarch64")) {
+    return vec![GpuInfo {
+      name: "Apple silicon (Metal)".to_string(),
+      vram: None,
+    }];
+  }
+  nvidia_gpus()
+}
+
+// installed along with the NVIDIA driver, no GPUs are listed without it
+pub(crate) fn nvidia_gpus() -> Vec<GpuInfo> {
+  let output = Command::new("nvidia-smi")
+    .args([
+      "--query-gpu=name,memory.total",
+      "--format=csv,noheader,nounits",
+    ])
+    .output();
+  match output {
+    Ok(output) if output.status.success() => {
+      parse_nvidia_smi(&String::from_utf8_lossy(&output.stdout))
+    }
+    _ => vec![],
+  }
+}
+
+// lines of `<name>, <memory in MiB>`
+fn parse_nvidia_smi(output: &str) -> Vec<GpuInfo> {
+  output
+    .lines()
+    .filter_map(|line| {
+      let (name, memory) = line.rsplit_once(',')?;
+      Some(GpuInfo {
+        name: name.trim().to_string(),
+        vram: memory
+          .trim()
+          .parse::<u64>()
+          .ok()
+          .map(|mib| mib * 1024 * 1024),
+      })
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+  use super::{parse_nvidia_smi, GpuInfo, SystemInfo};
+  use rstest::rstest;
+
+  #[rstest]
+  fn test_system_info_parse_nvidia_smi() {
+    let gpus = parse_nvidia_smi("NVIDIA GeForce RTX 4090, 24564\nTesla T4, [N/A]\n\n");
+    assert_eq!(
+      vec![
+        GpuInfo {
+          name: "NVIDIA GeForce RTX 4090".to_string(),
+          vram: Some(24564 * 1024 * 1024),
+        },
+        GpuInfo {
+          name: "Tesla T4".to_string(),
+          vram: None,
+        },
+      ],
+      gpus
+    );
+  }
+
+  #[rstest]
+  fn test_system_info_to_text() {
+    let info = SystemInfo {
+      version: "0.0.11".to_string(),
+      os: "linux".to_string(),
+      arch: "x86_64".to_string(),
+      cpus: 16,
+      cpu_features: vec!["avx".to_string(), "avx2".to_string()],
+      total_memory: 32 * 1024 * 1024 * 1024,
+      available_memory: 12 * 1024 * 1024 * 1024,
+      gpus: vec![],
+      bindings_commit: "abc1234".to_string(),
+      llama_cpp_system_info: "AVX = 1 | AVX2 = 1".to_string(),
+      bodhi_home: "/home/user/.cache/bodhi".to_string(),
+      hf_home: "/home/user/.cache/huggingface".to_string(),
+    };
+    assert_eq!(
+      r#"bodhi version: 0.0.11
+os: linux (x86_64)
+cpus: 16
+cpu features: avx, avx2
+memory: 12.00 GB available of 32.00 GB
+gpus: none
+bindings commit: abc1234
+llama.cpp: AVX = 1 | AVX2 = 1
+BODHI_HOME: /home/user/.cache/bodhi
+HF_HOME: /home/user/.cache/huggingface
+"#,
+      info.to_text()
+    );
+  }
+}
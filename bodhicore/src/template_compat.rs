@@ -0,0 +1,238 @@
+use minijinja::{
+  value::{from_args, ValueKind},
+  Error, ErrorKind, State, Value,
+};
+
+/// Resolves the methods of the python objects called by the chat templates written for
+/// transformers, e.g. `message['content'].strip()` or `message.items()`, that are not part of
+/// jinja. Registered as the unknown method callback of the template environment.
+pub(crate) fn python_method(
+  _state: &State,
+  value: &Value,
+  method: &str,
+  args: &[Value],
+) -> Result<Value, Error> {
+  match value.kind() {
+    ValueKind::String => {
+      let Some(s) = value.as_str() else {
+        return Err(unknown_method(value, method));
+      };
+      string_method(s, method, args).ok_or_else(|| unknown_method(value, method))?
+    }
+    ValueKind::Map => {
+      map_method(value, method, args).ok_or_else(|| unknown_method(value, method))?
+    }
+    _ => Err(unknown_method(value, method)),
+  }
+}
+
+fn unknown_method(value: &Value, method: &str) -> Error {
+  Error::new(
+    ErrorKind::UnknownMethod,
+    format!("{} has no method named {method}", value.kind()),
+  )
+}
+
+fn string_method(s: &str, method: &str, args: &[Value]) -> Option<Result<Value, Error>> {
+  let result = match method {
+    "strip" => from_args::<(Option<&str>,)>(args)
+      .map(|(chars,)| Value::from(trim_end(trim_start(s, chars), chars))),
+    "lstrip" => {
+      from_args::<(Option<&str>,)>(args).map(|(chars,)| Value::from(trim_start(s, chars)))
+    }
+    "rstrip" => from_args::<(Option<&str>,)>(args).map(|(chars,)| Value::from(trim_end(s, chars))),
+    "upper" => from_args::<()>(args).map(|_| Value::from(s.to_uppercase())),
+    "lower" => from_args::<()>(args).map(|_| Value::from(s.to_lowercase())),
+    "title" => from_args::<()>(args).map(|_| Value::from(title(s))),
+    "capitalize" => from_args::<()>(args).map(|_| {
+      let mut chars = s.chars();
+      let capitalized = match chars.next() {
+        Some(first) => first
+          .to_uppercase()
+          .chain(chars.as_str().to_lowercase().chars())
+          .collect(),
+        None => String::new(),
+      };
+      Value::from(capitalized)
+    }),
+    "startswith" => from_args::<(&str,)>(args).map(|(prefix,)| Value::from(s.starts_with(prefix))),
+    "endswith" => from_args::<(&str,)>(args).map(|(suffix,)| Value::from(s.ends_with(suffix))),
+    "split" => from_args::<(Option<&str>, Option<i64>)>(args).map(|(sep, maxsplit)| {
+      Value::from(split(s, sep, maxsplit.filter(|maxsplit| *maxsplit >= 0)))
+    }),
+    "replace" => from_args::<(&str, &str, Option<i64>)>(args).map(|(from, to, count)| match count
+      .filter(|count| *count >= 0)
+    {
+      Some(count) => Value::from(s.replacen(from, to, count as usize)),
+      None => Value::from(s.replace(from, to)),
+    }),
+    "find" => from_args::<(&str,)>(args).map(|(sub,)| {
+      let index = s
+        .find(sub)
+        .map(|index| s[..index].chars().count() as i64)
+        .unwrap_or(-1);
+      Value::from(index)
+    }),
+    "count" => from_args::<(&str,)>(args).map(|(sub,)| Value::from(s.matches(sub).count())),
+    "isspace" => from_args::<()>(args)
+      .map(|_| Value::from(!s.is_empty() && s.chars().all(char::is_whitespace))),
+    "isdigit" => from_args::<()>(args)
+      .map(|_| Value::from(!s.is_empty() && s.chars().all(|c| c.is_ascii_digit()))),
+    _ => return None,
+  };
+  Some(result)
+}
+
+fn map_method(value: &Value, method: &str, args: &[Value]) -> Option<Result<Value, Error>> {
+  let result = match method {
+    "items" => from_args::<()>(args).and_then(|_| {
+      let items = value
+        .try_iter()?
+        .map(|key| {
+          let item = value.get_item(&key)?;
+          Ok(Value::from(vec![key, item]))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+      Ok(Value::from(items))
+    }),
+    "keys" => {
+      from_args::<()>(args).and_then(|_| Ok(Value::from(value.try_iter()?.collect::<Vec<_>>())))
+    }
+    "values" => from_args::<()>(args).and_then(|_| {
+      let values = value
+        .try_iter()?
+        .map(|key| value.get_item(&key))
+        .collect::<Result<Vec<_>, Error>>()?;
+      Ok(Value::from(values))
+    }),
+    "get" => from_args::<(Value, Option<Value>)>(args).and_then(|(key, default)| {
+      let item = value.get_item(&key)?;
+      if item.is_undefined() {
+        Ok(default.unwrap_or_else(|| Value::from(())))
+      } else {
+        Ok(item)
+      }
+    }),
+    _ => return None,
+  };
+  Some(result)
+}
+
+// python strips the whitespace without the chars to strip
+fn trim_start<'a>(s: &'a str, chars: Option<&str>) -> &'a str {
+  match chars {
+    Some(chars) => s.trim_start_matches(|c| chars.contains(c)),
+    None => s.trim_start(),
+  }
+}
+
+fn trim_end<'a>(s: &'a str, chars: Option<&str>) -> &'a str {
+  match chars {
+    Some(chars) => s.trim_end_matches(|c| chars.contains(c)),
+    None => s.trim_end(),
+  }
+}
+
+// the first letter of each word upper cased and the rest lower cased, the words separated by
+// anything other than letters, as `str.title()`
+fn title(s: &str) -> String {
+  let mut result = String::with_capacity(s.len());
+  let mut in_word = false;
+  for c in s.chars() {
+    if in_word {
+      result.extend(c.to_lowercase());
+    } else {
+      result.extend(c.to_uppercase());
+    }
+    in_word = c.is_alphabetic();
+  }
+  result
+}
+
+fn split(s: &str, sep: Option<&str>, maxsplit: Option<i64>) -> Vec<Value> {
+  let maxsplit = maxsplit.map(|maxsplit| maxsplit as usize);
+  match (sep, maxsplit) {
+    (Some(sep), Some(maxsplit)) => s.splitn(maxsplit + 1, sep).map(Value::from).collect(),
+    (Some(sep), None) => s.split(sep).map(Value::from).collect(),
+    (None, maxsplit) => {
+      let mut parts = Vec::new();
+      let mut rest = s.trim_start();
+      while !rest.is_empty() {
+        if maxsplit.is_some_and(|maxsplit| parts.len() == maxsplit) {
+          parts.push(Value::from(rest));
+          break;
+        }
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        parts.push(Value::from(&rest[..end]));
+        rest = rest[end..].trim_start();
+      }
+      parts
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::python_method;
+  use minijinja::{context, Environment};
+  use rstest::rstest;
+
+  #[rstest]
+  #[case("{{ '  Monday  '.strip() }}|", "Monday|")]
+  #[case("{{ '  Monday  '.lstrip() }}|", "Monday  |")]
+  #[case("{{ '  Monday  '.rstrip() }}|", "  Monday|")]
+  #[case("{{ '--Monday--'.strip('-') }}", "Monday")]
+  #[case("{{ 'monday'.upper() }} {{ 'MONDAY'.lower() }}", "MONDAY monday")]
+  #[case("{{ 'the day after monday'.title() }}", "The Day After Monday")]
+  #[case("{{ 'tuesday IS next'.capitalize() }}", "Tuesday is next")]
+  #[case(
+    "{{ 'Monday'.startswith('Mon') }} {{ 'Monday'.endswith('Mon') }}",
+    "true false"
+  )]
+  #[case("{{ ' a b  c '.split() }}", r#"["a", "b", "c"]"#)]
+  #[case("{{ 'a,b,c'.split(',', 1) }}", r#"["a", "b,c"]"#)]
+  #[case(
+    "{{ 'a b c'.replace(' ', '-') }} {{ 'a b c'.replace(' ', '-', 1) }}",
+    "a-b-c a-b c"
+  )]
+  #[case("{{ 'Monday'.find('day') }} {{ 'Monday'.find('Tue') }}", "3 -1")]
+  #[case("{{ '  '.isspace() }} {{ '123'.isdigit() }}", "true true")]
+  #[case("{{ message['content'][1:3] }}", "ue")]
+  #[case(
+    "{% set ns = namespace(found=false) %}{% for m in [message] %}{% set ns.found = true %}{% endfor %}{{ ns.found }}",
+    "true"
+  )]
+  #[case(
+    "{% for key, value in message.items() %}{{ key }}={{ value }};{% endfor %}",
+    "content=Tuesday;role=user;"
+  )]
+  #[case(
+    "{{ message.keys() | list }} {{ message.values() | list }}",
+    r#"["content", "role"] ["Tuesday", "user"]"#
+  )]
+  #[case(
+    "{{ message.get('name', 'none') }} {{ message.get('role') }}",
+    "none user"
+  )]
+  fn test_python_method_renders(
+    #[case] template: &str,
+    #[case] expected: &str,
+  ) -> anyhow::Result<()> {
+    let mut env = Environment::new();
+    env.set_unknown_method_callback(python_method);
+    let message = std::collections::BTreeMap::from([("content", "Tuesday"), ("role", "user")]);
+    let result = env.render_str(template, context! { message => message })?;
+    assert_eq!(expected, result);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("{{ 'Monday'.reverse() }}")]
+  #[case("{{ [1, 2].strip() }}")]
+  #[case("{{ 'Monday'.startswith() }}")]
+  fn test_python_method_fails_the_unknown(#[case] template: &str) {
+    let mut env = Environment::new();
+    env.set_unknown_method_callback(python_method);
+    assert!(env.render_str(template, ()).is_err());
+  }
+}
@@ -1,13 +1,19 @@
 use crate::db::{
-  objs::{Conversation, Message},
+  objs::{
+    ApiKey, ApiKeyRole, AuditAction, AuditEntry, CanarySample, ClientUsage, Conversation,
+    ConversationUsage, ConversationsQuery, Download, Message, MessageMatch, MigrationStatus,
+    ShareLink, UsagePeriod, UsageRecord, UsageStats,
+  },
   DbError, DbService, DbServiceFn, TimeServiceFn,
 };
-use chrono::{DateTime, Timelike, Utc};
+use crate::objs::RequestPriority;
+use chrono::{DateTime, Duration, Timelike, Utc};
 use rstest::fixture;
 use sqlx::SqlitePool;
 use std::{
   fmt::{self, Formatter},
   fs::File,
+  path::Path,
   sync::Arc,
 };
 use tempfile::TempDir;
@@ -35,17 +41,106 @@ mockall::mock! {
   impl DbServiceFn for DbService {
     async fn migrate(&self) -> Result<(), DbError>;
 
+    async fn migration_status(&self) -> Result<Vec<MigrationStatus>, DbError>;
+
+    async fn backup(&self, path: &Path) -> Result<(), DbError>;
+
     async fn save_conversation(&self, conversation: &mut Conversation) -> Result<(), DbError>;
 
     async fn save_message(&self, message: &mut Message) -> Result<(), DbError>;
 
-    async fn list_conversations(&self) -> Result<Vec<Conversation>, DbError>;
+    async fn list_conversations(&self, query: &ConversationsQuery) -> Result<Vec<Conversation>, DbError>;
 
     async fn delete_conversations(&self, id: &str) -> Result<(), DbError>;
 
-    async fn delete_all_conversations(&self) -> Result<(), DbError>;
+    async fn delete_all_conversations(&self, owner: Option<&str>) -> Result<(), DbError>;
 
     async fn get_conversation_with_messages(&self, id: &str) -> Result<Conversation, DbError>;
+
+    async fn edit_message(
+      &self,
+      conversation_id: &str,
+      message_id: &str,
+      content: &str,
+    ) -> Result<Message, DbError>;
+
+    async fn branch_messages(&self, conversation_id: &str, message_id: &str) -> Result<(), DbError>;
+
+    async fn fork_conversation(
+      &self,
+      conversation_id: &str,
+      message_id: &str,
+    ) -> Result<Conversation, DbError>;
+
+    async fn create_share_link(
+      &self,
+      conversation_id: &str,
+      expires_in: Duration,
+    ) -> Result<ShareLink, DbError>;
+
+    async fn get_shared_conversation(&self, token: &str) -> Result<Conversation, DbError>;
+
+    async fn save_usage(&self, record: &mut UsageRecord) -> Result<(), DbError>;
+
+    async fn list_client_usage(
+      &self,
+      since: Option<DateTime<Utc>>,
+      limit: u32,
+    ) -> Result<Vec<ClientUsage>, DbError>;
+
+    async fn list_usage_stats(
+      &self,
+      period: UsagePeriod,
+      since: Option<DateTime<Utc>>,
+      limit: u32,
+    ) -> Result<Vec<UsageStats>, DbError>;
+
+    async fn list_conversation_usage(
+      &self,
+      since: Option<DateTime<Utc>>,
+      owner: Option<&str>,
+      limit: u32,
+    ) -> Result<Vec<ConversationUsage>, DbError>;
+
+    async fn search_messages(
+      &self,
+      query: &str,
+      owner: Option<&str>,
+      limit: u32,
+    ) -> Result<Vec<MessageMatch>, DbError>;
+
+    async fn tag_conversation(&self, conversation_id: &str, tag: &str) -> Result<(), DbError>;
+
+    async fn untag_conversation(&self, conversation_id: &str, tag: &str) -> Result<(), DbError>;
+
+    async fn save_canary_sample(&self, sample: &mut CanarySample) -> Result<(), DbError>;
+
+    async fn list_canary_samples(&self, limit: u32) -> Result<Vec<CanarySample>, DbError>;
+
+    async fn create_api_key(&self, name: &str, role: ApiKeyRole, priority: RequestPriority) -> Result<(ApiKey, String), DbError>;
+
+    async fn list_api_keys(&self) -> Result<Vec<ApiKey>, DbError>;
+
+    async fn revoke_api_key(&self, id: &str) -> Result<(), DbError>;
+
+    async fn find_api_key(&self, secret: &str) -> Result<ApiKey, DbError>;
+
+    async fn save_audit_entry(&self, entry: &mut AuditEntry) -> Result<(), DbError>;
+
+    async fn list_audit_entries(
+      &self,
+      action: Option<AuditAction>,
+      since: Option<DateTime<Utc>>,
+      limit: u32,
+    ) -> Result<Vec<AuditEntry>, DbError>;
+
+    async fn create_download(&self, download: &mut Download) -> Result<(), DbError>;
+
+    async fn update_download(&self, download: &mut Download) -> Result<(), DbError>;
+
+    async fn get_download(&self, id: &str) -> Result<Download, DbError>;
+
+    async fn list_downloads(&self, limit: u32) -> Result<Vec<Download>, DbError>;
   }
 
   impl std::fmt::Debug for DbService {
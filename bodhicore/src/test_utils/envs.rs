@@ -1,31 +1,22 @@
-use std::{env::VarError, fmt, path::PathBuf};
+// reads the variable from .env.test without loading it into the process environment,
+// as the tests run in parallel
+fn test_env_var(key: &str) -> String {
+  std::env::var(key)
+    .ok()
+    .or_else(|| {
+      dotenv::from_filename_iter(".env.test")
+        .ok()?
+        .flatten()
+        .find(|(name, _)| name == key)
+        .map(|(_, value)| value)
+    })
+    .unwrap()
+}
 
 pub fn hf_test_token_allowed() -> Option<String> {
-  dotenv::from_filename(".env.test").ok();
-  Some(std::env::var("HF_TEST_TOKEN_ALLOWED").unwrap())
+  Some(test_env_var("HF_TEST_TOKEN_ALLOWED"))
 }
 
 pub fn hf_test_token_public() -> Option<String> {
-  dotenv::from_filename(".env.test").ok();
-  Some(std::env::var("HF_TEST_TOKEN_PUBLIC").unwrap())
-}
-
-mockall::mock! {
-  pub EnvWrapper {
-    pub fn new() -> Self;
-
-    pub fn var(&self, key: &str) -> Result<String, VarError>;
-
-    pub fn home_dir(&self) -> Option<PathBuf>;
-
-    pub fn load_dotenv(&self);
-  }
-
-  impl std::fmt::Debug for EnvWrapper {
-    fn fmt<'a>(&self, f: &mut fmt::Formatter<'a>) -> fmt::Result;
-  }
-
-  impl Clone for EnvWrapper {
-    fn clone(&self) -> Self;
-  }
+  Some(test_env_var("HF_TEST_TOKEN_PUBLIC"))
 }
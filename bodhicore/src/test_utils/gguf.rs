@@ -0,0 +1,93 @@
+use crate::gguf::GGUFValue;
+use std::{fs, path::Path};
+
+fn write_string(buf: &mut Vec<u8>, value: &str) {
+  buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+  buf.extend_from_slice(value.as_bytes());
+}
+
+fn write_value(buf: &mut Vec<u8>, value: &GGUFValue) {
+  match value {
+    GGUFValue::U32(value) => buf.extend_from_slice(&value.to_le_bytes()),
+    GGUFValue::I32(value) => buf.extend_from_slice(&value.to_le_bytes()),
+    GGUFValue::U64(value) => buf.extend_from_slice(&value.to_le_bytes()),
+    GGUFValue::F32(value) => buf.extend_from_slice(&value.to_le_bytes()),
+    GGUFValue::Bool(value) => buf.push(*value as u8),
+    GGUFValue::String(value) => write_string(buf, value),
+    GGUFValue::Array(values) => {
+      let item_type = values.first().map(value_type).unwrap_or(8);
+      buf.extend_from_slice(&item_type.to_le_bytes());
+      buf.extend_from_slice(&(values.len() as u64).to_le_bytes());
+      for value in values {
+        write_value(buf, value);
+      }
+    }
+    value => unimplemented!("writing {value:?} not supported in tests"),
+  }
+}
+
+fn value_type(value: &GGUFValue) -> u32 {
+  match value {
+    GGUFValue::U32(_) => 4,
+    GGUFValue::I32(_) => 5,
+    GGUFValue::F32(_) => 6,
+    GGUFValue::Bool(_) => 7,
+    GGUFValue::String(_) => 8,
+    GGUFValue::Array(_) => 9,
+    GGUFValue::U64(_) => 10,
+    value => unimplemented!("writing {value:?} not supported in tests"),
+  }
+}
+
+/// Writes a GGUF file with the given metadata and no tensors, for tests.
+pub fn write_gguf(path: &Path, metadata: Vec<(&str, GGUFValue)>) -> anyhow::Result<()> {
+  let mut buf = Vec::<u8>::new();
+  buf.extend_from_slice(b"GGUF");
+  buf.extend_from_slice(&3u32.to_le_bytes());
+  buf.extend_from_slice(&0u64.to_le_bytes());
+  buf.extend_from_slice(&(metadata.len() as u64).to_le_bytes());
+  for (key, value) in metadata {
+    write_string(&mut buf, key);
+    buf.extend_from_slice(&value_type(&value).to_le_bytes());
+    write_value(&mut buf, &value);
+  }
+  fs::write(path, buf)?;
+  Ok(())
+}
+
+pub fn gguf_strings(values: &[&str]) -> GGUFValue {
+  GGUFValue::Array(
+    values
+      .iter()
+      .map(|value| GGUFValue::String(value.to_string()))
+      .collect(),
+  )
+}
+
+pub fn gguf_token_types(values: &[i32]) -> GGUFValue {
+  GGUFValue::Array(values.iter().map(|value| GGUFValue::I32(*value)).collect())
+}
+
+pub fn llama_vocab_metadata() -> Vec<(&'static str, GGUFValue)> {
+  vec![
+    (
+      "general.architecture",
+      GGUFValue::String("llama".to_string()),
+    ),
+    ("llama.context_length", GGUFValue::U32(4096)),
+    (
+      "tokenizer.ggml.model",
+      GGUFValue::String("llama".to_string()),
+    ),
+    (
+      "tokenizer.ggml.tokens",
+      gguf_strings(&[
+        "<unk>", "<s>", "</s>", "<0x0A>", "▁Hello", ",", "▁world", "!",
+      ]),
+    ),
+    (
+      "tokenizer.ggml.token_type",
+      gguf_token_types(&[2, 3, 3, 6, 1, 1, 1, 1]),
+    ),
+  ]
+}
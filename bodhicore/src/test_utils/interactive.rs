@@ -1,10 +1,42 @@
-use crate::{error::Result, objs::Alias, service::AppServiceFn};
+use crate::{
+  error::Result,
+  interactive::{BenchOptions, BenchResult, ChatOptions},
+  objs::{Alias, ChatCompletionRequest},
+  service::AppServiceFn,
+};
+use async_openai::types::{ChatCompletionRequestMessage, CreateChatCompletionResponse};
 use std::sync::Arc;
 
 mockall::mock! {
   pub InteractiveRuntime {
     pub fn new() -> Self;
 
-    pub fn execute(&self, alias: Alias, service: Arc<dyn AppServiceFn>) -> Result<()>;
+    pub fn execute(
+      &self,
+      alias: Alias,
+      service: Arc<dyn AppServiceFn>,
+      options: ChatOptions,
+    ) -> Result<()>;
+
+    pub fn complete(
+      &self,
+      alias: Alias,
+      service: Arc<dyn AppServiceFn>,
+      messages: Vec<ChatCompletionRequestMessage>,
+    ) -> Result<CreateChatCompletionResponse>;
+
+    pub fn replay(
+      &self,
+      alias: Alias,
+      service: Arc<dyn AppServiceFn>,
+      request: ChatCompletionRequest,
+    ) -> Result<CreateChatCompletionResponse>;
+
+    pub fn bench(
+      &self,
+      alias: Alias,
+      service: Arc<dyn AppServiceFn>,
+      options: BenchOptions,
+    ) -> Result<BenchResult>;
   }
 }
@@ -3,6 +3,7 @@ mod bodhi;
 mod common;
 mod db;
 mod envs;
+mod gguf;
 mod hf;
 mod http;
 mod interactive;
@@ -16,6 +17,7 @@ pub use bodhi::*;
 pub use common::*;
 pub use db::*;
 pub use envs::*;
+pub use gguf::*;
 pub use hf::*;
 pub use http::*;
 pub use io::*;
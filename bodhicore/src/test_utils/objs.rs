@@ -2,7 +2,7 @@ use super::SNAPSHOT;
 use crate::{
   cli::create::CreateCommandBuilder,
   objs::{
-    Alias, AliasBuilder, ChatTemplate, ChatTemplateId, GptContextParams, HubFile,
+    Alias, AliasBuilder, AliasFeature, ChatTemplate, ChatTemplateId, GptContextParams, HubFile,
     HubFileBuilder, OAIRequestParams, RemoteModel, Repo, TOKENIZER_CONFIG_JSON,
   },
   CreateCommand,
@@ -129,8 +129,10 @@ impl CreateCommand {
       .alias("testalias:instruct".to_string())
       .repo(Repo::try_from("MyFactory/testalias-gguf").unwrap())
       .filename("testalias.Q8_0.gguf".to_string())
+      .file(None)
       .chat_template(ChatTemplate::Id(ChatTemplateId::Llama3))
       .family(Some("testalias".to_string()))
+      .features(vec![AliasFeature::Chat])
       .force(false)
       .oai_request_params(OAIRequestParams::default())
       .context_params(GptContextParams::default())
@@ -1,10 +1,10 @@
-use super::{temp_bodhi_home, temp_hf_home, MockEnvWrapper};
+use super::{temp_bodhi_home, temp_hf_home};
 use crate::service::{
-  AppService, AppServiceFn, DataService, EnvService, EnvServiceFn, HfHubService, HubService,
-  LocalDataService, MockDataService, MockEnvServiceFn, MockHubService,
+  env_wrapper::EnvWrapper, AppService, AppServiceFn, DataService, EnvService, EnvServiceFn,
+  HfHubService, HubService, LocalDataService, MockDataService, MockEnvServiceFn, MockHubService,
 };
 use rstest::fixture;
-use std::{path::PathBuf, sync::Arc};
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
 use tempfile::TempDir;
 
 pub struct HubServiceTuple(pub TempDir, pub PathBuf, pub HfHubService);
@@ -40,8 +40,8 @@ pub fn app_service_stub(
 ) -> AppServiceTuple {
   let DataServiceTuple(temp_bodhi_home, bodhi_home, data_service) = data_service;
   let HubServiceTuple(temp_hf_home, hf_cache, hub_service) = hub_service;
-  let mock = MockEnvWrapper::default();
-  let env_service = EnvService::new_with_args(mock, bodhi_home.clone(), hf_cache.join(".."));
+  let env_wrapper = EnvWrapper::new(HashMap::new(), None);
+  let env_service = EnvService::new_with_args(env_wrapper, bodhi_home.clone(), hf_cache.join(".."));
   let service = AppService::new(Arc::new(env_service), hub_service, data_service);
   AppServiceTuple(temp_bodhi_home, temp_hf_home, bodhi_home, hf_cache, service)
 }
@@ -1,7 +1,6 @@
-use crate::{objs::*, SharedContextRwFn};
-use async_openai::types::CreateChatCompletionRequest;
+use crate::{objs::*, service::TemplateLimits, SharedContextRwFn};
 use llama_server_bindings::{Callback, GptParams};
-use std::ffi::c_void;
+use std::{ffi::c_void, path::PathBuf};
 use tokio::sync::mpsc::Sender;
 
 mockall::mock! {
@@ -25,18 +24,36 @@ mockall::mock! {
 
     async fn try_stop(&self) -> crate::shared_rw::Result<()>;
 
+    async fn force_stop(&self) -> crate::shared_rw::Result<()>;
+
     async fn has_model(&self) -> bool;
 
     async fn get_gpt_params(&self) -> crate::shared_rw::Result<Option<GptParams>>;
 
+    async fn warmup(&self) -> crate::shared_rw::Result<()>;
+
     async fn chat_completions(
       &self,
-      mut request: CreateChatCompletionRequest,
+      mut request: ChatCompletionRequest,
       alias: Alias,
       model_file: HubFile,
       tokenizer_file: HubFile,
+      template_limits: TemplateLimits,
+      userdata: Sender<String>,
+    ) -> crate::shared_rw::Result<()>;
+
+    async fn rerank(
+      &self,
+      request: RerankRequest,
+      alias: Alias,
+      model_file: HubFile,
       userdata: Sender<String>,
     ) -> crate::shared_rw::Result<()>;
+
+    async fn invalidate_chat_templates(
+      &self,
+      tokenizer_files: Vec<PathBuf>,
+    ) -> crate::shared_rw::Result<()>;
   }
 }
 
@@ -58,6 +75,19 @@ mockall::mock! {
       userdata: *mut c_void,
     ) -> llama_server_bindings::Result<()>;
 
+    pub fn rerank(
+      &self,
+      input: &str,
+      callback: Option<Callback>,
+      userdata: *mut c_void,
+    ) -> llama_server_bindings::Result<()>;
+
+    pub fn tokenize(
+      &self,
+      content: &str,
+      add_special: bool,
+    ) -> llama_server_bindings::Result<Vec<i32>>;
+
     pub fn stop(&mut self) -> llama_server_bindings::Result<()>;
   }
 
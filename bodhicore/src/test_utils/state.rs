@@ -1,5 +1,11 @@
-use crate::{db::DbServiceFn, server::RouterStateFn, service::AppServiceFn};
-use async_openai::types::CreateChatCompletionRequest;
+use crate::{
+  db::DbServiceFn,
+  objs::{ChatCompletionRequest, RerankRequest, RerankResponse},
+  server::{
+    AliasQuarantine, DownloadManager, Generations, InferenceMonitor, ResponseCache, RouterStateFn,
+  },
+  service::AppServiceFn,
+};
 use std::sync::Arc;
 use tokio::sync::mpsc::Sender;
 
@@ -13,11 +19,25 @@ mockall::mock! {
 
     fn db_service(&self) -> Arc<dyn DbServiceFn> ;
 
+    fn quarantine(&self) -> Arc<AliasQuarantine> ;
+
+    fn inference_monitor(&self) -> Arc<InferenceMonitor> ;
+
+    fn generations(&self) -> Arc<Generations> ;
+
+    fn response_cache(&self) -> Arc<ResponseCache> ;
+
+    fn downloads(&self) -> Arc<DownloadManager> ;
+
+    async fn loaded_model(&self) -> Option<String>;
+
     async fn chat_completions(
       &self,
-      request: CreateChatCompletionRequest,
+      request: ChatCompletionRequest,
       userdata: Sender<String>,
     ) -> crate::oai::Result<()>;
+
+    async fn rerank(&self, request: RerankRequest) -> crate::oai::Result<RerankResponse>;
   }
 
   impl Clone for RouterState {
@@ -8,16 +8,26 @@ use serde::{
   de::{self, MapAccess, Visitor},
   Deserialize, Deserializer, Serialize,
 };
-use std::{fmt, ops::Deref};
+use std::{
+  collections::HashMap,
+  fmt,
+  ops::Deref,
+  panic::{catch_unwind, AssertUnwindSafe},
+  path::PathBuf,
+  sync::{Arc, Mutex},
+};
 use validator::{Validate, ValidationError};
 
-use crate::objs::{validation_errors, HubFile, ObjError};
+use crate::objs::{HubFile, ObjError};
+use crate::service::TemplateLimits;
+use crate::shared_rw::ContextError;
+use crate::template_compat::python_method;
 
 pub fn raise_exception(err_text: String) -> Result<String, minijinja::Error> {
   Err(minijinja::Error::new(ErrorKind::SyntaxError, err_text))
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+#[derive(Debug, Clone, Deserialize, Serialize, Default, new)]
 pub struct ChatMessage {
   role: Option<String>,
   content: Option<String>,
@@ -61,6 +71,56 @@ impl<'a> From<&'a ChatCompletionRequestMessage> for ChatMessage {
   }
 }
 
+#[derive(Debug, thiserror::Error)]
+pub enum TemplateError {
+  #[error("chat_template missing in tokenizer_config.json")]
+  Missing,
+  #[error(transparent)]
+  Render(#[from] minijinja::Error),
+  #[error("chat template rendering panicked: {0}")]
+  Panic(String),
+  #[error("rendered chat template of {size} bytes exceeds the limit of {limit} bytes")]
+  OutputTooLarge { size: usize, limit: usize },
+}
+
+/// Structured details of a chat template failure, to point the user to the failing part of the template
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TemplateDiagnostic {
+  pub kind: String,
+  pub message: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub line: Option<usize>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub detail: Option<String>,
+}
+
+impl From<&TemplateError> for TemplateDiagnostic {
+  fn from(value: &TemplateError) -> Self {
+    let (kind, line, detail) = match value {
+      TemplateError::Missing => ("missing_template", None, None),
+      TemplateError::Render(err) => {
+        let kind = match err.kind() {
+          ErrorKind::SyntaxError => "syntax_error",
+          ErrorKind::OutOfFuel => "out_of_fuel",
+          ErrorKind::UndefinedError => "undefined",
+          ErrorKind::InvalidOperation => "invalid_operation",
+          ErrorKind::UnknownMethod => "unknown_method",
+          _ => "render_error",
+        };
+        (kind, err.line(), err.detail().map(str::to_string))
+      }
+      TemplateError::Panic(_) => ("panic", None, None),
+      TemplateError::OutputTooLarge { .. } => ("output_too_large", None, None),
+    };
+    TemplateDiagnostic {
+      kind: kind.to_string(),
+      message: value.to_string(),
+      line,
+      detail,
+    }
+  }
+}
+
 #[derive(Clone, Serialize, Deserialize, Default)]
 pub(crate) struct ChatTemplateInputs {
   messages: Vec<ChatMessage>,
@@ -120,32 +180,139 @@ impl TokenizerConfig {
   where
     for<'a> &'a T: Into<ChatMessage>,
   {
+    let result = self.render_chat_template(messages, &TemplateLimits::default())?;
+    Ok(result)
+  }
+
+  /// Renders the chat template within the given limits.
+  /// Runaway templates fail with out of fuel or recursion errors, and panics in the template engine are caught.
+  pub fn render_chat_template<T>(
+    &self,
+    messages: &[T],
+    limits: &TemplateLimits,
+  ) -> Result<String, TemplateError>
+  where
+    for<'a> &'a T: Into<ChatMessage>,
+  {
+    self.compile(limits)?.render(messages)
+  }
+
+  /// Parses the chat template once, to render it for each of the requests
+  pub fn compile(&self, limits: &TemplateLimits) -> Result<CompiledChatTemplate, TemplateError> {
     let chat_template = self
       .chat_template
       .chat_template()
-      .ok_or_else(|| {
-        let error = ValidationError::new("chat_template missing in tokenizer_config.json");
-        validation_errors("chat_template", error)
-      })?
-      .replace(".strip()", " | trim")
-      .replace(".title()", " | title");
-    let mut env = Box::new(Environment::new());
-    let template_str = chat_template.into_boxed_str();
-    env.add_function("raise_exception", raise_exception);
-    let template = Box::leak(env).template_from_str(Box::leak(template_str))?;
-    let messages: Vec<ChatMessage> = messages.iter().map(Into::into).collect();
+      .ok_or(TemplateError::Missing)?;
+    let env = catch_panic(|| {
+      let mut env = Environment::new();
+      env.set_fuel(Some(limits.fuel));
+      env.set_recursion_limit(limits.recursion_limit);
+      env.add_function("raise_exception", raise_exception);
+      env.set_unknown_method_callback(python_method);
+      env.add_template_owned(CHAT_TEMPLATE, chat_template)?;
+      Ok(env)
+    })?;
+    Ok(CompiledChatTemplate {
+      env,
+      bos_token: self.bos_token.clone(),
+      eos_token: self.eos_token.clone(),
+      limits: *limits,
+    })
+  }
+}
+
+const CHAT_TEMPLATE: &str = "chat_template";
+
+/// The chat template of a tokenizer config, compiled within the limits
+#[derive(Debug)]
+pub struct CompiledChatTemplate {
+  env: Environment<'static>,
+  bos_token: Option<String>,
+  eos_token: Option<String>,
+  limits: TemplateLimits,
+}
 
+impl CompiledChatTemplate {
+  pub fn render<T>(&self, messages: &[T]) -> Result<String, TemplateError>
+  where
+    for<'a> &'a T: Into<ChatMessage>,
+  {
     let inputs = ChatTemplateInputs {
-      messages,
+      messages: messages.iter().map(Into::into).collect(),
       bos_token: self.bos_token.clone(),
       eos_token: self.eos_token.clone(),
       add_generation_prompt: true,
     };
-    let result = template.render(inputs)?;
+    let result = catch_panic(|| self.env.get_template(CHAT_TEMPLATE)?.render(&inputs))?;
+    if result.len() > self.limits.max_output_bytes {
+      return Err(TemplateError::OutputTooLarge {
+        size: result.len(),
+        limit: self.limits.max_output_bytes,
+      });
+    }
     Ok(result)
   }
 }
 
+fn catch_panic<R>(f: impl FnOnce() -> Result<R, minijinja::Error>) -> Result<R, TemplateError> {
+  let result = catch_unwind(AssertUnwindSafe(f)).map_err(|panic| {
+    let message = panic
+      .downcast_ref::<&str>()
+      .map(|message| message.to_string())
+      .or_else(|| panic.downcast_ref::<String>().cloned())
+      .unwrap_or_else(|| "unknown panic".to_string());
+    TemplateError::Panic(message)
+  })?;
+  Ok(result?)
+}
+
+/// The compiled chat templates by the path of their tokenizer config, shared by the aliases using
+/// the same chat template. The files of a snapshot in the huggingface cache do not change, the
+/// template is compiled again only if the limits change.
+#[derive(Debug, Default)]
+pub struct ChatTemplateCache {
+  templates: Mutex<HashMap<PathBuf, Arc<CompiledChatTemplate>>>,
+}
+
+impl ChatTemplateCache {
+  pub fn get_or_compile(
+    &self,
+    tokenizer_file: HubFile,
+    limits: &TemplateLimits,
+  ) -> crate::shared_rw::Result<Arc<CompiledChatTemplate>> {
+    let path = tokenizer_file.path();
+    let cached = self
+      .templates
+      .lock()
+      .map_err(|err| ContextError::Unreachable(err.to_string()))?
+      .get(&path)
+      .filter(|compiled| compiled.limits == *limits)
+      .cloned();
+    if let Some(compiled) = cached {
+      return Ok(compiled);
+    }
+    let tokenizer_config = TokenizerConfig::try_from(tokenizer_file)?;
+    tokenizer_config.validate()?;
+    let compiled = Arc::new(tokenizer_config.compile(limits)?);
+    self
+      .templates
+      .lock()
+      .map_err(|err| ContextError::Unreachable(err.to_string()))?
+      .insert(path, compiled.clone());
+    Ok(compiled)
+  }
+
+  /// Drops the compiled templates of the tokenizer files, compiled again from the files when
+  /// next used
+  pub fn invalidate(&self, tokenizer_files: &[PathBuf]) {
+    if let Ok(mut templates) = self.templates.lock() {
+      for tokenizer_file in tokenizer_files {
+        templates.remove(tokenizer_file);
+      }
+    }
+  }
+}
+
 fn deserialize_token<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
 where
   D: Deserializer<'de>,
@@ -217,6 +384,14 @@ mod test {
   #[case("command-r", "CohereForAI/c4ai-command-r-plus")]
   #[case("openchat", "openchat/openchat-3.6-8b-20240522")]
   #[case("tinyllama", "TinyLlama/TinyLlama-1.1B-Chat-v1.0")]
+  #[case("chatml", "teknium/OpenHermes-2.5-Mistral-7B")]
+  #[case("mistral-v3", "mistralai/Mistral-7B-Instruct-v0.3")]
+  #[case("mistral-v7", "mistralai/Mistral-Large-Instruct-2411")]
+  #[case("qwen2.5", "Qwen/Qwen2.5-7B-Instruct")]
+  #[case("deepseek-v2", "deepseek-ai/DeepSeek-V2.5")]
+  #[case("deepseek-r1", "deepseek-ai/DeepSeek-R1-Distill-Qwen-7B")]
+  #[case("hermes2-pro", "NousResearch/Hermes-2-Pro-Llama-3-8B")]
+  #[case("hermes3", "NousResearch/Hermes-3-Llama-3.1-8B")]
   // #[case("zephyr", "HuggingFaceH4/zephyr-7b-beta")]
   fn test_tokenizer_config_apply_chat_template(
     #[case] format: String,
@@ -328,4 +503,148 @@ mod test {
     assert_eq!(expected, tokenizer_config);
     Ok(())
   }
+
+  #[rstest]
+  fn test_chat_template_cache_reuses_the_compiled_template(
+    hf_cache: (TempDir, PathBuf),
+  ) -> anyhow::Result<()> {
+    let (_temp_bodhi, hf_cache) = hf_cache;
+    let tokenizer_file = || {
+      HubFile::testalias_tokenizer_builder()
+        .hf_cache(hf_cache.clone())
+        .build()
+        .unwrap()
+    };
+    let cache = ChatTemplateCache::default();
+    let default_limits = TemplateLimits::default();
+    let compiled = cache.get_or_compile(tokenizer_file(), &default_limits)?;
+    let cached = cache.get_or_compile(tokenizer_file(), &default_limits)?;
+    assert!(Arc::ptr_eq(&compiled, &cached));
+    assert_eq!(
+      "<|begin_of_text|><|start_header_id|>user<|end_header_id|>\n\nWhat day comes after Monday?<|eot_id|><|start_header_id|>assistant<|end_header_id|>\n\n",
+      cached.render(&user_messages())?
+    );
+    let recompiled = cache.get_or_compile(tokenizer_file(), &limits())?;
+    assert!(!Arc::ptr_eq(&compiled, &recompiled));
+    cache.invalidate(&[tokenizer_file().path()]);
+    let invalidated = cache.get_or_compile(tokenizer_file(), &limits())?;
+    assert!(!Arc::ptr_eq(&recompiled, &invalidated));
+    Ok(())
+  }
+
+  fn user_messages() -> Vec<ChatMessage> {
+    vec![ChatMessage::new(
+      Some("user".to_string()),
+      Some("What day comes after Monday?".to_string()),
+    )]
+  }
+
+  fn limits() -> TemplateLimits {
+    TemplateLimits {
+      fuel: 10_000,
+      recursion_limit: 20,
+      max_output_bytes: 100,
+    }
+  }
+
+  #[rstest]
+  #[case(
+    "{% for i in range(1000) %}{% for j in range(1000) %}x{% endfor %}{% endfor %}",
+    "out_of_fuel"
+  )]
+  #[case(
+    "{% macro f(n) %}{{ f(n + 1) }}{% endmacro %}{{ f(0) }}",
+    "invalid_operation"
+  )]
+  #[case("{% for i in range(20) %}0123456789{% endfor %}", "output_too_large")]
+  #[case("{% for message in messages %}{{ message.content ", "syntax_error")]
+  #[case("{{ raise_exception('unsupported') }}", "syntax_error")]
+  #[case("{{ messages[0]['content'].reverse() }}", "unknown_method")]
+  fn test_tokenizer_config_render_chat_template_within_limits(
+    #[case] template: &str,
+    #[case] kind: &str,
+  ) -> anyhow::Result<()> {
+    let config = TokenizerConfig::new(
+      ChatTemplateVersions::Single(template.to_string()),
+      None,
+      None,
+    );
+    let result = config.render_chat_template(&user_messages(), &limits());
+    let err = result.expect_err("template should fail to render");
+    assert_eq!(kind, TemplateDiagnostic::from(&err).kind);
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_tokenizer_config_render_chat_template_python_methods() -> anyhow::Result<()> {
+    let config = TokenizerConfig::new(
+      ChatTemplateVersions::Single("{% set ns = namespace(system='') %}{% for message in messages %}{% if message['role'].startswith('sys') %}{% set ns.system = message['content'].rstrip() %}{% else %}{{ ns.system[:3].upper() }}|{{ message['content'].lstrip().split(' ')[-1] }}{% endif %}{% endfor %}".to_string()),
+      None,
+      None,
+    );
+    let messages = vec![
+      ChatMessage::new(
+        Some("system".to_string()),
+        Some("you are helpful  ".to_string()),
+      ),
+      ChatMessage::new(
+        Some("user".to_string()),
+        Some("  What day comes after Monday?".to_string()),
+      ),
+    ];
+    let result = config.render_chat_template(&messages, &TemplateLimits::default())?;
+    assert_eq!("YOU|Monday?", result);
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_tokenizer_config_render_chat_template_fuzz() -> anyhow::Result<()> {
+    use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+    let fragments = [
+      "{% for message in messages %}",
+      "{% endfor %}",
+      "{% if loop.index0 == 0 %}",
+      "{% if message['role'] == 'user' %}",
+      "{% else %}",
+      "{% endif %}",
+      "{{ message['content'] }}",
+      "{{ message['content'] | trim }}",
+      "{{ bos_token }}",
+      "{{ eos_token + bos_token }}",
+      "{% set content = message['role'] + ': ' %}",
+      "{{ content }}",
+      "{% macro m(x) %}{{ m(x) }}{% endmacro %}",
+      "{{ m(1) }}",
+      "{% for i in range(10000) %}",
+      "{{ raise_exception('error') }}",
+      "{{ messages[0]['content'][::-1] }}",
+      "{{",
+      "}}",
+      "{%",
+      "%}",
+      "<|im_start|>",
+      "\n",
+    ];
+    let mut rng = StdRng::seed_from_u64(1135);
+    for _ in 0..500 {
+      let len = rng.gen_range(1..12);
+      let template = (0..len)
+        .map(|_| *fragments.choose(&mut rng).unwrap())
+        .collect::<String>();
+      let config = TokenizerConfig::new(
+        ChatTemplateVersions::Single(template.clone()),
+        Some("<s>".to_string()),
+        Some("</s>".to_string()),
+      );
+      match config.render_chat_template(&user_messages(), &limits()) {
+        Ok(output) => assert!(output.len() <= limits().max_output_bytes, "{template}"),
+        Err(err) => {
+          let diagnostic = TemplateDiagnostic::from(&err);
+          assert!(!diagnostic.message.is_empty(), "{template}");
+        }
+      }
+    }
+    Ok(())
+  }
 }
@@ -12,3 +12,18 @@ pub(crate) fn to_safe_filename(input: &str) -> String {
   }
   sanitized
 }
+
+pub(crate) fn human_size(size: u64) -> String {
+  let units = ["B", "KB", "MB", "GB", "TB"];
+  let mut value = size as f64;
+  let mut unit = 0;
+  while value >= 1024.0 && unit < units.len() - 1 {
+    value /= 1024.0;
+    unit += 1;
+  }
+  if unit == 0 {
+    format!("{size} B")
+  } else {
+    format!("{value:.2} {}", units[unit])
+  }
+}
@@ -0,0 +1,460 @@
+use crate::{
+  error::Common,
+  objs::{Alias, Backend, ChatCompletionRequest, HubFile, RerankRequest},
+  service::TemplateLimits,
+  shared_rw::{ContextError, SharedContextRw, SharedContextRwFn},
+};
+use llama_server_bindings::{disable_llama_log, GptParams};
+use serde::{Deserialize, Serialize};
+use std::{
+  collections::HashMap,
+  path::PathBuf,
+  sync::{Arc, Mutex},
+};
+use tokio::{
+  io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+  sync::{
+    mpsc::{channel, Receiver, Sender},
+    oneshot,
+  },
+  task::JoinHandle,
+};
+
+// the generating requests of the worker, by the request id, cancelled by sending to them
+type Cancels = Arc<Mutex<HashMap<u64, oneshot::Sender<()>>>>;
+
+// the messages are exchanged as one json object per line, over the stdin and stdout of the worker
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct WorkerRequest {
+  pub(crate) id: u64,
+  pub(crate) op: WorkerOp,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub(crate) enum WorkerOp {
+  Reload {
+    params: Option<WorkerParams>,
+  },
+  Stop,
+  /// replies with a chunk of the params of the loaded model as json, `null` if none is loaded
+  LoadedParams,
+  Warmup,
+  ChatCompletions {
+    request: ChatCompletionRequest,
+    alias: Alias,
+    model_file: PathBuf,
+    tokenizer_file: PathBuf,
+    template_limits: TemplateLimits,
+  },
+  Rerank {
+    request: RerankRequest,
+    alias: Alias,
+    model_file: PathBuf,
+  },
+  InvalidateChatTemplates {
+    tokenizer_files: Vec<PathBuf>,
+  },
+  /// stops the generation of the request `id`, e.g. once its client disconnected, not replied to
+  Cancel {
+    id: u64,
+  },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub(crate) enum WorkerResponse {
+  Chunk {
+    id: u64,
+    data: String,
+  },
+  Done {
+    id: u64,
+    result: Result<(), WorkerFailure>,
+  },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub(crate) enum WorkerFailure {
+  ContextLengthExceeded { prompt_tokens: usize, n_ctx: usize },
+  Failed { message: String },
+}
+
+impl From<&ContextError> for WorkerFailure {
+  fn from(value: &ContextError) -> Self {
+    match value {
+      ContextError::ContextLengthExceeded {
+        prompt_tokens,
+        n_ctx,
+      } => WorkerFailure::ContextLengthExceeded {
+        prompt_tokens: *prompt_tokens,
+        n_ctx: *n_ctx,
+      },
+      err => WorkerFailure::Failed {
+        message: err.to_string(),
+      },
+    }
+  }
+}
+
+impl From<WorkerFailure> for ContextError {
+  fn from(value: WorkerFailure) -> Self {
+    match value {
+      WorkerFailure::ContextLengthExceeded {
+        prompt_tokens,
+        n_ctx,
+      } => ContextError::ContextLengthExceeded {
+        prompt_tokens,
+        n_ctx,
+      },
+      WorkerFailure::Failed { message } => ContextError::Worker(message),
+    }
+  }
+}
+
+/// The params of [`GptParams`] set by bodhi, sent to the worker to load the model
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct WorkerParams {
+  pub(crate) model: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub(crate) seed: Option<u32>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub(crate) n_threads: Option<u32>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub(crate) n_ctx: Option<i32>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub(crate) n_predict: Option<i32>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub(crate) n_parallel: Option<i32>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub(crate) n_keep: Option<i32>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub(crate) n_gpu_layers: Option<i32>,
+  #[serde(default)]
+  pub(crate) reranking: bool,
+}
+
+impl From<&GptParams> for WorkerParams {
+  fn from(value: &GptParams) -> Self {
+    WorkerParams {
+      model: value.model.clone(),
+      seed: value.seed,
+      n_threads: value.n_threads,
+      n_ctx: value.n_ctx,
+      n_predict: value.n_predict,
+      n_parallel: value.n_parallel,
+      n_keep: value.n_keep,
+      n_gpu_layers: value.n_gpu_layers,
+      reranking: value.reranking,
+    }
+  }
+}
+
+impl From<WorkerParams> for GptParams {
+  fn from(value: WorkerParams) -> Self {
+    GptParams {
+      model: value.model,
+      seed: value.seed,
+      n_threads: value.n_threads,
+      n_ctx: value.n_ctx,
+      n_predict: value.n_predict,
+      n_parallel: value.n_parallel,
+      n_keep: value.n_keep,
+      n_gpu_layers: value.n_gpu_layers,
+      reranking: value.reranking,
+      ..GptParams::default()
+    }
+  }
+}
+
+/// Serves the requests of the supervising server read from stdin, using the llama.cpp context
+/// of this process, till the stdin is closed
+#[allow(clippy::result_large_err)]
+pub async fn run_worker(strict_memory: bool, backend: Backend) -> crate::error::Result<()> {
+  disable_llama_log();
+  let ctx = Arc::new(SharedContextRw::new_with_options(None, strict_memory, backend).await?);
+  let (out_tx, out_rx) = channel::<WorkerResponse>(100);
+  let writer = tokio::spawn(write_responses(out_rx));
+  let cancels = Cancels::default();
+  let mut lines = BufReader::new(tokio::io::stdin()).lines();
+  while let Some(line) = lines.next_line().await.map_err(Common::from)? {
+    let request = match serde_json::from_str::<WorkerRequest>(&line) {
+      Ok(request) => request,
+      Err(err) => {
+        tracing::warn!(
+          ?err,
+          "error parsing the request of the supervisor, skipping"
+        );
+        continue;
+      }
+    };
+    if let WorkerOp::Cancel { id } = request.op {
+      cancel(&cancels, id);
+      continue;
+    }
+    tokio::spawn(handle_request(
+      ctx.clone(),
+      request,
+      cancels.clone(),
+      out_tx.clone(),
+    ));
+  }
+  tracing::info!("supervisor closed the stdin, stopping the worker");
+  ctx.try_stop().await?;
+  drop(out_tx);
+  if let Err(err) = writer.await {
+    tracing::warn!(?err, "error stopping the writer of the worker");
+  }
+  Ok(())
+}
+
+async fn write_responses(mut out_rx: Receiver<WorkerResponse>) {
+  let mut stdout = tokio::io::stdout();
+  while let Some(response) = out_rx.recv().await {
+    let mut line = match serde_json::to_string(&response) {
+      Ok(line) => line,
+      Err(err) => {
+        tracing::warn!(?err, "error serializing the response of the worker");
+        continue;
+      }
+    };
+    line.push('\n');
+    if let Err(err) = stdout.write_all(line.as_bytes()).await {
+      tracing::warn!(?err, "error writing to the supervisor, stopping the writer");
+      return;
+    }
+    let _ = stdout.flush().await;
+  }
+}
+
+async fn handle_request(
+  ctx: Arc<SharedContextRw>,
+  request: WorkerRequest,
+  cancels: Cancels,
+  out: Sender<WorkerResponse>,
+) {
+  let WorkerRequest { id, op } = request;
+  let result = match op {
+    WorkerOp::Reload { params } => ctx.reload(params.map(GptParams::from)).await,
+    WorkerOp::Stop => ctx.try_stop().await,
+    WorkerOp::LoadedParams => match ctx.get_gpt_params().await {
+      Ok(params) => {
+        let params = params.as_ref().map(WorkerParams::from);
+        match serde_json::to_string(&params) {
+          Ok(data) => {
+            let _ = out.send(WorkerResponse::Chunk { id, data }).await;
+            Ok(())
+          }
+          Err(err) => Err(ContextError::from(Common::SerdeJsonDeserialize(err))),
+        }
+      }
+      Err(err) => Err(err),
+    },
+    WorkerOp::Warmup => ctx.warmup().await,
+    WorkerOp::ChatCompletions {
+      request,
+      alias,
+      model_file,
+      tokenizer_file,
+      template_limits,
+    } => {
+      let (tx, rx) = channel::<String>(100);
+      let forward = forward_chunks(id, rx, register(&cancels, id), out.clone());
+      let result = async {
+        let model_file = HubFile::try_from(model_file)?;
+        let tokenizer_file = HubFile::try_from(tokenizer_file)?;
+        ctx
+          .chat_completions(
+            request,
+            alias,
+            model_file,
+            tokenizer_file,
+            template_limits,
+            tx,
+          )
+          .await
+      }
+      .await;
+      let _ = forward.await;
+      result
+    }
+    WorkerOp::Rerank {
+      request,
+      alias,
+      model_file,
+    } => {
+      let (tx, rx) = channel::<String>(100);
+      let forward = forward_chunks(id, rx, register(&cancels, id), out.clone());
+      let result = async {
+        let model_file = HubFile::try_from(model_file)?;
+        ctx.rerank(request, alias, model_file, tx).await
+      }
+      .await;
+      let _ = forward.await;
+      result
+    }
+    WorkerOp::InvalidateChatTemplates { tokenizer_files } => {
+      ctx.invalidate_chat_templates(tokenizer_files).await
+    }
+    WorkerOp::Cancel { id } => {
+      cancel(&cancels, id);
+      return;
+    }
+  };
+  if let Ok(mut cancels) = cancels.lock() {
+    cancels.remove(&id);
+  }
+  let result = result.map_err(|err| WorkerFailure::from(&err));
+  let _ = out.send(WorkerResponse::Done { id, result }).await;
+}
+
+fn register(cancels: &Cancels, id: u64) -> oneshot::Receiver<()> {
+  let (tx, rx) = oneshot::channel();
+  if let Ok(mut cancels) = cancels.lock() {
+    cancels.insert(id, tx);
+  }
+  rx
+}
+
+fn cancel(cancels: &Cancels, id: u64) {
+  let cancel = cancels
+    .lock()
+    .ok()
+    .and_then(|mut cancels| cancels.remove(&id));
+  if let Some(cancel) = cancel {
+    tracing::info!(id, "cancelling the generation of the request");
+    let _ = cancel.send(());
+  }
+}
+
+// completes once all the senders of the generated chunks are dropped, so the chunks are sent
+// before the request is reported done. On cancel the receiver is dropped, closing the sender of
+// the context, which stops the generation.
+fn forward_chunks(
+  id: u64,
+  mut rx: Receiver<String>,
+  mut cancel: oneshot::Receiver<()>,
+  out: Sender<WorkerResponse>,
+) -> JoinHandle<()> {
+  tokio::spawn(async move {
+    loop {
+      tokio::select! {
+        data = rx.recv() => {
+          let Some(data) = data else {
+            return;
+          };
+          if out.send(WorkerResponse::Chunk { id, data }).await.is_err() {
+            return;
+          }
+        }
+        Ok(()) = &mut cancel => return,
+      }
+    }
+  })
+}
+
+#[cfg(test)]
+mod test {
+  use super::{
+    cancel, forward_chunks, register, Cancels, WorkerFailure, WorkerOp, WorkerParams,
+    WorkerRequest, WorkerResponse,
+  };
+  use crate::shared_rw::ContextError;
+  use llama_server_bindings::GptParams;
+  use rstest::rstest;
+  use tokio::sync::mpsc::channel;
+
+  #[rstest]
+  fn test_worker_messages_round_trip() -> anyhow::Result<()> {
+    let request = WorkerRequest {
+      id: 7,
+      op: WorkerOp::Reload {
+        params: Some(WorkerParams {
+          model: "/models/llama3.gguf".to_string(),
+          n_ctx: Some(2048),
+          ..Default::default()
+        }),
+      },
+    };
+    let line = serde_json::to_string(&request)?;
+    assert_eq!(
+      r#"{"id":7,"op":{"type":"reload","params":{"model":"/models/llama3.gguf","n_ctx":2048,"reranking":false}}}"#,
+      line
+    );
+    assert_eq!(request, serde_json::from_str(&line)?);
+    let response = WorkerResponse::Done {
+      id: 7,
+      result: Err(WorkerFailure::ContextLengthExceeded {
+        prompt_tokens: 600,
+        n_ctx: 512,
+      }),
+    };
+    let line = serde_json::to_string(&response)?;
+    assert_eq!(response, serde_json::from_str(&line)?);
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_worker_cancel_message() -> anyhow::Result<()> {
+    let request = WorkerRequest {
+      id: 7,
+      op: WorkerOp::Cancel { id: 7 },
+    };
+    let line = serde_json::to_string(&request)?;
+    assert_eq!(r#"{"id":7,"op":{"type":"cancel","id":7}}"#, line);
+    assert_eq!(request, serde_json::from_str(&line)?);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_worker_cancel_closes_the_sender_of_the_generation() -> anyhow::Result<()> {
+    let cancels = Cancels::default();
+    let (tx, rx) = channel::<String>(1);
+    let (out, _out_rx) = channel::<WorkerResponse>(1);
+    let forward = forward_chunks(7, rx, register(&cancels, 7), out);
+    assert!(!tx.is_closed());
+    cancel(&cancels, 7);
+    forward.await?;
+    assert!(tx.is_closed());
+    assert!(cancels.lock().unwrap().is_empty());
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_worker_params_from_gpt_params() -> anyhow::Result<()> {
+    let gpt_params = GptParams {
+      model: "/models/llama3.gguf".to_string(),
+      n_ctx: Some(2048),
+      n_gpu_layers: Some(0),
+      reranking: true,
+      ..GptParams::default()
+    };
+    let params = WorkerParams::from(&gpt_params);
+    assert_eq!(gpt_params, GptParams::from(params));
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_worker_failure_keeps_context_length_exceeded() {
+    let err = ContextError::ContextLengthExceeded {
+      prompt_tokens: 600,
+      n_ctx: 512,
+    };
+    let failure = WorkerFailure::from(&err);
+    assert!(matches!(
+      ContextError::from(failure),
+      ContextError::ContextLengthExceeded {
+        prompt_tokens: 600,
+        n_ctx: 512
+      }
+    ));
+    let err = ContextError::Unreachable("context should not be None".to_string());
+    assert_eq!(
+      "context should not be None",
+      ContextError::from(WorkerFailure::from(&err)).to_string()
+    );
+  }
+}
@@ -61,6 +61,13 @@ pub async fn live_server(
   let serve_command = ServeCommand::ByParams {
     host: host.clone(),
     port,
+    port_range: 0,
+    capture_on_error: false,
+    uds: None,
+    ui_dir: None,
+    model: None,
+    strict: false,
+    detach: false,
   };
   let handle = serve_command.aexecute(app_service.clone(), None).await?;
   Ok(TestServerHandle { host, port, handle })